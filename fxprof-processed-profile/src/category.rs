@@ -42,7 +42,7 @@ impl From<CategoryHandle> for CategoryPairHandle {
 }
 
 /// The information about a category.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Category {
     pub name: String,
     pub color: CategoryColor,
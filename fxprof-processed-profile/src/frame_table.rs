@@ -74,9 +74,10 @@ impl FrameTable {
                             }
                             None => {
                                 // This isn't in the pre-provided symbol table, and we know it's in a library.
+                                // Name the frame after the library and the library-relative address,
+                                // so that it can still be looked up manually if symbolication fails.
+                                let location_string = format!("{}!+0x{address:x}", lib.name);
                                 global_libs.add_lib_used_rva(lib_index, address);
-
-                                let location_string = format!("0x{address:x}");
                                 (None, string_table.index_for_string(&location_string))
                             }
                         };
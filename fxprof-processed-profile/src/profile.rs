@@ -2,6 +2,7 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_derive::Serialize;
 use serde_json::json;
 
 use crate::category::{Category, CategoryHandle, CategoryPairHandle};
@@ -18,7 +19,10 @@ use crate::process::{Process, ThreadHandle};
 use crate::reference_timestamp::ReferenceTimestamp;
 use crate::string_table::{GlobalStringIndex, GlobalStringTable};
 use crate::thread::{ProcessHandle, Thread};
-use crate::{MarkerHandle, MarkerSchema, MarkerTiming, ProfilerMarker, SymbolTable, Timestamp};
+use crate::{
+    MarkerFieldFormat, MarkerHandle, MarkerSchema, MarkerTiming, ProfilerMarker, SymbolTable,
+    Timestamp,
+};
 
 /// The sampling interval used during profile recording.
 ///
@@ -98,7 +102,7 @@ pub struct StringHandle(GlobalStringIndex);
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Profile {
     pub(crate) product: String,
     pub(crate) interval: SamplingInterval,
@@ -111,6 +115,8 @@ pub struct Profile {
     pub(crate) reference_timestamp: ReferenceTimestamp,
     pub(crate) string_table: GlobalStringTable,
     pub(crate) marker_schemas: FastHashMap<&'static str, MarkerSchema>,
+    pub(crate) sample_weight_names: Option<(String, String)>,
+    pub(crate) extra_info: Vec<ExtraInfoSection>,
     used_pids: FastHashMap<u32, u32>,
     used_tids: FastHashMap<u32, u32>,
 }
@@ -137,6 +143,8 @@ impl Profile {
             processes: Vec::new(),
             string_table: GlobalStringTable::new(),
             marker_schemas: FastHashMap::default(),
+            sample_weight_names: None,
+            extra_info: Vec::new(),
             categories: vec![Category {
                 name: "Other".to_string(),
                 color: CategoryColor::Gray,
@@ -424,7 +432,58 @@ impl Profile {
         weight: i32,
     ) {
         let stack_index = self.stack_index_for_frames(thread, frames);
-        self.threads[thread.0].add_sample(timestamp, stack_index, cpu_delta, weight);
+        self.threads[thread.0].add_sample(timestamp, stack_index, cpu_delta, weight, 0);
+    }
+
+    /// Add a sample with a second, "auxiliary" weight, for profiles which
+    /// sample two events at once, e.g. CPU cycles and cache misses.
+    ///
+    /// The auxiliary weights are written to an `auxWeight` column next to the
+    /// `weight` column of the samples table. The Firefox Profiler only uses
+    /// `weight`, so a sample which only counts towards the auxiliary event
+    /// should have a weight of zero. Use
+    /// [`Profile::set_sample_weight_names`] to describe the two weights.
+    pub fn add_sample_with_aux_weight(
+        &mut self,
+        thread: ThreadHandle,
+        timestamp: Timestamp,
+        frames: impl Iterator<Item = FrameInfo>,
+        cpu_delta: CpuDelta,
+        weight: i32,
+        aux_weight: i32,
+    ) {
+        let stack_index = self.stack_index_for_frames(thread, frames);
+        self.threads[thread.0].add_sample(timestamp, stack_index, cpu_delta, weight, aux_weight);
+    }
+
+    /// Set the names of the events which the `weight` and `auxWeight` columns
+    /// of the samples tables count, e.g. "cpu-clock" and "cache-misses".
+    pub fn set_sample_weight_names(&mut self, weight: &str, aux_weight: &str) {
+        self.sample_weight_names = Some((weight.to_string(), aux_weight.to_string()));
+    }
+
+    /// Add an entry to a section of the profile's metadata, which the Firefox
+    /// Profiler shows in the profile info panel. The section is created when
+    /// the first entry is added to it.
+    pub fn add_extra_info(
+        &mut self,
+        section: &str,
+        label: &str,
+        format: MarkerFieldFormat,
+        value: impl Into<serde_json::Value>,
+    ) {
+        let entry = ExtraInfoEntry {
+            label: label.to_string(),
+            format,
+            value: value.into(),
+        };
+        match self.extra_info.iter_mut().find(|s| s.label == section) {
+            Some(section) => section.entries.push(entry),
+            None => self.extra_info.push(ExtraInfoSection {
+                label: section.to_string(),
+                entries: vec![entry],
+            }),
+        }
     }
 
     /// Add a sample with a CPU delta of zero. Internally, multiple consecutive
@@ -698,6 +757,20 @@ impl Serialize for Profile {
     }
 }
 
+/// A section of `meta.extra`.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ExtraInfoSection {
+    label: String,
+    entries: Vec<ExtraInfoEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ExtraInfoEntry {
+    label: String,
+    format: MarkerFieldFormat,
+    value: serde_json::Value,
+}
+
 struct SerializableProfileMeta<'a>(&'a Profile);
 
 impl<'a> Serialize for SerializableProfileMeta<'a> {
@@ -726,6 +799,15 @@ impl<'a> Serialize for SerializableProfileMeta<'a> {
                 "threadCPUDelta": "µs",
             }),
         )?;
+        if let Some((weight, aux_weight)) = &self.0.sample_weight_names {
+            map.serialize_entry(
+                "sampleWeightNames",
+                &json!({ "weight": weight, "auxWeight": aux_weight }),
+            )?;
+        }
+        if !self.0.extra_info.is_empty() {
+            map.serialize_entry("extra", &self.0.extra_info)?;
+        }
         map.serialize_entry("startTime", &self.0.reference_timestamp)?;
         map.serialize_entry("symbolicated", &false)?;
         map.serialize_entry("pausedRanges", &[] as &[()])?;
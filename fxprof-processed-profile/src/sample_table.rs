@@ -15,6 +15,10 @@ use crate::Timestamp;
 pub struct SampleTable {
     sample_type: WeightType,
     sample_weights: Vec<i32>,
+    /// A second weight for each sample, for profiles which sample two events
+    /// at once. Only serialized if any sample has a non-zero auxiliary weight.
+    sample_aux_weights: Vec<i32>,
+    has_aux_weights: bool,
     sample_timestamps: Vec<Timestamp>,
     /// An index into the thread's stack table for each sample. `None` means the empty stack.
     sample_stack_indexes: Vec<Option<usize>>,
@@ -109,6 +113,8 @@ impl SampleTable {
         Self {
             sample_type: WeightType::Samples,
             sample_weights: Vec::new(),
+            sample_aux_weights: Vec::new(),
+            has_aux_weights: false,
             sample_timestamps: Vec::new(),
             sample_stack_indexes: Vec::new(),
             sample_cpu_deltas: Vec::new(),
@@ -123,8 +129,11 @@ impl SampleTable {
         stack_index: Option<usize>,
         cpu_delta: CpuDelta,
         weight: i32,
+        aux_weight: i32,
     ) {
         self.sample_weights.push(weight);
+        self.sample_aux_weights.push(aux_weight);
+        self.has_aux_weights |= aux_weight != 0;
         self.sample_timestamps.push(timestamp);
         self.sample_stack_indexes.push(stack_index);
         self.sample_cpu_deltas.push(cpu_delta);
@@ -151,6 +160,9 @@ impl Serialize for SampleTable {
             map.serialize_entry("stack", &self.sample_stack_indexes)?;
             map.serialize_entry("time", &self.sample_timestamps)?;
             map.serialize_entry("weight", &self.sample_weights)?;
+            if self.has_aux_weights {
+                map.serialize_entry("auxWeight", &self.sample_aux_weights)?;
+            }
             map.serialize_entry("threadCPUDelta", &self.sample_cpu_deltas)?;
         } else {
             let mut indexes: Vec<usize> = (0..self.sample_timestamps.len()).collect();
@@ -167,6 +179,12 @@ impl Serialize for SampleTable {
                 "weight",
                 &SliceWithPermutation(&self.sample_weights, &indexes),
             )?;
+            if self.has_aux_weights {
+                map.serialize_entry(
+                    "auxWeight",
+                    &SliceWithPermutation(&self.sample_aux_weights, &indexes),
+                )?;
+            }
             map.serialize_entry(
                 "threadCPUDelta",
                 &SliceWithPermutation(&self.sample_cpu_deltas, &indexes),
@@ -25,7 +25,7 @@ pub struct ProcessHandle(pub(crate) usize);
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct CounterHandle(pub(crate) usize);
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Thread {
     process: ProcessHandle,
     tid: String,
@@ -125,9 +125,10 @@ impl Thread {
         stack_index: Option<usize>,
         cpu_delta: CpuDelta,
         weight: i32,
+        aux_weight: i32,
     ) {
         self.samples
-            .add_sample(timestamp, stack_index, cpu_delta, weight);
+            .add_sample(timestamp, stack_index, cpu_delta, weight, aux_weight);
         self.last_sample_stack = stack_index;
         self.last_sample_was_zero_cpu = cpu_delta == CpuDelta::ZERO;
     }
@@ -152,7 +153,7 @@ impl Thread {
         } else {
             let stack_index = self.last_sample_stack;
             self.samples
-                .add_sample(timestamp, stack_index, CpuDelta::ZERO, weight);
+                .add_sample(timestamp, stack_index, CpuDelta::ZERO, weight, 0);
             self.last_sample_was_zero_cpu = true;
         }
     }
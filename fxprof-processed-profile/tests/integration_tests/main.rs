@@ -942,21 +942,21 @@ fn profile_without_js() {
                 "stringArray": [
                   "0x7ffdb4824837",
                   "dump_syms",
-                  "0xc2704",
-                  "0xde777",
-                  "0x145418",
-                  "0x23eb61",
-                  "0x256d7e",
+                  "dump_syms!+0xc2704",
+                  "dump_syms!+0xde777",
+                  "dump_syms!+0x145418",
+                  "dump_syms!+0x23eb61",
+                  "dump_syms!+0x256d7e",
                   "libc.so.6",
                   "libc_symbol_1",
                   "libc_symbol_2",
-                  "0x106992",
-                  "0xdd2d6",
-                  "0xef3ce",
-                  "0x25318e",
-                  "0x1571b8",
-                  "0xb40e2",
-                  "0x2778f4",
+                  "dump_syms!+0x106992",
+                  "dump_syms!+0xdd2d6",
+                  "dump_syms!+0xef3ce",
+                  "dump_syms!+0x25318e",
+                  "dump_syms!+0x1571b8",
+                  "dump_syms!+0xb40e2",
+                  "dump_syms!+0x2778f4",
                   "libc_symbol_3",
                   "Experimental",
                   "CustomName"
@@ -1554,3 +1554,89 @@ fn profile_counters_with_sorted_processes() {
         )
     )
 }
+
+#[test]
+fn profile_with_aux_weights() {
+    let mut profile = Profile::new(
+        "test with aux weights",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    profile.set_sample_weight_names("cpu-clock", "cache-misses");
+    let process = profile.add_process("test", 123, Timestamp::from_millis_since_reference(0.0));
+    let thread = profile.add_thread(
+        process,
+        12345,
+        Timestamp::from_millis_since_reference(0.0),
+        true,
+    );
+    let category = profile.add_category("Regular", CategoryColor::Green);
+    let frames = |address: u64| {
+        std::iter::once(FrameInfo {
+            frame: Frame::InstructionPointer(address),
+            category_pair: category.into(),
+            flags: FrameFlags::empty(),
+        })
+    };
+    profile.add_sample(
+        thread,
+        Timestamp::from_millis_since_reference(1.0),
+        frames(0x1000),
+        CpuDelta::ZERO,
+        1,
+    );
+    profile.add_sample_with_aux_weight(
+        thread,
+        Timestamp::from_millis_since_reference(1.5),
+        frames(0x2000),
+        CpuDelta::ZERO,
+        0,
+        250,
+    );
+
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(
+        json["meta"]["sampleWeightNames"],
+        json!({ "weight": "cpu-clock", "auxWeight": "cache-misses" })
+    );
+    let samples = &json["threads"][0]["samples"];
+    assert_eq!(samples["weight"], json!([1, 0]));
+    assert_eq!(samples["auxWeight"], json!([0, 250]));
+}
+
+#[test]
+fn profile_with_extra_info() {
+    let mut profile = Profile::new(
+        "test with extra info",
+        ReferenceTimestamp::from_millis_since_unix_epoch(1636162232627.0),
+        SamplingInterval::from_millis(1),
+    );
+    let json = serde_json::to_value(&profile).unwrap();
+    assert!(json["meta"].get("extra").is_none());
+
+    profile.add_extra_info("Stack usage", "main (123)", MarkerFieldFormat::Bytes, 8192);
+    profile.add_extra_info("Recording", "Lost events", MarkerFieldFormat::Integer, 3);
+    profile.add_extra_info(
+        "Stack usage",
+        "worker (124)",
+        MarkerFieldFormat::Bytes,
+        4096,
+    );
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(
+        json["meta"]["extra"],
+        json!([
+            {
+                "label": "Stack usage",
+                "entries": [
+                    { "label": "main (123)", "format": "bytes", "value": 8192 },
+                    { "label": "worker (124)", "format": "bytes", "value": 4096 },
+                ],
+            },
+            {
+                "label": "Recording",
+                "entries": [{ "label": "Lost events", "format": "integer", "value": 3 }],
+            },
+        ])
+    );
+}
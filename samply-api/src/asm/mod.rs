@@ -1,19 +1,23 @@
+use std::collections::BTreeSet;
 use std::str::FromStr;
 
 use samply_symbols::debugid::DebugId;
 use samply_symbols::{
     object, CodeByteReadingError, CodeId, FileAndPathHelper, FileAndPathHelperError, LibraryInfo,
-    LookupAddress, SymbolManager,
+    LookupAddress, SymbolManager, SymbolMap,
 };
 use serde_json::json;
 use yaxpeax_arch::{Arch, DecodeError, LengthedInstruction, Reader, U8Reader};
-use yaxpeax_x86::amd64::{Opcode, Operand};
 
-use self::response_json::Response;
+use self::response_json::{
+    ControlFlow, ControlFlowKind, DestSymbol, InstructionSourceLine, Response,
+};
+use crate::api_file_path::to_api_file_path;
 use crate::asm::response_json::DecodedInstruction;
 
 mod request_json;
 mod response_json;
+mod riscv;
 
 #[derive(thiserror::Error, Debug)]
 enum AsmError {
@@ -100,10 +104,19 @@ impl<'a, H: FileAndPathHelper> AsmApi<'a, H> {
 
         let mut disassembly_len = *size;
 
+        // The symbol map is needed to find the end of the function, to find
+        // the symbols for the jump targets, and for the source lines.
+        let mut symbol_map = None;
+
         if *continue_until_function_end {
-            if let Some(function_end_address) = self
-                .get_function_end_address(&library_info, *start_address)
+            symbol_map = self
+                .symbol_manager
+                .load_symbol_map(&library_info)
                 .await
+                .ok();
+            if let Some(function_end_address) = symbol_map
+                .as_ref()
+                .and_then(|symbol_map| get_function_end_address(symbol_map, *start_address))
             {
                 if function_end_address >= *start_address
                     && function_end_address - *start_address > *size
@@ -119,11 +132,12 @@ impl<'a, H: FileAndPathHelper> AsmApi<'a, H> {
         // really two pieces of information: 0x2000 is the address of the function's
         // first instruction (ARM instructions are two-byte aligned), and the 0x1 bit
         // is the "thumb" bit, meaning that the instructions need to be decoded
-        // with the thumb decoder.
+        // with the thumb decoder. RISC-V instructions are two-byte aligned when
+        // the compressed instructions extension is used.
         let architecture = binary_image.arch();
         let rel_address = match architecture {
             Some("arm64" | "arm64e") => start_address & !0b11,
-            Some("arm") => start_address & !0b1,
+            Some("arm" | "riscv64") => start_address & !0b1,
             _ => *start_address,
         };
 
@@ -148,30 +162,129 @@ impl<'a, H: FileAndPathHelper> AsmApi<'a, H> {
                 CodeByteReadingError::FileIO(e) => AsmError::FileIO(e),
             })?;
 
-        decode_arch(bytes, architecture, rel_address, disassembly_len)
+        let mut response = decode_arch(bytes, architecture, rel_address, disassembly_len)?;
+
+        if symbol_map.is_none() {
+            symbol_map = self
+                .symbol_manager
+                .load_symbol_map(&library_info)
+                .await
+                .ok();
+        }
+        if let Some(symbol_map) = &symbol_map {
+            resolve_jump_targets(symbol_map, &mut response.instructions);
+            response.source_lines =
+                find_source_lines(symbol_map, rel_address, &response.instructions);
+        }
+
+        Ok(response)
     }
+}
 
-    async fn get_function_end_address(
-        &self,
-        library_info: &LibraryInfo,
-        address_within_function: u32,
-    ) -> Option<u32> {
-        let symbol_map_res = self.symbol_manager.load_symbol_map(library_info).await;
-        let symbol = symbol_map_res
-            .ok()?
-            .lookup_sync(LookupAddress::Relative(address_within_function))?
-            .symbol;
-        symbol.address.checked_add(symbol.size?)
+fn get_function_end_address<H: FileAndPathHelper>(
+    symbol_map: &SymbolMap<H>,
+    address_within_function: u32,
+) -> Option<u32> {
+    let symbol = symbol_map
+        .lookup_sync(LookupAddress::Relative(address_within_function))?
+        .symbol;
+    symbol.address.checked_add(symbol.size?)
+}
+
+/// Finds the source line of each instruction, from the line table entries
+/// of the disassembled range.
+fn find_source_lines<H: FileAndPathHelper>(
+    symbol_map: &SymbolMap<H>,
+    start_address: u32,
+    instructions: &[DecodedInstruction],
+) -> Vec<InstructionSourceLine> {
+    let Some(last_instruction) = instructions.last() else {
+        return Vec::new();
+    };
+    let end_address = start_address + last_instruction.offset + 1;
+    let line_ranges = symbol_map.source_line_ranges(start_address..end_address);
+    instructions
+        .iter()
+        .filter_map(|instruction| {
+            let address = start_address + instruction.offset;
+            let line_range = line_ranges
+                .iter()
+                .find(|line_range| line_range.start <= address && address < line_range.end)?;
+            Some(InstructionSourceLine {
+                offset: instruction.offset,
+                file: line_range.file_path.as_ref().map(to_api_file_path),
+                line: line_range.line_number,
+            })
+        })
+        .collect()
+}
+
+/// Fills in the symbols which contain the targets of jumps and calls.
+fn resolve_jump_targets<H: FileAndPathHelper>(
+    symbol_map: &SymbolMap<H>,
+    instructions: &mut [DecodedInstruction],
+) {
+    for control_flow in instructions
+        .iter_mut()
+        .filter_map(|instruction| instruction.control_flow.as_mut())
+    {
+        let Some(jump_target) = control_flow.jump_target else {
+            continue;
+        };
+        let Some(address_info) = symbol_map.lookup_sync(LookupAddress::Relative(jump_target))
+        else {
+            continue;
+        };
+        let symbol = address_info.symbol;
+        control_flow.dest_symbol = Some(DestSymbol {
+            offset: jump_target.saturating_sub(symbol.address),
+            name: symbol.name,
+            address: symbol.address,
+            size: symbol.size,
+        });
     }
 }
 
+/// Finds the instructions which start a basic block, see [`Response::block_starts`].
+fn find_block_starts(start_address: u32, instructions: &[DecodedInstruction]) -> Vec<u32> {
+    let mut block_starts = BTreeSet::new();
+    let mut previous_ends_block = true;
+    for instruction in instructions {
+        if previous_ends_block {
+            block_starts.insert(instruction.offset);
+        }
+        previous_ends_block = false;
+        let Some(control_flow) = &instruction.control_flow else {
+            continue;
+        };
+        if !control_flow.kind.ends_block() {
+            continue;
+        }
+        previous_ends_block = true;
+        let target_offset = control_flow
+            .jump_target
+            .and_then(|jump_target| jump_target.checked_sub(start_address));
+        if let Some(target_offset) = target_offset {
+            // Jumps into the middle of an instruction, or out of the
+            // disassembled range, don't start a block here.
+            if instructions
+                .binary_search_by_key(&target_offset, |instruction| instruction.offset)
+                .is_ok()
+            {
+                block_starts.insert(target_offset);
+            }
+        }
+    }
+    block_starts.into_iter().collect()
+}
+
 fn decode_arch(
     bytes: &[u8],
     arch: Option<&str>,
     rel_address: u32,
     decode_len: u32,
 ) -> Result<Response, AsmError> {
-    Ok(match arch {
+    let mut response = match arch {
         Some("x86") => decode::<yaxpeax_x86::protected_mode::Arch>(bytes, rel_address, decode_len),
         Some("x86_64" | "x86_64h") => {
             decode::<yaxpeax_x86::amd64::Arch>(bytes, rel_address, decode_len)
@@ -180,12 +293,58 @@ fn decode_arch(
             decode::<yaxpeax_arm::armv8::a64::ARMv8>(bytes, rel_address, decode_len)
         }
         Some("arm") => decode::<yaxpeax_arm::armv7::ARMv7>(bytes, rel_address, decode_len),
+        Some("riscv64") => riscv::decode_riscv64(bytes, rel_address, decode_len),
         _ => {
             return Err(AsmError::UnrecognizedArch(
                 arch.map_or_else(|| "unknown".to_string(), |a| a.to_string()),
             ))
         }
-    })
+    };
+    response.block_starts = find_block_starts(rel_address, &response.instructions);
+    Ok(response)
+}
+
+/// Classifies the x86 opcodes which change the control flow. This is a macro
+/// because the 32-bit and the 64-bit decoders have separate `Opcode` enums,
+/// which only differ in the name of the `jcxz` variant.
+macro_rules! x86_control_flow_kind {
+    ($opcode:expr, $module:ident, $jcxz:ident) => {{
+        use yaxpeax_x86::$module::Opcode;
+        match $opcode {
+            Opcode::CALL | Opcode::CALLF => Some(ControlFlowKind::Call),
+            Opcode::JMP | Opcode::JMPF => Some(ControlFlowKind::Jump),
+            Opcode::RETURN | Opcode::RETF | Opcode::IRET | Opcode::IRETD | Opcode::IRETQ => {
+                Some(ControlFlowKind::Return)
+            }
+            Opcode::$jcxz
+            | Opcode::LOOP
+            | Opcode::LOOPZ
+            | Opcode::LOOPNZ
+            | Opcode::JO
+            | Opcode::JNO
+            | Opcode::JB
+            | Opcode::JNB
+            | Opcode::JZ
+            | Opcode::JNZ
+            | Opcode::JNA
+            | Opcode::JA
+            | Opcode::JS
+            | Opcode::JNS
+            | Opcode::JP
+            | Opcode::JNP
+            | Opcode::JL
+            | Opcode::JGE
+            | Opcode::JLE
+            | Opcode::JG => Some(ControlFlowKind::Branch),
+            _ => None,
+        }
+    }};
+}
+
+/// The library-relative address which a relative x86 jump or call goes to.
+/// The offset is relative to the end of the instruction.
+fn x86_jump_target(rel_address: u32, offset: u32, inst_len: i64, rel: i64) -> i64 {
+    rel_address as i64 + offset as i64 + inst_len + rel
 }
 
 trait InstructionDecoding: Arch {
@@ -218,59 +377,31 @@ impl InstructionDecoding for yaxpeax_x86::amd64::Arch {
                 .to_string(),
         );
 
-        fn is_relative_branch(opcode: Opcode) -> bool {
-            matches!(
-                opcode,
-                Opcode::JMP
-                    | Opcode::JRCXZ
-                    | Opcode::LOOP
-                    | Opcode::LOOPZ
-                    | Opcode::LOOPNZ
-                    | Opcode::JO
-                    | Opcode::JNO
-                    | Opcode::JB
-                    | Opcode::JNB
-                    | Opcode::JZ
-                    | Opcode::JNZ
-                    | Opcode::JNA
-                    | Opcode::JA
-                    | Opcode::JS
-                    | Opcode::JNS
-                    | Opcode::JP
-                    | Opcode::JNP
-                    | Opcode::JL
-                    | Opcode::JGE
-                    | Opcode::JLE
-                    | Opcode::JG
-                    | Opcode::CALL
-            )
-        }
-
-        if is_relative_branch(inst.opcode()) {
-            match inst.operand(0) {
-                Operand::ImmediateI8(rel) => {
-                    let dest = rel_address as i64
-                        + offset as i64
-                        + inst.len().to_const() as i64
-                        + rel as i64;
-                    intel_insn = format!("{} 0x{:x}", inst.opcode(), dest);
-                    c_insn.clone_from(&intel_insn);
-                }
-                Operand::ImmediateI32(rel) => {
-                    let dest = rel_address as i64
-                        + offset as i64
-                        + inst.len().to_const() as i64
-                        + rel as i64;
-                    intel_insn = format!("{} 0x{:x}", inst.opcode(), dest);
-                    c_insn.clone_from(&intel_insn);
-                }
-                _ => {}
+        let kind = x86_control_flow_kind!(inst.opcode(), amd64, JRCXZ);
+        let mut jump_target = None;
+        if kind.is_some() && kind != Some(ControlFlowKind::Return) {
+            use yaxpeax_x86::amd64::Operand;
+            let rel = match inst.operand(0) {
+                Operand::ImmediateI8(rel) => Some(rel as i64),
+                Operand::ImmediateI32(rel) => Some(rel as i64),
+                _ => None,
             };
+            if let Some(rel) = rel {
+                let dest = x86_jump_target(rel_address, offset, inst.len().to_const() as i64, rel);
+                intel_insn = format!("{} 0x{:x}", inst.opcode(), dest);
+                c_insn.clone_from(&intel_insn);
+                jump_target = u32::try_from(dest).ok();
+            }
         }
 
         DecodedInstruction {
             offset,
             decoded_string_per_syntax: vec![intel_insn, c_insn],
+            control_flow: kind.map(|kind| ControlFlow {
+                kind,
+                jump_target,
+                dest_symbol: None,
+            }),
         }
     }
 }
@@ -285,13 +416,33 @@ impl InstructionDecoding for yaxpeax_x86::protected_mode::Arch {
     }
 
     fn stringify_inst(
-        _rel_address: u32,
+        rel_address: u32,
         offset: u32,
         inst: Self::Instruction,
     ) -> DecodedInstruction {
+        let kind = x86_control_flow_kind!(inst.opcode(), protected_mode, JECXZ);
+        let mut jump_target = None;
+        if kind.is_some() && kind != Some(ControlFlowKind::Return) {
+            use yaxpeax_x86::protected_mode::Operand;
+            let rel = match inst.operand(0) {
+                Operand::ImmediateI8(rel) => Some(rel as i64),
+                Operand::ImmediateI32(rel) => Some(rel as i64),
+                _ => None,
+            };
+            jump_target = rel.and_then(|rel| {
+                let dest = x86_jump_target(rel_address, offset, inst.len().to_const() as i64, rel);
+                u32::try_from(dest).ok()
+            });
+        }
+
         DecodedInstruction {
             offset,
             decoded_string_per_syntax: vec![inst.to_string()],
+            control_flow: kind.map(|kind| ControlFlow {
+                kind,
+                jump_target,
+                dest_symbol: None,
+            }),
         }
     }
 }
@@ -306,13 +457,49 @@ impl InstructionDecoding for yaxpeax_arm::armv8::a64::ARMv8 {
     }
 
     fn stringify_inst(
-        _rel_address: u32,
+        rel_address: u32,
         offset: u32,
         inst: Self::Instruction,
     ) -> DecodedInstruction {
+        use yaxpeax_arm::armv8::a64::{Opcode, Operand};
+        let kind = match inst.opcode {
+            Opcode::BL
+            | Opcode::BLR
+            | Opcode::BLRAA
+            | Opcode::BLRAAZ
+            | Opcode::BLRAB
+            | Opcode::BLRABZ => Some(ControlFlowKind::Call),
+            Opcode::B
+            | Opcode::BR
+            | Opcode::BRAA
+            | Opcode::BRAAZ
+            | Opcode::BRAB
+            | Opcode::BRABZ => Some(ControlFlowKind::Jump),
+            Opcode::Bcc(_) | Opcode::CBZ | Opcode::CBNZ | Opcode::TBZ | Opcode::TBNZ => {
+                Some(ControlFlowKind::Branch)
+            }
+            Opcode::RET
+            | Opcode::RETAA
+            | Opcode::RETAB
+            | Opcode::ERET
+            | Opcode::ERETAA
+            | Opcode::ERETAB => Some(ControlFlowKind::Return),
+            _ => None,
+        };
+        // The offset is relative to the start of the instruction.
+        let jump_target = inst.operands.iter().find_map(|operand| match operand {
+            Operand::PCOffset(rel) => u32::try_from(rel_address as i64 + offset as i64 + rel).ok(),
+            _ => None,
+        });
+
         DecodedInstruction {
             offset,
             decoded_string_per_syntax: vec![inst.to_string()],
+            control_flow: kind.map(|kind| ControlFlow {
+                kind,
+                jump_target,
+                dest_symbol: None,
+            }),
         }
     }
 }
@@ -338,17 +525,109 @@ impl InstructionDecoding for yaxpeax_arm::armv7::ARMv7 {
     }
 
     fn stringify_inst(
-        _rel_address: u32,
+        rel_address: u32,
         offset: u32,
         inst: Self::Instruction,
     ) -> DecodedInstruction {
+        let address = rel_address as i64 + offset as i64;
+        let control_flow =
+            thumb_control_flow(address, &inst).map(|(kind, jump_target)| ControlFlow {
+                kind,
+                jump_target: jump_target.and_then(|target| u32::try_from(target).ok()),
+                dest_symbol: None,
+            });
         DecodedInstruction {
             offset,
             decoded_string_per_syntax: vec![inst.to_string()],
+            control_flow,
         }
     }
 }
 
+/// The control flow of the Thumb instruction at `address`, with the jump
+/// target if it's known. Any instruction which writes to pc is a jump, and a
+/// return if the address comes from lr or the stack.
+fn thumb_control_flow(
+    address: i64,
+    inst: &yaxpeax_arm::armv7::Instruction,
+) -> Option<(ControlFlowKind, Option<i64>)> {
+    use yaxpeax_arm::armv7::{ConditionCode, Opcode, Operand, Reg};
+    const SP: u8 = 13;
+    const LR: u8 = 14;
+    const PC: u8 = 15;
+    let is_reg = |operand: &Operand, number: u8| match operand {
+        Operand::Reg(reg) => reg.number() == number,
+        _ => false,
+    };
+    let pops_pc =
+        |operand: &Operand| matches!(operand, Operand::RegList(list) if list & (1 << PC) != 0);
+    let is_stack = |operand: &Operand| match operand {
+        Operand::RegDeref(reg)
+        | Operand::RegDerefPostindexOffset(reg, ..)
+        | Operand::RegDerefPreindexOffset(reg, ..)
+        | Operand::RegWBack(reg, _)
+        | Operand::Reg(reg) => *reg == Reg::from_u8(SP),
+        _ => false,
+    };
+
+    // The branch offsets are in halfwords, relative to pc, which is the
+    // address of the instruction plus 4. The decoder already adds one halfword
+    // to the offsets of the 16-bit conditional branches and of cbz/cbnz.
+    let jump_target = inst.operands.iter().find_map(|operand| match operand {
+        Operand::BranchThumbOffset(rel) => {
+            let short_form = matches!(inst.opcode, Opcode::CBZ | Opcode::CBNZ)
+                || (inst.opcode == Opcode::B && !inst.wide && inst.condition != ConditionCode::AL);
+            let pc = if short_form { address + 2 } else { address + 4 };
+            // blx switches to ARM code, which is word-aligned.
+            let pc = if inst.opcode == Opcode::BLX {
+                pc & !3
+            } else {
+                pc
+            };
+            Some(pc + i64::from(*rel) * 2)
+        }
+        _ => None,
+    });
+
+    let operands = &inst.operands;
+    let kind = match inst.opcode {
+        Opcode::BL | Opcode::BLX => ControlFlowKind::Call,
+        Opcode::B => ControlFlowKind::Jump,
+        Opcode::CBZ | Opcode::CBNZ => ControlFlowKind::Branch,
+        Opcode::BX if is_reg(&operands[0], LR) => ControlFlowKind::Return,
+        Opcode::BX | Opcode::BXJ | Opcode::TBB | Opcode::TBH => ControlFlowKind::Jump,
+        Opcode::POP if pops_pc(&operands[0]) => ControlFlowKind::Return,
+        Opcode::LDM(..) if pops_pc(&operands[1]) => {
+            if is_stack(&operands[0]) {
+                ControlFlowKind::Return
+            } else {
+                ControlFlowKind::Jump
+            }
+        }
+        Opcode::LDR if is_reg(&operands[0], PC) => {
+            if is_stack(&operands[1]) {
+                ControlFlowKind::Return
+            } else {
+                ControlFlowKind::Jump
+            }
+        }
+        Opcode::MOV if is_reg(&operands[0], PC) => {
+            if is_reg(&operands[1], LR) {
+                ControlFlowKind::Return
+            } else {
+                ControlFlowKind::Jump
+            }
+        }
+        _ => return None,
+    };
+    let kind = if kind == ControlFlowKind::Jump && inst.condition != ConditionCode::AL {
+        ControlFlowKind::Branch
+    } else {
+        kind
+    };
+    Some((kind, jump_target))
+}
+
 fn decode<'a, A: InstructionDecoding>(
     bytes: &'a [u8],
     rel_address: u32,
@@ -404,6 +683,7 @@ where
                             )
                         })
                         .collect(),
+                    control_flow: None,
                 });
 
                 offset += A::ADJUST_BY_AFTER_ERROR as u32;
@@ -424,5 +704,89 @@ where
         arch: A::ARCH_NAME.to_string(),
         syntax: A::SYNTAX.iter().map(ToString::to_string).collect(),
         instructions,
+        block_starts: Vec::new(),
+        source_lines: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn control_flow(response: &Response) -> Vec<(u32, ControlFlowKind, Option<u32>)> {
+        response
+            .instructions
+            .iter()
+            .filter_map(|inst| {
+                let control_flow = inst.control_flow.as_ref()?;
+                Some((inst.offset, control_flow.kind, control_flow.jump_target))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn control_flow_and_block_starts() {
+        let bytes = [
+            0x74, 0x01, // je +1
+            0xc3, // ret
+            0xe8, 0x00, 0x00, 0x00, 0x00, // call +0
+        ];
+        let response = decode_arch(&bytes, Some("x86_64"), 0x100, 8).unwrap();
+        assert_eq!(
+            control_flow(&response),
+            [
+                (0, ControlFlowKind::Branch, Some(0x103)),
+                (2, ControlFlowKind::Return, None),
+                (3, ControlFlowKind::Call, Some(0x108)),
+            ]
+        );
+        assert_eq!(response.block_starts, [0, 2, 3]);
+
+        let bytes = [
+            0x97, 0x00, 0x00, 0x00, // auipc ra, 0x0
+            0xe7, 0x80, 0x00, 0x02, // jalr 32(ra)
+            0x63, 0x04, 0xb5, 0x00, // beq a0, a1, +8
+            0x3b, 0x05, 0xb5, 0x02, // mulw a0, a0, a1
+            0x41, 0x01, // addi sp, sp, 16
+            0x82, 0x80, // ret
+            0x01, 0xa0, // j +0
+        ];
+        let response = decode_arch(&bytes, Some("riscv64"), 0x1000, 22).unwrap();
+        assert_eq!(
+            control_flow(&response),
+            [
+                (4, ControlFlowKind::Call, None),
+                (8, ControlFlowKind::Branch, Some(0x1010)),
+                (18, ControlFlowKind::Return, None),
+                (20, ControlFlowKind::Jump, Some(0x1014)),
+            ]
+        );
+        assert_eq!(response.block_starts, [0, 12, 16, 20]);
+
+        let bytes = [
+            0x00, 0xf0, 0x02, 0xf8, // bl +8
+            0x01, 0xd0, // beq +6
+            0x08, 0xb1, // cbz r0, +6
+            0x98, 0x47, // blx r3
+            0x70, 0x47, // bx lr
+            0xfe, 0xe7, // b +0
+            0x10, 0xbd, // pop {r4, pc}
+            0x87, 0x46, // mov pc, r0
+        ];
+        let response = decode_arch(&bytes, Some("arm"), 0x2000, 20).unwrap();
+        assert_eq!(
+            control_flow(&response),
+            [
+                (0, ControlFlowKind::Call, Some(0x2008)),
+                (4, ControlFlowKind::Branch, Some(0x200a)),
+                (6, ControlFlowKind::Branch, Some(0x200c)),
+                (8, ControlFlowKind::Call, None),
+                (10, ControlFlowKind::Return, None),
+                (12, ControlFlowKind::Jump, Some(0x200c)),
+                (14, ControlFlowKind::Return, None),
+                (16, ControlFlowKind::Jump, None),
+            ]
+        );
+        assert_eq!(response.block_starts, [0, 6, 8, 10, 12, 14, 16]);
     }
 }
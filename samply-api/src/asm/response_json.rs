@@ -14,7 +14,7 @@ pub struct Response {
     #[serde(serialize_with = "crate::hex::as_hex_string")]
     pub size: u32,
 
-    /// The CPU architecture targeted by this binary, e.g. "i686", "x86_64", "arm", "aarch64"
+    /// The CPU architecture targeted by this binary, e.g. "i686", "x86_64", "arm", "aarch64", "riscv64"
     pub arch: String,
 
     /// A single-element Vec with the disassembly syntax used in the `instructions`,
@@ -26,6 +26,33 @@ pub struct Response {
 
     /// The disassembled instructions.
     pub instructions: Vec<DecodedInstruction>,
+
+    /// The offsets, relative to start_address, of the instructions which start
+    /// a basic block: the first instruction, the targets of jumps and branches
+    /// within the disassembled range, and the instructions after jumps, branches
+    /// and returns. Calls don't end a basic block.
+    pub block_starts: Vec<u32>,
+
+    /// The source lines of the instructions, from the line information in
+    /// the library's debug info. Instructions without line information are
+    /// left out, and the property is skipped if no instruction has any.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub source_lines: Vec<InstructionSourceLine>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionSourceLine {
+    /// Byte offset of the instruction from start_address.
+    pub offset: u32,
+
+    /// The path of the source file, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+
+    /// The line number, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
 }
 
 #[derive(Debug)]
@@ -35,6 +62,71 @@ pub struct DecodedInstruction {
 
     /// The decoded instruction as a string, one for each syntax (e.g. Intel and then C-Style).
     pub decoded_string_per_syntax: Vec<String>,
+
+    /// Set if this instruction is a call, jump, branch or return.
+    pub control_flow: Option<ControlFlow>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlFlow {
+    pub kind: ControlFlowKind,
+
+    /// The library-relative address which is jumped to, as a "0x"-prefixed hex
+    /// string. Not set for returns and for indirect jumps and calls, whose
+    /// target is only known at runtime.
+    #[serde(
+        serialize_with = "crate::hex::as_optional_hex_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub jump_target: Option<u32>,
+
+    /// The symbol which contains the jump target, if the target was found in
+    /// the library's symbols.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest_symbol: Option<DestSymbol>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ControlFlowKind {
+    /// A call to a function, which usually comes back to the next instruction.
+    Call,
+    /// An unconditional jump, including tail calls.
+    Jump,
+    /// A conditional branch, which either jumps or continues with the next
+    /// instruction.
+    Branch,
+    /// A return from the function.
+    Return,
+}
+
+impl ControlFlowKind {
+    /// Whether the next instruction can only be reached from elsewhere.
+    pub fn ends_block(self) -> bool {
+        !matches!(self, ControlFlowKind::Call)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DestSymbol {
+    pub name: String,
+
+    /// The symbol's library-relative address, as a "0x"-prefixed hex string.
+    #[serde(serialize_with = "crate::hex::as_hex_string")]
+    pub address: u32,
+
+    #[serde(
+        serialize_with = "crate::hex::as_optional_hex_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub size: Option<u32>,
+
+    /// The offset of the jump target from the symbol's address, as a
+    /// "0x"-prefixed hex string.
+    #[serde(serialize_with = "crate::hex::as_hex_string")]
+    pub offset: u32,
 }
 
 impl serde::Serialize for DecodedInstruction {
@@ -54,9 +146,12 @@ impl serde::Serialize for DecodedInstruction {
             seq.serialize_element(decoded_string)?;
         }
 
-        // In the future we may append more elements here, for extra per-instruction information.
-        // For example `{ "jumpTarget": "0x1390" }`.
-        // Or even `{ "jumpTarget": "0x2468", "destSymbol": { "name": "MyFunction()", "address": "0x2468", "size": "0x38" } }`
+        // Calls, jumps, branches and returns get an extra object at the end, for example
+        // `{ "kind": "branch", "jumpTarget": "0x1390" }`, or
+        // `{ "kind": "call", "jumpTarget": "0x2468", "destSymbol": { "name": "MyFunction()", "address": "0x2468", "size": "0x38", "offset": "0x0" } }`.
+        if let Some(control_flow) = &self.control_flow {
+            seq.serialize_element(control_flow)?;
+        }
 
         seq.end()
     }
@@ -66,30 +161,62 @@ impl serde::Serialize for DecodedInstruction {
 mod test {
     use serde_json::Result;
 
-    use super::{DecodedInstruction, Response};
+    use super::{
+        ControlFlow, ControlFlowKind, DecodedInstruction, DestSymbol, InstructionSourceLine,
+        Response,
+    };
 
     #[test]
     fn serialize_correctly() -> Result<()> {
         let response = Response {
             start_address: 0x1234,
-            size: 0x3,
+            size: 0x9,
             arch: "x86_64".to_string(),
             syntax: vec!["Intel".to_string()],
             instructions: vec![
                 DecodedInstruction {
                     offset: 0,
                     decoded_string_per_syntax: vec!["push rbp".to_string()],
+                    control_flow: None,
                 },
                 DecodedInstruction {
                     offset: 1,
                     decoded_string_per_syntax: vec!["mov rbp, rsp".to_string()],
+                    control_flow: None,
+                },
+                DecodedInstruction {
+                    offset: 4,
+                    decoded_string_per_syntax: vec!["call 0x2468".to_string()],
+                    control_flow: Some(ControlFlow {
+                        kind: ControlFlowKind::Call,
+                        jump_target: Some(0x2468),
+                        dest_symbol: Some(DestSymbol {
+                            name: "MyFunction()".to_string(),
+                            address: 0x2468,
+                            size: Some(0x38),
+                            offset: 0,
+                        }),
+                    }),
+                },
+            ],
+            block_starts: vec![0],
+            source_lines: vec![
+                InstructionSourceLine {
+                    offset: 0,
+                    file: Some("src/main.c".to_string()),
+                    line: Some(3),
+                },
+                InstructionSourceLine {
+                    offset: 4,
+                    file: Some("src/main.c".to_string()),
+                    line: Some(4),
                 },
             ],
         };
         let response = serde_json::to_string_pretty(&response)?;
         let expected = r#"{
   "startAddress": "0x1234",
-  "size": "0x3",
+  "size": "0x9",
   "arch": "x86_64",
   "syntax": [
     "Intel"
@@ -102,7 +229,36 @@ mod test {
     [
       1,
       "mov rbp, rsp"
+    ],
+    [
+      4,
+      "call 0x2468",
+      {
+        "kind": "call",
+        "jumpTarget": "0x2468",
+        "destSymbol": {
+          "name": "MyFunction()",
+          "address": "0x2468",
+          "size": "0x38",
+          "offset": "0x0"
+        }
+      }
     ]
+  ],
+  "blockStarts": [
+    0
+  ],
+  "sourceLines": [
+    {
+      "offset": 0,
+      "file": "src/main.c",
+      "line": 3
+    },
+    {
+      "offset": 4,
+      "file": "src/main.c",
+      "line": 4
+    }
   ]
 }"#;
         // eprintln!("{}", response);
@@ -0,0 +1,645 @@
+//! A disassembler for RV64GC code: the base integer instructions, the M, A,
+//! F, D, Zicsr and Zifencei extensions, and compressed instructions.
+//!
+//! yaxpeax doesn't have a RISC-V decoder, so this one is written out here.
+//! The output follows the GNU / LLVM syntax, with ABI register names and the
+//! common pseudo-instructions like `li`, `mv`, `j` and `ret`. Branch and jump
+//! targets are printed as library-relative addresses, like for x86.
+
+use super::response_json::{ControlFlow, ControlFlowKind, DecodedInstruction, Response};
+
+const ARCH_NAME: &str = "riscv64";
+const SYNTAX: &str = "RISC-V";
+
+const REGS: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+const FREGS: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2",
+    "fa3", "fa4", "fa5", "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9",
+    "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+pub fn decode_riscv64(bytes: &[u8], rel_address: u32, decode_len: u32) -> Response {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < decode_len {
+        let Some((len, text)) = decode_instruction(&bytes[offset as usize..]) else {
+            break;
+        };
+        let address = u64::from(rel_address) + u64::from(offset);
+        let jump_target = match &text {
+            Text::Jump(_, rel) => u32::try_from((address as i64).wrapping_add(*rel)).ok(),
+            Text::Plain(_) => None,
+        };
+        let text = text.into_string(address);
+        let control_flow = control_flow_kind(&text).map(|kind| ControlFlow {
+            kind,
+            jump_target,
+            dest_symbol: None,
+        });
+        instructions.push(DecodedInstruction {
+            offset,
+            decoded_string_per_syntax: vec![text],
+            control_flow,
+        });
+        offset += len;
+    }
+    Response {
+        start_address: rel_address,
+        size: offset,
+        arch: ARCH_NAME.to_string(),
+        syntax: vec![SYNTAX.to_string()],
+        instructions,
+        block_starts: Vec::new(),
+        source_lines: Vec::new(),
+    }
+}
+
+/// Classifies jumps by their mnemonic. `jal` and `jalr` are calls, unless
+/// they don't save the return address, in which case they are printed as `j`
+/// and `jr`, or with `zero` as the destination.
+fn control_flow_kind(text: &str) -> Option<ControlFlowKind> {
+    let (mnemonic, operands) = text.split_once(' ').unwrap_or((text, ""));
+    match mnemonic {
+        "ret" => Some(ControlFlowKind::Return),
+        "j" | "jr" => Some(ControlFlowKind::Jump),
+        "jal" | "jalr" if operands.starts_with("zero,") => Some(ControlFlowKind::Jump),
+        "jal" | "jalr" => Some(ControlFlowKind::Call),
+        // All mnemonics starting with "b" are branches in RV64GC.
+        _ if mnemonic.starts_with('b') => Some(ControlFlowKind::Branch),
+        _ => None,
+    }
+}
+
+/// A decoded instruction. Jumps and branches get their target address
+/// filled in once the instruction's address is known.
+enum Text {
+    Plain(String),
+    /// The mnemonic and operands before the target, and the target's offset
+    /// from the instruction.
+    Jump(String, i64),
+}
+
+impl Text {
+    fn into_string(self, address: u64) -> String {
+        match self {
+            Text::Plain(text) => text,
+            Text::Jump(prefix, rel) => {
+                format!("{prefix}0x{:x}", (address as i64).wrapping_add(rel))
+            }
+        }
+    }
+}
+
+/// Decodes the instruction at the start of `bytes`, and returns its length
+/// and text. Returns `None` if `bytes` ends in the middle of the instruction.
+fn decode_instruction(bytes: &[u8]) -> Option<(u32, Text)> {
+    let low = u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]);
+    if low & 0b11 != 0b11 {
+        let text =
+            decode_compressed(low).unwrap_or_else(|| Text::Plain(format!(".2byte 0x{low:04x}")));
+        return Some((2, text));
+    }
+    if low & 0b11100 == 0b11100 {
+        // Instructions longer than 32 bits aren't used by any ratified
+        // extension.
+        return Some((2, Text::Plain(format!(".2byte 0x{low:04x}"))));
+    }
+    let high = u16::from_le_bytes([*bytes.get(2)?, *bytes.get(3)?]);
+    let inst = u32::from(low) | (u32::from(high) << 16);
+    let text = decode_32(inst).unwrap_or_else(|| Text::Plain(format!(".4byte 0x{inst:08x}")));
+    Some((4, text))
+}
+
+/// Sign-extends the lowest `bits` bits of `value`.
+fn sext(value: u32, bits: u32) -> i32 {
+    ((value << (32 - bits)) as i32) >> (32 - bits)
+}
+
+fn plain(text: String) -> Option<Text> {
+    Some(Text::Plain(text))
+}
+
+fn decode_32(inst: u32) -> Option<Text> {
+    let rd = ((inst >> 7) & 0x1f) as usize;
+    let rs1 = ((inst >> 15) & 0x1f) as usize;
+    let rs2 = ((inst >> 20) & 0x1f) as usize;
+    let funct3 = (inst >> 12) & 0b111;
+    let funct7 = inst >> 25;
+    let imm_i = (inst as i32) >> 20;
+    let imm_s = ((inst as i32) >> 25 << 5) | ((inst >> 7) & 0x1f) as i32;
+    let (xd, x1, x2) = (REGS[rd], REGS[rs1], REGS[rs2]);
+    let (fd, f1, f2) = (FREGS[rd], FREGS[rs1], FREGS[rs2]);
+
+    match inst & 0x7f {
+        0x37 => plain(format!("lui {xd}, 0x{:x}", inst >> 12)),
+        0x17 => plain(format!("auipc {xd}, 0x{:x}", inst >> 12)),
+        0x6f => {
+            let imm = sext(
+                (inst & 0x80000000) >> 11
+                    | (inst & 0xff000)
+                    | (inst >> 9) & 0x800
+                    | (inst >> 20) & 0x7fe,
+                21,
+            );
+            Some(match rd {
+                0 => Text::Jump("j ".to_string(), imm.into()),
+                1 => Text::Jump("jal ".to_string(), imm.into()),
+                _ => Text::Jump(format!("jal {xd}, "), imm.into()),
+            })
+        }
+        0x67 if funct3 == 0 => plain(match (rd, rs1, imm_i) {
+            (0, 1, 0) => "ret".to_string(),
+            (0, _, 0) => format!("jr {x1}"),
+            (1, _, 0) => format!("jalr {x1}"),
+            _ => format!("jalr {xd}, {imm_i}({x1})"),
+        }),
+        0x63 => {
+            let imm = sext(
+                (inst & 0x80000000) >> 19
+                    | (inst << 4) & 0x800
+                    | (inst >> 20) & 0x7e0
+                    | (inst >> 7) & 0x1e,
+                13,
+            );
+            let name = ["beq", "bne", "", "", "blt", "bge", "bltu", "bgeu"][funct3 as usize];
+            if name.is_empty() {
+                return None;
+            }
+            Some(match (funct3, rs1, rs2) {
+                (0 | 1 | 4 | 5, _, 0) => Text::Jump(format!("{name}z {x1}, "), imm.into()),
+                (4, 0, _) => Text::Jump(format!("bgtz {x2}, "), imm.into()),
+                (5, 0, _) => Text::Jump(format!("blez {x2}, "), imm.into()),
+                _ => Text::Jump(format!("{name} {x1}, {x2}, "), imm.into()),
+            })
+        }
+        0x03 => {
+            let name = ["lb", "lh", "lw", "ld", "lbu", "lhu", "lwu", ""][funct3 as usize];
+            (!name.is_empty()).then(|| Text::Plain(format!("{name} {xd}, {imm_i}({x1})")))
+        }
+        0x23 => {
+            let name = ["sb", "sh", "sw", "sd"].get(funct3 as usize)?;
+            plain(format!("{name} {x2}, {imm_s}({x1})"))
+        }
+        0x13 => {
+            let shamt = (inst >> 20) & 0x3f;
+            plain(match (funct3, inst >> 26) {
+                (0, _) => match (rd, rs1, imm_i) {
+                    (0, 0, 0) => "nop".to_string(),
+                    (_, 0, _) => format!("li {xd}, {imm_i}"),
+                    (_, _, 0) => format!("mv {xd}, {x1}"),
+                    _ => format!("addi {xd}, {x1}, {imm_i}"),
+                },
+                (1, 0) => format!("slli {xd}, {x1}, {shamt}"),
+                (2, _) => format!("slti {xd}, {x1}, {imm_i}"),
+                (3, _) if imm_i == 1 => format!("seqz {xd}, {x1}"),
+                (3, _) => format!("sltiu {xd}, {x1}, {imm_i}"),
+                (4, _) if imm_i == -1 => format!("not {xd}, {x1}"),
+                (4, _) => format!("xori {xd}, {x1}, {imm_i}"),
+                (5, 0) => format!("srli {xd}, {x1}, {shamt}"),
+                (5, 0x10) => format!("srai {xd}, {x1}, {shamt}"),
+                (6, _) => format!("ori {xd}, {x1}, {imm_i}"),
+                (7, _) => format!("andi {xd}, {x1}, {imm_i}"),
+                _ => return None,
+            })
+        }
+        0x1b => plain(match (funct3, funct7) {
+            (0, _) if imm_i == 0 => format!("sext.w {xd}, {x1}"),
+            (0, _) => format!("addiw {xd}, {x1}, {imm_i}"),
+            (1, 0) => format!("slliw {xd}, {x1}, {rs2}"),
+            (5, 0) => format!("srliw {xd}, {x1}, {rs2}"),
+            (5, 0x20) => format!("sraiw {xd}, {x1}, {rs2}"),
+            _ => return None,
+        }),
+        0x33 => {
+            let name = match (funct7, funct3) {
+                (0, 0) => "add",
+                (0x20, 0) if rs1 == 0 => return plain(format!("neg {xd}, {x2}")),
+                (0x20, 0) => "sub",
+                (0, 1) => "sll",
+                (0, 2) => "slt",
+                (0, 3) if rs1 == 0 => return plain(format!("snez {xd}, {x2}")),
+                (0, 3) => "sltu",
+                (0, 4) => "xor",
+                (0, 5) => "srl",
+                (0x20, 5) => "sra",
+                (0, 6) => "or",
+                (0, 7) => "and",
+                (1, _) => [
+                    "mul", "mulh", "mulhsu", "mulhu", "div", "divu", "rem", "remu",
+                ][funct3 as usize],
+                _ => return None,
+            };
+            plain(format!("{name} {xd}, {x1}, {x2}"))
+        }
+        0x3b => {
+            let name = match (funct7, funct3) {
+                (0, 0) => "addw",
+                (0x20, 0) if rs1 == 0 => return plain(format!("negw {xd}, {x2}")),
+                (0x20, 0) => "subw",
+                (0, 1) => "sllw",
+                (0, 5) => "srlw",
+                (0x20, 5) => "sraw",
+                (1, 0) => "mulw",
+                (1, 4) => "divw",
+                (1, 5) => "divuw",
+                (1, 6) => "remw",
+                (1, 7) => "remuw",
+                _ => return None,
+            };
+            plain(format!("{name} {xd}, {x1}, {x2}"))
+        }
+        0x0f => match funct3 {
+            // The fm field is only used by fence.tso, and rd and rs1 are
+            // reserved.
+            0 if inst >> 28 != 0 || rd != 0 || rs1 != 0 => None,
+            0 => {
+                let (pred, succ) = ((inst >> 24) & 0xf, (inst >> 20) & 0xf);
+                if (pred, succ) == (0xf, 0xf) {
+                    plain("fence".to_string())
+                } else {
+                    plain(format!("fence {}, {}", fence_set(pred), fence_set(succ)))
+                }
+            }
+            1 if inst == 0x0000100f => plain("fence.i".to_string()),
+            _ => None,
+        },
+        0x73 => decode_system(inst, funct3, xd, x1, rs1),
+        0x2f => decode_atomic(inst, funct3, xd, x1, x2),
+        0x07 => {
+            let name = ["", "", "flw", "fld"].get(funct3 as usize)?;
+            (!name.is_empty()).then(|| Text::Plain(format!("{name} {fd}, {imm_i}({x1})")))
+        }
+        0x27 => {
+            let name = ["", "", "fsw", "fsd"].get(funct3 as usize)?;
+            (!name.is_empty()).then(|| Text::Plain(format!("{name} {f2}, {imm_s}({x1})")))
+        }
+        opcode @ (0x43 | 0x47 | 0x4b | 0x4f) => {
+            let fmt = fp_format(funct7 & 0b11)?;
+            let f3 = FREGS[(inst >> 27) as usize];
+            let name = match opcode {
+                0x43 => "fmadd",
+                0x47 => "fmsub",
+                0x4b => "fnmsub",
+                _ => "fnmadd",
+            };
+            let rm = rounding_mode(funct3)?;
+            plain(format!("{name}.{fmt} {fd}, {f1}, {f2}, {f3}{rm}"))
+        }
+        0x53 => decode_op_fp(funct7, funct3, rs2, (xd, x1), (fd, f1, f2)),
+        _ => None,
+    }
+}
+
+fn decode_system(inst: u32, funct3: u32, xd: &str, x1: &str, rs1: usize) -> Option<Text> {
+    if funct3 == 0 {
+        return plain(
+            match inst {
+                0x00000073 => "ecall",
+                0x00100073 => "ebreak",
+                0x10200073 => "sret",
+                0x30200073 => "mret",
+                0x10500073 => "wfi",
+                _ => return None,
+            }
+            .to_string(),
+        );
+    }
+    let csr = match inst >> 20 {
+        0x001 => "fflags".to_string(),
+        0x002 => "frm".to_string(),
+        0x003 => "fcsr".to_string(),
+        0xc00 => "cycle".to_string(),
+        0xc01 => "time".to_string(),
+        0xc02 => "instret".to_string(),
+        csr => format!("0x{csr:x}"),
+    };
+    let name = [
+        "", "csrrw", "csrrs", "csrrc", "", "csrrwi", "csrrsi", "csrrci",
+    ][funct3 as usize];
+    plain(match (funct3, xd, rs1) {
+        (4, _, _) => return None,
+        (2, _, 0) => format!("csrr {xd}, {csr}"),
+        (1, "zero", _) => format!("csrw {csr}, {x1}"),
+        (2, "zero", _) => format!("csrs {csr}, {x1}"),
+        (3, "zero", _) => format!("csrc {csr}, {x1}"),
+        (5..=7, _, _) => format!("{name} {xd}, {csr}, {rs1}"),
+        _ => format!("{name} {xd}, {csr}, {x1}"),
+    })
+}
+
+fn decode_atomic(inst: u32, funct3: u32, xd: &str, x1: &str, x2: &str) -> Option<Text> {
+    let width = match funct3 {
+        2 => "w",
+        3 => "d",
+        _ => return None,
+    };
+    let ordering = match (inst >> 25) & 0b11 {
+        0 => "",
+        1 => ".rl",
+        2 => ".aq",
+        _ => ".aqrl",
+    };
+    let name = match inst >> 27 {
+        0x02 if (inst >> 20) & 0x1f == 0 => {
+            return plain(format!("lr.{width}{ordering} {xd}, ({x1})"))
+        }
+        0x03 => "sc",
+        0x01 => "amoswap",
+        0x00 => "amoadd",
+        0x04 => "amoxor",
+        0x0c => "amoand",
+        0x08 => "amoor",
+        0x10 => "amomin",
+        0x14 => "amomax",
+        0x18 => "amominu",
+        0x1c => "amomaxu",
+        _ => return None,
+    };
+    plain(format!("{name}.{width}{ordering} {xd}, {x2}, ({x1})"))
+}
+
+fn decode_op_fp(
+    funct7: u32,
+    funct3: u32,
+    rs2: usize,
+    (xd, x1): (&str, &str),
+    (fd, f1, f2): (&str, &str, &str),
+) -> Option<Text> {
+    let fmt = fp_format(funct7 & 0b11)?;
+    // For the instructions which round, funct3 is the rounding mode.
+    let rm = || rounding_mode(funct3);
+    plain(match (funct7 >> 2, funct3) {
+        (0, _) => format!("fadd.{fmt} {fd}, {f1}, {f2}{}", rm()?),
+        (1, _) => format!("fsub.{fmt} {fd}, {f1}, {f2}{}", rm()?),
+        (2, _) => format!("fmul.{fmt} {fd}, {f1}, {f2}{}", rm()?),
+        (3, _) => format!("fdiv.{fmt} {fd}, {f1}, {f2}{}", rm()?),
+        (0xb, _) if rs2 == 0 => format!("fsqrt.{fmt} {fd}, {f1}{}", rm()?),
+        (4, 0) if f1 == f2 => format!("fmv.{fmt} {fd}, {f1}"),
+        (4, 1) if f1 == f2 => format!("fneg.{fmt} {fd}, {f1}"),
+        (4, 2) if f1 == f2 => format!("fabs.{fmt} {fd}, {f1}"),
+        (4, 0) => format!("fsgnj.{fmt} {fd}, {f1}, {f2}"),
+        (4, 1) => format!("fsgnjn.{fmt} {fd}, {f1}, {f2}"),
+        (4, 2) => format!("fsgnjx.{fmt} {fd}, {f1}, {f2}"),
+        (5, 0) => format!("fmin.{fmt} {fd}, {f1}, {f2}"),
+        (5, 1) => format!("fmax.{fmt} {fd}, {f1}, {f2}"),
+        // Converting from single to double precision is exact, and so is
+        // converting a 32-bit integer to double precision.
+        (8, _) if (fmt, rs2) == ("d", 0) => format!("fcvt.d.s {fd}, {f1}"),
+        (8, _) if (fmt, rs2) == ("s", 1) => format!("fcvt.s.d {fd}, {f1}{}", rm()?),
+        (0x1a, _) if fmt == "d" && rs2 < 2 => {
+            format!("fcvt.d.{} {fd}, {x1}", int_format(rs2)?)
+        }
+        (0x14, 2) => format!("feq.{fmt} {xd}, {f1}, {f2}"),
+        (0x14, 1) => format!("flt.{fmt} {xd}, {f1}, {f2}"),
+        (0x14, 0) => format!("fle.{fmt} {xd}, {f1}, {f2}"),
+        (0x18, _) => format!("fcvt.{}.{fmt} {xd}, {f1}{}", int_format(rs2)?, rm()?),
+        (0x1a, _) => format!("fcvt.{fmt}.{} {fd}, {x1}{}", int_format(rs2)?, rm()?),
+        (0x1c, 0) if rs2 == 0 => format!("fmv.x.{} {xd}, {f1}", fmv_format(fmt)),
+        (0x1c, 1) if rs2 == 0 => format!("fclass.{fmt} {xd}, {f1}"),
+        (0x1e, 0) if rs2 == 0 => format!("fmv.{}.x {fd}, {x1}", fmv_format(fmt)),
+        _ => return None,
+    })
+}
+
+fn fp_format(fmt: u32) -> Option<&'static str> {
+    match fmt {
+        0 => Some("s"),
+        1 => Some("d"),
+        _ => None,
+    }
+}
+
+/// The rounding mode operand, which is left out for the default, dynamic
+/// rounding mode.
+fn rounding_mode(funct3: u32) -> Option<&'static str> {
+    match funct3 {
+        0 => Some(", rne"),
+        1 => Some(", rtz"),
+        2 => Some(", rdn"),
+        3 => Some(", rup"),
+        4 => Some(", rmm"),
+        7 => Some(""),
+        _ => None,
+    }
+}
+
+fn int_format(rs2: usize) -> Option<&'static str> {
+    ["w", "wu", "l", "lu"].get(rs2).copied()
+}
+
+/// `fmv.x.w` moves single-precision values, `fmv.x.d` double-precision ones.
+fn fmv_format(fmt: &str) -> &'static str {
+    match fmt {
+        "s" => "w",
+        _ => "d",
+    }
+}
+
+fn fence_set(bits: u32) -> String {
+    let set: String = [(8, 'i'), (4, 'o'), (2, 'r'), (1, 'w')]
+        .into_iter()
+        .filter(|(bit, _)| bits & bit != 0)
+        .map(|(_, c)| c)
+        .collect();
+    if set.is_empty() {
+        "0".to_string()
+    } else {
+        set
+    }
+}
+
+fn decode_compressed(inst: u16) -> Option<Text> {
+    let inst = u32::from(inst);
+    let funct3 = inst >> 13;
+    let rd = ((inst >> 7) & 0x1f) as usize;
+    let rs2 = ((inst >> 2) & 0x1f) as usize;
+    // The 3-bit register fields of the CIW, CL, CS, CA and CB formats.
+    let rd_short = ((inst >> 2) & 0b111) as usize + 8;
+    let rs1_short = ((inst >> 7) & 0b111) as usize + 8;
+    let imm6 = sext((inst >> 7) & 0x20 | (inst >> 2) & 0x1f, 6);
+    let uimm_d = (inst >> 7) & 0x38 | (inst << 1) & 0xc0;
+    let uimm_w = (inst >> 7) & 0x38 | (inst >> 4) & 0x4 | (inst << 1) & 0x40;
+    let (xd, x2) = (REGS[rd], REGS[rs2]);
+    let (xd_short, x1_short) = (REGS[rd_short], REGS[rs1_short]);
+
+    match (inst & 0b11, funct3) {
+        (0, 0) => {
+            let imm =
+                (inst >> 7) & 0x30 | (inst >> 1) & 0x3c0 | (inst >> 4) & 0x4 | (inst >> 2) & 0x8;
+            (imm != 0).then(|| Text::Plain(format!("addi {xd_short}, sp, {imm}")))
+        }
+        (0, 1) => plain(format!("fld {}, {uimm_d}({x1_short})", FREGS[rd_short])),
+        (0, 2) => plain(format!("lw {xd_short}, {uimm_w}({x1_short})")),
+        (0, 3) => plain(format!("ld {xd_short}, {uimm_d}({x1_short})")),
+        (0, 5) => plain(format!("fsd {}, {uimm_d}({x1_short})", FREGS[rd_short])),
+        (0, 6) => plain(format!("sw {xd_short}, {uimm_w}({x1_short})")),
+        (0, 7) => plain(format!("sd {xd_short}, {uimm_d}({x1_short})")),
+        (1, 0) if rd == 0 => plain("nop".to_string()),
+        (1, 0) => plain(format!("addi {xd}, {xd}, {imm6}")),
+        (1, 1) if rd == 0 => None,
+        (1, 1) if imm6 == 0 => plain(format!("sext.w {xd}, {xd}")),
+        (1, 1) => plain(format!("addiw {xd}, {xd}, {imm6}")),
+        (1, 2) => plain(format!("li {xd}, {imm6}")),
+        (1, 3) if rd == 2 => {
+            let imm = sext(
+                (inst >> 3) & 0x200
+                    | (inst >> 2) & 0x10
+                    | (inst << 1) & 0x40
+                    | (inst << 4) & 0x180
+                    | (inst << 3) & 0x20,
+                10,
+            );
+            (imm != 0).then(|| Text::Plain(format!("addi sp, sp, {imm}")))
+        }
+        (1, 3) => {
+            let imm = sext((inst << 5) & 0x20000 | (inst << 10) & 0x1f000, 18);
+            (imm != 0 && rd != 0)
+                .then(|| Text::Plain(format!("lui {xd}, 0x{:x}", (imm >> 12) & 0xfffff)))
+        }
+        (1, 4) => {
+            let shamt = (inst >> 7) & 0x20 | (inst >> 2) & 0x1f;
+            plain(
+                match ((inst >> 10) & 0b11, (inst >> 12) & 1, (inst >> 5) & 0b11) {
+                    (0, _, _) => format!("srli {x1_short}, {x1_short}, {shamt}"),
+                    (1, _, _) => format!("srai {x1_short}, {x1_short}, {shamt}"),
+                    (2, _, _) => format!("andi {x1_short}, {x1_short}, {imm6}"),
+                    (_, bit12, funct2) => {
+                        let name = match (bit12, funct2) {
+                            (0, 0) => "sub",
+                            (0, 1) => "xor",
+                            (0, 2) => "or",
+                            (0, 3) => "and",
+                            (1, 0) => "subw",
+                            (1, 1) => "addw",
+                            _ => return None,
+                        };
+                        format!("{name} {x1_short}, {x1_short}, {xd_short}")
+                    }
+                },
+            )
+        }
+        (1, 5) => {
+            let imm = sext(
+                (inst >> 1) & 0x800
+                    | (inst >> 7) & 0x10
+                    | (inst >> 1) & 0x300
+                    | (inst << 2) & 0x400
+                    | (inst >> 1) & 0x40
+                    | (inst << 1) & 0x80
+                    | (inst >> 2) & 0xe
+                    | (inst << 3) & 0x20,
+                12,
+            );
+            Some(Text::Jump("j ".to_string(), imm.into()))
+        }
+        (1, 6 | 7) => {
+            let imm = sext(
+                (inst >> 4) & 0x100
+                    | (inst >> 7) & 0x18
+                    | (inst << 1) & 0xc0
+                    | (inst >> 2) & 0x6
+                    | (inst << 3) & 0x20,
+                9,
+            );
+            let name = if funct3 == 6 { "beqz" } else { "bnez" };
+            Some(Text::Jump(format!("{name} {x1_short}, "), imm.into()))
+        }
+        (2, 0) => {
+            let shamt = (inst >> 7) & 0x20 | (inst >> 2) & 0x1f;
+            plain(format!("slli {xd}, {xd}, {shamt}"))
+        }
+        (2, 1 | 3) => {
+            let imm = (inst >> 7) & 0x20 | (inst >> 2) & 0x18 | (inst << 4) & 0x1c0;
+            match funct3 {
+                1 => plain(format!("fld {}, {imm}(sp)", FREGS[rd])),
+                _ => (rd != 0).then(|| Text::Plain(format!("ld {xd}, {imm}(sp)"))),
+            }
+        }
+        (2, 2) => {
+            let imm = (inst >> 7) & 0x20 | (inst >> 2) & 0x1c | (inst << 4) & 0xc0;
+            (rd != 0).then(|| Text::Plain(format!("lw {xd}, {imm}(sp)")))
+        }
+        (2, 4) => plain(match ((inst >> 12) & 1, rd, rs2) {
+            (0, 0, 0) => return None,
+            (0, 1, 0) => "ret".to_string(),
+            (0, _, 0) => format!("jr {xd}"),
+            (0, _, _) => format!("mv {xd}, {x2}"),
+            (_, 0, 0) => "ebreak".to_string(),
+            (_, _, 0) => format!("jalr {xd}"),
+            _ => format!("add {xd}, {xd}, {x2}"),
+        }),
+        (2, 5 | 7) => {
+            let imm = (inst >> 7) & 0x38 | (inst >> 1) & 0x1c0;
+            match funct3 {
+                5 => plain(format!("fsd {}, {imm}(sp)", FREGS[rs2])),
+                _ => plain(format!("sd {x2}, {imm}(sp)")),
+            }
+        }
+        (2, 6) => {
+            let imm = (inst >> 7) & 0x3c | (inst >> 1) & 0xc0;
+            plain(format!("sw {x2}, {imm}(sp)"))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn disassemble(bytes: &[u8], rel_address: u32) -> Vec<String> {
+        let response = decode_riscv64(bytes, rel_address, bytes.len() as u32);
+        assert_eq!(response.size, bytes.len() as u32);
+        response
+            .instructions
+            .into_iter()
+            .map(|inst| inst.decoded_string_per_syntax.join(""))
+            .collect()
+    }
+
+    #[test]
+    fn function_with_compressed_instructions() {
+        let bytes = [
+            0x41, 0x11, // addi sp, sp, -16
+            0x06, 0xe4, // sd ra, 8(sp)
+            0x97, 0x00, 0x00, 0x00, // auipc ra, 0x0
+            0xe7, 0x80, 0x00, 0x02, // jalr 32(ra)
+            0x63, 0x04, 0xb5, 0x00, // beq a0, a1, +8
+            0x3b, 0x05, 0xb5, 0x02, // mulw a0, a0, a1
+            0xa2, 0x60, // ld ra, 8(sp)
+            0x41, 0x01, // addi sp, sp, 16
+            0x82, 0x80, // ret
+            0x01, 0xa0, // j +0
+        ];
+        assert_eq!(
+            disassemble(&bytes, 0x1000),
+            [
+                "addi sp, sp, -16",
+                "sd ra, 8(sp)",
+                "auipc ra, 0x0",
+                "jalr ra, 32(ra)",
+                "beq a0, a1, 0x1014",
+                "mulw a0, a0, a1",
+                "ld ra, 8(sp)",
+                "addi sp, sp, 16",
+                "ret",
+                "j 0x101a",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_and_truncated_instructions() {
+        assert_eq!(
+            disassemble(&[0x00, 0x00, 0x0b, 0x00, 0x00, 0x00], 0),
+            [".2byte 0x0000", ".4byte 0x0000000b"]
+        );
+        // Half of a 32-bit instruction.
+        assert!(decode_riscv64(&[0x97, 0x00], 0, 2).instructions.is_empty());
+    }
+}
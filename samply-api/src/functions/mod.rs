@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+
+use samply_symbols::{
+    FileAndPathHelper, FrameDebugInfo, LibraryInfo, LookupAddress, SymbolInfo, SymbolManager,
+    SymbolMap,
+};
+use serde_json::json;
+
+use crate::api_file_path::to_api_file_path;
+use crate::error::Error;
+use crate::to_debug_id;
+
+mod request_json;
+mod response_json;
+
+use request_json::AddressRange;
+use response_json::{Function, InlineNode, ModuleResult, Response};
+
+/// The maximum number of bytes per request whose inline frames are looked
+/// up. Every byte is looked up separately, because instruction boundaries
+/// aren't known, so this keeps requests with huge ranges from taking minutes.
+/// Functions beyond this limit are returned without their inlines.
+const MAX_INLINE_LOOKUP_BYTES: u32 = 0x10_0000;
+
+pub struct FunctionsApi<'a, H: FileAndPathHelper> {
+    symbol_manager: &'a SymbolManager<H>,
+}
+
+impl<'a, H: FileAndPathHelper> FunctionsApi<'a, H> {
+    /// Create a [`FunctionsApi`] instance which uses the provided [`SymbolManager`].
+    pub fn new(symbol_manager: &'a SymbolManager<H>) -> Self {
+        Self { symbol_manager }
+    }
+
+    pub async fn query_api_json(&self, request_json: &str) -> String {
+        match self.query_api_fallible_json(request_json).await {
+            Ok(response_json) => response_json,
+            Err(err) => json!({ "error": err.to_string() }).to_string(),
+        }
+    }
+
+    async fn query_api_fallible_json(&self, request_json: &str) -> Result<String, Error> {
+        let request: request_json::Request = serde_json::from_str(request_json)?;
+        let response = self.query_api(&request).await;
+        Ok(serde_json::to_string(&response)?)
+    }
+
+    async fn query_api(&self, request: &request_json::Request) -> Response {
+        let mut lookup_budget = MAX_INLINE_LOOKUP_BYTES;
+        let mut modules = Vec::new();
+        for module in &request.modules {
+            let (functions, error) =
+                match self.functions_for_module(module, &mut lookup_budget).await {
+                    Ok(functions) => (functions, None),
+                    Err(err) => (Vec::new(), Some((&err).into())),
+                };
+            modules.push(ModuleResult {
+                debug_name: module.debug_name.clone(),
+                debug_id: module.debug_id.clone(),
+                functions,
+                error,
+            });
+        }
+        Response { modules }
+    }
+
+    async fn functions_for_module(
+        &self,
+        module: &request_json::Module,
+        lookup_budget: &mut u32,
+    ) -> Result<Vec<Function>, samply_symbols::Error> {
+        let info = LibraryInfo {
+            debug_name: Some(module.debug_name.clone()),
+            debug_id: Some(to_debug_id(&module.debug_id)?),
+            ..Default::default()
+        };
+        let symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
+        let ranges = merge_ranges(&module.ranges);
+
+        // The functions which overlap a range either contain its start, or
+        // start inside of it.
+        let mut function_addresses: Vec<u32> = ranges.iter().map(|range| range.start).collect();
+        function_addresses.extend(
+            symbol_map
+                .iter_symbols()
+                .map(|(address, _)| address)
+                .filter(|address| contains(&ranges, *address)),
+        );
+        let mut symbols = BTreeMap::new();
+        for address in function_addresses {
+            if let Some(address_info) = symbol_map.lookup_sync(LookupAddress::Relative(address)) {
+                symbols.insert(address_info.symbol.address, address_info.symbol);
+            }
+        }
+
+        let mut functions = Vec::new();
+        for symbol in symbols.into_values() {
+            let overlaps = function_overlaps(&ranges, &symbol);
+            let byte_count: u32 = overlaps.iter().map(|range| range.end - range.start).sum();
+            let inlines = if byte_count <= *lookup_budget {
+                *lookup_budget -= byte_count;
+                Some(inline_tree(&symbol_map, &symbol, &overlaps).await)
+            } else {
+                None
+            };
+            functions.push(Function {
+                name: symbol.name,
+                address: symbol.address,
+                size: symbol.size,
+                inlines,
+            });
+        }
+        Ok(functions)
+    }
+}
+
+/// Sorts the ranges and merges the overlapping ones. Empty ranges are dropped.
+fn merge_ranges(ranges: &[AddressRange]) -> Vec<AddressRange> {
+    let mut ranges: Vec<AddressRange> = ranges
+        .iter()
+        .filter(|range| range.start < range.end)
+        .copied()
+        .collect();
+    ranges.sort_unstable_by_key(|range| range.start);
+    let mut merged: Vec<AddressRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Whether one of the merged, sorted `ranges` contains `address`.
+fn contains(ranges: &[AddressRange], address: u32) -> bool {
+    let index = ranges.partition_point(|range| range.end <= address);
+    matches!(ranges.get(index), Some(range) if range.start <= address)
+}
+
+/// The parts of the ranges which are inside the function. Without a known
+/// size, the function is assumed to extend to the end of the ranges, and the
+/// lookups stop at the next symbol.
+fn function_overlaps(ranges: &[AddressRange], symbol: &SymbolInfo) -> Vec<AddressRange> {
+    let function_end = symbol
+        .size
+        .and_then(|size| symbol.address.checked_add(size))
+        .unwrap_or(u32::MAX);
+    ranges
+        .iter()
+        .map(|range| AddressRange {
+            start: range.start.max(symbol.address),
+            end: range.end.min(function_end),
+        })
+        .filter(|range| range.start < range.end)
+        .collect()
+}
+
+/// Looks up the inline frames of every byte in the ranges of the function.
+async fn inline_tree<H: FileAndPathHelper>(
+    symbol_map: &SymbolMap<H>,
+    symbol: &SymbolInfo,
+    ranges: &[AddressRange],
+) -> Vec<InlineNode> {
+    let mut nodes = Vec::new();
+    for range in ranges {
+        for address in range.start..range.end {
+            let Some(address_info) = symbol_map.lookup(LookupAddress::Relative(address)).await
+            else {
+                break;
+            };
+            if address_info.symbol.address != symbol.address {
+                break;
+            }
+            if let Some(frames) = address_info.frames {
+                add_to_inline_tree(&mut nodes, address, &frames);
+            }
+        }
+    }
+    nodes
+}
+
+/// Adds the byte at `address` to the nodes of the inlined calls in `frames`,
+/// which are ordered from the innermost frame to the outer function.
+fn add_to_inline_tree(mut nodes: &mut Vec<InlineNode>, address: u32, frames: &[FrameDebugInfo]) {
+    let outer_to_inner = frames.iter().rev();
+    for (caller, inlinee) in outer_to_inner.clone().zip(outer_to_inner.skip(1)) {
+        let call_file = caller.file_path.as_ref().map(to_api_file_path);
+        let call_line = caller.line_number.and_then(NonZeroU32::new);
+        let index = nodes.iter().position(|node| {
+            node.function == inlinee.function
+                && node.call_file == call_file
+                && node.call_line == call_line
+        });
+        let index = index.unwrap_or_else(|| {
+            nodes.push(InlineNode {
+                function: inlinee.function.clone(),
+                call_file,
+                call_line,
+                ranges: Vec::new(),
+                inlines: Vec::new(),
+            });
+            nodes.len() - 1
+        });
+        let node = &mut nodes[index];
+        match node.ranges.last_mut() {
+            Some(range) if range.end == address => range.end += 1,
+            _ => node.ranges.push(response_json::AddressRange {
+                start: address,
+                end: address + 1,
+            }),
+        }
+        nodes = &mut node.inlines;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use samply_symbols::SourceFilePath;
+
+    use super::*;
+
+    fn frame(function: &str, line: u32) -> FrameDebugInfo {
+        FrameDebugInfo {
+            function: Some(function.to_string()),
+            file_path: Some(SourceFilePath::new("main.cpp".to_string(), None)),
+            line_number: Some(line),
+        }
+    }
+
+    #[test]
+    fn ranges_and_inline_tree() {
+        let range = |start, end| AddressRange { start, end };
+        let ranges = merge_ranges(&[range(0x30, 0x40), range(0x10, 0x20), range(0x18, 0x28)]);
+        assert_eq!(ranges, [range(0x10, 0x28), range(0x30, 0x40)]);
+        assert!(contains(&ranges, 0x10));
+        assert!(!contains(&ranges, 0x28));
+        assert!(contains(&ranges, 0x3f));
+
+        let mut nodes = Vec::new();
+        let inlined = [frame("inner", 3), frame("middle", 7), frame("outer", 20)];
+        add_to_inline_tree(&mut nodes, 0x10, &inlined);
+        add_to_inline_tree(&mut nodes, 0x11, &inlined);
+        add_to_inline_tree(&mut nodes, 0x12, &[frame("outer", 21)]);
+        add_to_inline_tree(&mut nodes, 0x13, &inlined[1..]);
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].function.as_deref(), Some("middle"));
+        assert_eq!(nodes[0].call_line, NonZeroU32::new(20));
+        assert_eq!(
+            nodes[0].ranges,
+            [
+                response_json::AddressRange {
+                    start: 0x10,
+                    end: 0x12
+                },
+                response_json::AddressRange {
+                    start: 0x13,
+                    end: 0x14
+                },
+            ]
+        );
+        assert_eq!(nodes[0].inlines[0].function.as_deref(), Some("inner"));
+        assert_eq!(nodes[0].inlines[0].call_line, NonZeroU32::new(7));
+    }
+}
@@ -0,0 +1,65 @@
+use serde_derive::Deserialize;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    pub modules: Vec<Module>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Module {
+    pub debug_name: String, // example: "xul.pdb"
+    pub debug_id: String,   // example: "A14CAFD390A3E1884C4C44205044422E1"
+
+    /// The address ranges whose functions should be returned.
+    pub ranges: Vec<AddressRange>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRange {
+    /// The start of the range, as a "0x"-prefixed hex string, interpreted as
+    /// a library-relative offset in bytes.
+    #[serde(deserialize_with = "crate::hex::from_prefixed_hex_str")]
+    pub start: u32,
+
+    /// The end of the range, exclusive, in the same format as `start`.
+    #[serde(deserialize_with = "crate::hex::from_prefixed_hex_str")]
+    pub end: u32,
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Result;
+
+    use super::{AddressRange, Request};
+
+    #[test]
+    fn parse_job() -> Result<()> {
+        let data = r#"
+        {
+          "modules": [
+            {
+              "debugName": "xul.pdb",
+              "debugId": "A14CAFD390A3E1884C4C44205044422E1",
+              "ranges": [
+                { "start": "0x1d04742", "end": "0x1d047c6" },
+                { "start": "0x2000", "end": "0x2010" }
+              ]
+            }
+          ]
+        }"#;
+
+        let r: Request = serde_json::from_str(data)?;
+        assert_eq!(r.modules.len(), 1);
+        assert_eq!(r.modules[0].debug_name, "xul.pdb");
+        assert_eq!(
+            r.modules[0].ranges[0],
+            AddressRange {
+                start: 0x1d04742,
+                end: 0x1d047c6
+            }
+        );
+        Ok(())
+    }
+}
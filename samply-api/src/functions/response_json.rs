@@ -0,0 +1,143 @@
+use std::num::NonZeroU32;
+
+use serde_derive::Serialize;
+
+use crate::symbolicate::response_json::Error;
+
+#[derive(Serialize, Debug)]
+pub struct Response {
+    /// One result per module, in the order of the request's modules.
+    pub modules: Vec<ModuleResult>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleResult {
+    pub debug_name: String,
+    pub debug_id: String,
+
+    /// The functions which overlap any of the requested ranges, sorted by
+    /// address.
+    pub functions: Vec<Function>,
+
+    /// Set if the symbols for this module couldn't be loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Function {
+    pub name: String,
+
+    #[serde(serialize_with = "crate::hex::as_hex_string")]
+    pub address: u32,
+
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::hex::as_optional_hex_string"
+    )]
+    pub size: Option<u32>,
+
+    /// The functions which were inlined into this function, within the
+    /// requested ranges. Missing if the request had too many bytes to look up.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inlines: Option<Vec<InlineNode>>,
+}
+
+/// A call which was inlined, and the calls which were inlined into it.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineNode {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+
+    /// The location of the call in the calling function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub call_file: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub call_line: Option<NonZeroU32>,
+
+    /// The address ranges of the inlined code, sorted by address.
+    pub ranges: Vec<AddressRange>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inlines: Vec<InlineNode>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRange {
+    #[serde(serialize_with = "crate::hex::as_hex_string")]
+    pub start: u32,
+
+    /// Exclusive.
+    #[serde(serialize_with = "crate::hex::as_hex_string")]
+    pub end: u32,
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU32;
+
+    use serde_json::Result;
+
+    use super::{AddressRange, Function, InlineNode, ModuleResult, Response};
+
+    #[test]
+    fn serialize_correctly() -> Result<()> {
+        let response = Response {
+            modules: vec![ModuleResult {
+                debug_name: "xul.pdb".to_string(),
+                debug_id: "A14CAFD390A3E1884C4C44205044422E1".to_string(),
+                functions: vec![Function {
+                    name: "nsAppShell::Run()".to_string(),
+                    address: 0x1000,
+                    size: Some(0x40),
+                    inlines: Some(vec![InlineNode {
+                        function: Some("RefPtr<nsThread>::get()".to_string()),
+                        call_file: Some("widget/nsAppShell.cpp".to_string()),
+                        call_line: NonZeroU32::new(12),
+                        ranges: vec![AddressRange {
+                            start: 0x1008,
+                            end: 0x1010,
+                        }],
+                        inlines: vec![],
+                    }]),
+                }],
+                error: None,
+            }],
+        };
+        let response = serde_json::to_string_pretty(&response)?;
+        let expected = r#"{
+  "modules": [
+    {
+      "debugName": "xul.pdb",
+      "debugId": "A14CAFD390A3E1884C4C44205044422E1",
+      "functions": [
+        {
+          "name": "nsAppShell::Run()",
+          "address": "0x1000",
+          "size": "0x40",
+          "inlines": [
+            {
+              "function": "RefPtr<nsThread>::get()",
+              "callFile": "widget/nsAppShell.cpp",
+              "callLine": 12,
+              "ranges": [
+                {
+                  "start": "0x1008",
+                  "end": "0x1010"
+                }
+              ]
+            }
+          ]
+        }
+      ]
+    }
+  ]
+}"#;
+        assert_eq!(response, expected);
+        Ok(())
+    }
+}
@@ -144,18 +144,22 @@
 
 use asm::AsmApi;
 use debugid::DebugId;
+use functions::FunctionsApi;
 pub use samply_symbols;
 pub use samply_symbols::debugid;
 use samply_symbols::{FileAndPathHelper, SymbolManager};
 use serde_json::json;
 use source::SourceApi;
+use symbol_table::SymbolTableApi;
 use symbolicate::SymbolicateApi;
 
 mod api_file_path;
 mod asm;
 mod error;
+mod functions;
 mod hex;
 mod source;
+mod symbol_table;
 mod symbolicate;
 
 pub(crate) fn to_debug_id(breakpad_id: &str) -> Result<DebugId, samply_symbols::Error> {
@@ -184,11 +188,16 @@ impl<'a, H: FileAndPathHelper> Api<'a, H> {
     ///
     /// The following "URLs" are supported:
     ///  - `/symbolicate/v5`: This API is documented at <https://tecken.readthedocs.io/en/latest/symbolication.html>.
-    ///    The returned data has two extra fields: inlines (per address) and module_errors (per job).
+    ///    The returned data has three extra fields: inlines (per address), and module_errors and
+    ///    module_provenance (per job).
     ///  - `/source/v1`: Experimental API. Symbolicates an address and lets you read one of the files in the
     ///    symbol information for that address.
-    ///  - `/asm/v1`: Experimental API. Symbolicates an address and lets you read one of the files in the
-    ///    symbol information for that address.
+    ///  - `/asm/v1`: Experimental API. Disassembles the machine code at an address range, with the
+    ///    jump targets and source lines of the instructions.
+    ///  - `/functions/v1`: Experimental API. Returns the functions which overlap the requested address
+    ///    ranges, with their sizes and the calls which were inlined into them.
+    ///  - `/symbol-table/v1`: Experimental API. Returns the symbol table of a library, one page of
+    ///    symbols at a time.
     pub async fn query_api(self, request_url: &str, request_json_data: &str) -> String {
         if request_url == "/symbolicate/v5" {
             let symbolicate_api = SymbolicateApi::new(self.symbol_manager);
@@ -199,6 +208,12 @@ impl<'a, H: FileAndPathHelper> Api<'a, H> {
         } else if request_url == "/asm/v1" {
             let asm_api = AsmApi::new(self.symbol_manager);
             asm_api.query_api_json(request_json_data).await
+        } else if request_url == "/functions/v1" {
+            let functions_api = FunctionsApi::new(self.symbol_manager);
+            functions_api.query_api_json(request_json_data).await
+        } else if request_url == "/symbol-table/v1" {
+            let symbol_table_api = SymbolTableApi::new(self.symbol_manager);
+            symbol_table_api.query_api_json(request_json_data).await
         } else {
             json!({ "error": format!("Unrecognized URL {request_url}") }).to_string()
         }
@@ -1,5 +1,7 @@
+use std::str::FromStr;
+
 use samply_symbols::{
-    FileAndPathHelper, FileAndPathHelperError, LibraryInfo, LookupAddress, SymbolManager,
+    CodeId, FileAndPathHelper, FileAndPathHelperError, LibraryInfo, LookupAddress, SymbolManager,
 };
 use serde_json::json;
 
@@ -57,15 +59,20 @@ impl<'a, H: FileAndPathHelper> SourceApi<'a, H> {
         let request_json::Request {
             debug_id,
             debug_name,
+            code_id,
             module_offset,
             file: requested_file,
         } = &request;
         let debug_id = to_debug_id(debug_id)?;
+        let code_id = code_id
+            .as_deref()
+            .and_then(|code_id| CodeId::from_str(code_id).ok());
 
         // Look up the address to see which file paths we are allowed to read.
         let info = LibraryInfo {
             debug_name: Some(debug_name.to_string()),
             debug_id: Some(debug_id),
+            code_id,
             ..Default::default()
         };
         let symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
@@ -11,6 +11,12 @@ pub struct Request {
     /// contains a reference to the requested file.
     pub debug_id: String,
 
+    /// The code ID of the library, e.g. the ELF build ID. This field is
+    /// optional. It lets the symbol files and the source file be found on
+    /// debuginfod servers, which only know libraries by their build ID.
+    #[serde(default)]
+    pub code_id: Option<String>,
+
     /// An address, as a "0x"-prefixed hex string, interpreted as a
     /// library-relative offset in bytes.
     /// This address is symbolicated, and any of the files referenced in
@@ -43,6 +49,7 @@ mod test {
         let r: Request = serde_json::from_str(data)?;
         println!("{r:?}");
         assert_eq!(r.module_offset, 30426946);
+        assert_eq!(r.code_id, None);
         Ok(())
     }
 }
@@ -0,0 +1,67 @@
+use samply_symbols::{FileAndPathHelper, LibraryInfo, SymbolManager, SymbolTablePage};
+use serde_json::json;
+
+use crate::error::Error;
+use crate::to_debug_id;
+
+mod request_json;
+mod response_json;
+
+/// The number of symbols in a page if the request doesn't say, and the
+/// maximum number, to keep the responses at a few megabytes.
+const DEFAULT_PAGE_SIZE: usize = 10_000;
+const MAX_PAGE_SIZE: usize = 100_000;
+
+pub struct SymbolTableApi<'a, H: FileAndPathHelper> {
+    symbol_manager: &'a SymbolManager<H>,
+}
+
+impl<'a, H: FileAndPathHelper> SymbolTableApi<'a, H> {
+    /// Create a [`SymbolTableApi`] instance which uses the provided [`SymbolManager`].
+    pub fn new(symbol_manager: &'a SymbolManager<H>) -> Self {
+        Self { symbol_manager }
+    }
+
+    pub async fn query_api_json(&self, request_json: &str) -> String {
+        match self.query_api_fallible_json(request_json).await {
+            Ok(response_json) => response_json,
+            Err(err) => json!({ "error": err.to_string() }).to_string(),
+        }
+    }
+
+    async fn query_api_fallible_json(&self, request_json: &str) -> Result<String, Error> {
+        let request: request_json::Request = serde_json::from_str(request_json)?;
+        let response = self.query_api(&request).await?;
+        Ok(serde_json::to_string(&response)?)
+    }
+
+    async fn query_api(
+        &self,
+        request: &request_json::Request,
+    ) -> Result<response_json::Response, Error> {
+        let info = LibraryInfo {
+            debug_name: Some(request.debug_name.clone()),
+            debug_id: Some(to_debug_id(&request.debug_id)?),
+            ..Default::default()
+        };
+        let symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
+        let page_size = request
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let page = SymbolTablePage::from_symbol_map(&symbol_map, request.start_address, page_size);
+        Ok(response_json::Response {
+            total_symbol_count: page.total_symbol_count,
+            symbols: page
+                .symbols
+                .into_iter()
+                .map(|symbol| response_json::Symbol {
+                    address: symbol.address,
+                    size: symbol.size,
+                    name: symbol.name,
+                })
+                .collect(),
+            next_start_address: page.next_start_address,
+        })
+    }
+}
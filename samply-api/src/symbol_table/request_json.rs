@@ -0,0 +1,51 @@
+use serde_derive::Deserialize;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    pub debug_name: String, // example: "xul.pdb"
+    pub debug_id: String,   // example: "A14CAFD390A3E1884C4C44205044422E1"
+
+    /// The address where the page starts, as a "0x"-prefixed hex string,
+    /// interpreted as a library-relative offset in bytes. This field is
+    /// optional and defaults to 0. For the following pages, pass the
+    /// `nextStartAddress` of the previous response.
+    #[serde(default, deserialize_with = "crate::hex::from_prefixed_hex_str")]
+    pub start_address: u32,
+
+    /// The maximum number of symbols in the response. This field is optional.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Result;
+
+    use super::Request;
+
+    #[test]
+    fn parse_job() -> Result<()> {
+        let data = r#"
+        {
+          "debugName": "xul.pdb",
+          "debugId": "A14CAFD390A3E1884C4C44205044422E1",
+          "startAddress": "0x1d04742",
+          "limit": 1000
+        }"#;
+
+        let r: Request = serde_json::from_str(data)?;
+        assert_eq!(r.start_address, 0x1d04742);
+        assert_eq!(r.limit, Some(1000));
+
+        let data = r#"
+        {
+          "debugName": "xul.pdb",
+          "debugId": "A14CAFD390A3E1884C4C44205044422E1"
+        }"#;
+        let r: Request = serde_json::from_str(data)?;
+        assert_eq!(r.start_address, 0);
+        assert_eq!(r.limit, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use serde_derive::Serialize;
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+    /// The number of symbols in the whole symbol table.
+    pub total_symbol_count: usize,
+
+    /// The symbols in this page, sorted by address.
+    pub symbols: Vec<Symbol>,
+
+    /// The start address of the next page, as a "0x"-prefixed hex string.
+    /// Missing on the last page.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::hex::as_optional_hex_string"
+    )]
+    pub next_start_address: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Symbol {
+    #[serde(serialize_with = "crate::hex::as_hex_string")]
+    pub address: u32,
+
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::hex::as_optional_hex_string"
+    )]
+    pub size: Option<u32>,
+
+    pub name: String,
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Result;
+
+    use super::{Response, Symbol};
+
+    #[test]
+    fn serialize_correctly() -> Result<()> {
+        let response = Response {
+            total_symbol_count: 2,
+            symbols: vec![Symbol {
+                address: 0x1000,
+                size: Some(0x40),
+                name: "main".to_string(),
+            }],
+            next_start_address: Some(0x1001),
+        };
+        let response = serde_json::to_string_pretty(&response)?;
+        let expected = r#"{
+  "totalSymbolCount": 2,
+  "symbols": [
+    {
+      "address": "0x1000",
+      "size": "0x40",
+      "name": "main"
+    }
+  ],
+  "nextStartAddress": "0x1001"
+}"#;
+        assert_eq!(response, expected);
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 
+use samply_symbols::debugid::DebugId;
 use samply_symbols::FrameDebugInfo;
 
 pub struct AddressResult {
@@ -11,9 +12,18 @@ pub struct AddressResult {
 
 pub type AddressResults = BTreeMap<u32, Option<AddressResult>>;
 
+/// The file which the symbols were taken from.
+pub struct SymbolFile {
+    /// The path or URL of the file, see
+    /// [`FileAndPathHelper::describe_loaded_file`](samply_symbols::FileAndPathHelper::describe_loaded_file).
+    pub file: String,
+    pub debug_id: DebugId,
+}
+
 pub struct LookedUpAddresses {
     pub address_results: AddressResults,
     pub symbol_count: u32,
+    pub symbol_file: Option<SymbolFile>,
 }
 
 impl LookedUpAddresses {
@@ -21,6 +31,7 @@ impl LookedUpAddresses {
         LookedUpAddresses {
             address_results: addresses.iter().map(|&addr| (addr, None)).collect(),
             symbol_count: 0,
+            symbol_file: None,
         }
     }
 
@@ -70,4 +81,17 @@ impl LookedUpAddresses {
     pub fn set_total_symbol_count(&mut self, total_symbol_count: u32) {
         self.symbol_count = total_symbol_count;
     }
+
+    pub fn set_symbol_file(&mut self, file: String, debug_id: DebugId) {
+        self.symbol_file = Some(SymbolFile { file, debug_id });
+    }
+
+    /// Whether any of the addresses had debug info, i.e. line numbers and
+    /// inline frames, and not just a symbol name.
+    pub fn has_inline_info(&self) -> bool {
+        self.address_results
+            .values()
+            .flatten()
+            .any(|result| result.inline_frames.is_some())
+    }
 }
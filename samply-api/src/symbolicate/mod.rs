@@ -92,6 +92,10 @@ impl<'a, H: FileAndPathHelper> SymbolicateApi<'a, H> {
         let symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
 
         symbolication_result.set_total_symbol_count(symbol_map.symbol_count() as u32);
+        symbolication_result.set_symbol_file(
+            self.symbol_manager.debug_file_description(&symbol_map),
+            symbol_map.debug_id(),
+        );
 
         for &address in &addresses {
             if let Some(address_info) = symbol_map.lookup_sync(LookupAddress::Relative(address)) {
@@ -160,7 +164,9 @@ fn create_response(
     request: &request_json::Request,
     symbolicated_addresses: HashMap<Lib, Result<LookedUpAddresses, samply_symbols::Error>>,
 ) -> response_json::Response {
-    use response_json::{DebugInfo, FrameDebugInfo, Response, Stack, StackFrame, Symbol};
+    use response_json::{
+        DebugInfo, FrameDebugInfo, ModuleProvenance, Response, Stack, StackFrame, Symbol,
+    };
 
     fn result_for_job(
         job: &request_json::Job,
@@ -168,6 +174,7 @@ fn create_response(
     ) -> response_json::Result {
         let mut found_modules = HashMap::new();
         let mut module_errors = HashMap::new();
+        let mut module_provenance = HashMap::new();
         let mut symbols_by_module_index = HashMap::new();
         for (module_index, lib) in job.memory_map.iter().enumerate() {
             if let Some(symbol_result) = symbolicated_addresses.get(lib) {
@@ -176,6 +183,14 @@ fn create_response(
                     Ok(symbols) => {
                         symbols_by_module_index
                             .insert(module_index as u32, &symbols.address_results);
+                        if let Some(symbol_file) = &symbols.symbol_file {
+                            let provenance = ModuleProvenance {
+                                file: symbol_file.file.clone(),
+                                debug_id: symbol_file.debug_id.breakpad().to_string(),
+                                has_inline_info: symbols.has_inline_info(),
+                            };
+                            module_provenance.insert(module_key.clone(), provenance);
+                        }
                     }
                     Err(err) => {
                         module_errors.insert(module_key.clone(), vec![err.into()]);
@@ -193,6 +208,7 @@ fn create_response(
             stacks: stacks.collect(),
             found_modules,
             module_errors,
+            module_provenance,
         }
     }
 
@@ -15,6 +15,22 @@ pub struct Result {
 
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub module_errors: HashMap<String, Vec<Error>>,
+
+    /// Where the symbols for each found module came from, so that users can
+    /// tell why two symbolications of the same binary differ.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub module_provenance: HashMap<String, ModuleProvenance>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ModuleProvenance {
+    /// The path or URL of the file which supplied the symbols.
+    pub file: String,
+    /// The debug ID of that file, in breakpad format.
+    pub debug_id: String,
+    /// Whether the file had debug info with line numbers and inline frames
+    /// for any of the requested addresses.
+    pub has_inline_info: bool,
 }
 
 #[derive(Serialize, Debug)]
@@ -137,6 +153,16 @@ mod test {
                 .cloned()
                 .collect(),
                 module_errors: HashMap::new(),
+                module_provenance: [(
+                    String::from("xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2"),
+                    response_json::ModuleProvenance {
+                        file: String::from("https://symbols.example.com/xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2/xul.sym"),
+                        debug_id: String::from("44E4EC8C2F41492B9369D6B9A059577C2"),
+                        has_inline_info: false,
+                    },
+                )]
+                .into_iter()
+                .collect(),
             }],
         };
         let response = serde_json::to_string_pretty(&response)?;
@@ -161,6 +187,13 @@ mod test {
       ],
       "found_modules": {
         "xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2": true
+      },
+      "module_provenance": {
+        "xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2": {
+          "file": "https://symbols.example.com/xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2/xul.sym",
+          "debug_id": "44E4EC8C2F41492B9369D6B9A059577C2",
+          "has_inline_info": false
+        }
       }
     }
   ]
@@ -120,6 +120,15 @@ impl FileAndPathHelper for Helper {
         })
     }
 
+    fn describe_loaded_file(&self, location: &FileLocationType) -> String {
+        // Keep the snapshots independent of where the fixtures are checked out.
+        let path = location
+            .0
+            .strip_prefix(fixtures_dir())
+            .unwrap_or(&location.0);
+        path.to_string_lossy().replace('\\', "/")
+    }
+
     fn get_candidate_paths_for_binary(
         &self,
         library_info: &LibraryInfo,
@@ -442,3 +451,41 @@ fn asm_x86_64() {
         "output-asm_x86_64.txt",
     )
 }
+
+#[test]
+fn functions_win64() {
+    compare_snapshot(
+        "/functions/v1",
+        r#"{
+            "modules": [
+                {
+                    "debugName": "firefox.pdb",
+                    "debugId": "8A913DE821D9DE764C4C44205044422E1",
+                    "ranges": [
+                        { "start": "0x17a20", "end": "0x17a5a" },
+                        { "start": "0x516a0", "end": "0x516a8" }
+                    ]
+                }
+            ]
+        }"#,
+        fixtures_dir().join("win64-local"),
+        "functions_win64.txt",
+        "output-functions_win64.txt",
+    )
+}
+
+#[test]
+fn symbol_table_win64() {
+    compare_snapshot(
+        "/symbol-table/v1",
+        r#"{
+            "debugName": "firefox.pdb",
+            "debugId": "8A913DE821D9DE764C4C44205044422E1",
+            "startAddress": "0x17a00",
+            "limit": 3
+        }"#,
+        fixtures_dir().join("win64-local"),
+        "symbol_table_win64.txt",
+        "output-symbol_table_win64.txt",
+    )
+}
@@ -0,0 +1,154 @@
+//! `samply-markers` lets an application add markers and counters to the
+//! profile while it is being recorded with `samply record`.
+//!
+//! When samply launches a process on Linux, it sets the `SAMPLY_MARKER_SOCKET`
+//! environment variable to the path of a socket. The functions in this crate
+//! send their messages to that socket, and samply adds them to the marker track
+//! of the calling thread. If the process isn't being profiled, or on other
+//! platforms, the functions do nothing.
+//!
+//! # Example
+//!
+//! ```
+//! fn render_frame() {
+//!     samply_markers::scope!("frame");
+//!     // ... The "frame" marker covers everything until the end of this scope.
+//!     samply_markers::counter("frames rendered", 1.0);
+//! }
+//! # render_frame();
+//! ```
+//!
+//! With the `tracing` feature, [`SamplyLayer`] turns the spans and events of
+//! applications which are instrumented with `tracing` into markers.
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod linux;
+#[cfg(feature = "tracing")]
+mod tracing_layer;
+
+#[cfg(feature = "tracing")]
+pub use tracing_layer::SamplyLayer;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use linux::{is_connected, send_message};
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn is_connected() -> bool {
+    false
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn send_message(_kind: char, _name: &str, _payload: Option<&str>) {}
+
+/// Returns whether the process is being recorded by samply, i.e. whether the
+/// markers and counters end up anywhere.
+pub fn is_enabled() -> bool {
+    is_connected()
+}
+
+/// Adds an instant marker.
+pub fn instant(name: &str) {
+    send_message('I', name, None);
+}
+
+/// Adds an instant marker with a payload, which is shown in the marker's
+/// tooltip.
+pub fn instant_with_payload(name: &str, payload: &str) {
+    send_message('I', name, Some(payload));
+}
+
+/// Begins an interval marker on the current thread. It lasts until
+/// [`end`] is called with the same name on the same thread.
+pub fn begin(name: &str) {
+    send_message('B', name, None);
+}
+
+/// Ends the most recent interval marker with this name on the current
+/// thread.
+pub fn end(name: &str) {
+    send_message('E', name, None);
+}
+
+/// Adds `delta` to the process's counter with this name.
+pub fn counter(name: &str, delta: f64) {
+    if is_enabled() {
+        send_message('C', name, Some(&delta.to_string()));
+    }
+}
+
+/// Ends an interval marker when it is dropped. Created by [`scope!`].
+#[must_use = "the marker ends when the guard is dropped"]
+pub struct ScopeGuard {
+    name: &'static str,
+}
+
+impl ScopeGuard {
+    /// Begins an interval marker which ends when the guard is dropped.
+    pub fn new(name: &'static str) -> Self {
+        begin(name);
+        Self { name }
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        end(self.name);
+    }
+}
+
+/// Adds an interval marker which lasts until the end of the current scope.
+///
+/// ```
+/// fn load_config() {
+///     samply_markers::scope!("load config");
+///     // ...
+/// }
+/// # load_config();
+/// ```
+#[macro_export]
+macro_rules! scope {
+    ($name:expr) => {
+        let _samply_markers_scope_guard = $crate::ScopeGuard::new($name);
+    };
+}
+
+/// Formats a message of samply's marker socket protocol. Tabs and newlines
+/// in the name and payload would break up the message, so they are replaced
+/// with spaces.
+#[cfg_attr(
+    not(any(target_os = "linux", target_os = "android", test)),
+    allow(dead_code)
+)]
+fn format_message(
+    kind: char,
+    timestamp_ns: u64,
+    tid: i64,
+    name: &str,
+    payload: Option<&str>,
+) -> String {
+    let sanitize = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+    let mut message = format!("{kind} {timestamp_ns} {tid} {}", sanitize(name));
+    if let Some(payload) = payload {
+        message.push('\t');
+        message.push_str(&sanitize(payload));
+    }
+    message.push('\n');
+    message
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_messages() {
+        assert_eq!(
+            format_message('B', 1000, 42, "load\tfile", None),
+            "B 1000 42 load file\n"
+        );
+        assert_eq!(
+            format_message('C', 2000, 42, "bytes", Some("1.5\n")),
+            "C 2000 42 bytes\t1.5 \n"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::sync::{Mutex, OnceLock};
+
+use crate::format_message;
+
+/// The connection to samply's marker socket, or `None` if the process isn't
+/// being recorded.
+static CONNECTION: OnceLock<Option<Mutex<UnixStream>>> = OnceLock::new();
+
+fn connection() -> Option<&'static Mutex<UnixStream>> {
+    CONNECTION
+        .get_or_init(|| {
+            let path = std::env::var_os("SAMPLY_MARKER_SOCKET")?;
+            UnixStream::connect(path).ok().map(Mutex::new)
+        })
+        .as_ref()
+}
+
+pub fn is_connected() -> bool {
+    connection().is_some()
+}
+
+pub fn send_message(kind: char, name: &str, payload: Option<&str>) {
+    let Some(connection) = connection() else {
+        return;
+    };
+    let message = format_message(kind, monotonic_time_ns(), current_tid(), name, payload);
+    if let Ok(mut stream) = connection.lock() {
+        // If samply has stopped recording, there's nobody to tell.
+        let _ = stream.write_all(message.as_bytes());
+    }
+}
+
+/// The current time from `CLOCK_MONOTONIC`, which is the clock of samply's
+/// perf events.
+fn monotonic_time_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+fn current_tid() -> i64 {
+    unsafe { libc::syscall(libc::SYS_gettid) }
+}
@@ -0,0 +1,79 @@
+use std::fmt::{self, Write};
+
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::Id;
+use tracing_core::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// A [`Layer`] which turns `tracing` spans into interval markers and
+/// `tracing` events into instant markers.
+///
+/// A span's marker covers the time between entering and exiting the span,
+/// on the thread which entered it. An async task's span therefore gets one
+/// marker for each poll of the task.
+///
+/// ```
+/// use tracing_subscriber::layer::SubscriberExt;
+/// use tracing_subscriber::util::SubscriberInitExt;
+///
+/// tracing_subscriber::registry()
+///     .with(samply_markers::SamplyLayer::new())
+///     .init();
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SamplyLayer {
+    _private: (),
+}
+
+impl SamplyLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for SamplyLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            crate::begin(span.name());
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            crate::end(span.name());
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if !crate::is_enabled() {
+            return;
+        }
+        let metadata = event.metadata();
+        let name = format!("{} {}", metadata.level(), metadata.target());
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        crate::instant_with_payload(&name, &visitor.fields);
+    }
+}
+
+/// Formats the message and fields of an event as `message key=value ...`.
+#[derive(Default)]
+struct FieldVisitor {
+    fields: String,
+}
+
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+        let _ = match field.name() {
+            "message" => write!(self.fields, "{value:?}"),
+            name => write!(self.fields, "{name}={value:?}"),
+        };
+    }
+}
@@ -346,6 +346,7 @@ fn object_arch_to_string(arch: object::Architecture) -> Option<&'static str> {
         object::Architecture::Aarch64 => "arm64",
         object::Architecture::I386 => "x86",
         object::Architecture::X86_64 => "x86_64",
+        object::Architecture::Riscv64 => "riscv64",
         _ => return None,
     };
     Some(s)
@@ -357,6 +358,7 @@ fn elf_machine_arch_to_string(elf_machine_arch: u32) -> Option<&'static str> {
         object::elf::EM_AARCH64 => "arm64",
         object::elf::EM_386 => "x86",
         object::elf::EM_X86_64 => "x86_64",
+        object::elf::EM_RISCV => "riscv64",
         _ => return None,
     };
     Some(s)
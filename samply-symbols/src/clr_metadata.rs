@@ -0,0 +1,347 @@
+//! Reading ECMA-335 metadata, the format of the type and method tables of
+//! .NET assemblies, which is also used by portable PDBs.
+
+use object::pe::IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR;
+use object::read::pe::{ImageNtHeaders, PeFile};
+use object::ReadRef;
+
+const METADATA_SIGNATURE: u32 = 0x424A_5342; // "BSJB"
+
+/// A row of the MethodDef table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodDef {
+    /// The relative address of the method body, or 0 if the method has no
+    /// body, e.g. because it is abstract.
+    pub rva: u32,
+    /// The method name, in the form `Namespace.Type::Method`.
+    pub name: String,
+}
+
+/// Returns the `rva..rva + size` range of the image's data.
+pub fn image_data<'data, Pe: ImageNtHeaders, R: ReadRef<'data>>(
+    pe: &PeFile<'data, Pe, R>,
+    (rva, size): (u32, u32),
+) -> Option<&'data [u8]> {
+    pe.section_table()
+        .pe_data_at(pe.data(), rva)?
+        .get(..size as usize)
+}
+
+/// Returns the CLI header and the metadata of a .NET assembly, or `None` if
+/// the image has no managed code.
+pub fn cli_header_and_metadata<'data, Pe: ImageNtHeaders, R: ReadRef<'data>>(
+    pe: &PeFile<'data, Pe, R>,
+) -> Option<(&'data [u8], &'data [u8])> {
+    let cor_header = image_data(
+        pe,
+        pe.data_directory(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR)?
+            .address_range(),
+    )?;
+    let metadata = image_data(pe, (read_u32(cor_header, 8)?, read_u32(cor_header, 12)?))?;
+    Some((cor_header, metadata))
+}
+
+/// Returns the stream with the given name, e.g. `#Strings`, from the
+/// metadata root.
+pub fn metadata_stream<'a>(metadata: &'a [u8], stream_name: &[u8]) -> Option<&'a [u8]> {
+    if read_u32(metadata, 0)? != METADATA_SIGNATURE {
+        return None;
+    }
+    let version_len = read_u32(metadata, 12)? as usize;
+    let stream_count_offset = 16 + version_len + 2;
+    let stream_count = read_u16(metadata, stream_count_offset)?;
+
+    let mut offset = stream_count_offset + 2;
+    for _ in 0..stream_count {
+        let stream_offset = read_u32(metadata, offset)? as usize;
+        let stream_size = read_u32(metadata, offset + 4)? as usize;
+        let name = null_terminated(metadata.get(offset + 8..)?);
+        if name == stream_name {
+            return metadata.get(stream_offset..stream_offset.checked_add(stream_size)?);
+        }
+        offset += 8 + (name.len() + 4) / 4 * 4;
+    }
+    None
+}
+
+/// Returns all rows of the MethodDef table. The method with row ID `rid` is
+/// at index `rid - 1`.
+pub fn method_defs(metadata: &[u8]) -> Option<Vec<MethodDef>> {
+    let tables = metadata_stream(metadata, b"#~").or_else(|| metadata_stream(metadata, b"#-"))?;
+    let strings = metadata_stream(metadata, b"#Strings")?;
+    MetadataTables::parse(tables)?.method_defs(strings)
+}
+
+// The metadata tables which are needed to find the start of the TypeDef and
+// MethodDef tables, and the tables which affect the size of their columns.
+const TABLE_MODULE: usize = 0x00;
+const TABLE_TYPEREF: usize = 0x01;
+const TABLE_TYPEDEF: usize = 0x02;
+const TABLE_FIELDPTR: usize = 0x03;
+const TABLE_FIELD: usize = 0x04;
+const TABLE_METHODPTR: usize = 0x05;
+const TABLE_METHODDEF: usize = 0x06;
+const TABLE_PARAMPTR: usize = 0x07;
+const TABLE_PARAM: usize = 0x08;
+const TABLE_MODULEREF: usize = 0x1A;
+const TABLE_TYPESPEC: usize = 0x1B;
+const TABLE_ASSEMBLYREF: usize = 0x23;
+
+/// The `#~` stream, which has the metadata tables.
+pub struct MetadataTables<'a> {
+    data: &'a [u8],
+    row_counts: [u32; 64],
+    /// The row counts of the tables in another metadata file, which the
+    /// indexes of a portable PDB can refer to.
+    external_row_counts: [u32; 64],
+    pub string_index_size: usize,
+    pub guid_index_size: usize,
+    pub blob_index_size: usize,
+    /// The offset of the first table's rows.
+    pub tables_offset: usize,
+}
+
+impl<'a> MetadataTables<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let heap_sizes = *data.get(6)?;
+        let valid = read_u64(data, 8)?;
+        let mut row_counts = [0; 64];
+        let mut offset = 24;
+        for (table, row_count) in row_counts.iter_mut().enumerate() {
+            if valid & (1 << table) != 0 {
+                *row_count = read_u32(data, offset)?;
+                offset += 4;
+            }
+        }
+        if heap_sizes & 0x40 != 0 {
+            // Extra data in uncompressed (#-) streams.
+            offset += 4;
+        }
+        let heap_index_size = |flag: u8| if heap_sizes & flag != 0 { 4 } else { 2 };
+        Some(Self {
+            data,
+            row_counts,
+            external_row_counts: [0; 64],
+            string_index_size: heap_index_size(0x01),
+            guid_index_size: heap_index_size(0x02),
+            blob_index_size: heap_index_size(0x04),
+            tables_offset: offset,
+        })
+    }
+
+    pub fn row_count(&self, table: usize) -> usize {
+        self.row_counts[table] as usize
+    }
+
+    /// Sets the row counts of the assembly's tables, for the tables of a
+    /// portable PDB.
+    pub fn set_external_row_counts(&mut self, row_counts: [u32; 64]) {
+        self.external_row_counts = row_counts;
+    }
+
+    fn index_row_count(&self, table: usize) -> u32 {
+        self.row_counts[table].max(self.external_row_counts[table])
+    }
+
+    pub fn index_size(&self, table: usize) -> usize {
+        if self.index_row_count(table) < 0x10000 {
+            2
+        } else {
+            4
+        }
+    }
+
+    pub fn coded_index_size(&self, tables: &[usize], tag_bits: u32) -> usize {
+        let max_row_count = tables.iter().map(|t| self.index_row_count(*t)).max();
+        if max_row_count.unwrap_or(0) < (1 << (16 - tag_bits)) {
+            2
+        } else {
+            4
+        }
+    }
+
+    pub fn read_index(&self, offset: usize, size: usize) -> Option<u32> {
+        match size {
+            2 => read_u16(self.data, offset).map(u32::from),
+            _ => read_u32(self.data, offset),
+        }
+    }
+
+    fn method_defs(&self, strings: &[u8]) -> Option<Vec<MethodDef>> {
+        if self.row_count(TABLE_METHODPTR) != 0 {
+            // Only edit-and-continue metadata has these indirections.
+            return None;
+        }
+        let string = |index: u32| {
+            let bytes = null_terminated(strings.get(index as usize..).unwrap_or_default());
+            String::from_utf8_lossy(bytes)
+        };
+
+        let str_size = self.string_index_size;
+        let module_row_size = 2 + str_size + 3 * self.guid_index_size;
+        let resolution_scope_size = self.coded_index_size(
+            &[
+                TABLE_MODULE,
+                TABLE_MODULEREF,
+                TABLE_ASSEMBLYREF,
+                TABLE_TYPEREF,
+            ],
+            2,
+        );
+        let typeref_row_size = resolution_scope_size + 2 * str_size;
+        let field_list_size = match self.row_count(TABLE_FIELDPTR) {
+            0 => self.index_size(TABLE_FIELD),
+            _ => self.index_size(TABLE_FIELDPTR),
+        };
+        let method_list_size = self.index_size(TABLE_METHODDEF);
+        let extends_size =
+            self.coded_index_size(&[TABLE_TYPEDEF, TABLE_TYPEREF, TABLE_TYPESPEC], 2);
+        let typedef_row_size = 4 + 2 * str_size + extends_size + field_list_size + method_list_size;
+        let fieldptr_row_size = self.index_size(TABLE_FIELD);
+        let field_row_size = 2 + str_size + self.blob_index_size;
+        let param_list_size = match self.row_count(TABLE_PARAMPTR) {
+            0 => self.index_size(TABLE_PARAM),
+            _ => self.index_size(TABLE_PARAMPTR),
+        };
+        let methoddef_row_size = 8 + str_size + self.blob_index_size + param_list_size;
+
+        let typedef_offset = self.tables_offset
+            + self.row_count(TABLE_MODULE) * module_row_size
+            + self.row_count(TABLE_TYPEREF) * typeref_row_size;
+        let methoddef_offset = typedef_offset
+            + self.row_count(TABLE_TYPEDEF) * typedef_row_size
+            + self.row_count(TABLE_FIELDPTR) * fieldptr_row_size
+            + self.row_count(TABLE_FIELD) * field_row_size;
+
+        let method_count = self.row_count(TABLE_METHODDEF);
+        let mut methods = Vec::with_capacity(method_count);
+        for rid in 0..method_count {
+            let offset = methoddef_offset + rid * methoddef_row_size;
+            methods.push(MethodDef {
+                rva: read_u32(self.data, offset)?,
+                name: string(self.read_index(offset + 8, str_size)?).into_owned(),
+            });
+        }
+
+        // Each type owns the methods from its MethodList up to the MethodList
+        // of the next type.
+        let typedef_count = self.row_count(TABLE_TYPEDEF);
+        let method_list_column = 4 + 2 * str_size + extends_size + field_list_size;
+        let method_list = |index: usize| {
+            let offset = typedef_offset + index * typedef_row_size + method_list_column;
+            self.read_index(offset, method_list_size)
+        };
+        for index in 0..typedef_count {
+            let offset = typedef_offset + index * typedef_row_size;
+            let name = string(self.read_index(offset + 4, str_size)?);
+            let namespace = string(self.read_index(offset + 4 + str_size, str_size)?);
+            let first_method = method_list(index)? as usize;
+            let end_method = if index + 1 < typedef_count {
+                method_list(index + 1)? as usize
+            } else {
+                method_count + 1
+            };
+            for rid in first_method..end_method.min(method_count + 1) {
+                let Some(method) = rid.checked_sub(1).and_then(|i| methods.get_mut(i)) else {
+                    continue;
+                };
+                let method_name = &method.name;
+                method.name = match namespace.as_ref() {
+                    "" => format!("{name}::{method_name}"),
+                    namespace => format!("{namespace}.{name}::{method_name}"),
+                };
+            }
+        }
+        Some(methods)
+    }
+}
+
+pub fn null_terminated(data: &[u8]) -> &[u8] {
+    let len = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    &data[..len]
+}
+
+pub fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+pub fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Reads the blob at `index` in the `#Blob` heap.
+pub fn blob(blobs: &[u8], index: u32) -> Option<&[u8]> {
+    let (len, offset) = decode_compressed_unsigned(blobs, index as usize)?;
+    blobs.get(offset..offset.checked_add(len as usize)?)
+}
+
+/// Reads the compressed unsigned integer at `offset`, in the encoding of
+/// ECMA-335 II.23.2. Returns the value and the offset after it.
+pub fn decode_compressed_unsigned(data: &[u8], offset: usize) -> Option<(u32, usize)> {
+    let first = u32::from(*data.get(offset)?);
+    let decoded = if first & 0x80 == 0 {
+        (first, offset + 1)
+    } else if first & 0x40 == 0 {
+        let second = u32::from(*data.get(offset + 1)?);
+        (((first & 0x3f) << 8) | second, offset + 2)
+    } else if first & 0x20 == 0 {
+        let bytes = data.get(offset..offset + 4)?;
+        let value = u32::from_be_bytes(bytes.try_into().ok()?);
+        (value & 0x1fff_ffff, offset + 4)
+    } else {
+        return None;
+    };
+    Some(decoded)
+}
+
+/// Reads a compressed signed integer. The sign is in the lowest bit of the
+/// encoded value, which is rotated by one bit.
+pub fn decode_compressed_signed(data: &[u8], offset: usize) -> Option<(i32, usize)> {
+    let (encoded, next_offset) = decode_compressed_unsigned(data, offset)?;
+    let value = (encoded >> 1) as i32;
+    if encoded & 1 == 0 {
+        return Some((value, next_offset));
+    }
+    let min = match next_offset - offset {
+        1 => -0x40,
+        2 => -0x2000,
+        _ => -0x1000_0000,
+    };
+    Some((value + min, next_offset))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compressed_integers() {
+        assert_eq!(decode_compressed_unsigned(&[0x03], 0), Some((0x03, 1)));
+        assert_eq!(
+            decode_compressed_unsigned(&[0x80, 0x80], 0),
+            Some((0x80, 2))
+        );
+        assert_eq!(
+            decode_compressed_unsigned(&[0xc0, 0x00, 0x40, 0x00], 0),
+            Some((0x4000, 4))
+        );
+        assert_eq!(decode_compressed_signed(&[0x06], 0), Some((3, 1)));
+        assert_eq!(decode_compressed_signed(&[0x7b], 0), Some((-3, 1)));
+        assert_eq!(decode_compressed_signed(&[0x80, 0x80], 0), Some((64, 2)));
+        assert_eq!(decode_compressed_signed(&[0x01], 0), Some((-64, 1)));
+        assert_eq!(
+            decode_compressed_signed(&[0xc0, 0x00, 0x00, 0x01], 0),
+            Some((-0x1000_0000, 4))
+        );
+    }
+}
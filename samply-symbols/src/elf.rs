@@ -415,6 +415,7 @@ impl<'data, T: FileContents + 'static> ElfObjectsTrait<T> for ElfObjects<'data,
             debug_id,
             function_starts.as_deref(),
             function_ends.as_deref(),
+            &[],
             self,
         );
 
@@ -146,6 +146,15 @@ pub enum Error {
     #[error("In the PE (Windows) binary, the embedded path to the PDB file did not end with a file name: {0}")]
     PdbPathWithoutFilename(String),
 
+    #[error("The portable PDB for the .NET assembly at path {0} could not be parsed")]
+    PortablePdbParseError(String),
+
+    #[error("The file is a portable PDB, which can only be used together with its .NET assembly")]
+    PortablePdbWithoutAssembly,
+
+    #[error("The .NET assembly at path {0} is a ReadyToRun image, so its portable PDB doesn't apply to its native code")]
+    PortablePdbForReadyToRunImage(String),
+
     #[error("Could not parse archive file at {0}, ArchiveFile::parse returned error: {1}.")]
     ArchiveParseError(PathBuf, #[source] Box<dyn std::error::Error + Send + Sync>),
 
@@ -283,6 +292,9 @@ impl Error {
             Error::NoMatchingPdbForBinary(_) => "NoMatchingPdbForBinary",
             Error::PdbPathNotUtf8(_) => "PdbPathNotUtf8",
             Error::PdbPathWithoutFilename(_) => "PdbPathWithoutFilename",
+            Error::PortablePdbParseError(_) => "PortablePdbParseError",
+            Error::PortablePdbWithoutAssembly => "PortablePdbWithoutAssembly",
+            Error::PortablePdbForReadyToRunImage(_) => "PortablePdbForReadyToRunImage",
             Error::ArchiveParseError(_, _) => "ArchiveParseError",
             Error::FileNotInArchive(_) => "FileNotInArchive",
             Error::PdbAddr2lineError(_) => "PdbAddr2lineError",
@@ -5,8 +5,8 @@ use std::sync::Mutex;
 
 use debugid::DebugId;
 use linux_perf_data::jitdump::{
-    JitCodeDebugInfoRecord, JitCodeLoadRecord, JitDumpReader, JitDumpRecord, JitDumpRecordHeader,
-    JitDumpRecordType,
+    JitCodeDebugInfoEntry, JitCodeDebugInfoRecord, JitCodeLoadRecord, JitDumpReader, JitDumpRecord,
+    JitDumpRecordHeader, JitDumpRecordType,
 };
 use linux_perf_data::linux_perf_event_reader::RawData;
 use linux_perf_data::Endianness;
@@ -85,6 +85,7 @@ impl JitDumpIndex {
 
                     entries.push(JitDumpIndexEntry {
                         code_load_record_offset: raw_record.start_offset,
+                        code_addr: record.code_addr,
                         code_bytes_offset: raw_record.start_offset
                             + record.code_bytes_offset_from_record_header_start() as u64,
                         name_len: record.function_name.len() as u32,
@@ -157,6 +158,8 @@ impl JitDumpIndex {
 #[derive(Debug, Clone)]
 pub struct JitDumpIndexEntry {
     pub code_load_record_offset: u64,
+    /// The address of the code when it was loaded, before any moves.
+    pub code_addr: u64,
     pub code_bytes_offset: u64,
     pub name_len: u32,
     pub code_debug_info_record_offset_and_len: Option<(u64, u32)>,
@@ -261,6 +264,12 @@ impl<'a, T: FileContents> JitDumpSymbolMapCache<'a, T> {
                 let mut record_data = RawData::Single(record_data);
                 record_data.skip(JitDumpRecordHeader::SIZE).ok()?;
                 let record = JitCodeDebugInfoRecord::parse(self.index.endian, record_data).ok()?;
+                if record.code_addr != desc.code_addr {
+                    // The debug info record which came before the load record is
+                    // for a different function, e.g. because the runtime writes
+                    // from multiple threads.
+                    return None;
+                }
                 Some(entry.insert(record))
             }
         }
@@ -280,7 +289,7 @@ impl<'a, T: FileContents> JitDumpSymbolMapInner<'a, T> {
         let debug_info = cache.get_debug_info(index);
         let frames = debug_info.and_then(|debug_info| {
             let lookup_avma = debug_info.code_addr + offset_relative_to_symbol;
-            let entry = debug_info.lookup(lookup_avma)?;
+            let entry = lookup_debug_info_entry(debug_info, lookup_avma)?;
             let file_path = String::from_utf8_lossy(&entry.file_path.as_slice()).into_owned();
             let frame = FrameDebugInfo {
                 function: Some(name.clone()),
@@ -300,6 +309,23 @@ impl<'a, T: FileContents> JitDumpSymbolMapInner<'a, T> {
     }
 }
 
+/// Finds the line entry which covers `avma`: the entry with the highest
+/// address at or below `avma`.
+///
+/// This doesn't rely on the entries being sorted by address. Runtimes like
+/// SpiderMonkey emit them in the order in which the code was generated, which
+/// isn't always the address order.
+fn lookup_debug_info_entry<'r, 'a>(
+    debug_info: &'r JitCodeDebugInfoRecord<'a>,
+    avma: u64,
+) -> Option<&'r JitCodeDebugInfoEntry<'a>> {
+    debug_info
+        .entries
+        .iter()
+        .filter(|entry| entry.code_addr <= avma)
+        .max_by_key(|entry| entry.code_addr)
+}
+
 impl<'a, T: FileContents> SymbolMapTrait for JitDumpSymbolMapInner<'a, T> {
     fn debug_id(&self) -> debugid::DebugId {
         self.index.debug_id
@@ -331,3 +357,27 @@ impl<'a, T: FileContents> SymbolMapTrait for JitDumpSymbolMapInner<'a, T> {
         self.lookup_by_entry_index(index, symbol_address, offset_from_symbol)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup_unsorted_debug_info() {
+        let entry = |code_addr, line| JitCodeDebugInfoEntry {
+            code_addr,
+            line,
+            column: 0,
+            file_path: RawData::Single(b"script.js"),
+        };
+        let debug_info = JitCodeDebugInfoRecord {
+            code_addr: 0x1000,
+            entries: vec![entry(0x1000, 10), entry(0x1040, 14), entry(0x1020, 12)],
+        };
+        let line_at = |avma| lookup_debug_info_entry(&debug_info, avma).map(|e| e.line);
+        assert_eq!(line_at(0xfff), None);
+        assert_eq!(line_at(0x1000), Some(10));
+        assert_eq!(line_at(0x1030), Some(12));
+        assert_eq!(line_at(0x1050), Some(14));
+    }
+}
@@ -55,6 +55,8 @@
 //! based on debug data, i.e. inline callstacks where each frame has a function name, a file name,
 //! and a line number.
 //! For debug data we support both DWARF debug data (inside mach-o and ELF binaries) and PDB debug data.
+//! Portable PDB files of .NET assemblies are supported together with their assembly; their addresses
+//! are the addresses of the IL code in the assembly.
 //!
 //! # Example
 //!
@@ -218,6 +220,7 @@ mod binary_image;
 mod breakpad;
 mod cache;
 mod chunked_read_buffer_manager;
+mod clr_metadata;
 mod compact_symbol_table;
 mod debugid_util;
 mod demangle;
@@ -230,9 +233,13 @@ mod jitdump;
 mod macho;
 mod mapped_path;
 mod path_mapper;
+mod portable_pdb;
+mod ready_to_run;
 mod shared;
+mod sourcelink;
 mod symbol_map;
 mod symbol_map_object;
+mod symbol_table_page;
 mod windows;
 
 pub use crate::binary_image::{BinaryImage, CodeByteReadingError};
@@ -253,13 +260,32 @@ pub use crate::shared::{
     ExternalFileAddressInFileRef, ExternalFileAddressRef, ExternalFileRef, FileAndPathHelper,
     FileAndPathHelperError, FileAndPathHelperResult, FileContents, FileContentsWrapper,
     FileLocation, FrameDebugInfo, FramesLookupResult, LibraryInfo, LookupAddress,
-    MultiArchDisambiguator, OptionallySendFuture, PeCodeId, SourceFilePath, SymbolInfo,
-    SyncAddressInfo,
+    MultiArchDisambiguator, OptionallySendFuture, PeCodeId, SourceFilePath, SourceLineRange,
+    SymbolInfo, SyncAddressInfo,
 };
 pub use crate::symbol_map::{SymbolMap, SymbolMapTrait};
+pub use crate::symbol_table_page::{SymbolTableEntry, SymbolTablePage};
+
+/// A trait for observing which files [`SymbolManager::load_symbol_map`] tries.
+/// This can be used to explain why a library ends up without symbols.
+pub trait SymbolMapLoadObserver: Send + Sync + 'static {
+    /// Called for each candidate file after it was tried, in the order in which
+    /// they are tried. `result` is `Ok` for the file whose symbol map is used.
+    fn on_candidate_tried(
+        &self,
+        library_info: &LibraryInfo,
+        location: &str,
+        result: Result<(), &Error>,
+    );
+
+    /// Called once per `load_symbol_map` call, after the last candidate.
+    fn on_symbol_map_load_finished(&self, library_info: &LibraryInfo, result: Result<(), &Error>);
+}
 
 pub struct SymbolManager<H: FileAndPathHelper> {
     helper: Arc<H>,
+    load_observer: Option<Arc<dyn SymbolMapLoadObserver>>,
+    pdb_function_arguments: bool,
 }
 
 impl<H, F, FL> SymbolManager<H>
@@ -272,22 +298,59 @@ where
     pub fn with_helper(helper: H) -> Self {
         Self {
             helper: Arc::new(helper),
+            load_observer: None,
+            pdb_function_arguments: true,
         }
     }
 
+    /// Set the observer which is told about the files that `load_symbol_map` tries.
+    pub fn set_load_observer(&mut self, observer: Option<Arc<dyn SymbolMapLoadObserver>>) {
+        self.load_observer = observer;
+    }
+
+    /// Set whether the names of functions from PDB files include the argument
+    /// types, so that overloaded functions can be told apart. This is on by
+    /// default. It only affects names which are formatted from the PDB's type
+    /// information, not demangled names of public symbols.
+    pub fn set_pdb_function_arguments(&mut self, include: bool) {
+        self.pdb_function_arguments = include;
+    }
+
     /// Exposes the helper.
     pub fn helper(&self) -> Arc<H> {
         self.helper.clone()
     }
 
+    /// Describes the file which supplied the symbols in `symbol_map`, see
+    /// [`FileAndPathHelper::describe_loaded_file`].
+    pub fn debug_file_description(&self, symbol_map: &SymbolMap<H>) -> String {
+        self.helper
+            .describe_loaded_file(symbol_map.debug_file_location())
+    }
+
+    /// Loads a source file from the path in the debug file, or, if that fails,
+    /// from its mapped path.
     pub async fn load_source_file(
         &self,
         debug_file_location: &H::FL,
         source_file_path: &SourceFilePath,
     ) -> Result<String, Error> {
-        let source_file_location = debug_file_location
-            .location_for_source_file(source_file_path.raw_path())
-            .ok_or(Error::FileLocationRefusedSourceFileLocation)?;
+        let raw_location =
+            debug_file_location.location_for_source_file(source_file_path.raw_path());
+        let mapped_location = source_file_path.mapped_path().and_then(|mapped_path| {
+            debug_file_location.location_for_mapped_source_file(mapped_path)
+        });
+        let mut last_error = Error::FileLocationRefusedSourceFileLocation;
+        for location in raw_location.into_iter().chain(mapped_location) {
+            match self.load_source_file_at(location).await {
+                Ok(source) => return Ok(source),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn load_source_file_at(&self, source_file_location: H::FL) -> Result<String, Error> {
         let file_contents = self
             .helper
             .load_file(source_file_location.clone())
@@ -304,11 +367,25 @@ where
     /// Obtain a symbol map for the library, given the (partial) `LibraryInfo`.
     /// At least the debug_id has to be given.
     pub async fn load_symbol_map(&self, library_info: &LibraryInfo) -> Result<SymbolMap<H>, Error> {
+        let result = self.load_symbol_map_impl(library_info).await;
+        if let Some(observer) = &self.load_observer {
+            observer.on_symbol_map_load_finished(library_info, result.as_ref().map(|_| ()));
+        }
+        result
+    }
+
+    async fn load_symbol_map_impl(
+        &self,
+        library_info: &LibraryInfo,
+    ) -> Result<SymbolMap<H>, Error> {
         if let Some((fl, symbol_map)) = self
             .helper()
             .as_ref()
             .get_symbol_map_for_library(library_info)
         {
+            if let Some(observer) = &self.load_observer {
+                observer.on_candidate_tried(library_info, &fl.to_string(), Ok(()));
+            }
             return Ok(SymbolMap::with_symbol_map_trait(fl, symbol_map));
         }
 
@@ -329,6 +406,13 @@ where
 
         let mut all_errors = Vec::new();
         for candidate_info in candidate_paths {
+            let location = match &candidate_info {
+                CandidatePathInfo::SingleFile(file_location) => file_location.to_string(),
+                CandidatePathInfo::InDyldCache {
+                    dyld_cache_path,
+                    dylib_path,
+                } => format!("{dylib_path} in {dyld_cache_path}"),
+            };
             let symbol_map = match candidate_info {
                 CandidatePathInfo::SingleFile(file_location) => {
                     self.load_symbol_map_from_location(
@@ -350,15 +434,20 @@ where
                 }
             };
 
-            match symbol_map {
-                Ok(symbol_map) if symbol_map.debug_id() == debug_id => return Ok(symbol_map),
-                Ok(symbol_map) => {
-                    all_errors.push(Error::UnmatchedDebugId(symbol_map.debug_id(), debug_id));
-                }
-                Err(e) => {
-                    all_errors.push(e);
+            let error = match symbol_map {
+                Ok(symbol_map) if symbol_map.debug_id() == debug_id => {
+                    if let Some(observer) = &self.load_observer {
+                        observer.on_candidate_tried(library_info, &location, Ok(()));
+                    }
+                    return Ok(symbol_map);
                 }
+                Ok(symbol_map) => Error::UnmatchedDebugId(symbol_map.debug_id(), debug_id),
+                Err(e) => e,
+            };
+            if let Some(observer) = &self.load_observer {
+                observer.on_candidate_tried(library_info, &location, Err(&error));
             }
+            all_errors.push(error);
         }
         let err = match all_errors.len() {
             0 => Error::NoCandidatePathForDebugFile(Box::new(library_info.clone())),
@@ -590,6 +679,7 @@ where
                         &file_contents,
                         file_location.clone(),
                         &*self.helper,
+                        self.pdb_function_arguments,
                     )
                     .await
                     {
@@ -607,7 +697,13 @@ where
                 )),
             }
         } else if windows::is_pdb_file(&file_contents) {
-            windows::get_symbol_map_for_pdb(file_contents, file_location)
+            windows::get_symbol_map_for_pdb(
+                file_contents,
+                file_location,
+                self.pdb_function_arguments,
+            )
+        } else if portable_pdb::is_portable_pdb_file(&file_contents) {
+            Err(Error::PortablePdbWithoutAssembly)
         } else if breakpad::is_breakpad_file(&file_contents) {
             let index_file_contents =
                 if let Some(index_file_location) = file_location.location_for_breakpad_symindex() {
@@ -416,6 +416,7 @@ impl<T: FileContents + 'static> ObjectSymbolMapOuter<T> for FileDataAndObject<T>
             debug_id,
             function_starts.as_deref(),
             function_ends.as_deref(),
+            &[],
             &(),
         );
 
@@ -41,6 +41,24 @@ pub enum MappedPath {
         /// The path to this file inside the bucket, e.g. `"ipc/ipdl/PBackgroundChild.cpp"`.
         path: String,
     },
+    /// A path to a file in a Perforce depot.
+    Perforce {
+        /// The Perforce server, e.g. `"perforce.example.com:1666"`.
+        server: String,
+        /// The depot path of this file without the leading `//`, e.g. `"depot/main/Source/Engine.cpp"`.
+        path: String,
+        /// The revision specifier, e.g. `"#3"` for a file revision or `"@12345"` for a changelist.
+        rev: String,
+    },
+    /// A path to a file in a Subversion repository (svn).
+    Svn {
+        /// The URL of the repository, e.g. `"https://svn.example.com/repos/game"`.
+        repo: String,
+        /// The path to this file inside the repository, e.g. `"trunk/src/main.cpp"`.
+        path: String,
+        /// The revision number, e.g. `"1234"`.
+        rev: String,
+    },
     /// A path to a file in a Rust package which is hosted in a cargo registry (usually on crates.io).
     Cargo {
         /// The name of the cargo registry, usually `"github.com-1ecc6299db9ec823"`.
@@ -86,6 +104,8 @@ impl MappedPath {
                 digest,
                 path,
             } => format!("s3:{bucket}:{digest}/{path}:"),
+            MappedPath::Perforce { server, path, rev } => format!("p4:{server}:{path}:{rev}"),
+            MappedPath::Svn { repo, path, rev } => format!("svn:{repo}:{path}:{rev}"),
             MappedPath::Cargo {
                 registry,
                 crate_name,
@@ -101,6 +121,8 @@ impl MappedPath {
             MappedPath::Git { path, .. } => path.clone(),
             MappedPath::Hg { path, .. } => path.clone(),
             MappedPath::S3 { path, .. } => path.clone(),
+            MappedPath::Perforce { path, .. } => path.clone(),
+            MappedPath::Svn { path, .. } => path.clone(),
             MappedPath::Cargo {
                 crate_name,
                 version,
@@ -133,6 +155,24 @@ fn s3_path(input: &str) -> IResult<&str, (String, String, String)> {
     Ok(("", (bucket.to_owned(), digest.to_owned(), path.to_owned())))
 }
 
+/// Parses paths whose repository may contain colons, e.g. for a port number,
+/// like `p4:perforce.example.com:1666:depot/main/file.cpp:#3`.
+fn repo_with_colons_path<'a>(
+    prefix: &'static str,
+) -> impl Fn(&'a str) -> IResult<&'a str, (String, String, String)> {
+    move |input| {
+        let (input, _) = tag(prefix)(input)?;
+        let mut parts = input.rsplitn(3, ':');
+        let (Some(rev), Some(path), Some(repo)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(Err::Error(nom::error::Error::new(input, ErrorKind::Tag)));
+        };
+        if repo.is_empty() || path.is_empty() || rev.is_empty() {
+            return Err(Err::Error(nom::error::Error::new(input, ErrorKind::Tag)));
+        }
+        Ok(("", (repo.to_owned(), path.to_owned(), rev.to_owned())))
+    }
+}
+
 fn cargo_path(input: &str) -> IResult<&str, (String, String, String, String)> {
     let (input, _) = tag("cargo:")(input)?;
     let (input, registry) = terminated(take_until1(":"), tag(":"))(input)?;
@@ -177,6 +217,12 @@ fn parse_special_path(input: &str) -> IResult<&str, MappedPath> {
             digest,
             path,
         }),
+        map(repo_with_colons_path("p4:"), |(server, path, rev)| {
+            MappedPath::Perforce { server, path, rev }
+        }),
+        map(repo_with_colons_path("svn:"), |(repo, path, rev)| {
+            MappedPath::Svn { repo, path, rev }
+        }),
         map(cargo_path, |(registry, crate_name, version, path)| {
             MappedPath::Cargo {
                 registry,
@@ -396,6 +442,34 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_perforce_and_svn_paths() {
+        assert_eq!(
+            MappedPath::from_special_path_str(
+                "p4:perforce.example.com:1666:depot/main/Source/Engine.cpp:#3"
+            ),
+            Some(MappedPath::Perforce {
+                server: "perforce.example.com:1666".to_string(),
+                path: "depot/main/Source/Engine.cpp".to_string(),
+                rev: "#3".to_string(),
+            })
+        );
+        assert_eq!(
+            MappedPath::from_special_path_str(
+                "svn:https://svn.example.com/repos/game:trunk/src/main.cpp:1234"
+            ),
+            Some(MappedPath::Svn {
+                repo: "https://svn.example.com/repos/game".to_string(),
+                path: "trunk/src/main.cpp".to_string(),
+                rev: "1234".to_string(),
+            })
+        );
+        assert_eq!(
+            MappedPath::from_special_path_str("svn:trunk/src/main.cpp:1234"),
+            None
+        );
+    }
+
     fn test_roundtrip(s: &str) {
         let mapped_path = MappedPath::from_special_path_str(s).unwrap();
         let roundtripped = mapped_path.to_special_path_str();
@@ -410,6 +484,8 @@ mod test {
         test_roundtrip("git:pdfium.googlesource.com/pdfium:core/fdrm/fx_crypt.cpp:dab1161c861cc239e48a17e1a5d729aa12785a53");
         test_roundtrip("s3:gecko-generated-sources:a5d3747707d6877b0e5cb0a364e3cb9fea8aa4feb6ead138952c2ba46d41045297286385f0e0470146f49403e46bd266e654dfca986de48c230f3a71c2aafed4/ipc/ipdl/PBackgroundChild.cpp:");
         test_roundtrip("s3:gecko-generated-sources:4fd754dd7ca7565035aaa3357b8cd99959a2dddceba0fc2f7018ef99fd78ea63d03f9bf928afdc29873089ee15431956791130b97f66ab8fcb88ec75f4ba6b04/aarch64-apple-darwin/release/build/swgl-580c7d646d09cf59/out/ps_text_run_ALPHA_PASS_TEXTURE_2D.h:");
+        test_roundtrip("p4:ssl:perforce.example.com:1666:depot/main/Source/Engine.cpp:@12345");
+        test_roundtrip("svn:https://svn.example.com:8443/repos/game:trunk/src/main.cpp:1234");
         test_roundtrip("cargo:github.com-1ecc6299db9ec823:addr2line-0.16.0:src/function.rs");
         test_roundtrip("cargo:github.com-1ecc6299db9ec823:tokio-1.6.1:src/runtime/task/mod.rs");
         test_roundtrip(
@@ -19,6 +19,19 @@ impl ExtraPathMapper for () {
     }
 }
 
+impl<E: ExtraPathMapper> ExtraPathMapper for Option<E> {
+    fn map_path(&mut self, path: &str) -> Option<MappedPath> {
+        self.as_mut()?.map_path(path)
+    }
+}
+
+/// Uses the second mapper for the paths which the first one doesn't map.
+impl<A: ExtraPathMapper, B: ExtraPathMapper> ExtraPathMapper for (A, B) {
+    fn map_path(&mut self, path: &str) -> Option<MappedPath> {
+        self.0.map_path(path).or_else(|| self.1.map_path(path))
+    }
+}
+
 pub struct PathMapper<E: ExtraPathMapper> {
     cache: HashMap<String, Option<MappedPath>>,
     extra_mapper: Option<E>,
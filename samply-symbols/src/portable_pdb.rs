@@ -0,0 +1,761 @@
+//! Symbol maps for .NET assemblies with portable PDBs.
+//!
+//! A portable PDB is the debug information of managed code. It is an ECMA-335
+//! metadata file whose tables map the IL instructions of each method to the
+//! source lines they were compiled from ("sequence points"). It doesn't have
+//! the method names or the method bodies, which are in the metadata of the
+//! assembly, so the symbol map is made from both files.
+//!
+//! The addresses in this symbol map are the relative addresses of the IL code
+//! in the assembly: the instruction at IL offset `o` in a method whose IL code
+//! starts at the RVA `r` has the relative address `r + o`. Native code which
+//! was compiled from the IL, by the JIT or ahead of time, has other addresses.
+
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use debugid::DebugId;
+use object::read::pe::{ImageNtHeaders, PeFile};
+use object::{LittleEndian as LE, ReadRef};
+
+use crate::clr_metadata::{
+    blob, cli_header_and_metadata, decode_compressed_signed, decode_compressed_unsigned,
+    metadata_stream, method_defs, read_u16, read_u32, read_u64, MetadataTables, MethodDef,
+};
+use crate::error::Error;
+use crate::path_mapper::PathMapper;
+use crate::shared::{
+    FileContents, FileContentsWrapper, FrameDebugInfo, FramesLookupResult, LookupAddress,
+    SourceFilePath, SymbolInfo,
+};
+use crate::sourcelink::SourceLinkPathMapper;
+use crate::symbol_map::{SymbolMap, SymbolMapTrait};
+use crate::{FileAndPathHelper, SyncAddressInfo};
+
+// The tables of a portable PDB, and the assembly's tables which their
+// indexes refer to.
+const TABLE_METHODDEF: usize = 0x06;
+const TABLE_DOCUMENT: usize = 0x30;
+const TABLE_METHODDEBUGINFORMATION: usize = 0x31;
+const TABLE_LOCALSCOPE: usize = 0x32;
+const TABLE_LOCALVARIABLE: usize = 0x33;
+const TABLE_LOCALCONSTANT: usize = 0x34;
+const TABLE_IMPORTSCOPE: usize = 0x35;
+const TABLE_STATEMACHINEMETHOD: usize = 0x36;
+const TABLE_CUSTOMDEBUGINFORMATION: usize = 0x37;
+
+/// The tables which a HasCustomDebugInformation coded index can refer to.
+const HAS_CUSTOM_DEBUG_INFORMATION_TABLES: &[usize] = &[
+    0x06, 0x04, 0x01, 0x02, 0x08, 0x09, 0x0A, 0x00, 0x0E, 0x17, 0x14, 0x11, 0x1A, 0x1B, 0x20, 0x23,
+    0x26, 0x27, 0x28, 0x2A, 0x2C, 0x2B, 0x30, 0x32, 0x33, 0x34, 0x35,
+];
+
+/// The Kind of the custom debug information with the SourceLink JSON,
+/// CC110556-A091-4D38-9FEC-25AB9A351A6A, as it is stored in the `#GUID` heap.
+const SOURCELINK_GUID: [u8; 16] = [
+    0x56, 0x05, 0x11, 0xCC, 0x91, 0xA0, 0x38, 0x4D, 0x9F, 0xEC, 0x25, 0xAB, 0x9A, 0x35, 0x1A, 0x6A,
+];
+
+pub fn is_portable_pdb_file<T: FileContents>(file_contents: &FileContentsWrapper<T>) -> bool {
+    if !matches!(file_contents.read_bytes_at(0, 4), Ok(b"BSJB")) {
+        return false;
+    }
+    match file_contents.read_bytes_at(0, file_contents.len()) {
+        Ok(data) => metadata_stream(data, b"#Pdb").is_some(),
+        Err(_) => false,
+    }
+}
+
+/// Creates a symbol map from a .NET assembly and its portable PDB.
+pub fn get_symbol_map_for_portable_pdb<'data, H: FileAndPathHelper, R: ReadRef<'data>>(
+    pe: &object::File<'data, R>,
+    pdb_contents: FileContentsWrapper<H::F>,
+    file_location: H::FL,
+) -> Result<SymbolMap<H>, Error> {
+    let symbol_map = match pe {
+        object::File::Pe32(pe) => PortablePdbSymbolMap::new(pe, &pdb_contents),
+        object::File::Pe64(pe) => PortablePdbSymbolMap::new(pe, &pdb_contents),
+        _ => None,
+    }
+    .ok_or_else(|| Error::PortablePdbParseError(file_location.to_string()))?;
+    Ok(SymbolMap::with_symbol_map_trait(
+        file_location,
+        Arc::new(symbol_map),
+    ))
+}
+
+/// The debug information of a portable PDB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PortablePdb {
+    /// The GUID and the timestamp which the assembly's CodeView entry refers to.
+    id: [u8; 20],
+    /// The document (source file) paths. The document with row ID `rid` is at
+    /// index `rid - 1`.
+    documents: Vec<String>,
+    /// The sequence points of each method. The sequence points of the method
+    /// with row ID `rid` in the assembly's MethodDef table are at index
+    /// `rid - 1`.
+    method_sequence_points: Vec<Vec<SequencePoint>>,
+    /// The SourceLink JSON, which maps the document paths to URLs.
+    sourcelink_json: Option<Vec<u8>>,
+}
+
+/// The IL instructions from `il_offset` up to the next sequence point of the
+/// method were compiled from the same source line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SequencePoint {
+    il_offset: u32,
+    /// The row ID of the document.
+    document: u32,
+    /// The start line, or `None` for hidden sequence points, which don't
+    /// correspond to a source line.
+    line: Option<u32>,
+}
+
+impl PortablePdb {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let pdb_stream = metadata_stream(data, b"#Pdb")?;
+        let id = pdb_stream.get(..20)?.try_into().ok()?;
+        let mut tables = MetadataTables::parse(metadata_stream(data, b"#~")?)?;
+        let blobs = metadata_stream(data, b"#Blob").unwrap_or_default();
+        let guids = metadata_stream(data, b"#GUID").unwrap_or_default();
+        if (0..TABLE_DOCUMENT).any(|table| tables.row_count(table) != 0) {
+            // Type system tables only exist in metadata which combines the
+            // assembly and its debug information.
+            return None;
+        }
+
+        // The #Pdb stream has the row counts of the assembly's tables, which
+        // determine the size of the indexes into them.
+        let referenced_tables = read_u64(pdb_stream, 24)?;
+        let mut external_row_counts = [0; 64];
+        let mut offset = 32;
+        for (table, row_count) in external_row_counts.iter_mut().enumerate() {
+            if referenced_tables & (1 << table) != 0 {
+                *row_count = read_u32(pdb_stream, offset)?;
+                offset += 4;
+            }
+        }
+        tables.set_external_row_counts(external_row_counts);
+
+        let blob_size = tables.blob_index_size;
+        let guid_size = tables.guid_index_size;
+        let document_index_size = tables.index_size(TABLE_DOCUMENT);
+        let method_index_size = tables.index_size(TABLE_METHODDEF);
+        let import_scope_index_size = tables.index_size(TABLE_IMPORTSCOPE);
+        let parent_size = tables.coded_index_size(HAS_CUSTOM_DEBUG_INFORMATION_TABLES, 5);
+        let row_sizes = [
+            // (Name, HashAlgorithm, Hash, Language)
+            (TABLE_DOCUMENT, 2 * blob_size + 2 * guid_size),
+            // (Document, SequencePoints)
+            (
+                TABLE_METHODDEBUGINFORMATION,
+                document_index_size + blob_size,
+            ),
+            // (Method, ImportScope, VariableList, ConstantList, StartOffset, Length)
+            (
+                TABLE_LOCALSCOPE,
+                method_index_size
+                    + import_scope_index_size
+                    + tables.index_size(TABLE_LOCALVARIABLE)
+                    + tables.index_size(TABLE_LOCALCONSTANT)
+                    + 8,
+            ),
+            // (Attributes, Index, Name)
+            (TABLE_LOCALVARIABLE, 4 + tables.string_index_size),
+            // (Name, Signature)
+            (TABLE_LOCALCONSTANT, tables.string_index_size + blob_size),
+            // (Parent, Imports)
+            (TABLE_IMPORTSCOPE, import_scope_index_size + blob_size),
+            // (MoveNextMethod, KickoffMethod)
+            (TABLE_STATEMACHINEMETHOD, 2 * method_index_size),
+            // (Parent, Kind, Value)
+            (
+                TABLE_CUSTOMDEBUGINFORMATION,
+                parent_size + guid_size + blob_size,
+            ),
+        ];
+        // The tables are stored one after another, in this order.
+        let rows = |table: usize| {
+            let preceding_tables = row_sizes.iter().take_while(|(t, _)| *t != table);
+            let table_offset = tables.tables_offset
+                + preceding_tables
+                    .map(|(t, row_size)| tables.row_count(*t) * row_size)
+                    .sum::<usize>();
+            let (_, row_size) = row_sizes.iter().find(|(t, _)| *t == table).unwrap();
+            (0..tables.row_count(table)).map(move |index| table_offset + index * row_size)
+        };
+        let blob_at = |offset: usize| {
+            let index = tables.read_index(offset, blob_size)?;
+            blob(blobs, index)
+        };
+
+        let documents = rows(TABLE_DOCUMENT)
+            .map(|offset| document_name(blobs, blob_at(offset)?))
+            .collect::<Option<Vec<_>>>()?;
+
+        let method_sequence_points = rows(TABLE_METHODDEBUGINFORMATION)
+            .map(|offset| {
+                let document = tables.read_index(offset, document_index_size)?;
+                sequence_points(blob_at(offset + document_index_size)?, document)
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let sourcelink_json = rows(TABLE_CUSTOMDEBUGINFORMATION).find_map(|offset| {
+            let kind = tables.read_index(offset + parent_size, guid_size)?;
+            let guid_offset = (kind.checked_sub(1)? as usize) * 16;
+            if guids.get(guid_offset..guid_offset + 16)? != SOURCELINK_GUID {
+                return None;
+            }
+            Some(blob_at(offset + parent_size + guid_size)?.to_vec())
+        });
+
+        Some(Self {
+            id,
+            documents,
+            method_sequence_points,
+            sourcelink_json,
+        })
+    }
+}
+
+/// Decodes a document name blob: a separator byte, followed by the blob
+/// indexes of the path components. A zero separator means that the components
+/// are concatenated without a separator.
+fn document_name(blobs: &[u8], name: &[u8]) -> Option<String> {
+    let separator = *name.first()?;
+    let mut path = Vec::new();
+    let mut offset = 1;
+    while offset < name.len() {
+        let (component, next_offset) = decode_compressed_unsigned(name, offset)?;
+        if offset != 1 && separator != 0 {
+            path.push(separator);
+        }
+        path.extend_from_slice(blob(blobs, component)?);
+        offset = next_offset;
+    }
+    Some(String::from_utf8_lossy(&path).into_owned())
+}
+
+/// Decodes a sequence points blob. `document` is the Document column of the
+/// method's row; if it is zero, the blob starts with the initial document.
+fn sequence_points(data: &[u8], mut document: u32) -> Option<Vec<SequencePoint>> {
+    let mut points = Vec::new();
+    if data.is_empty() {
+        return Some(points);
+    }
+    // Skip the LocalSignature.
+    let (_, mut offset) = decode_compressed_unsigned(data, 0)?;
+    if document == 0 {
+        (document, offset) = decode_compressed_unsigned(data, offset)?;
+    }
+
+    let mut il_offset = 0u32;
+    let mut previous_line = None;
+    while offset < data.len() {
+        let (il_offset_delta, next_offset) = decode_compressed_unsigned(data, offset)?;
+        offset = next_offset;
+        if il_offset_delta == 0 && !points.is_empty() {
+            // A document record, which switches to another document.
+            (document, offset) = decode_compressed_unsigned(data, offset)?;
+            continue;
+        }
+        il_offset = il_offset.checked_add(il_offset_delta)?;
+
+        // The column count is only unsigned if the line count is zero, and
+        // both are zero for hidden sequence points.
+        let (line_count, next_offset) = decode_compressed_unsigned(data, offset)?;
+        let (is_hidden, next_offset) = match line_count {
+            0 => {
+                let (column_count, next_offset) = decode_compressed_unsigned(data, next_offset)?;
+                (column_count == 0, next_offset)
+            }
+            _ => (false, decode_compressed_signed(data, next_offset)?.1),
+        };
+        offset = next_offset;
+
+        let line = if is_hidden {
+            None
+        } else {
+            // The first non-hidden sequence point has the absolute start line
+            // and column, the others have the delta to the previous ones.
+            let (line, next_offset) = match previous_line {
+                None => decode_compressed_unsigned(data, offset)?,
+                Some(previous_line) => {
+                    let (delta, next_offset) = decode_compressed_signed(data, offset)?;
+                    (u32::checked_add_signed(previous_line, delta)?, next_offset)
+                }
+            };
+            // Skip the start column.
+            offset = match previous_line {
+                None => decode_compressed_unsigned(data, next_offset)?.1,
+                Some(_) => decode_compressed_signed(data, next_offset)?.1,
+            };
+            previous_line = Some(line);
+            Some(line)
+        };
+        points.push(SequencePoint {
+            il_offset,
+            document,
+            line,
+        });
+    }
+    Some(points)
+}
+
+/// A method with IL code in the assembly.
+#[derive(Debug, Clone)]
+struct IlMethod {
+    /// The relative address range of the IL code.
+    range: Range<u32>,
+    name: String,
+    sequence_points: Vec<SequencePoint>,
+}
+
+/// Where a section of the assembly is stored in the file.
+#[derive(Debug, Clone, Copy)]
+struct IlSection {
+    rva: u32,
+    file_offset: u32,
+    file_size: u32,
+}
+
+pub struct PortablePdbSymbolMap {
+    debug_id: DebugId,
+    /// Sorted by address.
+    methods: Vec<IlMethod>,
+    documents: Vec<String>,
+    path_mapper: Mutex<PathMapper<Option<SourceLinkPathMapper>>>,
+    sections: Vec<IlSection>,
+}
+
+impl PortablePdbSymbolMap {
+    fn new<'data, Pe: ImageNtHeaders, R: ReadRef<'data>, T: FileContents>(
+        pe: &PeFile<'data, Pe, R>,
+        pdb_contents: &FileContentsWrapper<T>,
+    ) -> Option<Self> {
+        let pdb_data = pdb_contents.read_bytes_at(0, pdb_contents.len()).ok()?;
+        let pdb = PortablePdb::parse(pdb_data)?;
+        let (_, metadata) = cli_header_and_metadata(pe)?;
+        let sections = pe
+            .section_table()
+            .iter()
+            .map(|section| IlSection {
+                rva: section.virtual_address.get(LE),
+                file_offset: section.pointer_to_raw_data.get(LE),
+                file_size: section.size_of_raw_data.get(LE),
+            })
+            .collect();
+        let il_code = |rva: u32| il_code_range(pe.section_table().pe_data_at(pe.data(), rva)?, rva);
+        Some(Self::from_parts(
+            pdb,
+            method_defs(metadata)?,
+            il_code,
+            sections,
+        ))
+    }
+
+    fn from_parts(
+        pdb: PortablePdb,
+        method_defs: Vec<MethodDef>,
+        il_code: impl Fn(u32) -> Option<Range<u32>>,
+        sections: Vec<IlSection>,
+    ) -> Self {
+        let PortablePdb {
+            id,
+            documents,
+            mut method_sequence_points,
+            sourcelink_json,
+        } = pdb;
+        method_sequence_points.resize(method_defs.len(), Vec::new());
+        let mut methods: Vec<IlMethod> = method_defs
+            .into_iter()
+            .zip(method_sequence_points)
+            .filter(|(method_def, _)| method_def.rva != 0)
+            .filter_map(|(MethodDef { rva, name }, sequence_points)| {
+                Some(IlMethod {
+                    range: il_code(rva)?,
+                    name,
+                    sequence_points,
+                })
+            })
+            .collect();
+        methods.sort_by_key(|method| method.range.start);
+
+        // The CodeView entry of an assembly with a portable PDB has the GUID of
+        // the PDB ID, and an age of 1.
+        let debug_id = DebugId::from_guid_age(&id[..16], 1).unwrap();
+        let sourcelink_path_mapper = sourcelink_json
+            .as_deref()
+            .and_then(SourceLinkPathMapper::parse);
+        Self {
+            debug_id,
+            methods,
+            documents,
+            path_mapper: Mutex::new(PathMapper::new_with_maybe_extra_mapper(Some(
+                sourcelink_path_mapper,
+            ))),
+            sections,
+        }
+    }
+
+    fn rva_for_file_offset(&self, offset: u32) -> Option<u32> {
+        self.sections.iter().find_map(|section| {
+            let offset_in_section = offset.checked_sub(section.file_offset)?;
+            (offset_in_section < section.file_size).then(|| section.rva + offset_in_section)
+        })
+    }
+}
+
+/// Returns the relative address range of the IL code of the method body at
+/// `rva`, whose data starts with `body`. Bodies start with a tiny (1 byte) or
+/// a fat (12 bytes) header, which are told apart by the low bits.
+fn il_code_range(body: &[u8], rva: u32) -> Option<Range<u32>> {
+    let flags = *body.first()?;
+    let (header_size, code_size) = match flags & 3 {
+        2 => (1, u32::from(flags >> 2)),
+        3 => (u32::from(read_u16(body, 0)? >> 12) * 4, read_u32(body, 4)?),
+        _ => return None,
+    };
+    let start = rva.checked_add(header_size)?;
+    Some(start..start.checked_add(code_size)?)
+}
+
+impl SymbolMapTrait for PortablePdbSymbolMap {
+    fn debug_id(&self) -> DebugId {
+        self.debug_id
+    }
+
+    fn symbol_count(&self) -> usize {
+        self.methods.len()
+    }
+
+    fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
+        Box::new(
+            self.methods
+                .iter()
+                .map(|method| (method.range.start, Cow::Borrowed(method.name.as_str()))),
+        )
+    }
+
+    fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
+        let rva = match address {
+            LookupAddress::Relative(rva) => rva,
+            LookupAddress::Svma(_) => {
+                // SymbolMap converts SVMAs into relative addresses, with the
+                // image base address of the assembly.
+                return None;
+            }
+            LookupAddress::FileOffset(offset) => {
+                self.rva_for_file_offset(u32::try_from(offset).ok()?)?
+            }
+        };
+        let index = match self
+            .methods
+            .binary_search_by_key(&rva, |method| method.range.start)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let method = &self.methods[index];
+        if !method.range.contains(&rva) {
+            return None;
+        }
+        let symbol = SymbolInfo {
+            address: method.range.start,
+            size: Some(method.range.end - method.range.start),
+            name: method.name.clone(),
+        };
+
+        let il_offset = rva - method.range.start;
+        let point_count = method
+            .sequence_points
+            .partition_point(|point| point.il_offset <= il_offset);
+        let frames = match point_count.checked_sub(1) {
+            Some(index) => {
+                let point = method.sequence_points[index];
+                let file_path = match point.line {
+                    Some(_) => point
+                        .document
+                        .checked_sub(1)
+                        .and_then(|index| self.documents.get(index as usize))
+                        .map(|path| {
+                            let mapped_path = self.path_mapper.lock().unwrap().map_path(path);
+                            SourceFilePath::new(path.clone(), mapped_path)
+                        }),
+                    None => None,
+                };
+                Some(FramesLookupResult::Available(vec![FrameDebugInfo {
+                    function: Some(method.name.clone()),
+                    file_path,
+                    line_number: point.line,
+                }]))
+            }
+            None => None,
+        };
+        Some(SyncAddressInfo { symbol, frames })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapped_path::MappedPath;
+
+    /// Builds a portable PDB with the given document name blobs and
+    /// (Document, SequencePoints blob) rows, and optionally SourceLink JSON.
+    /// The `#Blob` heap starts with `path_components`.
+    fn build_portable_pdb(
+        path_components: &[&[u8]],
+        documents: &[&[u8]],
+        methods: &[(u16, &[u8])],
+        sourcelink_json: Option<&[u8]>,
+    ) -> Vec<u8> {
+        let mut blobs = vec![0];
+        let mut add_blob = |blob: &[u8]| {
+            let index = blobs.len() as u16;
+            blobs.push(blob.len() as u8);
+            blobs.extend_from_slice(blob);
+            index
+        };
+        for component in path_components {
+            add_blob(component);
+        }
+        let mut tables = vec![0, 0, 0, 0, 2, 0, 0, 1];
+        let mut valid: u64 = (1 << TABLE_DOCUMENT) | (1 << TABLE_METHODDEBUGINFORMATION);
+        if sourcelink_json.is_some() {
+            valid |= 1 << TABLE_CUSTOMDEBUGINFORMATION;
+        }
+        tables.extend_from_slice(&valid.to_le_bytes());
+        tables.extend_from_slice(&0u64.to_le_bytes());
+        tables.extend_from_slice(&(documents.len() as u32).to_le_bytes());
+        tables.extend_from_slice(&(methods.len() as u32).to_le_bytes());
+        if sourcelink_json.is_some() {
+            tables.extend_from_slice(&1u32.to_le_bytes());
+        }
+        let mut rows = Vec::new();
+        for name in documents {
+            rows.extend_from_slice(&add_blob(name).to_le_bytes());
+            rows.extend_from_slice(&[0; 6]);
+        }
+        for (document, sequence_points) in methods {
+            rows.extend_from_slice(&document.to_le_bytes());
+            rows.extend_from_slice(&add_blob(sequence_points).to_le_bytes());
+        }
+        if let Some(json) = sourcelink_json {
+            // Parent: the Module, Kind: the first GUID.
+            rows.extend_from_slice(&[0x27, 0x00, 0x01, 0x00]);
+            rows.extend_from_slice(&add_blob(json).to_le_bytes());
+        }
+        tables.extend_from_slice(&rows);
+        while blobs.len() % 4 != 0 {
+            blobs.push(0);
+        }
+        while tables.len() % 4 != 0 {
+            tables.push(0);
+        }
+        // The PDB ID, the entry point and no referenced assembly tables.
+        let mut pdb_stream = vec![0x11; 20];
+        pdb_stream.extend_from_slice(&[0; 12]);
+        let streams = [
+            ("#Pdb", pdb_stream),
+            ("#~", tables),
+            ("#Blob", blobs),
+            ("#GUID", SOURCELINK_GUID.to_vec()),
+        ];
+        let mut data = Vec::new();
+        data.extend_from_slice(b"BSJB");
+        data.extend_from_slice(&[1, 0, 1, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(b"v1\0\0");
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&(streams.len() as u16).to_le_bytes());
+        let headers_size: usize = streams
+            .iter()
+            .map(|(name, _)| 8 + (name.len() + 4) / 4 * 4)
+            .sum();
+        let mut stream_offset = data.len() + headers_size;
+        for (name, stream) in &streams {
+            data.extend_from_slice(&(stream_offset as u32).to_le_bytes());
+            data.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.resize(data.len() + 4 - name.len() % 4, 0);
+            stream_offset += stream.len();
+        }
+        for (_, stream) in &streams {
+            data.extend_from_slice(stream);
+        }
+        data
+    }
+
+    #[test]
+    fn parse_sequence_points() {
+        let sequence_points_1: &[u8] = &[
+            0x00, // LocalSignature
+            0x00, 0x01, 0x02, 0x0a, 0x05, // IL 0x0: line 10, columns 5..6
+            0x06, 0x00, 0x00, // IL 0x6: hidden
+            0x04, 0x00, 0x04, 0x04, 0x00, // IL 0xa: line 12, columns 5..9
+            0x00, 0x02, // switch to document 2
+            0x02, 0x03, 0x7f, 0x7b, 0x02, // IL 0xc: line 9, columns 6..5
+        ];
+        let sequence_points_2: &[u8] = &[
+            0x00, // LocalSignature
+            0x01, // initial document
+            0x00, 0x01, 0x04, 0x03, 0x01, // IL 0x0: line 3, columns 1..3
+        ];
+        // The blob indexes of "src" and "Program.cs" are 1 and 5.
+        let data = build_portable_pdb(
+            &[b"src", b"Program.cs"],
+            &[b"/\x00\x01\x05", b"\0\x05"],
+            &[(1, sequence_points_1), (0, sequence_points_2), (0, &[])],
+            None,
+        );
+        let pdb = PortablePdb::parse(&data).unwrap();
+        assert_eq!(pdb.id, [0x11; 20]);
+        assert_eq!(pdb.documents, vec!["/src/Program.cs", "Program.cs"]);
+        assert_eq!(pdb.method_sequence_points.len(), 3);
+        let point = |il_offset, document, line| SequencePoint {
+            il_offset,
+            document,
+            line,
+        };
+        assert_eq!(
+            pdb.method_sequence_points[0],
+            vec![
+                point(0x0, 1, Some(10)),
+                point(0x6, 1, None),
+                point(0xa, 1, Some(12)),
+                point(0xc, 2, Some(9)),
+            ]
+        );
+        assert_eq!(pdb.method_sequence_points[1], vec![point(0x0, 1, Some(3))]);
+        assert!(pdb.method_sequence_points[2].is_empty());
+        assert_eq!(pdb.sourcelink_json, None);
+    }
+
+    #[test]
+    fn parse_sourcelink() {
+        let json =
+            br#"{"documents":{"C:\src\*":"https://raw.githubusercontent.com/org/app/0123abcd/*"}}"#;
+        let data = build_portable_pdb(&[], &[], &[], Some(json));
+        let pdb = PortablePdb::parse(&data).unwrap();
+        assert_eq!(pdb.sourcelink_json.as_deref(), Some(&json[..]));
+    }
+
+    #[test]
+    fn document_names() {
+        let blobs = b"\0\0\x03src\x0aProgram.cs";
+        assert_eq!(
+            document_name(blobs, b"/\x01\x02\x06").as_deref(),
+            Some("/src/Program.cs")
+        );
+        assert_eq!(
+            document_name(blobs, b"\0\x02\x06").as_deref(),
+            Some("srcProgram.cs")
+        );
+    }
+
+    #[test]
+    fn lookup() {
+        let pdb = PortablePdb {
+            id: [0x22; 20],
+            documents: vec!["C:\\src\\Program.cs".to_string()],
+            method_sequence_points: vec![vec![
+                SequencePoint {
+                    il_offset: 0,
+                    document: 1,
+                    line: Some(10),
+                },
+                SequencePoint {
+                    il_offset: 4,
+                    document: 1,
+                    line: None,
+                },
+            ]],
+            sourcelink_json: Some(
+                br#"{"documents":{"C:\\src\\*":"https://raw.githubusercontent.com/org/app/0123abcd/*"}}"#
+                    .to_vec(),
+            ),
+        };
+        let method_defs = vec![
+            MethodDef {
+                rva: 0x2050,
+                name: "App.Program::Main".to_string(),
+            },
+            MethodDef {
+                rva: 0,
+                name: "App.Program::Abstract".to_string(),
+            },
+            MethodDef {
+                rva: 0x2060,
+                name: "App.Program::.ctor".to_string(),
+            },
+        ];
+        let il_code = |rva: u32| Some(rva + 1..rva + 9);
+        let sections = vec![IlSection {
+            rva: 0x2000,
+            file_offset: 0x200,
+            file_size: 0x200,
+        }];
+        let symbol_map = PortablePdbSymbolMap::from_parts(pdb, method_defs, il_code, sections);
+        assert_eq!(symbol_map.symbol_count(), 2);
+        let symbols: Vec<_> = symbol_map.iter_symbols().collect();
+        assert_eq!(
+            symbols,
+            vec![
+                (0x2051, Cow::Borrowed("App.Program::Main")),
+                (0x2061, Cow::Borrowed("App.Program::.ctor")),
+            ]
+        );
+
+        let info = symbol_map
+            .lookup_sync(LookupAddress::Relative(0x2053))
+            .unwrap();
+        assert_eq!(info.symbol.address, 0x2051);
+        assert_eq!(info.symbol.size, Some(8));
+        assert_eq!(
+            info.frames,
+            Some(FramesLookupResult::Available(vec![FrameDebugInfo {
+                function: Some("App.Program::Main".to_string()),
+                file_path: Some(SourceFilePath::new(
+                    "C:\\src\\Program.cs".to_string(),
+                    Some(MappedPath::Git {
+                        repo: "github.com/org/app".into(),
+                        path: "Program.cs".into(),
+                        rev: "0123abcd".into(),
+                    })
+                )),
+                line_number: Some(10),
+            }]))
+        );
+        let info = symbol_map
+            .lookup_sync(LookupAddress::FileOffset(0x256))
+            .unwrap();
+        assert_eq!(
+            info.frames,
+            Some(FramesLookupResult::Available(vec![FrameDebugInfo {
+                function: Some("App.Program::Main".to_string()),
+                file_path: None,
+                line_number: None,
+            }]))
+        );
+        let info = symbol_map
+            .lookup_sync(LookupAddress::Relative(0x2062))
+            .unwrap();
+        assert_eq!(info.symbol.name, "App.Program::.ctor");
+        assert_eq!(info.frames, None);
+        assert!(symbol_map
+            .lookup_sync(LookupAddress::Relative(0x2059))
+            .is_none());
+    }
+
+    #[test]
+    fn method_body_headers() {
+        assert_eq!(il_code_range(&[0x1a], 0x2050), Some(0x2051..0x2057));
+        let fat = [0x13, 0x30, 0x02, 0x00, 0x20, 0x00, 0x00, 0x00];
+        assert_eq!(il_code_range(&fat, 0x2060), Some(0x206c..0x208c));
+        assert_eq!(il_code_range(&[0x00], 0x2070), None);
+    }
+}
@@ -0,0 +1,215 @@
+//! Method names for .NET ReadyToRun images.
+//!
+//! ReadyToRun ("R2R") assemblies, e.g. the framework assemblies which ship
+//! with the .NET runtime, contain precompiled native code for their managed
+//! methods. They don't have native symbols, but the name of each precompiled
+//! method can be found by combining two tables: the ECMA-335 metadata has the
+//! names of all types and methods, and the ReadyToRun header's method entry
+//! point table maps each method to the `RUNTIME_FUNCTION` with its code.
+
+use object::pe::IMAGE_FILE_MACHINE_AMD64;
+use object::read::pe::{ImageNtHeaders, PeFile};
+use object::{LittleEndian as LE, ReadRef};
+
+use crate::clr_metadata::{
+    cli_header_and_metadata, image_data, method_defs, read_u16, read_u32, MethodDef,
+};
+
+/// A precompiled method in a ReadyToRun image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadyToRunMethod {
+    /// The relative address of the start of the method's code.
+    pub start: u32,
+    /// The relative address of the end of the method's code. Only x64 images
+    /// have the end addresses.
+    pub end: Option<u32>,
+    /// The method name, e.g. `System.Collections.Generic.List`1::Add`.
+    pub name: String,
+}
+
+const READYTORUN_SIGNATURE: u32 = 0x0052_5452; // "RTR"
+const READYTORUN_SECTION_RUNTIME_FUNCTIONS: u32 = 102;
+const READYTORUN_SECTION_METHODDEF_ENTRYPOINTS: u32 = 103;
+
+/// Images for other operating systems than Windows have their machine type
+/// XOR-ed with one of these values.
+const MACHINE_OS_OVERRIDES: [u16; 5] = [0, 0x4644, 0x7B79, 0x1993, 0xADC4];
+
+/// Returns the precompiled methods, or `None` if this is not a ReadyToRun
+/// image or if it can't be parsed.
+pub fn ready_to_run_methods<'data, Pe: ImageNtHeaders, R: ReadRef<'data>>(
+    pe: &PeFile<'data, Pe, R>,
+) -> Option<Vec<ReadyToRunMethod>> {
+    let (cor_header, metadata) = cli_header_and_metadata(pe)?;
+    let managed_native_header =
+        image_data(pe, (read_u32(cor_header, 64)?, read_u32(cor_header, 68)?))?;
+    if read_u32(managed_native_header, 0)? != READYTORUN_SIGNATURE {
+        return None;
+    }
+
+    let section_count = read_u32(managed_native_header, 12)?;
+    let mut runtime_functions = None;
+    let mut entry_points = None;
+    for i in 0..section_count as usize {
+        let offset = 16 + i * 12;
+        let section_type = read_u32(managed_native_header, offset)?;
+        let range = (
+            read_u32(managed_native_header, offset + 4)?,
+            read_u32(managed_native_header, offset + 8)?,
+        );
+        match section_type {
+            READYTORUN_SECTION_RUNTIME_FUNCTIONS => runtime_functions = image_data(pe, range),
+            READYTORUN_SECTION_METHODDEF_ENTRYPOINTS => entry_points = image_data(pe, range),
+            _ => {}
+        }
+    }
+    let runtime_functions = runtime_functions?;
+    let entry_points = NativeArray::new(entry_points?)?;
+
+    // x64 RUNTIME_FUNCTIONs are (BeginAddress, EndAddress, UnwindData), the
+    // ones for other architectures are (BeginAddress, UnwindData).
+    let machine = pe.nt_headers().file_header().machine.get(LE);
+    let has_end_address = MACHINE_OS_OVERRIDES
+        .iter()
+        .any(|os| machine ^ os == IMAGE_FILE_MACHINE_AMD64);
+    let runtime_function_size = if has_end_address { 12 } else { 8 };
+
+    let methods = method_defs(metadata)?
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, MethodDef { name, .. })| {
+            let runtime_function_index = entry_points.runtime_function_index(index as u32)?;
+            let offset = runtime_function_index as usize * runtime_function_size;
+            let start = read_u32(runtime_functions, offset)?;
+            let end = if has_end_address {
+                Some(read_u32(runtime_functions, offset + 4)?)
+            } else {
+                None
+            };
+            Some(ReadyToRunMethod { start, end, name })
+        })
+        .collect();
+    Some(methods)
+}
+
+/// Reads the unsigned integer at `offset` in the NativeFormat encoding of
+/// the .NET runtime. Returns the value and the offset after it.
+fn decode_unsigned(data: &[u8], offset: usize) -> Option<(u32, usize)> {
+    let byte = |i: usize| data.get(offset + i).map(|b| u32::from(*b));
+    let first = byte(0)?;
+    let decoded = if first & 1 == 0 {
+        (first >> 1, offset + 1)
+    } else if first & 2 == 0 {
+        ((first >> 2) | (byte(1)? << 6), offset + 2)
+    } else if first & 4 == 0 {
+        (
+            (first >> 3) | (byte(1)? << 5) | (byte(2)? << 13),
+            offset + 3,
+        )
+    } else if first & 8 == 0 {
+        (
+            (first >> 4) | (byte(1)? << 4) | (byte(2)? << 12) | (byte(3)? << 20),
+            offset + 4,
+        )
+    } else if first & 16 == 0 {
+        (read_u32(data, offset + 1)?, offset + 5)
+    } else {
+        return None;
+    };
+    Some(decoded)
+}
+
+/// A sparse array in the NativeFormat encoding: a table of blocks of 16
+/// elements, where each block is a binary tree whose leaves are the present
+/// elements.
+struct NativeArray<'a> {
+    data: &'a [u8],
+    base_offset: usize,
+    element_count: u32,
+    entry_index_size: u32,
+}
+
+const NATIVE_ARRAY_BLOCK_SIZE: u32 = 16;
+
+impl<'a> NativeArray<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let (header, base_offset) = decode_unsigned(data, 0)?;
+        Some(Self {
+            data,
+            base_offset,
+            element_count: header >> 2,
+            entry_index_size: header & 3,
+        })
+    }
+
+    /// Returns the offset of the element at `index`, if present.
+    fn get(&self, index: u32) -> Option<usize> {
+        if index >= self.element_count {
+            return None;
+        }
+        let block = (index / NATIVE_ARRAY_BLOCK_SIZE) as usize;
+        let block_offset = match self.entry_index_size {
+            0 => u32::from(*self.data.get(self.base_offset + block)?),
+            1 => u32::from(read_u16(self.data, self.base_offset + 2 * block)?),
+            _ => read_u32(self.data, self.base_offset + 4 * block)?,
+        };
+        let mut offset = self.base_offset + block_offset as usize;
+        let mut bit = NATIVE_ARRAY_BLOCK_SIZE >> 1;
+        while bit > 0 {
+            let (node, next_offset) = decode_unsigned(self.data, offset)?;
+            if index & bit != 0 {
+                if node & 2 != 0 {
+                    offset += (node >> 2) as usize;
+                    bit >>= 1;
+                    continue;
+                }
+            } else if node & 1 != 0 {
+                offset = next_offset;
+                bit >>= 1;
+                continue;
+            }
+            // A leaf which stands for the only element in this subtree.
+            if node & 3 == 0 && node >> 2 == index & (NATIVE_ARRAY_BLOCK_SIZE - 1) {
+                return Some(next_offset);
+            }
+            return None;
+        }
+        Some(offset)
+    }
+
+    /// For the method entry point table: Returns the index of the
+    /// RUNTIME_FUNCTION of the method at `index`, if it was precompiled.
+    fn runtime_function_index(&self, index: u32) -> Option<u32> {
+        let (id, _) = decode_unsigned(self.data, self.get(index)?)?;
+        // The low bit says whether the entry has fixups.
+        Some(match id & 1 {
+            0 => id >> 1,
+            _ => id >> 2,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn native_array_entry_points() {
+        assert_eq!(decode_unsigned(&[0xb1, 0x04], 0), Some((300, 2)));
+
+        let data = [
+            0x10, // two elements, 8-bit block offsets
+            0x01, // the first block starts at offset 2
+            0x02, // bit 8: left child follows
+            0x02, // bit 4: left child follows
+            0x02, // bit 2: left child follows
+            0x16, // bit 1: left child follows, right child 2 bytes from here
+            0x14, // element 0: runtime function 5, without fixups
+            0x1a, // element 1: runtime function 3, with fixups
+        ];
+        let array = NativeArray::new(&data).unwrap();
+        assert_eq!(array.runtime_function_index(0), Some(5));
+        assert_eq!(array.runtime_function_index(1), Some(3));
+        assert_eq!(array.runtime_function_index(2), None);
+    }
+}
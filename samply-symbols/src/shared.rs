@@ -409,6 +409,14 @@ pub trait FileAndPathHelper {
         location: Self::FL,
     ) -> std::pin::Pin<Box<dyn OptionallySendFuture<Output = FileAndPathHelperResult<Self::F>> + '_>>;
 
+    /// Describes where the file at `location` was actually read from, for
+    /// example its path on disk or the URL it was downloaded from. This is
+    /// only asked for locations which were loaded successfully, and lets users
+    /// tell which file supplied the symbols for a library.
+    fn describe_loaded_file(&self, location: &Self::FL) -> String {
+        location.to_string()
+    }
+
     /// Ask the helper to return a SymbolMap if it happens to have one available already.
     fn get_symbol_map_for_library(
         &self,
@@ -465,6 +473,20 @@ pub struct FrameDebugInfo {
     pub line_number: Option<u32>,
 }
 
+/// A range of relative addresses whose instructions were generated from the
+/// same source line, according to the line table of the debug information.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLineRange {
+    /// The relative address of the first instruction in this range.
+    pub start: u32,
+    /// The relative address after the last instruction in this range.
+    pub end: u32,
+    /// The [`SourceFilePath`] of the source line, if known.
+    pub file_path: Option<SourceFilePath>,
+    /// The line number, if known.
+    pub line_number: Option<u32>,
+}
+
 /// A trait which abstracts away the token that's passed to the [`FileAndPathHelper::load_file`]
 /// trait method.
 ///
@@ -501,6 +523,16 @@ pub trait FileLocation: Clone + Display {
     /// those relative paths relative to the current working directory.
     fn location_for_source_file(&self, source_file_path: &str) -> Option<Self>;
 
+    /// Called on the location of a debug file in order to create a location for a
+    /// source file from its mapped path, e.g. a permalink into a repository which
+    /// was found in the PDB's srcsrv stream. This location is used if the file
+    /// can't be loaded from the location from `location_for_source_file`.
+    ///
+    /// The default implementation refuses all mapped paths.
+    fn location_for_mapped_source_file(&self, _mapped_path: &MappedPath) -> Option<Self> {
+        None
+    }
+
     /// Called on the location of a Breakpad sym file, to get a location for its
     /// corresponding symindex file.
     fn location_for_breakpad_symindex(&self) -> Option<Self>;
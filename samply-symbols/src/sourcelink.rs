@@ -0,0 +1,126 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::mapped_path::MappedPath;
+use crate::path_mapper::ExtraPathMapper;
+
+/// Map raw file paths to special "permalink" paths, using SourceLink JSON.
+///
+/// SourceLink JSON is found in the `sourcelink` stream of PDBs which were linked
+/// with `/SOURCELINK`, and in the custom debug information of portable PDBs. It
+/// looks like this:
+///
+/// ```json
+/// {"documents": {"C:\\src\\app\\*": "https://raw.githubusercontent.com/org/app/<rev>/*"}}
+/// ```
+///
+/// A path which ends in `*` matches all paths with this prefix, and the rest of
+/// the path is put in place of the `*` in the URL. Paths are matched ignoring
+/// case, and the most specific path wins.
+pub struct SourceLinkPathMapper {
+    /// Sorted by precedence: the exact paths, then the prefixes, longest first.
+    documents: Vec<SourceLinkDocument>,
+    cache: HashMap<String, Option<MappedPath>>,
+}
+
+struct SourceLinkDocument {
+    /// The path or the path prefix, in ASCII lowercase.
+    path: String,
+    is_prefix: bool,
+    url: String,
+}
+
+impl SourceLinkPathMapper {
+    pub fn parse(json: &[u8]) -> Option<Self> {
+        let json: serde_json::Value = serde_json::from_slice(json).ok()?;
+        let mut documents: Vec<_> = json
+            .get("documents")?
+            .as_object()?
+            .iter()
+            .filter_map(|(path, url)| {
+                let (path, is_prefix) = match path.strip_suffix('*') {
+                    Some(prefix) => (prefix, true),
+                    None => (path.as_str(), false),
+                };
+                Some(SourceLinkDocument {
+                    path: path.to_ascii_lowercase(),
+                    is_prefix,
+                    url: url.as_str()?.to_string(),
+                })
+            })
+            .collect();
+        documents.sort_by_key(|document| (document.is_prefix, Reverse(document.path.len())));
+        Some(Self {
+            documents,
+            cache: HashMap::new(),
+        })
+    }
+
+    fn url_for_path(&self, path: &str) -> Option<String> {
+        let lowercase_path = path.to_ascii_lowercase();
+        self.documents.iter().find_map(|document| {
+            if !document.is_prefix {
+                return (lowercase_path == document.path).then(|| document.url.clone());
+            }
+            if !lowercase_path.starts_with(&document.path) {
+                return None;
+            }
+            let rest = path[document.path.len()..].replace('\\', "/");
+            Some(document.url.replacen('*', &rest, 1))
+        })
+    }
+}
+
+impl ExtraPathMapper for SourceLinkPathMapper {
+    fn map_path(&mut self, path: &str) -> Option<MappedPath> {
+        if let Some(value) = self.cache.get(path) {
+            return value.clone();
+        }
+
+        let value = self
+            .url_for_path(path)
+            .and_then(|url| MappedPath::from_url(&url));
+        self.cache.insert(path.to_string(), value.clone());
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn map_paths() {
+        let json = br#"{
+            "documents": {
+                "C:\\src\\app\\*": "https://raw.githubusercontent.com/org/app/0123abcd/*",
+                "C:\\src\\app\\vendor\\lib\\*": "https://raw.githubusercontent.com/org/lib/4567ef01/*",
+                "C:\\src\\generated.cs": "https://example.com/generated.cs"
+            }
+        }"#;
+        let mut mapper = SourceLinkPathMapper::parse(json).unwrap();
+        assert_eq!(
+            mapper.map_path(r"c:\Src\App\Program.cs"),
+            Some(MappedPath::Git {
+                repo: "github.com/org/app".into(),
+                path: "Program.cs".into(),
+                rev: "0123abcd".into(),
+            })
+        );
+        assert_eq!(
+            mapper.map_path(r"C:\src\app\vendor\lib\src\Lib.cs"),
+            Some(MappedPath::Git {
+                repo: "github.com/org/lib".into(),
+                path: "src/Lib.cs".into(),
+                rev: "4567ef01".into(),
+            })
+        );
+        assert_eq!(
+            mapper.url_for_path(r"C:\src\generated.cs").as_deref(),
+            Some("https://example.com/generated.cs")
+        );
+        assert_eq!(mapper.map_path(r"C:\src\generated.cs"), None);
+        assert_eq!(mapper.map_path(r"D:\other\file.cs"), None);
+        assert!(SourceLinkPathMapper::parse(b"{}").is_none());
+    }
+}
@@ -1,9 +1,10 @@
 use std::borrow::Cow;
+use std::ops::Range;
 use std::sync::Arc;
 
 use debugid::DebugId;
 
-use crate::shared::LookupAddress;
+use crate::shared::{LookupAddress, SourceLineRange};
 use crate::{
     AddressInfo, ExternalFileAddressRef, ExternalFileRef, FileAndPathHelper, FileLocation,
     FrameDebugInfo, FramesLookupResult, SyncAddressInfo,
@@ -17,6 +18,12 @@ pub trait SymbolMapTrait {
     fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_>;
 
     fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo>;
+
+    /// The line table entries which overlap the given range of relative
+    /// addresses, sorted by address. Empty if there is no line information.
+    fn source_line_ranges(&self, _range: Range<u32>) -> Vec<SourceLineRange> {
+        Vec::new()
+    }
 }
 
 pub trait SymbolMapTraitWithExternalFileSupport<FC>: SymbolMapTrait {
@@ -49,6 +56,7 @@ pub struct SymbolMap<H: FileAndPathHelper> {
     debug_file_location: H::FL,
     inner: InnerSymbolMap<H::F>,
     helper: Option<Arc<H>>,
+    relative_address_base: Option<u64>,
 }
 
 impl<H: FileAndPathHelper> SymbolMap<H> {
@@ -60,6 +68,7 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
             debug_file_location,
             inner: InnerSymbolMap::WithoutAddFile(inner),
             helper: None,
+            relative_address_base: None,
         }
     }
 
@@ -72,6 +81,7 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
             debug_file_location,
             inner: InnerSymbolMap::WithAddFile(inner),
             helper: Some(helper),
+            relative_address_base: None,
         }
     }
 
@@ -83,6 +93,7 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
             debug_file_location,
             inner: InnerSymbolMap::Direct(inner),
             helper: None,
+            relative_address_base: None,
         }
     }
 
@@ -110,12 +121,38 @@ impl<H: FileAndPathHelper> SymbolMap<H> {
         self.inner().iter_symbols()
     }
 
+    /// Sets the [`relative_address_base`](crate::relative_address_base) of
+    /// the binary, for debug files which don't know it, like PDB files. This
+    /// makes lookups by [`LookupAddress::Svma`] work for them.
+    pub fn set_relative_address_base(&mut self, relative_address_base: u64) {
+        self.relative_address_base = Some(relative_address_base);
+    }
+
+    /// Converts SVMAs into relative addresses if the base is known, so that
+    /// the inner symbol map doesn't need to know it.
+    fn resolve_address(&self, address: LookupAddress) -> Option<LookupAddress> {
+        match (address, self.relative_address_base) {
+            (LookupAddress::Svma(svma), Some(base)) => Some(LookupAddress::Relative(
+                u32::try_from(svma.checked_sub(base)?).ok()?,
+            )),
+            _ => Some(address),
+        }
+    }
+
     pub fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
-        self.inner().lookup_sync(address)
+        self.inner().lookup_sync(self.resolve_address(address)?)
+    }
+
+    /// Returns the source lines of the instructions in the given range of
+    /// relative addresses, for example to annotate disassembled code. Unlike
+    /// [`lookup_sync`](Self::lookup_sync), this covers every line table entry
+    /// in the range, not just the one at a single address.
+    pub fn source_line_ranges(&self, range: Range<u32>) -> Vec<SourceLineRange> {
+        self.inner().source_line_ranges(range)
     }
 
     pub async fn lookup(&self, address: LookupAddress) -> Option<AddressInfo> {
-        let address_info = self.inner().lookup_sync(address)?;
+        let address_info = self.inner().lookup_sync(self.resolve_address(address)?)?;
         let symbol = address_info.symbol;
         let (mut external, inner) = match (address_info.frames, &self.inner) {
             (Some(FramesLookupResult::Available(frames)), _) => {
@@ -1,5 +1,6 @@
 use std::borrow::Cow;
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::slice;
 use std::sync::{Arc, Mutex};
 
@@ -16,7 +17,7 @@ use crate::dwarf::convert_frames;
 use crate::path_mapper::PathMapper;
 use crate::shared::{
     relative_address_base, ExternalFileAddressInFileRef, ExternalFileAddressRef, ExternalFileRef,
-    FramesLookupResult, LookupAddress, SymbolInfo,
+    FramesLookupResult, LookupAddress, SourceFilePath, SourceLineRange, SymbolInfo,
 };
 use crate::symbol_map::{
     GetInnerSymbolMap, GetInnerSymbolMapWithLookupFramesExt, SymbolMapTrait,
@@ -30,6 +31,9 @@ enum FullSymbolListEntry<'a, Symbol> {
     Synthesized,
     /// A synthesized symbol for the entry point of the object.
     SynthesizedEntryPoint,
+    /// A named function which is known from some other information, e.g. a
+    /// precompiled method of a .NET ReadyToRun image.
+    Extra(&'a str),
     Symbol(Symbol),
     Export(object::Export<'a>),
     EndAddress,
@@ -40,6 +44,7 @@ impl<'a, Symbol: object::ObjectSymbol<'a>> std::fmt::Debug for FullSymbolListEnt
         match self {
             Self::Synthesized => write!(f, "Synthesized"),
             Self::SynthesizedEntryPoint => write!(f, "SynthesizedEntryPoint"),
+            Self::Extra(name) => f.debug_tuple("Extra").field(name).finish(),
             Self::Symbol(arg0) => f
                 .debug_tuple("Symbol")
                 .field(&arg0.name().unwrap())
@@ -59,6 +64,7 @@ impl<'a, Symbol: object::ObjectSymbol<'a>> FullSymbolListEntry<'a, Symbol> {
             FullSymbolListEntry::EndAddress => return None,
             FullSymbolListEntry::Synthesized => format!("fun_{addr:x}").into(),
             FullSymbolListEntry::SynthesizedEntryPoint => "EntryPoint".into(),
+            FullSymbolListEntry::Extra(name) => (*name).into(),
             FullSymbolListEntry::Symbol(symbol) => {
                 String::from_utf8_lossy(symbol.name_bytes().ok()?)
             }
@@ -69,7 +75,9 @@ impl<'a, Symbol: object::ObjectSymbol<'a>> FullSymbolListEntry<'a, Symbol> {
 
     fn counts_as_proper_symbol(&self) -> bool {
         match self {
-            FullSymbolListEntry::Symbol(_) | FullSymbolListEntry::Export(_) => true,
+            FullSymbolListEntry::Symbol(_)
+            | FullSymbolListEntry::Export(_)
+            | FullSymbolListEntry::Extra(_) => true,
             FullSymbolListEntry::EndAddress
             | FullSymbolListEntry::Synthesized
             | FullSymbolListEntry::SynthesizedEntryPoint => false,
@@ -87,6 +95,7 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
         base_address: u64,
         function_start_addresses: Option<&[u32]>,
         function_end_addresses: Option<&[u32]>,
+        extra_symbols: &[(u32, &'a str)],
     ) -> Self
     where
         'a: 'file,
@@ -167,7 +176,14 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
             }
         }
 
-        // 4. Placeholder symbols based on function start addresses
+        // 4. Extra symbols from other information
+        entries.extend(
+            extra_symbols
+                .iter()
+                .map(|(address, name)| (*address, FullSymbolListEntry::Extra(name))),
+        );
+
+        // 5. Placeholder symbols based on function start addresses
         if let Some(function_start_addresses) = function_start_addresses {
             // Use function start addresses with synthesized symbols of the form fun_abcdef
             // as the ultimate fallback.
@@ -181,7 +197,7 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
             );
         }
 
-        // 5. A placeholder symbol for the entry point.
+        // 6. A placeholder symbol for the entry point.
         if let Some(entry_point) = object_file.entry().checked_sub(base_address) {
             entries.push((
                 entry_point as u32,
@@ -189,7 +205,7 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
             ));
         }
 
-        // 6. End addresses from text section ends
+        // 7. End addresses from text section ends
         // These entries serve to "terminate" the last function of each section,
         // so that addresses in the following section are not considered
         // to be part of the last function of that previous section.
@@ -205,7 +221,7 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
                 }),
         );
 
-        // 7. End addresses for sized symbols
+        // 8. End addresses for sized symbols
         // These addresses serve to "terminate" functions symbols.
         entries.extend(
             object_file
@@ -227,7 +243,7 @@ impl<'a, Symbol: object::ObjectSymbol<'a> + 'a> SymbolList<'a, Symbol> {
                 }),
         );
 
-        // 8. End addresses for known functions ends
+        // 9. End addresses for known functions ends
         // These addresses serve to "terminate" functions from function_start_addresses.
         // They come from .eh_frame or .pdata info, which has the function size.
         if let Some(function_end_addresses) = function_end_addresses {
@@ -581,6 +597,36 @@ where
         }
         Some(SyncAddressInfo { symbol, frames })
     }
+
+    fn source_line_ranges(&self, range: Range<u32>) -> Vec<SourceLineRange> {
+        let Some(context) = self.context.as_ref() else {
+            return Vec::new();
+        };
+        let context = context.lock().unwrap();
+        let base = self.image_base_address;
+        let Ok(locations) =
+            context.find_location_range(base + u64::from(range.start), base + u64::from(range.end))
+        else {
+            return Vec::new();
+        };
+        let mut path_mapper = self.path_mapper.lock().unwrap();
+        locations
+            .filter_map(|(svma, size, location)| {
+                let start = u32::try_from(svma.checked_sub(base)?).ok()?;
+                let end = start.checked_add(u32::try_from(size).ok()?)?;
+                let file_path = location.file.map(|file| {
+                    let mapped_path = path_mapper.map_path(file);
+                    SourceFilePath::new(file.into(), mapped_path)
+                });
+                Some(SourceLineRange {
+                    start,
+                    end,
+                    file_path,
+                    line_number: location.line,
+                })
+            })
+            .collect()
+    }
 }
 
 pub struct SymbolMapIter<'data, 'map, Symbol: object::ObjectSymbol<'data>> {
@@ -645,6 +691,7 @@ pub struct ObjectSymbolMapInnerWrapper<'data, FC>(
 );
 
 impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<'file, O, Symbol, DDM>(
         object_file: &'file O,
         addr2line_context: Option<addr2line::Context<EndianSlice<'a, RunTimeEndian>>>,
@@ -652,6 +699,7 @@ impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
         debug_id: DebugId,
         function_start_addresses: Option<&[u32]>,
         function_end_addresses: Option<&[u32]>,
+        extra_symbols: &[(u32, &'a str)],
         dwo_dwarf_maker: &'a DDM,
     ) -> Self
     where
@@ -666,6 +714,7 @@ impl<'a, FC: FileContents + 'static> ObjectSymbolMapInnerWrapper<'a, FC> {
             base_address,
             function_start_addresses,
             function_end_addresses,
+            extra_symbols,
         );
 
         let inner = ObjectSymbolMapInner {
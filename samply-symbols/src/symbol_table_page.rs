@@ -0,0 +1,114 @@
+use std::borrow::Cow;
+
+use crate::{FileAndPathHelper, LookupAddress, SymbolMap};
+
+/// A part of a symbol table, sorted by address. The whole table can be read
+/// page by page, by starting each page at the previous page's
+/// `next_start_address`.
+///
+/// Symbols with the same address are only listed once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolTablePage {
+    /// The symbols in this page, in ascending address order.
+    pub symbols: Vec<SymbolTableEntry>,
+    /// The start address for the next page, or `None` if this is the last
+    /// page.
+    pub next_start_address: Option<u32>,
+    /// The number of symbols in the whole symbol table, including symbols
+    /// with the same address.
+    pub total_symbol_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolTableEntry {
+    /// The symbol's address, as a library-relative offset in bytes.
+    pub address: u32,
+    /// The function size, in bytes. May have been approximated from
+    /// neighboring symbols.
+    pub size: Option<u32>,
+    /// The symbol name, demangled.
+    pub name: String,
+}
+
+impl SymbolTablePage {
+    /// Collects at most `max_count` symbols, starting with the symbol at
+    /// `start_address` or the first one after it.
+    pub fn from_symbol_map<H: FileAndPathHelper>(
+        map: &SymbolMap<H>,
+        start_address: u32,
+        max_count: usize,
+    ) -> Self {
+        let (symbols, next_start_address) =
+            first_symbols_from(map.iter_symbols(), start_address, max_count);
+        let symbols = symbols
+            .into_iter()
+            .map(|(address, name)| {
+                let size = map
+                    .lookup_sync(LookupAddress::Relative(address))
+                    .filter(|info| info.symbol.address == address)
+                    .and_then(|info| info.symbol.size);
+                SymbolTableEntry {
+                    address,
+                    size,
+                    name: name.into_owned(),
+                }
+            })
+            .collect();
+        Self {
+            symbols,
+            next_start_address,
+            total_symbol_count: map.symbol_count(),
+        }
+    }
+}
+
+/// Finds the `max_count` symbols with the lowest addresses which are at least
+/// `start_address`, and the address where the next page starts. The symbols
+/// don't need to be sorted, because not all symbol maps iterate in order.
+fn first_symbols_from<'a>(
+    symbols: impl Iterator<Item = (u32, Cow<'a, str>)>,
+    start_address: u32,
+    max_count: usize,
+) -> (Vec<(u32, Cow<'a, str>)>, Option<u32>) {
+    let mut symbols: Vec<_> = symbols
+        .filter(|(address, _)| *address >= start_address)
+        .collect();
+    symbols.sort_unstable_by(|(a, a_name), (b, b_name)| a.cmp(b).then_with(|| a_name.cmp(b_name)));
+    symbols.dedup_by_key(|(address, _)| *address);
+    if symbols.len() <= max_count {
+        return (symbols, None);
+    }
+    symbols.truncate(max_count);
+    let next_start_address = match symbols.last() {
+        Some((address, _)) => address.checked_add(1),
+        None => Some(start_address),
+    };
+    (symbols, next_start_address)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pages() {
+        let symbols = [(0x30, "c"), (0x10, "a"), (0x20, "b2"), (0x20, "b1")];
+        let iter = || {
+            symbols
+                .iter()
+                .map(|(address, name)| (*address, Cow::from(*name)))
+        };
+
+        let (page, next) = first_symbols_from(iter(), 0, 2);
+        assert_eq!(page, [(0x10, "a".into()), (0x20, "b1".into())]);
+        assert_eq!(next, Some(0x21));
+
+        let (page, next) = first_symbols_from(iter(), 0x21, 2);
+        assert_eq!(page, [(0x30, "c".into())]);
+        assert_eq!(next, None);
+
+        let (page, next) = first_symbols_from(iter(), 0x31, 2);
+        assert!(page.is_empty());
+        assert_eq!(next, None);
+    }
+}
@@ -1,14 +1,15 @@
 use std::borrow::Cow;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::ops::Deref;
+use std::ops::{Deref, Range};
 use std::sync::{Arc, Mutex};
 
 use debugid::DebugId;
 use nom::bytes::complete::{tag, take_until1};
 use nom::combinator::eof;
 use nom::sequence::terminated;
-use object::{File, FileKind};
-use pdb::PDB;
+use object::{File, FileKind, ReadRef};
+use pdb::{AddressMap, FallibleIterator, PdbInternalRva, Rva, PDB};
 use pdb_addr2line::pdb;
 use yoke::Yoke;
 use yoke_derive::Yokeable;
@@ -18,10 +19,13 @@ use crate::dwarf::Addr2lineContextData;
 use crate::error::{Context, Error};
 use crate::mapped_path::MappedPath;
 use crate::path_mapper::{ExtraPathMapper, PathMapper};
+use crate::portable_pdb;
+use crate::ready_to_run::{ready_to_run_methods, ReadyToRunMethod};
 use crate::shared::{
     FileAndPathHelper, FileContents, FileContentsWrapper, FileLocation, FrameDebugInfo,
-    FramesLookupResult, LookupAddress, SourceFilePath, SymbolInfo,
+    FramesLookupResult, LookupAddress, SourceFilePath, SourceLineRange, SymbolInfo,
 };
+use crate::sourcelink::SourceLinkPathMapper;
 use crate::symbol_map::{GetInnerSymbolMap, SymbolMap, SymbolMapTrait};
 use crate::symbol_map_object::{
     ObjectSymbolMap, ObjectSymbolMapInnerWrapper, ObjectSymbolMapOuter,
@@ -33,6 +37,7 @@ pub async fn load_symbol_map_for_pdb_corresponding_to_binary<H: FileAndPathHelpe
     file_contents: &FileContentsWrapper<H::F>,
     file_location: H::FL,
     helper: &H,
+    function_arguments: bool,
 ) -> Result<SymbolMap<H>, Error> {
     use object::Object;
     let pe =
@@ -53,13 +58,27 @@ pub async fn load_symbol_map_for_pdb_corresponding_to_binary<H: FileAndPathHelpe
         .load_file(pdb_location)
         .await
         .map_err(|e| Error::HelperErrorDuringOpenFile(pdb_path_str.to_string(), e))?;
-    let symbol_map = get_symbol_map_for_pdb(FileContentsWrapper::new(pdb_file), file_location)?;
+    let pdb_file = FileContentsWrapper::new(pdb_file);
+    let mut symbol_map = if portable_pdb::is_portable_pdb_file(&pdb_file) {
+        if is_ready_to_run(&pe) {
+            // The portable PDB's IL offsets don't apply to the precompiled
+            // native code, which get_symbol_map_for_pe has the names for.
+            return Err(Error::PortablePdbForReadyToRunImage(
+                file_location.to_string(),
+            ));
+        }
+        portable_pdb::get_symbol_map_for_portable_pdb(&pe, pdb_file, file_location)?
+    } else {
+        get_symbol_map_for_pdb(pdb_file, file_location, function_arguments)?
+    };
     if symbol_map.debug_id() != binary_debug_id {
         return Err(Error::UnmatchedDebugId(
             binary_debug_id,
             symbol_map.debug_id(),
         ));
     }
+    // The PDB doesn't know the image base address, so take it from the binary.
+    symbol_map.set_relative_address_base(pe.relative_address_base());
     Ok(symbol_map)
 }
 
@@ -78,11 +97,21 @@ pub fn get_symbol_map_for_pe<H: FileAndPathHelper>(
     ))
 }
 
+fn is_ready_to_run<'data, R: ReadRef<'data>>(pe: &File<'data, R>) -> bool {
+    let methods = match pe {
+        File::Pe32(pe) => ready_to_run_methods(pe),
+        File::Pe64(pe) => ready_to_run_methods(pe),
+        _ => None,
+    };
+    methods.is_some()
+}
+
 #[derive(Yokeable)]
 struct PeObject<'data, T: FileContents> {
     file_data: &'data FileContentsWrapper<T>,
     object: File<'data, &'data FileContentsWrapper<T>>,
     addr2line_context: Addr2lineContextData,
+    ready_to_run_methods: Vec<ReadyToRunMethod>,
 }
 
 impl<'data, T: FileContents> PeObject<'data, T> {
@@ -90,10 +119,16 @@ impl<'data, T: FileContents> PeObject<'data, T> {
         file_data: &'data FileContentsWrapper<T>,
         object: File<'data, &'data FileContentsWrapper<T>>,
     ) -> Self {
+        let ready_to_run_methods = match &object {
+            File::Pe32(pe) => ready_to_run_methods(pe),
+            File::Pe64(pe) => ready_to_run_methods(pe),
+            _ => None,
+        };
         Self {
             file_data,
             object,
             addr2line_context: Addr2lineContextData::new(),
+            ready_to_run_methods: ready_to_run_methods.unwrap_or_default(),
         }
     }
 }
@@ -121,10 +156,22 @@ impl<T: FileContents + 'static> ObjectSymbolMapOuter<T> for PeSymbolMapDataAndOb
             file_data,
             object,
             addr2line_context,
+            ready_to_run_methods,
         } = &self.0.get();
         let debug_id = debug_id_for_object(object)
             .ok_or(Error::InvalidInputError("debug ID cannot be read"))?;
-        let (function_starts, function_ends) = compute_function_addresses_pe(object);
+        let (function_starts, mut function_ends) = compute_function_addresses_pe(object);
+
+        // Managed methods of .NET ReadyToRun images.
+        let extra_symbols: Vec<(u32, &str)> = ready_to_run_methods
+            .iter()
+            .map(|method| (method.start, method.name.as_str()))
+            .collect();
+        let method_ends = ready_to_run_methods.iter().filter_map(|method| method.end);
+        function_ends
+            .get_or_insert_with(Vec::new)
+            .extend(method_ends);
+
         let symbol_map = ObjectSymbolMapInnerWrapper::new(
             object,
             addr2line_context
@@ -134,6 +181,7 @@ impl<T: FileContents + 'static> ObjectSymbolMapOuter<T> for PeSymbolMapDataAndOb
             debug_id,
             function_starts.as_deref(),
             function_ends.as_deref(),
+            &extra_symbols,
             &(),
         );
 
@@ -162,9 +210,87 @@ pub fn is_pdb_file<F: FileContents>(file: &FileContentsWrapper<F>) -> bool {
 }
 
 struct PdbObject<'data, FC: FileContents + 'static> {
+    file_contents: &'data FileContentsWrapper<FC>,
     context_data: pdb_addr2line::ContextPdbData<'data, 'data, &'data FileContentsWrapper<FC>>,
     debug_id: DebugId,
     srcsrv_stream: Option<Box<dyn Deref<Target = [u8]> + Send + 'data>>,
+    sourcelink_stream: Option<Box<dyn Deref<Target = [u8]> + Send + 'data>>,
+    sections: Vec<PeSection>,
+    /// Only present if the binary was rearranged after linking.
+    omap_address_map: Option<AddressMap<'data>>,
+    /// Whether function names from the type information include the
+    /// argument types.
+    function_arguments: bool,
+}
+
+/// Where a section of the PE image is stored in the file, from the section
+/// headers stream of the PDB.
+#[derive(Debug, Clone, Copy)]
+struct PeSection {
+    rva: u32,
+    file_offset: u32,
+    /// The number of bytes of the section which are stored in the file.
+    file_size: u32,
+}
+
+impl PeSection {
+    fn from_header(header: &pdb::ImageSectionHeader) -> Self {
+        // The raw data is padded to the file alignment, and can be longer than
+        // the section itself.
+        let file_size = match header.virtual_size {
+            0 => header.size_of_raw_data,
+            virtual_size => header.size_of_raw_data.min(virtual_size),
+        };
+        Self {
+            rva: header.virtual_address,
+            file_offset: header.pointer_to_raw_data,
+            file_size,
+        }
+    }
+}
+
+/// Whether the address map translates addresses, i.e. whether the PDB has
+/// OMAP streams because the binary was rearranged after linking.
+fn has_omap(address_map: &AddressMap) -> bool {
+    let full_range = Rva(1)..Rva(u32::MAX);
+    let mut ranges = address_map.internal_rva_ranges(full_range.clone());
+    !(ranges.next() == Some(PdbInternalRva(1)..PdbInternalRva(u32::MAX)) && ranges.next().is_none())
+}
+
+/// Translates the functions of an OMAP-rearranged binary, given as ranges in
+/// the PDB's original address space, into the contiguous pieces they occupy
+/// in the binary. The returned pieces are sorted by address.
+fn function_pieces<I: Iterator<Item = Range<u32>>>(
+    functions: Vec<(Range<u32>, String)>,
+    rva_ranges: impl Fn(Range<u32>) -> I,
+) -> Vec<(Range<u32>, String)> {
+    let mut pieces: Vec<(Range<u32>, String)> = Vec::new();
+    for (internal_range, name) in functions {
+        let first_piece_index = pieces.len();
+        for range in rva_ranges(internal_range) {
+            // The OMAP can split up ranges which stay contiguous in the binary.
+            match pieces[first_piece_index..].last_mut() {
+                Some((last_range, _)) if last_range.end == range.start => {
+                    last_range.end = range.end;
+                }
+                _ => pieces.push((range, name.clone())),
+            }
+        }
+    }
+    pieces.sort_unstable_by_key(|(range, _)| range.start);
+    pieces
+}
+
+/// Converts an offset in the PE file into a relative address.
+fn rva_for_file_offset(sections: &[PeSection], file_offset: u32) -> Option<u32> {
+    sections.iter().find_map(|section| {
+        let offset_in_section = file_offset.checked_sub(section.file_offset)?;
+        if offset_in_section < section.file_size {
+            section.rva.checked_add(offset_in_section)
+        } else {
+            None
+        }
+    })
 }
 
 trait PdbObjectTrait {
@@ -178,18 +304,33 @@ impl<'data, FC: FileContents + 'static> PdbObjectTrait for PdbObject<'data, FC>
     fn make_pdb_symbol_map(&self) -> Result<PdbSymbolMapInner<'_>, Error> {
         let context = self.make_context()?;
 
-        let path_mapper = match &self.srcsrv_stream {
+        let srcsrv_path_mapper = match &self.srcsrv_stream {
             Some(srcsrv_stream) => Some(SrcSrvPathMapper::new(srcsrv::SrcSrvStream::parse(
                 srcsrv_stream.deref(),
             )?)),
             None => None,
         };
-        let path_mapper = PathMapper::new_with_maybe_extra_mapper(path_mapper);
+        let sourcelink_path_mapper = self
+            .sourcelink_stream
+            .as_ref()
+            .and_then(|stream| SourceLinkPathMapper::parse(stream));
+        let path_mapper = PathMapper::new_with_maybe_extra_mapper(Some((
+            srcsrv_path_mapper,
+            sourcelink_path_mapper,
+        )));
+
+        let omap_function_pieces = self
+            .omap_address_map
+            .as_ref()
+            .map(|address_map| omap_function_pieces(&*context, address_map));
 
         let symbol_map = PdbSymbolMapInner {
             context,
             debug_id: self.debug_id,
             path_mapper: Mutex::new(path_mapper),
+            sections: self.sections.clone(),
+            omap_function_pieces,
+            line_reader: Box::new(PdbLineReader(self.file_contents)),
         };
         Ok(symbol_map)
     }
@@ -199,11 +340,159 @@ impl<'data, FC: FileContents + 'static> PdbObject<'data, FC> {
     fn make_context<'object>(
         &'object self,
     ) -> Result<Box<dyn PdbAddr2lineContextTrait + Send + 'object>, Error> {
-        let context = self.context_data.make_context().context("make_context()")?;
+        let mut flags = pdb_addr2line::TypeFormatterFlags::default();
+        if !self.function_arguments {
+            flags |= pdb_addr2line::TypeFormatterFlags::NO_ARGUMENTS;
+        }
+        let context = self
+            .context_data
+            .make_context_with_formatter_flags(flags)
+            .context("make_context()")?;
         Ok(Box::new(context))
     }
 }
 
+/// Collects the function pieces of an OMAP-rearranged binary.
+///
+/// pdb-addr2line reports function addresses translated into the binary's
+/// address space, but a function's code can be scattered across the binary,
+/// so function boundaries are only meaningful in the PDB's original address
+/// space. Each function covers the original range up to the next function.
+fn omap_function_pieces(
+    context: &dyn PdbAddr2lineContextTrait,
+    address_map: &AddressMap,
+) -> Vec<(Range<u32>, String)> {
+    let mut functions: Vec<_> = context
+        .functions()
+        .filter_map(|f| {
+            let internal_start = Rva(f.start_rva).to_internal_rva(address_map)?.0;
+            let size = f
+                .end_rva
+                .and_then(|end_rva| end_rva.checked_sub(f.start_rva))
+                .unwrap_or(0);
+            let start_rva = f.start_rva;
+            let name = f.name.unwrap_or_else(|| format!("fun_{start_rva:x}"));
+            Some((internal_start, size, name))
+        })
+        .collect();
+    functions.sort_unstable_by_key(|(internal_start, _, _)| *internal_start);
+
+    let next_starts: Vec<Option<u32>> = functions
+        .iter()
+        .skip(1)
+        .map(|(next_start, _, _)| Some(*next_start))
+        .chain(std::iter::once(None))
+        .collect();
+    let functions = functions
+        .into_iter()
+        .zip(next_starts)
+        .map(|((internal_start, size, name), next_start)| {
+            let internal_end =
+                next_start.unwrap_or_else(|| internal_start.saturating_add(size.max(1)));
+            (internal_start..internal_end, name)
+        })
+        .collect();
+    function_pieces(functions, |range| {
+        address_map
+            .rva_ranges(PdbInternalRva(range.start)..PdbInternalRva(range.end))
+            .map(|range| range.start.0..range.end.0)
+    })
+}
+
+/// A line table entry, with the raw file path.
+struct PdbLineRange {
+    rva_range: Range<u32>,
+    file_path: Option<String>,
+    line_number: u32,
+}
+
+trait PdbLineReaderTrait {
+    fn line_ranges(&self, rva_range: Range<u32>) -> Result<Vec<PdbLineRange>, pdb::Error>;
+}
+
+/// Reads the line programs of the PDB's modules. pdb-addr2line only gives
+/// us the line at a single address, so we open the PDB separately for this.
+struct PdbLineReader<'data, FC: FileContents + 'static>(&'data FileContentsWrapper<FC>);
+
+impl<'data, FC: FileContents + 'static> PdbLineReaderTrait for PdbLineReader<'data, FC> {
+    fn line_ranges(&self, rva_range: Range<u32>) -> Result<Vec<PdbLineRange>, pdb::Error> {
+        let mut pdb = PDB::open(self.0)?;
+        let address_map = pdb.address_map()?;
+        let string_table = pdb.string_table().ok();
+        let dbi = pdb.debug_information()?;
+        let modules: Vec<_> = dbi.modules()?.collect()?;
+        let mut contributions: Vec<_> = dbi.section_contributions()?.collect()?;
+        contributions.sort_unstable_by_key(|c| (c.offset.section, c.offset.offset));
+        let mut module_infos = HashMap::new();
+
+        // Each lines subsection covers one function. Walk through the range
+        // one subsection at a time, starting at the first address which
+        // isn't covered yet.
+        let mut line_ranges = Vec::new();
+        let mut probe = rva_range.start;
+        while probe < rva_range.end {
+            let mut next_probe = probe + 1;
+            let offset = Rva(probe).to_internal_offset(&address_map);
+            let module_index = offset.and_then(|offset| {
+                let index = contributions
+                    .partition_point(|c| {
+                        (c.offset.section, c.offset.offset) <= (offset.section, offset.offset)
+                    })
+                    .checked_sub(1)?;
+                let contribution = &contributions[index];
+                let is_inside = contribution.offset.section == offset.section
+                    && offset.offset - contribution.offset.offset < contribution.size;
+                is_inside.then_some(contribution.module)
+            });
+            if let (Some(offset), Some(module_index)) = (offset, module_index) {
+                let module_info = match module_infos.entry(module_index) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(match modules.get(module_index) {
+                        Some(module) => pdb.module_info(module)?,
+                        None => None,
+                    }),
+                };
+                if let Some(module_info) = module_info {
+                    let line_program = module_info.line_program()?;
+                    let mut lines = line_program.lines_for_symbol(offset);
+                    while let Some(line) = lines.next()? {
+                        let (Some(start), Some(length)) =
+                            (line.offset.to_rva(&address_map), line.length)
+                        else {
+                            continue;
+                        };
+                        let end = start.0.saturating_add(length);
+                        if end > probe {
+                            next_probe = next_probe.max(end);
+                        }
+                        if !(start.0 < rva_range.end && rva_range.start < end) {
+                            continue;
+                        }
+                        let file_path =
+                            match (&string_table, line_program.get_file_info(line.file_index)) {
+                                (Some(string_table), Ok(file_info)) => file_info
+                                    .name
+                                    .to_string_lossy(string_table)
+                                    .ok()
+                                    .map(Cow::into_owned),
+                                _ => None,
+                            };
+                        line_ranges.push(PdbLineRange {
+                            rva_range: start.0..end,
+                            file_path,
+                            line_number: line.line_start,
+                        });
+                    }
+                }
+            }
+            probe = next_probe;
+        }
+        line_ranges.sort_by_key(|line_range| line_range.rva_range.start);
+        line_ranges.dedup_by_key(|line_range| line_range.rva_range.clone());
+        Ok(line_ranges)
+    }
+}
+
 trait PdbAddr2lineContextTrait {
     fn find_frames(
         &self,
@@ -236,7 +525,24 @@ pub struct PdbSymbolMapInnerWrapper<'data>(Box<dyn SymbolMapTrait + Send + 'data
 struct PdbSymbolMapInner<'object> {
     context: Box<dyn PdbAddr2lineContextTrait + Send + 'object>,
     debug_id: DebugId,
-    path_mapper: Mutex<PathMapper<SrcSrvPathMapper<'object>>>,
+    path_mapper: Mutex<PathMapper<PdbPathMapper<'object>>>,
+    sections: Vec<PeSection>,
+    /// For OMAP-rearranged binaries, the functions' pieces, sorted by address.
+    omap_function_pieces: Option<Vec<(Range<u32>, String)>>,
+    line_reader: Box<dyn PdbLineReaderTrait + Send + 'object>,
+}
+
+impl<'object> PdbSymbolMapInner<'object> {
+    /// Finds the function piece which contains `rva`, in an OMAP-rearranged
+    /// binary.
+    fn omap_function_piece(&self, rva: u32) -> Option<&Range<u32>> {
+        let pieces = self.omap_function_pieces.as_ref()?;
+        let index = pieces
+            .partition_point(|(range, _)| range.start <= rva)
+            .checked_sub(1)?;
+        let (range, _) = &pieces[index];
+        range.contains(&rva).then_some(range)
+    }
 }
 
 impl<'object> SymbolMapTrait for PdbSymbolMapInner<'object> {
@@ -245,10 +551,19 @@ impl<'object> SymbolMapTrait for PdbSymbolMapInner<'object> {
     }
 
     fn symbol_count(&self) -> usize {
-        self.context.function_count()
+        match &self.omap_function_pieces {
+            Some(pieces) => pieces.len(),
+            None => self.context.function_count(),
+        }
     }
 
     fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
+        if let Some(pieces) = &self.omap_function_pieces {
+            let iter = pieces
+                .iter()
+                .map(|(range, name)| (range.start, Cow::Borrowed(name.as_str())));
+            return Box::new(iter);
+        }
         let iter = self.context.functions().map(|f| {
             let start_rva = f.start_rva;
             (
@@ -263,25 +578,31 @@ impl<'object> SymbolMapTrait for PdbSymbolMapInner<'object> {
         let rva = match address {
             LookupAddress::Relative(rva) => rva,
             LookupAddress::Svma(_) => {
-                // TODO: Convert svma into rva by subtracting the image base address.
-                // Does the PDB know about the image base address?
+                // The PDB doesn't know the image base address. If the binary is
+                // known, SymbolMap converts the SVMA into a relative address.
                 return None;
             }
-            LookupAddress::FileOffset(_) => {
-                // TODO
-                // Does the PDB know at which file offsets the sections are stored in the binary?
-                return None;
+            LookupAddress::FileOffset(offset) => {
+                rva_for_file_offset(&self.sections, u32::try_from(offset).ok()?)?
             }
         };
         let function_frames = self.context.find_frames(rva).ok()??;
-        let symbol_address = function_frames.start_rva;
         let symbol_name = match &function_frames.frames.last().unwrap().function {
             Some(name) => demangle::demangle_any(name),
             None => "unknown".to_string(),
         };
-        let function_size = function_frames
-            .end_rva
-            .map(|end_rva| end_rva - function_frames.start_rva);
+        let (symbol_address, function_size) = match self.omap_function_piece(rva) {
+            // The function's start address can be far away from the piece
+            // which contains the address, so the piece is the symbol.
+            Some(piece) => (piece.start, Some(piece.end - piece.start)),
+            None if self.omap_function_pieces.is_some() => (rva, None),
+            None => (
+                function_frames.start_rva,
+                function_frames
+                    .end_rva
+                    .map(|end_rva| end_rva - function_frames.start_rva),
+            ),
+        };
 
         let symbol = SymbolInfo {
             address: symbol_address,
@@ -310,6 +631,25 @@ impl<'object> SymbolMapTrait for PdbSymbolMapInner<'object> {
 
         Some(SyncAddressInfo { symbol, frames })
     }
+
+    fn source_line_ranges(&self, range: Range<u32>) -> Vec<SourceLineRange> {
+        let Ok(line_ranges) = self.line_reader.line_ranges(range) else {
+            return Vec::new();
+        };
+        let mut path_mapper = self.path_mapper.lock().unwrap();
+        line_ranges
+            .into_iter()
+            .map(|line_range| SourceLineRange {
+                start: line_range.rva_range.start,
+                end: line_range.rva_range.end,
+                file_path: line_range.file_path.map(|path| {
+                    let mapped_path = path_mapper.map_path(&path);
+                    SourceFilePath::new(path, mapped_path)
+                }),
+                line_number: Some(line_range.line_number),
+            })
+            .collect()
+    }
 }
 
 fn box_stream<'data, T>(stream: T) -> Box<dyn Deref<Target = [u8]> + Send + 'data>
@@ -326,7 +666,7 @@ pub struct PdbObjectWithFileData<T: FileContents + 'static>(
 );
 
 impl<T: FileContents + 'static> PdbObjectWithFileData<T> {
-    fn new(file_data: PdbFileData<T>) -> Result<Self, Error> {
+    fn new(file_data: PdbFileData<T>, function_arguments: bool) -> Result<Self, Error> {
         let data_and_object = Yoke::try_attach_to_cart(Box::new(file_data), |file_data| {
             let mut pdb = PDB::open(&file_data.0)?;
             let info = pdb.pdb_information().context("pdb_information")?;
@@ -339,14 +679,32 @@ impl<T: FileContents + 'static> PdbObjectWithFileData<T> {
                 Err(pdb::Error::StreamNameNotFound | pdb::Error::StreamNotFound(_)) => None,
                 Err(e) => return Err(Error::PdbError("pdb.named_stream(srcsrv)", e)),
             };
+            let sourcelink_stream = match pdb.named_stream(b"sourcelink") {
+                Ok(stream) => Some(box_stream(stream)),
+                Err(pdb::Error::StreamNameNotFound | pdb::Error::StreamNotFound(_)) => None,
+                Err(e) => return Err(Error::PdbError("pdb.named_stream(sourcelink)", e)),
+            };
+
+            // Without section headers, only lookups by relative address work.
+            let sections = match pdb.sections() {
+                Ok(Some(headers)) => headers.iter().map(PeSection::from_header).collect(),
+                _ => Vec::new(),
+            };
+
+            let omap_address_map = pdb.address_map().ok().filter(has_omap);
 
             let context_data = pdb_addr2line::ContextPdbData::try_from_pdb(pdb)
                 .context("ContextConstructionData::try_from_pdb")?;
 
             let pdb_object = PdbObject {
+                file_contents: &file_data.0,
                 context_data,
                 debug_id,
                 srcsrv_stream,
+                sourcelink_stream,
+                sections,
+                omap_address_map,
+                function_arguments,
             };
 
             Ok(PdbObjectWrapper(Box::new(pdb_object)))
@@ -409,13 +767,19 @@ impl<T: FileContents> SymbolMapTrait for PdbSymbolMap<T> {
     fn lookup_sync(&self, address: LookupAddress) -> Option<SyncAddressInfo> {
         self.with_inner(|inner| inner.lookup_sync(address))
     }
+
+    fn source_line_ranges(&self, range: Range<u32>) -> Vec<SourceLineRange> {
+        self.with_inner(|inner| inner.source_line_ranges(range))
+    }
 }
 
 pub fn get_symbol_map_for_pdb<H: FileAndPathHelper>(
     file_contents: FileContentsWrapper<H::F>,
     debug_file_location: H::FL,
+    function_arguments: bool,
 ) -> Result<SymbolMap<H>, Error> {
-    let file_data_and_object = PdbObjectWithFileData::new(PdbFileData(file_contents))?;
+    let file_data_and_object =
+        PdbObjectWithFileData::new(PdbFileData(file_contents), function_arguments)?;
     let symbol_map = PdbSymbolMap::new(file_data_and_object)?;
     Ok(SymbolMap::new_plain(
         debug_file_location,
@@ -423,6 +787,10 @@ pub fn get_symbol_map_for_pdb<H: FileAndPathHelper>(
     ))
 }
 
+/// The srcsrv stream takes precedence over the SourceLink stream if a PDB has
+/// both.
+type PdbPathMapper<'a> = (Option<SrcSrvPathMapper<'a>>, Option<SourceLinkPathMapper>);
+
 /// Map raw file paths to special "permalink" paths, using the srcsrv stream.
 /// This allows finding source code for applications that were not compiled on this
 /// machine, for example when using PDBs that were downloaded from a symbol server.
@@ -430,6 +798,8 @@ pub fn get_symbol_map_for_pdb<H: FileAndPathHelper>(
 ///   - "hg:<repo>:<path>:<rev>"
 ///   - "git:<repo>:<path>:<rev>"
 ///   - "s3:<bucket>:<digest_and_path>:"
+///   - "p4:<server>:<path>:<rev>"
+///   - "svn:<repo>:<path>:<rev>"
 struct SrcSrvPathMapper<'a> {
     srcsrv_stream: srcsrv::SrcSrvStream<'a>,
     cache: HashMap<String, Option<MappedPath>>,
@@ -449,10 +819,12 @@ impl<'a> ExtraPathMapper for SrcSrvPathMapper<'a> {
             Ok(Some((srcsrv::SourceRetrievalMethod::Download { url }, _map))) => {
                 MappedPath::from_url(&url)
             }
-            Ok(Some((srcsrv::SourceRetrievalMethod::ExecuteCommand { .. }, map))) => {
+            Ok(Some((srcsrv::SourceRetrievalMethod::ExecuteCommand { command, env, .. }, map))) => {
                 // We're not going to execute a command here.
-                // Instead, we have special handling for a few known cases (well, only one case for now).
+                // Instead, we have special handling for a few known cases.
                 self.gitiles_to_mapped_path(&map)
+                    .or_else(|| parse_perforce_command(&command, &env))
+                    .or_else(|| parse_svn_command(&command, &map))
             }
             _ => None,
         };
@@ -609,10 +981,169 @@ fn parse_gitiles_url(input: &str) -> Result<MappedPath, nom::Err<nom::error::Err
     })
 }
 
+/// Splits a command line into its arguments, and removes the double quotes.
+fn command_line_args(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = None;
+    let mut in_quotes = false;
+    for c in command.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                arg.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !in_quotes => args.extend(arg.take()),
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+/// Returns the arguments after the invocation of `program`, which may be
+/// given with a directory and with an `.exe` extension, e.g. in
+/// `cmd /c C:\tools\svn.exe cat ...`.
+fn program_args<'a>(args: &'a [String], program: &str) -> Option<&'a [String]> {
+    let position = args.iter().position(|arg| {
+        let file_name = arg.rsplit(['\\', '/']).next().unwrap_or_default();
+        let file_name = file_name.to_ascii_lowercase();
+        file_name.strip_suffix(".exe").unwrap_or(&file_name) == program
+    })?;
+    Some(&args[position + 1..])
+}
+
+/// Whether a revision can be used in a permalink, i.e. whether it's a number
+/// rather than something like "head".
+fn is_revision_number(rev: &str) -> bool {
+    !rev.is_empty() && rev.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// PDBs from Perforce depots get the source files with `p4 print`, e.g.
+/// `p4.exe -p perforce.example.com:1666 print -o "<target>" -q "//depot/main/Engine.cpp#3"`.
+/// If the command has no `-p` option, the server is in the P4PORT environment
+/// variable.
+fn parse_perforce_command(command: &str, env: &HashMap<String, String>) -> Option<MappedPath> {
+    let args = command_line_args(command);
+    let args = program_args(&args, "p4")?;
+    let print_position = args.iter().position(|arg| arg == "print")?;
+    let global_options = &args[..print_position];
+    let server = match global_options.iter().position(|arg| arg == "-p") {
+        Some(index) => global_options.get(index + 1)?,
+        None => env.get("P4PORT")?,
+    };
+    let file_spec = args[print_position + 1..]
+        .iter()
+        .find_map(|arg| arg.strip_prefix("//"))?;
+    let (path, rev) = file_spec.split_at(file_spec.rfind(['#', '@'])?);
+    if path.is_empty() || !is_revision_number(&rev[1..]) {
+        return None;
+    }
+    Some(MappedPath::Perforce {
+        server: server.clone(),
+        path: path.to_owned(),
+        rev: rev.to_owned(),
+    })
+}
+
+/// PDBs from Subversion repositories get the source files with `svn cat`, e.g.
+/// `cmd /c svn.exe cat "https://svn.example.com/repos/game/trunk/main.cpp@1234" --non-interactive > "<target>"`.
+/// The revision can also be given with `-r`. The command only has the URL of
+/// the file, so the repository URL is taken from the srcsrv variable with the
+/// longest prefix of the URL, or else it is the root of the server.
+fn parse_svn_command(command: &str, vars: &HashMap<String, String>) -> Option<MappedPath> {
+    let args = command_line_args(command);
+    let args = program_args(&args, "svn")?;
+    let cat_position = args.iter().position(|arg| arg == "cat")?;
+    let args = &args[cat_position + 1..];
+    let mut rev = args
+        .iter()
+        .position(|arg| arg == "-r" || arg == "--revision")
+        .and_then(|index| args.get(index + 1))
+        .cloned();
+    let url = args.iter().find(|arg| arg.contains("://"))?;
+    let url = match url.rsplit_once('@') {
+        // An '@' in the host part of the URL is followed by a slash.
+        Some((url, peg_rev)) if !peg_rev.contains('/') => {
+            rev = rev.or_else(|| Some(peg_rev.to_owned()));
+            url
+        }
+        _ => url.as_str(),
+    };
+    let rev = rev.filter(|rev| is_revision_number(rev))?;
+
+    let repo = vars
+        .values()
+        .map(|value| value.trim_end_matches('/'))
+        .filter(|value| value.contains("://"))
+        .filter(|value| {
+            url.strip_prefix(value)
+                .map_or(false, |rest| rest.starts_with('/'))
+        })
+        .max_by_key(|value| value.len());
+    let repo = match repo {
+        Some(repo) => repo,
+        None => {
+            let host_start = url.find("://")? + 3;
+            &url[..host_start + url[host_start..].find('/')?]
+        }
+    };
+    let path = url[repo.len()..].trim_start_matches('/');
+    if path.is_empty() {
+        return None;
+    }
+    Some(MappedPath::Svn {
+        repo: repo.to_owned(),
+        path: path.to_owned(),
+        rev,
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_address_map_without_omap() {
+        assert!(!has_omap(&AddressMap::default()));
+    }
+
+    #[test]
+    fn test_function_pieces() {
+        // Moves the original range 0x1100..0x1180 to 0x3000, and leaves the
+        // rest in place, split into two OMAP records at 0x1080.
+        let rva_ranges = |range: Range<u32>| {
+            let records = [
+                (0x1000, 0x1000),
+                (0x1080, 0x1080),
+                (0x1100, 0x3000),
+                (0x1180, 0x1100),
+            ];
+            let mut ranges = Vec::new();
+            for (i, &(source, target)) in records.iter().enumerate() {
+                let record_end = records.get(i + 1).map_or(u32::MAX, |r| r.0);
+                let start = range.start.max(source);
+                let end = range.end.min(record_end);
+                if start < end {
+                    ranges.push(start - source + target..end - source + target);
+                }
+            }
+            ranges.into_iter()
+        };
+        let functions = vec![
+            (0x1000..0x1140, "first".to_string()),
+            (0x1140..0x1200, "second".to_string()),
+        ];
+        assert_eq!(
+            function_pieces(functions, rva_ranges),
+            vec![
+                (0x1000..0x1100, "first".to_string()),
+                (0x1100..0x1180, "second".to_string()),
+                (0x3000..0x3040, "first".to_string()),
+                (0x3040..0x3080, "second".to_string()),
+            ]
+        );
+    }
+
     #[test]
     fn test_parse_gitiles_url() {
         assert_eq!(
@@ -638,4 +1169,79 @@ mod test {
             Err(nom::Err::Error(nom::error::Error::new("otherstuff", nom::error::ErrorKind::Eof)))
         );
     }
+
+    #[test]
+    fn test_srcsrv_perforce() {
+        let stream = r#"SRCSRV: ini ------------------------------------------------
+VERSION=1
+VERCTRL=Perforce
+SRCSRV: variables ------------------------------------------
+P4_EXTRACT_TARGET=%targ%\%var2%\%fnbksl%(%var3%)\%var4%\%fnfile%(%var1%)
+P4_EXTRACT_CMD=p4.exe -p %fnvar%(%var2%) print -o %srcsrvtrg% -q "//%var3%#%var4%"
+GAMESERVER=perforce.example.com:1666
+SRCSRVTRG=%P4_EXTRACT_TARGET%
+SRCSRVCMD=%P4_EXTRACT_CMD%
+SRCSRV: source files ---------------------------------------
+d:\build\game\source\engine.cpp*GAMESERVER*depot/game/main/Source/Engine.cpp*3
+d:\build\game\source\head.cpp*GAMESERVER*depot/game/main/Source/Head.cpp*head
+SRCSRV: end ------------------------------------------------"#;
+        let mut path_mapper =
+            SrcSrvPathMapper::new(srcsrv::SrcSrvStream::parse(stream.as_bytes()).unwrap());
+        assert_eq!(
+            path_mapper.map_path(r"d:\build\game\source\engine.cpp"),
+            Some(MappedPath::Perforce {
+                server: "perforce.example.com:1666".into(),
+                path: "depot/game/main/Source/Engine.cpp".into(),
+                rev: "#3".into(),
+            })
+        );
+        assert_eq!(path_mapper.map_path(r"d:\build\game\source\head.cpp"), None);
+
+        let env = HashMap::from([("P4PORT".to_string(), "ssl:p4.example.com:1666".to_string())]);
+        assert_eq!(
+            parse_perforce_command(r#"p4 print -q "//depot/a b/file.cpp@12345""#, &env),
+            Some(MappedPath::Perforce {
+                server: "ssl:p4.example.com:1666".into(),
+                path: "depot/a b/file.cpp".into(),
+                rev: "@12345".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_srcsrv_svn() {
+        let stream = r#"SRCSRV: ini ------------------------------------------------
+VERSION=1
+VERCTRL=Subversion
+SRCSRV: variables ------------------------------------------
+SVN_EXTRACT_TARGET=%targ%\%fnbksl%(%var3%)\%var4%\%fnfile%(%var1%)
+SVN_EXTRACT_CMD=cmd /c svn.exe cat "%var2%%var3%@%var4%" --non-interactive > "%svn_extract_target%"
+SRCSRVTRG=%svn_extract_target%
+SRCSRVCMD=%svn_extract_cmd%
+SRCSRV: source files ---------------------------------------
+c:\work\game\trunk\src\main.cpp*https://svn.example.com/repos/game*/trunk/src/main.cpp*1234
+SRCSRV: end ------------------------------------------------"#;
+        let mut path_mapper =
+            SrcSrvPathMapper::new(srcsrv::SrcSrvStream::parse(stream.as_bytes()).unwrap());
+        assert_eq!(
+            path_mapper.map_path(r"c:\work\game\trunk\src\main.cpp"),
+            Some(MappedPath::Svn {
+                repo: "https://svn.example.com/repos/game".into(),
+                path: "trunk/src/main.cpp".into(),
+                rev: "1234".into(),
+            })
+        );
+
+        assert_eq!(
+            parse_svn_command(
+                "svn cat -r 77 https://svn.example.com/repos/game/trunk/src/main.cpp",
+                &HashMap::new()
+            ),
+            Some(MappedPath::Svn {
+                repo: "https://svn.example.com".into(),
+                path: "repos/game/trunk/src/main.cpp".into(),
+                rev: "77".into(),
+            })
+        );
+    }
 }
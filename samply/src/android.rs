@@ -0,0 +1,248 @@
+//! Helpers for `samply record-android`, which records an app on an Android
+//! device with simpleperf, over adb, and pulls the perf.data file so that it
+//! can be imported on this machine.
+
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::shared::ctrl_c::CtrlC;
+
+/// Where simpleperf and the recording are stored on the device.
+const DEVICE_SIMPLEPERF_PATH: &str = "/data/local/tmp/simpleperf";
+const DEVICE_PERF_DATA_PATH: &str = "/data/local/tmp/samply-perf.data";
+
+/// How long simpleperf gets for writing out the recording once it's been
+/// asked to stop.
+const STOP_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct AndroidRecordingProps {
+    pub package: String,
+    pub rate: f64,
+    pub duration: Option<f64>,
+    /// Push the simpleperf binary from this NDK, instead of using the one on
+    /// the device.
+    pub ndk: Option<PathBuf>,
+}
+
+/// Runs adb commands for a specific device, or the only connected one.
+pub struct Adb {
+    serial: Option<String>,
+}
+
+impl Adb {
+    pub fn new(serial: Option<String>) -> Self {
+        Self { serial }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("adb");
+        if let Some(serial) = &self.serial {
+            command.arg("-s").arg(serial);
+        }
+        command
+    }
+
+    /// Runs a shell command on the device and returns its output.
+    pub fn shell(&self, args: &[&str]) -> io::Result<String> {
+        let output = self.command().arg("shell").args(args).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "adb shell {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    }
+
+    fn run(&self, args: &[&OsStr]) -> io::Result<()> {
+        let output = self.command().args(args).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "adb {} failed: {}",
+                args.iter()
+                    .map(|arg| arg.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn push(&self, local: &Path, remote: &str) -> io::Result<()> {
+        self.run(&[OsStr::new("push"), local.as_os_str(), OsStr::new(remote)])
+    }
+
+    pub fn pull(&self, remote: &str, local: &Path) -> io::Result<()> {
+        self.run(&[OsStr::new("pull"), OsStr::new(remote), local.as_os_str()])
+    }
+}
+
+/// Records the app with simpleperf until the duration has elapsed or Ctrl+C
+/// is pressed, and pulls the perf.data file to `output_file`.
+pub fn record(adb: &Adb, props: &AndroidRecordingProps, output_file: &Path) -> io::Result<()> {
+    let abi = adb.shell(&["getprop", "ro.product.cpu.abi"])?;
+    let simpleperf = match &props.ndk {
+        Some(ndk) => {
+            let local_simpleperf = ndk_simpleperf_path(ndk, &abi).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("The NDK doesn't have a simpleperf binary for {abi}"),
+                )
+            })?;
+            adb.push(&local_simpleperf, DEVICE_SIMPLEPERF_PATH)?;
+            adb.shell(&["chmod", "a+x", DEVICE_SIMPLEPERF_PATH])?;
+            DEVICE_SIMPLEPERF_PATH
+        }
+        None => "simpleperf",
+    };
+
+    let rate = (props.rate.round() as u64).max(1).to_string();
+    let duration = props.duration.map(|duration| duration.to_string());
+    let mut args = vec![
+        simpleperf,
+        "record",
+        "--app",
+        &props.package,
+        "-g",
+        "-f",
+        &rate,
+        "-o",
+        DEVICE_PERF_DATA_PATH,
+    ];
+    if let Some(duration) = &duration {
+        args.extend(["--duration", duration]);
+    }
+
+    // Ctrl+C also reaches adb, which closes the shell on the device. simpleperf
+    // stops recording when its terminal goes away, and writes out the file.
+    let mut ctrl_c_receiver = CtrlC::observe_oneshot();
+    match &props.duration {
+        Some(duration) => eprintln!("Recording {} for {duration} seconds...", props.package),
+        None => eprintln!("Recording {} until Ctrl+C...", props.package),
+    }
+    let status = adb
+        .command()
+        .arg("shell")
+        .args(&args)
+        .stdin(Stdio::null())
+        .status()?;
+    let interrupted = ctrl_c_receiver.try_recv().is_ok();
+    ctrl_c_receiver.close();
+    if !status.success() && !interrupted {
+        return Err(simpleperf_error(status));
+    }
+
+    wait_for_simpleperf_exit(adb)?;
+    adb.pull(DEVICE_PERF_DATA_PATH, output_file)?;
+    let _ = adb.shell(&["rm", "-f", DEVICE_PERF_DATA_PATH]);
+    Ok(())
+}
+
+fn simpleperf_error(status: ExitStatus) -> io::Error {
+    io::Error::other(format!(
+        "simpleperf record failed ({status}). The app needs to be installed, and debuggable or profileable."
+    ))
+}
+
+/// Waits until the simpleperf process on the device, which may still be
+/// writing the recording, has exited.
+fn wait_for_simpleperf_exit(adb: &Adb) -> io::Result<()> {
+    let start = Instant::now();
+    // pidof exits with an error if there is no such process.
+    while adb.shell(&["pidof", "simpleperf"]).is_ok() {
+        if start.elapsed() > STOP_TIMEOUT {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "simpleperf didn't finish writing the recording",
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    Ok(())
+}
+
+/// The name of the NDK's directory for an ABI's simpleperf binary.
+fn simpleperf_arch(abi: &str) -> Option<&'static str> {
+    match abi {
+        "arm64-v8a" => Some("arm64"),
+        "armeabi-v7a" => Some("arm"),
+        "x86_64" => Some("x86_64"),
+        "x86" => Some("x86"),
+        _ => None,
+    }
+}
+
+/// The target triple of an ABI, as used in the NDK's sysroot.
+fn ndk_triple(abi: &str) -> Option<&'static str> {
+    match abi {
+        "arm64-v8a" => Some("aarch64-linux-android"),
+        "armeabi-v7a" => Some("arm-linux-androideabi"),
+        "x86_64" => Some("x86_64-linux-android"),
+        "x86" => Some("i686-linux-android"),
+        _ => None,
+    }
+}
+
+fn ndk_simpleperf_path(ndk: &Path, abi: &str) -> Option<PathBuf> {
+    let path = ndk
+        .join("simpleperf/bin/android")
+        .join(simpleperf_arch(abi)?)
+        .join("simpleperf");
+    path.is_file().then_some(path)
+}
+
+/// The NDK's library directories for all ABIs, which contain unstripped
+/// copies of the libraries that apps ship from the NDK, like libc++_shared.so.
+pub fn ndk_symbol_dirs(ndk: &Path) -> Vec<PathBuf> {
+    let Ok(hosts) = std::fs::read_dir(ndk.join("toolchains/llvm/prebuilt")) else {
+        return Vec::new();
+    };
+    let mut dirs = Vec::new();
+    for host in hosts.flatten() {
+        let lib_dir = host.path().join("sysroot/usr/lib");
+        for abi in ["arm64-v8a", "armeabi-v7a", "x86_64", "x86"] {
+            let dir = lib_dir.join(ndk_triple(abi).unwrap());
+            if dir.is_dir() {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_ndk_files() {
+        let ndk = tempfile::tempdir().unwrap();
+        let simpleperf_dir = ndk.path().join("simpleperf/bin/android/arm64");
+        std::fs::create_dir_all(&simpleperf_dir).unwrap();
+        std::fs::write(simpleperf_dir.join("simpleperf"), b"").unwrap();
+        let lib_dir = ndk
+            .path()
+            .join("toolchains/llvm/prebuilt/linux-x86_64/sysroot/usr/lib");
+        std::fs::create_dir_all(lib_dir.join("aarch64-linux-android")).unwrap();
+        std::fs::create_dir_all(lib_dir.join("i686-linux-android")).unwrap();
+
+        assert_eq!(
+            ndk_simpleperf_path(ndk.path(), "arm64-v8a"),
+            Some(simpleperf_dir.join("simpleperf"))
+        );
+        assert_eq!(ndk_simpleperf_path(ndk.path(), "x86_64"), None);
+        assert_eq!(ndk_simpleperf_path(ndk.path(), "mips"), None);
+        assert_eq!(
+            ndk_symbol_dirs(ndk.path()),
+            vec![
+                lib_dir.join("aarch64-linux-android"),
+                lib_dir.join("i686-linux-android")
+            ]
+        );
+    }
+}
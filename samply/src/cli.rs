@@ -0,0 +1,3275 @@
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use wholesym::SymbolBackend;
+
+use crate::config::Config;
+use crate::history::{History, HistoryEntry};
+use crate::import::{ImportInput, Importer, ImporterRegistry};
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use crate::linux::profiler;
+use crate::linux_shared::UsdtProbeSpec;
+#[cfg(target_os = "macos")]
+use crate::mac::profiler;
+use crate::profile_json_preparse::parse_libinfo_map_from_profile_file;
+use crate::profile_tools::{
+    CategoryRules, Exporter, ExporterRegistry, Milestone, PostProcessingOptions, ScrubOptions,
+    TimelineAlignment,
+};
+use crate::publish_symbols::{SymbolFileKind, SymbolStore};
+use crate::server::{start_server_main, PortSelection, ServerProps};
+use crate::server_limits::RequestLimits;
+use crate::server_tls::TlsSource;
+use crate::shared::etw_provider_spec::EtwProviderSpec;
+use crate::shared::included_processes::IncludedProcesses;
+use crate::shared::output_markers::OutputMarkerPatterns;
+use crate::shared::recording_props::{
+    AuxEvent, CoreClrProfileProps, ProcessLaunchProps, ProfileCreationProps, RecordingMode,
+    RecordingProps,
+};
+use crate::shared::recursion_folding::RecursionFolding;
+use crate::shared::symbol_props::{self, symbol_backend_name, SymbolProps};
+#[cfg(target_os = "windows")]
+use crate::windows::profiler;
+use crate::{
+    android, compare_symbols, doctor, drop_folder, dump_unwind, history, import, profile_tools,
+    publish_symbols, server, symbol_cache, top, warm_symbols,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "samply",
+    version,
+    about = r#"
+samply is a sampling CPU profiler.
+Run a command, record a CPU profile of its execution, and open the profiler UI.
+Recording is currently supported on Linux and macOS.
+On other platforms, samply can only load existing profiles.
+
+EXAMPLES:
+    # Default usage:
+    samply record ./yourcommand yourargs
+
+    # On Linux, you can also profile existing processes by pid:
+    samply record -p 12345 # Linux only
+
+    # Alternative usage: Save profile to file for later viewing, and then load it.
+    samply record --save-only -o prof.json -- ./yourcommand yourargs
+    samply load prof.json # Opens in the browser and supplies symbols
+
+    # List the profiles in a shared folder, and import recordings dropped into it:
+    samply serve --watch-dir ./profiles --address 0.0.0.0
+
+    # Put default options, like `rate = 4000` or `port = "3000+"`, into
+    # ~/.config/samply/config.toml, optionally in a [record] or [load] section.
+
+    # Record again whenever the sources change, and list the last 5 profiles:
+    samply watch --watch src -- cargo run --release
+
+    # Import perf.data files from Linux perf:
+    samply import perf.data
+
+    # Import an ETW trace, e.g. one captured with WPR (Windows only):
+    samply import trace.etl
+
+    # Import a pprof profile, e.g. from Go's runtime/pprof:
+    samply import cpu.pprof
+
+    # Import simpleperf's protobuf output (simpleperf's perf.data works too):
+    samply import simpleperf_report.pb
+
+    # Import async-profiler's collapsed output, e.g. to merge it with a native profile:
+    samply import --save-only -o java.json profile.collapsed
+
+    # Add the events of an LTTng trace to a profile as markers:
+    babeltrace2 --clock-seconds lttng-trace/ > trace.txt
+    samply import trace.txt --add-markers-to profile.json -o combined.json
+
+    # View the thread stacks in a crash minidump, with symbols from a directory:
+    samply import crash.dmp --symbol-dir path/to/symbols
+
+    # List the profiles recorded so far, and serve a page to reopen them:
+    samply list
+    samply list --serve
+
+    # Upload a profile to profiler.firefox.com and print the link:
+    samply upload profile.json
+
+    # Merge several profiles into one:
+    samply merge run1.json run2.json -o merged.json
+
+    # Merge a Firefox profile, saved from the Firefox Profiler, with a samply recording of the same time:
+    samply merge profile.json firefox-profile.json.gz -o combined.json
+
+    # Compare a profile before and after an optimization:
+    samply diff before.json after.json
+
+    # Cut a profile down to the time between 5 and 12 seconds:
+    samply trim --from 5s --to 12s profile.json -o trimmed.json
+
+    # Print how long it took until main() and until the first paint:
+    samply milestones profile.json
+
+    # Export to the folded stacks format, e.g. for flamegraph.pl or inferno:
+    samply export --format collapsed profile.json -o profile.folded
+
+    # Export for speedscope:
+    samply export --format speedscope profile.json -o profile.speedscope.json
+
+    # Export for Perfetto or chrome://tracing:
+    samply export --format chrome-trace profile.json -o trace.json
+
+    # Export the native allocations as a flame graph of allocated bytes, and list the leaks:
+    samply export --format collapsed-allocated-bytes profile.json -o allocations.folded
+    samply export --format leak-report profile.json
+
+    # Print a text summary of the hottest functions, e.g. in CI logs:
+    samply report profile.json
+
+    # Browse the hottest functions and the call tree in the terminal:
+    samply top profile.json
+
+    # Watch a recording in the terminal while it's running, from a second terminal:
+    samply record --live --save-only -o profile.json -- ./yourcommand yourargs
+    samply top --follow profile.json
+
+    # Publish the symbols of a release build, for profiles recorded elsewhere:
+    samply publish-symbols target/release/myapp --server https://symbols.example.com/
+
+    # Before profiling a new release, fetch the symbols of the modules which changed:
+    samply publish-symbols target/release/myapp --server https://symbols.example.com/ --manifest v2.txt
+    samply warm-symbols v2.txt --since v1.txt --symbol-server https://symbols.example.com/
+
+    # Compare the line numbers from DWARF and from a Breakpad .sym file:
+    samply compare-symbols target/release/myapp --backends dwarf breakpad --breakpad-symbol-dir syms
+
+    # Print the unwind rules at an address, relative to the library's base address:
+    samply dump-unwind target/release/libmylib.so --addr 0x1a2b0
+"#
+)]
+struct Opt {
+    #[command(subcommand)]
+    action: Action,
+
+    /// Read default options from this file instead of config.toml in
+    /// samply's config directory, e.g. ~/.config/samply/config.toml.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Action {
+    #[cfg(any(
+        target_os = "android",
+        target_os = "macos",
+        target_os = "linux",
+        target_os = "windows"
+    ))]
+    /// Record a profile and display it.
+    Record(RecordArgs),
+
+    #[cfg(any(
+        target_os = "android",
+        target_os = "macos",
+        target_os = "linux",
+        target_os = "windows"
+    ))]
+    /// Record a profile again whenever the watched files change, and serve a
+    /// page which lists the most recent profiles.
+    Watch(WatchArgs),
+
+    /// Record an app on an Android device with simpleperf, over adb, and
+    /// display the profile. Pass the directory with the unstripped native
+    /// libraries of the app as --symbol-dir.
+    RecordAndroid(RecordAndroidArgs),
+
+    /// Load a profile from a file and display it.
+    Load(LoadArgs),
+
+    /// Serve a page which lists the profiles in a directory. Recordings which
+    /// are dropped into the directory, like perf.data files or ETW traces, are
+    /// imported when they arrive.
+    Serve(ServeArgs),
+
+    /// Run a symbolication service which profiles from other machines and CI
+    /// jobs can be symbolicated with. The symbol directories, symbol servers,
+    /// cache directories and port can be given in the [serve-symbols] section
+    /// of the file passed with --config.
+    ServeSymbols(ServeSymbolsArgs),
+
+    /// Import a perf.data file from perf or simpleperf, simpleperf's protobuf
+    /// output, a pprof profile (.pprof, .pb.gz), folded stacks (.collapsed,
+    /// .folded) e.g. from async-profiler, a minidump, an LTTng trace printed
+    /// by babeltrace2 or, on Windows, an ETW trace (.etl), and display the
+    /// profile.
+    Import(ImportArgs),
+
+    /// List the profiles which samply has recorded or imported, or serve a
+    /// page which links to them.
+    List(ListArgs),
+
+    /// Upload a profile to profiler.firefox.com and print the link to it.
+    Upload(UploadArgs),
+
+    /// Merge multiple profiles into a single profile and display it. Profiles
+    /// saved from the Firefox Profiler, like Firefox profiles, can be merged
+    /// too.
+    Merge(MergeArgs),
+
+    /// Compare two profiles. The resulting profile's call tree shows the
+    /// difference in sample counts for each call node.
+    Diff(DiffArgs),
+
+    /// Cut a profile down to a time range.
+    Trim(TrimArgs),
+
+    /// Find startup milestones in a profile, like the time until main() runs,
+    /// and print them.
+    Milestones(MilestonesArgs),
+
+    /// Convert a profile into a format which other tools understand.
+    Export(ExportArgs),
+
+    /// Show the functions with the most samples and a flame graph in an
+    /// interactive terminal view.
+    Top(TopArgs),
+
+    /// Print a text summary of a profile, with the functions with the most
+    /// samples, per-thread sample counts and lost events.
+    Report(ReportArgs),
+
+    /// Upload the symbols of locally built binaries to a symbol store, keyed
+    /// by debug ID, so that profiles recorded on other machines can be
+    /// symbolicated.
+    PublishSymbols(PublishSymbolsArgs),
+
+    /// Fetch the symbols of the modules which changed between two releases
+    /// into the local symbol cache, so that the first profiles after a deploy
+    /// are symbolicated quickly.
+    WarmSymbols(WarmSymbolsArgs),
+
+    /// Look up the symbols of a binary with two kinds of symbol files, e.g.
+    /// DWARF and Breakpad, and print the addresses for which they disagree.
+    CompareSymbols(CompareSymbolsArgs),
+
+    /// Show how much space the downloaded symbol files take up, and remove
+    /// them.
+    Cache(CacheArgs),
+
+    /// Print the unwind information covering an address of a binary, to
+    /// diagnose broken stacks.
+    DumpUnwind(DumpUnwindArgs),
+
+    /// Check whether this machine is set up for recording, e.g. the
+    /// perf_event_paranoid level on Linux, and print how to fix what isn't.
+    Doctor,
+
+    #[cfg(target_os = "windows")]
+    #[clap(hide = true)]
+    /// Used in the elevated helper process.
+    RunElevatedHelper(RunElevatedHelperArgs),
+
+    /// Codesign the samply binary on macOS to allow attaching to processes.
+    #[cfg(target_os = "macos")]
+    Setup,
+}
+
+#[derive(Debug, Args)]
+struct LoadArgs {
+    /// Paths to the files that should be loaded. With more than one file, the
+    /// server shows a page which lists them.
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// A directory from which more profiles can be added to the list while
+    /// the server is running, by POSTing their path to <prefix>/profiles.
+    /// Can be specified multiple times.
+    #[arg(long = "profile-dir", value_name = "DIR")]
+    profile_dirs: Vec<PathBuf>,
+
+    #[command(flatten)]
+    server_args: ServerArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct ListArgs {
+    /// Only list the most recent profiles.
+    #[arg(long, value_name = "COUNT")]
+    last: Option<usize>,
+
+    /// Serve a page which lists the profiles, to open them in the profiler.
+    #[arg(long)]
+    serve: bool,
+
+    #[command(flatten)]
+    server_args: ServerArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct ServeSymbolsArgs {
+    /// The path under which the symbolication API is served, so that the
+    /// symbol server URL stays the same across restarts. A random path is
+    /// used if not given.
+    #[arg(long, value_name = "PATH", value_parser = parse_path_prefix)]
+    path_prefix: Option<String>,
+
+    #[command(flatten)]
+    server_args: ServerArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct ServeArgs {
+    /// Directory to watch for profiles (.json, .json.gz) and recordings
+    /// (perf.data, .etl, .pprof and the other formats of samply import). The
+    /// imported profiles are saved in its .converted subdirectory.
+    #[arg(long, value_name = "DIR")]
+    watch_dir: PathBuf,
+
+    #[command(flatten)]
+    server_args: ServerArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct ImportArgs {
+    /// Path to the profile file that should be imported.
+    file: PathBuf,
+
+    /// The format of the file. If not given, it's detected from the file
+    /// name and contents.
+    #[arg(long, value_name = "FORMAT", value_parser = import_format_parser())]
+    format: Option<String>,
+
+    #[command(flatten)]
+    profile_creation_args: ProfileCreationArgs,
+
+    /// Do not run a local server after recording.
+    #[arg(short, long)]
+    save_only: bool,
+
+    /// Output filename.
+    #[arg(short, long, default_value = "profile.json")]
+    output: PathBuf,
+
+    #[command(flatten)]
+    server_args: ServerArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+
+    /// Only include processes with this name substring (can be specified multiple times).
+    #[arg(long)]
+    name: Option<Vec<String>>,
+
+    /// Only include process with this PID (can be specified multiple times).
+    #[arg(long)]
+    pid: Option<Vec<u32>>,
+
+    /// Explicitly specify architecture of profile to import.
+    #[arg(long)]
+    override_arch: Option<String>,
+
+    /// Enable CoreCLR event conversion.
+    #[clap(long, require_equals = true, value_name = "FLAG", value_enum, value_delimiter = ',', num_args = 0.., default_values_t = vec![CoreClrArgs::Enabled])]
+    coreclr: Vec<CoreClrArgs>,
+
+    /// Add the events of the imported LTTng trace as markers to this existing
+    /// profile, lined up by timestamp, instead of creating a new profile.
+    #[arg(long, value_name = "PROFILE")]
+    add_markers_to: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct UploadArgs {
+    /// Path to the profile file that should be uploaded.
+    file: PathBuf,
+
+    /// Don't look up function names. The shared profile will only show the
+    /// names which are already in the file.
+    #[arg(long)]
+    no_symbolicate: bool,
+
+    /// Print debugging output about symbol lookups.
+    #[arg(short, long)]
+    verbose: bool,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct MergeArgs {
+    /// Paths to the profile files that should be merged.
+    #[arg(required = true, num_args = 2..)]
+    files: Vec<PathBuf>,
+
+    /// How to place the timelines of the merged profiles relative to each other.
+    #[arg(long, value_enum, default_value_t = TimelineArg::Absolute)]
+    timeline: TimelineArg,
+
+    /// Do not run a local server after merging.
+    #[arg(short, long)]
+    save_only: bool,
+
+    /// Output filename.
+    #[arg(short, long, default_value = "merged.json")]
+    output: PathBuf,
+
+    #[command(flatten)]
+    server_args: ServerArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct DiffArgs {
+    /// The profile from before the change.
+    before: PathBuf,
+
+    /// The profile from after the change.
+    after: PathBuf,
+
+    /// Do not run a local server after comparing.
+    #[arg(short, long)]
+    save_only: bool,
+
+    /// Output filename.
+    #[arg(short, long, default_value = "diff.json")]
+    output: PathBuf,
+
+    #[command(flatten)]
+    server_args: ServerArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct TrimArgs {
+    /// Path to the profile file that should be trimmed.
+    input: PathBuf,
+
+    /// The start of the time range to keep, relative to the start of the profile,
+    /// e.g. "5s" or "1500ms". Defaults to the start of the profile.
+    #[arg(long, value_parser = parse_time_offset)]
+    from: Option<f64>,
+
+    /// The end of the time range to keep, relative to the start of the profile.
+    /// Defaults to the end of the profile.
+    #[arg(long, value_parser = parse_time_offset)]
+    to: Option<f64>,
+
+    /// Do not run a local server after trimming.
+    #[arg(short, long)]
+    save_only: bool,
+
+    /// Output filename.
+    #[arg(short, long, default_value = "trimmed.json")]
+    output: PathBuf,
+
+    #[command(flatten)]
+    server_args: ServerArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct MilestonesArgs {
+    /// Path to the profile file.
+    input: PathBuf,
+
+    /// A milestone preset: "main" (process start until main() runs) or
+    /// "first-paint" (until the first FirstPaint / FirstContentfulPaint marker).
+    /// If neither --preset nor --milestone is given, all presets are used.
+    #[arg(long)]
+    preset: Vec<String>,
+
+    /// A custom milestone, either NAME=func:FUNCTION for the first sample in a
+    /// function, or NAME=marker:MARKER for the first marker with this name.
+    /// Function names are only known in symbolicated profiles.
+    #[arg(long)]
+    milestone: Vec<Milestone>,
+
+    /// Write a copy of the profile with a marker for each milestone to this file.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct ExportArgs {
+    /// Path to the profile file that should be exported.
+    input: PathBuf,
+
+    /// The format to export to.
+    #[arg(long, value_parser = export_format_parser())]
+    format: String,
+
+    /// Output filename. If not given, the output is written to stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Don't look up function names. The exported frames are named after
+    /// their addresses, unless the profile has been symbolicated already.
+    #[arg(long)]
+    no_symbolicate: bool,
+
+    /// Print debugging output about symbol lookups.
+    #[arg(short, long)]
+    verbose: bool,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct TopArgs {
+    /// Path to the profile file that should be shown.
+    input: PathBuf,
+
+    /// Update the view whenever the profile file changes, e.g. while
+    /// `samply record --live` writes it.
+    #[arg(long)]
+    follow: bool,
+
+    /// Don't look up function names.
+    #[arg(long)]
+    no_symbolicate: bool,
+
+    /// Print debugging output about symbol lookups.
+    #[arg(short, long)]
+    verbose: bool,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct ReportArgs {
+    /// Path to the profile file that should be summarized.
+    input: PathBuf,
+
+    /// How many functions to list in each table.
+    #[arg(long, default_value = "10")]
+    top: usize,
+
+    /// Output filename. If not given, the report is written to stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Don't look up function names.
+    #[arg(long)]
+    no_symbolicate: bool,
+
+    /// Print debugging output about symbol lookups.
+    #[arg(short, long)]
+    verbose: bool,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct CompareSymbolsArgs {
+    /// Path to the binary whose symbols should be compared.
+    binary: PathBuf,
+
+    /// The two kinds of symbol files to compare: dwarf, breakpad or pdb.
+    #[arg(long, num_args = 2, required = true, value_names = ["A", "B"], value_parser = parse_symbol_backend_arg)]
+    backends: Vec<SymbolBackend>,
+
+    /// How many differing addresses to print.
+    #[arg(long, default_value = "20")]
+    limit: usize,
+
+    /// Print debugging output about symbol lookups.
+    #[arg(short, long)]
+    verbose: bool,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct CacheArgs {
+    /// Remove all downloaded symbol files.
+    #[arg(long, conflicts_with = "trim_to")]
+    clear: bool,
+
+    /// Remove the least recently used files until the cache is at most this
+    /// size, e.g. 10GB.
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    trim_to: Option<usize>,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct DumpUnwindArgs {
+    /// Path to the binary.
+    binary: PathBuf,
+
+    /// The address, relative to the base address of the binary, like the
+    /// addresses shown in the profiler. Hexadecimal with 0x prefix, or decimal.
+    #[arg(long, value_parser = parse_address)]
+    addr: u32,
+}
+
+#[derive(Debug, Args)]
+struct PublishSymbolsArgs {
+    /// Paths to the binaries whose symbols should be published.
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// The symbol store: an http(s) URL which accepts PUT requests at the
+    /// symbol file paths, an s3://bucket/prefix or gs://bucket/prefix URL, or a
+    /// local directory. Buckets use the credentials from the environment, e.g.
+    /// AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY.
+    #[arg(long)]
+    server: String,
+
+    /// An extra HTTP header for the uploads, e.g. "Auth-Token: 1234". Can be
+    /// given multiple times.
+    #[arg(long, value_name = "NAME: VALUE", value_parser = parse_header)]
+    header: Vec<(String, String)>,
+
+    /// Upload the original debug files instead of Breakpad .sym files. These
+    /// include line numbers and inline frames, but are usually much larger.
+    #[arg(long)]
+    original: bool,
+
+    /// Don't upload files which are already in the symbol store.
+    #[arg(long)]
+    skip_existing: bool,
+
+    /// Also write a build manifest, which lists the debug name and debug ID
+    /// of each binary, for samply warm-symbols.
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct WarmSymbolsArgs {
+    /// The build manifest of the new release, as written by samply
+    /// publish-symbols --manifest.
+    manifest: PathBuf,
+
+    /// The build manifest of the previous release. Only the modules which
+    /// aren't in it are fetched. By default, all modules are fetched.
+    #[arg(long, value_name = "OLD_MANIFEST")]
+    since: Option<PathBuf>,
+
+    /// Print each module whose symbols were fetched.
+    #[arg(short, long)]
+    verbose: bool,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum TimelineArg {
+    /// Keep each profile's recorded start time, e.g. for profiles from several machines
+    /// which were recorded at the same time.
+    Absolute,
+    /// Let all profiles start at the same time, e.g. for several benchmark iterations.
+    Aligned,
+    /// Place the profiles one after another.
+    Concatenated,
+}
+
+#[allow(unused)]
+#[derive(Debug, Args)]
+struct RecordArgs {
+    /// Sampling rate, in Hz
+    #[arg(short, long, default_value = "1000")]
+    rate: f64,
+
+    /// Limit the recorded time to the specified number of seconds
+    #[arg(short, long)]
+    duration: Option<f64>,
+
+    /// How many times to run the profiled command. Each run shows up as its
+    /// own process in the profile, unless --merge-iterations is used.
+    #[arg(long, default_value = "1", visible_alias = "iterations")]
+    iteration_count: u32,
+
+    /// Merge the runs of the profiled command into one process, e.g. for noisy
+    /// microbenchmarks where a single run isn't representative.
+    #[arg(long)]
+    merge_iterations: bool,
+
+    #[command(flatten)]
+    profile_creation_args: ProfileCreationArgs,
+
+    /// Do not run a local server after recording.
+    #[arg(short, long)]
+    save_only: bool,
+
+    /// Look up the function names after recording, upload the profile to
+    /// profiler.firefox.com and print the link, instead of running a local
+    /// server.
+    #[arg(long)]
+    upload: bool,
+
+    /// Output filename.
+    #[arg(short, long, default_value = "profile.json")]
+    output: PathBuf,
+
+    #[command(flatten)]
+    ci_args: CiArgs,
+
+    #[command(flatten)]
+    server_args: ServerArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+
+    /// Profile the execution of this command.
+    #[arg(
+        required_unless_present_any = ["pid", "all"],
+        conflicts_with_all = ["pid", "all"],
+        allow_hyphen_values = true,
+        trailing_var_arg = true
+    )]
+    command: Vec<std::ffi::OsString>,
+
+    /// Process ID of existing process to attach to.
+    #[arg(short, long, conflicts_with = "all")]
+    pid: Option<u32>,
+
+    /// Profile entire system (all processes). Not supported on macOS.
+    #[arg(short, long, conflicts_with = "pid")]
+    all: bool,
+
+    /// Enable CoreCLR event capture.
+    #[clap(long, require_equals = true, value_name = "FLAG", value_enum, value_delimiter = ',', num_args = 0.., default_missing_value = "enabled")]
+    coreclr: Vec<CoreClrArgs>,
+
+    /// VM hack for arm64 Windows VMs to not try to record PROFILE events (Windows only).
+    #[cfg(target_os = "windows")]
+    #[arg(long)]
+    vm_hack: bool,
+
+    /// Enable Graphics-related event capture.
+    #[arg(long)]
+    gfx: bool,
+
+    /// Enable browser-related event capture (JavaScript stacks and trace events)
+    #[arg(long)]
+    browsers: bool,
+
+    /// Record the kinds of keyboard and mouse input events (not their contents)
+    /// as markers. Linux only, needs read access to /dev/input.
+    #[arg(long)]
+    input_markers: bool,
+
+    /// Record which application's window had the focus as markers.
+    /// Linux X11 sessions only, needs xprop. Wayland sessions, macOS and
+    /// Windows are not supported.
+    #[arg(long)]
+    focus_markers: bool,
+
+    /// Also sample this hardware event, and store its counts as a second
+    /// weight of each sample. Use --swap-weights to make it the primary
+    /// weight. Linux only.
+    #[arg(long, value_enum)]
+    aux_event: Option<AuxEventArg>,
+
+    /// Keep recording for this many seconds after the launched command has
+    /// exited, e.g. to capture daemonized child processes or asynchronous
+    /// teardown work. Linux and macOS only.
+    #[arg(long, value_name = "SECONDS", conflicts_with_all = ["pid", "all"])]
+    linger: Option<f64>,
+
+    /// Stop recording once the profiled program adds a marker with this name
+    /// to its marker file. Linux and macOS only.
+    #[arg(long, value_name = "NAME")]
+    stop_on_marker: Option<String>,
+
+    /// Record the firings of this USDT probe in the profiled binary as
+    /// markers, with the probe's arguments. Can be given multiple times.
+    /// Linux only.
+    #[arg(long = "usdt", value_name = "PROVIDER:PROBE")]
+    usdt_probes: Vec<UsdtProbeSpec>,
+
+    /// Record the events of this ETW provider as markers, with the event's
+    /// fields. The provider is a GUID or a registered provider name, or
+    /// `*Name` for TraceLogging and EventSource providers, optionally followed
+    /// by `:<keywords>:<level>`. Can be given multiple times. Windows only.
+    #[arg(long = "etw-provider", value_name = "PROVIDER")]
+    etw_providers: Vec<EtwProviderSpec>,
+
+    /// Add an instant marker whenever a line of the launched command's stdout
+    /// or stderr matches this regular expression. The marker's name is the
+    /// matched text, and the captured groups are shown in its tooltip. Can be
+    /// given multiple times. Linux only.
+    #[arg(long, value_name = "REGEX", conflicts_with_all = ["pid", "all"])]
+    marker_from_output: Vec<regex::Regex>,
+
+    /// Record every line of the launched command's stdout and stderr as a
+    /// marker on an "Output" track, so that the log output can be browsed on
+    /// the timeline. Linux only.
+    #[arg(long, conflicts_with_all = ["pid", "all"])]
+    log_output: bool,
+
+    /// Also add a marker for each line which is written to the FIFO at this
+    /// path, named after the line's text. The FIFO is created if it doesn't
+    /// exist. Independently of this option, sending SIGUSR2 to samply adds a
+    /// "user marker N" marker, and so does pressing Enter in samply's terminal
+    /// when recording with --pid. Linux only.
+    #[arg(long, value_name = "PATH")]
+    marker_fifo: Option<PathBuf>,
+
+    /// Show the functions of launched Python 3.12+ processes in the stacks,
+    /// interleaved with the native frames, by turning on CPython's perf
+    /// trampolines (PYTHONPERFSUPPORT=1). Linux only.
+    #[arg(long, conflicts_with_all = ["pid", "all"])]
+    python: bool,
+
+    /// Record samply's own CPU usage, the number of ring buffer wakeups and
+    /// the number of lost records as counters and in the profile metadata,
+    /// to check that the profiler doesn't perturb the results. Linux only.
+    #[arg(long)]
+    measure_overhead: bool,
+
+    /// Start the server when the recording starts, instead of when it ends.
+    /// The profile is written every few seconds while recording, and reloading
+    /// the profiler shows what has been recorded so far. With --save-only, the
+    /// profile is only written, e.g. for `samply top --follow`. Linux only.
+    #[arg(long)]
+    live: bool,
+
+    /// How often the profile is written with --live, in seconds.
+    #[arg(long, value_name = "SECONDS", default_value = "5", requires = "live")]
+    live_interval: f64,
+}
+
+#[derive(Debug, Args)]
+struct RecordAndroidArgs {
+    /// The package name of the app to profile, e.g. com.example.app. The app
+    /// needs to be debuggable or profileable.
+    #[arg(long)]
+    package: String,
+
+    /// The serial number of the device, if more than one device is connected.
+    #[arg(short = 'S', long)]
+    serial: Option<String>,
+
+    /// Sampling rate, in Hz
+    #[arg(short, long, default_value = "1000")]
+    rate: f64,
+
+    /// Limit the recorded time to the specified number of seconds. By
+    /// default, recording stops when Ctrl+C is pressed.
+    #[arg(short, long)]
+    duration: Option<f64>,
+
+    /// Path to an Android NDK. Its simpleperf is used instead of the one on
+    /// the device, and its libraries, like libc++_shared.so, are used for
+    /// symbols. Defaults to $ANDROID_NDK_HOME.
+    #[arg(long)]
+    ndk: Option<PathBuf>,
+
+    /// Where to store the perf.data file from the device.
+    #[arg(long, default_value = "perf.data")]
+    perf_data: PathBuf,
+
+    #[command(flatten)]
+    profile_creation_args: ProfileCreationArgs,
+
+    /// Do not run a local server after recording.
+    #[arg(short, long)]
+    save_only: bool,
+
+    /// Output filename.
+    #[arg(short, long, default_value = "profile.json")]
+    output: PathBuf,
+
+    #[command(flatten)]
+    server_args: ServerArgs,
+
+    #[command(flatten)]
+    symbol_args: SymbolArgs,
+}
+
+#[derive(Debug, Args)]
+struct WatchArgs {
+    /// Directory for the recorded profiles. The profiles are named after
+    /// --output, with a number for each recording, e.g. profile-3.json.
+    #[arg(long, default_value = "samply-watch")]
+    output_dir: PathBuf,
+
+    /// How many of the most recent profiles to keep. Older profiles are deleted.
+    #[arg(long, default_value = "5", value_parser = clap::value_parser!(u32).range(1..))]
+    keep: u32,
+
+    /// Record again when this file changes. Directories are watched
+    /// recursively. Can be specified multiple times. Defaults to the profiled
+    /// command if it's a path to a file. If nothing is watched, samply records
+    /// again whenever Enter is pressed.
+    #[arg(long = "watch", value_name = "PATH")]
+    watch_paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    record_args: RecordArgs,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum CoreClrArgs {
+    Enabled,
+    #[cfg(target_os = "windows")]
+    GcMarkers,
+    #[cfg(target_os = "windows")]
+    GcSuspendedThreads,
+    #[cfg(target_os = "windows")]
+    GcDetailedAllocs,
+    #[cfg(target_os = "windows")]
+    EventStacks,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum AuxEventArg {
+    Instructions,
+    CacheReferences,
+    CacheMisses,
+    BranchMisses,
+}
+
+impl std::fmt::Display for CoreClrArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Arguments for recording in automated performance checks.
+#[derive(Debug, Args)]
+struct CiArgs {
+    /// Don't run a server or open the profiler. After recording, print a
+    /// summary of the profile as JSON on the last line of stdout: the sample
+    /// count, lost events, the ratio of unsymbolicated samples and the top
+    /// functions. Exits with code 3 if one of the thresholds is exceeded.
+    #[arg(long)]
+    ci: bool,
+
+    /// Fail if the profile has fewer samples than this.
+    #[arg(long, value_name = "COUNT", requires = "ci")]
+    min_samples: Option<usize>,
+
+    /// Fail if the profile has more lost events than this.
+    #[arg(long, value_name = "COUNT", requires = "ci")]
+    max_lost_events: Option<u64>,
+
+    /// Fail if a larger fraction of the samples than this, between 0 and 1,
+    /// has no function name for its leaf frame after symbolication.
+    #[arg(long, value_name = "RATIO", requires = "ci")]
+    max_unsymbolicated_ratio: Option<f64>,
+
+    /// Fail if the function is on the stack in more than this percentage of
+    /// the samples, given as FUNCTION=PERCENT (can be specified multiple
+    /// times).
+    #[arg(long, value_name = "FUNCTION=PERCENT", requires = "ci", value_parser = parse_function_share)]
+    max_function_share: Vec<(String, f64)>,
+}
+
+#[derive(Debug, Args)]
+struct ServerArgs {
+    /// Do not open the profiler UI.
+    #[arg(short, long)]
+    no_open: bool,
+
+    /// The address to use for the local web server
+    #[arg(long, default_value = "127.0.0.1")]
+    address: String,
+
+    /// The port to use for the local web server
+    #[arg(short = 'P', long, default_value = "3000+")]
+    port: String,
+
+    /// Also serve the symbolication API over gRPC on this port. The service is
+    /// described by samply's proto/symbolication.proto.
+    #[cfg(feature = "grpc")]
+    #[arg(long, value_name = "PORT")]
+    grpc_port: Option<String>,
+
+    /// Listen on this Unix domain socket instead of a TCP port, e.g. behind a
+    /// reverse proxy. Only the current user can connect to the socket.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["address", "port"])]
+    unix_socket: Option<PathBuf>,
+
+    /// The URL under which the server is reachable, e.g. through a reverse
+    /// proxy. It is used in the printed and served links.
+    #[arg(long, value_name = "URL")]
+    public_url: Option<String>,
+
+    /// Serve over HTTPS, e.g. to open the profile from another machine. The
+    /// certificate is self-signed unless --tls-cert and --tls-key are given.
+    #[arg(long)]
+    tls: bool,
+
+    /// A PEM file with the certificate chain to serve HTTPS with. Implies --tls.
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// A PEM file with the private key of --tls-cert.
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Require an `Authorization: Bearer` header with a token for the
+    /// symbolication, source and assembly requests, including over gRPC.
+    /// The token is taken from SAMPLY_AUTH_TOKEN, or generated and printed.
+    /// The profiler UI can't send the token, so this is for servers which
+    /// are queried by other tools.
+    #[arg(long)]
+    require_auth: bool,
+
+    /// Only let web pages from this origin query the server, e.g. a
+    /// self-hosted profiler at https://profiler.example.com, next to the
+    /// profiler which samply opens (PROFILER_URL, or profiler.firefox.com).
+    /// Can be given multiple times. By default, pages from any origin can.
+    #[arg(long, value_name = "ORIGIN")]
+    allow_origin: Vec<String>,
+
+    /// Handle at most this many symbolication requests at the same time.
+    /// Further requests wait until one of them is done.
+    #[arg(long, value_name = "COUNT")]
+    max_concurrent_requests: Option<usize>,
+
+    /// Answer more than this many symbolication requests per minute from one
+    /// client address with 429 Too Many Requests.
+    #[arg(long, value_name = "COUNT")]
+    max_requests_per_minute: Option<u32>,
+
+    /// Print debugging output.
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+/// Arguments describing where to obtain symbol files.
+#[derive(Debug, Args)]
+struct SymbolArgs {
+    /// Extra directories containing symbol files
+    #[arg(long)]
+    symbol_dir: Vec<PathBuf>,
+
+    /// Additional URLs of symbol servers serving PDB / DLL / EXE files. Can
+    /// also be an s3://bucket/prefix or gs://bucket/prefix URL.
+    #[arg(long)]
+    windows_symbol_server: Vec<String>,
+
+    /// Overrides the default cache directory for Windows symbol files which were downloaded from a symbol server
+    #[arg(long)]
+    windows_symbol_cache: Option<PathBuf>,
+
+    /// Additional URLs of symbol servers serving Breakpad .sym files. Can also
+    /// be an s3://bucket/prefix or gs://bucket/prefix URL.
+    #[arg(long)]
+    breakpad_symbol_server: Vec<String>,
+
+    /// Additional local directories containing Breakpad .sym files
+    #[arg(long)]
+    breakpad_symbol_dir: Vec<String>,
+
+    /// Overrides the default cache directory for Breakpad symbol files
+    #[arg(long)]
+    breakpad_symbol_cache: Option<PathBuf>,
+
+    /// Extra directory containing symbol files, with the directory structure used by simpleperf's scripts
+    #[arg(long)]
+    simpleperf_binary_cache: Option<PathBuf>,
+
+    /// Only use one kind of symbol file for a library, given as
+    /// DEBUGNAME=BACKEND, where BACKEND is dwarf, breakpad or pdb
+    /// (can be specified multiple times)
+    #[arg(long, value_name = "DEBUGNAME=BACKEND", value_parser = parse_forced_backend)]
+    symbol_backend: Vec<(String, SymbolBackend)>,
+
+    /// Write a report of the symbol lookup to this file: for each library,
+    /// every file which was tried, why it was not used (missing, wrong debug
+    /// ID, parse error) and which file was used. The report is JSON if the
+    /// file name ends in .json, and text otherwise.
+    #[arg(long, value_name = "REPORT")]
+    symbol_debug: Option<PathBuf>,
+
+    /// Keep the directories with downloaded symbol files below this size,
+    /// e.g. 20GB, by removing the least recently used files after downloads.
+    /// By default, the cache is not limited. See also samply cache.
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    symbol_cache_size: Option<usize>,
+
+    /// Download at most this many symbol files at the same time from each
+    /// symbol server. By default, there is no limit.
+    #[arg(long, value_name = "COUNT")]
+    max_downloads_per_server: Option<usize>,
+
+    /// Leave out the argument types from the names of functions whose names
+    /// come from the type information in PDB files. By default, the argument
+    /// types are included so that overloaded functions can be told apart.
+    #[arg(long)]
+    no_pdb_function_args: bool,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct ProfileCreationArgs {
+    /// Set a custom name for the recorded profile.
+    /// By default it is either the command that was run or the process pid.
+    #[arg(long)]
+    profile_name: Option<String>,
+
+    /// Only include the main thread of each process in order to reduce profile size,
+    /// only respected on Windows and macOS
+    #[arg(long)]
+    main_thread_only: bool,
+
+    /// Merge non-overlapping threads of the same name.
+    #[arg(long)]
+    reuse_threads: bool,
+
+    /// Fold repeated frames at the base of the stack.
+    #[arg(long)]
+    fold_recursive_prefix: bool,
+
+    /// Collapse cycles of up to this many frames which repeat back to back
+    /// anywhere in the stack, e.g. in recursive interpreters and parsers.
+    /// A length of 1 folds directly recursive functions.
+    #[arg(long, value_name = "MAX_CYCLE_LENGTH")]
+    fold_recursion: Option<usize>,
+
+    /// With --fold-recursion, keep this many repetitions of each cycle and
+    /// only collapse the recursion which goes deeper than that.
+    #[arg(
+        long,
+        value_name = "DEPTH",
+        default_value_t = 1,
+        requires = "fold_recursion"
+    )]
+    fold_recursion_depth: usize,
+
+    /// Fold frames whose function name matches this regular expression into
+    /// their caller, e.g. interpreter trampolines or `std::function`
+    /// plumbing. Can be given multiple times. Native function names are only
+    /// known if the profile is symbolicated, e.g. with
+    /// --unstable-presymbolicate.
+    #[arg(long, value_name = "REGEX")]
+    fold_frames: Vec<regex::Regex>,
+
+    /// If a process produces jitdump or marker files, unlink them after
+    /// opening. This ensures that the files will not be left in /tmp,
+    /// but it will also be impossible to look at JIT disassembly, and line
+    /// numbers will be missing for JIT frames.
+    #[arg(long)]
+    unlink_aux_files: bool,
+
+    /// Create a separate thread for each CPU. Not supported on macOS
+    #[arg(long)]
+    per_cpu_threads: bool,
+
+    /// Emit .syms.json sidecar file containing gathered symbol info for all frames referenced by
+    /// this profile. With this file along with the profile, samply can load the profile
+    /// and provide symbols to the front end without needing debug files to be
+    /// available. (Unstable: will probably change to include the full information
+    /// in the profile.json, instead of a sidecar file.)
+    #[arg(long)]
+    unstable_presymbolicate: bool,
+
+    /// Emit markers for any unknown ETW events that are encountered.
+    #[cfg(target_os = "windows")]
+    #[arg(long)]
+    unknown_event_markers: bool,
+
+    /// Remove personal information before saving the profile, so that it can be
+    /// shared: home directory paths, environment variable values and URLs in
+    /// markers. Libraries in home directories can't be symbolicated from local
+    /// files afterwards.
+    #[arg(long)]
+    scrub: bool,
+
+    /// Also replace function names containing this string. Can be given
+    /// multiple times. Implies --scrub.
+    #[arg(long, value_name = "STRING")]
+    scrub_function: Vec<String>,
+
+    /// Keep the saved profile below this size, e.g. 200MB, so that it can still
+    /// be loaded in the browser. Larger profiles are downsampled: only some of
+    /// the samples are kept, and rarely sampled stacks are merged into their
+    /// parent. The applied reduction is shown in the profile info panel.
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    max_output_size: Option<usize>,
+
+    /// For profiles which sampled two events, e.g. with --aux-event, weight
+    /// the samples by the second event instead of the first.
+    #[arg(long)]
+    swap_weights: bool,
+
+    /// Put frames into categories according to the rules in this file, one
+    /// `REGEX -> CATEGORY` rule per line, e.g. `^libGL -> Graphics`. The
+    /// first rule matching the function or library name of a frame wins.
+    /// Native function names are only known if the profile is symbolicated,
+    /// e.g. with --unstable-presymbolicate.
+    #[arg(long, value_name = "FILE")]
+    category_rules: Option<PathBuf>,
+
+    /// Don't add the profile to the recording history, which samply list
+    /// shows.
+    #[arg(long)]
+    no_history: bool,
+
+    /// Run this shell command with the path of the finished profile as its
+    /// last argument, before the profile is served, e.g. to archive it or to
+    /// check it for regressions. The path is also in $SAMPLY_PROFILE.
+    #[arg(long, value_name = "COMMAND")]
+    post_process: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct RunElevatedHelperArgs {
+    #[arg(long)]
+    ipc_directory: PathBuf,
+
+    #[arg(long)]
+    output_path: PathBuf,
+}
+
+pub fn main() {
+    env_logger::init();
+
+    let opt = parse_opt_with_config();
+    match opt.action {
+        Action::Load(load_args) => {
+            let mut libinfo_map = HashMap::new();
+            for profile_filename in &load_args.files {
+                let input_file = match File::open(profile_filename) {
+                    Ok(file) => file,
+                    Err(err) => {
+                        eprintln!("Could not open file {:?}: {}", profile_filename, err);
+                        std::process::exit(1)
+                    }
+                };
+
+                match parse_libinfo_map_from_profile_file(input_file, profile_filename) {
+                    Ok(map) => libinfo_map.extend(map),
+                    Err(err) => {
+                        eprintln!("Could not parse {:?} as JSON: {}", profile_filename, err);
+                        eprintln!(
+                            "If this is a perf.data file, please use `samply import` instead."
+                        );
+                        std::process::exit(1)
+                    }
+                }
+            }
+            match load_args.files.as_slice() {
+                [profile_filename] if load_args.profile_dirs.is_empty() => start_server_main(
+                    profile_filename,
+                    load_args.server_props(),
+                    load_args.symbol_props(),
+                    libinfo_map,
+                ),
+                files => server::start_profile_list_server_main(
+                    Arc::new(Mutex::new(files.to_vec())),
+                    load_args.profile_dirs.clone(),
+                    load_args.server_props(),
+                    load_args.symbol_props(),
+                    libinfo_map,
+                ),
+            }
+        }
+
+        Action::Serve(serve_args) => {
+            run_serve(serve_args);
+        }
+
+        Action::ServeSymbols(serve_symbols_args) => {
+            server::start_symbol_server_main(
+                serve_symbols_args.server_props(),
+                serve_symbols_args.symbol_props(),
+            );
+        }
+
+        Action::List(list_args) => {
+            run_list(list_args);
+        }
+
+        Action::Import(import_args) => {
+            let input_file = match File::open(&import_args.file) {
+                Ok(file) => file,
+                Err(err) => {
+                    eprintln!("Could not open file {:?}: {}", import_args.file, err);
+                    std::process::exit(1)
+                }
+            };
+            let profile_creation_props = import_args.profile_creation_props();
+            let post_processing = profile_creation_props.post_processing.clone();
+            if let Some(profile_filename) = &import_args.add_markers_to {
+                add_lttng_markers_to_profile_file(
+                    &input_file,
+                    profile_filename,
+                    &import_args.output,
+                );
+            } else {
+                convert_file_to_profile(
+                    &import_args.file,
+                    &input_file,
+                    import_args.format.as_deref(),
+                    &import_args.output,
+                    profile_creation_props,
+                    import_args.included_processes(),
+                );
+            }
+            if let Err(err) =
+                profile_tools::post_process_profile_file(&import_args.output, &post_processing)
+            {
+                eprintln!("Couldn't post-process the profile: {err}");
+                std::process::exit(1)
+            }
+            if let Some(server_props) = import_args.server_props() {
+                serve_written_profile(
+                    &import_args.output,
+                    server_props,
+                    import_args.symbol_props(),
+                );
+            }
+        }
+
+        Action::RecordAndroid(record_android_args) => {
+            run_record_android(record_android_args);
+        }
+
+        Action::Upload(upload_args) => {
+            let symbol_props =
+                (!upload_args.no_symbolicate).then(|| upload_args.symbol_args.symbol_props());
+            upload_profile_or_exit(&upload_args.file, symbol_props, upload_args.verbose);
+        }
+
+        Action::Merge(merge_args) => {
+            let profiles = merge_args
+                .files
+                .iter()
+                .map(|file| read_profile_or_exit(file))
+                .collect();
+            let merged = match profile_tools::merge_profiles(profiles, merge_args.alignment()) {
+                Ok(merged) => merged,
+                Err(err) => {
+                    eprintln!("Could not merge profiles: {err}");
+                    std::process::exit(1)
+                }
+            };
+            write_profile_or_exit(&merge_args.output, &merged);
+            if let Some(server_props) = merge_args.server_props() {
+                serve_written_profile(&merge_args.output, server_props, merge_args.symbol_props());
+            }
+        }
+
+        Action::Diff(diff_args) => {
+            let before = read_profile_or_exit(&diff_args.before);
+            let after = read_profile_or_exit(&diff_args.after);
+            let diff = match profile_tools::diff_profiles(before, after) {
+                Ok(diff) => diff,
+                Err(err) => {
+                    eprintln!("Could not compare profiles: {err}");
+                    std::process::exit(1)
+                }
+            };
+            write_profile_or_exit(&diff_args.output, &diff);
+            if let Some(server_props) = diff_args.server_props() {
+                serve_written_profile(&diff_args.output, server_props, diff_args.symbol_props());
+            }
+        }
+
+        Action::Milestones(milestones_args) => {
+            let mut profile = read_profile_or_exit(&milestones_args.input);
+            let milestones = milestones_args.milestones();
+            let reached = profile_tools::find_milestones(&profile, &milestones);
+            if reached.is_empty() {
+                eprintln!("None of the milestones were found in the profile.");
+            }
+            for milestone in &reached {
+                println!(
+                    "{:<16} {:>10.2}ms  ({}, pid {})",
+                    milestone.name,
+                    milestone.time - milestone.process_start,
+                    milestone.process_name,
+                    milestone.pid
+                );
+            }
+            if let Some(output) = &milestones_args.output {
+                if let Err(err) = profile_tools::add_milestone_markers(&mut profile, &reached) {
+                    eprintln!("Could not add milestone markers: {err}");
+                    std::process::exit(1)
+                }
+                write_profile_or_exit(output, &profile);
+            }
+        }
+
+        Action::Export(export_args) => {
+            let mut profile = read_profile_or_exit(&export_args.input);
+            if !export_args.no_symbolicate {
+                if let Err(err) = profile_tools::symbolicate_profile(
+                    &mut profile,
+                    export_args.symbol_args.symbol_props(),
+                    export_args.verbose,
+                ) {
+                    eprintln!("Could not symbolicate the profile: {err}");
+                    std::process::exit(1)
+                }
+            }
+            let registry = ExporterRegistry::new();
+            let exporter = registry.find(&export_args.format).expect("checked by clap");
+            let result = match &export_args.output {
+                Some(output) => File::create(output)
+                    .map_err(profile_tools::Error::from)
+                    .and_then(|file| export_profile(&profile, exporter, file)),
+                None => export_profile(&profile, exporter, std::io::stdout().lock()),
+            };
+            if let Err(err) = result {
+                eprintln!("Could not export the profile: {err}");
+                std::process::exit(1)
+            }
+        }
+
+        Action::Top(top_args) => {
+            let result = if top_args.follow {
+                top::follow_top(&top_args.input, |path| {
+                    // The file doesn't exist before the first snapshot.
+                    let mut profile = profile_tools::read_profile(path).ok()?;
+                    if !top_args.no_symbolicate {
+                        // Show the profile without function names rather than
+                        // leaving the view, if symbolication fails.
+                        let _ = profile_tools::symbolicate_profile(
+                            &mut profile,
+                            top_args.symbol_args.symbol_props(),
+                            false,
+                        );
+                    }
+                    Some(profile)
+                })
+            } else {
+                let mut profile = read_profile_or_exit(&top_args.input);
+                if !top_args.no_symbolicate {
+                    if let Err(err) = profile_tools::symbolicate_profile(
+                        &mut profile,
+                        top_args.symbol_args.symbol_props(),
+                        top_args.verbose,
+                    ) {
+                        eprintln!("Could not symbolicate the profile: {err}");
+                        std::process::exit(1)
+                    }
+                }
+                top::run_top(&profile)
+            };
+            if let Err(err) = result {
+                eprintln!("Could not show the profile: {err}");
+                std::process::exit(1)
+            }
+        }
+
+        Action::Report(report_args) => {
+            let mut profile = read_profile_or_exit(&report_args.input);
+            if !report_args.no_symbolicate {
+                if let Err(err) = profile_tools::symbolicate_profile(
+                    &mut profile,
+                    report_args.symbol_args.symbol_props(),
+                    report_args.verbose,
+                ) {
+                    eprintln!("Could not symbolicate the profile: {err}");
+                    std::process::exit(1)
+                }
+            }
+            let result = match &report_args.output {
+                Some(output) => File::create(output)
+                    .map_err(profile_tools::Error::from)
+                    .and_then(|file| {
+                        profile_tools::write_report(&profile, report_args.top, BufWriter::new(file))
+                    }),
+                None => {
+                    profile_tools::write_report(&profile, report_args.top, std::io::stdout().lock())
+                }
+            };
+            if let Err(err) = result {
+                eprintln!("Could not write the report: {err}");
+                std::process::exit(1)
+            }
+        }
+
+        Action::CompareSymbols(compare_args) => {
+            let [a, b] = [compare_args.backends[0], compare_args.backends[1]];
+            let comparison = match compare_symbols::compare_symbols(
+                &compare_args.binary,
+                [a, b],
+                compare_args.symbol_args.symbol_props(),
+                compare_args.verbose,
+            ) {
+                Ok(comparison) => comparison,
+                Err(err) => {
+                    eprintln!("{err}");
+                    std::process::exit(1)
+                }
+            };
+            let (a, b) = (symbol_backend_name(a), symbol_backend_name(b));
+            println!(
+                "Compared {} addresses in {} between {a} and {b}: {} differ.",
+                comparison.compared_addresses,
+                comparison.debug_name,
+                comparison.differences.len()
+            );
+            for difference in comparison.differences.iter().take(compare_args.limit) {
+                let [result_a, result_b] = &difference.results;
+                println!();
+                println!("0x{:x}", difference.address);
+                println!("  {a}: {}", result_a.as_deref().unwrap_or("(no symbol)"));
+                println!("  {b}: {}", result_b.as_deref().unwrap_or("(no symbol)"));
+            }
+            if !comparison.differences.is_empty() {
+                std::process::exit(1);
+            }
+        }
+
+        Action::DumpUnwind(dump_args) => {
+            let stdout = std::io::stdout().lock();
+            if let Err(err) =
+                dump_unwind::dump_unwind_rules(&dump_args.binary, dump_args.addr, stdout)
+            {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+
+        Action::PublishSymbols(publish_args) => {
+            let store = SymbolStore::new(&publish_args.server, publish_args.header.clone());
+            let kind = if publish_args.original {
+                SymbolFileKind::Original
+            } else {
+                SymbolFileKind::Breakpad
+            };
+            let mut manifest = Vec::new();
+            for file in &publish_args.files {
+                if publish_args.manifest.is_some() {
+                    match warm_symbols::manifest_entry_for_binary(file) {
+                        Ok(entry) => manifest.push(entry),
+                        Err(err) => {
+                            eprintln!("{err}");
+                            std::process::exit(1)
+                        }
+                    }
+                }
+                let symbol_files = match publish_symbols::symbol_files_for_binary(file, kind) {
+                    Ok(symbol_files) => symbol_files,
+                    Err(err) => {
+                        eprintln!("{err}");
+                        std::process::exit(1)
+                    }
+                };
+                for symbol_file in symbol_files {
+                    if publish_args.skip_existing {
+                        match publish_symbols::is_already_stored(&store, &symbol_file.store_path) {
+                            Ok(true) => {
+                                eprintln!(
+                                    "Skipped {}, it's already stored",
+                                    symbol_file.store_path
+                                );
+                                continue;
+                            }
+                            Ok(false) => {}
+                            Err(err) => {
+                                eprintln!("Could not check the symbol store: {err}");
+                                std::process::exit(1)
+                            }
+                        }
+                    }
+                    match publish_symbols::store_symbol_file(&store, symbol_file) {
+                        Ok(location) => eprintln!("Published {location}"),
+                        Err(err) => {
+                            eprintln!("Could not publish the symbols of {file:?}: {err}");
+                            std::process::exit(1)
+                        }
+                    }
+                }
+            }
+            if let Some(manifest_path) = &publish_args.manifest {
+                let result = File::create(manifest_path)
+                    .and_then(|file| warm_symbols::write_manifest(&manifest, BufWriter::new(file)));
+                if let Err(err) = result {
+                    eprintln!("Could not write the manifest {manifest_path:?}: {err}");
+                    std::process::exit(1)
+                }
+            }
+        }
+
+        Action::WarmSymbols(warm_args) => {
+            let read_manifest = |path: &Path| match warm_symbols::read_manifest(path) {
+                Ok(manifest) => manifest,
+                Err(err) => {
+                    eprintln!("Could not read the manifest {path:?}: {err}");
+                    std::process::exit(1)
+                }
+            };
+            let new = read_manifest(&warm_args.manifest);
+            let modules = match &warm_args.since {
+                Some(old_manifest) => {
+                    warm_symbols::changed_modules(&read_manifest(old_manifest), &new)
+                }
+                None => new,
+            };
+            eprintln!("Fetching the symbols of {} modules...", modules.len());
+            let failures = warm_symbols::warm_symbols(
+                &modules,
+                warm_args.symbol_args.symbol_props(),
+                warm_args.verbose,
+            );
+            for (module, err) in &failures {
+                eprintln!(
+                    "Could not fetch the symbols of {} {}: {err}",
+                    module.debug_name,
+                    module.debug_id.breakpad()
+                );
+            }
+            eprintln!(
+                "Fetched the symbols of {} of {} modules.",
+                modules.len() - failures.len(),
+                modules.len()
+            );
+            if !failures.is_empty() {
+                std::process::exit(1)
+            }
+        }
+
+        Action::Trim(trim_args) => {
+            let profile = read_profile_or_exit(&trim_args.input);
+            let trimmed = match profile_tools::trim_profile(profile, trim_args.from, trim_args.to) {
+                Ok(trimmed) => trimmed,
+                Err(err) => {
+                    eprintln!("Could not trim profile: {err}");
+                    std::process::exit(1)
+                }
+            };
+            write_profile_or_exit(&trim_args.output, &trimmed);
+            if let Some(server_props) = trim_args.server_props() {
+                serve_written_profile(&trim_args.output, server_props, trim_args.symbol_props());
+            }
+        }
+
+        #[cfg(any(
+            target_os = "android",
+            target_os = "macos",
+            target_os = "linux",
+            target_os = "windows"
+        ))]
+        Action::Record(record_args) => {
+            let recording_props = record_args.recording_props();
+            let recording_mode = record_args.recording_mode();
+            let profile_creation_props = record_args.profile_creation_props();
+            let symbol_props = record_args.symbol_props();
+            let server_props = record_args.server_props();
+
+            let exit_status = match profiler::start_recording(
+                recording_mode,
+                recording_props,
+                profile_creation_props,
+                symbol_props,
+                server_props,
+            ) {
+                Ok(exit_status) => exit_status,
+                Err(err) => {
+                    eprintln!("Encountered an error during profiling: {err:?}");
+                    std::process::exit(1);
+                }
+            };
+            if record_args.upload {
+                upload_profile_or_exit(
+                    &record_args.output,
+                    Some(record_args.symbol_props()),
+                    record_args.server_args.verbose,
+                );
+            }
+            if record_args.ci_args.ci {
+                let summary = summarize_recorded_profile(&record_args);
+                println!(
+                    "{}",
+                    serde_json::to_string(&summary).expect("Couldn't serialize the summary")
+                );
+                if !summary.violations.is_empty() {
+                    for violation in &summary.violations {
+                        eprintln!("Threshold exceeded: {violation}");
+                    }
+                    std::process::exit(CI_THRESHOLD_EXCEEDED_EXIT_CODE);
+                }
+            }
+            std::process::exit(exit_status.code().unwrap_or(0));
+        }
+
+        #[cfg(any(
+            target_os = "android",
+            target_os = "macos",
+            target_os = "linux",
+            target_os = "windows"
+        ))]
+        Action::Watch(watch_args) => {
+            run_watch(watch_args);
+        }
+
+        #[cfg(target_os = "windows")]
+        Action::RunElevatedHelper(RunElevatedHelperArgs {
+            ipc_directory,
+            output_path,
+        }) => {
+            crate::windows::run_elevated_helper(&ipc_directory, output_path);
+        }
+
+        Action::Cache(cache_args) => {
+            let dirs = symbol_cache::symbol_cache_dirs(&cache_args.symbol_args.symbol_props());
+            let eviction = match cache_args.trim_to {
+                _ if cache_args.clear => Some(symbol_cache::clear(&dirs)),
+                Some(max_size) => Some(symbol_cache::trim(&dirs, max_size as u64)),
+                None => None,
+            };
+            if let Some(eviction) = eviction {
+                eprintln!(
+                    "Removed {} files ({}).",
+                    eviction.removed_files,
+                    symbol_cache::format_bytes(eviction.removed_bytes)
+                );
+            }
+            if let Err(err) = symbol_cache::print_usage(&dirs, std::io::stdout().lock()) {
+                eprintln!("Could not print the cache usage: {err}");
+                std::process::exit(1)
+            }
+        }
+
+        Action::Doctor => {
+            let checks = doctor::run_checks();
+            if let Err(err) = doctor::print_report(&checks, std::io::stdout().lock()) {
+                eprintln!("Could not print the report: {err}");
+                std::process::exit(1)
+            }
+            if checks
+                .iter()
+                .any(|check| check.status == doctor::CheckStatus::Error)
+            {
+                std::process::exit(1)
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        Action::Setup => {
+            crate::mac::codesign_setup::codesign_setup();
+        }
+    }
+}
+
+impl WatchArgs {
+    /// The --watch paths, or the profiled command if it's a path to a file.
+    #[allow(unused)]
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        if !self.watch_paths.is_empty() {
+            return self.watch_paths.clone();
+        }
+        let command = self.record_args.command.first().map(PathBuf::from);
+        command
+            .filter(|command| command.components().count() > 1 && command.is_file())
+            .into_iter()
+            .collect()
+    }
+}
+
+impl LoadArgs {
+    fn server_props(&self) -> ServerProps {
+        self.server_args.server_props()
+    }
+
+    fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+}
+
+impl RecordAndroidArgs {
+    fn ndk(&self) -> Option<PathBuf> {
+        self.ndk
+            .clone()
+            .or_else(|| std::env::var_os("ANDROID_NDK_HOME").map(PathBuf::from))
+    }
+
+    fn symbol_props(&self) -> SymbolProps {
+        let mut symbol_props = self.symbol_args.symbol_props();
+        if let Some(ndk) = self.ndk() {
+            symbol_props
+                .symbol_dir
+                .extend(android::ndk_symbol_dirs(&ndk));
+        }
+        symbol_props
+    }
+
+    fn profile_creation_props(&self) -> ProfileCreationProps {
+        let profile_name = match &self.profile_creation_args.profile_name {
+            Some(profile_name) => profile_name.clone(),
+            None => self.package.clone(),
+        };
+        ProfileCreationProps {
+            profile_name,
+            main_thread_only: self.profile_creation_args.main_thread_only,
+            reuse_threads: self.profile_creation_args.reuse_threads,
+            recursion_folding: self.profile_creation_args.recursion_folding(),
+            unlink_aux_files: self.profile_creation_args.unlink_aux_files,
+            create_per_cpu_threads: self.profile_creation_args.per_cpu_threads,
+            override_arch: None,
+            unstable_presymbolicate: self.profile_creation_args.unstable_presymbolicate,
+            coreclr: CoreClrProfileProps::default(),
+            unknown_event_markers: false,
+            post_processing: self.profile_creation_args.post_processing_options(),
+        }
+    }
+}
+
+impl ListArgs {
+    fn server_props(&self) -> ServerProps {
+        self.server_args.server_props()
+    }
+
+    fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+}
+
+impl ServeArgs {
+    fn server_props(&self) -> ServerProps {
+        self.server_args.server_props()
+    }
+
+    fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+}
+
+impl ServeSymbolsArgs {
+    fn server_props(&self) -> ServerProps {
+        ServerProps {
+            path_prefix: self.path_prefix.clone(),
+            ..self.server_args.server_props()
+        }
+    }
+
+    fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+}
+
+impl ImportArgs {
+    fn server_props(&self) -> Option<ServerProps> {
+        if self.save_only {
+            None
+        } else {
+            Some(self.server_args.server_props())
+        }
+    }
+
+    fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+
+    fn profile_creation_props(&self) -> ProfileCreationProps {
+        let profile_name = if let Some(profile_name) = &self.profile_creation_args.profile_name {
+            profile_name.clone()
+        } else {
+            let name = self.file.file_name().unwrap_or(self.file.as_os_str());
+            name.to_string_lossy().into()
+        };
+        ProfileCreationProps {
+            profile_name,
+            main_thread_only: self.profile_creation_args.main_thread_only,
+            reuse_threads: self.profile_creation_args.reuse_threads,
+            recursion_folding: self.profile_creation_args.recursion_folding(),
+            unlink_aux_files: self.profile_creation_args.unlink_aux_files,
+            create_per_cpu_threads: self.profile_creation_args.per_cpu_threads,
+            override_arch: self.override_arch.clone(),
+            unstable_presymbolicate: self.profile_creation_args.unstable_presymbolicate,
+            coreclr: to_coreclr_profile_props(&self.coreclr),
+            #[cfg(target_os = "windows")]
+            unknown_event_markers: self.profile_creation_args.unknown_event_markers,
+            #[cfg(not(target_os = "windows"))]
+            unknown_event_markers: false,
+            post_processing: self.profile_creation_args.post_processing_options(),
+        }
+    }
+
+    fn included_processes(&self) -> Option<IncludedProcesses> {
+        match (&self.name, &self.pid) {
+            (None, None) => None, // No filtering, include all processes
+            (names, pids) => Some(IncludedProcesses {
+                name_substrings: names.clone().unwrap_or_default(),
+                pids: pids.clone().unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+impl MergeArgs {
+    fn server_props(&self) -> Option<ServerProps> {
+        if self.save_only {
+            None
+        } else {
+            Some(self.server_args.server_props())
+        }
+    }
+
+    fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+
+    fn alignment(&self) -> TimelineAlignment {
+        match self.timeline {
+            TimelineArg::Absolute => TimelineAlignment::Absolute,
+            TimelineArg::Aligned => TimelineAlignment::Aligned,
+            TimelineArg::Concatenated => TimelineAlignment::Concatenated,
+        }
+    }
+}
+
+impl DiffArgs {
+    fn server_props(&self) -> Option<ServerProps> {
+        if self.save_only {
+            None
+        } else {
+            Some(self.server_args.server_props())
+        }
+    }
+
+    fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+}
+
+impl MilestonesArgs {
+    fn milestones(&self) -> Vec<Milestone> {
+        let presets: Vec<&str> = if self.preset.is_empty() && self.milestone.is_empty() {
+            vec!["main", "first-paint"]
+        } else {
+            self.preset.iter().map(String::as_str).collect()
+        };
+        let mut milestones = Vec::new();
+        for preset in presets {
+            match profile_tools::preset_milestones(preset) {
+                Some(preset_milestones) => milestones.extend(preset_milestones),
+                None => {
+                    eprintln!("Unknown milestone preset {preset:?}");
+                    std::process::exit(1)
+                }
+            }
+        }
+        milestones.extend(self.milestone.iter().cloned());
+        milestones
+    }
+}
+
+impl TrimArgs {
+    fn server_props(&self) -> Option<ServerProps> {
+        if self.save_only {
+            None
+        } else {
+            Some(self.server_args.server_props())
+        }
+    }
+
+    fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+}
+
+impl RecordArgs {
+    #[allow(unused)]
+    fn server_props(&self) -> Option<ServerProps> {
+        if self.save_only || self.upload || self.ci_args.ci {
+            None
+        } else {
+            Some(self.server_args.server_props())
+        }
+    }
+
+    fn symbol_props(&self) -> SymbolProps {
+        self.symbol_args.symbol_props()
+    }
+
+    #[allow(unused)]
+    pub fn recording_props(&self) -> RecordingProps {
+        let time_limit = self.duration.map(Duration::from_secs_f64);
+        if self.rate <= 0.0 {
+            eprintln!(
+                "Error: sampling rate must be greater than zero, got {}",
+                self.rate
+            );
+            std::process::exit(1);
+        }
+        let interval = Duration::from_secs_f64(1.0 / self.rate);
+        if let Some(linger) = self
+            .linger
+            .filter(|linger| linger.is_nan() || *linger < 0.0)
+        {
+            eprintln!("Error: --linger must not be negative, got {linger}");
+            std::process::exit(1);
+        }
+        cfg_if::cfg_if! {
+            if #[cfg(target_os = "windows")] {
+                let vm_hack = self.vm_hack;
+            } else {
+                let vm_hack = false;
+            }
+        }
+        if self.input_markers && !cfg!(any(target_os = "android", target_os = "linux")) {
+            eprintln!("Warning: --input-markers is currently only supported on Linux.");
+        }
+        if self.focus_markers && !cfg!(any(target_os = "android", target_os = "linux")) {
+            eprintln!("Error: --focus-markers is currently only supported in Linux X11 sessions.");
+            std::process::exit(1);
+        }
+        if self.aux_event.is_some() && !cfg!(any(target_os = "android", target_os = "linux")) {
+            eprintln!("Warning: --aux-event is currently only supported on Linux.");
+        }
+        if self.linger.is_some() && cfg!(target_os = "windows") {
+            eprintln!("Warning: --linger is currently not supported on Windows.");
+        }
+        if self.stop_on_marker.is_some() && cfg!(target_os = "windows") {
+            eprintln!("Warning: --stop-on-marker is currently not supported on Windows.");
+        }
+        if !self.usdt_probes.is_empty() && !cfg!(any(target_os = "linux", target_os = "android")) {
+            eprintln!("Warning: --usdt is currently only supported on Linux.");
+        }
+        if !self.etw_providers.is_empty() && !cfg!(target_os = "windows") {
+            eprintln!("Warning: --etw-provider is currently only supported on Windows.");
+        }
+        if !self.marker_from_output.is_empty()
+            && !cfg!(any(target_os = "linux", target_os = "android"))
+        {
+            eprintln!("Warning: --marker-from-output is currently only supported on Linux.");
+        }
+        if self.log_output && !cfg!(any(target_os = "linux", target_os = "android")) {
+            eprintln!("Warning: --log-output is currently only supported on Linux.");
+        }
+        if self.marker_fifo.is_some() && !cfg!(any(target_os = "linux", target_os = "android")) {
+            eprintln!("Warning: --marker-fifo is currently only supported on Linux.");
+        }
+        if self.python && !cfg!(any(target_os = "linux", target_os = "android")) {
+            eprintln!("Warning: --python is currently only supported on Linux.");
+        }
+        if self.measure_overhead && !cfg!(any(target_os = "linux", target_os = "android")) {
+            eprintln!("Warning: --measure-overhead is currently only supported on Linux.");
+        }
+        if self.live && !cfg!(any(target_os = "linux", target_os = "android")) {
+            eprintln!("Warning: --live is currently only supported on Linux.");
+        }
+        let aux_event = self.aux_event.map(|aux_event| match aux_event {
+            AuxEventArg::Instructions => AuxEvent::Instructions,
+            AuxEventArg::CacheReferences => AuxEvent::CacheReferences,
+            AuxEventArg::CacheMisses => AuxEvent::CacheMisses,
+            AuxEventArg::BranchMisses => AuxEvent::BranchMisses,
+        });
+
+        RecordingProps {
+            output_file: self.output.clone(),
+            time_limit,
+            interval,
+            vm_hack,
+            gfx: self.gfx,
+            browsers: self.browsers,
+            input_markers: self.input_markers,
+            focus_markers: self.focus_markers,
+            aux_event,
+            linger: self.linger.map(Duration::from_secs_f64),
+            stop_on_marker: self.stop_on_marker.clone(),
+            usdt_probes: self.usdt_probes.clone(),
+            etw_providers: self.etw_providers.clone(),
+            output_marker_patterns: OutputMarkerPatterns::new(self.marker_from_output.clone()),
+            log_output: self.log_output,
+            marker_fifo: self.marker_fifo.clone(),
+            python: self.python,
+            measure_overhead: self.measure_overhead,
+            live_interval: self
+                .live
+                .then(|| Duration::from_secs_f64(self.live_interval)),
+        }
+    }
+
+    pub fn recording_mode(&self) -> RecordingMode {
+        let (command, iteration_count) = match (self.all, &self.pid) {
+            (true, _) => return RecordingMode::All,
+            (false, Some(pid)) => return RecordingMode::Pid(*pid),
+            (false, None) => (&self.command, self.iteration_count),
+        };
+
+        assert!(
+            !command.is_empty(),
+            "CLI parsing should have ensured that we have at least one command name"
+        );
+        let mut env_vars = Vec::new();
+        let mut i = 0;
+        while let Some((var_name, var_val)) = command.get(i).and_then(|s| split_at_first_equals(s))
+        {
+            env_vars.push((var_name.to_owned(), var_val.to_owned()));
+            i += 1;
+        }
+        if i == command.len() {
+            eprintln!("Error: No command name found. Every item looks like an environment variable (contains '='): {command:?}");
+            std::process::exit(1);
+        }
+        let command_name = command[i].clone();
+        let args = command[(i + 1)..].to_owned();
+        let launch_props = ProcessLaunchProps {
+            env_vars,
+            command_name,
+            args,
+            iteration_count,
+        };
+
+        RecordingMode::Launch(launch_props)
+    }
+
+    pub fn profile_creation_props(&self) -> ProfileCreationProps {
+        let profile_name = self.profile_creation_args.profile_name.clone();
+        let profile_name = profile_name.unwrap_or_else(|| match self.recording_mode() {
+            RecordingMode::All => "All processes".to_string(),
+            RecordingMode::Pid(pid) => format!("PID {pid}"),
+            RecordingMode::Launch(launch_props) => {
+                launch_props.command_name.to_string_lossy().to_string()
+            }
+        });
+        ProfileCreationProps {
+            profile_name,
+            main_thread_only: self.profile_creation_args.main_thread_only,
+            reuse_threads: self.profile_creation_args.reuse_threads || self.merge_iterations,
+            recursion_folding: self.profile_creation_args.recursion_folding(),
+            unlink_aux_files: self.profile_creation_args.unlink_aux_files,
+            create_per_cpu_threads: self.profile_creation_args.per_cpu_threads,
+            override_arch: None,
+            unstable_presymbolicate: self.profile_creation_args.unstable_presymbolicate,
+            coreclr: to_coreclr_profile_props(&self.coreclr),
+            #[cfg(target_os = "windows")]
+            unknown_event_markers: self.profile_creation_args.unknown_event_markers,
+            #[cfg(not(target_os = "windows"))]
+            unknown_event_markers: false,
+            post_processing: self.profile_creation_args.post_processing_options(),
+        }
+    }
+}
+
+impl ProfileCreationArgs {
+    fn recursion_folding(&self) -> RecursionFolding {
+        match self.fold_recursion {
+            Some(max_cycle_length) => RecursionFolding::Cycles {
+                max_cycle_length,
+                max_depth: self.fold_recursion_depth,
+            },
+            None if self.fold_recursive_prefix => RecursionFolding::Prefix,
+            None => RecursionFolding::None,
+        }
+    }
+
+    fn post_processing_options(&self) -> PostProcessingOptions {
+        PostProcessingOptions {
+            scrub: self.scrub_options(),
+            max_output_size: self.max_output_size,
+            swap_sample_weights: self.swap_weights,
+            category_rules: self.category_rules(),
+            fold_frames: self.fold_frames.clone(),
+            history_entry: (!self.no_history).then(HistoryEntry::start),
+            command: self.post_process.clone(),
+        }
+    }
+
+    fn category_rules(&self) -> Option<CategoryRules> {
+        let path = self.category_rules.as_deref()?;
+        match CategoryRules::read(path) {
+            Ok(rules) => Some(rules),
+            Err(err) => {
+                eprintln!("Could not read the category rules from {path:?}: {err}");
+                std::process::exit(1)
+            }
+        }
+    }
+
+    fn scrub_options(&self) -> Option<ScrubOptions> {
+        if !self.scrub && self.scrub_function.is_empty() {
+            return None;
+        }
+        Some(ScrubOptions {
+            function_denylist: self.scrub_function.clone(),
+        })
+    }
+}
+
+impl ServerArgs {
+    pub fn server_props(&self) -> ServerProps {
+        let open_in_browser = !self.no_open;
+        let port_selection = match PortSelection::try_from_str(&self.port) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!(
+                    "Could not parse port as <u16> or <u16>+, got port {}, error: {}",
+                    self.port, e
+                );
+                std::process::exit(1)
+            }
+        };
+
+        #[cfg(feature = "grpc")]
+        let grpc_port_selection =
+            self.grpc_port
+                .as_ref()
+                .map(|grpc_port| match PortSelection::try_from_str(grpc_port) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        eprintln!(
+                            "Could not parse gRPC port as <u16> or <u16>+, got port {}, error: {}",
+                            grpc_port, e
+                        );
+                        std::process::exit(1)
+                    }
+                });
+
+        let tls = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Some(TlsSource::Files {
+                cert: cert.clone(),
+                key: key.clone(),
+            }),
+            _ if self.tls => Some(TlsSource::SelfSigned),
+            _ => None,
+        };
+        let auth_token = self.require_auth.then(|| {
+            std::env::var("SAMPLY_AUTH_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty())
+                .unwrap_or_else(server::generate_token)
+        });
+
+        // parse address from string
+        let address = match IpAddr::from_str(&self.address) {
+            Ok(addr) => addr,
+            Err(e) => {
+                eprintln!(
+                    "Could not parse address as IpAddr, got address {:?}, error: {}",
+                    self.address, e
+                );
+                std::process::exit(1)
+            }
+        };
+
+        ServerProps {
+            address,
+            port_selection,
+            #[cfg(feature = "grpc")]
+            grpc_port_selection,
+            verbose: self.verbose,
+            open_in_browser,
+            unix_socket: self.unix_socket.clone(),
+            public_url: self.public_url.clone(),
+            tls,
+            auth_token,
+            path_prefix: None,
+            allowed_origins: self.allow_origin.clone(),
+            request_limits: RequestLimits {
+                max_concurrent_requests: self.max_concurrent_requests,
+                max_requests_per_minute: self.max_requests_per_minute,
+            },
+        }
+    }
+}
+
+impl SymbolArgs {
+    pub fn symbol_props(&self) -> SymbolProps {
+        SymbolProps {
+            symbol_dir: self.symbol_dir.clone(),
+            windows_symbol_server: self.windows_symbol_server.clone(),
+            windows_symbol_cache: self.windows_symbol_cache.clone(),
+            breakpad_symbol_server: self.breakpad_symbol_server.clone(),
+            breakpad_symbol_dir: self.breakpad_symbol_dir.clone(),
+            breakpad_symbol_cache: self.breakpad_symbol_cache.clone(),
+            simpleperf_binary_cache: self.simpleperf_binary_cache.clone(),
+            forced_backends: self.symbol_backend.clone(),
+            symbol_debug_report: self.symbol_debug.clone(),
+            symbol_cache_max_size: self.symbol_cache_size.map(|size| size as u64),
+            max_downloads_per_server: self.max_downloads_per_server,
+            pdb_function_arguments: !self.no_pdb_function_args,
+        }
+    }
+}
+
+fn to_coreclr_profile_props(coreclr_args: &[CoreClrArgs]) -> CoreClrProfileProps {
+    // on Windows, the ..Default::default() has no effect, and clippy doesn't like it
+    #[allow(clippy::needless_update)]
+    CoreClrProfileProps {
+        enabled: coreclr_args.contains(&CoreClrArgs::Enabled),
+        #[cfg(target_os = "windows")]
+        gc_markers: coreclr_args.contains(&CoreClrArgs::GcMarkers),
+        #[cfg(target_os = "windows")]
+        gc_suspensions: coreclr_args.contains(&CoreClrArgs::GcSuspendedThreads),
+        #[cfg(target_os = "windows")]
+        gc_detailed_allocs: coreclr_args.contains(&CoreClrArgs::GcDetailedAllocs),
+        #[cfg(target_os = "windows")]
+        event_stacks: coreclr_args.contains(&CoreClrArgs::EventStacks),
+        ..Default::default()
+    }
+}
+
+/// Parses a time like "5s", "1.5s" or "300ms" into milliseconds. Plain
+/// numbers are seconds.
+fn parse_time_offset(s: &str) -> Result<f64, String> {
+    let (number, factor) = if let Some(ms) = s.strip_suffix("ms") {
+        (ms, 1.0)
+    } else if let Some(secs) = s.strip_suffix('s') {
+        (secs, 1000.0)
+    } else {
+        (s, 1000.0)
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n >= 0.0 => Ok(n * factor),
+        _ => Err(format!(
+            "Invalid time {s:?}, expected something like \"5s\" or \"300ms\""
+        )),
+    }
+}
+
+/// Parses a size like "200MB", "1.5GiB" or "4096" (bytes) into bytes.
+fn parse_byte_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let factor: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => {
+            return Err(format!(
+                "Unknown size unit in {s:?}, expected e.g. KB, MB or GB"
+            ))
+        }
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n > 0.0 => Ok((n * factor as f64) as usize),
+        _ => Err(format!(
+            "Invalid size {s:?}, expected something like \"200MB\""
+        )),
+    }
+}
+
+/// Strips the slashes around a path prefix. The prefix can't be empty,
+/// because the paths of the API would lose their leading slash.
+fn parse_path_prefix(s: &str) -> Result<String, String> {
+    match s.trim_matches('/') {
+        "" => Err("The path prefix needs to contain something other than slashes".to_string()),
+        path_prefix => Ok(path_prefix.to_string()),
+    }
+}
+
+/// Parses an HTTP header given as "Name: value".
+fn parse_symbol_backend_arg(s: &str) -> Result<SymbolBackend, String> {
+    symbol_props::parse_symbol_backend(s)
+        .ok_or_else(|| format!("unknown symbol backend {s:?}, expected dwarf, breakpad or pdb"))
+}
+
+fn parse_forced_backend(s: &str) -> Result<(String, SymbolBackend), String> {
+    let (debug_name, backend) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected DEBUGNAME=BACKEND, got {s:?}"))?;
+    Ok((debug_name.to_owned(), parse_symbol_backend_arg(backend)?))
+}
+
+/// The exit code of `samply record --ci` if the profile exceeds a threshold.
+const CI_THRESHOLD_EXCEEDED_EXIT_CODE: i32 = 3;
+
+/// Symbolicates the profile which `samply record --ci` has written, and
+/// summarizes it.
+fn summarize_recorded_profile(record_args: &RecordArgs) -> profile_tools::ProfileSummary {
+    let mut profile = read_profile_or_exit(&record_args.output);
+    if let Err(err) = profile_tools::symbolicate_profile(
+        &mut profile,
+        record_args.symbol_props(),
+        record_args.server_args.verbose,
+    ) {
+        eprintln!("Could not symbolicate the profile: {err}");
+        std::process::exit(1)
+    }
+    let ci_args = &record_args.ci_args;
+    let thresholds = profile_tools::SummaryThresholds {
+        min_samples: ci_args.min_samples,
+        max_lost_events: ci_args.max_lost_events,
+        max_unsymbolicated_ratio: ci_args.max_unsymbolicated_ratio,
+        max_function_share: ci_args.max_function_share.clone(),
+    };
+    profile_tools::summarize_profile(&profile, 10, &thresholds)
+}
+
+fn parse_function_share(s: &str) -> Result<(String, f64), String> {
+    let (function, percent) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected FUNCTION=PERCENT, got {s:?}"))?;
+    let percent = percent
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|err| format!("invalid percentage {percent:?}: {err}"))?;
+    Ok((function.to_owned(), percent))
+}
+
+fn parse_address(s: &str) -> Result<u32, String> {
+    let result = match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    result.map_err(|err| format!("invalid address {s:?}: {err}"))
+}
+
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_owned(), value.trim().to_owned()))
+        }
+        _ => Err(format!("Invalid header {s:?}, expected \"Name: value\"")),
+    }
+}
+
+fn export_format_parser() -> clap::builder::PossibleValuesParser {
+    clap::builder::PossibleValuesParser::new(ExporterRegistry::new().possible_values())
+}
+
+fn export_profile(
+    profile: &serde_json::Value,
+    exporter: &dyn Exporter,
+    writer: impl std::io::Write,
+) -> Result<(), profile_tools::Error> {
+    let mut writer = BufWriter::new(writer);
+    exporter.export(profile, &mut writer)?;
+    std::io::Write::flush(&mut writer)?;
+    Ok(())
+}
+
+fn run_list(list_args: ListArgs) {
+    let Some(history) = History::open() else {
+        eprintln!("Could not find the data directory for the recording history.");
+        std::process::exit(1);
+    };
+    let mut entries = match history.entries() {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("Could not read the recording history: {err}");
+            std::process::exit(1);
+        }
+    };
+    if let Some(last) = list_args.last {
+        entries.drain(..entries.len().saturating_sub(last));
+    }
+
+    if list_args.serve {
+        let profiles = entries.into_iter().map(|entry| entry.path).collect();
+        server::start_profile_list_server_main(
+            Arc::new(Mutex::new(profiles)),
+            Vec::new(),
+            list_args.server_props(),
+            list_args.symbol_props(),
+            HashMap::new(),
+        );
+        return;
+    }
+    if entries.is_empty() {
+        println!("No profiles have been recorded yet.");
+    }
+    for entry in entries {
+        let commit = entry.git_commit.as_deref().unwrap_or("-");
+        println!(
+            "{} UTC  {:>7.1}s  {commit:<12}  {}",
+            history::format_date(entry.date),
+            entry.duration,
+            entry.command
+        );
+        println!("    {}", entry.path.display());
+    }
+}
+
+fn run_serve(serve_args: ServeArgs) -> ! {
+    let mut folder = match drop_folder::DropFolder::new(&serve_args.watch_dir) {
+        Ok(folder) => folder,
+        Err(err) => {
+            eprintln!(
+                "Could not create the directory {:?}: {err}",
+                serve_args.watch_dir
+            );
+            std::process::exit(1);
+        }
+    };
+    let profiles = folder.profiles();
+    let addable_dirs = vec![serve_args.watch_dir.clone()];
+    let server_props = serve_args.server_props();
+    let symbol_props = serve_args.symbol_props();
+    std::thread::spawn(move || {
+        server::start_profile_list_server_main(
+            profiles,
+            addable_dirs,
+            server_props,
+            symbol_props,
+            HashMap::new(),
+        )
+    });
+
+    loop {
+        for (path, kind) in folder.poll() {
+            match kind {
+                drop_folder::DroppedFileKind::Profile => folder.add(path),
+                drop_folder::DroppedFileKind::Recording => {
+                    let output = folder.converted_path(&path);
+                    if folder.is_converted(&path) || import_dropped_recording(&path, &output) {
+                        folder.add(output);
+                    }
+                }
+            }
+        }
+        std::thread::sleep(drop_folder::POLL_INTERVAL);
+    }
+}
+
+/// Imports a recording with `samply import` in a child process, so that a
+/// recording which can't be imported doesn't stop the server.
+fn import_dropped_recording(path: &Path, output: &Path) -> bool {
+    eprintln!("Importing {path:?}...");
+    let status = std::env::current_exe().and_then(|samply| {
+        std::process::Command::new(samply)
+            .arg("import")
+            .arg(path)
+            .arg("--save-only")
+            .arg("--no-history")
+            .arg("-o")
+            .arg(output)
+            .status()
+    });
+    match status {
+        Ok(status) if status.success() => {
+            eprintln!("Saved the profile to {output:?}.");
+            true
+        }
+        Ok(status) => {
+            eprintln!("Could not import {path:?}, samply import exited with {status}.");
+            false
+        }
+        Err(err) => {
+            eprintln!("Could not run samply import for {path:?}: {err}");
+            false
+        }
+    }
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "macos",
+    target_os = "linux",
+    target_os = "windows"
+))]
+fn run_record_android(args: RecordAndroidArgs) {
+    if args.rate <= 0.0 {
+        eprintln!(
+            "Error: sampling rate must be greater than zero, got {}",
+            args.rate
+        );
+        std::process::exit(1);
+    }
+    let adb = android::Adb::new(args.serial.clone());
+    let recording_props = android::AndroidRecordingProps {
+        package: args.package.clone(),
+        rate: args.rate,
+        duration: args.duration,
+        ndk: args.ndk(),
+    };
+    if let Err(err) = android::record(&adb, &recording_props, &args.perf_data) {
+        eprintln!("Error: Could not record {}: {err}", args.package);
+        std::process::exit(1);
+    }
+
+    let input_file = match File::open(&args.perf_data) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("Could not open file {:?}: {}", args.perf_data, err);
+            std::process::exit(1)
+        }
+    };
+    let profile_creation_props = args.profile_creation_props();
+    let post_processing = profile_creation_props.post_processing.clone();
+    // The paths in the recording are paths on the device. Binaries which
+    // aren't on this machine are looked up by file name in this directory.
+    let binary_dir = args.symbol_args.symbol_dir.first().cloned();
+    let input = ImportInput {
+        path: &args.perf_data,
+        file: &input_file,
+        binary_dir: binary_dir.as_deref(),
+        included_processes: None,
+    };
+    let registry = ImporterRegistry::new();
+    let importer = registry.find("perf").expect("perf is a built-in format");
+    import_profile(importer, input, &args.output, profile_creation_props);
+    if let Err(err) = profile_tools::post_process_profile_file(&args.output, &post_processing) {
+        eprintln!("Couldn't post-process the profile: {err}");
+        std::process::exit(1)
+    }
+    if !args.save_only {
+        serve_written_profile(
+            &args.output,
+            args.server_args.server_props(),
+            args.symbol_props(),
+        );
+    }
+}
+
+fn run_watch(watch_args: WatchArgs) -> ! {
+    let record_args = &watch_args.record_args;
+    if record_args.command.is_empty() {
+        eprintln!("Error: samply watch needs a command to run, it can't attach to processes.");
+        std::process::exit(1);
+    }
+    if record_args.upload {
+        eprintln!("Error: samply watch doesn't support --upload.");
+        std::process::exit(1);
+    }
+
+    let mut rotation = match crate::watch::ProfileRotation::new(
+        &watch_args.output_dir,
+        &record_args.output,
+        watch_args.keep as usize,
+    ) {
+        Ok(rotation) => rotation,
+        Err(err) => {
+            eprintln!(
+                "Could not create the directory {:?}: {err}",
+                watch_args.output_dir
+            );
+            std::process::exit(1);
+        }
+    };
+    if let Some(server_props) = record_args.server_props() {
+        let profiles = rotation.profiles();
+        let addable_dirs = vec![watch_args.output_dir.clone()];
+        let symbol_props = record_args.symbol_props();
+        std::thread::spawn(move || {
+            server::start_profile_list_server_main(
+                profiles,
+                addable_dirs,
+                server_props,
+                symbol_props,
+                HashMap::new(),
+            )
+        });
+    }
+    let watched_paths = crate::watch::WatchedPaths::new(watch_args.watched_paths());
+
+    loop {
+        let output_file = rotation.next_path();
+        let recording_props = RecordingProps {
+            output_file: output_file.clone(),
+            ..record_args.recording_props()
+        };
+        match profiler::start_recording(
+            record_args.recording_mode(),
+            recording_props,
+            record_args.profile_creation_props(),
+            record_args.symbol_props(),
+            None,
+        ) {
+            Ok(exit_status) if !exit_status.success() => {
+                eprintln!("The command exited with {exit_status}.");
+            }
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("Encountered an error during profiling: {err:?}");
+            }
+        }
+        if output_file.exists() {
+            eprintln!("Saved the profile to {output_file:?}.");
+            rotation.add(output_file);
+        }
+
+        // Changes which happen during the recording, like a `cargo run`
+        // rebuilding the binary, don't trigger another recording.
+        let last_modification = watched_paths.latest_modification();
+        if watched_paths.is_empty() {
+            eprintln!("Press Enter to record again, or Ctrl+C to stop.");
+            let mut line = String::new();
+            match std::io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => std::process::exit(0),
+                Ok(_) => {}
+            }
+        } else {
+            eprintln!(
+                "Waiting for changes to {:?}. Press Ctrl+C to stop.",
+                watched_paths.paths()
+            );
+            watched_paths.wait_for_change(last_modification);
+        }
+    }
+}
+
+/// Uploads the profile, after looking up its function names if `symbol_props`
+/// is given, because the Firefox Profiler can't symbolicate shared profiles.
+fn upload_profile_or_exit(path: &Path, symbol_props: Option<SymbolProps>, verbose: bool) {
+    let mut profile = read_profile_or_exit(path);
+    if let Some(symbol_props) = symbol_props {
+        if let Err(err) = profile_tools::symbolicate_profile(&mut profile, symbol_props, verbose) {
+            eprintln!("Could not symbolicate the profile: {err}");
+            std::process::exit(1)
+        }
+    }
+    eprintln!("Uploading {path:?} to profiler.firefox.com...");
+    match profile_tools::upload_profile(&profile) {
+        Ok(url) => {
+            eprintln!("Anyone with this link can view the profile:");
+            println!("{url}");
+        }
+        Err(err) => {
+            eprintln!("Could not upload the profile: {err}");
+            std::process::exit(1)
+        }
+    }
+}
+
+fn read_profile_or_exit(path: &Path) -> serde_json::Value {
+    match profile_tools::read_profile(path) {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("Could not read profile {:?}: {}", path, err);
+            std::process::exit(1)
+        }
+    }
+}
+
+fn write_profile_or_exit(path: &Path, profile: &serde_json::Value) {
+    if let Err(err) = profile_tools::write_profile(path, profile) {
+        eprintln!("Couldn't write output file {:?}: {}", path, err);
+        std::process::exit(1)
+    }
+}
+
+/// Starts the server for a profile file which samply has just written.
+fn serve_written_profile(
+    profile_filename: &Path,
+    server_props: ServerProps,
+    symbol_props: SymbolProps,
+) {
+    let libinfo_map = parse_libinfo_map_from_profile_file(
+        File::open(profile_filename).expect("Couldn't open file we just wrote"),
+        profile_filename,
+    )
+    .expect("Couldn't parse libinfo map from profile file");
+    start_server_main(profile_filename, server_props, symbol_props, libinfo_map);
+}
+
+fn split_at_first_equals(s: &OsStr) -> Option<(&OsStr, &OsStr)> {
+    let bytes = s.as_encoded_bytes();
+    let pos = bytes.iter().position(|b| *b == b'=')?;
+    let name = &bytes[..pos];
+    let val = &bytes[(pos + 1)..];
+    // SAFETY:
+    // - `name` and `val` only contain content that originated from `OsStr::as_encoded_bytes`
+    // - Only split with ASCII '=' which is a non-empty UTF-8 substring
+    let (name, val) = unsafe {
+        (
+            OsStr::from_encoded_bytes_unchecked(name),
+            OsStr::from_encoded_bytes_unchecked(val),
+        )
+    };
+    Some((name, val))
+}
+
+fn import_format_parser() -> clap::builder::PossibleValuesParser {
+    clap::builder::PossibleValuesParser::new(ImporterRegistry::new().possible_values())
+}
+
+fn convert_file_to_profile(
+    filename: &Path,
+    input_file: &File,
+    format: Option<&str>,
+    output_filename: &Path,
+    profile_creation_props: ProfileCreationProps,
+    included_processes: Option<IncludedProcesses>,
+) {
+    let registry = ImporterRegistry::new();
+    let importer = match format {
+        Some(format) => registry.find(format).expect("checked by clap"),
+        None => {
+            exit_for_unsupported_format(filename);
+            match registry.detect(filename, input_file) {
+                Some(importer) => importer,
+                None => {
+                    eprintln!("Error: Could not detect the format of {filename:?}.");
+                    std::process::exit(1);
+                }
+            }
+        }
+    };
+    let input = ImportInput {
+        path: filename,
+        file: input_file,
+        binary_dir: None,
+        included_processes,
+    };
+    import_profile(importer, input, output_filename, profile_creation_props);
+}
+
+/// Exits with advice for the formats which need to be converted with another
+/// tool first.
+fn exit_for_unsupported_format(filename: &Path) {
+    if import::lttng::is_ctf_trace_path(filename) {
+        eprintln!(
+            "Error: Could not import CTF trace from {}",
+            filename.to_string_lossy()
+        );
+        eprintln!("Print it with babeltrace2 --clock-seconds first, and import the text output.");
+        std::process::exit(1);
+    }
+
+    if filename.extension() == Some(OsStr::new("jfr")) {
+        eprintln!(
+            "Error: Could not import JFR recording from file {}",
+            filename.to_string_lossy()
+        );
+        eprintln!("Convert it to folded stacks first, e.g. with async-profiler's jfrconv, and import the .collapsed file.");
+        std::process::exit(1);
+    }
+}
+
+fn import_profile(
+    importer: &dyn Importer,
+    input: ImportInput,
+    output_filename: &Path,
+    profile_creation_props: ProfileCreationProps,
+) {
+    let profile = match importer.convert(input, profile_creation_props) {
+        Ok(profile) => profile,
+        Err(error) => {
+            eprintln!("Error importing {}: {error}", importer.description());
+            std::process::exit(1);
+        }
+    };
+    let output_file = match File::create(output_filename) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("Couldn't create output file {:?}: {}", output_filename, err);
+            std::process::exit(1);
+        }
+    };
+    let writer = BufWriter::new(output_file);
+    serde_json::to_writer(writer, &profile).expect("Couldn't write converted profile JSON");
+}
+
+fn read_lttng_trace(input_file: &File) -> import::lttng::Trace {
+    match import::lttng::read_trace(BufReader::new(input_file)) {
+        Ok(trace) => trace,
+        Err(error) => {
+            eprintln!("Error importing LTTng trace: {error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn add_lttng_markers_to_profile_file(
+    input_file: &File,
+    profile_filename: &Path,
+    output_filename: &Path,
+) {
+    let trace = read_lttng_trace(input_file);
+    let mut profile = match profile_tools::read_profile(profile_filename) {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("Couldn't read profile {:?}: {}", profile_filename, err);
+            std::process::exit(1);
+        }
+    };
+    match import::lttng::add_markers_to_profile(&mut profile, &trace) {
+        Ok(0) => {}
+        Ok(unmatched_event_count) => eprintln!(
+            "Warning: {unmatched_event_count} events didn't belong to any thread in the profile."
+        ),
+        Err(error) => {
+            eprintln!("Error adding LTTng markers: {error}");
+            std::process::exit(1);
+        }
+    }
+    if let Err(err) = profile_tools::write_profile(output_filename, &profile) {
+        eprintln!("Couldn't write {:?}: {}", output_filename, err);
+        std::process::exit(1);
+    }
+}
+
+/// Parses the command line, with the options from the configuration file as
+/// defaults.
+fn parse_opt_with_config() -> Opt {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let explicit_path = config_path_arg(&args);
+    let config = match &explicit_path {
+        Some(path) => Config::read(path),
+        None => match Config::default_path().filter(|path| path.exists()) {
+            Some(path) => Config::read(&path),
+            None => Ok(Config::default()),
+        },
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error in the samply configuration file: {err}");
+            std::process::exit(1)
+        }
+    };
+    Opt::parse_from(config.apply_to_args(&Opt::command(), args))
+}
+
+/// The value of --config, which has to be known before the command line can
+/// be parsed.
+fn config_path_arg(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1).take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn verify_cli() {
+        Opt::command().debug_assert();
+    }
+
+    #[cfg(any(target_os = "android", target_os = "macos", target_os = "linux"))]
+    #[test]
+    fn verify_cli_record() {
+        let opt = Opt::parse_from(["samply", "record", "rustup", "show"]);
+        assert!(
+            matches!(opt.action, Action::Record(record_args) if record_args.command == ["rustup", "show"])
+        );
+
+        let opt = Opt::parse_from(["samply", "record", "rustup", "--no-open"]);
+        assert!(
+        matches!(opt.action, Action::Record(record_args) if record_args.command == ["rustup", "--no-open"]),
+        "Arguments of the form --arg should be considered part of the command even if they match samply options."
+    );
+
+        let opt = Opt::parse_from(["samply", "record", "--no-open", "rustup"]);
+        assert!(
+            matches!(opt.action, Action::Record(record_args) if record_args.command == ["rustup"] && record_args.server_args.no_open),
+            "Arguments which come before the command name should be treated as samply arguments."
+        );
+
+        // Make sure you can't pass both a pid and a command name at the same time.
+        let opt_res = Opt::try_parse_from(["samply", "record", "-p", "1234", "rustup"]);
+        assert!(opt_res.is_err());
+
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--iterations",
+            "5",
+            "--merge-iterations",
+            "./bench",
+        ]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected a record action");
+        };
+        assert_eq!(record_args.iteration_count, 5);
+        assert!(record_args.profile_creation_props().reuse_threads);
+    }
+
+    #[test]
+    fn verify_cli_live() {
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--live",
+            "--live-interval",
+            "2",
+            "./app",
+        ]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected a record action");
+        };
+        assert_eq!(
+            record_args.recording_props().live_interval,
+            Some(Duration::from_secs(2))
+        );
+
+        let opt = Opt::parse_from(["samply", "record", "./app"]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected a record action");
+        };
+        assert_eq!(record_args.recording_props().live_interval, None);
+
+        let opt_res = Opt::try_parse_from(["samply", "record", "--live-interval", "2", "./app"]);
+        assert!(opt_res.is_err());
+    }
+
+    #[test]
+    fn verify_cli_trim() {
+        let opt = Opt::parse_from([
+            "samply", "trim", "--from", "5s", "--to", "12500ms", "a.json",
+        ]);
+        assert!(
+            matches!(opt.action, Action::Trim(trim_args) if trim_args.from == Some(5000.0) && trim_args.to == Some(12500.0))
+        );
+
+        let opt_res = Opt::try_parse_from(["samply", "trim", "--from", "5 minutes", "a.json"]);
+        assert!(opt_res.is_err());
+    }
+
+    #[test]
+    fn verify_cli_max_output_size() {
+        let opt = Opt::parse_from([
+            "samply",
+            "import",
+            "--max-output-size",
+            "200MB",
+            "perf.data",
+        ]);
+        assert!(
+            matches!(opt.action, Action::Import(import_args) if import_args.profile_creation_args.max_output_size == Some(200_000_000))
+        );
+
+        let opt_res = Opt::try_parse_from([
+            "samply",
+            "import",
+            "--max-output-size",
+            "200 apples",
+            "perf.data",
+        ]);
+        assert!(opt_res.is_err());
+    }
+
+    #[cfg(any(
+        target_os = "android",
+        target_os = "macos",
+        target_os = "linux",
+        target_os = "windows"
+    ))]
+    #[test]
+    fn verify_cli_watch() {
+        let opt = Opt::parse_from([
+            "samply",
+            "watch",
+            "--keep",
+            "3",
+            "--watch",
+            "src",
+            "cargo",
+            "run",
+            "--release",
+        ]);
+        assert!(
+            matches!(opt.action, Action::Watch(watch_args) if watch_args.keep == 3
+                && watch_args.watched_paths() == [PathBuf::from("src")]
+                && watch_args.record_args.command == ["cargo", "run", "--release"])
+        );
+
+        let opt_res = Opt::try_parse_from(["samply", "watch", "--keep", "0", "./app"]);
+        assert!(opt_res.is_err());
+    }
+
+    #[cfg(any(
+        target_os = "android",
+        target_os = "macos",
+        target_os = "linux",
+        target_os = "windows"
+    ))]
+    #[test]
+    fn verify_cli_ci() {
+        let opt = Opt::parse_from([
+            "samply",
+            "record",
+            "--ci",
+            "--max-function-share",
+            "parse_json=40%",
+            "./bench",
+        ]);
+        let Action::Record(record_args) = opt.action else {
+            panic!("expected the record subcommand");
+        };
+        assert!(record_args.server_props().is_none());
+        assert_eq!(
+            record_args.ci_args.max_function_share,
+            [("parse_json".to_owned(), 40.0)]
+        );
+
+        let opt_res = Opt::try_parse_from(["samply", "record", "--min-samples", "10", "./bench"]);
+        assert!(opt_res.is_err());
+    }
+
+    #[test]
+    fn verify_cli_unix_socket() {
+        let opt = Opt::parse_from([
+            "samply",
+            "load",
+            "--unix-socket",
+            "/tmp/samply.sock",
+            "a.json",
+        ]);
+        let Action::Load(load_args) = opt.action else {
+            panic!("expected the load subcommand");
+        };
+        let server_props = load_args.server_props();
+        assert_eq!(
+            server_props.unix_socket.as_deref(),
+            Some(Path::new("/tmp/samply.sock"))
+        );
+        assert!(server_props.public_url.is_none());
+        assert!(server_props.tls.is_none());
+
+        let opt_res = Opt::try_parse_from([
+            "samply",
+            "load",
+            "--unix-socket",
+            "/tmp/samply.sock",
+            "-P",
+            "4000",
+            "a.json",
+        ]);
+        assert!(opt_res.is_err());
+    }
+
+    #[test]
+    fn verify_cli_load_multiple() {
+        let opt = Opt::parse_from(["samply", "load", "a.json", "b.json.gz"]);
+        assert!(
+            matches!(opt.action, Action::Load(load_args) if load_args.files == [PathBuf::from("a.json"), PathBuf::from("b.json.gz")])
+        );
+        assert!(Opt::try_parse_from(["samply", "load"]).is_err());
+    }
+
+    #[test]
+    fn verify_cli_tls() {
+        let opt = Opt::parse_from(["samply", "load", "--tls", "a.json"]);
+        let Action::Load(load_args) = opt.action else {
+            panic!("expected the load subcommand");
+        };
+        assert_eq!(load_args.server_props().tls, Some(TlsSource::SelfSigned));
+
+        let opt_res = Opt::try_parse_from(["samply", "load", "--tls-cert", "cert.pem", "a.json"]);
+        assert!(opt_res.is_err());
+    }
+
+    #[test]
+    fn verify_cli_path_prefix() {
+        let opt = Opt::parse_from(["samply", "serve-symbols", "--path-prefix", "/symbols/"]);
+        let Action::ServeSymbols(serve_symbols_args) = opt.action else {
+            panic!("expected the serve-symbols subcommand");
+        };
+        assert_eq!(serve_symbols_args.path_prefix.as_deref(), Some("symbols"));
+
+        for path_prefix in ["", "/", "//"] {
+            let opt_res =
+                Opt::try_parse_from(["samply", "serve-symbols", "--path-prefix", path_prefix]);
+            assert!(opt_res.is_err(), "{path_prefix:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn verify_cli_list() {
+        let opt = Opt::parse_from(["samply", "list", "--last", "5", "--serve"]);
+        assert!(
+            matches!(opt.action, Action::List(list_args) if list_args.last == Some(5) && list_args.serve)
+        );
+    }
+
+    #[test]
+    fn verify_cli_serve() {
+        let opt = Opt::parse_from(["samply", "serve", "--watch-dir", "profiles", "-P", "3000"]);
+        assert!(
+            matches!(opt.action, Action::Serve(serve_args) if serve_args.watch_dir == Path::new("profiles"))
+        );
+
+        let opt_res = Opt::try_parse_from(["samply", "serve"]);
+        assert!(opt_res.is_err());
+    }
+
+    #[test]
+    fn verify_cli_serve_symbols() {
+        let config = Config::parse(
+            r#"
+            [serve-symbols]
+            address = "0.0.0.0"
+            port = 8001
+            path-prefix = "symbols"
+            max-requests-per-minute = 60
+            max-downloads-per-server = 4
+            symbol-dir = ["/srv/symbols"]
+            breakpad-symbol-cache = "/var/cache/samply"
+            "#,
+        )
+        .unwrap();
+        let args = ["samply", "serve-symbols", "--port", "9000"].map(OsString::from);
+        let opt = Opt::parse_from(config.apply_to_args(&Opt::command(), args.to_vec()));
+        let Action::ServeSymbols(serve_symbols_args) = opt.action else {
+            panic!("expected the serve-symbols subcommand");
+        };
+        let server_props = serve_symbols_args.server_props();
+        assert_eq!(server_props.address.to_string(), "0.0.0.0");
+        assert!(matches!(
+            server_props.port_selection,
+            PortSelection::OnePort(9000)
+        ));
+        assert_eq!(server_props.path_prefix.as_deref(), Some("symbols"));
+        assert_eq!(
+            server_props.request_limits.max_requests_per_minute,
+            Some(60)
+        );
+        assert_eq!(server_props.request_limits.max_concurrent_requests, None);
+        let symbol_props = serve_symbols_args.symbol_props();
+        assert_eq!(symbol_props.symbol_dir, [PathBuf::from("/srv/symbols")]);
+        assert_eq!(symbol_props.max_downloads_per_server, Some(4));
+        assert_eq!(
+            symbol_props.breakpad_symbol_cache,
+            Some(PathBuf::from("/var/cache/samply"))
+        );
+    }
+
+    #[test]
+    fn verify_cli_cache() {
+        let opt = Opt::parse_from(["samply", "cache", "--trim-to", "10GB"]);
+        let Action::Cache(cache_args) = opt.action else {
+            panic!("expected the cache subcommand");
+        };
+        assert_eq!(cache_args.trim_to, Some(10_000_000_000));
+        assert!(!cache_args.clear);
+
+        assert!(Opt::try_parse_from(["samply", "cache", "--clear", "--trim-to", "1GB"]).is_err());
+
+        let opt = Opt::parse_from(["samply", "load", "--symbol-cache-size", "2GiB", "a.json"]);
+        let Action::Load(load_args) = opt.action else {
+            panic!("expected the load subcommand");
+        };
+        assert_eq!(
+            load_args.symbol_props().symbol_cache_max_size,
+            Some(2 << 30)
+        );
+    }
+}
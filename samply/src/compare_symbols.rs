@@ -0,0 +1,138 @@
+//! Compares the symbol information which two kinds of symbol files give for
+//! the same binary, to track down discrepancies like wrong line numbers from
+//! one of them.
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use wholesym::{AddressInfo, LookupAddress, SymbolBackend, SymbolManager, SymbolMap};
+
+use crate::server::create_symbol_manager_config;
+use crate::shared::symbol_props::{symbol_backend_name, SymbolProps};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Could not read {0:?}: {1}")]
+    Binary(PathBuf, wholesym::Error),
+
+    #[error("{0:?} has no debug ID, so its symbol files can't be looked up")]
+    NoDebugId(PathBuf),
+
+    #[error("Could not load the {} symbols: {1}", symbol_backend_name(*.0))]
+    Symbols(SymbolBackend, wholesym::Error),
+}
+
+/// The result of looking up the same addresses with two symbol backends.
+pub struct SymbolComparison {
+    pub debug_name: String,
+    pub compared_addresses: usize,
+    pub differences: Vec<SymbolDifference>,
+}
+
+/// An address for which the backends disagree. `None` means that the backend
+/// found no symbol for the address.
+pub struct SymbolDifference {
+    pub address: u32,
+    pub results: [Option<String>; 2],
+}
+
+/// Looks up the start of every symbol known to either backend, and the middle
+/// between each symbol and the next, with both backends.
+#[tokio::main(flavor = "current_thread")]
+pub async fn compare_symbols(
+    binary_path: &Path,
+    backends: [SymbolBackend; 2],
+    symbol_props: SymbolProps,
+    verbose: bool,
+) -> Result<SymbolComparison, Error> {
+    let info = SymbolManager::library_info_for_binary_at_path(binary_path, None)
+        .await
+        .map_err(|err| Error::Binary(binary_path.to_owned(), err))?;
+    let (Some(debug_name), Some(debug_id)) = (info.debug_name.clone(), info.debug_id) else {
+        return Err(Error::NoDebugId(binary_path.to_owned()));
+    };
+
+    let mut symbol_maps = Vec::new();
+    for backend in backends {
+        let config = create_symbol_manager_config(symbol_props.clone(), verbose)
+            .force_backend(debug_name.clone(), backend);
+        let symbol_manager = SymbolManager::with_config(config);
+        symbol_manager.add_known_library(info.clone());
+        let symbol_map = symbol_manager
+            .load_symbol_map(&debug_name, debug_id)
+            .await
+            .map_err(|err| Error::Symbols(backend, err))?;
+        symbol_maps.push(symbol_map);
+    }
+
+    let addresses = addresses_to_compare(
+        symbol_maps
+            .iter()
+            .flat_map(|symbol_map| symbol_map.iter_symbols().map(|(address, _)| address)),
+    );
+    let mut differences = Vec::new();
+    for &address in &addresses {
+        let a = describe_lookup(&symbol_maps[0], address).await;
+        let b = describe_lookup(&symbol_maps[1], address).await;
+        if a != b {
+            differences.push(SymbolDifference {
+                address,
+                results: [a, b],
+            });
+        }
+    }
+    Ok(SymbolComparison {
+        debug_name,
+        compared_addresses: addresses.len(),
+        differences,
+    })
+}
+
+/// The sorted, deduplicated symbol start addresses, plus the middle between
+/// each start address and the next.
+fn addresses_to_compare(symbol_starts: impl IntoIterator<Item = u32>) -> Vec<u32> {
+    let starts: BTreeSet<u32> = symbol_starts.into_iter().collect();
+    let starts: Vec<u32> = starts.into_iter().collect();
+    let middles = starts
+        .windows(2)
+        .map(|pair| pair[0] + (pair[1] - pair[0]) / 2);
+    let addresses: BTreeSet<u32> = starts.iter().copied().chain(middles).collect();
+    addresses.into_iter().collect()
+}
+
+async fn describe_lookup(symbol_map: &SymbolMap, address: u32) -> Option<String> {
+    let info = symbol_map.lookup(LookupAddress::Relative(address)).await?;
+    Some(describe_address_info(&info))
+}
+
+/// The symbol name, followed by the function, file and line of every frame,
+/// from the innermost inlined frame outwards.
+fn describe_address_info(info: &AddressInfo) -> String {
+    let mut description = info.symbol.name.clone();
+    for frame in info.frames.iter().flatten() {
+        let function = frame.function.as_deref().unwrap_or("?");
+        let file = frame
+            .file_path
+            .as_ref()
+            .map_or("?".to_owned(), |path| path.display_path());
+        let line = frame
+            .line_number
+            .map_or("?".to_owned(), |line| line.to_string());
+        write!(description, " | {function} at {file}:{line}").unwrap();
+    }
+    description
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compare_symbol_starts_and_middles() {
+        assert_eq!(
+            addresses_to_compare([0x30, 0x10, 0x20, 0x10, 0x21]),
+            [0x10, 0x18, 0x20, 0x21, 0x28, 0x30]
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! The configuration file, `config.toml` in samply's config directory (e.g.
+//! `~/.config/samply/config.toml` on Linux), or the file given with
+//! `--config`. It provides defaults for the command line options:
+//!
+//! ```toml
+//! rate = 4000
+//! symbol-server = ["https://symbols.example.com/"]
+//!
+//! [record]
+//! main-thread-only = true
+//! category-rules = "/home/me/samply-categories.txt"
+//!
+//! [load]
+//! port = "3000+"
+//!
+//! [serve-symbols]
+//! address = "0.0.0.0"
+//! port = 8001
+//! path-prefix = "symbols"
+//! symbol-dir = ["/srv/symbols"]
+//! ```
+//!
+//! The keys are the long names of the options. Keys at the top apply to
+//! every subcommand which has the option, keys in a `[subcommand]` section
+//! only to that subcommand. Options given on the command line override the
+//! configured values.
+//!
+//! Only the part of TOML which these values need is supported: strings,
+//! numbers, booleans and single-line arrays.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command};
+use platform_dirs::AppDirs;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("Could not read {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+
+    #[error("Line {0}: {1}")]
+    Syntax(usize, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    /// A string or a number, as it would be given on the command line.
+    Scalar(String),
+    Bool(bool),
+    Array(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// (section, key, value), in file order. The section is `None` for the
+    /// keys before the first section header.
+    entries: Vec<(Option<String>, String, ConfigValue)>,
+}
+
+impl Config {
+    /// The path of the configuration file in samply's config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = AppDirs::new(Some("samply"), false)?.config_dir;
+        Some(config_dir.join("config.toml"))
+    }
+
+    pub fn read(path: &Path) -> Result<Self, ConfigError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_owned(), err))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut entries = Vec::new();
+        let mut section = None;
+        for (index, line) in text.lines().enumerate() {
+            let syntax_error = |message: &str| ConfigError::Syntax(index + 1, message.to_owned());
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let (name, rest) = header
+                    .split_once(']')
+                    .ok_or_else(|| syntax_error("expected `]`"))?;
+                if !is_comment_or_empty(rest) {
+                    return Err(syntax_error("unexpected text after the section header"));
+                }
+                section = Some(name.trim().to_owned());
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| syntax_error("expected `key = value`"))?;
+            let key = key.trim().trim_matches('"');
+            if key.is_empty() {
+                return Err(syntax_error("the key is empty"));
+            }
+            let value = parse_value(value).map_err(|message| syntax_error(&message))?;
+            entries.push((section.clone(), key.replace('_', "-"), value));
+        }
+        Ok(Self { entries })
+    }
+
+    /// Returns the command line arguments with the configured options for
+    /// the subcommand inserted after the subcommand's name, except for the
+    /// options which the arguments already contain.
+    pub fn apply_to_args(&self, command: &Command, args: Vec<OsString>) -> Vec<OsString> {
+        // Options which the configuration provides may be required, so the
+        // arguments on their own may not parse.
+        let matches = command
+            .clone()
+            .ignore_errors(true)
+            .get_matches_from(args.clone());
+        let Some((subcommand_name, subcommand_matches)) = matches.subcommand() else {
+            return args;
+        };
+        let Some(subcommand) = command.find_subcommand(subcommand_name) else {
+            return args;
+        };
+        let Some(subcommand_index) = args
+            .iter()
+            .skip(1)
+            .position(|arg| *arg == *subcommand_name)
+            .map(|index| index + 1)
+        else {
+            return args;
+        };
+
+        let mut config_args = Vec::new();
+        for (key, value) in self.options_for_subcommand(subcommand_name) {
+            let Some(arg) = subcommand
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key))
+            else {
+                continue;
+            };
+            if is_given(subcommand_matches, arg.get_id().as_str()) {
+                continue;
+            }
+            match value {
+                ConfigValue::Bool(true) if matches!(arg.get_action(), ArgAction::SetTrue) => {
+                    config_args.push(format!("--{key}"));
+                }
+                ConfigValue::Bool(false) if matches!(arg.get_action(), ArgAction::SetTrue) => {}
+                ConfigValue::Bool(value) => config_args.push(format!("--{key}={value}")),
+                ConfigValue::Scalar(value) => config_args.push(format!("--{key}={value}")),
+                ConfigValue::Array(values) => {
+                    config_args.extend(values.iter().map(|value| format!("--{key}={value}")));
+                }
+            }
+        }
+
+        let mut args = args;
+        let rest = args.split_off(subcommand_index + 1);
+        args.extend(config_args.into_iter().map(OsString::from));
+        args.extend(rest);
+        args
+    }
+
+    /// The configured options for the subcommand. The subcommand's section
+    /// overrides the keys at the top.
+    fn options_for_subcommand(&self, subcommand_name: &str) -> Vec<(&str, &ConfigValue)> {
+        let mut options: Vec<(&str, &ConfigValue)> = Vec::new();
+        let applicable = self.entries.iter().filter(|(section, _, _)| {
+            section.is_none() || section.as_deref() == Some(subcommand_name)
+        });
+        // Keys without a section come first, so that section keys replace them.
+        let (global, in_section): (Vec<_>, Vec<_>) =
+            applicable.partition(|(section, _, _)| section.is_none());
+        for (_, key, value) in global.into_iter().chain(in_section) {
+            options.retain(|(existing, _)| existing != key);
+            options.push((key, value));
+        }
+        options
+    }
+}
+
+fn is_given(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches.try_get_raw(id).map(|_| matches.value_source(id)),
+        Ok(Some(ValueSource::CommandLine))
+    )
+}
+
+fn is_comment_or_empty(s: &str) -> bool {
+    let s = s.trim();
+    s.is_empty() || s.starts_with('#')
+}
+
+fn parse_value(text: &str) -> Result<ConfigValue, String> {
+    let text = text.trim();
+    if let Some(items) = text.strip_prefix('[') {
+        let mut values = Vec::new();
+        let mut rest = items.trim_start();
+        loop {
+            if let Some(after) = rest.strip_prefix(']') {
+                rest = after;
+                break;
+            }
+            let (value, after) = parse_scalar(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after.trim_start();
+            } else if !rest.starts_with(']') {
+                return Err("expected `,` or `]` in the array".to_owned());
+            }
+        }
+        if !is_comment_or_empty(rest) {
+            return Err("unexpected text after the array".to_owned());
+        }
+        return Ok(ConfigValue::Array(values));
+    }
+    let (value, rest) = parse_scalar(text)?;
+    if !is_comment_or_empty(rest) {
+        return Err("unexpected text after the value".to_owned());
+    }
+    Ok(match value.as_str() {
+        "true" if !text.starts_with(['"', '\'']) => ConfigValue::Bool(true),
+        "false" if !text.starts_with(['"', '\'']) => ConfigValue::Bool(false),
+        _ => ConfigValue::Scalar(value),
+    })
+}
+
+/// Parses a string, number or boolean at the start of `text`, and returns it
+/// with the remaining text.
+fn parse_scalar(text: &str) -> Result<(String, &str), String> {
+    if let Some(literal) = text.strip_prefix('\'') {
+        let (value, rest) = literal
+            .split_once('\'')
+            .ok_or("the string is not terminated")?;
+        return Ok((value.to_owned(), rest));
+    }
+    if let Some(basic) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = basic.char_indices();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '"' => return Ok((value, &basic[index + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(c @ ('"' | '\\')) => value.push(c),
+                    _ => return Err("unsupported escape sequence in the string".to_owned()),
+                },
+                c => value.push(c),
+            }
+        }
+        return Err("the string is not terminated".to_owned());
+    }
+    let end = text
+        .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+        .unwrap_or(text.len());
+    let (value, rest) = text.split_at(end);
+    if value.is_empty() {
+        return Err("expected a value".to_owned());
+    }
+    Ok((value.replace('_', ""), rest))
+}
+
+#[cfg(test)]
+mod test {
+    use clap::{Args, Parser, Subcommand};
+
+    use super::*;
+
+    #[derive(Debug, Parser)]
+    struct TestOpt {
+        #[command(subcommand)]
+        action: TestAction,
+    }
+
+    #[derive(Debug, Subcommand)]
+    enum TestAction {
+        Record(TestRecordArgs),
+    }
+
+    #[derive(Debug, Args)]
+    struct TestRecordArgs {
+        #[arg(long, default_value_t = 1000.0)]
+        rate: f64,
+        #[arg(long)]
+        main_thread_only: bool,
+        #[arg(long)]
+        symbol_server: Vec<String>,
+        #[arg(long)]
+        port: String,
+        command: Vec<String>,
+    }
+
+    #[test]
+    fn configured_options_are_defaults() {
+        let config = Config::parse(
+            r#"
+            # Defaults for all subcommands
+            rate = 4_000
+            symbol-server = ["https://a.example.com/", 'https://b.example.com/']
+            unknown = 'ignored'
+
+            [record] # Recording
+            main_thread_only = true
+            port = "3000+"
+            "#,
+        )
+        .unwrap();
+        let args = |args: &[&str]| -> Vec<String> {
+            let args = args.iter().map(OsString::from).collect();
+            config
+                .apply_to_args(&<TestOpt as clap::CommandFactory>::command(), args)
+                .into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect()
+        };
+        assert_eq!(
+            args(&["samply", "record", "--rate", "10", "ls"]),
+            [
+                "samply",
+                "record",
+                "--symbol-server=https://a.example.com/",
+                "--symbol-server=https://b.example.com/",
+                "--main-thread-only",
+                "--port=3000+",
+                "--rate",
+                "10",
+                "ls"
+            ]
+        );
+        let opt = TestOpt::parse_from(args(&["samply", "record", "ls"]));
+        let TestAction::Record(record_args) = opt.action;
+        assert_eq!(record_args.rate, 4000.0);
+        assert!(record_args.main_thread_only);
+        assert_eq!(record_args.port, "3000+");
+
+        assert!(matches!(
+            Config::parse("rate = [1, 2"),
+            Err(ConfigError::Syntax(1, _))
+        ));
+    }
+}
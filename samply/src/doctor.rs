@@ -0,0 +1,317 @@
+//! `samply doctor`: checks whether this machine is set up for recording, and
+//! prints how to fix what isn't, so that users don't run into permission
+//! errors in the middle of `samply record`.
+
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    /// Recording works, but with limitations.
+    Warning,
+    /// Recording fails.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What to do about a warning or an error.
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        status: CheckStatus,
+        detail: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Runs the checks for the current platform.
+pub fn run_checks() -> Vec<Check> {
+    platform_checks()
+}
+
+/// Prints one line per check, with the fix indented below it.
+pub fn print_report(checks: &[Check], mut out: impl Write) -> std::io::Result<()> {
+    for check in checks {
+        let label = match check.status {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warning => "warning",
+            CheckStatus::Error => "error",
+        };
+        writeln!(out, "[{label:>7}] {}: {}", check.name, check.detail)?;
+        if let Some(fix) = &check.fix {
+            for line in fix.lines() {
+                writeln!(out, "          {line}")?;
+            }
+        }
+    }
+    let errors = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Error)
+        .count();
+    let warnings = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Warning)
+        .count();
+    match (errors, warnings) {
+        (0, 0) => writeln!(out, "\nEverything is set up for recording."),
+        (0, _) => writeln!(out, "\nRecording works, with the limitations above."),
+        _ => writeln!(
+            out,
+            "\nRecording will fail until the errors above are fixed."
+        ),
+    }
+}
+
+/// The capabilities which matter for recording, from `CapEff` in
+/// `/proc/self/status`.
+#[cfg_attr(not(any(target_os = "android", target_os = "linux")), allow(dead_code))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Capabilities {
+    root: bool,
+    perfmon: bool,
+    syslog: bool,
+}
+
+#[cfg_attr(not(any(target_os = "android", target_os = "linux")), allow(dead_code))]
+impl Capabilities {
+    const CAP_SYSLOG: u32 = 34;
+    const CAP_SYS_ADMIN: u32 = 21;
+    const CAP_PERFMON: u32 = 38;
+
+    fn from_proc_status(status: &str, root: bool) -> Self {
+        let effective = status
+            .lines()
+            .find_map(|line| line.strip_prefix("CapEff:"))
+            .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+            .unwrap_or(0);
+        let has = |cap: u32| effective & (1 << cap) != 0;
+        Self {
+            root,
+            // Before Linux 5.8, CAP_SYS_ADMIN covered what CAP_PERFMON does.
+            perfmon: has(Self::CAP_PERFMON) || has(Self::CAP_SYS_ADMIN),
+            syslog: has(Self::CAP_SYSLOG),
+        }
+    }
+}
+
+#[cfg_attr(not(any(target_os = "android", target_os = "linux")), allow(dead_code))]
+fn paranoid_check(level: Option<i32>, caps: Capabilities) -> Check {
+    const NAME: &str = "perf_event_paranoid";
+    let Some(level) = level else {
+        return Check::problem(
+            NAME,
+            CheckStatus::Error,
+            "/proc/sys/kernel/perf_event_paranoid does not exist, so the kernel has no perf events",
+            "Use a kernel built with CONFIG_PERF_EVENTS. In containers, the host's seccomp\n\
+             profile may also need to allow perf_event_open.",
+        );
+    };
+    if level <= 1 {
+        return Check::ok(NAME, format!("{level}, processes can be profiled"));
+    }
+    if caps.root || caps.perfmon {
+        return Check::ok(
+            NAME,
+            format!(
+                "{level}, but samply runs as root or has CAP_PERFMON, so the limit does not apply"
+            ),
+        );
+    }
+    Check::problem(
+        NAME,
+        CheckStatus::Error,
+        format!("{level}, which does not allow profiling without root"),
+        "Run `echo '1' | sudo tee /proc/sys/kernel/perf_event_paranoid`, or give samply\n\
+         the capability with `sudo setcap cap_perfmon+ep $(which samply)`.",
+    )
+}
+
+#[cfg_attr(not(any(target_os = "android", target_os = "linux")), allow(dead_code))]
+fn kptr_restrict_check(level: Option<i32>, caps: Capabilities) -> Check {
+    const NAME: &str = "kptr_restrict";
+    let hidden = match level {
+        None | Some(0) => false,
+        Some(1) => !caps.root && !caps.syslog,
+        Some(_) => true,
+    };
+    let level = level.map_or_else(|| "unknown".to_owned(), |level| level.to_string());
+    if !hidden {
+        return Check::ok(NAME, format!("{level}, kernel symbols are readable"));
+    }
+    Check::problem(
+        NAME,
+        CheckStatus::Warning,
+        format!("{level}, so kernel frames will not have function names"),
+        "Run `echo '0' | sudo tee /proc/sys/kernel/kptr_restrict`.",
+    )
+}
+
+#[cfg_attr(not(any(target_os = "android", target_os = "linux")), allow(dead_code))]
+fn capability_check(caps: Capabilities) -> Check {
+    const NAME: &str = "capabilities";
+    if caps.root {
+        Check::ok(NAME, "running as root")
+    } else if caps.perfmon {
+        Check::ok(NAME, "CAP_PERFMON is effective")
+    } else {
+        Check::ok(
+            NAME,
+            "not root and no CAP_PERFMON; the sysctls above decide what is allowed",
+        )
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn platform_checks() -> Vec<Check> {
+    let read_level =
+        |path: &str| -> Option<i32> { std::fs::read_to_string(path).ok()?.trim().parse().ok() };
+    let root = unsafe { libc::geteuid() } == 0;
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let caps = Capabilities::from_proc_status(&status, root);
+    vec![
+        capability_check(caps),
+        paranoid_check(read_level("/proc/sys/kernel/perf_event_paranoid"), caps),
+        kptr_restrict_check(read_level("/proc/sys/kernel/kptr_restrict"), caps),
+    ]
+}
+
+#[cfg(target_os = "macos")]
+fn platform_checks() -> Vec<Check> {
+    const NAME: &str = "debugger entitlement";
+    let Ok(exe) = std::env::current_exe() else {
+        return vec![Check::problem(
+            NAME,
+            CheckStatus::Warning,
+            "could not find the samply binary",
+            "Run `samply setup` to be able to attach to running processes.",
+        )];
+    };
+    let entitlements = std::process::Command::new("codesign")
+        .args(["-d", "--entitlements", "-", "--xml"])
+        .arg(&exe)
+        .output();
+    let has_entitlement = entitlements.is_ok_and(|output| {
+        String::from_utf8_lossy(&output.stdout).contains("com.apple.security.cs.debugger")
+    });
+    let check = if has_entitlement {
+        Check::ok(
+            NAME,
+            "samply is signed with com.apple.security.cs.debugger and can attach to processes",
+        )
+    } else {
+        Check::problem(
+            NAME,
+            CheckStatus::Warning,
+            "samply is not signed with com.apple.security.cs.debugger, so task_for_pid fails \
+             and `samply record -p` cannot attach to running processes",
+            "Run `samply setup` to sign samply for this machine. Launching a command with\n\
+             `samply record` works without it.",
+        )
+    };
+    vec![check]
+}
+
+#[cfg(target_os = "windows")]
+fn platform_checks() -> Vec<Check> {
+    let elevation = if crate::windows::is_elevated() {
+        Check::ok(
+            "administrator",
+            "samply runs elevated and can start ETW sessions",
+        )
+    } else {
+        Check::problem(
+            "administrator",
+            CheckStatus::Warning,
+            "samply does not run elevated, so recording asks for administrator rights \
+             through a UAC prompt to start the ETW kernel session",
+            "Accept the prompt, or run samply from an elevated terminal (or with `sudo`).",
+        )
+    };
+    let xperf = match which::which("xperf") {
+        Ok(path) => Check::ok("xperf", format!("found at {}", path.display())),
+        Err(_) => Check::problem(
+            "xperf",
+            CheckStatus::Error,
+            "xperf.exe is not in the PATH",
+            "Install the Windows Performance Toolkit from the Windows ADK:\n\
+             https://learn.microsoft.com/en-us/windows-hardware/test/wpt/",
+        ),
+    };
+    vec![elevation, xperf]
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "windows"
+)))]
+fn platform_checks() -> Vec<Check> {
+    vec![Check::problem(
+        "platform",
+        CheckStatus::Error,
+        format!("recording is not supported on {}", std::env::consts::OS),
+        "Record on Linux, macOS or Windows, and use `samply import` or `samply load` here.",
+    )]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paranoid_level_and_capabilities() {
+        let status = "Name:\tsamply\nCapEff:\t0000004000000000\n";
+        let perfmon = Capabilities::from_proc_status(status, false);
+        assert!(perfmon.perfmon && !perfmon.syslog);
+        let none = Capabilities::default();
+
+        assert_eq!(paranoid_check(Some(1), none).status, CheckStatus::Ok);
+        assert_eq!(paranoid_check(Some(2), perfmon).status, CheckStatus::Ok);
+        let check = paranoid_check(Some(2), none);
+        assert_eq!(check.status, CheckStatus::Error);
+        assert!(check.fix.unwrap().contains("perf_event_paranoid"));
+        assert_eq!(paranoid_check(None, none).status, CheckStatus::Error);
+
+        assert_eq!(kptr_restrict_check(Some(0), none).status, CheckStatus::Ok);
+        assert_eq!(
+            kptr_restrict_check(Some(1), none).status,
+            CheckStatus::Warning
+        );
+        let root = Capabilities { root: true, ..none };
+        assert_eq!(kptr_restrict_check(Some(1), root).status, CheckStatus::Ok);
+        assert_eq!(
+            kptr_restrict_check(Some(2), root).status,
+            CheckStatus::Warning
+        );
+
+        let mut report = Vec::new();
+        print_report(&[paranoid_check(Some(2), none)], &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("[  error] perf_event_paranoid: 2,"));
+        assert!(report.ends_with("Recording will fail until the errors above are fixed.\n"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Helpers for `samply serve --watch-dir`, which lists the profiles in a
+//! directory and imports the recordings which are dropped into it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// How often the directory is checked for new files.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The directory, inside the watched directory, for the imported profiles.
+const CONVERTED_DIR_NAME: &str = ".converted";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DroppedFileKind {
+    /// A profile in the Firefox Profiler format, which is listed as is.
+    Profile,
+    /// A recording which `samply import` converts into a profile, like a
+    /// perf.data file or an ETW trace.
+    Recording,
+}
+
+impl DroppedFileKind {
+    /// Which kind of file this is, by its name. Returns `None` for files
+    /// which should be ignored, like hidden files or the `.syms.json` files
+    /// next to the profiles.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy();
+        if name.starts_with('.') || name.ends_with(".syms.json") {
+            return None;
+        }
+        if name.ends_with(".json") || name.ends_with(".json.gz") {
+            return Some(Self::Profile);
+        }
+        let is_recording = name.starts_with("perf.data")
+            || [
+                ".data",
+                ".etl",
+                ".pprof",
+                ".pb",
+                ".pb.gz",
+                ".collapsed",
+                ".folded",
+                ".dmp",
+            ]
+            .iter()
+            .any(|extension| name.ends_with(extension));
+        is_recording.then_some(Self::Recording)
+    }
+}
+
+/// A directory into which profiles and recordings are dropped. Files are
+/// picked up once their size and modification time have stopped changing,
+/// so that files which are still being copied aren't imported.
+pub struct DropFolder {
+    dir: PathBuf,
+    converted_dir: PathBuf,
+    /// The size and modification time of each file at the last poll.
+    last_seen: HashMap<PathBuf, (u64, SystemTime)>,
+    /// The modification time of each file when it was picked up.
+    picked_up: HashMap<PathBuf, SystemTime>,
+    profiles: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl DropFolder {
+    /// The directory and its `.converted` subdirectory are created if they
+    /// don't exist.
+    pub fn new(dir: &Path) -> std::io::Result<Self> {
+        let converted_dir = dir.join(CONVERTED_DIR_NAME);
+        std::fs::create_dir_all(&converted_dir)?;
+        Ok(Self {
+            dir: dir.to_owned(),
+            converted_dir,
+            last_seen: HashMap::new(),
+            picked_up: HashMap::new(),
+            profiles: Default::default(),
+        })
+    }
+
+    /// The list of profiles, oldest first, which is shared with the server's
+    /// index page.
+    pub fn profiles(&self) -> Arc<Mutex<Vec<PathBuf>>> {
+        self.profiles.clone()
+    }
+
+    /// Returns the files which are new or have changed since they were last
+    /// picked up, and which haven't changed since the previous poll.
+    pub fn poll(&mut self) -> Vec<(PathBuf, DroppedFileKind)> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut current = HashMap::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() || DroppedFileKind::from_path(&path).is_none() {
+                continue;
+            }
+            if let Ok(modified) = metadata.modified() {
+                current.insert(path, (metadata.len(), modified));
+            }
+        }
+
+        let mut ready: Vec<(PathBuf, SystemTime)> = current
+            .iter()
+            .filter(|(path, state)| self.last_seen.get(*path) == Some(state))
+            .filter(|(path, (_, modified))| self.picked_up.get(*path) != Some(modified))
+            .map(|(path, (_, modified))| (path.clone(), *modified))
+            .collect();
+        ready.sort_by_key(|(_, modified)| *modified);
+        for (path, modified) in &ready {
+            self.picked_up.insert(path.clone(), *modified);
+        }
+        self.last_seen = current;
+        ready
+            .into_iter()
+            .filter_map(|(path, _)| {
+                let kind = DroppedFileKind::from_path(&path)?;
+                Some((path, kind))
+            })
+            .collect()
+    }
+
+    /// The path of the profile which is imported from the recording at `path`.
+    pub fn converted_path(&self, path: &Path) -> PathBuf {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.converted_dir.join(format!("{name}.json"))
+    }
+
+    /// Whether the recording at `path` has been imported since it was last
+    /// modified, e.g. before the server was restarted.
+    pub fn is_converted(&self, path: &Path) -> bool {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        match (modified(path), modified(&self.converted_path(path))) {
+            (Some(recording), Some(profile)) => profile >= recording,
+            _ => false,
+        }
+    }
+
+    /// Adds a profile to the list, or moves it to the end if it was updated.
+    pub fn add(&mut self, path: PathBuf) {
+        let mut profiles = self.profiles.lock().unwrap();
+        profiles.retain(|profile| profile != &path);
+        profiles.push(path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pick_up_stable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut folder = DropFolder::new(dir.path()).unwrap();
+        let recording = dir.path().join("perf.data");
+        std::fs::write(&recording, "PERFILE2").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+        std::fs::write(dir.path().join("profile.syms.json"), "{}").unwrap();
+
+        // The first poll only records the size, in case the file is still
+        // being written.
+        assert!(folder.poll().is_empty());
+        assert_eq!(
+            folder.poll(),
+            [(recording.clone(), DroppedFileKind::Recording)]
+        );
+        assert!(folder.poll().is_empty());
+        assert!(!folder.is_converted(&recording));
+        assert_eq!(
+            folder.converted_path(&recording),
+            dir.path().join(".converted/perf.data.json")
+        );
+
+        let profile = dir.path().join("profile.json.gz");
+        std::fs::write(&profile, "").unwrap();
+        assert!(folder.poll().is_empty());
+        assert_eq!(folder.poll(), [(profile.clone(), DroppedFileKind::Profile)]);
+        folder.add(profile.clone());
+        folder.add(profile.clone());
+        assert_eq!(*folder.profiles().lock().unwrap(), [profile]);
+    }
+
+    #[test]
+    fn classify_dropped_files() {
+        let kind = |name: &str| DroppedFileKind::from_path(Path::new(name));
+        assert_eq!(kind("dir/perf.data.old"), Some(DroppedFileKind::Recording));
+        assert_eq!(kind("trace.etl"), Some(DroppedFileKind::Recording));
+        assert_eq!(kind("cpu.pb.gz"), Some(DroppedFileKind::Recording));
+        assert_eq!(kind("profile.json"), Some(DroppedFileKind::Profile));
+        assert_eq!(kind("profile.syms.json"), None);
+        assert_eq!(kind(".perf.data.partial"), None);
+        assert_eq!(kind("README.md"), None);
+    }
+}
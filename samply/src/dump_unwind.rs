@@ -0,0 +1,306 @@
+//! Prints the unwind information which covers an address in a binary, to help
+//! diagnose broken stacks.
+//!
+//! The sources are listed in the order in which framehop consults them when
+//! unwinding: compact unwind info (`__unwind_info`) for Mach-O, the function
+//! table (`.pdata`) for PE, and `.eh_frame` for ELF and Mach-O. When none of
+//! them covers the address, framehop falls back to frame pointers. On top of
+//! that, framehop analyzes the instructions at the address to detect whether
+//! it is in a function's prologue or epilogue; those rules are not shown here.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use gimli::{
+    BaseAddresses, CfaRule, EhFrame, Reader, ReaderOffset, Register, RegisterRule, RunTimeEndian,
+    UnwindContext, UnwindSection,
+};
+use macho_unwind_info::opcodes::{OpcodeArm64, OpcodeX86_64};
+use object::{Architecture, Object, ObjectSection};
+use pe_unwind_info::x86_64::{FunctionTableEntries, UnwindInfo, UnwindInfoTrailer};
+use wholesym::samply_symbols;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Could not read {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+
+    #[error("Could not parse {0:?}: {1}")]
+    Object(PathBuf, object::Error),
+
+    #[error("Unwind information for {0:?} binaries is not supported")]
+    UnsupportedArchitecture(Architecture),
+
+    #[error("Could not parse the {0} section: {1}")]
+    Section(String, String),
+
+    #[error("Could not write the output: {0}")]
+    Write(#[from] std::io::Error),
+}
+
+/// Writes the unwind rules for `address`, which is relative to the image base
+/// like the addresses in profiles.
+pub fn dump_unwind_rules(
+    binary_path: &Path,
+    address: u32,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let data = std::fs::read(binary_path).map_err(|err| Error::Io(binary_path.to_owned(), err))?;
+    let file =
+        object::File::parse(&data[..]).map_err(|err| Error::Object(binary_path.to_owned(), err))?;
+    let arch = file.architecture();
+    if !matches!(arch, Architecture::X86_64 | Architecture::Aarch64) {
+        return Err(Error::UnsupportedArchitecture(arch));
+    }
+    let base_svma = samply_symbols::relative_address_base(&file);
+    let svma = base_svma + u64::from(address);
+    writeln!(
+        writer,
+        "Unwind information for {} at 0x{address:x} (SVMA 0x{svma:x}):",
+        binary_path.display()
+    )?;
+
+    let mut found = false;
+    if let Some(section) = file.section_by_name("__unwind_info") {
+        found |= write_compact_unwind_info(&section_data(&section)?, arch, address, &mut writer)?;
+    }
+    if let Some(section) = file.section_by_name(".pdata") {
+        found |= write_pe_unwind_info(&file, &section_data(&section)?, arch, address, &mut writer)?;
+    }
+    if let Some(section) = file
+        .section_by_name(".eh_frame")
+        .or_else(|| file.section_by_name("__eh_frame"))
+    {
+        let endian = if file.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        let data = section_data(&section)?;
+        let mut eh_frame = EhFrame::new(&data, endian);
+        if arch == Architecture::X86_64 {
+            eh_frame.set_address_size(8);
+        }
+        let mut bases = BaseAddresses::default().set_eh_frame(section.address());
+        if let Some(text) = file.section_by_name(".text") {
+            bases = bases.set_text(text.address());
+        }
+        if let Some(got) = file.section_by_name(".got") {
+            bases = bases.set_got(got.address());
+        }
+        found |= write_eh_frame_info(&eh_frame, &bases, arch, svma, &mut writer)?;
+    }
+    if !found {
+        writeln!(
+            writer,
+            "No unwind information covers this address, so samply unwinds using frame pointers."
+        )?;
+    }
+    Ok(())
+}
+
+fn section_data<'data>(section: &impl ObjectSection<'data>) -> Result<Vec<u8>, Error> {
+    let data = section.uncompressed_data().map_err(|err| {
+        Error::Section(section.name().unwrap_or("unknown").into(), err.to_string())
+    })?;
+    Ok(data.into_owned())
+}
+
+fn write_compact_unwind_info(
+    data: &[u8],
+    arch: Architecture,
+    address: u32,
+    writer: &mut impl Write,
+) -> Result<bool, Error> {
+    let unwind_info = macho_unwind_info::UnwindInfo::parse(data)
+        .map_err(|err| Error::Section("__unwind_info".into(), err.to_string()))?;
+    let function = unwind_info
+        .lookup(address)
+        .map_err(|err| Error::Section("__unwind_info".into(), err.to_string()))?;
+    let Some(function) = function else {
+        return Ok(false);
+    };
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "Compact unwind info, function 0x{:x}..0x{:x}:",
+        function.start_address, function.end_address
+    )?;
+    match arch {
+        Architecture::Aarch64 => writeln!(writer, "  {}", OpcodeArm64::parse(function.opcode))?,
+        _ => writeln!(writer, "  {}", OpcodeX86_64::parse(function.opcode))?,
+    }
+    Ok(true)
+}
+
+fn write_pe_unwind_info<'data>(
+    file: &object::File<'data>,
+    pdata: &[u8],
+    arch: Architecture,
+    address: u32,
+    writer: &mut impl Write,
+) -> Result<bool, Error> {
+    if arch != Architecture::X86_64 {
+        // pe-unwind-info only understands the x86_64 format.
+        return Ok(false);
+    }
+    let base_svma = samply_symbols::relative_address_base(file);
+    let memory_at_rva = |rva: u32| -> Option<Vec<u8>> {
+        let svma = base_svma + u64::from(rva);
+        file.sections().find_map(|section| {
+            let offset = svma.checked_sub(section.address())?;
+            let data = section.data().ok()?;
+            data.get(offset as usize..).map(<[u8]>::to_vec)
+        })
+    };
+    let Some(mut function) = FunctionTableEntries::parse(pdata).lookup(address).copied() else {
+        return Ok(false);
+    };
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "PE unwind info, function 0x{:x}..0x{:x}:",
+        function.begin_address.get(),
+        function.end_address.get()
+    )?;
+    // The prolog offset only matters for the function containing the address.
+    // The operations of chained unwind info have always been executed.
+    let mut prolog_offset = Some(address - function.begin_address.get());
+    loop {
+        let unwind_info_data =
+            memory_at_rva(function.unwind_info_address.get()).ok_or_else(|| {
+                Error::Section(".pdata".into(), "unwind info outside of the image".into())
+            })?;
+        let unwind_info = UnwindInfo::parse(&unwind_info_data)
+            .ok_or_else(|| Error::Section(".pdata".into(), "invalid unwind info".into()))?;
+        if let Some(frame_register) = unwind_info.frame_register() {
+            writeln!(
+                writer,
+                "  Frame register: {frame_register:?} + 0x{:x}",
+                unwind_info.frame_register_offset()
+            )?;
+        }
+        for (offset, operation) in unwind_info.unwind_operations() {
+            let skipped = match prolog_offset {
+                Some(prolog_offset) if u32::from(offset) > prolog_offset => {
+                    " (not yet executed at this address)"
+                }
+                _ => "",
+            };
+            writeln!(
+                writer,
+                "  After prolog offset 0x{offset:x}: undo {operation:?}{skipped}"
+            )?;
+        }
+        let Some(UnwindInfoTrailer::ChainedUnwindInfo { chained }) = unwind_info.trailer() else {
+            break;
+        };
+        function = *chained;
+        prolog_offset = None;
+        writeln!(
+            writer,
+            "  Chained to function 0x{:x}..0x{:x}:",
+            function.begin_address.get(),
+            function.end_address.get()
+        )?;
+    }
+    Ok(true)
+}
+
+fn write_eh_frame_info<R: Reader>(
+    eh_frame: &EhFrame<R>,
+    bases: &BaseAddresses,
+    arch: Architecture,
+    svma: u64,
+    writer: &mut impl Write,
+) -> Result<bool, Error> {
+    let fde = match eh_frame.fde_for_address(bases, svma, EhFrame::cie_from_offset) {
+        Ok(fde) => fde,
+        Err(gimli::Error::NoUnwindInfoForAddress) => return Ok(false),
+        Err(err) => return Err(Error::Section(".eh_frame".into(), err.to_string())),
+    };
+    let mut context = Box::new(UnwindContext::new());
+    let row = fde
+        .unwind_info_for_address(eh_frame, bases, &mut context, svma)
+        .map_err(|err| Error::Section(".eh_frame".into(), err.to_string()))?;
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "eh_frame, FDE 0x{:x}..0x{:x}, row 0x{:x}..0x{:x}:",
+        fde.initial_address(),
+        fde.initial_address() + fde.len(),
+        row.start_address(),
+        row.end_address()
+    )?;
+    writeln!(writer, "  CFA = {}", describe_cfa_rule(row.cfa(), arch))?;
+    let return_address_register = fde.cie().return_address_register();
+    for (register, rule) in row.registers() {
+        let name = if *register == return_address_register {
+            "return address".to_owned()
+        } else {
+            register_name(*register, arch)
+        };
+        writeln!(writer, "  {name} = {}", describe_register_rule(rule, arch))?;
+    }
+    Ok(true)
+}
+
+fn register_name(register: Register, arch: Architecture) -> String {
+    let name = match arch {
+        Architecture::Aarch64 => gimli::AArch64::register_name(register),
+        _ => gimli::X86_64::register_name(register),
+    };
+    match name {
+        Some(name) => name.to_owned(),
+        None => format!("r{}", register.0),
+    }
+}
+
+fn describe_cfa_rule<T: ReaderOffset>(rule: &CfaRule<T>, arch: Architecture) -> String {
+    match rule {
+        CfaRule::RegisterAndOffset { register, offset } => {
+            format!("{}{offset:+}", register_name(*register, arch))
+        }
+        CfaRule::Expression(_) => "<DWARF expression>".to_owned(),
+    }
+}
+
+fn describe_register_rule<T: ReaderOffset>(rule: &RegisterRule<T>, arch: Architecture) -> String {
+    match rule {
+        RegisterRule::Undefined => "undefined".to_owned(),
+        RegisterRule::SameValue => "same value".to_owned(),
+        RegisterRule::Offset(offset) => format!("[CFA{offset:+}]"),
+        RegisterRule::ValOffset(offset) => format!("CFA{offset:+}"),
+        RegisterRule::Register(register) => register_name(*register, arch),
+        RegisterRule::Expression(_) => "[<DWARF expression>]".to_owned(),
+        RegisterRule::ValExpression(_) => "<DWARF expression>".to_owned(),
+        RegisterRule::Architectural => "architectural".to_owned(),
+        RegisterRule::Constant(value) => format!("0x{value:x}"),
+        _ => "unknown".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn describe_dwarf_rules() {
+        let cfa = CfaRule::<usize>::RegisterAndOffset {
+            register: gimli::X86_64::RSP,
+            offset: 16,
+        };
+        assert_eq!(describe_cfa_rule(&cfa, Architecture::X86_64), "rsp+16");
+        assert_eq!(
+            describe_register_rule(&RegisterRule::<usize>::Offset(-16), Architecture::X86_64),
+            "[CFA-16]"
+        );
+        assert_eq!(
+            describe_register_rule(
+                &RegisterRule::<usize>::Register(gimli::AArch64::X29),
+                Architecture::Aarch64
+            ),
+            "X29"
+        );
+    }
+}
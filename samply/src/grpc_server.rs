@@ -0,0 +1,1008 @@
+//! Serves the symbolication API over gRPC, as described by
+//! `proto/symbolication.proto`.
+//!
+//! The requests are translated into the JSON API which the profiler uses, so
+//! both interfaces always return the same results. The server speaks
+//! HTTP/2 without TLS ("h2c") and doesn't support compressed messages.
+//!
+//! `SymbolicateStream` answers each request message of a stream as soon as
+//! it has been symbolicated, so that services can send any number of jobs
+//! over one call without waiting for, or holding on to, all the results.
+//!
+//! Unlike the JSON API, the gRPC API isn't behind the secret path prefix:
+//! browsers can't make gRPC requests, so web pages can't reach it either.
+
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, LengthLimitError, Limited, StreamBody};
+use hyper::body::{Bytes, Frame};
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use hyper::server::conn::http2;
+use hyper::service::service_fn;
+use hyper::{Request, Response};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use prost::Message;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::sync::OwnedSemaphorePermit;
+use wholesym::SymbolManager;
+
+use crate::server::{has_bearer_token, GracefulShutdown};
+use crate::server_limits::{retry_after_secs, RequestLimiter};
+use crate::server_metrics::ServerMetrics;
+
+const SERVICE_PATH: &str = "/samply.symbolication.v1.Symbolication/";
+
+/// The size limit of a request message, which is also gRPC's default.
+const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// The size of the header in front of each message: a compression flag and
+/// the message length.
+const FRAME_HEADER_SIZE: usize = 5;
+
+/// How many response messages of a `SymbolicateStream` call are symbolicated
+/// ahead of the client reading them.
+const STREAM_BUFFER_MESSAGES: usize = 4;
+
+/// The characters which need to be percent-encoded in a `grpc-message`.
+const GRPC_MESSAGE_CHARS: &AsciiSet = &CONTROLS.add(b'%');
+
+/// A gRPC status code and message, sent in the response trailers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Status {
+    code: u32,
+    message: String,
+}
+
+impl Status {
+    const OK: u32 = 0;
+    const CANCELLED: u32 = 1;
+    const UNKNOWN: u32 = 2;
+    const INVALID_ARGUMENT: u32 = 3;
+    const RESOURCE_EXHAUSTED: u32 = 8;
+    const UNIMPLEMENTED: u32 = 12;
+    const INTERNAL: u32 = 13;
+    const UNAUTHENTICATED: u32 = 16;
+
+    fn new(code: u32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+pub async fn run_grpc_server(
+    listener: TcpListener,
+    symbol_manager: Arc<SymbolManager>,
+    metrics: Arc<ServerMetrics>,
+    auth_token: Option<Arc<String>>,
+    request_limiter: Arc<RequestLimiter>,
+    shutdown: GracefulShutdown,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    loop {
+        let (stream, client_addr) = tokio::select! {
+            stream_res = listener.accept() => stream_res?,
+            _ = shutdown.signalled() => return Ok(()),
+        };
+        let io = TokioIo::new(stream);
+        let symbol_manager = symbol_manager.clone();
+        let metrics = metrics.clone();
+        let auth_token = auth_token.clone();
+        let request_limiter = request_limiter.clone();
+        let connection_shutdown = shutdown.clone();
+
+        shutdown.spawn(async move {
+            let connection = http2::Builder::new(TokioExecutor::new()).serve_connection(
+                io,
+                service_fn(move |req| {
+                    grpc_service(
+                        req,
+                        symbol_manager.clone(),
+                        metrics.clone(),
+                        auth_token.clone(),
+                        request_limiter.clone(),
+                        client_addr.ip(),
+                    )
+                }),
+            );
+            tokio::pin!(connection);
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = connection_shutdown.signalled() => {
+                    // Sends GOAWAY, and finishes the calls in flight.
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(err) = result {
+                println!("Error serving gRPC connection: {:?}", err);
+            }
+        });
+    }
+}
+
+async fn grpc_service(
+    req: Request<hyper::body::Incoming>,
+    symbol_manager: Arc<SymbolManager>,
+    metrics: Arc<ServerMetrics>,
+    auth_token: Option<Arc<String>>,
+    request_limiter: Arc<RequestLimiter>,
+    client_addr: IpAddr,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, hyper::Error> {
+    if let Some(token) = auth_token.as_deref() {
+        if !has_bearer_token(req.headers(), token) {
+            return Ok(grpc_response(Err(Status::new(
+                Status::UNAUTHENTICATED,
+                "The request needs an authorization header with the server's token",
+            ))));
+        }
+    }
+    if let Err(wait) = request_limiter.check_rate(Some(client_addr), Instant::now()) {
+        return Ok(grpc_response(Err(Status::new(
+            Status::RESOURCE_EXHAUSTED,
+            format!(
+                "Too many requests, retry in {} seconds",
+                retry_after_secs(wait)
+            ),
+        ))));
+    }
+    let permit = request_limiter.acquire().await;
+    let path = req.uri().path().to_owned();
+    let method = path.strip_prefix(SERVICE_PATH).unwrap_or_default();
+    if method == "SymbolicateStream" {
+        return Ok(symbolicate_stream(
+            req.into_body(),
+            symbol_manager,
+            metrics,
+            path,
+            permit,
+        ));
+    }
+    let start = Instant::now();
+    let body = Limited::new(req.into_body(), FRAME_HEADER_SIZE + MAX_MESSAGE_SIZE);
+    let body = match body.collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(err) => return Ok(grpc_response(Err(body_error_status(err)))),
+    };
+    let result = match method {
+        "Symbolicate" => {
+            call_json_api(
+                &body,
+                &symbol_manager,
+                "/symbolicate/v5",
+                symbolicate_request_json,
+                symbolicate_response_from_json,
+            )
+            .await
+        }
+        "QueryAsm" => {
+            call_json_api(
+                &body,
+                &symbol_manager,
+                "/asm/v1",
+                asm_request_json,
+                asm_response_from_json,
+            )
+            .await
+        }
+        "QuerySource" => {
+            call_json_api(
+                &body,
+                &symbol_manager,
+                "/source/v1",
+                source_request_json,
+                source_response_from_json,
+            )
+            .await
+        }
+        _ => Err(Status::new(
+            Status::UNIMPLEMENTED,
+            format!("Unknown method {method}"),
+        )),
+    };
+    metrics.record_request(&path, start.elapsed());
+    Ok(grpc_response(result))
+}
+
+/// The status for a request body which couldn't be read completely.
+fn body_error_status(err: Box<dyn std::error::Error + Send + Sync>) -> Status {
+    if err.is::<LengthLimitError>() {
+        message_too_large()
+    } else {
+        Status::new(Status::CANCELLED, err.to_string())
+    }
+}
+
+fn message_too_large() -> Status {
+    Status::new(
+        Status::RESOURCE_EXHAUSTED,
+        format!("Request messages can be at most {MAX_MESSAGE_SIZE} bytes"),
+    )
+}
+
+/// Decodes the request message, runs it through the JSON API at `url`, and
+/// returns the encoded response message.
+async fn call_json_api<Req: Message + Default, Resp: Message>(
+    body: &[u8],
+    symbol_manager: &SymbolManager,
+    url: &str,
+    to_json: fn(&Req) -> Value,
+    from_json: fn(&Value) -> Result<Resp, Status>,
+) -> Result<Bytes, Status> {
+    let request = Req::decode(decode_grpc_frame(body)?)
+        .map_err(|e| Status::new(Status::INVALID_ARGUMENT, e.to_string()))?;
+    let response_json = symbol_manager
+        .query_json_api(url, &to_json(&request).to_string())
+        .await;
+    let response_json: Value = serde_json::from_str(&response_json)
+        .map_err(|e| Status::new(Status::INTERNAL, e.to_string()))?;
+    if let Some(error) = response_json.get("error") {
+        let message = error.as_str().unwrap_or("Unknown error");
+        return Err(Status::new(Status::UNKNOWN, message));
+    }
+    Ok(encode_grpc_frame(&from_json(&response_json)?))
+}
+
+/// Handles a `SymbolicateStream` call. The request messages are symbolicated
+/// one after the other while the request body is still arriving, and each
+/// response message is sent as soon as it's ready. The call holds on to the
+/// request limiter's `permit` until it's done, and is recorded in the
+/// `metrics` under `path` when it's done.
+fn symbolicate_stream(
+    mut body: hyper::body::Incoming,
+    symbol_manager: Arc<SymbolManager>,
+    metrics: Arc<ServerMetrics>,
+    path: String,
+    permit: Option<OwnedSemaphorePermit>,
+) -> Response<BoxBody<Bytes, Infallible>> {
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER_MESSAGES);
+    tokio::spawn(async move {
+        let _permit = permit;
+        let start = Instant::now();
+        let status = match symbolicate_stream_messages(&mut body, &symbol_manager, &sender).await {
+            Ok(()) => Status::new(Status::OK, ""),
+            Err(status) => status,
+        };
+        metrics.record_request(&path, start.elapsed());
+        let _ = sender.send(Frame::trailers(status_trailers(&status))).await;
+    });
+    let frames = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        let frame = receiver.recv().await?;
+        Some((Ok(frame), receiver))
+    });
+    grpc_body_response(StreamBody::new(frames).boxed())
+}
+
+async fn symbolicate_stream_messages(
+    body: &mut hyper::body::Incoming,
+    symbol_manager: &SymbolManager,
+    sender: &mpsc::Sender<Frame<Bytes>>,
+) -> Result<(), Status> {
+    let mut buffer = Vec::new();
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|e| Status::new(Status::CANCELLED, e.to_string()))?;
+        let Ok(data) = frame.into_data() else {
+            continue;
+        };
+        buffer.extend_from_slice(&data);
+        while let Some(request_frame) = take_grpc_frame(&mut buffer)? {
+            let response_frame = call_json_api(
+                &request_frame,
+                symbol_manager,
+                "/symbolicate/v5",
+                symbolicate_request_json,
+                symbolicate_response_from_json,
+            )
+            .await?;
+            if sender.send(Frame::data(response_frame)).await.is_err() {
+                // The client has gone away.
+                return Ok(());
+            }
+        }
+    }
+    if !buffer.is_empty() {
+        return Err(Status::new(
+            Status::INVALID_ARGUMENT,
+            "Incomplete gRPC message frame at the end of the stream",
+        ));
+    }
+    Ok(())
+}
+
+fn grpc_response(result: Result<Bytes, Status>) -> Response<BoxBody<Bytes, Infallible>> {
+    let (message, status) = match result {
+        Ok(message) => (Some(message), Status::new(Status::OK, "")),
+        Err(status) => (None, status),
+    };
+    let frames = message
+        .map(Frame::data)
+        .into_iter()
+        .chain(std::iter::once(Frame::trailers(status_trailers(&status))))
+        .map(Ok);
+    grpc_body_response(StreamBody::new(futures_util::stream::iter(frames)).boxed())
+}
+
+fn grpc_body_response(body: BoxBody<Bytes, Infallible>) -> Response<BoxBody<Bytes, Infallible>> {
+    let mut response = Response::new(body);
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/grpc+proto"),
+    );
+    response
+}
+
+fn status_trailers(status: &Status) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", HeaderValue::from(status.code));
+    if !status.message.is_empty() {
+        let message = utf8_percent_encode(&status.message, GRPC_MESSAGE_CHARS).to_string();
+        if let Ok(message) = HeaderValue::from_str(&message) {
+            trailers.insert("grpc-message", message);
+        }
+    }
+    trailers
+}
+
+/// Returns the message in a gRPC length-prefixed message frame.
+fn decode_grpc_frame(body: &[u8]) -> Result<&[u8], Status> {
+    let invalid = || Status::new(Status::INVALID_ARGUMENT, "Invalid gRPC message frame");
+    let (&compressed, rest) = body.split_first().ok_or_else(invalid)?;
+    if compressed != 0 {
+        return Err(Status::new(
+            Status::UNIMPLEMENTED,
+            "Compressed messages are not supported",
+        ));
+    }
+    let len_bytes = rest.get(..4).ok_or_else(invalid)?;
+    let len = u32::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
+    rest[4..].get(..len).ok_or_else(invalid)
+}
+
+/// Removes the first length-prefixed message frame from `buffer` and returns
+/// it, if it has been received completely. Fails as soon as the header shows
+/// that the message is too large, so that it isn't buffered.
+fn take_grpc_frame(buffer: &mut Vec<u8>) -> Result<Option<Vec<u8>>, Status> {
+    let Some(len_bytes) = buffer.get(1..FRAME_HEADER_SIZE) else {
+        return Ok(None);
+    };
+    let message_len = u32::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
+    if message_len > MAX_MESSAGE_SIZE {
+        return Err(message_too_large());
+    }
+    let frame_len = FRAME_HEADER_SIZE + message_len;
+    if buffer.len() < frame_len {
+        return Ok(None);
+    }
+    let rest = buffer.split_off(frame_len);
+    Ok(Some(std::mem::replace(buffer, rest)))
+}
+
+fn encode_grpc_frame(message: &impl Message) -> Bytes {
+    let len = message.encoded_len();
+    let mut frame = Vec::with_capacity(5 + len);
+    frame.push(0);
+    frame.extend_from_slice(&(len as u32).to_be_bytes());
+    message.encode(&mut frame).unwrap();
+    frame.into()
+}
+
+fn symbolicate_request_json(request: &proto::SymbolicateRequest) -> Value {
+    let jobs: Vec<Value> = request
+        .jobs
+        .iter()
+        .map(|job| {
+            let memory_map: Vec<Value> = job
+                .memory_map
+                .iter()
+                .map(|lib| json!([lib.debug_name, lib.breakpad_id]))
+                .collect();
+            let stacks: Vec<Value> = job
+                .stacks
+                .iter()
+                .map(|stack| {
+                    let frames: Vec<Value> = stack
+                        .frames
+                        .iter()
+                        .map(|frame| json!([frame.module_index, frame.address]))
+                        .collect();
+                    Value::Array(frames)
+                })
+                .collect();
+            json!({ "memoryMap": memory_map, "stacks": stacks })
+        })
+        .collect();
+    json!({ "jobs": jobs })
+}
+
+fn symbolicate_response_from_json(json: &Value) -> Result<proto::SymbolicateResponse, Status> {
+    let results = array(json, "results")?
+        .iter()
+        .map(|result| {
+            let stacks = array(result, "stacks")?
+                .iter()
+                .map(|stack| {
+                    let frames = stack
+                        .as_array()
+                        .ok_or_else(|| unexpected_json("stack"))?
+                        .iter()
+                        .map(symbolicated_frame_from_json)
+                        .collect::<Result<_, _>>()?;
+                    Ok(proto::SymbolicatedStack { frames })
+                })
+                .collect::<Result<_, Status>>()?;
+            let found_modules = result
+                .get("found_modules")
+                .and_then(Value::as_object)
+                .map(|modules| {
+                    modules
+                        .iter()
+                        .filter_map(|(module, found)| Some((module.clone(), found.as_bool()?)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let mut module_errors = Vec::new();
+            if let Some(errors) = result.get("module_errors").and_then(Value::as_object) {
+                for (module, errors) in errors {
+                    for error in errors.as_array().into_iter().flatten() {
+                        module_errors.push(proto::ModuleError {
+                            module: module.clone(),
+                            name: string(error, "name").unwrap_or_default(),
+                            message: string(error, "message").unwrap_or_default(),
+                        });
+                    }
+                }
+            }
+            let module_provenance = result
+                .get("module_provenance")
+                .and_then(Value::as_object)
+                .map(|modules| {
+                    modules
+                        .iter()
+                        .map(|(module, provenance)| {
+                            let provenance = proto::ModuleProvenance {
+                                file: string(provenance, "file").unwrap_or_default(),
+                                debug_id: string(provenance, "debug_id").unwrap_or_default(),
+                                has_inline_info: provenance["has_inline_info"]
+                                    .as_bool()
+                                    .unwrap_or(false),
+                            };
+                            (module.clone(), provenance)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            Ok(proto::SymbolicateResult {
+                stacks,
+                found_modules,
+                module_errors,
+                module_provenance,
+            })
+        })
+        .collect::<Result<_, Status>>()?;
+    Ok(proto::SymbolicateResponse { results })
+}
+
+fn symbolicated_frame_from_json(frame: &Value) -> Result<proto::SymbolicatedFrame, Status> {
+    let symbol = match string(frame, "function") {
+        Some(function) => Some(proto::Symbol {
+            function,
+            function_offset: hex(frame, "function_offset")?.unwrap_or(0),
+            function_size: hex(frame, "function_size")?,
+            file: string(frame, "file"),
+            line: number(frame, "line"),
+            inlines: frame
+                .get("inlines")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .map(|inline| proto::InlinedFrame {
+                    function: string(inline, "function"),
+                    file: string(inline, "file"),
+                    line: number(inline, "line"),
+                })
+                .collect(),
+        }),
+        None => None,
+    };
+    Ok(proto::SymbolicatedFrame {
+        frame: number(frame, "frame").unwrap_or(0),
+        module_offset: hex(frame, "module_offset")?.unwrap_or(0),
+        module: string(frame, "module").unwrap_or_default(),
+        symbol,
+    })
+}
+
+fn asm_request_json(request: &proto::AsmRequest) -> Value {
+    json!({
+        "name": request.name,
+        "codeId": request.code_id,
+        "debugName": request.debug_name,
+        "debugId": request.debug_id,
+        "startAddress": format!("{:#x}", request.start_address),
+        "size": format!("{:#x}", request.size),
+        "continueUntilFunctionEnd": request.continue_until_function_end,
+    })
+}
+
+fn asm_response_from_json(json: &Value) -> Result<proto::AsmResponse, Status> {
+    let instructions = array(json, "instructions")?
+        .iter()
+        .map(|instruction| {
+            // Each instruction is an array of the form `[offset, "decoded", ...]`.
+            let (offset, decoded) = instruction
+                .as_array()
+                .and_then(|values| values.split_first())
+                .ok_or_else(|| unexpected_json("instruction"))?;
+            Ok(proto::Instruction {
+                offset: offset.as_u64().unwrap_or(0) as u32,
+                decoded: decoded
+                    .iter()
+                    .filter_map(|s| Some(s.as_str()?.to_owned()))
+                    .collect(),
+            })
+        })
+        .collect::<Result<_, Status>>()?;
+    Ok(proto::AsmResponse {
+        start_address: hex(json, "startAddress")?.unwrap_or(0),
+        size: hex(json, "size")?.unwrap_or(0),
+        arch: string(json, "arch").unwrap_or_default(),
+        syntax: array(json, "syntax")?
+            .iter()
+            .filter_map(|s| Some(s.as_str()?.to_owned()))
+            .collect(),
+        instructions,
+    })
+}
+
+fn source_request_json(request: &proto::SourceRequest) -> Value {
+    json!({
+        "debugName": request.debug_name,
+        "debugId": request.debug_id,
+        "moduleOffset": format!("{:#x}", request.module_offset),
+        "file": request.file,
+    })
+}
+
+fn source_response_from_json(json: &Value) -> Result<proto::SourceResponse, Status> {
+    Ok(proto::SourceResponse {
+        symbols_last_modified: string(json, "symbolsLastModified"),
+        source_last_modified: string(json, "sourceLastModified"),
+        file: string(json, "file").unwrap_or_default(),
+        source: string(json, "source").unwrap_or_default(),
+    })
+}
+
+fn unexpected_json(what: &str) -> Status {
+    Status::new(
+        Status::INTERNAL,
+        format!("Unexpected {what} in the JSON API response"),
+    )
+}
+
+fn array<'a>(json: &'a Value, key: &str) -> Result<&'a Vec<Value>, Status> {
+    json.get(key)
+        .and_then(Value::as_array)
+        .ok_or_else(|| unexpected_json(key))
+}
+
+fn string(json: &Value, key: &str) -> Option<String> {
+    Some(json.get(key)?.as_str()?.to_owned())
+}
+
+fn number(json: &Value, key: &str) -> Option<u32> {
+    u32::try_from(json.get(key)?.as_u64()?).ok()
+}
+
+/// Reads a `"0x"`-prefixed hex string, as used for addresses in the JSON API.
+fn hex(json: &Value, key: &str) -> Result<Option<u32>, Status> {
+    let Some(value) = json.get(key) else {
+        return Ok(None);
+    };
+    value
+        .as_str()
+        .and_then(|s| s.strip_prefix("0x"))
+        .and_then(|s| u32::from_str_radix(s, 16).ok())
+        .map(Some)
+        .ok_or_else(|| unexpected_json(key))
+}
+
+/// The messages from `proto/symbolication.proto`. The `messages_match_proto`
+/// test checks that they haven't drifted apart.
+mod proto {
+    use std::collections::HashMap;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Library {
+        #[prost(string, tag = "1")]
+        pub debug_name: String,
+        #[prost(string, tag = "2")]
+        pub breakpad_id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StackFrame {
+        #[prost(uint32, tag = "1")]
+        pub module_index: u32,
+        #[prost(uint32, tag = "2")]
+        pub address: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Stack {
+        #[prost(message, repeated, tag = "1")]
+        pub frames: Vec<StackFrame>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SymbolicateJob {
+        #[prost(message, repeated, tag = "1")]
+        pub memory_map: Vec<Library>,
+        #[prost(message, repeated, tag = "2")]
+        pub stacks: Vec<Stack>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SymbolicateRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub jobs: Vec<SymbolicateJob>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SymbolicateResponse {
+        #[prost(message, repeated, tag = "1")]
+        pub results: Vec<SymbolicateResult>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SymbolicateResult {
+        #[prost(message, repeated, tag = "1")]
+        pub stacks: Vec<SymbolicatedStack>,
+        #[prost(map = "string, bool", tag = "2")]
+        pub found_modules: HashMap<String, bool>,
+        #[prost(message, repeated, tag = "3")]
+        pub module_errors: Vec<ModuleError>,
+        #[prost(map = "string, message", tag = "4")]
+        pub module_provenance: HashMap<String, ModuleProvenance>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModuleProvenance {
+        #[prost(string, tag = "1")]
+        pub file: String,
+        #[prost(string, tag = "2")]
+        pub debug_id: String,
+        #[prost(bool, tag = "3")]
+        pub has_inline_info: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModuleError {
+        #[prost(string, tag = "1")]
+        pub module: String,
+        #[prost(string, tag = "2")]
+        pub name: String,
+        #[prost(string, tag = "3")]
+        pub message: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SymbolicatedStack {
+        #[prost(message, repeated, tag = "1")]
+        pub frames: Vec<SymbolicatedFrame>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SymbolicatedFrame {
+        #[prost(uint32, tag = "1")]
+        pub frame: u32,
+        #[prost(uint32, tag = "2")]
+        pub module_offset: u32,
+        #[prost(string, tag = "3")]
+        pub module: String,
+        #[prost(message, optional, tag = "4")]
+        pub symbol: Option<Symbol>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Symbol {
+        #[prost(string, tag = "1")]
+        pub function: String,
+        #[prost(uint32, tag = "2")]
+        pub function_offset: u32,
+        #[prost(uint32, optional, tag = "3")]
+        pub function_size: Option<u32>,
+        #[prost(string, optional, tag = "4")]
+        pub file: Option<String>,
+        #[prost(uint32, optional, tag = "5")]
+        pub line: Option<u32>,
+        #[prost(message, repeated, tag = "6")]
+        pub inlines: Vec<InlinedFrame>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InlinedFrame {
+        #[prost(string, optional, tag = "1")]
+        pub function: Option<String>,
+        #[prost(string, optional, tag = "2")]
+        pub file: Option<String>,
+        #[prost(uint32, optional, tag = "3")]
+        pub line: Option<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AsmRequest {
+        #[prost(string, optional, tag = "1")]
+        pub name: Option<String>,
+        #[prost(string, optional, tag = "2")]
+        pub code_id: Option<String>,
+        #[prost(string, optional, tag = "3")]
+        pub debug_name: Option<String>,
+        #[prost(string, optional, tag = "4")]
+        pub debug_id: Option<String>,
+        #[prost(uint32, tag = "5")]
+        pub start_address: u32,
+        #[prost(uint32, tag = "6")]
+        pub size: u32,
+        #[prost(bool, tag = "7")]
+        pub continue_until_function_end: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AsmResponse {
+        #[prost(uint32, tag = "1")]
+        pub start_address: u32,
+        #[prost(uint32, tag = "2")]
+        pub size: u32,
+        #[prost(string, tag = "3")]
+        pub arch: String,
+        #[prost(string, repeated, tag = "4")]
+        pub syntax: Vec<String>,
+        #[prost(message, repeated, tag = "5")]
+        pub instructions: Vec<Instruction>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Instruction {
+        #[prost(uint32, tag = "1")]
+        pub offset: u32,
+        #[prost(string, repeated, tag = "2")]
+        pub decoded: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SourceRequest {
+        #[prost(string, tag = "1")]
+        pub debug_name: String,
+        #[prost(string, tag = "2")]
+        pub debug_id: String,
+        #[prost(uint32, tag = "3")]
+        pub module_offset: u32,
+        #[prost(string, tag = "4")]
+        pub file: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SourceResponse {
+        #[prost(string, optional, tag = "1")]
+        pub symbols_last_modified: Option<String>,
+        #[prost(string, optional, tag = "2")]
+        pub source_last_modified: Option<String>,
+        #[prost(string, tag = "3")]
+        pub file: String,
+        #[prost(string, tag = "4")]
+        pub source: String,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grpc_frame_round_trip() {
+        let request = proto::SourceRequest {
+            debug_name: "xul.pdb".to_owned(),
+            debug_id: "44E4EC8C2F41492B9369D6B9A059577C2".to_owned(),
+            module_offset: 0x1234,
+            file: "deflate.c".to_owned(),
+        };
+        let frame = encode_grpc_frame(&request);
+        assert_eq!(frame[0], 0);
+        let message = decode_grpc_frame(&frame).unwrap();
+        assert_eq!(proto::SourceRequest::decode(message).unwrap(), request);
+        assert_eq!(
+            decode_grpc_frame(&frame[..frame.len() - 1])
+                .unwrap_err()
+                .code,
+            Status::INVALID_ARGUMENT
+        );
+
+        // Streamed frames can arrive split up or several at once.
+        let mut buffer = frame[..3].to_vec();
+        assert_eq!(take_grpc_frame(&mut buffer), Ok(None));
+        buffer.extend_from_slice(&frame[3..]);
+        buffer.extend_from_slice(&frame);
+        let taken = take_grpc_frame(&mut buffer).unwrap();
+        assert_eq!(taken.as_deref(), Some(&frame[..]));
+        let taken = take_grpc_frame(&mut buffer).unwrap();
+        assert_eq!(taken.as_deref(), Some(&frame[..]));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn large_messages_are_rejected() {
+        // The header of a frame which claims a 4 GiB message is enough to
+        // reject it.
+        let mut buffer = vec![0, 0xff, 0xff, 0xff, 0xff];
+        let status = take_grpc_frame(&mut buffer).unwrap_err();
+        assert_eq!(status.code, Status::RESOURCE_EXHAUSTED);
+
+        let mut buffer = vec![0];
+        buffer.extend_from_slice(&(MAX_MESSAGE_SIZE as u32).to_be_bytes());
+        assert_eq!(take_grpc_frame(&mut buffer), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn large_bodies_are_rejected() {
+        let body = http_body_util::Full::new(Bytes::from(vec![0; 100]));
+        let err = Limited::new(body, 99).collect().await.unwrap_err();
+        assert_eq!(body_error_status(err).code, Status::RESOURCE_EXHAUSTED);
+    }
+
+    #[test]
+    fn symbolicate_via_json_api() {
+        let request = proto::SymbolicateRequest {
+            jobs: vec![proto::SymbolicateJob {
+                memory_map: vec![proto::Library {
+                    debug_name: "xul.pdb".to_owned(),
+                    breakpad_id: "44E4EC8C2F41492B9369D6B9A059577C2".to_owned(),
+                }],
+                stacks: vec![proto::Stack {
+                    frames: vec![proto::StackFrame {
+                        module_index: 0,
+                        address: 0x1a2b,
+                    }],
+                }],
+            }],
+        };
+        assert_eq!(
+            symbolicate_request_json(&request),
+            json!({
+                "jobs": [{
+                    "memoryMap": [["xul.pdb", "44E4EC8C2F41492B9369D6B9A059577C2"]],
+                    "stacks": [[[0, 0x1a2b]]],
+                }]
+            })
+        );
+
+        let response_json = json!({
+            "results": [{
+                "stacks": [[{
+                    "frame": 0,
+                    "module_offset": "0x1a2b",
+                    "module": "xul.pdb",
+                    "function": "nsThread::Run()",
+                    "function_offset": "0x1b",
+                    "function_size": "0x40",
+                    "file": "xpcom/threads/nsThread.cpp",
+                    "line": 87,
+                    "inlines": [{ "function": "Inner()", "line": 12 }],
+                }, {
+                    "frame": 1,
+                    "module_offset": "0x10",
+                    "module": "xul.pdb",
+                }]],
+                "found_modules": { "xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2": true },
+                "module_provenance": {
+                    "xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2": {
+                        "file": "/symbols/xul.pdb",
+                        "debug_id": "44E4EC8C2F41492B9369D6B9A059577C2",
+                        "has_inline_info": true,
+                    }
+                },
+            }]
+        });
+        let response = symbolicate_response_from_json(&response_json).unwrap();
+        let result = &response.results[0];
+        assert!(result.found_modules["xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2"]);
+        let provenance = &result.module_provenance["xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2"];
+        assert_eq!(provenance.file, "/symbols/xul.pdb");
+        assert!(provenance.has_inline_info);
+        let frames = &result.stacks[0].frames;
+        assert_eq!(frames[0].module_offset, 0x1a2b);
+        let symbol = frames[0].symbol.as_ref().unwrap();
+        assert_eq!(symbol.function, "nsThread::Run()");
+        assert_eq!(symbol.function_offset, 0x1b);
+        assert_eq!(symbol.function_size, Some(0x40));
+        assert_eq!(symbol.line, Some(87));
+        assert_eq!(symbol.inlines[0].function.as_deref(), Some("Inner()"));
+        assert_eq!(symbol.inlines[0].file, None);
+        assert_eq!(frames[1].frame, 1);
+        assert_eq!(frames[1].symbol, None);
+    }
+
+    /// The fields of the messages in the .proto file, as "Message.field =
+    /// tag: type" strings.
+    fn proto_file_fields(proto: &str) -> Vec<String> {
+        let mut fields = Vec::new();
+        let mut message = None;
+        for line in proto.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix("message ") {
+                message = Some(name.trim_end_matches(" {"));
+            } else if line == "}" {
+                message = None;
+            } else if let (Some(message), Some(field)) = (message, line.strip_suffix(';')) {
+                let (declaration, tag) = field.split_once(" = ").unwrap();
+                let (field_type, name) = declaration.rsplit_once(' ').unwrap();
+                fields.push(format!("{message}.{name} = {tag}: {field_type}"));
+            }
+        }
+        fields
+    }
+
+    /// The fields of the structs in `mod proto`, in the same form as
+    /// `proto_file_fields`.
+    fn proto_mod_fields(source: &str) -> Vec<String> {
+        let start = source.find("\nmod proto {").unwrap();
+        let end = start + source[start..].find("\n}\n").unwrap();
+        let mut fields = Vec::new();
+        let mut message = None;
+        let mut attribute = "";
+        for line in source[start..end].lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix("pub struct ") {
+                message = Some(name.trim_end_matches(" {"));
+            } else if let Some(prost) = line.strip_prefix("#[prost(") {
+                attribute = prost.trim_end_matches(")]");
+            } else if let (Some(message), Some(field)) = (message, line.strip_prefix("pub ")) {
+                let (name, rust_type) = field.trim_end_matches(',').split_once(": ").unwrap();
+                let (kind, tag) = attribute.rsplit_once(", tag = ").unwrap();
+                // Message types are given by the Rust type, e.g. `Vec<Stack>`
+                // or `HashMap<String, ModuleProvenance>`.
+                let message_type = rust_type
+                    .trim_end_matches('>')
+                    .rsplit(['<', ' '])
+                    .next()
+                    .unwrap();
+                let field_type = if let Some(map) = kind.strip_prefix("map = ") {
+                    let (key, value) = map.trim_matches('"').split_once(", ").unwrap();
+                    let value = if value == "message" {
+                        message_type
+                    } else {
+                        value
+                    };
+                    format!("map<{key}, {value}>")
+                } else {
+                    match kind.split_once(", ") {
+                        Some(("message", "repeated")) => format!("repeated {message_type}"),
+                        // Singular message fields are always optional in proto3.
+                        Some(("message", "optional")) => message_type.to_owned(),
+                        Some((scalar, label)) => format!("{label} {scalar}"),
+                        None => kind.to_owned(),
+                    }
+                };
+                fields.push(format!(
+                    "{message}.{name} = {}: {field_type}",
+                    tag.trim_matches('"')
+                ));
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn messages_match_proto() {
+        let proto_fields = proto_file_fields(include_str!("../proto/symbolication.proto"));
+        let mod_fields = proto_mod_fields(include_str!("grpc_server.rs"));
+        assert!(proto_fields.contains(&"Symbol.function_size = 3: optional uint32".to_owned()));
+        assert!(mod_fields
+            .contains(&"SymbolicateResult.found_modules = 2: map<string, bool>".to_owned()));
+        assert_eq!(mod_fields, proto_fields);
+    }
+}
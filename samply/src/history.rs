@@ -0,0 +1,201 @@
+//! The recording history: an index of the profiles which samply has recorded
+//! or imported, so that older profiles can be found again with `samply list`.
+//!
+//! The index is a JSON file in samply's data directory. Profiles which have
+//! been deleted or moved are dropped from the index when it's next updated.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use fs4::FileExt;
+use platform_dirs::AppDirs;
+use serde_derive::{Deserialize, Serialize};
+
+/// The number of profiles which are kept in the index.
+const MAX_ENTRIES: usize = 1000;
+
+/// A recorded or imported profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// The absolute path of the profile file.
+    pub path: PathBuf,
+    /// The samply command line which created the profile.
+    pub command: String,
+    /// When samply was started, in seconds since the Unix epoch.
+    pub date: u64,
+    /// How long it took to record or import the profile, in seconds.
+    pub duration: f64,
+    /// The commit which was checked out in the working directory, if it's
+    /// a git repository.
+    pub git_commit: Option<String>,
+    #[serde(skip)]
+    started: Option<Instant>,
+}
+
+impl HistoryEntry {
+    /// Starts an entry for the running samply command. The profile path and
+    /// the duration are filled in by [`HistoryEntry::finish`].
+    pub fn start() -> Self {
+        let mut command: Vec<String> = std::env::args_os()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        if let Some(program) = command.first_mut() {
+            *program = "samply".to_owned();
+        }
+        let date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self {
+            path: PathBuf::new(),
+            command: command.join(" "),
+            date,
+            duration: 0.0,
+            git_commit: current_git_commit(),
+            started: Some(Instant::now()),
+        }
+    }
+
+    /// Fills in the profile path and the time since the entry was started.
+    pub fn finish(&self, profile_path: &Path) -> Self {
+        Self {
+            path: std::fs::canonicalize(profile_path).unwrap_or_else(|_| profile_path.to_owned()),
+            duration: self
+                .started
+                .map_or(0.0, |started| started.elapsed().as_secs_f64()),
+            ..self.clone()
+        }
+    }
+}
+
+fn current_git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let commit = String::from_utf8(output.stdout).ok()?;
+    let commit = commit.trim();
+    (output.status.success() && !commit.is_empty()).then(|| commit.to_owned())
+}
+
+/// The index file.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    /// The index in samply's data directory.
+    pub fn open() -> Option<Self> {
+        let data_dir = AppDirs::new(Some("samply"), false)?.data_dir;
+        Some(Self::at(data_dir.join("history.json")))
+    }
+
+    pub fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The profiles which still exist, oldest first.
+    pub fn entries(&self) -> std::io::Result<Vec<HistoryEntry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        FileExt::lock_shared(&file)?;
+        let mut entries = read_entries(&file)?;
+        entries.retain(|entry| entry.path.exists());
+        Ok(entries)
+    }
+
+    /// Adds a profile to the index. An older entry for the same file is
+    /// replaced.
+    pub fn add(&self, entry: HistoryEntry) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        // Other samply processes may be adding their profiles at the same time.
+        file.lock_exclusive()?;
+        let mut entries = read_entries(&file)?;
+        entries.retain(|e| e.path != entry.path && e.path.exists());
+        entries.push(entry);
+        let excess = entries.len().saturating_sub(MAX_ENTRIES);
+        entries.drain(..excess);
+
+        file.set_len(0)?;
+        file.rewind()?;
+        serde_json::to_writer(&mut file, &entries)?;
+        file.flush()
+    }
+}
+
+fn read_entries(file: &File) -> std::io::Result<Vec<HistoryEntry>> {
+    if file.metadata()?.len() == 0 {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+/// Formats the seconds since the Unix epoch as a UTC date and time, like
+/// `2024-05-01 13:45`.
+pub fn format_date(secs_since_epoch: u64) -> String {
+    let days = (secs_since_epoch / 86400) as i64;
+    let secs_of_day = secs_since_epoch % 86400;
+    // Howard Hinnant's days_from_civil, in reverse.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn add_and_list_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::at(dir.path().join("data/history.json"));
+        assert!(history.entries().unwrap().is_empty());
+
+        let profile = dir.path().join("profile.json");
+        std::fs::write(&profile, "{}").unwrap();
+        let entry = HistoryEntry {
+            path: PathBuf::new(),
+            command: "samply record ./app".to_owned(),
+            date: 1714571100,
+            duration: 0.0,
+            git_commit: Some("0123456789ab".to_owned()),
+            started: None,
+        };
+        history.add(entry.finish(&profile)).unwrap();
+        history.add(entry.finish(&profile)).unwrap();
+        let gone = dir.path().join("gone.json");
+        history.add(entry.finish(&gone)).unwrap();
+
+        let entries = history.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, profile.canonicalize().unwrap());
+        assert_eq!(entries[0].command, "samply record ./app");
+        assert_eq!(format_date(entries[0].date), "2024-05-01 13:45");
+    }
+}
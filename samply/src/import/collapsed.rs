@@ -0,0 +1,197 @@
+//! Converts folded stacks, as written by async-profiler's `collapsed` output
+//! format and by flamegraph.pl's stackcollapse scripts, into the Firefox
+//! Profiler format.
+//!
+//! Each line is a semicolon-separated stack, from the root to the leaf,
+//! followed by a space and a sample count. Folded stacks have no timestamps,
+//! so each thread's samples are laid out one after the other.
+//!
+//! async-profiler annotates frames with a suffix when it's run with the
+//! `ann` option: `_[j]` for JIT-compiled, `_[i]` for inlined, `_[0]` for
+//! interpreted and `_[1]` for C1-compiled Java methods, and `_[k]` for kernel
+//! functions. With the `threads` option, each stack starts with a
+//! `[name tid=123]` frame. Both are understood here. JFR recordings can be
+//! converted to this format with async-profiler's `jfrconv`.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+use std::time::SystemTime;
+
+use fxprof_processed_profile::{
+    CategoryColor, CategoryHandle, CategoryPairHandle, CpuDelta, Frame, FrameFlags, FrameInfo,
+    Profile, ReferenceTimestamp, SamplingInterval, ThreadHandle, Timestamp,
+};
+
+use crate::shared::recording_props::ProfileCreationProps;
+
+/// async-profiler's default sampling interval.
+const DEFAULT_INTERVAL_MILLIS: u64 = 10;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Line {0} doesn't end with a sample count")]
+    MissingCount(usize),
+}
+
+/// Whether the file name looks like folded stacks, e.g. `profile.collapsed`
+/// or `profile.folded`.
+pub fn is_collapsed_file_name(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    [".collapsed", ".folded"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+pub fn convert(
+    reader: impl BufRead,
+    file_mod_time: Option<SystemTime>,
+    profile_creation_props: ProfileCreationProps,
+) -> Result<Profile, Error> {
+    let reference_timestamp = match file_mod_time {
+        Some(time) => ReferenceTimestamp::from_system_time(time),
+        None => ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+    };
+    let interval = SamplingInterval::from_millis(DEFAULT_INTERVAL_MILLIS);
+    let mut profile = Profile::new(
+        &profile_creation_props.profile_name,
+        reference_timestamp,
+        interval,
+    );
+    let java_category: CategoryPairHandle =
+        profile.add_category("Java", CategoryColor::Green).into();
+    let kernel_category: CategoryPairHandle =
+        profile.add_category("Kernel", CategoryColor::Orange).into();
+    let process = profile.add_process(
+        &profile_creation_props.profile_name,
+        0,
+        Timestamp::from_nanos_since_reference(0),
+    );
+
+    // The thread handles by thread label, and the time after each thread's
+    // last sample.
+    let mut threads: HashMap<String, (ThreadHandle, u64)> = HashMap::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let (stack, count) = line
+            .rsplit_once(' ')
+            .and_then(|(stack, count)| Some((stack, count.parse::<u64>().ok()?)))
+            .ok_or(Error::MissingCount(index + 1))?;
+        let mut frame_names = stack.split(';').peekable();
+        let thread_label = frame_names
+            .next_if(|name| name.starts_with('[') && name.contains(" tid="))
+            .unwrap_or("");
+
+        let thread_count = threads.len();
+        let (thread, next_sample_time) =
+            threads.entry(thread_label.to_owned()).or_insert_with(|| {
+                let (name, tid) = parse_thread_label(thread_label);
+                let is_main = thread_count == 0;
+                let start_time = Timestamp::from_nanos_since_reference(0);
+                let thread = profile.add_thread(process, tid, start_time, is_main);
+                if let Some(name) = name {
+                    profile.set_thread_name(thread, name);
+                }
+                (thread, 0)
+            });
+
+        let frames: Vec<FrameInfo> = frame_names
+            .map(|name| {
+                let (name, category_pair) = match name.rsplit_once("_[") {
+                    Some((function, "j]" | "i]" | "0]" | "1]")) => (function, java_category),
+                    Some((function, "k]")) => (function, kernel_category),
+                    _ => (name, CategoryHandle::OTHER.into()),
+                };
+                FrameInfo {
+                    frame: Frame::Label(profile.intern_string(name)),
+                    category_pair,
+                    flags: FrameFlags::empty(),
+                }
+            })
+            .collect();
+        let timestamp = Timestamp::from_nanos_since_reference(*next_sample_time);
+        *next_sample_time += interval.nanos() * count;
+        let weight = i32::try_from(count).unwrap_or(i32::MAX);
+        profile.add_sample(
+            *thread,
+            timestamp,
+            frames.into_iter(),
+            CpuDelta::ZERO,
+            weight,
+        );
+    }
+    Ok(profile)
+}
+
+/// Splits async-profiler's `[name tid=123]` thread frame into the name and
+/// the tid.
+fn parse_thread_label(label: &str) -> (Option<&str>, u32) {
+    let Some(label) = label.strip_prefix('[').and_then(|l| l.strip_suffix(']')) else {
+        return (None, 0);
+    };
+    match label.rsplit_once(" tid=") {
+        Some((name, tid)) => (Some(name), tid.parse().unwrap_or(0)),
+        None => (Some(label), 0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shared::recursion_folding::RecursionFolding;
+
+    #[test]
+    fn convert_async_profiler_output() {
+        let collapsed = "\
+[main tid=101];java/lang/Thread.run_[j];App.work_[j];App.hash_[i] 3
+[main tid=101];java/lang/Thread.run_[j];App.work_[j];write;ksys_write_[k] 2
+[GC Thread#0 tid=102];GCTaskThread::run 5
+";
+        let props = ProfileCreationProps {
+            profile_name: "app".to_owned(),
+            main_thread_only: false,
+            reuse_threads: false,
+            recursion_folding: RecursionFolding::None,
+            unlink_aux_files: false,
+            create_per_cpu_threads: false,
+            override_arch: None,
+            unstable_presymbolicate: false,
+            coreclr: Default::default(),
+            unknown_event_markers: false,
+            post_processing: Default::default(),
+        };
+        let profile = convert(collapsed.as_bytes(), None, props).unwrap();
+        let json = serde_json::to_value(&profile).unwrap();
+
+        let main = &json["threads"][0];
+        assert_eq!(main["tid"], "101");
+        assert_eq!(main["samples"]["weight"], serde_json::json!([3, 2]));
+        assert_eq!(main["samples"]["time"], serde_json::json!([0.0, 30.0]));
+        let names = crate::profile_tools::frame_names(main);
+        let stack = main["samples"]["stack"][1].as_u64().unwrap() as usize;
+        let prefixes: Vec<_> =
+            crate::profile_tools::column_values(main.get("stackTable"), "prefix").collect();
+        let frames: Vec<_> =
+            crate::profile_tools::column_values(main.get("stackTable"), "frame").collect();
+        let stack_names: Vec<&str> = crate::profile_tools::stack_frames(&prefixes, &frames, stack)
+            .into_iter()
+            .map(|frame| names[frame].as_str())
+            .collect();
+        assert_eq!(
+            stack_names,
+            ["java/lang/Thread.run", "App.work", "write", "ksys_write"]
+        );
+
+        let gc = &json["threads"][1];
+        assert_eq!(gc["name"], "GC Thread#0");
+        assert_eq!(gc["tid"], "102");
+        assert_eq!(gc["samples"]["weight"], serde_json::json!([5]));
+    }
+}
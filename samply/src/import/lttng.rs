@@ -0,0 +1,497 @@
+//! Converts LTTng traces into markers, either in a new profile or added to an
+//! existing profile.
+//!
+//! LTTng writes its traces in the Common Trace Format (CTF). Rather than
+//! decoding CTF ourselves, we read the text output of `babeltrace2`, the
+//! reference CTF reader, which looks like this:
+//!
+//! ```text
+//! [1718000000.123456789] (+0.000012345) myhost ust_app:request_start: { cpu_id = 1 }, { vpid = 1234, vtid = 1235, procname = "app" }, { id = 7, path = "/index.html" }
+//! ```
+//!
+//! The trace needs to be printed with `babeltrace2 --clock-seconds TRACE_DIR`
+//! so that the timestamps are absolute, which is needed for lining up the
+//! events with the samples of a profile. Events are assigned to threads using
+//! the `vtid` / `tid` and `vpid` / `pid` context fields, which can be added
+//! with `lttng add-context`. Events without thread information are assigned
+//! to one thread per CPU.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::SystemTime;
+
+use fxprof_processed_profile::{
+    CategoryColor, MarkerDynamicField, MarkerFieldFormat, MarkerLocation, MarkerSchema,
+    MarkerSchemaField, MarkerTiming, Profile, ProfilerMarker, ReferenceTimestamp, SamplingInterval,
+    ThreadHandle, Timestamp,
+};
+use serde_json::{json, Value};
+
+use crate::profile_tools;
+use crate::shared::recording_props::ProfileCreationProps;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Line {0} is not a babeltrace2 event line")]
+    InvalidLine(usize),
+
+    #[error(
+        "The trace has time-of-day timestamps, which can't be lined up with a profile. \
+         Print the trace with babeltrace2 --clock-seconds."
+    )]
+    NoAbsoluteTimestamps,
+
+    #[error("Invalid profile: {0}")]
+    Profile(#[from] profile_tools::Error),
+}
+
+/// A single event from the trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    /// Nanoseconds since the Unix epoch, or since midnight if the trace was
+    /// printed without `--clock-seconds`.
+    pub timestamp_ns: u64,
+    /// The event name, e.g. `ust_app:request_start` or `sched_switch`.
+    pub name: String,
+    pub cpu: Option<u32>,
+    pub pid: Option<u32>,
+    pub tid: Option<u32>,
+    pub procname: Option<String>,
+    /// The remaining context and payload fields, as printed by babeltrace2.
+    pub fields: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub events: Vec<TraceEvent>,
+    /// Whether the timestamps are relative to the Unix epoch.
+    pub has_absolute_timestamps: bool,
+}
+
+/// Whether the directory or file is a CTF trace, i.e. something which needs
+/// to be converted with babeltrace2 before it can be imported.
+pub fn is_ctf_trace_path(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == "metadata") || path.join("metadata").is_file()
+}
+
+/// Whether the file starts with an event line in babeltrace2's text format.
+pub fn is_babeltrace_text_file(mut file: impl Read + Seek) -> bool {
+    let mut first_line = String::new();
+    let matches = BufReader::new((&mut file).take(4096))
+        .read_line(&mut first_line)
+        .is_ok()
+        && parse_event_line(first_line.trim_end()).is_some();
+    let _ = file.seek(SeekFrom::Start(0));
+    matches
+}
+
+pub fn read_trace(reader: impl BufRead) -> Result<Trace, Error> {
+    let mut events = Vec::new();
+    let mut has_absolute_timestamps = true;
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let (event, is_absolute) = parse_event_line(line).ok_or(Error::InvalidLine(index + 1))?;
+        has_absolute_timestamps &= is_absolute;
+        events.push(event);
+    }
+    events.sort_by_key(|event| event.timestamp_ns);
+    Ok(Trace {
+        events,
+        has_absolute_timestamps,
+    })
+}
+
+/// Creates a profile with one marker per event.
+pub fn convert(
+    trace: &Trace,
+    file_mod_time: Option<SystemTime>,
+    profile_creation_props: ProfileCreationProps,
+) -> Profile {
+    let first_event_ns = trace.events.first().map_or(0, |event| event.timestamp_ns);
+    let reference_timestamp = match (trace.has_absolute_timestamps, file_mod_time) {
+        (true, _) => ReferenceTimestamp::from_millis_since_unix_epoch(first_event_ns as f64 / 1e6),
+        (false, Some(time)) => ReferenceTimestamp::from_system_time(time),
+        (false, None) => ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+    };
+    let mut profile = Profile::new(
+        &profile_creation_props.profile_name,
+        reference_timestamp,
+        SamplingInterval::from_millis(1),
+    );
+    let category = profile.add_category("LTTng", CategoryColor::Purple);
+    let start_time = Timestamp::from_nanos_since_reference(0);
+
+    let mut processes = HashMap::new();
+    let mut threads: HashMap<ThreadKey, ThreadHandle> = HashMap::new();
+    for event in &trace.events {
+        let key = ThreadKey::for_event(event);
+        let thread = *threads.entry(key).or_insert_with(|| {
+            let pid = event.pid.unwrap_or(0);
+            let process = *processes.entry(pid).or_insert_with(|| {
+                let name = match (&event.procname, event.pid) {
+                    (Some(procname), _) => procname.clone(),
+                    (None, Some(pid)) => format!("Process {pid}"),
+                    (None, None) => "Kernel".to_owned(),
+                };
+                profile.add_process(&name, pid, start_time)
+            });
+            let (tid, is_main) = match key {
+                ThreadKey::Tid(tid) => (tid, Some(tid) == event.pid),
+                ThreadKey::Cpu(cpu) => (cpu, false),
+            };
+            let thread = profile.add_thread(process, tid, start_time, is_main);
+            if let ThreadKey::Cpu(cpu) = key {
+                profile.set_thread_name(thread, &format!("CPU {cpu}"));
+            } else if let Some(procname) = &event.procname {
+                profile.set_thread_name(thread, procname);
+            }
+            thread
+        });
+        let timestamp = Timestamp::from_nanos_since_reference(event.timestamp_ns - first_event_ns);
+        profile.add_marker(
+            thread,
+            category,
+            &event.name,
+            TraceEventMarker::new(event),
+            MarkerTiming::Instant(timestamp),
+        );
+    }
+    profile
+}
+
+/// Adds one marker per event to the matching thread of an existing profile,
+/// using the profile's start time to line up the timestamps. Events are
+/// matched to threads by tid, or to the main thread of their process if
+/// the profile doesn't have their thread. Returns the number of events which
+/// didn't match any thread.
+pub fn add_markers_to_profile(profile: &mut Value, trace: &Trace) -> Result<usize, Error> {
+    if !trace.has_absolute_timestamps {
+        return Err(Error::NoAbsoluteTimestamps);
+    }
+    let start_time =
+        profile["meta"]["startTime"]
+            .as_f64()
+            .ok_or(profile_tools::Error::InvalidProfile(
+                "missing meta.startTime",
+            ))?;
+    let category = profile["meta"]["categories"]
+        .as_array()
+        .and_then(|categories| categories.iter().position(|c| c["name"] == "Other"))
+        .unwrap_or(0);
+
+    let threads = profile_tools::threads_mut(profile)?;
+    let thread_index_by_tid: HashMap<String, usize> = threads
+        .iter()
+        .enumerate()
+        .filter_map(|(index, thread)| Some((id_string(&thread["tid"])?, index)))
+        .collect();
+    let main_thread_index_by_pid: HashMap<String, usize> = threads
+        .iter()
+        .enumerate()
+        .filter(|(_, thread)| thread["isMainThread"] == true)
+        .filter_map(|(index, thread)| Some((id_string(&thread["pid"])?, index)))
+        .collect();
+
+    let mut unmatched_event_count = 0;
+    for event in &trace.events {
+        let thread_index = event
+            .tid
+            .and_then(|tid| thread_index_by_tid.get(&tid.to_string()))
+            .or_else(|| main_thread_index_by_pid.get(&event.pid?.to_string()));
+        let Some(&thread_index) = thread_index else {
+            unmatched_event_count += 1;
+            continue;
+        };
+        // Subtract the whole milliseconds separately, so that the large
+        // timestamps don't lose the sub-millisecond precision.
+        let whole_ms = (event.timestamp_ns / 1_000_000) as f64 - start_time.floor();
+        let fraction_ms = (event.timestamp_ns % 1_000_000) as f64 / 1e6 - start_time.fract();
+        let time = whole_ms + fraction_ms;
+        profile_tools::push_thread_marker(
+            &mut threads[thread_index],
+            &event.name,
+            category,
+            TraceEventMarker::new(event).json_marker_data(),
+            time,
+            None,
+        );
+    }
+    profile_tools::add_marker_schema(profile, TraceEventMarker::schema());
+    Ok(unmatched_event_count)
+}
+
+/// Returns a tid or pid from the profile JSON, which can be a string or a number.
+fn id_string(id: &Value) -> Option<String> {
+    match id {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ThreadKey {
+    Tid(u32),
+    Cpu(u32),
+}
+
+impl ThreadKey {
+    fn for_event(event: &TraceEvent) -> Self {
+        match (event.tid, event.pid) {
+            (Some(tid), _) | (None, Some(tid)) => ThreadKey::Tid(tid),
+            (None, None) => ThreadKey::Cpu(event.cpu.unwrap_or(0)),
+        }
+    }
+}
+
+/// Parses a line of babeltrace2's text output. Also returns whether the
+/// timestamp is relative to the Unix epoch, rather than to midnight.
+fn parse_event_line(line: &str) -> Option<(TraceEvent, bool)> {
+    let (timestamp, rest) = line.strip_prefix('[')?.split_once("] ")?;
+    let (timestamp_ns, is_absolute) = parse_timestamp(timestamp)?;
+    // Skip the delta to the previous event, e.g. "(+0.000012345)".
+    let rest = match rest.strip_prefix("(+") {
+        Some(rest) => rest.split_once(") ")?.1,
+        None => rest,
+    };
+    // The event name may be preceded by the hostname, and it ends with a
+    // colon, e.g. "myhost ust_app:request_start:".
+    let (header, body) = match rest.split_once(": {") {
+        Some((header, body)) => (header, Some(body)),
+        None => (rest.strip_suffix(':')?, None),
+    };
+    let name = header.rsplit(' ').next()?.to_owned();
+
+    let mut event = TraceEvent {
+        timestamp_ns,
+        name,
+        cpu: None,
+        pid: None,
+        tid: None,
+        procname: None,
+        fields: Vec::new(),
+    };
+    if let Some(body) = body {
+        // The body consists of braced field lists: "a = 1 }, { b = 2 }".
+        for section in split_top_level(&format!("{{{body}"), ", ") {
+            if section == "{ }" {
+                continue;
+            }
+            let section = section.strip_prefix("{ ")?.strip_suffix(" }")?;
+            for field in split_top_level(section, ", ") {
+                let (key, value) = field.split_once(" = ")?;
+                match key {
+                    "cpu_id" => event.cpu = value.parse().ok(),
+                    "vpid" | "pid" => event.pid = value.parse().ok(),
+                    "vtid" | "tid" => event.tid = value.parse().ok(),
+                    "procname" => event.procname = Some(unquote(value).to_owned()),
+                    _ => event
+                        .fields
+                        .push((key.to_owned(), unquote(value).to_owned())),
+                }
+            }
+        }
+    }
+    Some((event, is_absolute))
+}
+
+/// Parses "1718000000.123456789" (with `--clock-seconds`) or
+/// "12:34:56.123456789".
+fn parse_timestamp(timestamp: &str) -> Option<(u64, bool)> {
+    let (whole, fraction) = timestamp.split_once('.')?;
+    let nanos: u64 = format!("{fraction:0<9}").get(..9)?.parse().ok()?;
+    let (seconds, is_absolute) = match whole.split(':').collect::<Vec<_>>()[..] {
+        [seconds] => (seconds.parse::<u64>().ok()?, true),
+        [hours, minutes, seconds] => {
+            let hours: u64 = hours.parse().ok()?;
+            let minutes: u64 = minutes.parse().ok()?;
+            let seconds: u64 = seconds.parse().ok()?;
+            (hours * 3600 + minutes * 60 + seconds, false)
+        }
+        _ => return None,
+    };
+    Some((seconds * 1_000_000_000 + nanos, is_absolute))
+}
+
+/// Splits `s` at each `separator` which is neither inside a string nor
+/// inside braces or brackets.
+fn split_top_level<'a>(s: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut part_start = 0;
+    for (index, c) in s.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            _ if depth == 0 && s[index..].starts_with(separator) && index >= part_start => {
+                parts.push(&s[part_start..index]);
+                part_start = index + separator.len();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[part_start..]);
+    parts
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// A marker for an event from an LTTng trace.
+struct TraceEventMarker {
+    name: String,
+    fields: String,
+}
+
+impl TraceEventMarker {
+    fn new(event: &TraceEvent) -> Self {
+        let fields: Vec<String> = event
+            .fields
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        Self {
+            name: event.name.clone(),
+            fields: fields.join(", "),
+        }
+    }
+}
+
+impl ProfilerMarker for TraceEventMarker {
+    const MARKER_TYPE_NAME: &'static str = "LTTngEvent";
+
+    fn json_marker_data(&self) -> Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "name": self.name,
+            "fields": self.fields,
+        })
+    }
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![MarkerLocation::MarkerChart, MarkerLocation::MarkerTable],
+            chart_label: Some("{marker.data.name}"),
+            tooltip_label: Some("{marker.data.name} {marker.data.fields}"),
+            table_label: Some("{marker.data.name} {marker.data.fields}"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "name",
+                    label: "Event",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "fields",
+                    label: "Fields",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TRACE: &str = r#"[1718000000.000100000] (+?.?????????) myhost ust_app:request_start: { cpu_id = 1 }, { vpid = 1234, vtid = 1235, procname = "app" }, { id = 7, path = "/a, b", span = { begin = 1, end = 2 } }
+[1718000000.000300000] (+0.000200000) myhost ust_app:request_end: { cpu_id = 1 }, { vpid = 1234, vtid = 1235, procname = "app" }, { id = 7 }
+[1718000000.000200000] (+0.000100000) myhost irq_handler_entry: { cpu_id = 3 }, { irq = 16, name = "eth0" }
+"#;
+
+    #[test]
+    fn read_babeltrace_output() {
+        assert!(is_babeltrace_text_file(std::io::Cursor::new(TRACE)));
+        assert!(!is_babeltrace_text_file(std::io::Cursor::new(
+            "main;foo 3\n"
+        )));
+
+        let trace = read_trace(TRACE.as_bytes()).unwrap();
+        assert!(trace.has_absolute_timestamps);
+        let names: Vec<_> = trace.events.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "ust_app:request_start",
+                "irq_handler_entry",
+                "ust_app:request_end"
+            ]
+        );
+        let start = &trace.events[0];
+        assert_eq!(start.timestamp_ns, 1_718_000_000_000_100_000);
+        assert_eq!(
+            (start.pid, start.tid, start.cpu),
+            (Some(1234), Some(1235), Some(1))
+        );
+        assert_eq!(start.procname.as_deref(), Some("app"));
+        assert_eq!(
+            start.fields,
+            [
+                ("id".to_owned(), "7".to_owned()),
+                ("path".to_owned(), "/a, b".to_owned()),
+                ("span".to_owned(), "{ begin = 1, end = 2 }".to_owned()),
+            ]
+        );
+        assert_eq!(trace.events[1].tid, None);
+        assert_eq!(trace.events[1].cpu, Some(3));
+    }
+
+    #[test]
+    fn add_markers_to_existing_profile() {
+        let trace = read_trace(TRACE.as_bytes()).unwrap();
+        let mut profile = json!({
+            "meta": {
+                "startTime": 1_718_000_000_000.0,
+                "categories": [{ "name": "Other" }],
+            },
+            "threads": [
+                { "pid": "1234", "tid": "1234", "isMainThread": true, "stringArray": [] },
+                { "pid": "1234", "tid": "1235", "isMainThread": false, "stringArray": [] },
+            ],
+        });
+        let unmatched = add_markers_to_profile(&mut profile, &trace).unwrap();
+        assert_eq!(unmatched, 1);
+
+        let markers = &profile["threads"][1]["markers"];
+        assert_eq!(markers["length"], 2);
+        assert_eq!(markers["startTime"], json!([0.1, 0.3]));
+        assert_eq!(markers["phase"], json!([0, 0]));
+        assert_eq!(
+            markers["data"][0]["fields"],
+            "id=7, path=/a, b, span={ begin = 1, end = 2 }"
+        );
+        assert_eq!(
+            profile["threads"][1]["stringArray"],
+            json!(["ust_app:request_start", "ust_app:request_end"])
+        );
+        assert!(profile["threads"][0]["markers"].is_null());
+        assert_eq!(profile["meta"]["markerSchema"][0]["name"], "LTTngEvent");
+    }
+}
@@ -0,0 +1,665 @@
+//! Converts minidumps, as written by Breakpad, Crashpad and Windows, into a
+//! profile with one sample per thread: the thread's stack at the time of the
+//! crash. The Firefox Profiler then works as a crash stack viewer, and the
+//! usual symbolication finds the symbols for the modules in the dump.
+//!
+//! Stacks are walked with frame pointers, starting from each thread's saved
+//! registers and reading the stack memory which was captured in the dump.
+//! The crashing thread starts from the exception's registers.
+//!
+//! Only the streams which are needed for this are read: the system info, the
+//! thread list, the module list, the exception, the thread names and the misc
+//! info, for the process ID.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use byteorder::{ByteOrder, LittleEndian};
+use debugid::DebugId;
+use framehop::aarch64::{CacheAarch64, UnwindRegsAarch64, UnwinderAarch64};
+use framehop::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+use framehop::{FrameAddress, Unwinder};
+use fxprof_processed_profile::{
+    CategoryHandle, CpuDelta, Frame, FrameFlags, FrameInfo, LibraryInfo, MarkerDynamicField,
+    MarkerFieldFormat, MarkerLocation, MarkerSchema, MarkerSchemaField, MarkerStaticField,
+    MarkerTiming, Profile, ProfilerMarker, ReferenceTimestamp, SamplingInterval, Timestamp,
+};
+use serde_json::json;
+use wholesym::samply_symbols::DebugIdExt;
+use wholesym::{CodeId, ElfBuildId, PeCodeId};
+
+use crate::shared::recording_props::ProfileCreationProps;
+
+const MAGIC: &[u8] = b"MDMP";
+
+const THREAD_LIST_STREAM: u32 = 3;
+const MODULE_LIST_STREAM: u32 = 4;
+const EXCEPTION_STREAM: u32 = 6;
+const SYSTEM_INFO_STREAM: u32 = 7;
+const MISC_INFO_STREAM: u32 = 15;
+const THREAD_NAME_LIST_STREAM: u32 = 24;
+
+const PROCESSOR_ARCHITECTURE_AMD64: u16 = 9;
+const PROCESSOR_ARCHITECTURE_ARM64: u16 = 12;
+/// Written by older versions of Breakpad.
+const PROCESSOR_ARCHITECTURE_ARM64_BREAKPAD: u16 = 0x8003;
+
+/// The CodeView signature of PDB 7.0 debug info, used by PE modules.
+const CV_SIGNATURE_RSDS: u32 = u32::from_le_bytes(*b"RSDS");
+/// The CodeView signature which Breakpad uses for ELF build IDs.
+const CV_SIGNATURE_ELF: u32 = u32::from_le_bytes(*b"BpEL");
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Not a minidump file")]
+    NoMagic,
+
+    #[error("The minidump is truncated at offset {0}")]
+    Truncated(u64),
+
+    #[error("The minidump has no {0} stream")]
+    MissingStream(&'static str),
+
+    #[error("Minidumps for processor architecture {0} are not supported")]
+    UnsupportedArchitecture(u16),
+}
+
+/// Whether the file starts with the minidump signature.
+/// The file position is restored afterwards.
+pub fn is_minidump_file(mut file: impl Read + Seek) -> bool {
+    let mut magic = [0; MAGIC.len()];
+    let matches = file.read_exact(&mut magic).is_ok() && magic == MAGIC;
+    let _ = file.seek(SeekFrom::Start(0));
+    matches
+}
+
+pub fn convert(
+    mut reader: impl Read,
+    profile_creation_props: ProfileCreationProps,
+) -> Result<Profile, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    if !data.starts_with(MAGIC) {
+        return Err(Error::NoMagic);
+    }
+    convert_minidump(&Minidump(&data), &profile_creation_props)
+}
+
+fn convert_minidump(
+    dump: &Minidump,
+    profile_creation_props: &ProfileCreationProps,
+) -> Result<Profile, Error> {
+    let stream_count = dump.u32(8)?;
+    let directory_rva = dump.u32(12)?;
+    let time_date_stamp = dump.u32(20)?;
+    let mut streams = HashMap::new();
+    for i in 0..stream_count {
+        let entry = u64::from(directory_rva) + u64::from(i) * 12;
+        let stream_type = dump.u32(entry)?;
+        let size = dump.u32(entry + 4)?;
+        let rva = dump.u32(entry + 8)?;
+        streams.entry(stream_type).or_insert((rva, size));
+    }
+    let stream = |stream_type: u32| streams.get(&stream_type).map(|&(rva, _)| u64::from(rva));
+
+    let system_info = stream(SYSTEM_INFO_STREAM).ok_or(Error::MissingStream("system info"))?;
+    let architecture = dump.u16(system_info)?;
+    let thread_list = stream(THREAD_LIST_STREAM).ok_or(Error::MissingStream("thread list"))?;
+
+    let modules = match stream(MODULE_LIST_STREAM) {
+        Some(rva) => read_modules(dump, rva)?,
+        None => Vec::new(),
+    };
+    let thread_names = match stream(THREAD_NAME_LIST_STREAM) {
+        Some(rva) => read_thread_names(dump, rva)?,
+        None => HashMap::new(),
+    };
+    let exception = match stream(EXCEPTION_STREAM) {
+        Some(rva) => Some(read_exception(dump, rva)?),
+        None => None,
+    };
+    // MINIDUMP_MISC_INFO: SizeOfInfo, Flags1, ProcessId, which is only valid
+    // if the first flag is set.
+    let pid = match stream(MISC_INFO_STREAM) {
+        Some(rva) if dump.u32(rva + 4)? & 1 != 0 => dump.u32(rva + 8)?,
+        _ => 0,
+    };
+
+    let mut profile = Profile::new(
+        &profile_creation_props.profile_name,
+        ReferenceTimestamp::from_duration_since_unix_epoch(Duration::from_secs(
+            time_date_stamp.into(),
+        )),
+        SamplingInterval::from_millis(1),
+    );
+    let crash_time = Timestamp::from_nanos_since_reference(0);
+    // The executable is the first module.
+    let process_name = modules
+        .first()
+        .map(|module| file_name(&module.path))
+        .unwrap_or(&profile_creation_props.profile_name);
+    let process = profile.add_process(process_name, pid, crash_time);
+    for module in &modules {
+        let lib = profile.add_lib(LibraryInfo {
+            name: file_name(&module.path).to_owned(),
+            debug_name: module.debug_name.clone(),
+            path: module.path.clone(),
+            debug_path: module.debug_path.clone(),
+            debug_id: module.debug_id,
+            code_id: module.code_id.clone(),
+            arch: None,
+            symbol_table: None,
+        });
+        profile.add_lib_mapping(
+            process,
+            lib,
+            module.base,
+            module.base + u64::from(module.size),
+            0,
+        );
+    }
+
+    let thread_count = dump.u32(thread_list)?;
+    let crashed_tid = exception.as_ref().map(|exception| exception.tid);
+    for i in 0..u64::from(thread_count) {
+        // MINIDUMP_THREAD is 48 bytes: ThreadId, SuspendCount, PriorityClass,
+        // Priority, Teb, Stack (a memory descriptor) and ThreadContext.
+        let entry = thread_list + 4 + i * 48;
+        let tid = dump.u32(entry)?;
+        let stack_start = dump.u64(entry + 24)?;
+        let stack = dump.location(entry + 32)?;
+        let mut context = dump.location(entry + 40)?;
+        let is_crashed_thread = Some(tid) == crashed_tid;
+        if let (true, Some(exception)) = (is_crashed_thread, &exception) {
+            context = exception.context;
+        }
+
+        let is_main = is_crashed_thread || (crashed_tid.is_none() && i == 0);
+        let thread = profile.add_thread(process, tid, crash_time, is_main);
+        if let Some(name) = thread_names.get(&tid) {
+            profile.set_thread_name(thread, name);
+        }
+
+        let read_stack = |address: u64| {
+            let offset = address.checked_sub(stack_start).ok_or(())?;
+            let offset = usize::try_from(offset).map_err(|_| ())?;
+            let bytes = stack
+                .get(offset..)
+                .filter(|bytes| bytes.len() >= 8)
+                .ok_or(())?;
+            Ok(LittleEndian::read_u64(bytes))
+        };
+        let frame_addresses = match architecture {
+            PROCESSOR_ARCHITECTURE_AMD64 => walk_stack_x86_64(context, read_stack),
+            PROCESSOR_ARCHITECTURE_ARM64 | PROCESSOR_ARCHITECTURE_ARM64_BREAKPAD => {
+                walk_stack_aarch64(context, read_stack)
+            }
+            architecture => return Err(Error::UnsupportedArchitecture(architecture)),
+        };
+        let frames: Vec<FrameInfo> = frame_addresses
+            .into_iter()
+            .rev()
+            .map(|address| FrameInfo {
+                frame: match address {
+                    FrameAddress::InstructionPointer(address) => Frame::InstructionPointer(address),
+                    FrameAddress::ReturnAddress(address) => Frame::ReturnAddress(address.into()),
+                },
+                category_pair: CategoryHandle::OTHER.into(),
+                flags: FrameFlags::empty(),
+            })
+            .collect();
+        profile.add_sample(thread, crash_time, frames.into_iter(), CpuDelta::ZERO, 1);
+
+        if let (true, Some(exception)) = (is_crashed_thread, &exception) {
+            profile.add_marker(
+                thread,
+                CategoryHandle::OTHER,
+                "Crash",
+                CrashMarker {
+                    code: exception.code,
+                    address: exception.address,
+                },
+                MarkerTiming::Instant(crash_time),
+            );
+        }
+    }
+    Ok(profile)
+}
+
+/// The raw minidump data, with little-endian accessors which fail on
+/// truncated data.
+struct Minidump<'a>(&'a [u8]);
+
+impl<'a> Minidump<'a> {
+    fn bytes(&self, offset: u64, size: u64) -> Result<&'a [u8], Error> {
+        let start = usize::try_from(offset).map_err(|_| Error::Truncated(offset))?;
+        let end = usize::try_from(offset + size).map_err(|_| Error::Truncated(offset))?;
+        self.0.get(start..end).ok_or(Error::Truncated(offset))
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16, Error> {
+        Ok(LittleEndian::read_u16(self.bytes(offset, 2)?))
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32, Error> {
+        Ok(LittleEndian::read_u32(self.bytes(offset, 4)?))
+    }
+
+    fn u64(&self, offset: u64) -> Result<u64, Error> {
+        Ok(LittleEndian::read_u64(self.bytes(offset, 8)?))
+    }
+
+    /// Reads a MINIDUMP_LOCATION_DESCRIPTOR, i.e. a size and an RVA, and
+    /// returns the data it points to.
+    fn location(&self, offset: u64) -> Result<&'a [u8], Error> {
+        let size = self.u32(offset)?;
+        let rva = self.u32(offset + 4)?;
+        self.bytes(rva.into(), size.into())
+    }
+
+    /// Reads a MINIDUMP_STRING, i.e. a byte length and UTF-16 code units.
+    fn string(&self, offset: u64) -> Result<String, Error> {
+        let length = self.u32(offset)?;
+        let bytes = self.bytes(offset + 4, length.into())?;
+        let units: Vec<u16> = bytes.chunks_exact(2).map(LittleEndian::read_u16).collect();
+        Ok(String::from_utf16_lossy(&units))
+    }
+}
+
+struct MinidumpModule {
+    base: u64,
+    size: u32,
+    path: String,
+    debug_name: String,
+    debug_path: String,
+    debug_id: DebugId,
+    code_id: Option<String>,
+}
+
+fn read_modules(dump: &Minidump, module_list: u64) -> Result<Vec<MinidumpModule>, Error> {
+    let count = dump.u32(module_list)?;
+    let mut modules = Vec::new();
+    for i in 0..u64::from(count) {
+        // MINIDUMP_MODULE is 108 bytes: BaseOfImage, SizeOfImage, CheckSum,
+        // TimeDateStamp, ModuleNameRva, VersionInfo (52 bytes), CvRecord,
+        // MiscRecord and two reserved u64s.
+        let entry = module_list + 4 + i * 108;
+        let base = dump.u64(entry)?;
+        let size = dump.u32(entry + 8)?;
+        let time_date_stamp = dump.u32(entry + 16)?;
+        let path = dump.string(dump.u32(entry + 20)?.into())?;
+        let cv_record = dump.location(entry + 76)?;
+
+        let mut module = MinidumpModule {
+            base,
+            size,
+            debug_name: file_name(&path).to_owned(),
+            debug_path: path.clone(),
+            path,
+            debug_id: DebugId::nil(),
+            code_id: None,
+        };
+        let signature = cv_record.get(..4).map(LittleEndian::read_u32);
+        match signature {
+            Some(CV_SIGNATURE_RSDS) if cv_record.len() >= 24 => {
+                // Followed by the GUID, the age and the PDB path.
+                let age = LittleEndian::read_u32(&cv_record[20..]);
+                let pdb_path = &cv_record[24..];
+                let pdb_path_len = pdb_path.iter().position(|&b| b == 0);
+                let pdb_path = String::from_utf8_lossy(&pdb_path[..pdb_path_len.unwrap_or(0)]);
+                module.debug_id = DebugId::from_guid_age(&cv_record[4..20], age).unwrap();
+                if !pdb_path.is_empty() {
+                    module.debug_name = file_name(&pdb_path).to_owned();
+                    module.debug_path = pdb_path.into_owned();
+                }
+                module.code_id = Some(
+                    CodeId::PeCodeId(PeCodeId {
+                        timestamp: time_date_stamp,
+                        image_size: size,
+                    })
+                    .to_string(),
+                );
+            }
+            Some(CV_SIGNATURE_ELF) => {
+                let build_id = &cv_record[4..];
+                module.debug_id = DebugId::from_identifier(build_id, true);
+                module.code_id =
+                    Some(CodeId::ElfBuildId(ElfBuildId::from_bytes(build_id)).to_string());
+            }
+            _ => {}
+        }
+        modules.push(module);
+    }
+    Ok(modules)
+}
+
+fn read_thread_names(
+    dump: &Minidump,
+    thread_name_list: u64,
+) -> Result<HashMap<u32, String>, Error> {
+    let count = dump.u32(thread_name_list)?;
+    let mut names = HashMap::new();
+    for i in 0..u64::from(count) {
+        // MINIDUMP_THREAD_NAME is a packed ThreadId and a 64 bit RVA.
+        let entry = thread_name_list + 4 + i * 12;
+        let tid = dump.u32(entry)?;
+        let name = dump.string(dump.u64(entry + 4)?)?;
+        if !name.is_empty() {
+            names.insert(tid, name);
+        }
+    }
+    Ok(names)
+}
+
+struct MinidumpException<'a> {
+    tid: u32,
+    code: u32,
+    address: u64,
+    context: &'a [u8],
+}
+
+fn read_exception<'a>(dump: &Minidump<'a>, exception: u64) -> Result<MinidumpException<'a>, Error> {
+    // MINIDUMP_EXCEPTION_STREAM: ThreadId, alignment, the 152 byte
+    // MINIDUMP_EXCEPTION (ExceptionCode, ExceptionFlags, ExceptionRecord,
+    // ExceptionAddress, ...) and ThreadContext.
+    Ok(MinidumpException {
+        tid: dump.u32(exception)?,
+        code: dump.u32(exception + 8)?,
+        address: dump.u64(exception + 24)?,
+        context: dump.location(exception + 160)?,
+    })
+}
+
+/// Walks the stack from the registers in an AMD64 CONTEXT.
+fn walk_stack_x86_64(
+    context: &[u8],
+    read_stack: impl FnMut(u64) -> Result<u64, ()>,
+) -> Vec<FrameAddress> {
+    let Some(registers) = context.get(..0x100) else {
+        return Vec::new();
+    };
+    let sp = LittleEndian::read_u64(&registers[0x98..]);
+    let bp = LittleEndian::read_u64(&registers[0xa0..]);
+    let pc = LittleEndian::read_u64(&registers[0xf8..]);
+    let unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    let mut cache = CacheX86_64::<_>::new();
+    walk_stack(
+        &unwinder,
+        &mut cache,
+        pc,
+        UnwindRegsX86_64::new(pc, sp, bp),
+        read_stack,
+    )
+}
+
+/// Walks the stack from the registers in an ARM64 CONTEXT. Breakpad's older
+/// ARM64 context has the same layout for the registers which are used here.
+fn walk_stack_aarch64(
+    context: &[u8],
+    read_stack: impl FnMut(u64) -> Result<u64, ()>,
+) -> Vec<FrameAddress> {
+    let Some(registers) = context.get(..272) else {
+        return Vec::new();
+    };
+    let fp = LittleEndian::read_u64(&registers[240..]);
+    let lr = LittleEndian::read_u64(&registers[248..]);
+    let sp = LittleEndian::read_u64(&registers[256..]);
+    let pc = LittleEndian::read_u64(&registers[264..]);
+    let unwinder = UnwinderAarch64::<Vec<u8>>::new();
+    let mut cache = CacheAarch64::<_>::new();
+    walk_stack(
+        &unwinder,
+        &mut cache,
+        pc,
+        UnwindRegsAarch64::new(lr, sp, fp),
+        read_stack,
+    )
+}
+
+/// Returns the frame addresses from the innermost frame outwards. The walk
+/// stops at the first error, which is usually the end of the stack.
+fn walk_stack<U: Unwinder>(
+    unwinder: &U,
+    cache: &mut U::Cache,
+    pc: u64,
+    regs: U::UnwindRegs,
+    mut read_stack: impl FnMut(u64) -> Result<u64, ()>,
+) -> Vec<FrameAddress> {
+    let mut frames = unwinder.iter_frames(pc, regs, cache, &mut read_stack);
+    let mut addresses = Vec::new();
+    while let Ok(Some(address)) = frames.next() {
+        addresses.push(address);
+    }
+    addresses
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+#[derive(Debug, Clone)]
+struct CrashMarker {
+    code: u32,
+    address: u64,
+}
+
+impl ProfilerMarker for CrashMarker {
+    const MARKER_TYPE_NAME: &'static str = "Crash";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "code": format!("0x{:x}", self.code),
+            "address": format!("0x{:x}", self.address),
+        })
+    }
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![
+                MarkerLocation::MarkerChart,
+                MarkerLocation::MarkerTable,
+                MarkerLocation::TimelineOverview,
+            ],
+            chart_label: Some("{marker.data.code}"),
+            tooltip_label: Some("Crash {marker.data.code} at {marker.data.address}"),
+            table_label: Some("Crash {marker.data.code} at {marker.data.address}"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "code",
+                    label: "Exception code or signal",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "address",
+                    label: "Address",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Static(MarkerStaticField {
+                    label: "Description",
+                    value: "The exception which was recorded in the minidump.",
+                }),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shared::recursion_folding::RecursionFolding;
+
+    /// Appends `bytes` and returns their MINIDUMP_LOCATION_DESCRIPTOR.
+    fn append(data: &mut Vec<u8>, bytes: &[u8]) -> Vec<u8> {
+        let rva = data.len() as u32;
+        data.extend_from_slice(bytes);
+        [(bytes.len() as u32).to_le_bytes(), rva.to_le_bytes()].concat()
+    }
+
+    fn utf16_string(s: &str) -> Vec<u8> {
+        let units: Vec<u8> = s.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        [(units.len() as u32).to_le_bytes().to_vec(), units].concat()
+    }
+
+    fn amd64_context(pc: u64, sp: u64, bp: u64) -> Vec<u8> {
+        let mut context = vec![0; 0x100];
+        context[0x98..0xa0].copy_from_slice(&sp.to_le_bytes());
+        context[0xa0..0xa8].copy_from_slice(&bp.to_le_bytes());
+        context[0xf8..0x100].copy_from_slice(&pc.to_le_bytes());
+        context
+    }
+
+    fn thread(tid: u32, stack_start: u64, stack: &[u8], context: &[u8]) -> Vec<u8> {
+        [
+            &tid.to_le_bytes()[..],
+            &[0; 20],
+            &stack_start.to_le_bytes(),
+            stack,
+            context,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn convert_crashed_process() {
+        let stream_count = 5;
+        let mut data = vec![0; 32 + stream_count * 12];
+
+        // A frame pointer chain: the crashing function was called from
+        // 0x10002000, which was called from 0x10003000.
+        let stack: Vec<u8> = [0u64, 0, 0x7020, 0x10002000, 0, 0x10003000]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let crashed_stack = append(&mut data, &stack);
+        let crashed_context = append(&mut data, &amd64_context(0, 0, 0));
+        let exception_context = append(&mut data, &amd64_context(0x10001234, 0x7000, 0x7010));
+        let worker_stack = append(&mut data, &[0; 16]);
+        let worker_context = append(&mut data, &amd64_context(0x10004000, 0x8000, 0));
+
+        let module_name = append(&mut data, &utf16_string(r"C:\app\app.exe"));
+        let guid: Vec<u8> = (0..16).collect();
+        let cv_record = append(
+            &mut data,
+            &[
+                &b"RSDS"[..],
+                &guid,
+                &1u32.to_le_bytes(),
+                b"C:\\build\\app.pdb\0",
+            ]
+            .concat(),
+        );
+        let worker_name = append(&mut data, &utf16_string("worker"));
+
+        let mut system_info = vec![0; 56];
+        system_info[..2].copy_from_slice(&PROCESSOR_ARCHITECTURE_AMD64.to_le_bytes());
+        let thread_list = [
+            &2u32.to_le_bytes()[..],
+            &thread(10, 0x7000, &crashed_stack, &crashed_context),
+            &thread(11, 0x8000, &worker_stack, &worker_context),
+        ]
+        .concat();
+        let module_list = [
+            &1u32.to_le_bytes()[..],
+            &0x10000000u64.to_le_bytes(),
+            &0x10000u32.to_le_bytes(),
+            &0u32.to_le_bytes(),
+            &0x12345678u32.to_le_bytes(),
+            &module_name[4..],
+            &[0; 52],
+            &cv_record,
+            &[0; 24],
+        ]
+        .concat();
+        let mut exception = vec![0; 160];
+        exception[..4].copy_from_slice(&10u32.to_le_bytes());
+        exception[8..12].copy_from_slice(&0xc0000005u32.to_le_bytes());
+        exception[24..32].copy_from_slice(&0x10001234u64.to_le_bytes());
+        exception.extend_from_slice(&exception_context);
+        let thread_names = [
+            &1u32.to_le_bytes()[..],
+            &11u32.to_le_bytes(),
+            &u64::from(u32::from_le_bytes(worker_name[4..].try_into().unwrap())).to_le_bytes(),
+        ]
+        .concat();
+
+        let mut directory = Vec::new();
+        for (stream_type, stream) in [
+            (SYSTEM_INFO_STREAM, system_info),
+            (THREAD_LIST_STREAM, thread_list),
+            (MODULE_LIST_STREAM, module_list),
+            (EXCEPTION_STREAM, exception),
+            (THREAD_NAME_LIST_STREAM, thread_names),
+        ] {
+            directory.extend_from_slice(&stream_type.to_le_bytes());
+            directory.extend_from_slice(&append(&mut data, &stream));
+        }
+        data[32..32 + directory.len()].copy_from_slice(&directory);
+        let header = [
+            MAGIC,
+            &0xa793u32.to_le_bytes(),
+            &(stream_count as u32).to_le_bytes(),
+            &32u32.to_le_bytes(),
+            &0u32.to_le_bytes(),
+            &1_700_000_000u32.to_le_bytes(),
+            &0u64.to_le_bytes(),
+        ]
+        .concat();
+        data[..32].copy_from_slice(&header);
+        assert!(is_minidump_file(std::io::Cursor::new(&data)));
+
+        let props = ProfileCreationProps {
+            profile_name: "crash".to_owned(),
+            main_thread_only: false,
+            reuse_threads: false,
+            recursion_folding: RecursionFolding::None,
+            unlink_aux_files: false,
+            create_per_cpu_threads: false,
+            override_arch: None,
+            unstable_presymbolicate: false,
+            coreclr: Default::default(),
+            unknown_event_markers: false,
+            post_processing: Default::default(),
+        };
+        let profile = convert(&data[..], props).unwrap();
+        let json = serde_json::to_value(&profile).unwrap();
+
+        let lib = &json["libs"][0];
+        assert_eq!(lib["name"], "app.exe");
+        assert_eq!(lib["debugName"], "app.pdb");
+        assert_eq!(lib["codeId"], "1234567810000");
+        assert_eq!(lib["breakpadId"], "030201000504070608090A0B0C0D0E0F1");
+
+        let sample_stack = |thread: &serde_json::Value| -> Vec<String> {
+            let names = crate::profile_tools::frame_names(thread);
+            let stack = thread["samples"]["stack"][0].as_u64().unwrap() as usize;
+            let prefixes: Vec<_> =
+                crate::profile_tools::column_values(thread.get("stackTable"), "prefix").collect();
+            let frames: Vec<_> =
+                crate::profile_tools::column_values(thread.get("stackTable"), "frame").collect();
+            crate::profile_tools::stack_frames(&prefixes, &frames, stack)
+                .into_iter()
+                .map(|frame| names[frame].clone())
+                .collect()
+        };
+        let crashed = &json["threads"][0];
+        assert_eq!(crashed["processName"], "app.exe");
+        assert_eq!(crashed["tid"], "10");
+        assert_eq!(
+            sample_stack(crashed),
+            ["app.exe!+0x2fff", "app.exe!+0x1fff", "app.exe!+0x1234"]
+        );
+        assert_eq!(crashed["markers"]["data"][0]["code"], "0xc0000005");
+
+        let worker = &json["threads"][1];
+        assert_eq!(worker["name"], "worker");
+        assert_eq!(sample_stack(worker), ["app.exe!+0x4000"]);
+    }
+}
@@ -1 +1,10 @@
+pub mod collapsed;
+pub mod lttng;
+pub mod minidump;
 pub mod perf;
+mod perf_compressed;
+pub mod pprof;
+mod registry;
+pub mod simpleperf;
+
+pub use registry::{ImportInput, Importer, ImporterRegistry};
@@ -4,16 +4,23 @@ use std::io::{Read, Seek};
 use std::path::Path;
 use std::time::SystemTime;
 
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use framehop::{Module, Unwinder};
 use fxprof_processed_profile::{Profile, ReferenceTimestamp};
-use linux_perf_data::{linux_perf_event_reader, DsoInfo, DsoKey, PerfFileReader, PerfFileRecord};
+use linux_perf_data::{
+    linux_perf_event_reader, DsoInfo, DsoKey, Endianness, Feature, PerfFileReader, PerfFileRecord,
+    UserRecordType,
+};
 use linux_perf_event_reader::EventRecord;
 
+use super::perf_compressed;
 use crate::linux_shared::{
-    ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64, Converter, EventInterpretation, KnownEvent,
-    MmapRangeOrVec,
+    is_injected_jit_mmap, parse_lbr_call_stack, set_record_timestamp, ConvertRegs,
+    ConvertRegsAarch64, ConvertRegsX86_64, Converter, EventInterpretation, KnownEvent,
+    MmapRangeOrVec, OffCpuIndicator,
 };
 use crate::shared::recording_props::ProfileCreationProps;
+use crate::shared::timestamp_converter::TscConversion;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -22,9 +29,34 @@ pub enum Error {
 
     #[error("Linux Perf error: {0}")]
     LinuxPerf(#[from] linux_perf_data::Error),
+
+    #[error("Could not decompress the records: {0}")]
+    Decompression(String),
 }
 
 pub fn convert<C: Read + Seek>(
+    mut cursor: C,
+    file_mod_time: Option<SystemTime>,
+    extra_dir: Option<&Path>,
+    profile_creation_props: ProfileCreationProps,
+) -> Result<Profile, Error> {
+    if perf_compressed::is_compressed(&mut cursor)? {
+        // Recorded with `perf record -z`. The decompressed file can be much
+        // larger than the compressed one, so it's written to disk.
+        let mut decompressed = tempfile::tempfile()?;
+        perf_compressed::decompress(cursor, &mut decompressed)?;
+        decompressed.rewind()?;
+        return convert_uncompressed(
+            decompressed,
+            file_mod_time,
+            extra_dir,
+            profile_creation_props,
+        );
+    }
+    convert_uncompressed(cursor, file_mod_time, extra_dir, profile_creation_props)
+}
+
+fn convert_uncompressed<C: Read + Seek>(
     cursor: C,
     file_mod_time: Option<SystemTime>,
     extra_dir: Option<&Path>,
@@ -70,7 +102,7 @@ fn convert_impl<U, C, R>(
     file_mod_time: Option<SystemTime>,
     extra_dir: Option<&Path>,
     cache: U::Cache,
-    profile_creation_props: ProfileCreationProps,
+    mut profile_creation_props: ProfileCreationProps,
 ) -> Profile
 where
     U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
@@ -123,11 +155,24 @@ where
         eprintln!("event {event_name}");
     }
     let interpretation = EventInterpretation::divine_from_attrs(attributes);
+    if interpretation.off_cpu_indicator == Some(OffCpuIndicator::ContextSwitches)
+        && interpretation.context_switches_have_cpu
+    {
+        // The switch records say which thread ran on which CPU when, so we can
+        // show a track per CPU, and "Running on CPU" markers on the threads.
+        profile_creation_props.create_per_cpu_threads = true;
+    }
     let simpleperf_symbol_tables = perf_file.simpleperf_symbol_tables().ok().flatten();
     let reference_timestamp = if let Some(seconds_since_unix_epoch) =
         get_simpleperf_timestamp(simpleperf_meta_info.as_ref())
     {
         ReferenceTimestamp::from_millis_since_unix_epoch(seconds_since_unix_epoch * 1000.0)
+    } else if let Some(reference_timestamp) = get_clock_data_reference_timestamp(
+        perf_file.feature_section_data(Feature::CLOCK_DATA),
+        endian,
+        first_sample_time,
+    ) {
+        reference_timestamp
     } else if let Some(mod_time) = file_mod_time {
         ReferenceTimestamp::from_system_time(mod_time)
     } else {
@@ -152,36 +197,65 @@ where
     let mut last_timestamp = 0;
 
     while let Ok(Some(record)) = record_iter.next_record(&mut perf_file) {
-        let (record, parsed_record, attr_index) = match record {
+        let (record, mut parsed_record, attr_index) = match record {
             PerfFileRecord::EventRecord { attr_index, record } => match record.parse() {
                 Ok(r) => (record, r, attr_index),
                 Err(_) => continue,
             },
-            PerfFileRecord::UserRecord(_) => continue,
-        };
-        if let Some(timestamp) = record.timestamp() {
-            if timestamp < last_timestamp {
-                eprintln!(
-                    "bad timestamp ordering; {timestamp} is earlier but arrived after {last_timestamp}"
-                );
+            PerfFileRecord::UserRecord(record) => {
+                if record.record_type == UserRecordType::PERF_TIME_CONV {
+                    if let Some(tsc_conversion) = parse_time_conv(&record.data.as_slice(), endian) {
+                        converter.set_tsc_conversion(tsc_conversion);
+                    }
+                }
+                continue;
             }
+        };
+        // The timestamps of the mmap records from `perf inject --jit` are out of
+        // order by design, they mustn't be mistaken for clock skew.
+        let timestamp = if is_injected_jit_mmap(&parsed_record) {
+            None
+        } else {
+            record
+                .timestamp()
+                .map(|timestamp| converter.correct_timestamp(timestamp))
+        };
+        if let Some(timestamp) = timestamp {
+            set_record_timestamp(&mut parsed_record, timestamp);
             last_timestamp = timestamp;
         }
 
         match parsed_record {
             EventRecord::Sample(e) => {
+                let lbr_call_stack = parse_lbr_call_stack(&record);
                 if attr_index == interpretation.main_event_attr_index {
-                    converter.handle_main_event_sample::<C>(&e);
+                    converter.handle_main_event_sample::<C>(&e, lbr_call_stack.as_deref());
                 } else if Some(attr_index) == interpretation.sched_switch_attr_index {
                     converter.handle_sched_switch_sample::<C>(&e);
+                } else if Some(attr_index) == interpretation.aux_event_attr_index {
+                    converter.handle_aux_event_sample::<C>(&e);
+                } else if interpretation
+                    .track_event_attr_indices
+                    .contains(&attr_index)
+                {
+                    converter.handle_track_event_sample::<C>(
+                        &e,
+                        attr_index,
+                        lbr_call_stack.as_deref(),
+                    );
                 }
 
                 match interpretation.known_event_indices.get(&attr_index) {
                     Some(KnownEvent::RssStat) => converter.handle_rss_stat_sample::<C>(&e),
+                    Some(KnownEvent::SchedWakeup) => converter.handle_sched_wakeup_sample::<C>(&e),
                     _ => {
-                        // the main event and sched_switch are already covered by regular samples so don't add other event markers
+                        // the main event, sched_switch, the aux event and the events with their own tracks are already covered by regular samples so don't add other event markers
                         if !(attr_index == interpretation.main_event_attr_index
-                            || Some(attr_index) == interpretation.sched_switch_attr_index)
+                            || Some(attr_index) == interpretation.sched_switch_attr_index
+                            || Some(attr_index) == interpretation.aux_event_attr_index
+                            || interpretation
+                                .track_event_attr_indices
+                                .contains(&attr_index))
                         {
                             converter.handle_other_event_sample::<C>(&e, attr_index)
                         }
@@ -192,7 +266,7 @@ where
                 converter.handle_fork(e);
             }
             EventRecord::Comm(e) => {
-                converter.handle_comm(e, record.timestamp());
+                converter.handle_comm(e, timestamp);
             }
             EventRecord::Exit(e) => {
                 converter.handle_exit(e);
@@ -204,12 +278,18 @@ where
                 converter.handle_mmap2(e, last_timestamp);
             }
             EventRecord::ContextSwitch(e) => {
-                let common = match record.common_data() {
+                let mut common = match record.common_data() {
                     Ok(common) => common,
                     Err(_) => continue,
                 };
+                common.timestamp = timestamp;
                 converter.handle_context_switch(e, common);
             }
+            EventRecord::Lost(e) => {
+                if let Ok(common) = record.common_data() {
+                    converter.handle_lost(e.count, common);
+                }
+            }
             _ => {
                 // println!("{:?}", record.record_type);
             }
@@ -225,6 +305,55 @@ fn get_simpleperf_timestamp(meta_info: Option<&HashMap<&str, &str>>) -> Option<f
     timestamp_str.parse().ok()
 }
 
+/// The wall-clock time of the first sample, from the CLOCK_DATA feature
+/// section. perf writes this section when recording with `--clockid`, and it
+/// lets us line the profile up with other captures from the same machine
+/// much more precisely than the file's modification time.
+fn get_clock_data_reference_timestamp(
+    section: Option<&[u8]>,
+    endian: Endianness,
+    first_sample_time: u64,
+) -> Option<ReferenceTimestamp> {
+    fn parse<T: ByteOrder>(section: &[u8]) -> Option<(u64, u64)> {
+        // u32 version, u32 clockid, u64 wall_clock_ns, u64 clockid_time_ns
+        let wall_clock_ns = T::read_u64(section.get(8..16)?);
+        let clockid_time_ns = T::read_u64(section.get(16..24)?);
+        Some((wall_clock_ns, clockid_time_ns))
+    }
+    let section = section?;
+    let (wall_clock_ns, clockid_time_ns) = match endian {
+        Endianness::LittleEndian => parse::<LittleEndian>(section)?,
+        Endianness::BigEndian => parse::<BigEndian>(section)?,
+    };
+    let first_sample_wall_clock_ns =
+        i128::from(wall_clock_ns) - i128::from(clockid_time_ns) + i128::from(first_sample_time);
+    Some(ReferenceTimestamp::from_millis_since_unix_epoch(
+        first_sample_wall_clock_ns as f64 / 1_000_000.0,
+    ))
+}
+
+/// Parses a PERF_RECORD_TIME_CONV record, which says how TSC values, e.g. in
+/// jitdump files, are converted into perf timestamps.
+fn parse_time_conv(data: &[u8], endian: Endianness) -> Option<TscConversion> {
+    fn parse<T: ByteOrder>(data: &[u8]) -> Option<TscConversion> {
+        let field = |index: usize| data.get(index * 8..index * 8 + 8).map(T::read_u64);
+        Some(TscConversion {
+            time_shift: field(0)?,
+            time_mult: field(1)?,
+            time_zero: field(2)?,
+            // Older versions of perf only write the first three fields.
+            time_cycles: field(3).unwrap_or(0),
+            time_mask: field(4).unwrap_or(u64::MAX),
+            // The byte at offset 40 is cap_user_time_zero.
+            cap_user_time_short: data.get(41).is_some_and(|&b| b != 0),
+        })
+    }
+    match endian {
+        Endianness::LittleEndian => parse::<LittleEndian>(data),
+        Endianness::BigEndian => parse::<BigEndian>(data),
+    }
+}
+
 /// This is a terrible hack to work around ambiguous build IDs in old versions
 /// of perf (tested with perf 5.4.224). Those versions of perf do two things:
 ///
@@ -253,3 +382,39 @@ fn fixup_perf_jit_build_ids(build_ids: &mut HashMap<DsoKey, DsoInfo>) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_time_metadata() {
+        let mut time_conv = Vec::new();
+        for field in [31u64, 1 << 30, 1_000_000, 500, 0xffff_ffff] {
+            time_conv.extend_from_slice(&field.to_le_bytes());
+        }
+        time_conv.extend_from_slice(&[1, 1, 0, 0, 0, 0, 0, 0]);
+        let conversion = parse_time_conv(&time_conv, Endianness::LittleEndian).unwrap();
+        assert_eq!(conversion.time_zero, 1_000_000);
+        assert_eq!(conversion.time_cycles, 500);
+        assert!(conversion.cap_user_time_short);
+        let old_conversion = parse_time_conv(&time_conv[..24], Endianness::LittleEndian).unwrap();
+        assert_eq!(old_conversion.time_mask, u64::MAX);
+        assert!(!old_conversion.cap_user_time_short);
+        assert!(parse_time_conv(&time_conv[..16], Endianness::LittleEndian).is_none());
+
+        let mut clock_data = vec![1, 0, 0, 0, 1, 0, 0, 0];
+        clock_data.extend_from_slice(&1_700_000_000_000_000_000u64.to_le_bytes());
+        clock_data.extend_from_slice(&5_000_000_000u64.to_le_bytes());
+        let reference = get_clock_data_reference_timestamp(
+            Some(&clock_data),
+            Endianness::LittleEndian,
+            4_000_000_000,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(reference).unwrap(),
+            serde_json::json!(1_699_999_999_000.0)
+        );
+    }
+}
@@ -0,0 +1,427 @@
+//! Decompresses perf.data files which were recorded with `perf record -z`.
+//!
+//! perf compresses the records from its ring buffers into one long Zstandard
+//! stream, which is split over `PERF_RECORD_COMPRESSED` records. The stream is
+//! flushed after each chunk of records, so that the records which perf writes
+//! uncompressed in between, like `FINISHED_ROUND`, belong after the records
+//! which have been decompressed up to that point.
+//!
+//! The file is rewritten without compression, so that the rest of the import
+//! can read it like any other perf.data file.
+
+use std::collections::VecDeque;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use ruzstd::decoding::block_decoder;
+use ruzstd::decoding::scratch::DecoderScratch;
+use ruzstd::frame::read_frame_header;
+
+use super::perf::Error;
+
+/// The size of `struct perf_file_header`.
+const FILE_HEADER_SIZE: usize = 104;
+/// The offset of the data section's offset and size in the file header.
+const DATA_SECTION_OFFSET: usize = 40;
+/// The offset of the feature bitmap in the file header.
+const FEATURES_OFFSET: usize = 72;
+const HEADER_COMPRESSED: u64 = 27;
+
+const PERF_EVENT_HEADER_SIZE: usize = 8;
+const PERF_RECORD_COMPRESSED: u32 = 81;
+/// Like `PERF_RECORD_COMPRESSED`, but with the size of the compressed data
+/// before the data, because the record is padded to eight bytes.
+const PERF_RECORD_COMPRESSED2: u32 = 83;
+
+/// The largest frame header of a Zstandard frame.
+const MAX_FRAME_HEADER_SIZE: usize = 18;
+
+#[derive(Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn from_magic(magic: &[u8]) -> Option<Self> {
+        match magic {
+            b"PERFILE2" => Some(Endian::Little),
+            b"2ELIFREP" => Some(Endian::Big),
+            _ => None,
+        }
+    }
+
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = bytes[..2].try_into().unwrap();
+        match self {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = bytes[..4].try_into().unwrap();
+        match self {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        }
+    }
+
+    fn u64(self, bytes: &[u8]) -> u64 {
+        let bytes = bytes[..8].try_into().unwrap();
+        match self {
+            Endian::Little => u64::from_le_bytes(bytes),
+            Endian::Big => u64::from_be_bytes(bytes),
+        }
+    }
+
+    fn u64_bytes(self, value: u64) -> [u8; 8] {
+        match self {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        }
+    }
+}
+
+/// Whether the perf.data file has the `HEADER_COMPRESSED` feature, i.e.
+/// whether it was recorded with `perf record -z`. The reader is rewound.
+pub fn is_compressed(reader: &mut (impl Read + Seek)) -> std::io::Result<bool> {
+    let mut header = [0; FILE_HEADER_SIZE];
+    let result = reader.read_exact(&mut header);
+    reader.rewind()?;
+    if result.is_err() {
+        return Ok(false);
+    }
+    let Some(endian) = Endian::from_magic(&header[..8]) else {
+        return Ok(false);
+    };
+    let features = endian.u64(&header[FEATURES_OFFSET..]);
+    Ok(features & (1 << HEADER_COMPRESSED) != 0)
+}
+
+/// Writes the perf.data file from `reader` to `output`, with the compressed
+/// records replaced by the records they contain.
+pub fn decompress(
+    mut reader: impl Read + Seek,
+    mut output: impl Write + Seek,
+) -> Result<(), Error> {
+    let mut header = [0; FILE_HEADER_SIZE];
+    reader.rewind()?;
+    reader.read_exact(&mut header)?;
+    let endian = Endian::from_magic(&header[..8])
+        .ok_or(Error::Decompression("not a perf.data file".to_string()))?;
+    let data_offset = endian.u64(&header[DATA_SECTION_OFFSET..]);
+    let data_size = endian.u64(&header[DATA_SECTION_OFFSET + 8..]);
+    let feature_count: u32 = (0..4)
+        .map(|i| endian.u64(&header[FEATURES_OFFSET + i * 8..]).count_ones())
+        .sum();
+
+    // Everything before the data section, i.e. the header and the attributes,
+    // stays the same.
+    reader.rewind()?;
+    std::io::copy(&mut (&mut reader).take(data_offset), &mut output)?;
+
+    let mut records = RecordWriter::new(&mut output);
+    let mut stream = ZstdStream::default();
+    let mut read_size = 0;
+    let mut record = Vec::new();
+    while read_size < data_size {
+        record.resize(PERF_EVENT_HEADER_SIZE, 0);
+        reader.read_exact(&mut record)?;
+        let record_type = endian.u32(&record[0..]);
+        let size = endian.u16(&record[6..]) as usize;
+        if size < PERF_EVENT_HEADER_SIZE {
+            return Err(Error::Decompression(format!(
+                "invalid record size {size} at offset {}",
+                data_offset + read_size
+            )));
+        }
+        record.resize(size, 0);
+        reader.read_exact(&mut record[PERF_EVENT_HEADER_SIZE..])?;
+        read_size += size as u64;
+
+        let body = &record[PERF_EVENT_HEADER_SIZE..];
+        let compressed = match record_type {
+            PERF_RECORD_COMPRESSED => body,
+            PERF_RECORD_COMPRESSED2 if body.len() >= 8 => {
+                let compressed_size = endian.u64(body) as usize;
+                &body[8..][..compressed_size.min(body.len() - 8)]
+            }
+            _ => {
+                records.add_uncompressed_record(stream.position(), record.clone())?;
+                continue;
+            }
+        };
+        let decompressed = stream.push(compressed)?;
+        records.add_decompressed_bytes(&decompressed)?;
+    }
+    let decompressed = stream.finish();
+    records.add_decompressed_bytes(&decompressed)?;
+    let new_data_size = records.finish()?;
+
+    // The feature sections come after the data section, and start with a
+    // table of their offsets and sizes.
+    let mut feature_table = vec![0; feature_count as usize * 16];
+    reader.read_exact(&mut feature_table)?;
+    for entry in feature_table.chunks_exact_mut(16) {
+        let offset = endian.u64(entry) + new_data_size - data_size;
+        entry[..8].copy_from_slice(&endian.u64_bytes(offset));
+    }
+    output.write_all(&feature_table)?;
+    std::io::copy(&mut reader, &mut output)?;
+
+    output.seek(SeekFrom::Start(DATA_SECTION_OFFSET as u64 + 8))?;
+    output.write_all(&endian.u64_bytes(new_data_size))?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Writes the decompressed records, and puts each uncompressed record at its
+/// position in the decompressed stream. The decompressor holds back the most
+/// recent bytes of the stream, so the uncompressed records need to wait for
+/// the decompressed records in front of them.
+struct RecordWriter<W: Write> {
+    output: W,
+    /// The number of decompressed bytes which have been written.
+    stream_position: u64,
+    /// The uncompressed records which haven't been written yet, with their
+    /// position in the decompressed stream.
+    waiting_records: VecDeque<(u64, Vec<u8>)>,
+    written_size: u64,
+}
+
+impl<W: Write> RecordWriter<W> {
+    fn new(output: W) -> Self {
+        Self {
+            output,
+            stream_position: 0,
+            waiting_records: VecDeque::new(),
+            written_size: 0,
+        }
+    }
+
+    fn add_uncompressed_record(&mut self, position: u64, record: Vec<u8>) -> std::io::Result<()> {
+        self.waiting_records.push_back((position, record));
+        self.write_waiting_records()
+    }
+
+    fn add_decompressed_bytes(&mut self, mut bytes: &[u8]) -> std::io::Result<()> {
+        loop {
+            self.write_waiting_records()?;
+            if bytes.is_empty() {
+                return Ok(());
+            }
+            let len = match self.waiting_records.front() {
+                Some((position, _)) => {
+                    ((position - self.stream_position) as usize).min(bytes.len())
+                }
+                None => bytes.len(),
+            };
+            self.output.write_all(&bytes[..len])?;
+            self.stream_position += len as u64;
+            self.written_size += len as u64;
+            bytes = &bytes[len..];
+        }
+    }
+
+    fn write_waiting_records(&mut self) -> std::io::Result<()> {
+        while let Some((position, record)) = self.waiting_records.front() {
+            if *position > self.stream_position {
+                break;
+            }
+            self.output.write_all(record)?;
+            self.written_size += record.len() as u64;
+            self.waiting_records.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining records and returns the size of the data section.
+    fn finish(mut self) -> std::io::Result<u64> {
+        for (_, record) in std::mem::take(&mut self.waiting_records) {
+            self.output.write_all(&record)?;
+            self.written_size += record.len() as u64;
+        }
+        Ok(self.written_size)
+    }
+}
+
+/// A Zstandard decompressor which is fed the compressed data in pieces which
+/// don't necessarily end at a block boundary.
+#[derive(Default)]
+struct ZstdStream {
+    /// The compressed bytes which haven't been decoded yet.
+    pending: Vec<u8>,
+    /// The state of the current frame, if a frame header has been read.
+    frame: Option<DecoderScratch>,
+    has_checksum: bool,
+    /// The number of bytes to skip after the end of a frame, for its checksum.
+    skip: usize,
+    /// The number of decompressed bytes which have been returned.
+    drained: u64,
+}
+
+impl ZstdStream {
+    /// The number of bytes which have been decompressed so far, including
+    /// the ones which haven't been returned yet.
+    fn position(&self) -> u64 {
+        self.drained + self.frame.as_ref().map_or(0, |f| f.buffer.len() as u64)
+    }
+
+    /// Decodes all complete blocks and returns the decompressed bytes which
+    /// aren't needed for decoding the following blocks.
+    fn push(&mut self, compressed: &[u8]) -> Result<Vec<u8>, Error> {
+        self.pending.extend_from_slice(compressed);
+        let mut decompressed = Vec::new();
+        let mut source = &self.pending[..];
+        loop {
+            if self.skip > 0 {
+                let len = self.skip.min(source.len());
+                source = &source[len..];
+                self.skip -= len;
+            }
+            let Some(frame) = &mut self.frame else {
+                if source.is_empty() {
+                    break;
+                }
+                let mut frame_source = source;
+                match read_frame_header(&mut frame_source) {
+                    Ok((frame, _)) => {
+                        let window_size = frame
+                            .header
+                            .window_size()
+                            .map_err(|e| Error::Decompression(e.to_string()))?;
+                        self.has_checksum = frame.header.descriptor.content_checksum_flag();
+                        self.frame = Some(DecoderScratch::new(window_size as usize));
+                        source = frame_source;
+                        continue;
+                    }
+                    Err(_) if source.len() < MAX_FRAME_HEADER_SIZE => break,
+                    Err(e) => return Err(Error::Decompression(e.to_string())),
+                }
+            };
+
+            let mut block_source = source;
+            let mut block_decoder = block_decoder::new();
+            let Ok((block_header, _)) = block_decoder.read_block_header(&mut block_source) else {
+                break;
+            };
+            if block_source.len() < block_header.content_size as usize {
+                break;
+            }
+            block_decoder
+                .decode_block_content(&block_header, frame, &mut block_source)
+                .map_err(|e| Error::Decompression(e.to_string()))?;
+            source = block_source;
+            if block_header.last_block {
+                decompressed.extend(frame.buffer.drain());
+                self.frame = None;
+                self.skip = if self.has_checksum { 4 } else { 0 };
+            }
+        }
+        let consumed = self.pending.len() - source.len();
+        self.pending.drain(..consumed);
+
+        if let Some(frame) = &mut self.frame {
+            decompressed.extend(frame.buffer.drain_to_window_size().unwrap_or_default());
+        }
+        self.drained += decompressed.len() as u64;
+        Ok(decompressed)
+    }
+
+    /// Returns the decompressed bytes which have been held back.
+    fn finish(&mut self) -> Vec<u8> {
+        let decompressed = match self.frame.take() {
+            Some(mut frame) => frame.buffer.drain(),
+            None => Vec::new(),
+        };
+        self.drained += decompressed.len() as u64;
+        decompressed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn record(record_type: u32, body: &[u8]) -> Vec<u8> {
+        let mut record = record_type.to_le_bytes().to_vec();
+        record.extend(0u16.to_le_bytes());
+        record.extend((8 + body.len() as u16).to_le_bytes());
+        record.extend(body);
+        record
+    }
+
+    /// A Zstandard block which stores the data without compressing it.
+    fn raw_block(data: &[u8], last: bool) -> Vec<u8> {
+        let block_header = (data.len() as u32) << 3 | u32::from(last);
+        let mut block = block_header.to_le_bytes()[..3].to_vec();
+        block.extend(data);
+        block
+    }
+
+    fn perf_file(data: &[u8], features: u64, feature_sections: &[u8]) -> Vec<u8> {
+        let mut file = b"PERFILE2".to_vec();
+        file.extend(104u64.to_le_bytes()); // header size
+        file.extend(0u64.to_le_bytes()); // attr size
+        file.extend([0; 16]); // attrs
+        file.extend(104u64.to_le_bytes());
+        file.extend((data.len() as u64).to_le_bytes());
+        file.extend([0; 16]); // event types
+        file.extend(features.to_le_bytes());
+        file.extend([0; 24]);
+        file.extend(data);
+        let feature_offset = file.len() as u64 + 16;
+        file.extend(feature_offset.to_le_bytes());
+        file.extend((feature_sections.len() as u64).to_le_bytes());
+        file.extend(feature_sections);
+        file
+    }
+
+    #[test]
+    fn decompress_records() {
+        let comm = record(3, b"comm....");
+        let mmap = record(1, b"mmap....");
+        let sample = record(9, b"sample..");
+        let finished_round = record(68, b"");
+        let frame_header = [0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x00];
+
+        // The first chunk is split over two compressed records, in the
+        // middle of a block.
+        let mut stream = frame_header.to_vec();
+        stream.extend(raw_block(&[mmap.clone(), sample.clone()].concat(), false));
+        let (first, second) = stream.split_at(14);
+        let mut data = comm.clone();
+        data.extend(record(PERF_RECORD_COMPRESSED, first));
+        data.extend(record(PERF_RECORD_COMPRESSED, second));
+        data.extend(&finished_round);
+        let block = raw_block(&sample, false);
+        let mut compressed2 = (block.len() as u64).to_le_bytes().to_vec();
+        compressed2.extend(&block);
+        compressed2.extend([0; 5]); // padding
+        data.extend(record(PERF_RECORD_COMPRESSED2, &compressed2));
+        data.extend(&finished_round);
+
+        let features = 1 << HEADER_COMPRESSED;
+        let input = perf_file(&data, features, b"section");
+        let mut input = Cursor::new(input);
+        assert!(is_compressed(&mut input).unwrap());
+
+        let mut output = Cursor::new(Vec::new());
+        decompress(&mut input, &mut output).unwrap();
+        let expected_data = [
+            comm,
+            mmap,
+            sample.clone(),
+            finished_round.clone(),
+            sample,
+            finished_round,
+        ]
+        .concat();
+        assert_eq!(
+            output.into_inner(),
+            perf_file(&expected_data, features, b"section")
+        );
+    }
+}
@@ -0,0 +1,423 @@
+//! Converts pprof profiles, as written by Go's runtime/pprof and by many
+//! continuous profilers, into the Firefox Profiler format.
+//!
+//! pprof profiles contain aggregated stacks without timestamps, so the samples
+//! are spread evenly across the profile's duration. Profiles with several
+//! sample types, e.g. Go heap profiles with allocated and in-use bytes, are
+//! converted using the default sample type, or the last one if there's no
+//! default, like `go tool pprof` does.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+use debugid::DebugId;
+use flate2::read::GzDecoder;
+use fxprof_processed_profile::{
+    CategoryHandle, CpuDelta, Frame, FrameFlags, FrameInfo, LibraryInfo, Profile,
+    ReferenceTimestamp, SamplingInterval, Timestamp,
+};
+use prost::Message;
+use wholesym::samply_symbols::DebugIdExt;
+
+use crate::shared::recording_props::ProfileCreationProps;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid pprof data: {0}")]
+    Decode(#[from] prost::DecodeError),
+
+    #[error("The pprof profile has no sample types")]
+    NoSampleTypes,
+}
+
+/// Whether the file name looks like a pprof file, e.g. `cpu.pprof` or
+/// `profile.pb.gz`.
+pub fn is_pprof_file_name(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    [".pprof", ".pprof.gz", ".pb", ".pb.gz"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+/// Reads a pprof protobuf file, which may be gzip-compressed.
+pub fn convert(
+    mut reader: impl Read,
+    profile_creation_props: ProfileCreationProps,
+) -> Result<Profile, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    if data.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&data[..]).read_to_end(&mut decompressed)?;
+        data = decompressed;
+    }
+    let pprof = proto::Profile::decode(&data[..])?;
+    convert_pprof(&pprof, &profile_creation_props)
+}
+
+fn convert_pprof(
+    pprof: &proto::Profile,
+    profile_creation_props: &ProfileCreationProps,
+) -> Result<Profile, Error> {
+    let string = |index: i64| {
+        pprof
+            .string_table
+            .get(index as usize)
+            .map_or("", String::as_str)
+    };
+    let last_sample_type = pprof.sample_type.len().checked_sub(1);
+    let sample_type_index = match pprof.default_sample_type {
+        0 => last_sample_type,
+        default => pprof
+            .sample_type
+            .iter()
+            .position(|sample_type| sample_type.r#type == default)
+            .or(last_sample_type),
+    }
+    .ok_or(Error::NoSampleTypes)?;
+    let sample_type = &pprof.sample_type[sample_type_index];
+    let sample_type_name = string(sample_type.r#type);
+    let sample_unit = string(sample_type.unit);
+    if pprof.sample_type.len() > 1 {
+        eprintln!("Using the pprof sample type {sample_type_name} ({sample_unit}).");
+    }
+
+    // For CPU profiles, the period is the sampling interval.
+    let period_nanos = match &pprof.period_type {
+        Some(period_type) if string(period_type.unit) == "nanoseconds" && pprof.period > 0 => {
+            Some(pprof.period as u64)
+        }
+        _ => None,
+    };
+    let interval = match period_nanos {
+        Some(nanos) => SamplingInterval::from_nanos(nanos),
+        None => SamplingInterval::from_millis(1),
+    };
+    let reference_timestamp = ReferenceTimestamp::from_duration_since_unix_epoch(
+        Duration::from_nanos(pprof.time_nanos.max(0) as u64),
+    );
+    let mut profile = Profile::new(
+        &profile_creation_props.profile_name,
+        reference_timestamp,
+        interval,
+    );
+
+    let process_name = pprof
+        .mapping
+        .first()
+        .map(|mapping| file_name(string(mapping.filename)))
+        .filter(|name| !name.is_empty())
+        .unwrap_or(&profile_creation_props.profile_name);
+    let process = profile.add_process(process_name, 0, Timestamp::from_nanos_since_reference(0));
+    let thread = profile.add_thread(process, 0, Timestamp::from_nanos_since_reference(0), true);
+
+    // Map the binaries, so that unsymbolicated addresses can be symbolicated
+    // once the profile is loaded.
+    for mapping in &pprof.mapping {
+        let path = string(mapping.filename);
+        if path.is_empty() || mapping.memory_limit <= mapping.memory_start {
+            continue;
+        }
+        let build_id = string(mapping.build_id);
+        let build_id_bytes = parse_hex(build_id);
+        let lib = profile.add_lib(LibraryInfo {
+            name: file_name(path).to_owned(),
+            debug_name: file_name(path).to_owned(),
+            path: path.to_owned(),
+            debug_path: path.to_owned(),
+            debug_id: build_id_bytes
+                .as_deref()
+                .map(|id| DebugId::from_identifier(id, true))
+                .unwrap_or_default(),
+            code_id: build_id_bytes.map(|_| build_id.to_owned()),
+            arch: None,
+            symbol_table: None,
+        });
+        // The file offset is only the same as the relative address for
+        // binaries whose segments are mapped at their file offsets, which is
+        // the common case for the executable segment.
+        profile.add_lib_mapping(
+            process,
+            lib,
+            mapping.memory_start,
+            mapping.memory_limit,
+            mapping.file_offset as u32,
+        );
+    }
+
+    let functions: HashMap<u64, &proto::Function> =
+        pprof.function.iter().map(|f| (f.id, f)).collect();
+    let locations: HashMap<u64, &proto::Location> =
+        pprof.location.iter().map(|l| (l.id, l)).collect();
+
+    let sample_count = pprof.sample.len() as u64;
+    let duration_nanos = match pprof.duration_nanos {
+        duration if duration > 0 => duration as u64,
+        _ => interval.nanos() * sample_count,
+    };
+    let is_time = sample_unit == "nanoseconds";
+    for (i, sample) in pprof.sample.iter().enumerate() {
+        let value = sample.value.get(sample_type_index).copied().unwrap_or(0);
+        if value == 0 {
+            continue;
+        }
+        let weight = match period_nanos {
+            Some(period) if is_time => (value as f64 / period as f64).round() as i64,
+            _ => value,
+        };
+        let cpu_delta = if is_time {
+            CpuDelta::from_nanos(value.max(0) as u64)
+        } else {
+            CpuDelta::ZERO
+        };
+
+        // The first location is the leaf, and the last line of a location is
+        // the function into which the previous lines were inlined.
+        let mut frames = Vec::new();
+        for (depth, location_id) in sample.location_id.iter().enumerate().rev() {
+            let Some(location) = locations.get(location_id) else {
+                continue;
+            };
+            if location.line.is_empty() {
+                frames.push(match location.address {
+                    0 => Frame::Label(profile.intern_string("<unknown>")),
+                    address if depth == 0 => Frame::InstructionPointer(address),
+                    address => Frame::AdjustedReturnAddress(address),
+                });
+                continue;
+            }
+            for line in location.line.iter().rev() {
+                let name = match functions.get(&line.function_id) {
+                    Some(function) => string(function.name),
+                    None => "<unknown>",
+                };
+                frames.push(Frame::Label(profile.intern_string(name)));
+            }
+        }
+
+        let timestamp =
+            Timestamp::from_nanos_since_reference(duration_nanos * i as u64 / sample_count.max(1));
+        let frames = frames.into_iter().map(|frame| FrameInfo {
+            frame,
+            category_pair: CategoryHandle::OTHER.into(),
+            flags: FrameFlags::empty(),
+        });
+        let weight = weight.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        profile.add_sample(thread, timestamp, frames, cpu_delta, weight);
+    }
+    Ok(profile)
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if s.is_empty() || s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The messages of pprof's profile.proto which samply uses.
+mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Profile {
+        #[prost(message, repeated, tag = "1")]
+        pub sample_type: Vec<ValueType>,
+        #[prost(message, repeated, tag = "2")]
+        pub sample: Vec<Sample>,
+        #[prost(message, repeated, tag = "3")]
+        pub mapping: Vec<Mapping>,
+        #[prost(message, repeated, tag = "4")]
+        pub location: Vec<Location>,
+        #[prost(message, repeated, tag = "5")]
+        pub function: Vec<Function>,
+        #[prost(string, repeated, tag = "6")]
+        pub string_table: Vec<String>,
+        #[prost(int64, tag = "9")]
+        pub time_nanos: i64,
+        #[prost(int64, tag = "10")]
+        pub duration_nanos: i64,
+        #[prost(message, optional, tag = "11")]
+        pub period_type: Option<ValueType>,
+        #[prost(int64, tag = "12")]
+        pub period: i64,
+        #[prost(int64, tag = "14")]
+        pub default_sample_type: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ValueType {
+        #[prost(int64, tag = "1")]
+        pub r#type: i64,
+        #[prost(int64, tag = "2")]
+        pub unit: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Sample {
+        #[prost(uint64, repeated, tag = "1")]
+        pub location_id: Vec<u64>,
+        #[prost(int64, repeated, tag = "2")]
+        pub value: Vec<i64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Mapping {
+        #[prost(uint64, tag = "1")]
+        pub id: u64,
+        #[prost(uint64, tag = "2")]
+        pub memory_start: u64,
+        #[prost(uint64, tag = "3")]
+        pub memory_limit: u64,
+        #[prost(uint64, tag = "4")]
+        pub file_offset: u64,
+        #[prost(int64, tag = "5")]
+        pub filename: i64,
+        #[prost(int64, tag = "6")]
+        pub build_id: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Location {
+        #[prost(uint64, tag = "1")]
+        pub id: u64,
+        #[prost(uint64, tag = "2")]
+        pub mapping_id: u64,
+        #[prost(uint64, tag = "3")]
+        pub address: u64,
+        #[prost(message, repeated, tag = "4")]
+        pub line: Vec<Line>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Line {
+        #[prost(uint64, tag = "1")]
+        pub function_id: u64,
+        #[prost(int64, tag = "2")]
+        pub line: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Function {
+        #[prost(uint64, tag = "1")]
+        pub id: u64,
+        #[prost(int64, tag = "2")]
+        pub name: i64,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::proto::{Function, Line, Location, Sample, ValueType};
+    use super::*;
+    use crate::shared::recursion_folding::RecursionFolding;
+
+    #[test]
+    fn convert_go_cpu_profile() {
+        let strings = [
+            "",
+            "samples",
+            "count",
+            "cpu",
+            "nanoseconds",
+            "main",
+            "work",
+            "inlined",
+        ];
+        let pprof = proto::Profile {
+            sample_type: vec![
+                ValueType { r#type: 1, unit: 2 },
+                ValueType { r#type: 3, unit: 4 },
+            ],
+            sample: vec![
+                Sample {
+                    location_id: vec![2, 1],
+                    value: vec![3, 30_000_000],
+                },
+                Sample {
+                    location_id: vec![1],
+                    value: vec![1, 10_000_000],
+                },
+            ],
+            location: vec![
+                Location {
+                    id: 1,
+                    line: vec![Line {
+                        function_id: 1,
+                        line: 10,
+                    }],
+                    ..Default::default()
+                },
+                Location {
+                    id: 2,
+                    line: vec![
+                        Line {
+                            function_id: 3,
+                            line: 20,
+                        },
+                        Line {
+                            function_id: 2,
+                            line: 30,
+                        },
+                    ],
+                    ..Default::default()
+                },
+            ],
+            function: vec![
+                Function { id: 1, name: 5 },
+                Function { id: 2, name: 6 },
+                Function { id: 3, name: 7 },
+            ],
+            string_table: strings.iter().map(|s| s.to_string()).collect(),
+            period_type: Some(ValueType { r#type: 3, unit: 4 }),
+            period: 10_000_000,
+            ..Default::default()
+        };
+        let props = ProfileCreationProps {
+            profile_name: "cpu.pprof".to_owned(),
+            main_thread_only: false,
+            reuse_threads: false,
+            recursion_folding: RecursionFolding::None,
+            unlink_aux_files: false,
+            create_per_cpu_threads: false,
+            override_arch: None,
+            unstable_presymbolicate: false,
+            coreclr: Default::default(),
+            unknown_event_markers: false,
+            post_processing: Default::default(),
+        };
+        let profile = convert(&pprof.encode_to_vec()[..], props).unwrap();
+        let json = serde_json::to_value(&profile).unwrap();
+        let thread = &json["threads"][0];
+        assert_eq!(thread["samples"]["weight"], serde_json::json!([3, 1]));
+
+        // The first sample's stack is main -> work -> inlined.
+        let names = crate::profile_tools::frame_names(thread);
+        let stack = thread["samples"]["stack"][0].as_u64().unwrap() as usize;
+        let prefixes: Vec<_> = thread["stackTable"]["prefix"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .collect();
+        let frames: Vec<_> = thread["stackTable"]["frame"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .collect();
+        let stack_names: Vec<&str> = crate::profile_tools::stack_frames(&prefixes, &frames, stack)
+            .into_iter()
+            .map(|frame| names[frame].as_str())
+            .collect();
+        assert_eq!(stack_names, ["main", "work", "inlined"]);
+    }
+}
@@ -0,0 +1,343 @@
+//! The input formats of `samply import`. Each format is an [`Importer`],
+//! and the [`ImporterRegistry`] picks the importer for a file, either by the
+//! name given with `--format` or by looking at the file.
+//!
+//! New formats, e.g. behind a feature flag, implement [`Importer`] and are
+//! added with [`ImporterRegistry::register`].
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::SystemTime;
+
+use clap::builder::PossibleValue;
+use fxprof_processed_profile::Profile;
+
+use crate::shared::included_processes::IncludedProcesses;
+use crate::shared::recording_props::ProfileCreationProps;
+
+pub type ImportError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The file which is imported, and the import options which only some
+/// formats use.
+pub struct ImportInput<'a> {
+    pub path: &'a Path,
+    pub file: &'a File,
+    /// A directory with the binaries of the profiled machine, for formats
+    /// which refer to binaries by path.
+    pub binary_dir: Option<&'a Path>,
+    /// Only used for ETW traces.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    pub included_processes: Option<IncludedProcesses>,
+}
+
+impl ImportInput<'_> {
+    pub fn file_mod_time(&self) -> Option<SystemTime> {
+        self.file.metadata().ok()?.modified().ok()
+    }
+}
+
+pub trait Importer {
+    /// The name for `samply import --format`.
+    fn name(&self) -> &'static str;
+
+    /// What the format is, for the help text and error messages, e.g.
+    /// "pprof profile".
+    fn description(&self) -> &'static str;
+
+    /// Whether the file looks like it has this format. Used when no format
+    /// is given on the command line.
+    fn matches(&self, path: &Path, file: &File) -> bool;
+
+    fn convert(
+        &self,
+        input: ImportInput,
+        profile_creation_props: ProfileCreationProps,
+    ) -> Result<Profile, ImportError>;
+}
+
+pub struct ImporterRegistry {
+    /// In the order in which they're tried on files of unknown format.
+    importers: Vec<Box<dyn Importer>>,
+}
+
+impl Default for ImporterRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ImporterRegistry {
+    /// A registry with the built-in formats.
+    pub fn new() -> Self {
+        Self {
+            importers: vec![
+                Box::new(EtlImporter),
+                Box::new(SimpleperfImporter),
+                Box::new(MinidumpImporter),
+                Box::new(CollapsedImporter),
+                Box::new(LttngImporter),
+                Box::new(PprofImporter),
+                // Matches any file, so it needs to come last.
+                Box::new(PerfImporter),
+            ],
+        }
+    }
+
+    /// Adds an importer. Added importers are tried before the built-in ones,
+    /// so that they can claim files which a built-in importer would accept.
+    #[allow(unused)]
+    pub fn register(&mut self, importer: Box<dyn Importer>) {
+        self.importers.insert(0, importer);
+    }
+
+    pub fn find(&self, name: &str) -> Option<&dyn Importer> {
+        self.importers
+            .iter()
+            .find(|importer| importer.name() == name)
+            .map(Box::as_ref)
+    }
+
+    /// The first importer which accepts the file.
+    pub fn detect(&self, path: &Path, file: &File) -> Option<&dyn Importer> {
+        self.importers
+            .iter()
+            .find(|importer| importer.matches(path, file))
+            .map(Box::as_ref)
+    }
+
+    /// The format names, for clap.
+    pub fn possible_values(&self) -> Vec<PossibleValue> {
+        self.importers
+            .iter()
+            .map(|importer| PossibleValue::new(importer.name()).help(importer.description()))
+            .collect()
+    }
+}
+
+struct EtlImporter;
+
+impl Importer for EtlImporter {
+    fn name(&self) -> &'static str {
+        "etl"
+    }
+
+    fn description(&self) -> &'static str {
+        "ETW trace"
+    }
+
+    fn matches(&self, path: &Path, _file: &File) -> bool {
+        path.extension().is_some_and(|ext| ext == "etl")
+    }
+
+    #[cfg(target_os = "windows")]
+    fn convert(
+        &self,
+        input: ImportInput,
+        profile_creation_props: ProfileCreationProps,
+    ) -> Result<Profile, ImportError> {
+        Ok(crate::windows::import::convert_etl_file(
+            input.path,
+            profile_creation_props,
+            input.included_processes,
+        ))
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn convert(
+        &self,
+        _input: ImportInput,
+        _profile_creation_props: ProfileCreationProps,
+    ) -> Result<Profile, ImportError> {
+        Err("Importing ETW traces is only supported on Windows.".into())
+    }
+}
+
+struct SimpleperfImporter;
+
+impl Importer for SimpleperfImporter {
+    fn name(&self) -> &'static str {
+        "simpleperf"
+    }
+
+    fn description(&self) -> &'static str {
+        "simpleperf protobuf file"
+    }
+
+    fn matches(&self, _path: &Path, file: &File) -> bool {
+        super::simpleperf::is_simpleperf_proto_file(file)
+    }
+
+    fn convert(
+        &self,
+        input: ImportInput,
+        profile_creation_props: ProfileCreationProps,
+    ) -> Result<Profile, ImportError> {
+        let reader = BufReader::new(input.file);
+        let profile =
+            super::simpleperf::convert(reader, input.file_mod_time(), profile_creation_props)?;
+        Ok(profile)
+    }
+}
+
+struct MinidumpImporter;
+
+impl Importer for MinidumpImporter {
+    fn name(&self) -> &'static str {
+        "minidump"
+    }
+
+    fn description(&self) -> &'static str {
+        "minidump"
+    }
+
+    fn matches(&self, _path: &Path, file: &File) -> bool {
+        super::minidump::is_minidump_file(file)
+    }
+
+    fn convert(
+        &self,
+        input: ImportInput,
+        profile_creation_props: ProfileCreationProps,
+    ) -> Result<Profile, ImportError> {
+        let reader = BufReader::new(input.file);
+        Ok(super::minidump::convert(reader, profile_creation_props)?)
+    }
+}
+
+struct CollapsedImporter;
+
+impl Importer for CollapsedImporter {
+    fn name(&self) -> &'static str {
+        "collapsed"
+    }
+
+    fn description(&self) -> &'static str {
+        "folded stacks"
+    }
+
+    fn matches(&self, path: &Path, _file: &File) -> bool {
+        super::collapsed::is_collapsed_file_name(path)
+    }
+
+    fn convert(
+        &self,
+        input: ImportInput,
+        profile_creation_props: ProfileCreationProps,
+    ) -> Result<Profile, ImportError> {
+        let reader = BufReader::new(input.file);
+        let profile =
+            super::collapsed::convert(reader, input.file_mod_time(), profile_creation_props)?;
+        Ok(profile)
+    }
+}
+
+struct LttngImporter;
+
+impl Importer for LttngImporter {
+    fn name(&self) -> &'static str {
+        "lttng"
+    }
+
+    fn description(&self) -> &'static str {
+        "LTTng trace printed by babeltrace2"
+    }
+
+    fn matches(&self, _path: &Path, file: &File) -> bool {
+        super::lttng::is_babeltrace_text_file(file)
+    }
+
+    fn convert(
+        &self,
+        input: ImportInput,
+        profile_creation_props: ProfileCreationProps,
+    ) -> Result<Profile, ImportError> {
+        let trace = super::lttng::read_trace(BufReader::new(input.file))?;
+        Ok(super::lttng::convert(
+            &trace,
+            input.file_mod_time(),
+            profile_creation_props,
+        ))
+    }
+}
+
+struct PprofImporter;
+
+impl Importer for PprofImporter {
+    fn name(&self) -> &'static str {
+        "pprof"
+    }
+
+    fn description(&self) -> &'static str {
+        "pprof profile"
+    }
+
+    fn matches(&self, path: &Path, _file: &File) -> bool {
+        super::pprof::is_pprof_file_name(path)
+    }
+
+    fn convert(
+        &self,
+        input: ImportInput,
+        profile_creation_props: ProfileCreationProps,
+    ) -> Result<Profile, ImportError> {
+        let reader = BufReader::new(input.file);
+        Ok(super::pprof::convert(reader, profile_creation_props)?)
+    }
+}
+
+struct PerfImporter;
+
+impl Importer for PerfImporter {
+    fn name(&self) -> &'static str {
+        "perf"
+    }
+
+    fn description(&self) -> &'static str {
+        "perf.data file"
+    }
+
+    fn matches(&self, _path: &Path, _file: &File) -> bool {
+        true
+    }
+
+    fn convert(
+        &self,
+        input: ImportInput,
+        profile_creation_props: ProfileCreationProps,
+    ) -> Result<Profile, ImportError> {
+        let path = input.path.canonicalize()?;
+        let binary_dir = input.binary_dir.or(path.parent());
+        let reader = BufReader::new(input.file);
+        let profile = super::perf::convert(
+            reader,
+            input.file_mod_time(),
+            binary_dir,
+            profile_creation_props,
+        )?;
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_importers() {
+        let registry = ImporterRegistry::new();
+        assert_eq!(registry.find("pprof").unwrap().name(), "pprof");
+        assert!(registry.find("unknown").is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let detect = |file_name: &str| {
+            let path = dir.path().join(file_name);
+            std::fs::write(&path, "main;work 3\n").unwrap();
+            let file = File::open(&path).unwrap();
+            registry.detect(&path, &file).unwrap().name()
+        };
+        assert_eq!(detect("stacks.folded"), "collapsed");
+        assert_eq!(detect("cpu.pb.gz"), "pprof");
+        assert_eq!(detect("perf.data"), "perf");
+    }
+}
@@ -0,0 +1,399 @@
+//! Converts the protobuf output of `simpleperf report-sample --protobuf`
+//! into the Firefox Profiler format.
+//!
+//! simpleperf's perf.data files are imported by the perf importer. The
+//! protobuf output is useful when only it is available, e.g. from Android
+//! Studio or from scripts around simpleperf. Its call chains are already
+//! symbolicated, including JIT-compiled and interpreted Java methods, so all
+//! frames become label frames.
+//!
+//! The file starts with the magic `SIMPLEPERF` and a u16 version, followed by
+//! records which are each prefixed with their u32 size. A size of zero ends
+//! the file.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::SystemTime;
+
+use fxprof_processed_profile::{
+    CategoryColor, CategoryHandle, CategoryPairHandle, CpuDelta, Frame, FrameFlags, FrameInfo,
+    ProcessHandle, Profile, ReferenceTimestamp, SamplingInterval, ThreadHandle, Timestamp,
+};
+use prost::Message;
+
+use crate::shared::recording_props::ProfileCreationProps;
+
+const MAGIC: &[u8] = b"SIMPLEPERF";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid simpleperf record: {0}")]
+    Decode(#[from] prost::DecodeError),
+
+    #[error("Not a simpleperf protobuf file")]
+    NoMagic,
+
+    #[error("Unsupported simpleperf protobuf version {0}")]
+    UnsupportedVersion(u16),
+}
+
+/// Whether the file starts with the magic of simpleperf's protobuf output.
+/// The file position is restored afterwards.
+pub fn is_simpleperf_proto_file(mut file: impl Read + Seek) -> bool {
+    let mut magic = [0; MAGIC.len()];
+    let matches = file.read_exact(&mut magic).is_ok() && magic == MAGIC;
+    let _ = file.seek(SeekFrom::Start(0));
+    matches
+}
+
+pub fn convert(
+    mut reader: impl Read,
+    file_mod_time: Option<SystemTime>,
+    profile_creation_props: ProfileCreationProps,
+) -> Result<Profile, Error> {
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(Error::NoMagic);
+    }
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if version != 1 {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    let mut records = Vec::new();
+    loop {
+        let mut size = [0; 4];
+        reader.read_exact(&mut size)?;
+        let size = u32::from_le_bytes(size) as usize;
+        if size == 0 {
+            break;
+        }
+        let mut data = vec![0; size];
+        reader.read_exact(&mut data)?;
+        if let Some(record) = proto::Record::decode(&data[..])?.record_data {
+            records.push(record);
+        }
+    }
+
+    let reference_timestamp =
+        ReferenceTimestamp::from_system_time(file_mod_time.unwrap_or_else(SystemTime::now));
+    Ok(convert_records(
+        records,
+        reference_timestamp,
+        &profile_creation_props,
+    ))
+}
+
+fn convert_records(
+    records: Vec<proto::RecordData>,
+    reference_timestamp: ReferenceTimestamp,
+    profile_creation_props: &ProfileCreationProps,
+) -> Profile {
+    use proto::RecordData;
+
+    let mut samples = Vec::new();
+    let mut files = HashMap::new();
+    let mut threads = HashMap::new();
+    let mut meta_info = None;
+    for record in records {
+        match record {
+            RecordData::Sample(sample) => samples.push(sample),
+            RecordData::File(file) => {
+                files.insert(file.id, file);
+            }
+            RecordData::Thread(thread) => {
+                threads.insert(thread.thread_id, thread);
+            }
+            RecordData::MetaInfo(info) => meta_info = Some(info),
+            RecordData::Lost(lost) if lost.lost_count > 0 => {
+                eprintln!(
+                    "simpleperf lost {} of {} samples.",
+                    lost.lost_count, lost.sample_count
+                );
+            }
+            _ => {}
+        }
+    }
+
+    let mut profile = Profile::new(
+        &profile_creation_props.profile_name,
+        reference_timestamp,
+        SamplingInterval::from_millis(1),
+    );
+    let java_category: CategoryPairHandle =
+        profile.add_category("Java", CategoryColor::Green).into();
+
+    // Only the first event type is converted, like the main event of
+    // perf.data files. Clock events count nanoseconds.
+    let event_type = meta_info
+        .as_ref()
+        .and_then(|info| info.event_type.first())
+        .map_or("", String::as_str);
+    let is_clock_event = event_type.ends_with("clock");
+    let app_package_name = meta_info
+        .as_ref()
+        .map(|info| info.app_package_name.as_str())
+        .filter(|name| !name.is_empty());
+
+    let first_sample_time = samples.iter().map(|s| s.time).min().unwrap_or(0);
+    let mut processes: HashMap<u32, ProcessHandle> = HashMap::new();
+    let mut profile_threads: HashMap<u32, ThreadHandle> = HashMap::new();
+    for sample in samples.iter().filter(|s| s.event_type_id == 0) {
+        let timestamp = Timestamp::from_nanos_since_reference(sample.time - first_sample_time);
+        let tid = sample.thread_id as u32;
+        let thread_handle = *profile_threads.entry(tid).or_insert_with(|| {
+            let thread_info = threads.get(&tid);
+            let pid = thread_info.map_or(tid, |thread| thread.process_id);
+            let process = *processes.entry(pid).or_insert_with(|| {
+                let process_name = threads
+                    .get(&pid)
+                    .map(|main_thread| main_thread.thread_name.as_str())
+                    .or(app_package_name)
+                    .unwrap_or("Process");
+                profile.add_process(process_name, pid, timestamp)
+            });
+            let thread = profile.add_thread(process, tid, timestamp, tid == pid);
+            if let Some(thread_info) = thread_info {
+                profile.set_thread_name(thread, &thread_info.thread_name);
+            }
+            thread
+        });
+
+        // The first call chain entry is the leaf.
+        let frames: Vec<FrameInfo> = sample
+            .callchain
+            .iter()
+            .rev()
+            .map(|entry| {
+                let file = files.get(&entry.file_id);
+                let symbol = usize::try_from(entry.symbol_id)
+                    .ok()
+                    .and_then(|index| file?.symbol.get(index));
+                let name = match (symbol, file) {
+                    (Some(symbol), _) => symbol.clone(),
+                    (None, Some(file)) => {
+                        let file_name = file.path.rsplit('/').next().unwrap_or(&file.path);
+                        format!("{file_name}+0x{:x}", entry.vaddr_in_file)
+                    }
+                    (None, None) => format!("0x{:x}", entry.vaddr_in_file),
+                };
+                let category_pair = match entry.execution_type {
+                    proto::EXECUTION_TYPE_INTERPRETED_JVM_METHOD
+                    | proto::EXECUTION_TYPE_JIT_JVM_METHOD => java_category,
+                    _ => CategoryHandle::OTHER.into(),
+                };
+                FrameInfo {
+                    frame: Frame::Label(profile.intern_string(&name)),
+                    category_pair,
+                    flags: FrameFlags::empty(),
+                }
+            })
+            .collect();
+        let cpu_delta = if is_clock_event {
+            CpuDelta::from_nanos(sample.event_count)
+        } else {
+            CpuDelta::ZERO
+        };
+        profile.add_sample(thread_handle, timestamp, frames.into_iter(), cpu_delta, 1);
+    }
+    profile
+}
+
+/// The messages of simpleperf's cmd_report_sample.proto which samply uses.
+mod proto {
+    pub const EXECUTION_TYPE_INTERPRETED_JVM_METHOD: i32 = 1;
+    pub const EXECUTION_TYPE_JIT_JVM_METHOD: i32 = 2;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Record {
+        #[prost(oneof = "RecordData", tags = "1, 2, 3, 4, 5")]
+        pub record_data: Option<RecordData>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum RecordData {
+        #[prost(message, tag = "1")]
+        Sample(Sample),
+        #[prost(message, tag = "2")]
+        Lost(LostSituation),
+        #[prost(message, tag = "3")]
+        File(File),
+        #[prost(message, tag = "4")]
+        Thread(Thread),
+        #[prost(message, tag = "5")]
+        MetaInfo(MetaInfo),
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Sample {
+        /// Monotonic clock time in nanoseconds.
+        #[prost(uint64, tag = "1")]
+        pub time: u64,
+        #[prost(int32, tag = "2")]
+        pub thread_id: i32,
+        #[prost(message, repeated, tag = "3")]
+        pub callchain: Vec<CallChainEntry>,
+        #[prost(uint64, tag = "4")]
+        pub event_count: u64,
+        #[prost(uint32, tag = "5")]
+        pub event_type_id: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CallChainEntry {
+        #[prost(uint64, tag = "1")]
+        pub vaddr_in_file: u64,
+        #[prost(uint32, tag = "2")]
+        pub file_id: u32,
+        /// -1 if the address has no symbol.
+        #[prost(int32, tag = "3")]
+        pub symbol_id: i32,
+        #[prost(int32, tag = "4")]
+        pub execution_type: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct LostSituation {
+        #[prost(uint64, tag = "1")]
+        pub sample_count: u64,
+        #[prost(uint64, tag = "2")]
+        pub lost_count: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct File {
+        #[prost(uint32, tag = "1")]
+        pub id: u32,
+        #[prost(string, tag = "2")]
+        pub path: String,
+        #[prost(string, repeated, tag = "3")]
+        pub symbol: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Thread {
+        #[prost(uint32, tag = "1")]
+        pub thread_id: u32,
+        #[prost(uint32, tag = "2")]
+        pub process_id: u32,
+        #[prost(string, tag = "3")]
+        pub thread_name: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MetaInfo {
+        #[prost(string, repeated, tag = "1")]
+        pub event_type: Vec<String>,
+        #[prost(string, tag = "2")]
+        pub app_package_name: String,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::proto::{CallChainEntry, File, MetaInfo, Record, RecordData, Sample, Thread};
+    use super::*;
+    use crate::shared::recursion_folding::RecursionFolding;
+
+    fn encode_file(records: Vec<RecordData>) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&1u16.to_le_bytes());
+        for record in records {
+            let record = Record {
+                record_data: Some(record),
+            }
+            .encode_to_vec();
+            data.extend_from_slice(&(record.len() as u32).to_le_bytes());
+            data.extend_from_slice(&record);
+        }
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn convert_report_sample_output() {
+        let entry = |symbol_id, execution_type| CallChainEntry {
+            vaddr_in_file: 0x1234,
+            file_id: 1,
+            symbol_id,
+            execution_type,
+        };
+        let data = encode_file(vec![
+            RecordData::MetaInfo(MetaInfo {
+                event_type: vec!["cpu-clock".to_owned()],
+                app_package_name: "com.example.app".to_owned(),
+            }),
+            RecordData::Sample(Sample {
+                time: 5_000_000,
+                thread_id: 11,
+                callchain: vec![entry(-1, 0), entry(1, 2), entry(0, 0)],
+                event_count: 1_000_000,
+                event_type_id: 0,
+            }),
+            RecordData::File(File {
+                id: 1,
+                path: "/system/lib64/libart.so".to_owned(),
+                symbol: vec![
+                    "art_quick_invoke_stub".to_owned(),
+                    "MainActivity.onCreate".to_owned(),
+                ],
+            }),
+            RecordData::Thread(Thread {
+                thread_id: 11,
+                process_id: 10,
+                thread_name: "RenderThread".to_owned(),
+            }),
+        ]);
+        assert!(is_simpleperf_proto_file(std::io::Cursor::new(&data)));
+
+        let props = ProfileCreationProps {
+            profile_name: "app".to_owned(),
+            main_thread_only: false,
+            reuse_threads: false,
+            recursion_folding: RecursionFolding::None,
+            unlink_aux_files: false,
+            create_per_cpu_threads: false,
+            override_arch: None,
+            unstable_presymbolicate: false,
+            coreclr: Default::default(),
+            unknown_event_markers: false,
+            post_processing: Default::default(),
+        };
+        let profile = convert(&data[..], None, props).unwrap();
+        let json = serde_json::to_value(&profile).unwrap();
+        let thread = &json["threads"][0];
+        assert_eq!(thread["name"], "RenderThread");
+        assert_eq!(thread["processName"], "com.example.app");
+
+        let names = crate::profile_tools::frame_names(thread);
+        let stack = thread["samples"]["stack"][0].as_u64().unwrap() as usize;
+        let prefixes: Vec<_> = thread["stackTable"]["prefix"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .collect();
+        let frames: Vec<_> = thread["stackTable"]["frame"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .collect();
+        let stack_names: Vec<&str> = crate::profile_tools::stack_frames(&prefixes, &frames, stack)
+            .into_iter()
+            .map(|frame| names[frame].as_str())
+            .collect();
+        assert_eq!(
+            stack_names,
+            [
+                "art_quick_invoke_stub",
+                "MainActivity.onCreate",
+                "libart.so+0x1234"
+            ]
+        );
+    }
+}
@@ -0,0 +1,56 @@
+//! samply's recording engine, for programs which want to record profiles
+//! without running the samply command line tool, like test harnesses and
+//! benchmark runners. See [`Recorder`].
+
+#[cfg(target_os = "macos")]
+mod mac;
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+mod linux;
+
+#[cfg(target_os = "windows")]
+mod windows;
+
+mod android;
+mod cli;
+mod compare_symbols;
+mod config;
+mod doctor;
+mod drop_folder;
+mod dump_unwind;
+#[cfg(feature = "grpc")]
+mod grpc_server;
+mod history;
+mod import;
+mod linux_shared;
+mod name;
+mod profile_json_preparse;
+mod profile_tools;
+mod publish_symbols;
+mod server;
+mod server_health;
+mod server_limits;
+mod server_metrics;
+mod server_tls;
+mod shared;
+mod symbol_cache;
+mod top;
+mod warm_symbols;
+#[cfg(any(
+    target_os = "android",
+    target_os = "macos",
+    target_os = "linux",
+    target_os = "windows"
+))]
+mod watch;
+
+pub use fxprof_processed_profile::Profile;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub use linux::recorder::{Recorder, RecorderOptions};
+
+/// Runs the samply command line tool. This is only public so that the
+/// binary can call it.
+#[doc(hidden)]
+pub fn run_cli() {
+    cli::main();
+}
@@ -0,0 +1,73 @@
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::shared::app_markers::{parse_app_marker_message, AppMarkerEvent};
+
+/// A Unix socket on which the launched process can send markers, see
+/// [`MARKER_SOCKET_ENV_VAR`](crate::shared::app_markers::MARKER_SOCKET_ENV_VAR).
+///
+/// Connections are accepted and read on background threads, which forward
+/// the parsed messages to the receiver.
+pub struct AppMarkerSocket {
+    path: PathBuf,
+    sender: Sender<AppMarkerEvent>,
+    receiver: Receiver<AppMarkerEvent>,
+}
+
+impl AppMarkerSocket {
+    pub fn bind() -> io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("samply-markers-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let listener_sender = sender.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = listener_sender.clone();
+                thread::spawn(move || read_messages(stream, &sender));
+            }
+        });
+        Ok(Self {
+            path,
+            sender,
+            receiver,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// A sender for markers from other sources, which then arrive at the
+    /// receiver together with the markers from the socket.
+    pub fn sender(&self) -> Sender<AppMarkerEvent> {
+        self.sender.clone()
+    }
+
+    pub fn receiver(&self) -> &Receiver<AppMarkerEvent> {
+        &self.receiver
+    }
+}
+
+impl Drop for AppMarkerSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn read_messages(stream: UnixStream, sender: &Sender<AppMarkerEvent>) {
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if let Some(event) = parse_app_marker_message(&line) {
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+use crate::shared::focus_events::FocusEvent;
+
+/// Tracks the focused window during the recording, by watching the
+/// `_NET_ACTIVE_WINDOW` property of the X11 root window with `xprop -spy`.
+///
+/// This only works on X11 desktops. Wayland has no protocol which lets us
+/// observe the focused window of other clients, and in a Wayland session
+/// xprop would only see the XWayland windows.
+pub struct FocusEventRecorder {
+    xprop: Child,
+    thread: JoinHandle<Vec<FocusEvent>>,
+}
+
+impl FocusEventRecorder {
+    /// Starts `xprop`. Fails if this isn't an X11 session or if xprop isn't
+    /// installed.
+    pub fn start() -> Result<Self, String> {
+        if is_wayland_session() {
+            return Err(
+                "Focus markers are not supported in Wayland sessions, which don't let us \
+                 observe the focused window."
+                    .to_owned(),
+            );
+        }
+        if std::env::var_os("DISPLAY").is_none() {
+            return Err("Focus markers need an X11 display, but DISPLAY is not set.".to_owned());
+        }
+        let mut xprop = Command::new("xprop")
+            .args(["-root", "-spy", "_NET_ACTIVE_WINDOW"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("Could not run xprop, which focus markers need: {err}"))?;
+        let stdout = xprop.stdout.take().expect("xprop's stdout is piped");
+        let thread = thread::spawn(move || {
+            BufReader::new(stdout)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| parse_active_window_line(&line))
+                .map(|window_id| {
+                    let timestamp_raw = monotonic_time_ns();
+                    let (app, window_title) = match window_id {
+                        0 => (None, None),
+                        window_id => query_window_info(window_id),
+                    };
+                    FocusEvent {
+                        timestamp_raw,
+                        app,
+                        window_title,
+                    }
+                })
+                .collect()
+        });
+        Ok(Self { xprop, thread })
+    }
+
+    /// Stops watching the focus and returns the focus changes, together with
+    /// the stop time.
+    pub fn stop(mut self) -> (Vec<FocusEvent>, u64) {
+        let end_raw = monotonic_time_ns();
+        let _ = self.xprop.kill();
+        let _ = self.xprop.wait();
+        let events = self.thread.join().unwrap_or_default();
+        (events, end_raw)
+    }
+}
+
+/// Returns the current time of CLOCK_MONOTONIC, which is also the clock of
+/// our perf events.
+pub fn monotonic_time_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+fn is_wayland_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+        || std::env::var("XDG_SESSION_TYPE").is_ok_and(|session_type| session_type == "wayland")
+}
+
+/// Parses a line like `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x4a00003`.
+fn parse_active_window_line(line: &str) -> Option<u64> {
+    let (_, id) = line.rsplit_once("# ")?;
+    u64::from_str_radix(id.trim().strip_prefix("0x")?, 16).ok()
+}
+
+/// Returns the application name (the class part of WM_CLASS) and the title
+/// of the given window.
+fn query_window_info(window_id: u64) -> (Option<String>, Option<String>) {
+    let Ok(output) = Command::new("xprop")
+        .args([
+            "-id",
+            &format!("{window_id:#x}"),
+            "WM_CLASS",
+            "_NET_WM_NAME",
+        ])
+        .stderr(Stdio::null())
+        .output()
+    else {
+        return (None, None);
+    };
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut app = None;
+    let mut title = None;
+    for line in output.lines() {
+        let Some((name, value)) = line.split_once(" = ") else {
+            continue;
+        };
+        // String lists are printed as `"first", "second"`.
+        let last_string = value
+            .rsplit(", ")
+            .next()
+            .map(|s| s.trim_matches('"').to_owned());
+        if name.starts_with("WM_CLASS") {
+            app = last_string;
+        } else if name.starts_with("_NET_WM_NAME") {
+            title = Some(value.trim_matches('"').to_owned());
+        }
+    }
+    (app, title)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_xprop_spy_output() {
+        assert_eq!(
+            parse_active_window_line("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x4a00003"),
+            Some(0x4a00003)
+        );
+        assert_eq!(
+            parse_active_window_line("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x0"),
+            Some(0)
+        );
+        assert_eq!(
+            parse_active_window_line("_NET_ACTIVE_WINDOW:  not found."),
+            None
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use mio::unix::SourceFd;
+use mio::{Events, Interest, Poll, Token};
+
+use super::sys::{
+    InputEvent as RawInputEvent, ABS_X, ABS_Y, BTN_MISC, BTN_TOUCH, EVIOCSCLOCKID, EV_ABS, EV_KEY,
+    EV_REL, KEY_OK, REL_HWHEEL, REL_HWHEEL_HI_RES, REL_WHEEL, REL_WHEEL_HI_RES, REL_X, REL_Y,
+};
+use crate::shared::input_events::{InputEvent, InputEventKind};
+
+/// Reads keyboard and pointer events from the evdev devices in /dev/input
+/// on a background thread, for the duration of the recording.
+pub struct InputEventRecorder {
+    should_stop: Arc<AtomicBool>,
+    thread: JoinHandle<Vec<InputEvent>>,
+}
+
+impl InputEventRecorder {
+    /// Opens all readable input devices and starts the reader thread.
+    ///
+    /// Returns `None` if no device could be opened. Reading input devices
+    /// usually requires root or membership in the "input" group.
+    pub fn start() -> Option<Self> {
+        let devices = open_input_devices();
+        if devices.is_empty() {
+            eprintln!("Warning: Could not open any input devices in /dev/input.");
+            eprintln!("Input event markers require read access to these devices, which is usually");
+            eprintln!("granted by membership in the 'input' group.");
+            return None;
+        }
+
+        let should_stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let should_stop = should_stop.clone();
+            move || read_input_events(devices, &should_stop)
+        });
+        Some(Self {
+            should_stop,
+            thread,
+        })
+    }
+
+    /// Stops the reader thread and returns all events, sorted by timestamp.
+    pub fn stop(self) -> Vec<InputEvent> {
+        self.should_stop.store(true, Ordering::Relaxed);
+        let mut events = self.thread.join().unwrap_or_default();
+        events.sort_by_key(|e| e.timestamp_raw);
+        events
+    }
+}
+
+fn open_input_devices() -> Vec<File> {
+    let Ok(entries) = std::fs::read_dir("/dev/input") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("event"))
+        .filter_map(|entry| {
+            let file = OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(entry.path())
+                .ok()?;
+            // Use the same clock as our perf events, so that the timestamps line up.
+            let clock_id: libc::c_int = libc::CLOCK_MONOTONIC;
+            let result = unsafe { libc::ioctl(file.as_raw_fd(), EVIOCSCLOCKID as _, &clock_id) };
+            if result != 0 {
+                return None;
+            }
+            Some(file)
+        })
+        .collect()
+}
+
+fn read_input_events(mut devices: Vec<File>, should_stop: &AtomicBool) -> Vec<InputEvent> {
+    let mut events = Vec::new();
+    let Ok(mut poll) = Poll::new() else {
+        return events;
+    };
+    for (i, device) in devices.iter().enumerate() {
+        let _ = poll.registry().register(
+            &mut SourceFd(&device.as_raw_fd()),
+            Token(i),
+            Interest::READABLE,
+        );
+    }
+
+    let mut poll_events = Events::with_capacity(16);
+    let mut buffer = vec![0u8; std::mem::size_of::<RawInputEvent>() * 64];
+    while !should_stop.load(Ordering::Relaxed) {
+        if poll
+            .poll(&mut poll_events, Some(Duration::from_millis(100)))
+            .is_err()
+        {
+            break;
+        }
+        for poll_event in poll_events.iter() {
+            let device = &mut devices[poll_event.token().0];
+            loop {
+                match device.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(len) => events.extend(parse_input_events(&buffer[..len])),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+    events
+}
+
+fn parse_input_events(bytes: &[u8]) -> impl Iterator<Item = InputEvent> + '_ {
+    bytes
+        .chunks_exact(std::mem::size_of::<RawInputEvent>())
+        .filter_map(|chunk| {
+            // SAFETY: The chunk has the size of RawInputEvent, and any bit pattern is valid.
+            let raw: RawInputEvent = unsafe { std::ptr::read_unaligned(chunk.as_ptr().cast()) };
+            let kind = input_event_kind(raw.kind, raw.code, raw.value)?;
+            let timestamp_raw =
+                raw.time.tv_sec as u64 * 1_000_000_000 + raw.time.tv_usec as u64 * 1_000;
+            Some(InputEvent {
+                timestamp_raw,
+                kind,
+            })
+        })
+}
+
+fn input_event_kind(kind: u16, code: u16, value: i32) -> Option<InputEventKind> {
+    match kind {
+        EV_KEY if code == BTN_TOUCH => match value {
+            1 => Some(InputEventKind::Touch),
+            _ => None,
+        },
+        EV_KEY if (BTN_MISC..KEY_OK).contains(&code) => match value {
+            1 => Some(InputEventKind::MouseDown),
+            0 => Some(InputEventKind::MouseUp),
+            _ => None,
+        },
+        EV_KEY => match value {
+            1 => Some(InputEventKind::KeyDown),
+            0 => Some(InputEventKind::KeyUp),
+            _ => None, // Ignore auto-repeat.
+        },
+        EV_REL => match code {
+            REL_X | REL_Y => Some(InputEventKind::MouseMove),
+            REL_WHEEL | REL_HWHEEL | REL_WHEEL_HI_RES | REL_HWHEEL_HI_RES => {
+                Some(InputEventKind::MouseWheel)
+            }
+            _ => None,
+        },
+        EV_ABS => match code {
+            ABS_X | ABS_Y => Some(InputEventKind::MouseMove),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,97 @@
+//! JIT symbols for Java.
+//!
+//! HotSpot JVMs (JDK 17 and newer) can write the same /tmp/perf-<pid>.map
+//! file as other JITs, without an agent: launched JVMs write it when they
+//! exit, and JVMs which are still running at the end of the recording are
+//! asked to write it with `jcmd`.
+
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The JVM options for launched processes. `PreserveFramePointer` makes
+/// frame pointer unwinding work through JIT code. Older JVMs ignore the
+/// options they don't know, rather than refusing to start.
+const PERF_MAP_JVM_OPTIONS: &str = "-XX:+IgnoreUnrecognizedVMOptions -XX:+UnlockDiagnosticVMOptions -XX:+DumpPerfMapAtExit -XX:+PreserveFramePointer";
+
+/// Adds our JVM options to `JAVA_TOOL_OPTIONS`, which every JVM picks up,
+/// keeping the options which are already set.
+pub fn add_jvm_perf_map_options(env_vars: &mut Vec<(OsString, OsString)>) {
+    let existing = match env_vars
+        .iter()
+        .position(|(name, _)| name == "JAVA_TOOL_OPTIONS")
+    {
+        Some(index) => Some(env_vars.remove(index).1),
+        None => std::env::var_os("JAVA_TOOL_OPTIONS"),
+    };
+    env_vars.push((
+        "JAVA_TOOL_OPTIONS".into(),
+        java_tool_options_with_perf_map(existing.as_deref()),
+    ));
+}
+
+fn java_tool_options_with_perf_map(existing: Option<&OsStr>) -> OsString {
+    match existing {
+        Some(existing) if !existing.is_empty() => {
+            let mut options = existing.to_owned();
+            options.push(" ");
+            options.push(PERF_MAP_JVM_OPTIONS);
+            options
+        }
+        _ => PERF_MAP_JVM_OPTIONS.into(),
+    }
+}
+
+/// Whether the process has the HotSpot JVM loaded.
+pub fn is_jvm_process(pid: i32) -> bool {
+    std::fs::read_to_string(format!("/proc/{pid}/maps"))
+        .is_ok_and(|maps| maps.lines().any(|line| line.ends_with("/libjvm.so")))
+}
+
+/// Asks a running JVM to write its /tmp/perf-<pid>.map file.
+pub fn dump_jvm_perf_map(pid: i32) {
+    let status = Command::new(find_jcmd(pid))
+        .arg(pid.to_string())
+        .arg("Compiler.perfmap")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            eprintln!("Warning: jcmd {pid} Compiler.perfmap failed ({status}). Java frames won't have names.");
+        }
+        Err(err) => {
+            eprintln!(
+                "Warning: Could not run jcmd to get the JIT symbols of Java process {pid}: {err}"
+            );
+        }
+    }
+}
+
+/// Prefers the jcmd from the same JDK as the JVM, and falls back to the one
+/// in PATH.
+fn find_jcmd(pid: i32) -> PathBuf {
+    std::fs::read_link(format!("/proc/{pid}/exe"))
+        .ok()
+        .and_then(|java| Some(java.parent()?.join("jcmd")))
+        .filter(|jcmd| jcmd.exists())
+        .unwrap_or_else(|| Path::new("jcmd").to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_java_tool_options() {
+        assert_eq!(
+            java_tool_options_with_perf_map(None),
+            OsString::from(PERF_MAP_JVM_OPTIONS)
+        );
+        assert_eq!(
+            java_tool_options_with_perf_map(Some(OsStr::new("-Xmx1g"))),
+            OsString::from(format!("-Xmx1g {PERF_MAP_JVM_OPTIONS}"))
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Sender, TrySendError};
+use fxprof_processed_profile::Profile;
+
+use crate::linux_shared::{Converter, MmapRangeOrVec};
+
+type NativeConverter =
+    Converter<framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>>;
+
+/// Writes snapshots of the profile during the recording, for `--live`, so
+/// that the profiler can be reloaded, or `samply top --follow` can be used, to
+/// see what has been recorded so far.
+///
+/// The snapshots are serialized on a separate thread, so that writing a big
+/// profile doesn't make the recording thread lose events. While the previous
+/// snapshot is still being written, no new snapshot is taken, because taking
+/// one copies the whole profile.
+pub struct LiveSnapshots {
+    interval: Duration,
+    last_snapshot: Option<Instant>,
+    sender: Option<Sender<Profile>>,
+    /// Set from when a snapshot is taken until it has been written.
+    writing: Arc<AtomicBool>,
+    writer_thread: Option<JoinHandle<()>>,
+}
+
+impl LiveSnapshots {
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded::<Profile>(1);
+        let writing = Arc::new(AtomicBool::new(false));
+        let writer_writing = writing.clone();
+        let writer_thread = thread::spawn(move || {
+            for profile in receiver {
+                if let Err(e) = write_atomically(&path, &profile) {
+                    eprintln!("Could not write the live profile to {path:?}: {e}");
+                }
+                writer_writing.store(false, Ordering::Release);
+            }
+        });
+        Self {
+            interval,
+            last_snapshot: None,
+            sender: Some(sender),
+            writing,
+            writer_thread: Some(writer_thread),
+        }
+    }
+
+    /// Writes a snapshot if the last one is long enough ago and has been
+    /// written.
+    pub fn poll(&mut self, converter: &NativeConverter) {
+        self.poll_with(|| converter.snapshot());
+    }
+
+    fn poll_with(&mut self, snapshot: impl FnOnce() -> Profile) {
+        if self
+            .last_snapshot
+            .is_some_and(|last_snapshot| last_snapshot.elapsed() < self.interval)
+        {
+            return;
+        }
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        if self.writing.swap(true, Ordering::Acquire) {
+            return;
+        }
+        self.last_snapshot = Some(Instant::now());
+        match sender.try_send(snapshot()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => self.writing.store(false, Ordering::Release),
+            Err(TrySendError::Disconnected(_)) => self.sender = None,
+        }
+    }
+
+    /// Waits for the snapshot which is being written, so that it doesn't
+    /// replace the final profile.
+    pub fn finish(mut self) {
+        self.sender = None;
+        if let Some(writer_thread) = self.writer_thread.take() {
+            let _ = writer_thread.join();
+        }
+    }
+}
+
+/// Writes the profile to a temporary file next to `path` and renames it, so
+/// that the server never sends a partially written profile.
+fn write_atomically(path: &Path, profile: &Profile) -> std::io::Result<()> {
+    let temp_path = path.with_extension("live.tmp");
+    {
+        let writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(writer, profile)?;
+    }
+    std::fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{ReferenceTimestamp, SamplingInterval};
+
+    use super::*;
+
+    #[test]
+    fn snapshot_replaces_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profile.json");
+        std::fs::write(&path, "old").unwrap();
+        let profile = Profile::new(
+            "app",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        write_atomically(&path, &profile).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(json["meta"]["product"], "app");
+        assert!(!path.with_extension("live.tmp").exists());
+    }
+
+    #[test]
+    fn no_snapshot_while_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profile.json");
+        let mut snapshots = LiveSnapshots::new(path.clone(), Duration::ZERO);
+        snapshots.writing.store(true, Ordering::Release);
+        snapshots.poll_with(|| panic!("took a snapshot while the last one was being written"));
+
+        snapshots.writing.store(false, Ordering::Release);
+        snapshots.poll_with(|| {
+            Profile::new(
+                "app",
+                ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+                SamplingInterval::from_millis(1),
+            )
+        });
+        snapshots.finish();
+        assert!(path.exists());
+    }
+}
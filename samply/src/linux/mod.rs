@@ -1,7 +1,16 @@
+mod app_marker_socket;
+mod focus_events;
+mod input_events;
+mod jvm;
+mod live;
+mod output_capture;
+mod overhead;
 mod perf_event;
 mod perf_group;
 mod proc_maps;
 mod process;
 pub mod profiler;
+pub mod recorder;
 mod sorter;
 mod sys;
+mod user_markers;
@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crossbeam_channel::Sender;
+
+use super::focus_events::monotonic_time_ns;
+use crate::shared::app_markers::{AppMarkerEvent, AppMarkerEventKind};
+use crate::shared::output_markers::{OutputLine, OutputMarkerPatterns, OutputStream};
+
+/// What happens to the lines of the launched command's output.
+#[derive(Clone, Default)]
+pub struct OutputWatchers {
+    /// For `--marker-from-output`: Lines which match the patterns are sent
+    /// as instant markers for the main thread of the process.
+    pub markers: Option<(OutputMarkerPatterns, Sender<AppMarkerEvent>)>,
+    /// For `--log-output`: All lines are collected here.
+    pub log: Option<OutputLog>,
+}
+
+impl OutputWatchers {
+    pub fn is_empty(&self) -> bool {
+        self.markers.is_none() && self.log.is_none()
+    }
+}
+
+/// The output lines of all launched processes, for `--log-output`.
+#[derive(Debug, Clone, Default)]
+pub struct OutputLog(Arc<Mutex<Vec<OutputLine>>>);
+
+impl OutputLog {
+    /// Returns the collected lines, sorted by timestamp.
+    pub fn take_lines(&self) -> Vec<OutputLine> {
+        let mut lines = std::mem::take(&mut *self.0.lock().unwrap());
+        lines.sort_by_key(|line| line.timestamp_raw);
+        lines
+    }
+}
+
+/// Pipes which replace the stdout and stderr of a launched process, so that
+/// its output lines can become markers. The output is passed through to our
+/// own stdout and stderr.
+pub struct OutputCapture {
+    stdout_pipe: (OwnedFd, OwnedFd),
+    stderr_pipe: (OwnedFd, OwnedFd),
+}
+
+impl OutputCapture {
+    pub fn new() -> io::Result<Self> {
+        let stdout_pipe = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC)?;
+        let stderr_pipe = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC)?;
+        Ok(Self {
+            stdout_pipe,
+            stderr_pipe,
+        })
+    }
+
+    /// The write ends of the pipes, which the child process installs as its
+    /// stdout and stderr.
+    pub fn child_fds(&self) -> [RawFd; 2] {
+        [
+            self.stdout_pipe.1.as_raw_fd(),
+            self.stderr_pipe.1.as_raw_fd(),
+        ]
+    }
+
+    /// Closes our copies of the write ends and starts reading the output of
+    /// the process with this pid.
+    pub fn start_watching(self, pid: i32, watchers: &OutputWatchers) {
+        let (stdout_read, stdout_write) = self.stdout_pipe;
+        let (stderr_read, stderr_write) = self.stderr_pipe;
+        drop((stdout_write, stderr_write));
+        let stdout_watchers = watchers.clone();
+        thread::spawn(move || {
+            pass_through_lines(
+                stdout_read,
+                OutputStream::Stdout,
+                io::stdout(),
+                pid,
+                &stdout_watchers,
+            )
+        });
+        let watchers = watchers.clone();
+        thread::spawn(move || {
+            pass_through_lines(
+                stderr_read,
+                OutputStream::Stderr,
+                io::stderr(),
+                pid,
+                &watchers,
+            )
+        });
+    }
+}
+
+fn pass_through_lines(
+    pipe: OwnedFd,
+    stream: OutputStream,
+    mut output: impl Write,
+    pid: i32,
+    watchers: &OutputWatchers,
+) {
+    let mut reader = BufReader::new(File::from(pipe));
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let timestamp_raw = monotonic_time_ns();
+        let _ = output.write_all(&line);
+        let _ = output.flush();
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+        if let Some(log) = &watchers.log {
+            log.0.lock().unwrap().push(OutputLine {
+                timestamp_raw,
+                stream,
+                text: text.to_string(),
+            });
+        }
+        let Some((patterns, sender)) = &watchers.markers else {
+            continue;
+        };
+        let Some(marker) = patterns.marker_for_line(text) else {
+            continue;
+        };
+        let _ = sender.send(AppMarkerEvent {
+            kind: AppMarkerEventKind::Instant,
+            timestamp_raw,
+            tid: pid,
+            name: marker.name,
+            payload: marker.payload,
+        });
+    }
+}
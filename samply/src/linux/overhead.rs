@@ -0,0 +1,87 @@
+use super::focus_events::monotonic_time_ns;
+use crate::linux_shared::{Converter, MmapRangeOrVec};
+use crate::shared::profiler_overhead::{OverheadSample, OverheadTotals};
+
+type NativeConverter =
+    Converter<framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>>;
+
+/// How often the overhead counters get a sample.
+const SAMPLE_INTERVAL_NS: u64 = 100_000_000;
+
+/// Measures samply's own overhead during the recording, for
+/// `--measure-overhead`.
+pub struct OverheadMeter {
+    /// The profiled process, which the counters are shown on.
+    pid: i32,
+    start_raw: u64,
+    last_sample_raw: u64,
+    last_cpu_time_ns: u64,
+    pending_wakeups: u64,
+    pending_lost_records: u64,
+    totals: OverheadTotals,
+}
+
+impl OverheadMeter {
+    pub fn new(pid: u32) -> Self {
+        let now = monotonic_time_ns();
+        Self {
+            pid: pid as i32,
+            start_raw: now,
+            last_sample_raw: now,
+            last_cpu_time_ns: own_cpu_time_ns(),
+            pending_wakeups: 0,
+            pending_lost_records: 0,
+            totals: OverheadTotals::default(),
+        }
+    }
+
+    /// Called whenever the ring buffers have been read.
+    pub fn on_wakeup(&mut self) {
+        self.pending_wakeups += 1;
+    }
+
+    pub fn on_lost_records(&mut self, count: u64) {
+        self.pending_lost_records += count;
+    }
+
+    /// Adds a sample to the counters if the last one is long enough ago.
+    pub fn poll(&mut self, converter: &mut NativeConverter) {
+        let now = monotonic_time_ns();
+        if now - self.last_sample_raw >= SAMPLE_INTERVAL_NS {
+            self.add_sample(now, converter);
+        }
+    }
+
+    /// Adds the last sample and the totals.
+    pub fn finish(mut self, converter: &mut NativeConverter) {
+        let now = monotonic_time_ns();
+        self.add_sample(now, converter);
+        self.totals.duration_ns = now - self.start_raw;
+        converter.add_overhead_totals(&self.totals);
+    }
+
+    fn add_sample(&mut self, now: u64, converter: &mut NativeConverter) {
+        let cpu_time_ns = own_cpu_time_ns();
+        let sample = OverheadSample {
+            timestamp_raw: now,
+            cpu_time_ns: cpu_time_ns.saturating_sub(self.last_cpu_time_ns),
+            wakeups: std::mem::take(&mut self.pending_wakeups),
+            lost_records: std::mem::take(&mut self.pending_lost_records),
+        };
+        self.last_sample_raw = now;
+        self.last_cpu_time_ns = cpu_time_ns;
+        self.totals.add(&sample);
+        converter.add_overhead_sample(self.pid, &sample);
+    }
+}
+
+/// The user and system CPU time of all of samply's threads.
+fn own_cpu_time_ns() -> u64 {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return 0;
+    }
+    let timeval_ns =
+        |tv: libc::timeval| tv.tv_sec as u64 * 1_000_000_000 + tv.tv_usec as u64 * 1000;
+    timeval_ns(usage.ru_utime) + timeval_ns(usage.ru_stime)
+}
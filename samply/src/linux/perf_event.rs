@@ -1,10 +1,12 @@
 use std::cell::RefCell;
 use std::cmp::max;
 use std::collections::BinaryHeap;
+use std::ffi::CString;
 use std::ops::Range;
 use std::os::unix::io::RawFd;
 use std::rc::Rc;
 use std::sync::atomic::{fence, Ordering};
+use std::sync::Arc;
 use std::{cmp, fmt, io, mem, ptr, slice};
 
 use libc::{self, c_void, pid_t};
@@ -86,6 +88,8 @@ pub struct Perf {
     fd: RawFd,
     position: u64,
     parse_info: RecordParseInfo,
+    is_aux_event: bool,
+    usdt_probe_index: Option<usize>,
 }
 
 impl Drop for Perf {
@@ -148,6 +152,22 @@ fn next_raw_event(
 pub enum EventSource {
     HwCpuCycles,
     SwCpuClock,
+    HwInstructions,
+    HwCacheReferences,
+    HwCacheMisses,
+    HwBranchMisses,
+}
+
+/// Where to attach a uprobe, for recording the firings of a USDT probe.
+#[derive(Clone, Debug)]
+pub struct UprobeTarget {
+    pub path: Arc<CString>,
+    /// The file offset of the probed instruction.
+    pub offset: u64,
+    /// The file offset of the probe's semaphore, if it has one.
+    pub ref_ctr_offset: Option<u64>,
+    /// The user registers which are sampled when the probe fires.
+    pub regs_mask: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -163,6 +183,8 @@ pub struct PerfBuilder {
     enable_on_exec: bool,
     exclude_kernel: bool,
     gather_context_switches: bool,
+    is_aux_event: bool,
+    usdt_probe: Option<(usize, UprobeTarget)>,
 }
 
 impl PerfBuilder {
@@ -227,6 +249,24 @@ impl PerfBuilder {
         self
     }
 
+    /// Opens the event as the auxiliary event, which is sampled next to the
+    /// main event. Only the main event reports mmaps, comms, tasks and context
+    /// switches, so that they aren't processed twice.
+    pub fn aux_event(mut self) -> Self {
+        self.is_aux_event = true;
+        self
+    }
+
+    /// Opens a uprobe instead of the sampled event, which records a sample
+    /// every time the USDT probe with this index fires. Like the auxiliary
+    /// event, it doesn't report mmaps, comms, tasks and context switches.
+    pub fn usdt_probe(mut self, index: usize, target: UprobeTarget) -> Self {
+        self.stack_size = 0;
+        self.reg_mask = target.regs_mask;
+        self.usdt_probe = Some((index, target));
+        self
+    }
+
     pub fn open(self) -> io::Result<Perf> {
         let pid = self.pid;
         let cpu = self.cpu.map(|cpu| cpu as i32).unwrap_or(-1);
@@ -237,7 +277,8 @@ impl PerfBuilder {
         let inherit = self.inherit;
         let start_disabled = self.start_disabled;
         let exclude_kernel = self.exclude_kernel;
-        let gather_context_switches = self.gather_context_switches;
+        let is_secondary_event = self.is_aux_event || self.usdt_probe.is_some();
+        let gather_context_switches = self.gather_context_switches && !is_secondary_event;
 
         // debug!(
         //     "Opening perf events; pid={}, cpu={}, frequency={}, stack_size={}, reg_mask=0x{:016X}, event_source={:?}, inherit={}, start_disabled={}...",
@@ -295,6 +336,22 @@ impl PerfBuilder {
                 attr.kind = PERF_TYPE_SOFTWARE;
                 attr.config = PERF_COUNT_SW_CPU_CLOCK;
             }
+            EventSource::HwInstructions => {
+                attr.kind = PERF_TYPE_HARDWARE;
+                attr.config = PERF_COUNT_HW_INSTRUCTIONS;
+            }
+            EventSource::HwCacheReferences => {
+                attr.kind = PERF_TYPE_HARDWARE;
+                attr.config = PERF_COUNT_HW_CACHE_REFERENCES;
+            }
+            EventSource::HwCacheMisses => {
+                attr.kind = PERF_TYPE_HARDWARE;
+                attr.config = PERF_COUNT_HW_CACHE_MISSES;
+            }
+            EventSource::HwBranchMisses => {
+                attr.kind = PERF_TYPE_HARDWARE;
+                attr.config = PERF_COUNT_HW_BRANCH_MISSES;
+            }
         }
 
         attr.sample_type = PERF_SAMPLE_IP
@@ -316,6 +373,15 @@ impl PerfBuilder {
         attr.sample_period_or_freq = frequency;
         attr.clock_id = libc::CLOCK_MONOTONIC;
 
+        if let Some((_, target)) = &self.usdt_probe {
+            attr.kind = uprobe_pmu_type()?;
+            // The uprobe PMU's ref_ctr_offset field is in bits 32-63 of config.
+            attr.config = target.ref_ctr_offset.unwrap_or(0) << 32;
+            attr.bp_addr_or_config = target.path.as_ptr() as u64;
+            attr.bp_len_or_config = target.offset;
+            attr.sample_period_or_freq = 1;
+        }
+
         attr.flags = PERF_ATTR_FLAG_DISABLED
             | PERF_ATTR_FLAG_MMAP
             | PERF_ATTR_FLAG_MMAP2
@@ -326,6 +392,18 @@ impl PerfBuilder {
             | PERF_ATTR_FLAG_SAMPLE_ID_ALL
             | PERF_ATTR_FLAG_USE_CLOCKID;
 
+        if self.usdt_probe.is_some() {
+            attr.flags &= !PERF_ATTR_FLAG_FREQ;
+        }
+
+        if is_secondary_event {
+            attr.flags &= !(PERF_ATTR_FLAG_MMAP
+                | PERF_ATTR_FLAG_MMAP2
+                | PERF_ATTR_FLAG_MMAP_DATA
+                | PERF_ATTR_FLAG_COMM
+                | PERF_ATTR_FLAG_TASK);
+        }
+
         if self.enable_on_exec {
             attr.flags |= PERF_ATTR_FLAG_ENABLE_ON_EXEC;
         }
@@ -407,6 +485,8 @@ impl PerfBuilder {
             fd,
             position: 0,
             parse_info,
+            is_aux_event: self.is_aux_event,
+            usdt_probe_index: self.usdt_probe.map(|(index, _)| index),
         };
 
         if !start_disabled {
@@ -417,6 +497,14 @@ impl PerfBuilder {
     }
 }
 
+/// The type of the uprobe PMU, for opening uprobes with perf_event_open.
+fn uprobe_pmu_type() -> io::Result<u32> {
+    let data = std::fs::read_to_string("/sys/bus/event_source/devices/uprobe/type")?;
+    data.trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid uprobe PMU type"))
+}
+
 impl Perf {
     pub fn max_sample_rate() -> Option<u64> {
         let data = std::fs::read_to_string("/proc/sys/kernel/perf_event_max_sample_rate").ok()?;
@@ -436,6 +524,8 @@ impl Perf {
             enable_on_exec: false,
             exclude_kernel: true,
             gather_context_switches: false,
+            is_aux_event: false,
+            usdt_probe: None,
         }
     }
 
@@ -524,6 +614,8 @@ pub struct EventRef {
     prev_position: u64,
     position: u64,
     parse_info: RecordParseInfo,
+    is_aux_event: bool,
+    usdt_probe_index: Option<usize>,
 }
 
 impl fmt::Debug for EventRef {
@@ -551,6 +643,17 @@ impl EventRef {
 
         self.event_location.get(buffer, self.parse_info)
     }
+
+    /// Whether this event comes from the auxiliary event's ring buffer.
+    pub fn is_aux_event(&self) -> bool {
+        self.is_aux_event
+    }
+
+    /// The index of the USDT probe, if this event comes from a uprobe's
+    /// ring buffer.
+    pub fn usdt_probe_index(&self) -> Option<usize> {
+        self.usdt_probe_index
+    }
 }
 
 pub struct EventIter<'a> {
@@ -580,6 +683,8 @@ impl<'a> Iterator for EventIter<'a> {
             prev_position,
             position: perf.position,
             parse_info: self.perf.parse_info,
+            is_aux_event: self.perf.is_aux_event,
+            usdt_probe_index: self.perf.usdt_probe_index,
         })
     }
 }
@@ -10,7 +10,7 @@ use linux_perf_data::linux_perf_event_reader::get_record_timestamp;
 use mio::unix::SourceFd;
 use mio::{Events, Interest, Poll, Token};
 
-use super::perf_event::{EventRef, EventSource, Perf};
+use super::perf_event::{EventRef, EventSource, Perf, PerfBuilder, UprobeTarget};
 use super::sorter::EventSorter;
 
 struct StoppedProcess(u32);
@@ -72,6 +72,8 @@ pub struct PerfGroup {
     stack_size: u32,
     regs_mask: u64,
     event_source: EventSource,
+    aux_event_source: Option<EventSource>,
+    usdt_probes: Vec<UprobeTarget>,
     stopped_processes: Vec<StoppedProcess>,
 }
 
@@ -98,7 +100,14 @@ pub enum AttachMode {
 }
 
 impl PerfGroup {
-    pub fn new(frequency: u32, stack_size: u32, regs_mask: u64, event_source: EventSource) -> Self {
+    pub fn new(
+        frequency: u32,
+        stack_size: u32,
+        regs_mask: u64,
+        event_source: EventSource,
+        aux_event_source: Option<EventSource>,
+        usdt_probes: Vec<UprobeTarget>,
+    ) -> Self {
         PerfGroup {
             event_sorter: EventSorter::new(),
             members: Default::default(),
@@ -107,20 +116,32 @@ impl PerfGroup {
             frequency,
             stack_size,
             event_source,
+            aux_event_source,
+            usdt_probes,
             regs_mask,
             stopped_processes: Vec::new(),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn open(
         pid: u32,
         frequency: u32,
         stack_size: u32,
         event_source: EventSource,
+        aux_event_source: Option<EventSource>,
         regs_mask: u64,
+        usdt_probes: Vec<UprobeTarget>,
         attach_mode: AttachMode,
     ) -> Result<Self, io::Error> {
-        let mut group = PerfGroup::new(frequency, stack_size, regs_mask, event_source);
+        let mut group = PerfGroup::new(
+            frequency,
+            stack_size,
+            regs_mask,
+            event_source,
+            aux_event_source,
+            usdt_probes,
+        );
         group.open_process(pid, attach_mode)?;
         Ok(group)
     }
@@ -150,9 +171,7 @@ impl PerfGroup {
                 builder = builder.enable_on_exec();
             }
 
-            let perf = builder.open()?;
-
-            perf_events.push((Some(cpu), perf));
+            self.open_perfs(builder, Some(cpu), &mut perf_events)?;
         }
 
         if cpu_count * (threads.len() + 1) >= 1000 {
@@ -169,9 +188,7 @@ impl PerfGroup {
                 if attach_mode == AttachMode::AttachWithEnableOnExec {
                     builder = builder.enable_on_exec();
                 }
-                let perf = builder.open()?;
-
-                perf_events.push((None, perf));
+                self.open_perfs(builder, None, &mut perf_events)?;
             }
         } else {
             for cpu in 0..cpu_count as u32 {
@@ -190,9 +207,7 @@ impl PerfGroup {
                     if attach_mode == AttachMode::AttachWithEnableOnExec {
                         builder = builder.enable_on_exec();
                     }
-                    let perf = builder.open()?;
-
-                    perf_events.push((Some(cpu), perf));
+                    self.open_perfs(builder, Some(cpu), &mut perf_events)?;
                 }
             }
         }
@@ -210,6 +225,36 @@ impl PerfGroup {
         Ok(())
     }
 
+    /// Opens the main event, and the auxiliary event and the uprobes for the
+    /// USDT probes with the same settings.
+    fn open_perfs(
+        &mut self,
+        builder: PerfBuilder,
+        cpu: Option<u32>,
+        perf_events: &mut Vec<(Option<u32>, Perf)>,
+    ) -> Result<(), io::Error> {
+        let aux_builder = self
+            .aux_event_source
+            .map(|source| builder.clone().event_source(source).aux_event());
+        perf_events.push((cpu, builder.clone().open()?));
+        if let Some(aux_builder) = aux_builder {
+            perf_events.push((cpu, aux_builder.open()?));
+        }
+        for index in 0..self.usdt_probes.len() {
+            let target = self.usdt_probes[index].clone();
+            match builder.clone().usdt_probe(index, target).open() {
+                Ok(perf) => perf_events.push((cpu, perf)),
+                Err(error) => {
+                    // Don't give up on the recording because of the probes.
+                    eprintln!("Warning: Could not attach to the USDT probes: {error}");
+                    self.usdt_probes.clear();
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn is_empty(&self) -> bool {
         self.members.is_empty()
     }
@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 use std::ffi::{CString, OsStr, OsString};
-use std::os::fd::{AsFd, AsRawFd, IntoRawFd, OwnedFd};
+use std::os::fd::{AsFd, AsRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::os::raw::c_char;
 use std::os::unix::prelude::OsStrExt;
 
@@ -16,10 +16,13 @@ pub struct SuspendedLaunchedProcess {
 }
 
 impl SuspendedLaunchedProcess {
+    /// If `output_fds` is given, the process uses these file descriptors as
+    /// its stdout and stderr.
     pub fn launch_in_suspended_state(
         command_name: &OsStr,
         command_args: &[OsString],
         env_vars: &[(OsString, OsString)],
+        output_fds: Option<[RawFd; 2]>,
     ) -> std::io::Result<Self> {
         let argv: Vec<CString> = std::iter::once(command_name)
             .chain(command_args.iter().map(|s| s.as_os_str()))
@@ -57,6 +60,10 @@ impl SuspendedLaunchedProcess {
                 // std::panic::always_abort();
                 nix::unistd::close(resume_sp.into_raw_fd()).unwrap();
                 nix::unistd::close(execerr_rp.into_raw_fd()).unwrap();
+                if let Some([stdout_fd, stderr_fd]) = output_fds {
+                    nix::unistd::dup2(stdout_fd, libc::STDOUT_FILENO).unwrap();
+                    nix::unistd::dup2(stderr_fd, libc::STDERR_FILENO).unwrap();
+                }
                 Self::run_child(resume_rp, execerr_sp, &argv, envp)
             }
             nix::unistd::ForkResult::Parent { child } => {
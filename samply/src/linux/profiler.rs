@@ -1,33 +1,47 @@
 use std::collections::HashMap;
+use std::ffi::{CString, OsStr, OsString};
 use std::fs::File;
 use std::io::BufWriter;
 use std::ops::Deref;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::process::ExitStatusExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, SystemTime};
 
 use crossbeam_channel::{Receiver, Sender};
-use fxprof_processed_profile::ReferenceTimestamp;
+use fxprof_processed_profile::{Profile, ReferenceTimestamp};
 use linux_perf_data::linux_perf_event_reader::{
     CpuMode, Endianness, EventRecord, Mmap2FileId, Mmap2InodeAndVersion, Mmap2Record, RawData,
 };
 use nix::sys::wait::WaitStatus;
 use tokio::sync::oneshot;
 
-use super::perf_event::EventSource;
+use super::app_marker_socket::AppMarkerSocket;
+use super::focus_events::FocusEventRecorder;
+use super::input_events::InputEventRecorder;
+use super::jvm::{add_jvm_perf_map_options, dump_jvm_perf_map, is_jvm_process};
+use super::live::LiveSnapshots;
+use super::output_capture::{OutputCapture, OutputLog, OutputWatchers};
+use super::overhead::OverheadMeter;
+use super::perf_event::{EventSource, UprobeTarget};
 use super::perf_group::{AttachMode, PerfGroup};
 use super::proc_maps;
 use super::process::SuspendedLaunchedProcess;
+use super::user_markers::UserMarkerRecorder;
 use crate::linux_shared::vdso::VdsoObject;
 use crate::linux_shared::{
-    ConvertRegs, Converter, EventInterpretation, MmapRangeOrVec, OffCpuIndicator,
+    find_usdt_probes, set_record_timestamp, ConvertRegs, Converter, EventInterpretation,
+    MmapRangeOrVec, OffCpuIndicator, UsdtProbe, UsdtProbeSpec,
 };
-use crate::server::{start_server_main, ServerProps};
+use crate::profile_tools::{post_process_profile_file, PostProcessingOptions};
+use crate::server::{start_live_server_main, start_server_main, ServerProps};
+use crate::shared::app_markers::{AppMarkerEvent, MARKER_SOCKET_ENV_VAR};
 use crate::shared::ctrl_c::CtrlC;
 use crate::shared::recording_props::{
-    ProcessLaunchProps, ProfileCreationProps, RecordingMode, RecordingProps,
+    AuxEvent, ProcessLaunchProps, ProfileCreationProps, RecordingMode, RecordingProps,
 };
 use crate::shared::symbol_props::SymbolProps;
 
@@ -37,12 +51,13 @@ pub type ConvertRegsNative = crate::linux_shared::ConvertRegsX86_64;
 #[cfg(target_arch = "aarch64")]
 pub type ConvertRegsNative = crate::linux_shared::ConvertRegsAarch64;
 
+#[allow(clippy::result_unit_err)]
 pub fn start_recording(
     recording_mode: RecordingMode,
     recording_props: RecordingProps,
     profile_creation_props: ProfileCreationProps,
     symbol_props: SymbolProps,
-    server_props: Option<ServerProps>,
+    mut server_props: Option<ServerProps>,
 ) -> Result<ExitStatus, ()> {
     let process_launch_props = match recording_mode {
         RecordingMode::All => {
@@ -81,6 +96,44 @@ pub fn start_recording(
         }
     }
 
+    // Make launched JVMs write their JIT symbols to /tmp/perf-<pid>.map.
+    add_jvm_perf_map_options(&mut env_vars);
+
+    if recording_props.python && !env_vars.iter().any(|p| p.0 == "PYTHONPERFSUPPORT") {
+        // CPython 3.12+ then calls each Python function through a small
+        // trampoline, which it lists in /tmp/perf-<pid>.map.
+        env_vars.push(("PYTHONPERFSUPPORT".into(), "1".into()));
+    }
+
+    let usdt_probes = match find_executable(&command_name) {
+        Some(path) => find_requested_usdt_probes(&path, &recording_props.usdt_probes),
+        None => Vec::new(),
+    };
+
+    // Let the launched process send markers over a socket.
+    let app_marker_socket = AppMarkerSocket::bind().ok();
+    if let Some(socket) = &app_marker_socket {
+        env_vars.push((
+            MARKER_SOCKET_ENV_VAR.into(),
+            socket.path().as_os_str().to_owned(),
+        ));
+    }
+
+    // With --marker-from-output, matching output lines are sent to the same
+    // channel as the markers from the socket.
+    let output_marker_patterns = recording_props.output_marker_patterns.clone();
+    let output_watchers = OutputWatchers {
+        markers: if output_marker_patterns.is_empty() {
+            None
+        } else {
+            app_marker_socket
+                .as_ref()
+                .map(|socket| (output_marker_patterns, socket.sender()))
+        },
+        log: recording_props.log_output.then(OutputLog::default),
+    };
+    let output_log = output_watchers.log.clone();
+
     // Ignore Ctrl+C while the subcommand is running. The signal still reaches the process
     // under observation while we continue to record it. (ctrl+c will send the SIGINT signal
     // to all processes in the foreground process group).
@@ -88,11 +141,21 @@ pub fn start_recording(
 
     // Start a new process for the launched command and get its pid.
     // The command will not start running until we tell it to.
-    let process =
-        SuspendedLaunchedProcess::launch_in_suspended_state(&command_name, &args, &env_vars)
-            .unwrap();
+    let process = launch_command(&command_name, &args, &env_vars, &output_watchers).unwrap();
     let pid = process.pid();
 
+    // With --live, the profile is written during the recording, and the
+    // server, if any, already runs while recording.
+    let live_interval = recording_props.live_interval;
+    let live_server = match live_interval {
+        Some(_) => start_live_server(
+            &recording_props.output_file,
+            server_props.take(),
+            &symbol_props,
+        ),
+        None => None,
+    };
+
     // Create a channel for the observer thread to notify the main thread once
     // profiling has been initialized and the launched process can start.
     let (profile_another_pid_request_sender, profile_another_pid_request_receiver) =
@@ -104,9 +167,19 @@ pub fn start_recording(
     let output_file_copy = recording_props.output_file.clone();
     let interval = recording_props.interval;
     let time_limit = recording_props.time_limit;
+    let input_markers = recording_props.input_markers;
+    let measure_overhead = recording_props.measure_overhead;
+    let focus_markers = recording_props.focus_markers;
+    let aux_event = recording_props.aux_event;
+    let stop_on_marker = recording_props.stop_on_marker.clone();
+    let marker_fifo = recording_props.marker_fifo.clone();
     let observer_thread = thread::spawn(move || {
         let unstable_presymbolicate = profile_creation_props.unstable_presymbolicate;
-        let mut converter = make_converter(interval, profile_creation_props);
+        let post_processing = profile_creation_props.post_processing.clone();
+        let mut converter = make_converter(interval, aux_event, profile_creation_props);
+        if let Some(name) = stop_on_marker {
+            converter.watch_for_stop_marker(name);
+        }
 
         // Wait for the initial pid to profile.
         let SamplerRequest::StartProfilingAnotherProcess(pid, attach_mode) =
@@ -116,7 +189,24 @@ pub fn start_recording(
         };
 
         // Create the perf events, setting ENABLE_ON_EXEC.
-        let perf_group = init_profiler(interval, pid, attach_mode, &mut converter);
+        let perf_group = init_profiler(
+            interval,
+            aux_event,
+            pid,
+            attach_mode,
+            usdt_probes,
+            &mut converter,
+        )
+        .unwrap_or_else(|err| exit_for_init_error(err));
+
+        let input_event_recorder = input_markers.then(InputEventRecorder::start).flatten();
+        let focus_event_recorder = focus_markers
+            .then(|| FocusEventRecorder::start().unwrap_or_else(|err| exit_for_focus_error(&err)));
+        // The launched command reads from the terminal, so leave stdin alone.
+        let user_marker_recorder = UserMarkerRecorder::start(false, marker_fifo.as_deref());
+        let overhead_meter = measure_overhead.then(|| OverheadMeter::new(pid));
+        let live_snapshots =
+            live_interval.map(|interval| LiveSnapshots::new(output_file_copy.clone(), interval));
 
         // Tell the main thread to tell the child process to begin executing.
         profile_another_pid_reply_sender.send(true).unwrap();
@@ -138,6 +228,14 @@ pub fn start_recording(
             profile_another_pid_reply_sender,
             stop_receiver,
             unstable_presymbolicate,
+            post_processing,
+            input_event_recorder,
+            focus_event_recorder,
+            user_marker_recorder,
+            app_marker_socket,
+            output_log,
+            overhead_meter,
+            live_snapshots,
         );
     });
 
@@ -187,10 +285,12 @@ pub fn start_recording(
             );
             break;
         }
+        if observer_thread.is_finished() {
+            eprintln!("Skipping remaining iterations because the recording has stopped.");
+            break;
+        }
         eprintln!("Running iteration {i} of {iteration_count}...");
-        let process =
-            SuspendedLaunchedProcess::launch_in_suspended_state(&command_name, &args, &env_vars)
-                .unwrap();
+        let process = launch_command(&command_name, &args, &env_vars, &output_watchers).unwrap();
         let pid = process.pid();
 
         // Tell the sampler to start profiling another pid, and wait for it to signal us to go ahead.
@@ -222,9 +322,19 @@ pub fn start_recording(
         wait_status = process.wait().expect("couldn't wait for child");
     }
 
-    profile_another_pid_request_sender
-        .send(SamplerRequest::StopProfilingOncePerfEventsExhausted)
-        .unwrap();
+    if let Some(linger) = recording_props.linger {
+        // Keep recording for a while, for example to see what the launched
+        // command's daemonized children are doing.
+        eprintln!(
+            "The command has exited, recording for another {:.1} seconds...",
+            linger.as_secs_f64()
+        );
+        thread::sleep(linger);
+    }
+
+    // The observer thread may have stopped already, e.g. because of --stop-on-marker.
+    let _ = profile_another_pid_request_sender
+        .send(SamplerRequest::StopProfilingOncePerfEventsExhausted);
 
     // The launched subprocess is done. From now on, we want to terminate if the user presses Ctrl+C.
     ctrl_c_receiver.close();
@@ -236,6 +346,9 @@ pub fn start_recording(
         .join()
         .expect("couldn't join observer thread");
 
+    if let Some(live_server) = live_server {
+        wait_for_live_server(live_server);
+    }
     if let Some(server_props) = server_props {
         let profile_filename = &recording_props.output_file;
         let libinfo_map = crate::profile_json_preparse::parse_libinfo_map_from_profile_file(
@@ -259,11 +372,23 @@ fn start_profiling_pid(
     recording_props: RecordingProps,
     profile_creation_props: ProfileCreationProps,
     symbol_props: SymbolProps,
-    server_props: Option<ServerProps>,
+    mut server_props: Option<ServerProps>,
 ) {
     // When the first Ctrl+C is received, stop recording.
     let ctrl_c_receiver = CtrlC::observe_oneshot();
 
+    // With --live, the profile is written during the recording, and the
+    // server, if any, already runs while recording.
+    let live_interval = recording_props.live_interval;
+    let live_server = match live_interval {
+        Some(_) => start_live_server(
+            &recording_props.output_file,
+            server_props.take(),
+            &symbol_props,
+        ),
+        None => None,
+    };
+
     // Create a channel for the observer thread to notify the main thread once
     // profiling has been initialized.
     let (profile_another_pid_request_sender, profile_another_pid_request_receiver) =
@@ -277,13 +402,44 @@ fn start_profiling_pid(
             let interval = recording_props.interval;
             let time_limit = recording_props.time_limit;
             let unstable_presymbolicate = profile_creation_props.unstable_presymbolicate;
-            let mut converter = make_converter(interval, profile_creation_props);
+            let post_processing = profile_creation_props.post_processing.clone();
+            let mut converter =
+                make_converter(interval, recording_props.aux_event, profile_creation_props);
+            if let Some(name) = recording_props.stop_on_marker {
+                converter.watch_for_stop_marker(name);
+            }
             let SamplerRequest::StartProfilingAnotherProcess(pid, attach_mode) =
                 profile_another_pid_request_receiver.recv().unwrap()
             else {
                 panic!("The first message should be a StartProfilingAnotherProcess")
             };
-            let perf_group = init_profiler(interval, pid, attach_mode, &mut converter);
+            let usdt_probes = find_requested_usdt_probes(
+                Path::new(&format!("/proc/{pid}/exe")),
+                &recording_props.usdt_probes,
+            );
+            let perf_group = init_profiler(
+                interval,
+                recording_props.aux_event,
+                pid,
+                attach_mode,
+                usdt_probes,
+                &mut converter,
+            )
+            .unwrap_or_else(|err| exit_for_init_error(err));
+            let input_event_recorder = recording_props
+                .input_markers
+                .then(InputEventRecorder::start)
+                .flatten();
+            let focus_event_recorder = recording_props.focus_markers.then(|| {
+                FocusEventRecorder::start().unwrap_or_else(|err| exit_for_focus_error(&err))
+            });
+            let user_marker_recorder =
+                UserMarkerRecorder::start(true, recording_props.marker_fifo.as_deref());
+            let overhead_meter = recording_props
+                .measure_overhead
+                .then(|| OverheadMeter::new(pid));
+            let live_snapshots = live_interval
+                .map(|interval| LiveSnapshots::new(recording_props.output_file.clone(), interval));
 
             // Tell the main thread that we are now executing.
             profile_another_pid_reply_sender.send(true).unwrap();
@@ -298,6 +454,14 @@ fn start_profiling_pid(
                 profile_another_pid_reply_sender,
                 ctrl_c_receiver,
                 unstable_presymbolicate,
+                post_processing,
+                input_event_recorder,
+                focus_event_recorder,
+                user_marker_recorder,
+                None,
+                None,
+                overhead_meter,
+                live_snapshots,
             )
         }
     });
@@ -315,10 +479,14 @@ fn start_profiling_pid(
 
     // Now that we know that profiler initialization has succeeded, tell the user about it.
     eprintln!("Recording process with PID {pid} until Ctrl+C...");
+    eprintln!(
+        "Press Enter, or send SIGUSR2 to samply (PID {}), to add a marker.",
+        std::process::id()
+    );
 
-    profile_another_pid_request_sender
-        .send(SamplerRequest::StopProfilingOncePerfEventsExhausted)
-        .unwrap();
+    // The observer thread may have stopped already, e.g. because of --stop-on-marker.
+    let _ = profile_another_pid_request_sender
+        .send(SamplerRequest::StopProfilingOncePerfEventsExhausted);
 
     // Now wait for the observer thread to quit. It will keep running until the
     // CtrlC receiver has been notified, or until all perf events are closed,
@@ -330,6 +498,9 @@ fn start_profiling_pid(
     // From now on, pressing Ctrl+C will kill our process, because the observer will have
     // dropped its CtrlC receiver by now.
 
+    if let Some(live_server) = live_server {
+        wait_for_live_server(live_server);
+    }
     if let Some(server_props) = server_props {
         let libinfo_map = crate::profile_json_preparse::parse_libinfo_map_from_profile_file(
             File::open(&output_file).expect("Couldn't open file we just wrote"),
@@ -347,8 +518,84 @@ fn paranoia_level() -> Option<u32> {
     Some(level)
 }
 
-fn make_converter(
+/// Launches the command in a suspended state. If there are output watchers,
+/// the command's output is captured and handed to them.
+fn launch_command(
+    command_name: &OsStr,
+    args: &[OsString],
+    env_vars: &[(OsString, OsString)],
+    output_watchers: &OutputWatchers,
+) -> std::io::Result<SuspendedLaunchedProcess> {
+    if output_watchers.is_empty() {
+        return SuspendedLaunchedProcess::launch_in_suspended_state(
+            command_name,
+            args,
+            env_vars,
+            None,
+        );
+    }
+    let capture = OutputCapture::new()?;
+    let process = SuspendedLaunchedProcess::launch_in_suspended_state(
+        command_name,
+        args,
+        env_vars,
+        Some(capture.child_fds()),
+    )?;
+    capture.start_watching(process.pid() as i32, output_watchers);
+    Ok(process)
+}
+
+/// Finds the executable which is run for a command name, like the shell does.
+fn find_executable(command_name: &OsStr) -> Option<PathBuf> {
+    let command_path = Path::new(command_name);
+    if command_name.as_bytes().contains(&b'/') {
+        return Some(command_path.to_owned());
+    }
+    let path_var = std::env::var_os("PATH")?;
+    std::env::split_paths(&path_var)
+        .map(|dir| dir.join(command_path))
+        .find(|path| path.is_file())
+}
+
+/// Looks up the probes which were requested with --usdt in the profiled
+/// binary, and warns about the ones which can't be found.
+fn find_requested_usdt_probes(binary: &Path, specs: &[UsdtProbeSpec]) -> Vec<UsdtProbe> {
+    if specs.is_empty() {
+        return Vec::new();
+    }
+    match find_usdt_probes(binary, specs) {
+        Ok((probes, missing)) => {
+            for spec in missing {
+                eprintln!(
+                    "Warning: Could not find the USDT probe {spec} in {}.",
+                    binary.display()
+                );
+            }
+            probes
+        }
+        Err(error) => {
+            eprintln!(
+                "Warning: Could not read the USDT probes of {}: {error}",
+                binary.display()
+            );
+            Vec::new()
+        }
+    }
+}
+
+fn exit_for_init_error(err: std::io::Error) -> ! {
+    eprintln!("Failed to start profiling: {err}");
+    std::process::exit(1)
+}
+
+fn exit_for_focus_error(err: &str) -> ! {
+    eprintln!("Error: {err} Run without --focus-markers to record anyway.");
+    std::process::exit(1)
+}
+
+pub(super) fn make_converter(
     interval: Duration,
+    aux_event: Option<AuxEvent>,
     profile_creation_props: ProfileCreationProps,
 ) -> Converter<framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>> {
     let interval_nanos = if interval.as_nanos() > 0 {
@@ -368,14 +615,22 @@ fn make_converter(
     let interpretation = EventInterpretation {
         main_event_attr_index: 0,
         main_event_name: "cycles".to_string(),
+        aux_event_attr_index: aux_event.map(|_| 1),
+        track_event_attr_indices: Vec::new(),
         sampling_is_time_based: Some(interval_nanos),
         off_cpu_indicator: Some(OffCpuIndicator::ContextSwitches),
+        context_switches_have_cpu: true,
         sched_switch_attr_index: None,
         known_event_indices: HashMap::new(),
-        event_names: vec!["cycles".to_string()],
+        event_names: std::iter::once("cycles")
+            .chain(aux_event.map(|aux_event| aux_event.name()))
+            .map(ToOwned::to_owned)
+            .collect(),
     };
 
-    Converter::<framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>>::new(
+    let mut converter = Converter::<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >::new(
         &profile_creation_props,
         ReferenceTimestamp::from_system_time(SystemTime::now()),
         None,
@@ -388,17 +643,23 @@ fn make_converter(
         interpretation,
         None,
         false,
-    )
+    );
+    converter.open_files_through_process_roots();
+    converter
 }
 
-fn init_profiler(
+/// Opens the perf events for the process and tells the converter about the
+/// process's existing threads and mappings.
+pub(super) fn init_profiler(
     interval: Duration,
+    aux_event: Option<AuxEvent>,
     pid: u32,
     attach_mode: AttachMode,
+    usdt_probes: Vec<UsdtProbe>,
     converter: &mut Converter<
         framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
     >,
-) -> PerfGroup {
+) -> std::io::Result<PerfGroup> {
     let interval_nanos = if interval.as_nanos() > 0 {
         interval.as_nanos() as u64
     } else {
@@ -409,12 +670,32 @@ fn init_profiler(
     let stack_size = 32000;
     let regs_mask = ConvertRegsNative::regs_mask();
 
+    let aux_event_source = aux_event.map(|aux_event| match aux_event {
+        AuxEvent::Instructions => EventSource::HwInstructions,
+        AuxEvent::CacheReferences => EventSource::HwCacheReferences,
+        AuxEvent::CacheMisses => EventSource::HwCacheMisses,
+        AuxEvent::BranchMisses => EventSource::HwBranchMisses,
+    });
+    let uprobe_targets: Vec<UprobeTarget> = usdt_probes
+        .iter()
+        .map(|probe| UprobeTarget {
+            path: Arc::new(
+                CString::new(probe.path.as_os_str().as_bytes()).expect("paths have no NUL bytes"),
+            ),
+            offset: probe.file_offset,
+            ref_ctr_offset: probe.semaphore_file_offset,
+            regs_mask: probe.regs_mask(),
+        })
+        .collect();
+    converter.set_usdt_probes(usdt_probes);
     let perf = PerfGroup::open(
         pid,
         frequency,
         stack_size,
         EventSource::HwCpuCycles,
+        aux_event_source,
         regs_mask,
+        uprobe_targets.clone(),
         attach_mode,
     );
 
@@ -422,16 +703,16 @@ fn init_profiler(
         if error.kind() == std::io::ErrorKind::PermissionDenied {
             if let Some(level) = paranoia_level() {
                 if level > 1 {
-                    eprintln!();
-                    eprintln!(
-                        "'/proc/sys/kernel/perf_event_paranoid' is currently set to {level}."
-                    );
-                    eprintln!("In order for samply to work with a non-root user, this level needs");
-                    eprintln!("to be set to 1 or lower.");
-                    eprintln!("You can execute the following command and then try again:");
-                    eprintln!("    echo '1' | sudo tee /proc/sys/kernel/perf_event_paranoid");
-                    eprintln!();
-                    std::process::exit(1);
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::PermissionDenied,
+                        format!(
+                            "'/proc/sys/kernel/perf_event_paranoid' is currently set to {level}.\n\
+                             In order for samply to work with a non-root user, this level needs\n\
+                             to be set to 1 or lower.\n\
+                             You can execute the following command and then try again:\n    \
+                             echo '1' | sudo tee /proc/sys/kernel/perf_event_paranoid"
+                        ),
+                    ));
                 }
             }
         }
@@ -441,34 +722,29 @@ fn init_profiler(
         Ok(perf) => perf,
         Err(_) => {
             // We've already checked for permission denied due to paranoia
-            // level, and exited with a warning in that case.
+            // level, and returned an explanation in that case.
 
             // Another reason for the error could be the type of perf event:
             // The "Hardware CPU cycles" event is not supported in some contexts, for example in VMs.
-            // Try a different event type.
-            let perf = PerfGroup::open(
+            // Try a different event type. Hardware events for the aux event won't work either.
+            if aux_event.is_some() {
+                eprintln!("Warning: Hardware events are not available, ignoring --aux-event.");
+            }
+            PerfGroup::open(
                 pid,
                 frequency,
                 stack_size,
                 EventSource::SwCpuClock,
+                None,
                 regs_mask,
+                uprobe_targets,
                 attach_mode,
-            );
-            match perf {
-                Ok(perf) => perf, // Success!
-                Err(error) => {
-                    eprintln!("Failed to start profiling: {error}");
-                    std::process::exit(1);
-                }
-            }
+            )?
         }
     };
 
     // TODO: Gather threads / processes recursively, here and in PerfGroup setup.
-    for entry in std::fs::read_dir(format!("/proc/{pid}/task"))
-        .unwrap()
-        .flatten()
-    {
+    for entry in std::fs::read_dir(format!("/proc/{pid}/task"))?.flatten() {
         let tid: u32 = entry.file_name().to_string_lossy().parse().unwrap();
         let comm_path = format!("/proc/{pid}/task/{tid}/comm");
         if let Ok(buffer) = std::fs::read(comm_path) {
@@ -478,7 +754,7 @@ fn init_profiler(
         }
     }
 
-    let maps = read_string_lossy(format!("/proc/{pid}/maps")).expect("couldn't read proc maps");
+    let maps = read_string_lossy(format!("/proc/{pid}/maps"))?;
     let maps = proc_maps::parse(&maps);
 
     let vdso_file_id = VdsoObject::shared_instance_for_this_process()
@@ -538,27 +814,88 @@ fn init_profiler(
         }
     }
 
-    perf
+    Ok(perf)
 }
 
-enum SamplerRequest {
+pub(super) enum SamplerRequest {
     StartProfilingAnotherProcess(u32, AttachMode),
     StopProfilingOncePerfEventsExhausted,
 }
 
 #[allow(clippy::too_many_arguments)]
 fn run_profiler(
-    mut perf: PerfGroup,
-    mut converter: Converter<
+    perf: PerfGroup,
+    converter: Converter<
         framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
     >,
     output_filename: &Path,
     _time_limit: Option<Duration>,
     more_processes_request_receiver: Receiver<SamplerRequest>,
     more_processes_reply_sender: Sender<bool>,
-    mut stop_receiver: oneshot::Receiver<()>,
+    stop_receiver: oneshot::Receiver<()>,
     unstable_presymbolicate: bool,
+    post_processing: PostProcessingOptions,
+    input_event_recorder: Option<InputEventRecorder>,
+    focus_event_recorder: Option<FocusEventRecorder>,
+    user_marker_recorder: Option<UserMarkerRecorder>,
+    app_marker_socket: Option<AppMarkerSocket>,
+    output_log: Option<OutputLog>,
+    overhead_meter: Option<OverheadMeter>,
+    live_snapshots: Option<LiveSnapshots>,
 ) {
+    let profile = record_profile(
+        perf,
+        converter,
+        more_processes_request_receiver,
+        more_processes_reply_sender,
+        stop_receiver,
+        input_event_recorder,
+        focus_event_recorder,
+        user_marker_recorder,
+        app_marker_socket
+            .as_ref()
+            .map(|socket| socket.receiver().clone()),
+        output_log,
+        overhead_meter,
+        live_snapshots,
+    );
+
+    {
+        let output_file = File::create(output_filename).unwrap();
+        let writer = BufWriter::new(output_file);
+        serde_json::to_writer(writer, &profile).expect("Couldn't write JSON");
+    }
+
+    post_process_profile_file(output_filename, &post_processing)
+        .expect("Couldn't post-process the profile");
+
+    if unstable_presymbolicate {
+        crate::shared::symbol_precog::presymbolicate(
+            &profile,
+            &output_filename.with_extension("syms.json"),
+        );
+    }
+}
+
+/// Processes the perf events until the processes have quit, or until the stop
+/// receiver is notified, and returns the profile.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn record_profile(
+    mut perf: PerfGroup,
+    mut converter: Converter<
+        framehop::UnwinderNative<MmapRangeOrVec, framehop::MayAllocateDuringUnwind>,
+    >,
+    more_processes_request_receiver: Receiver<SamplerRequest>,
+    more_processes_reply_sender: Sender<bool>,
+    mut stop_receiver: oneshot::Receiver<()>,
+    input_event_recorder: Option<InputEventRecorder>,
+    focus_event_recorder: Option<FocusEventRecorder>,
+    user_marker_recorder: Option<UserMarkerRecorder>,
+    app_markers: Option<Receiver<AppMarkerEvent>>,
+    output_log: Option<OutputLog>,
+    mut overhead_meter: Option<OverheadMeter>,
+    mut live_snapshots: Option<LiveSnapshots>,
+) -> Profile {
     // eprintln!("Running...");
 
     let mut should_stop_profiling_once_perf_events_exhausted = false;
@@ -570,6 +907,12 @@ fn run_profiler(
             break;
         }
 
+        if let Some(app_markers) = &app_markers {
+            for event in app_markers.try_iter() {
+                converter.handle_app_marker_event(event);
+            }
+        }
+
         match more_processes_request_receiver.try_recv() {
             Ok(SamplerRequest::StartProfilingAnotherProcess(another_pid, attach_mode)) => {
                 match perf.open_process(another_pid, attach_mode) {
@@ -618,21 +961,30 @@ fn run_profiler(
 
         perf.consume_events(&mut |event_ref| {
             let record = event_ref.get();
-            let parsed_record = record.parse().unwrap();
+            let mut parsed_record = record.parse().unwrap();
             // debug!("Recording parsed_record: {:#?}", parsed_record);
 
-            if let Some(timestamp) = record.timestamp() {
-                if timestamp < last_timestamp {
-                    // eprintln!(
-                    //     "bad timestamp ordering; {timestamp} is earlier but arrived after {last_timestamp}"
-                    // );
-                }
+            let timestamp = record
+                .timestamp()
+                .map(|timestamp| converter.correct_timestamp(timestamp));
+            if let Some(timestamp) = timestamp {
+                set_record_timestamp(&mut parsed_record, timestamp);
                 last_timestamp = timestamp;
             }
 
+            if let Some(probe_index) = event_ref.usdt_probe_index() {
+                if let EventRecord::Sample(e) = &parsed_record {
+                    converter.handle_usdt_sample(e, probe_index);
+                }
+                return;
+            }
+
             match parsed_record {
+                EventRecord::Sample(e) if event_ref.is_aux_event() => {
+                    converter.handle_aux_event_sample::<ConvertRegsNative>(&e);
+                }
                 EventRecord::Sample(e) => {
-                    converter.handle_main_event_sample::<ConvertRegsNative>(&e);
+                    converter.handle_main_event_sample::<ConvertRegsNative>(&e, None);
                     /*
                     } else if interpretation.sched_switch_attr_index == Some(attr_index) {
                         converter.handle_sched_switch_sample::<C>(e);
@@ -642,7 +994,7 @@ fn run_profiler(
                     converter.handle_fork(e);
                 }
                 EventRecord::Comm(e) => {
-                    converter.handle_comm(e, record.timestamp());
+                    converter.handle_comm(e, timestamp);
                 }
                 EventRecord::Exit(e) => {
                     converter.handle_exit(e);
@@ -654,15 +1006,22 @@ fn run_profiler(
                     converter.handle_mmap2(e, last_timestamp);
                 }
                 EventRecord::ContextSwitch(e) => {
-                    let common = match record.common_data() {
+                    let mut common = match record.common_data() {
                         Ok(common) => common,
                         Err(_) => return,
                     };
+                    common.timestamp = timestamp;
                     converter.handle_context_switch(e, common);
                 }
                 EventRecord::Lost(event) => {
                     pending_lost_events += event.count;
                     total_lost_events += event.count;
+                    if let Some(overhead_meter) = &mut overhead_meter {
+                        overhead_meter.on_lost_records(event.count);
+                    }
+                    if let Ok(common) = record.common_data() {
+                        converter.handle_lost(event.count, common);
+                    }
                     return;
                 }
                 _ => {}
@@ -674,27 +1033,82 @@ fn run_profiler(
             }
         });
 
+        if converter.has_seen_stop_marker() {
+            eprintln!("Found the marker for stopping the recording.");
+            break;
+        }
+
         perf.wait();
+        if let Some(overhead_meter) = &mut overhead_meter {
+            overhead_meter.on_wakeup();
+            overhead_meter.poll(&mut converter);
+        }
+        if let Some(live_snapshots) = &mut live_snapshots {
+            live_snapshots.poll(&converter);
+        }
+    }
+
+    // Don't let a snapshot replace the final profile.
+    if let Some(live_snapshots) = live_snapshots {
+        live_snapshots.finish();
     }
 
     if total_lost_events > 0 {
         eprintln!("Lost {total_lost_events} events.");
     }
 
-    let profile = converter.finish();
-
-    {
-        let output_file = File::create(output_filename).unwrap();
-        let writer = BufWriter::new(output_file);
-        serde_json::to_writer(writer, &profile).expect("Couldn't write JSON");
+    if let Some(input_event_recorder) = input_event_recorder {
+        converter.add_input_event_markers(&input_event_recorder.stop());
+    }
+    if let Some(focus_event_recorder) = focus_event_recorder {
+        let (events, end_raw) = focus_event_recorder.stop();
+        converter.add_focus_markers(&events, end_raw);
+    }
+    if let Some(user_marker_recorder) = user_marker_recorder {
+        converter.add_user_markers(&user_marker_recorder.stop());
+    }
+    if let Some(output_log) = output_log {
+        converter.add_output_log_markers(&output_log.take_lines());
+    }
+    if let Some(overhead_meter) = overhead_meter {
+        overhead_meter.finish(&mut converter);
     }
 
-    if unstable_presymbolicate {
-        crate::shared::symbol_precog::presymbolicate(
-            &profile,
-            &output_filename.with_extension("syms.json"),
-        );
+    // JVMs which are still running, e.g. when recording with --pid, only
+    // write their JIT symbols when asked to.
+    for pid in converter.live_pids() {
+        if is_jvm_process(pid) {
+            dump_jvm_perf_map(pid);
+        }
     }
+
+    converter.finish()
+}
+
+/// Starts the server for `--live` on its own thread, so that it can serve the
+/// snapshots while the recording is running. Without server props, e.g. with
+/// `--save-only`, only the snapshots are written.
+fn start_live_server(
+    output_file: &Path,
+    server_props: Option<ServerProps>,
+    symbol_props: &SymbolProps,
+) -> Option<thread::JoinHandle<()>> {
+    // Don't show the profile from an earlier recording before the first
+    // snapshot has been written.
+    let _ = std::fs::remove_file(output_file);
+    let server_props = server_props?;
+    let output_file = output_file.to_owned();
+    let symbol_props = symbol_props.clone();
+    Some(thread::spawn(move || {
+        start_live_server_main(&output_file, server_props, symbol_props)
+    }))
+}
+
+/// Keeps serving the finished profile until the user presses Ctrl+C.
+fn wait_for_live_server(live_server: thread::JoinHandle<()>) {
+    eprintln!("The recording has finished. Reload the profiler to see the whole profile.");
+    eprintln!("Press Ctrl+C to stop the server.");
+    let _ = live_server.join();
 }
 
 pub fn read_string_lossy<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
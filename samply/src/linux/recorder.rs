@@ -0,0 +1,280 @@
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crossbeam_channel::Sender;
+use fxprof_processed_profile::Profile;
+use nix::sys::wait::WaitStatus;
+use tokio::sync::oneshot;
+
+use super::focus_events::monotonic_time_ns;
+use super::perf_group::AttachMode;
+use super::process::{RunningProcess, SuspendedLaunchedProcess};
+use super::profiler::{init_profiler, make_converter, record_profile, SamplerRequest};
+use crate::shared::app_markers::{AppMarkerEvent, AppMarkerEventKind};
+use crate::shared::recording_props::ProfileCreationProps;
+use crate::shared::recursion_folding::RecursionFolding;
+
+/// Options for a [`Recorder`].
+#[derive(Debug, Clone)]
+pub struct RecorderOptions {
+    /// The time between two samples.
+    pub interval: Duration,
+    /// The name of the profile, which the Firefox Profiler shows in its title.
+    pub profile_name: String,
+}
+
+impl Default for RecorderOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(1),
+            profile_name: "samply".to_owned(),
+        }
+    }
+}
+
+/// Records a profile of a process, like `samply record`, and returns it
+/// instead of saving and serving it.
+///
+/// ```no_run
+/// use samply::{Recorder, RecorderOptions};
+///
+/// let recorder = Recorder::launch("./my-benchmark", &[], RecorderOptions::default())?;
+/// recorder.add_marker("Started");
+/// let (exit_status, profile) = recorder.wait()?;
+/// serde_json::to_writer(std::fs::File::create("profile.json")?, &profile)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// The profile's libraries aren't symbolicated; load the saved profile with
+/// `samply load` to see function names.
+pub struct Recorder {
+    pid: u32,
+    /// The launched process, until it has been waited for.
+    process: Option<RunningProcess>,
+    markers: Sender<AppMarkerEvent>,
+    requests: Sender<SamplerRequest>,
+    stop_sender: oneshot::Sender<()>,
+    observer_thread: JoinHandle<Profile>,
+}
+
+impl Recorder {
+    /// Launches the command and records it until [`Recorder::wait`] or
+    /// [`Recorder::stop`] is called.
+    pub fn launch(
+        command: impl AsRef<OsStr>,
+        args: &[OsString],
+        options: RecorderOptions,
+    ) -> io::Result<Self> {
+        let process =
+            SuspendedLaunchedProcess::launch_in_suspended_state(command.as_ref(), args, &[], None)?;
+        let pid = process.pid();
+        let recorder = Self::start(pid, AttachMode::AttachWithEnableOnExec, options)?;
+        // If starting the recording failed, dropping the suspended process
+        // makes it exit without running the command.
+        let process = process.unsuspend_and_run()?;
+        Ok(Self {
+            process: Some(process),
+            ..recorder
+        })
+    }
+
+    /// Records the running process with this pid until [`Recorder::stop`] is
+    /// called.
+    pub fn attach(pid: u32, options: RecorderOptions) -> io::Result<Self> {
+        Self::start(pid, AttachMode::StopAttachEnableResume, options)
+    }
+
+    fn start(pid: u32, attach_mode: AttachMode, options: RecorderOptions) -> io::Result<Self> {
+        let (requests, request_receiver) = crossbeam_channel::bounded(2);
+        let (reply_sender, _reply_receiver) = crossbeam_channel::bounded(2);
+        let (markers, marker_receiver) = crossbeam_channel::unbounded();
+        let (stop_sender, stop_receiver) = oneshot::channel();
+        let (init_sender, init_receiver) = crossbeam_channel::bounded(1);
+        let profile_creation_props = profile_creation_props(options.profile_name);
+        let interval = options.interval;
+        let observer_thread = thread::spawn(move || {
+            let mut converter = make_converter(interval, None, profile_creation_props);
+            let perf =
+                match init_profiler(interval, None, pid, attach_mode, Vec::new(), &mut converter) {
+                    Ok(perf) => {
+                        let _ = init_sender.send(Ok(()));
+                        perf
+                    }
+                    Err(err) => {
+                        let _ = init_sender.send(Err(err));
+                        return converter.finish();
+                    }
+                };
+            record_profile(
+                perf,
+                converter,
+                request_receiver,
+                reply_sender,
+                stop_receiver,
+                None,
+                None,
+                None,
+                Some(marker_receiver),
+                None,
+                None,
+                None,
+            )
+        });
+        init_receiver
+            .recv()
+            .map_err(|_| io::Error::other("the recording thread panicked"))??;
+        Ok(Self {
+            pid,
+            process: None,
+            markers,
+            requests,
+            stop_sender,
+            observer_thread,
+        })
+    }
+
+    /// The pid of the recorded process.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Adds an instant marker to the main thread of the recorded process.
+    pub fn add_marker(&self, name: &str) {
+        self.send_marker(AppMarkerEventKind::Instant, name);
+    }
+
+    /// Starts an interval marker on the main thread of the recorded process,
+    /// which lasts until [`Recorder::end_marker`] is called with the same
+    /// name.
+    pub fn begin_marker(&self, name: &str) {
+        self.send_marker(AppMarkerEventKind::Begin, name);
+    }
+
+    /// Ends the interval marker which [`Recorder::begin_marker`] started.
+    pub fn end_marker(&self, name: &str) {
+        self.send_marker(AppMarkerEventKind::End, name);
+    }
+
+    fn send_marker(&self, kind: AppMarkerEventKind, name: &str) {
+        let _ = self.markers.send(AppMarkerEvent {
+            kind,
+            timestamp_raw: monotonic_time_ns(),
+            tid: self.pid as i32,
+            name: name.to_owned(),
+            payload: None,
+        });
+    }
+
+    /// Waits for the launched process to exit, and returns its exit status
+    /// and the profile.
+    pub fn wait(mut self) -> io::Result<(ExitStatus, Profile)> {
+        let process = self.process.take().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only launched processes can be waited for",
+            )
+        })?;
+        let wait_status = process.wait()?;
+        let exit_status = exit_status_from_wait_status(wait_status)
+            .ok_or_else(|| io::Error::other(format!("unexpected wait status {wait_status:?}")))?;
+        // Keep recording the processes which the command has started, until
+        // they have quit too.
+        let _ = self
+            .requests
+            .send(SamplerRequest::StopProfilingOncePerfEventsExhausted);
+        Ok((exit_status, join_observer_thread(self.observer_thread)))
+    }
+
+    /// Stops recording and returns the profile. A launched process keeps
+    /// running.
+    pub fn stop(self) -> Profile {
+        let _ = self
+            .requests
+            .send(SamplerRequest::StopProfilingOncePerfEventsExhausted);
+        let _ = self.stop_sender.send(());
+        join_observer_thread(self.observer_thread)
+    }
+}
+
+/// Converts the status of a process which has quit into the raw wait status
+/// which `ExitStatus` wraps on Unix. Returns `None` if the process hasn't quit.
+fn exit_status_from_wait_status(wait_status: WaitStatus) -> Option<ExitStatus> {
+    match wait_status {
+        WaitStatus::Exited(_pid, exit_code) => Some(ExitStatus::from_raw((exit_code & 0xff) << 8)),
+        WaitStatus::Signaled(_pid, signal, core_dumped) => {
+            let core_dump_flag = if core_dumped { 0x80 } else { 0 };
+            Some(ExitStatus::from_raw(signal as i32 | core_dump_flag))
+        }
+        _ => None,
+    }
+}
+
+fn join_observer_thread(observer_thread: JoinHandle<Profile>) -> Profile {
+    match observer_thread.join() {
+        Ok(profile) => profile,
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+fn profile_creation_props(profile_name: String) -> ProfileCreationProps {
+    ProfileCreationProps {
+        profile_name,
+        main_thread_only: false,
+        reuse_threads: false,
+        recursion_folding: RecursionFolding::None,
+        unlink_aux_files: false,
+        create_per_cpu_threads: false,
+        override_arch: None,
+        unstable_presymbolicate: false,
+        coreclr: Default::default(),
+        unknown_event_markers: false,
+        post_processing: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nix::sys::signal::Signal;
+    use nix::unistd::Pid;
+
+    use super::*;
+
+    #[test]
+    fn exit_status() {
+        let pid = Pid::from_raw(1);
+        let status = exit_status_from_wait_status(WaitStatus::Exited(pid, 3)).unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert!(!status.success());
+        let status = exit_status_from_wait_status(WaitStatus::Exited(pid, 0)).unwrap();
+        assert!(status.success());
+
+        let status = WaitStatus::Signaled(pid, Signal::SIGKILL, false);
+        let status = exit_status_from_wait_status(status).unwrap();
+        assert_eq!(status.code(), None);
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+        assert!(!status.success());
+
+        let status = WaitStatus::Stopped(pid, Signal::SIGSTOP);
+        assert_eq!(exit_status_from_wait_status(status), None);
+    }
+
+    #[test]
+    fn wait_for_launched_process() {
+        let args = ["-c".into(), "exit 3".into()];
+        let recorder = match Recorder::launch("sh", &args, RecorderOptions::default()) {
+            Ok(recorder) => recorder,
+            Err(err) => {
+                // Recording needs perf events, which aren't available
+                // everywhere, e.g. in containers.
+                eprintln!("Skipping the test, could not start recording: {err}");
+                return;
+            }
+        };
+        let (status, _profile) = recorder.wait().unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+}
@@ -51,6 +51,10 @@ pub const PERF_ATTR_FLAG_USE_CLOCKID: u64 = flag!(25);
 pub const PERF_ATTR_FLAG_CONTEX_SWITCH: u64 = flag!(26);
 
 pub const PERF_COUNT_HW_CPU_CYCLES: u64 = 0;
+pub const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+pub const PERF_COUNT_HW_CACHE_REFERENCES: u64 = 2;
+pub const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+pub const PERF_COUNT_HW_BRANCH_MISSES: u64 = 5;
 pub const PERF_COUNT_HW_REF_CPU_CYCLES: u64 = 9;
 
 pub const PERF_COUNT_SW_CPU_CLOCK: u64 = 0;
@@ -222,6 +226,7 @@ mod ioctl {
         pub const IOC_SIZEBITS: c_ulong = 14;
         pub const IOC_DIRBITS: c_ulong = 2;
         pub const IOC_NONE: c_ulong = 0;
+        pub const IOC_WRITE: c_ulong = 1;
     }
 
     #[cfg(any(
@@ -236,6 +241,7 @@ mod ioctl {
         pub const IOC_SIZEBITS: c_ulong = 13;
         pub const IOC_DIRBITS: c_ulong = 3;
         pub const IOC_NONE: c_ulong = 1;
+        pub const IOC_WRITE: c_ulong = 4;
     }
 
     pub use self::arch::*;
@@ -263,9 +269,46 @@ macro_rules! io {
     };
 }
 
+macro_rules! iow {
+    ($kind:expr, $nr:expr, $size:expr) => {
+        ioc!(ioctl::IOC_WRITE, $kind, $nr, $size)
+    };
+}
+
 pub const PERF_EVENT_IOC_ENABLE: c_ulong = io!(b'$', 0);
 pub const PERF_EVENT_IOC_DISABLE: c_ulong = io!(b'$', 1);
 
+/// Sets the clock which is used for the timestamps of evdev input events.
+pub const EVIOCSCLOCKID: c_ulong = iow!(b'E', 0xa0, std::mem::size_of::<c_int>() as c_ulong);
+
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+pub const REL_HWHEEL: u16 = 0x06;
+pub const REL_WHEEL: u16 = 0x08;
+pub const REL_WHEEL_HI_RES: u16 = 0x0b;
+pub const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+pub const ABS_X: u16 = 0x00;
+pub const ABS_Y: u16 = 0x01;
+
+pub const BTN_MISC: u16 = 0x100;
+pub const BTN_TOUCH: u16 = 0x14a;
+pub const KEY_OK: u16 = 0x160;
+
+/// `struct input_event` from linux/input.h, as read from /dev/input/event* devices.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct InputEvent {
+    pub time: libc::timeval,
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
 #[repr(C)]
 pub struct PerfEventAttr {
     pub kind: u32,
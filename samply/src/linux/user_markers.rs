@@ -0,0 +1,211 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use mio::unix::SourceFd;
+use mio::{Events, Interest, Poll, Token};
+
+use super::focus_events::monotonic_time_ns;
+use crate::shared::user_markers::{user_marker_name, UserMarker};
+
+/// The write end of the pipe through which the SIGUSR2 handler wakes up the
+/// recorder thread, or -1.
+static SIGNAL_PIPE_WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle_sigusr2(_signal: libc::c_int) {
+    let fd = SIGNAL_PIPE_WRITE_FD.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // write() is async-signal-safe. If the pipe is full, there are
+        // enough pending markers already.
+        unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+    }
+}
+
+const SIGNAL_TOKEN: Token = Token(0);
+const STDIN_TOKEN: Token = Token(1);
+const FIFO_TOKEN: Token = Token(2);
+
+/// Adds a marker whenever samply receives SIGUSR2, and, if requested,
+/// whenever a line is entered in samply's terminal or written to the marker
+/// FIFO. The line's text becomes the marker name.
+pub struct UserMarkerRecorder {
+    should_stop: Arc<AtomicBool>,
+    thread: JoinHandle<Vec<UserMarker>>,
+    created_fifo: Option<PathBuf>,
+}
+
+impl UserMarkerRecorder {
+    pub fn start(read_stdin: bool, fifo_path: Option<&Path>) -> Option<Self> {
+        let (signal_read, signal_write) =
+            nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC | nix::fcntl::OFlag::O_NONBLOCK)
+                .ok()?;
+        let (fifo, created_fifo) = match fifo_path.map(open_fifo) {
+            Some(Ok((fifo, created))) => (Some(fifo), created.then(|| fifo_path.unwrap().into())),
+            Some(Err(err)) => {
+                eprintln!(
+                    "Warning: Could not open the marker FIFO {:?}: {err}",
+                    fifo_path.unwrap()
+                );
+                (None, None)
+            }
+            None => (None, None),
+        };
+
+        SIGNAL_PIPE_WRITE_FD.store(signal_write.into_raw_fd(), Ordering::Relaxed);
+        unsafe {
+            libc::signal(
+                libc::SIGUSR2,
+                handle_sigusr2 as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            )
+        };
+
+        let should_stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let should_stop = should_stop.clone();
+            move || read_user_markers(signal_read, read_stdin, fifo, &should_stop)
+        });
+        Some(Self {
+            should_stop,
+            thread,
+            created_fifo,
+        })
+    }
+
+    /// Stops the recorder thread and returns all markers, sorted by timestamp.
+    pub fn stop(self) -> Vec<UserMarker> {
+        unsafe { libc::signal(libc::SIGUSR2, libc::SIG_DFL) };
+        let fd = SIGNAL_PIPE_WRITE_FD.swap(-1, Ordering::Relaxed);
+        if fd >= 0 {
+            unsafe { libc::close(fd) };
+        }
+        self.should_stop.store(true, Ordering::Relaxed);
+        let markers = self.thread.join().unwrap_or_default();
+        if let Some(path) = self.created_fifo {
+            let _ = std::fs::remove_file(path);
+        }
+        markers
+    }
+}
+
+/// Opens the FIFO at `path`, creating it if it doesn't exist. Returns whether
+/// it was created.
+///
+/// The FIFO is opened for writing as well, so that it doesn't report the end
+/// of the file when a writer closes it.
+fn open_fifo(path: &Path) -> io::Result<(File, bool)> {
+    let created = match std::fs::metadata(path) {
+        Ok(metadata) if metadata.file_type().is_fifo() => false,
+        Ok(_) => return Err(io::Error::other("the file exists and is not a FIFO")),
+        Err(_) => {
+            nix::unistd::mkfifo(path, nix::sys::stat::Mode::from_bits_truncate(0o600))?;
+            true
+        }
+    };
+    let fifo = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    Ok((fifo, created))
+}
+
+fn read_user_markers(
+    signal_pipe: OwnedFd,
+    read_stdin: bool,
+    mut fifo: Option<File>,
+    should_stop: &AtomicBool,
+) -> Vec<UserMarker> {
+    let mut markers = Vec::new();
+    let Ok(mut poll) = Poll::new() else {
+        return markers;
+    };
+    let mut signal_pipe = File::from(signal_pipe);
+    let _ = poll.registry().register(
+        &mut SourceFd(&signal_pipe.as_raw_fd()),
+        SIGNAL_TOKEN,
+        Interest::READABLE,
+    );
+    if read_stdin {
+        let _ = poll.registry().register(
+            &mut SourceFd(&libc::STDIN_FILENO),
+            STDIN_TOKEN,
+            Interest::READABLE,
+        );
+    }
+    if let Some(fifo) = &fifo {
+        let _ = poll.registry().register(
+            &mut SourceFd(&fifo.as_raw_fd()),
+            FIFO_TOKEN,
+            Interest::READABLE,
+        );
+    }
+
+    let mut poll_events = Events::with_capacity(4);
+    let mut buffer = [0u8; 4096];
+    let mut stdin_line = Vec::new();
+    let mut fifo_line = Vec::new();
+    while !should_stop.load(Ordering::Relaxed) {
+        if poll
+            .poll(&mut poll_events, Some(Duration::from_millis(100)))
+            .is_err()
+        {
+            break;
+        }
+        for poll_event in poll_events.iter() {
+            let timestamp_raw = monotonic_time_ns();
+            match poll_event.token() {
+                SIGNAL_TOKEN => {
+                    while let Ok(len @ 1..) = signal_pipe.read(&mut buffer) {
+                        for _ in 0..len {
+                            let name = user_marker_name("", markers.len() + 1);
+                            markers.push(UserMarker {
+                                timestamp_raw,
+                                name,
+                            });
+                        }
+                    }
+                }
+                STDIN_TOKEN => {
+                    // stdin is blocking, so only read once. The terminal hands
+                    // out one line at a time.
+                    let len = unsafe {
+                        libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr().cast(), buffer.len())
+                    };
+                    if len > 0 {
+                        stdin_line.extend_from_slice(&buffer[..len as usize]);
+                        take_lines(&mut stdin_line, timestamp_raw, &mut markers);
+                    }
+                }
+                FIFO_TOKEN => {
+                    let Some(fifo) = &mut fifo else {
+                        continue;
+                    };
+                    while let Ok(len @ 1..) = fifo.read(&mut buffer) {
+                        fifo_line.extend_from_slice(&buffer[..len]);
+                    }
+                    take_lines(&mut fifo_line, timestamp_raw, &mut markers);
+                }
+                _ => {}
+            }
+        }
+    }
+    markers
+}
+
+/// Turns the complete lines at the start of `pending` into markers.
+fn take_lines(pending: &mut Vec<u8>, timestamp_raw: u64, markers: &mut Vec<UserMarker>) {
+    while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+        let line: Vec<u8> = pending.drain(..=end).collect();
+        let name = user_marker_name(&String::from_utf8_lossy(&line), markers.len() + 1);
+        markers.push(UserMarker {
+            timestamp_raw,
+            name,
+        });
+    }
+}
@@ -0,0 +1,97 @@
+use fxprof_processed_profile::{MarkerFieldFormat, Profile};
+use linux_perf_data::linux_perf_event_reader::EventRecord;
+
+/// Detects records whose timestamps go backwards, which happens when the
+/// clocks of the CPUs aren't synchronized, e.g. with an unstable TSC or after
+/// a VM has been migrated to a different host. The timestamps of such records
+/// are clamped to the latest timestamp seen so far, so that the samples stay
+/// in order.
+#[derive(Debug, Default)]
+pub struct ClockSkewDetector {
+    latest_timestamp: u64,
+    skewed_record_count: u64,
+    max_skew_ns: u64,
+}
+
+impl ClockSkewDetector {
+    /// Returns the timestamp which should be used for a record.
+    pub fn correct(&mut self, timestamp: u64) -> u64 {
+        if timestamp == 0 {
+            // Records which were synthesized at the start of the recording
+            // can have a zero timestamp.
+            return timestamp;
+        }
+        if timestamp < self.latest_timestamp {
+            self.skewed_record_count += 1;
+            self.max_skew_ns = self.max_skew_ns.max(self.latest_timestamp - timestamp);
+            return self.latest_timestamp;
+        }
+        self.latest_timestamp = timestamp;
+        timestamp
+    }
+
+    /// Warns about the clamped records on stderr and in the profile's
+    /// metadata, if there were any.
+    pub fn report(&self, profile: &mut Profile) {
+        if self.skewed_record_count == 0 {
+            return;
+        }
+        eprintln!(
+            "Warning: {} events had timestamps up to {:.3}ms earlier than events which were recorded before them, probably because the clocks of the CPUs aren't synchronized. Their timestamps have been adjusted.",
+            self.skewed_record_count,
+            self.max_skew_ns as f64 / 1_000_000.0
+        );
+        let section = "Clock skew between CPUs";
+        profile.add_extra_info(
+            section,
+            "Events with adjusted timestamps",
+            MarkerFieldFormat::Integer,
+            self.skewed_record_count,
+        );
+        profile.add_extra_info(
+            section,
+            "Largest skew",
+            MarkerFieldFormat::Nanoseconds,
+            self.max_skew_ns,
+        );
+    }
+}
+
+/// Replaces the timestamp of a sample, fork or exit record, e.g. with the
+/// timestamp returned by [`ClockSkewDetector::correct`].
+pub fn set_record_timestamp(record: &mut EventRecord, timestamp: u64) {
+    match record {
+        EventRecord::Sample(e) => e.timestamp = Some(timestamp),
+        EventRecord::Fork(e) | EventRecord::Exit(e) => e.timestamp = timestamp,
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{ReferenceTimestamp, SamplingInterval};
+
+    use super::*;
+
+    #[test]
+    fn clamp_skewed_timestamps() {
+        let mut detector = ClockSkewDetector::default();
+        assert_eq!(detector.correct(1000), 1000);
+        assert_eq!(detector.correct(0), 0);
+        assert_eq!(detector.correct(2000), 2000);
+        assert_eq!(detector.correct(1500), 2000);
+        assert_eq!(detector.correct(1900), 2000);
+        assert_eq!(detector.correct(2100), 2100);
+
+        let mut profile = Profile::new(
+            "test",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        detector.report(&mut profile);
+        let json = serde_json::to_value(&profile).unwrap();
+        let entries = &json["meta"]["extra"][0]["entries"];
+        assert_eq!(entries[0]["value"], 2);
+        assert_eq!(entries[1]["value"], 500);
+    }
+}
@@ -8,8 +8,9 @@ use debugid::DebugId;
 use framehop::{ExplicitModuleSectionInfo, FrameAddress, Module, Unwinder};
 use fxprof_processed_profile::{
     CategoryColor, CategoryHandle, CategoryPairHandle, CpuDelta, LibraryHandle, LibraryInfo,
-    MarkerDynamicField, MarkerFieldFormat, MarkerLocation, MarkerSchemaField, MarkerTiming,
-    Profile, ProfilerMarker, ReferenceTimestamp, SamplingInterval, SymbolTable, ThreadHandle,
+    MarkerDynamicField, MarkerFieldFormat, MarkerLocation, MarkerSchemaField, MarkerStaticField,
+    MarkerTiming, Profile, ProfilerMarker, ReferenceTimestamp, SamplingInterval, SymbolTable,
+    ThreadHandle,
 };
 use linux_perf_data::simpleperf_dso_type::{DSO_DEX_FILE, DSO_KERNEL, DSO_KERNEL_MODULE};
 use linux_perf_data::{
@@ -18,8 +19,8 @@ use linux_perf_data::{
 };
 use linux_perf_event_reader::constants::PERF_CONTEXT_MAX;
 use linux_perf_event_reader::{
-    CommOrExecRecord, CommonData, ContextSwitchRecord, ForkOrExitRecord, Mmap2FileId, Mmap2Record,
-    MmapRecord, RawDataU64, SampleRecord,
+    CommOrExecRecord, CommonData, ContextSwitchRecord, CpuMode, ForkOrExitRecord, Mmap2FileId,
+    Mmap2Record, MmapRecord, RawDataU64, SampleRecord, TaskWasPreempted,
 };
 use memmap2::Mmap;
 use object::{CompressedFileRange, CompressionFormat, Object, ObjectSection};
@@ -29,31 +30,52 @@ use wholesym::samply_symbols::demangle_any;
 use wholesym::{samply_symbols, CodeId, ElfBuildId};
 
 use super::avma_range::AvmaRange;
+use super::clock_skew::ClockSkewDetector;
 use super::convert_regs::ConvertRegs;
 use super::event_interpretation::{EventInterpretation, OffCpuIndicator};
-use super::injected_jit_object::{correct_bad_perf_jit_so_file, jit_function_name};
+use super::event_tracks::EventTracks;
+use super::injected_jit_object::{
+    correct_bad_perf_jit_so_file, is_injected_jit_so_path, jit_function_name,
+};
 use super::kernel_symbols::{kernel_module_build_id, KernelSymbols};
+use super::lbr::replace_user_frames_with_lbr_call_stack;
 use super::mmap_range_or_vec::MmapRangeOrVec;
 use super::pe_mappings::{PeMappings, SuspectedPeMapping};
 use super::per_cpu::Cpus;
+use super::priority_inversion::{PriorityInversion, PriorityInversionDetector};
 use super::processes::Processes;
 use super::rss_stat::{RssStat, MM_ANONPAGES, MM_FILEPAGES, MM_SHMEMPAGES, MM_SWAPENTS};
+use super::sched_switch::SchedSwitch;
+use super::sched_wakeup::SchedWakeup;
+use super::stack_usage::StackUsage;
 use super::svma_file_range::compute_vma_bias;
+use super::usdt::UsdtProbe;
 use super::vdso::VdsoObject;
+use crate::shared::app_markers::{AppMarker, AppMarkerEvent, AppMarkerEventKind, AppMarkerPairer};
 use crate::shared::context_switch::{ContextSwitchHandler, OffCpuSampleGroup};
+use crate::shared::focus_events::{add_focus_markers, FocusEvent};
+use crate::shared::input_events::{add_input_event_markers, InputEvent};
 use crate::shared::jit_category_manager::JitCategoryManager;
 use crate::shared::lib_mappings::{AndroidArtInfo, LibMappingInfo};
+use crate::shared::marker_file::MarkerNameWatcher;
+use crate::shared::output_markers::{add_output_log_markers, OutputLine};
 use crate::shared::process_sample_data::{
     OtherEventMarker, RssStatMarker, RssStatMember, SchedSwitchMarkerOnCpuTrack,
     SchedSwitchMarkerOnThreadTrack,
 };
+use crate::shared::profiler_overhead::{OverheadCounters, OverheadSample, OverheadTotals};
 use crate::shared::recording_props::ProfileCreationProps;
-use crate::shared::timestamp_converter::TimestampConverter;
+use crate::shared::recursion_folding::RecursionFolding;
+use crate::shared::timestamp_converter::{TimestampConverter, TscConversion};
 use crate::shared::types::{StackFrame, StackMode};
 use crate::shared::unresolved_samples::{
     UnresolvedSamples, UnresolvedStackHandle, UnresolvedStacks,
 };
-use crate::shared::utils::open_file_with_fallback;
+use crate::shared::user_markers::{add_user_markers, UserMarker};
+use crate::shared::utils::{
+    foreign_process_root, open_file_from_perf_build_id_cache, open_file_in_process_root,
+    open_file_with_fallback, perf_build_id_cache_dir,
+};
 
 pub type BoxedProductNameGenerator = Box<dyn FnOnce(&str) -> String>;
 
@@ -71,6 +93,7 @@ where
     delayed_product_name_generator: Option<BoxedProductNameGenerator>,
     linux_version: Option<String>,
     extra_binary_artifact_dir: Option<PathBuf>,
+    perf_build_id_cache_dir: Option<PathBuf>,
     context_switch_handler: ContextSwitchHandler,
     unresolved_stacks: UnresolvedStacks,
     off_cpu_weight_per_sample: i32,
@@ -85,9 +108,38 @@ where
     jit_category_manager: JitCategoryManager,
     cpus: Option<Cpus>,
 
-    /// Whether repeated frames at the base of the stack should be folded
-    /// into one frame.
-    fold_recursive_prefix: bool,
+    /// The library that each thread is currently mapping into memory, by tid.
+    pending_library_mappings: HashMap<i32, PendingLibraryMapping>,
+
+    priority_inversion_detector: PriorityInversionDetector,
+
+    /// The range of user stack pointers in each thread's samples.
+    stack_usage: StackUsage,
+
+    /// The sample tracks of the sampled events other than the main event.
+    event_tracks: EventTracks,
+
+    clock_skew_detector: ClockSkewDetector,
+
+    /// The USDT probes which are recorded, by the index in their events.
+    usdt_probes: Vec<UsdtProbe>,
+
+    /// Matches the begin and end messages from the marker socket.
+    app_marker_pairer: AppMarkerPairer,
+
+    /// Watches the marker files for the marker which ends the recording.
+    stop_marker_watcher: Option<MarkerNameWatcher>,
+
+    /// The counters of `--measure-overhead`, once the first sample is added.
+    overhead_counters: Option<OverheadCounters>,
+
+    /// The root directories of processes in other mount namespaces, by pid.
+    /// Only used when recording, because the processes of an imported
+    /// perf.data file don't exist anymore.
+    process_roots: Option<HashMap<i32, Option<PathBuf>>>,
+
+    /// How repeated frames in the stacks are collapsed.
+    recursion_folding: RecursionFolding,
 
     /// Determines how the addresses in sample call chains should be interpreted.
     /// Any addresses after the first frame address are either "return addresses"
@@ -104,6 +156,10 @@ where
 
 const DEFAULT_OFF_CPU_SAMPLING_INTERVAL_NS: u64 = 1_000_000; // 1ms
 
+/// The number of wakers kept in a wake-up chain. Long-running producer /
+/// consumer loops would otherwise grow their chains without bound.
+const MAX_WAKE_CHAIN_LENGTH: usize = 8;
+
 impl<U> Converter<U>
 where
     U: Unwinder<Module = Module<MmapRangeOrVec>> + Default,
@@ -132,6 +188,12 @@ where
             reference_timestamp,
             interval,
         );
+        if let Some(aux_event_name) = interpretation
+            .aux_event_attr_index
+            .and_then(|index| interpretation.event_names.get(index))
+        {
+            profile.set_sample_weight_names(&interpretation.main_event_name, aux_event_name);
+        }
         let (off_cpu_sampling_interval_ns, off_cpu_weight_per_sample) =
             match &interpretation.sampling_is_time_based {
                 Some(interval_ns) => (*interval_ns, 1),
@@ -210,6 +272,7 @@ where
         let timestamp_converter = TimestampConverter {
             reference_raw: first_sample_time,
             raw_to_ns_factor: 1,
+            tsc_conversion: None,
         };
 
         let cpus = if profile_creation_props.create_per_cpu_threads {
@@ -233,6 +296,7 @@ where
             delayed_product_name_generator,
             linux_version: linux_version.map(ToOwned::to_owned),
             extra_binary_artifact_dir: extra_binary_artifact_dir.map(ToOwned::to_owned),
+            perf_build_id_cache_dir: perf_build_id_cache_dir(),
             off_cpu_weight_per_sample,
             context_switch_handler: ContextSwitchHandler::new(off_cpu_sampling_interval_ns),
             unresolved_stacks: UnresolvedStacks::default(),
@@ -245,14 +309,29 @@ where
             simpleperf_symbol_tables_kernel_modules,
             pe_mappings: PeMappings::new(),
             jit_category_manager: JitCategoryManager::new(),
-            fold_recursive_prefix: profile_creation_props.fold_recursive_prefix,
+            recursion_folding: profile_creation_props.recursion_folding,
             cpus,
+            pending_library_mappings: HashMap::new(),
+            priority_inversion_detector: PriorityInversionDetector::default(),
+            stack_usage: StackUsage::default(),
+            event_tracks: EventTracks::default(),
+            clock_skew_detector: ClockSkewDetector::default(),
+            usdt_probes: Vec::new(),
+            app_marker_pairer: AppMarkerPairer::default(),
+            stop_marker_watcher: None,
+            overhead_counters: None,
+            process_roots: None,
             call_chain_return_addresses_are_preadjusted,
         }
     }
 
     pub fn finish(mut self) -> Profile {
+        for (tid, mapping) in std::mem::take(&mut self.pending_library_mappings) {
+            self.add_library_mapping_marker(tid, mapping);
+        }
         let mut profile = self.profile;
+        self.stack_usage.add_to_profile(&mut profile);
+        self.clock_skew_detector.report(&mut profile);
         self.processes.finish(
             &mut profile,
             &self.unresolved_stacks,
@@ -262,9 +341,194 @@ where
         profile
     }
 
+    /// Returns a copy of the profile with the samples so far, for `--live`.
+    pub fn snapshot(&self) -> Profile {
+        let mut profile = self.profile.clone();
+        self.processes
+            .flush_snapshot(&mut profile, &self.unresolved_stacks);
+        profile
+    }
+
+    /// Returns the timestamp which should be used for a record, which is
+    /// different from `timestamp` if the record's timestamp is earlier than
+    /// the timestamp of a record which came before it.
+    pub fn correct_timestamp(&mut self, timestamp: u64) -> u64 {
+        self.clock_skew_detector.correct(timestamp)
+    }
+
+    /// Sets how TSC values are converted into perf timestamps, from the
+    /// perf.data file's TIME_CONV record.
+    pub fn set_tsc_conversion(&mut self, tsc_conversion: TscConversion) {
+        self.timestamp_converter.tsc_conversion = Some(tsc_conversion);
+    }
+
+    /// Makes [`Converter::has_seen_stop_marker`] return true once a marker
+    /// with this name has been added to one of the profiled marker files.
+    pub fn watch_for_stop_marker(&mut self, name: String) {
+        self.stop_marker_watcher = Some(MarkerNameWatcher::new(name));
+    }
+
+    /// Makes the converter open the files of processes which run in a
+    /// container through `/proc/<pid>/root`, because their paths don't exist
+    /// on the host. Only call this for live recordings.
+    pub fn open_files_through_process_roots(&mut self) {
+        self.process_roots = Some(HashMap::new());
+    }
+
+    fn process_root(&mut self, pid: i32) -> Option<PathBuf> {
+        let process_roots = self.process_roots.as_mut()?;
+        process_roots
+            .entry(pid)
+            .or_insert_with(|| foreign_process_root(pid))
+            .clone()
+    }
+
+    /// The pids of the processes which are still running, i.e. which haven't
+    /// been finished when the process exited.
+    pub fn live_pids(&self) -> Vec<i32> {
+        self.processes.live_pids()
+    }
+
+    pub fn has_seen_stop_marker(&mut self) -> bool {
+        match &mut self.stop_marker_watcher {
+            Some(watcher) => watcher.poll(),
+            None => false,
+        }
+    }
+
+    /// Adds markers for user input which happened during the recording.
+    /// The events need to be sorted by timestamp.
+    pub fn add_input_event_markers(&mut self, events: &[InputEvent]) {
+        add_input_event_markers(&mut self.profile, events, &self.timestamp_converter);
+    }
+
+    /// Adds the markers which the user added by hand during the recording.
+    /// The markers need to be sorted by timestamp.
+    pub fn add_user_markers(&mut self, markers: &[UserMarker]) {
+        add_user_markers(&mut self.profile, markers, &self.timestamp_converter);
+    }
+
+    /// Adds the output lines of the launched command, for `--log-output`.
+    /// The lines need to be sorted by timestamp.
+    pub fn add_output_log_markers(&mut self, lines: &[OutputLine]) {
+        add_output_log_markers(&mut self.profile, lines, &self.timestamp_converter);
+    }
+
+    /// Adds a sample of samply's own overhead to the counters on the process
+    /// `pid`, which are created by the first call.
+    pub fn add_overhead_sample(&mut self, pid: i32, sample: &OverheadSample) {
+        let counters = match self.overhead_counters {
+            Some(counters) => counters,
+            None => {
+                let process = self.processes.get_by_pid(pid, &mut self.profile);
+                let counters = OverheadCounters::new(&mut self.profile, process.profile_process);
+                *self.overhead_counters.insert(counters)
+            }
+        };
+        counters.add_sample(&mut self.profile, sample, &self.timestamp_converter);
+    }
+
+    /// Adds the totals of samply's own overhead to the profile metadata.
+    pub fn add_overhead_totals(&mut self, totals: &OverheadTotals) {
+        totals.add_to_profile(&mut self.profile);
+    }
+
+    pub fn set_usdt_probes(&mut self, usdt_probes: Vec<UsdtProbe>) {
+        self.usdt_probes = usdt_probes;
+    }
+
+    /// Adds an instant marker for a firing of a USDT probe, with the decoded
+    /// arguments of the probe.
+    pub fn handle_usdt_sample(&mut self, e: &SampleRecord, probe_index: usize) {
+        let (Some(pid), Some(tid), Some(timestamp)) = (e.pid, e.tid, e.timestamp) else {
+            return;
+        };
+        let Some(probe) = self.usdt_probes.get(probe_index) else {
+            return;
+        };
+        let marker = UsdtMarker {
+            args: probe.format_args(e.user_regs.as_ref()),
+        };
+        let name = probe.marker_name();
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+        let timestamp = self.timestamp_converter.convert_time(timestamp);
+        self.profile.add_marker(
+            thread.profile_thread,
+            CategoryHandle::OTHER,
+            &name,
+            marker,
+            MarkerTiming::Instant(timestamp),
+        );
+    }
+
+    /// Adds a marker which the profiled process sent over the marker socket
+    /// to the marker track of its thread. Markers from threads which haven't
+    /// been seen in any records are dropped.
+    pub fn handle_app_marker_event(&mut self, event: AppMarkerEvent) {
+        if event.kind == AppMarkerEventKind::Counter {
+            self.handle_app_counter_event(event);
+            return;
+        }
+        let Some(marker) = self.app_marker_pairer.handle_event(event) else {
+            return;
+        };
+        let Some(pid) = self.processes.pid_for_tid(marker.tid) else {
+            return;
+        };
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let thread = process
+            .threads
+            .get_thread_by_tid(marker.tid, &mut self.profile);
+        let start = self.timestamp_converter.convert_time(marker.start_raw);
+        let timing = match marker.end_raw {
+            Some(end_raw) => {
+                MarkerTiming::Interval(start, self.timestamp_converter.convert_time(end_raw))
+            }
+            None => MarkerTiming::Instant(start),
+        };
+        self.profile.add_marker(
+            thread.profile_thread,
+            CategoryHandle::OTHER,
+            &marker.name,
+            AppMarker {
+                payload: marker.payload,
+            },
+            timing,
+        );
+    }
+
+    fn handle_app_counter_event(&mut self, event: AppMarkerEvent) {
+        let Some(delta) = event.payload.and_then(|delta| delta.parse::<f64>().ok()) else {
+            return;
+        };
+        let Some(pid) = self.processes.pid_for_tid(event.tid) else {
+            return;
+        };
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let counter = process.get_or_make_app_counter(&event.name, &mut self.profile);
+        let timestamp = self.timestamp_converter.convert_time(event.timestamp_raw);
+        self.profile
+            .add_counter_sample(counter, timestamp, delta, 1);
+    }
+
+    /// Adds markers for the focused window during the recording. The events
+    /// need to be sorted by timestamp; `end_raw` is the end of the recording.
+    pub fn add_focus_markers(&mut self, events: &[FocusEvent], end_raw: u64) {
+        add_focus_markers(
+            &mut self.profile,
+            events,
+            end_raw,
+            &self.timestamp_converter,
+        );
+    }
+
+    /// `lbr_call_stack` has the call sites from the sample's LBR call stack,
+    /// if it was recorded with `perf record --call-graph lbr`.
     pub fn handle_main_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
+        lbr_call_stack: Option<&[u64]>,
     ) {
         let pid = e.pid.expect("Can't handle samples without pids");
         let tid = e.tid.expect("Can't handle samples without tids");
@@ -285,6 +549,7 @@ where
             &mut self.profile,
             &self.timestamp_converter,
         );
+        process.check_perf_map(timestamp, &mut self.jit_category_manager, &mut self.profile);
 
         let mut stack = Vec::new();
         Self::get_sample_stack::<C>(
@@ -292,8 +557,9 @@ where
             &process.unwinder,
             &mut self.cache,
             &mut stack,
-            self.fold_recursive_prefix,
+            self.recursion_folding,
             self.call_chain_return_addresses_are_preadjusted,
+            lbr_call_stack,
         );
 
         let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
@@ -305,6 +571,11 @@ where
 
         thread.last_sample_timestamp = Some(timestamp);
         let thread_handle = thread.profile_thread;
+        if let Some(regs) = &e.user_regs {
+            let (_, sp, _) = C::convert_regs(regs);
+            self.stack_usage
+                .observe(thread_handle, tid, thread.name.as_deref(), sp);
+        }
 
         // Consume off-cpu time and clear any saved off-CPU stack.
         let off_cpu_sample = self
@@ -411,19 +682,40 @@ where
             &process.unwinder,
             &mut self.cache,
             &mut stack,
-            self.fold_recursive_prefix,
+            self.recursion_folding,
             self.call_chain_return_addresses_are_preadjusted,
+            None,
         );
 
         let stack_index = self
             .unresolved_stacks
             .convert_no_kernel(stack.iter().rev().cloned());
-        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
-        thread.off_cpu_stack = Some(stack_index);
 
         let timestamp_mono = e
             .timestamp
             .expect("Can't handle context switch without time");
+        let sched_switch = e
+            .raw
+            .and_then(|raw| SchedSwitch::parse(raw, self.endian).ok());
+        let prev_was_runnable = sched_switch
+            .as_ref()
+            .is_some_and(SchedSwitch::prev_was_runnable);
+        if let Some(sched_switch) = &sched_switch {
+            let detector = &mut self.priority_inversion_detector;
+            detector.set_priority(sched_switch.prev_pid, sched_switch.prev_prio);
+            detector.switch_out(sched_switch.prev_pid, timestamp_mono, prev_was_runnable);
+            if sched_switch.next_pid != 0 {
+                detector.set_priority(sched_switch.next_pid, sched_switch.next_prio);
+                detector.switch_in(sched_switch.next_pid, timestamp_mono);
+            }
+        }
+
+        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+        thread.off_cpu_stack = Some(stack_index);
+        if !prev_was_runnable {
+            thread.wake_chain.clear();
+        }
+
         if self.off_cpu_indicator == Some(OffCpuIndicator::SchedSwitchAndSamples) {
             // Treat this sched_switch sample as a switch-out.
             // Sometimes we have sched_switch samples but no context switch records; for
@@ -527,8 +819,9 @@ where
             &process.unwinder,
             &mut self.cache,
             &mut stack,
-            self.fold_recursive_prefix,
+            self.recursion_folding,
             self.call_chain_return_addresses_are_preadjusted,
+            None,
         );
         let unresolved_stack = self.unresolved_stacks.convert(stack.into_iter().rev());
         let thread_handle = process.threads.main_thread.profile_thread;
@@ -555,6 +848,136 @@ where
         );
     }
 
+    /// Handles a sched_waking or sched_wakeup sample. The waking thread gets a
+    /// "Wake" marker with the stack which woke the other thread, and the woken
+    /// thread gets a "Woken" marker with the chain of wake-ups that led to it,
+    /// e.g. "A (1) → B (2) → C (3)", so that latency can be followed across
+    /// threads.
+    pub fn handle_sched_wakeup_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+    ) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let tid = e.tid.expect("Can't handle samples without tids");
+
+        let Some(raw) = e.raw else { return };
+        let Ok(wakeup) = SchedWakeup::parse(raw, self.endian) else {
+            return;
+        };
+
+        let Some(timestamp_mono) = e.timestamp else {
+            eprintln!("sched_wakeup record doesn't have a timestamp");
+            return;
+        };
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let timing = MarkerTiming::Instant(timestamp);
+        let wakee = format!("{} ({})", wakeup.comm, wakeup.pid);
+        self.priority_inversion_detector
+            .set_priority(wakeup.pid, wakeup.prio);
+        let priority_inversion =
+            self.priority_inversion_detector
+                .wakeup(tid, wakeup.pid, timestamp_mono);
+
+        // Thread 0 is the idle thread, which wakes threads from interrupts.
+        // Those wake-ups start a new chain.
+        let (waker, chain) = if tid == 0 {
+            ("<Idle> (0)".to_string(), Vec::new())
+        } else {
+            let process = self.processes.get_by_pid(pid, &mut self.profile);
+            process.check_jitdump(
+                &mut self.jit_category_manager,
+                &mut self.profile,
+                &self.timestamp_converter,
+            );
+
+            let mut stack = Vec::new();
+            Self::get_sample_stack::<C>(
+                e,
+                &process.unwinder,
+                &mut self.cache,
+                &mut stack,
+                self.recursion_folding,
+                self.call_chain_return_addresses_are_preadjusted,
+                None,
+            );
+            let unresolved_stack = self.unresolved_stacks.convert(stack.into_iter().rev());
+
+            let process_name = process.name.clone();
+            let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+            let thread_handle = thread.profile_thread;
+            let waker_name = thread.name.clone().or(process_name);
+            let waker = format!("{} ({tid})", waker_name.as_deref().unwrap_or("unknown"));
+            let mut chain = thread.wake_chain.clone();
+            chain.push(waker.clone());
+            if chain.len() > MAX_WAKE_CHAIN_LENGTH {
+                chain.drain(..chain.len() - MAX_WAKE_CHAIN_LENGTH);
+            }
+
+            let marker = WakeupMarker {
+                waker: waker.clone(),
+                wakee: wakee.clone(),
+                chain: format!("{} → {wakee}", chain.join(" → ")),
+            };
+            let marker_handle = self.profile.add_marker(
+                thread_handle,
+                CategoryHandle::OTHER,
+                "Wake",
+                marker,
+                timing.clone(),
+            );
+            process.unresolved_samples.attach_stack_to_marker(
+                thread_handle,
+                timestamp,
+                timestamp_mono,
+                unresolved_stack,
+                marker_handle,
+            );
+            (waker, chain)
+        };
+
+        // Only threads which were seen before get a marker, because we don't
+        // know which process an unknown thread belongs to.
+        let Some(wakee_pid) = self.processes.pid_for_tid(wakeup.pid) else {
+            return;
+        };
+        let chain_description = if chain.is_empty() {
+            wakee.clone()
+        } else {
+            format!("{} → {wakee}", chain.join(" → "))
+        };
+        let process = self.processes.get_by_pid(wakee_pid, &mut self.profile);
+        let thread = process
+            .threads
+            .get_thread_by_tid(wakeup.pid, &mut self.profile);
+        thread.wake_chain = chain;
+        if let Some(priority_inversion) = priority_inversion {
+            let start = self
+                .timestamp_converter
+                .convert_time(priority_inversion.blocked_since);
+            self.profile.add_marker(
+                thread.profile_thread,
+                CategoryHandle::OTHER,
+                "Priority inversion",
+                PriorityInversionMarker {
+                    waker: waker.clone(),
+                    priority_inversion,
+                },
+                MarkerTiming::Interval(start, timestamp),
+            );
+        }
+        self.profile.add_marker(
+            thread.profile_thread,
+            CategoryHandle::OTHER,
+            "Woken",
+            WakeupMarker {
+                waker,
+                wakee,
+                chain: chain_description,
+            },
+            timing,
+        );
+    }
+
     pub fn handle_other_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
         &mut self,
         e: &SampleRecord,
@@ -579,8 +1002,9 @@ where
             &process.unwinder,
             &mut self.cache,
             &mut stack,
-            self.fold_recursive_prefix,
+            self.recursion_folding,
             self.call_chain_return_addresses_are_preadjusted,
+            None,
         );
 
         let thread_handle = match e.tid {
@@ -613,6 +1037,123 @@ where
         }
     }
 
+    /// Handles a sample of the auxiliary event, e.g. cache-misses next to cycles.
+    /// The sample's period, i.e. the number of events since the previous sample,
+    /// becomes the auxiliary weight of a sample with zero regular weight.
+    pub fn handle_aux_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+    ) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let timestamp_mono = e
+            .timestamp
+            .expect("Can't handle samples without timestamps");
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        process.check_jitdump(
+            &mut self.jit_category_manager,
+            &mut self.profile,
+            &self.timestamp_converter,
+        );
+
+        let mut stack = Vec::new();
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            &mut stack,
+            self.recursion_folding,
+            self.call_chain_return_addresses_are_preadjusted,
+            None,
+        );
+
+        let thread_handle = match e.tid {
+            Some(tid) => {
+                process
+                    .threads
+                    .get_thread_by_tid(tid, &mut self.profile)
+                    .profile_thread
+            }
+            None => process.threads.main_thread.profile_thread,
+        };
+
+        let unresolved_stack = self.unresolved_stacks.convert(stack.into_iter().rev());
+        let aux_weight = i32::try_from(e.period.unwrap_or(1)).unwrap_or(i32::MAX);
+        process.unresolved_samples.add_aux_event_sample(
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            unresolved_stack,
+            aux_weight,
+            None,
+        );
+    }
+
+    /// Handles a sample of a sampled event which gets its own track, e.g.
+    /// cache-misses next to cycles in a perf.data file.
+    pub fn handle_track_event_sample<C: ConvertRegs<UnwindRegs = U::UnwindRegs>>(
+        &mut self,
+        e: &SampleRecord,
+        attr_index: usize,
+        lbr_call_stack: Option<&[u64]>,
+    ) {
+        let pid = e.pid.expect("Can't handle samples without pids");
+        let tid = e.tid.expect("Can't handle samples without tids");
+        if tid == 0 {
+            // Ignore samples in the idle thread.
+            return;
+        }
+        let timestamp_mono = e
+            .timestamp
+            .expect("Can't handle samples without timestamps");
+        let timestamp = self.timestamp_converter.convert_time(timestamp_mono);
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        process.check_jitdump(
+            &mut self.jit_category_manager,
+            &mut self.profile,
+            &self.timestamp_converter,
+        );
+
+        let mut stack = Vec::new();
+        Self::get_sample_stack::<C>(
+            e,
+            &process.unwinder,
+            &mut self.cache,
+            &mut stack,
+            self.recursion_folding,
+            self.call_chain_return_addresses_are_preadjusted,
+            lbr_call_stack,
+        );
+
+        let process_handle = process.threads.profile_process;
+        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+        let event_name = self
+            .event_names
+            .get(attr_index)
+            .map_or("<unknown event>", String::as_str);
+        let track = self.event_tracks.get_or_create(
+            pid,
+            tid,
+            attr_index,
+            process_handle,
+            thread.name.as_deref(),
+            event_name,
+            timestamp,
+            &mut self.profile,
+        );
+
+        let unresolved_stack = self.unresolved_stacks.convert(stack.into_iter().rev());
+        process.unresolved_samples.add_sample(
+            track,
+            timestamp,
+            timestamp_mono,
+            unresolved_stack,
+            CpuDelta::ZERO,
+            1,
+            None,
+        );
+    }
+
     /// Get the stack contained in this sample, and put it into `stack`.
     ///
     /// We can have both the kernel stack and the user stack, or just one of
@@ -637,8 +1178,9 @@ where
         unwinder: &U,
         cache: &mut U::Cache,
         stack: &mut Vec<StackFrame>,
-        fold_recursive_prefix: bool,
+        recursion_folding: RecursionFolding,
         call_chain_return_addresses_are_preadjusted: bool,
+        lbr_call_stack: Option<&[u64]>,
     ) {
         stack.truncate(0);
 
@@ -669,8 +1211,20 @@ where
             }
         }
 
+        // The LBR call stack is more reliable than the frame pointer callchain.
+        let lbr_call_stack = lbr_call_stack.filter(|call_sites| !call_sites.is_empty());
+        if let Some(call_sites) = lbr_call_stack {
+            let user_ip = match e.cpu_mode {
+                CpuMode::User => e.ip,
+                _ => None,
+            };
+            replace_user_frames_with_lbr_call_stack(stack, call_sites, user_ip);
+        }
+
         // Append the user stack with the help of DWARF unwinding.
-        if let (Some(regs), Some((user_stack, _))) = (&e.user_regs, e.user_stack) {
+        if let (None, Some(regs), Some((user_stack, _))) =
+            (lbr_call_stack, &e.user_regs, e.user_stack)
+        {
             let ustack_bytes = RawDataU64::from_raw_data::<LittleEndian>(user_stack);
             let (pc, sp, regs) = C::convert_regs(regs);
             let mut read_stack = |addr: u64| {
@@ -707,22 +1261,25 @@ where
             if let Some(ip) = e.ip {
                 stack.push(StackFrame::InstructionPointer(ip, e.cpu_mode.into()));
             }
-        } else if fold_recursive_prefix {
-            let last_frame = *stack.last().unwrap();
-            while stack.len() >= 2 && stack[stack.len() - 2] == last_frame {
-                stack.pop();
-            }
+        } else {
+            recursion_folding.fold(stack);
         }
     }
 
     pub fn handle_mmap(&mut self, e: MmapRecord, timestamp: u64) {
         let mut path = e.path.as_slice();
-        self.add_mmap_marker(e.pid, e.tid, &path, timestamp);
+        let is_injected_jit_so = is_injected_jit_so_path(&path);
+        if !is_injected_jit_so {
+            self.add_mmap_marker(e.pid, e.tid, &path, timestamp);
+        }
 
         if self.check_jitdump_or_marker_file(&path, e.pid, e.tid) {
             // Not a DSO.
             return;
         }
+        if !is_injected_jit_so {
+            self.track_library_mapping(e.pid, e.tid, &path, e.is_executable, timestamp);
+        }
 
         if e.page_offset == 0 {
             self.pe_mappings.check_mmap(&path, e.address);
@@ -764,21 +1321,32 @@ where
     }
 
     pub fn handle_mmap2(&mut self, e: Mmap2Record, timestamp: u64) {
+        const PROT_EXEC: u32 = 0b100;
+
         let path = e.path.as_slice();
-        self.add_mmap_marker(e.pid, e.tid, &path, timestamp);
+        // `perf inject --jit` synthesizes one mapping per JIT function. These
+        // aren't real mmap calls, so they don't get markers.
+        let is_injected_jit_so = is_injected_jit_so_path(&path);
+        if !is_injected_jit_so {
+            self.add_mmap_marker(e.pid, e.tid, &path, timestamp);
+        }
 
         if self.check_jitdump_or_marker_file(&path, e.pid, e.tid) {
             // Not a DSO.
             return;
         }
+        // `perf inject --jit` stores the st_mode of the jitted .so file in the
+        // prot field, so PROT_EXEC is only set if the file is world-readable.
+        let is_executable = e.protection & PROT_EXEC != 0 || is_injected_jit_so;
+        if !is_injected_jit_so {
+            self.track_library_mapping(e.pid, e.tid, &path, is_executable, timestamp);
+        }
 
         if e.page_offset == 0 {
             self.pe_mappings.check_mmap(&path, e.address);
         }
 
-        const PROT_EXEC: u32 = 0b100;
-        if e.protection & PROT_EXEC == 0 && !self.simpleperf_symbol_tables_user.contains_key(&*path)
-        {
+        if !is_executable && !self.simpleperf_symbol_tables_user.contains_key(&*path) {
             // Ignore non-executable mappings.
             // Don't ignore mappings that simpleperf found symbols for, even if they're
             // non-executable. TODO: Find out why .vdex and .jar mappings with symbols aren't
@@ -823,8 +1391,13 @@ where
             None => path,
         };
 
+        let path = match self.process_root(pid) {
+            Some(root) => root.join(path.trim_start_matches('/')),
+            None => PathBuf::from(path),
+        };
+
         if filename.starts_with("jit-") && filename.ends_with(".dump") {
-            let jitdump_path = Path::new(path);
+            let jitdump_path = &path;
             let process = self.processes.get_by_pid(pid, &mut self.profile);
             let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
             let profile_thread = thread.profile_thread;
@@ -837,7 +1410,10 @@ where
         }
 
         if filename.starts_with("marker-") && filename.ends_with(".txt") {
-            let marker_file_path = Path::new(path);
+            let marker_file_path = &path;
+            if let Some(watcher) = &mut self.stop_marker_watcher {
+                watcher.add_file(marker_file_path);
+            }
             let process = self.processes.get_by_pid(pid, &mut self.profile);
             let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
             let profile_thread = thread.profile_thread;
@@ -852,6 +1428,29 @@ where
         false
     }
 
+    /// Adds a marker for events which the kernel dropped because the ring
+    /// buffer was full, on the thread which the kernel attributed them to.
+    pub fn handle_lost(&mut self, count: u64, common: CommonData) {
+        let (Some(pid), Some(tid), Some(timestamp)) = (common.pid, common.tid, common.timestamp)
+        else {
+            return;
+        };
+        if tid == 0 {
+            return;
+        }
+        let process = self.processes.get_by_pid(pid, &mut self.profile);
+        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+        let timestamp = timestamp.max(self.timestamp_converter.reference_raw);
+        let timestamp = self.timestamp_converter.convert_time(timestamp);
+        self.profile.add_marker(
+            thread.profile_thread,
+            CategoryHandle::OTHER,
+            "Lost events",
+            LostEventsMarker(count),
+            MarkerTiming::Instant(timestamp),
+        );
+    }
+
     pub fn handle_context_switch(&mut self, e: ContextSwitchRecord, common: CommonData) {
         let pid = common.pid.expect("Can't handle samples without pids");
         let tid = common.tid.expect("Can't handle samples without tids");
@@ -867,6 +1466,8 @@ where
 
         match e {
             ContextSwitchRecord::In { .. } => {
+                self.priority_inversion_detector.switch_in(tid, timestamp);
+
                 // Consume off-cpu time and clear the saved off-CPU stack.
                 let off_cpu_sample = self
                     .context_switch_handler
@@ -943,7 +1544,16 @@ where
             ContextSwitchRecord::Out { preempted, .. } => {
                 self.context_switch_handler
                     .handle_switch_out(timestamp, &mut thread.context_switch_data);
-                if let (Some(cpus), Some(cpu_index)) = (&mut self.cpus, Some(common.cpu.unwrap())) {
+                if preempted == TaskWasPreempted::No {
+                    // The thread blocked, so whatever wakes it next starts a new chain.
+                    thread.wake_chain.clear();
+                }
+                self.priority_inversion_detector.switch_out(
+                    tid,
+                    timestamp,
+                    preempted == TaskWasPreempted::Yes,
+                );
+                if let (Some(cpus), Some(cpu_index)) = (&mut self.cpus, common.cpu) {
                     let combined_thread = cpus.combined_thread_handle();
                     let cpu = cpus.get_mut(cpu_index as usize, &mut self.profile);
                     self.context_switch_handler
@@ -1006,6 +1616,12 @@ where
     pub fn handle_exit(&mut self, e: ForkOrExitRecord) {
         let is_main = e.pid == e.tid;
         let end_time = self.timestamp_converter.convert_time(e.timestamp);
+        self.event_tracks.notify_exit(
+            e.pid,
+            (!is_main).then_some(e.tid),
+            end_time,
+            &mut self.profile,
+        );
         if is_main {
             self.processes.remove(
                 e.pid,
@@ -1280,10 +1896,25 @@ where
         let mut file = None;
         let mut path = mapping_info.path.to_string_lossy().to_string();
 
-        if let Ok((f, p)) = open_file_with_fallback(
-            &mapping_info.path,
-            self.extra_binary_artifact_dir.as_deref(),
-        ) {
+        let opened_file = self
+            .process_root(process_pid)
+            .and_then(|root| open_file_in_process_root(&root, &mapping_info.path).ok())
+            .or_else(|| {
+                open_file_with_fallback(
+                    &mapping_info.path,
+                    self.extra_binary_artifact_dir.as_deref(),
+                )
+                .ok()
+            })
+            .or_else(|| {
+                // The file may have been removed or replaced since the recording,
+                // but perf may have copied it into its build-id cache.
+                open_file_from_perf_build_id_cache(
+                    self.perf_build_id_cache_dir.as_deref()?,
+                    build_id?,
+                )
+            });
+        if let Some((f, p)) = opened_file {
             // Fix up bad files from `perf inject --jit`.
             if let Some((fixed_file, fixed_path)) = correct_bad_perf_jit_so_file(&f, &path) {
                 file = Some(fixed_file);
@@ -1642,6 +2273,82 @@ where
             MarkerTiming::Instant(timestamp),
         );
     }
+
+    /// The dynamic loader maps a library with a quick succession of mmap calls
+    /// for the file's segments, on the thread which calls dlopen (or on the
+    /// main thread during startup). We combine each such run of mmaps for the
+    /// same file into one marker, from the first to the last mapping.
+    ///
+    /// This is only the mapping phase of the load: the mmap records don't tell
+    /// us when the loader is done with relocations and initializers. Windows
+    /// image loads are out of scope, their ETW events carry no duration.
+    fn track_library_mapping(
+        &mut self,
+        pid: i32,
+        tid: i32,
+        path_slice: &[u8],
+        is_executable: bool,
+        timestamp: u64,
+    ) {
+        if self.current_sample_time == self.timestamp_converter.reference_raw {
+            // Ignore mmap events before the first sample, like in add_mmap_marker.
+            return;
+        }
+        if pid == -1 || path_slice.is_empty() || path_slice.starts_with(b"[") {
+            // Ignore kernel modules and special mappings like [vdso].
+            return;
+        }
+        if let Some(mapping) = self.pending_library_mappings.get_mut(&tid) {
+            if mapping.path == path_slice {
+                mapping.end = timestamp;
+                mapping.has_executable_mapping |= is_executable;
+                return;
+            }
+        }
+        let mapping = PendingLibraryMapping {
+            pid,
+            path: path_slice.to_owned(),
+            start: timestamp,
+            end: timestamp,
+            has_executable_mapping: is_executable,
+        };
+        if let Some(previous_mapping) = self.pending_library_mappings.insert(tid, mapping) {
+            self.add_library_mapping_marker(tid, previous_mapping);
+        }
+    }
+
+    fn add_library_mapping_marker(&mut self, tid: i32, mapping: PendingLibraryMapping) {
+        if !mapping.has_executable_mapping {
+            // This was a data file, not a library.
+            return;
+        }
+        let process = self.processes.get_by_pid(mapping.pid, &mut self.profile);
+        let thread = process.threads.get_thread_by_tid(tid, &mut self.profile);
+        let start = self.timestamp_converter.convert_time(mapping.start);
+        let timing = if mapping.end == mapping.start {
+            // A single mmap call, there's no interval to show.
+            MarkerTiming::Instant(start)
+        } else {
+            MarkerTiming::Interval(start, self.timestamp_converter.convert_time(mapping.end))
+        };
+        let path = String::from_utf8_lossy(&mapping.path).into_owned();
+        self.profile.add_marker(
+            thread.profile_thread,
+            CategoryHandle::OTHER,
+            "LibraryMapping",
+            LibraryMappingMarker(path),
+            timing,
+        );
+    }
+}
+
+/// A library which a thread is in the process of mapping into memory.
+struct PendingLibraryMapping {
+    pid: i32,
+    path: Vec<u8>,
+    start: u64,
+    end: u64,
+    has_executable_mapping: bool,
 }
 
 // #[test]
@@ -1793,6 +2500,237 @@ fn path_from_unix_bytes(path_slice: &[u8]) -> Option<&Path> {
     Some(Path::new(std::str::from_utf8(path_slice).ok()?))
 }
 
+struct LibraryMappingMarker(String);
+
+impl ProfilerMarker for LibraryMappingMarker {
+    const MARKER_TYPE_NAME: &'static str = "LibraryMapping";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "name": self.0
+        })
+    }
+
+    fn schema() -> fxprof_processed_profile::MarkerSchema {
+        fxprof_processed_profile::MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![
+                MarkerLocation::MarkerChart,
+                MarkerLocation::MarkerTable,
+                MarkerLocation::TimelineOverview,
+            ],
+            chart_label: Some("{marker.data.name}"),
+            tooltip_label: Some("Library mapping - {marker.data.name}"),
+            table_label: Some("Library mapping - {marker.data.name}"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "name",
+                    label: "Path",
+                    format: MarkerFieldFormat::FilePath,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Static(MarkerStaticField {
+                    label: "Description",
+                    value: "The time from the first to the last mmap of this library's segments by the dynamic loader. This doesn't include relocations or initializers.",
+                }),
+            ],
+        }
+    }
+}
+
+struct WakeupMarker {
+    waker: String,
+    wakee: String,
+    chain: String,
+}
+
+impl ProfilerMarker for WakeupMarker {
+    const MARKER_TYPE_NAME: &'static str = "Wakeup";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "waker": self.waker,
+            "wakee": self.wakee,
+            "chain": self.chain,
+        })
+    }
+
+    fn schema() -> fxprof_processed_profile::MarkerSchema {
+        fxprof_processed_profile::MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![MarkerLocation::MarkerChart, MarkerLocation::MarkerTable],
+            chart_label: Some("{marker.data.wakee}"),
+            tooltip_label: Some("{marker.data.waker} woke {marker.data.wakee}"),
+            table_label: Some("{marker.data.chain}"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "waker",
+                    label: "Waker",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "wakee",
+                    label: "Wakee",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "chain",
+                    label: "Wake-up chain",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Static(MarkerStaticField {
+                    label: "Description",
+                    value: "Emitted when the sched:sched_waking or sched:sched_wakeup tracepoint is hit. Search for a thread's name and tid to follow the chain across threads.",
+                }),
+            ],
+        }
+    }
+}
+
+struct PriorityInversionMarker {
+    waker: String,
+    priority_inversion: PriorityInversion,
+}
+
+impl ProfilerMarker for PriorityInversionMarker {
+    const MARKER_TYPE_NAME: &'static str = "PriorityInversion";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "waker": self.waker,
+            "priority": self.priority_inversion.blocked_priority,
+            "wakerPriority": self.priority_inversion.waker_priority,
+            "wakerPreempted": self.priority_inversion.waker_preempted_ns as f64 / 1_000_000.0,
+        })
+    }
+
+    fn schema() -> fxprof_processed_profile::MarkerSchema {
+        fxprof_processed_profile::MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![
+                MarkerLocation::MarkerChart,
+                MarkerLocation::MarkerTable,
+                MarkerLocation::TimelineOverview,
+            ],
+            chart_label: Some("Priority inversion with {marker.data.waker}"),
+            tooltip_label: Some("Blocked on {marker.data.waker}, which was preempted for {marker.data.wakerPreempted}"),
+            table_label: Some("Blocked on {marker.data.waker}, which was preempted for {marker.data.wakerPreempted}"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "waker",
+                    label: "Woken by",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "priority",
+                    label: "Priority",
+                    format: MarkerFieldFormat::Integer,
+                    searchable: false,
+                }),
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "wakerPriority",
+                    label: "Waker priority",
+                    format: MarkerFieldFormat::Integer,
+                    searchable: false,
+                }),
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "wakerPreempted",
+                    label: "Waker preempted for",
+                    format: MarkerFieldFormat::Duration,
+                    searchable: false,
+                }),
+                MarkerSchemaField::Static(MarkerStaticField {
+                    label: "Description",
+                    value: "This thread was blocked until a thread with a lower priority (a higher number) woke it, and that thread was preempted in the meantime. It likely held a resource which this thread was waiting for.",
+                }),
+            ],
+        }
+    }
+}
+
+struct UsdtMarker {
+    args: String,
+}
+
+impl ProfilerMarker for UsdtMarker {
+    const MARKER_TYPE_NAME: &'static str = "USDT";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "args": self.args,
+        })
+    }
+
+    fn schema() -> fxprof_processed_profile::MarkerSchema {
+        fxprof_processed_profile::MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![MarkerLocation::MarkerChart, MarkerLocation::MarkerTable],
+            chart_label: Some("{marker.name}"),
+            tooltip_label: Some("{marker.name} {marker.data.args}"),
+            table_label: Some("{marker.name} {marker.data.args}"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "args",
+                    label: "Arguments",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Static(MarkerStaticField {
+                    label: "Description",
+                    value: "Emitted when the profiled process hit a USDT probe which was selected with --usdt.",
+                }),
+            ],
+        }
+    }
+}
+
+struct LostEventsMarker(u64);
+
+impl ProfilerMarker for LostEventsMarker {
+    const MARKER_TYPE_NAME: &'static str = "LostEvents";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "count": self.0
+        })
+    }
+
+    fn schema() -> fxprof_processed_profile::MarkerSchema {
+        fxprof_processed_profile::MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![
+                MarkerLocation::MarkerChart,
+                MarkerLocation::MarkerTable,
+                MarkerLocation::TimelineOverview,
+            ],
+            chart_label: Some("{marker.data.count}"),
+            tooltip_label: Some("Lost {marker.data.count} events"),
+            table_label: Some("Lost {marker.data.count} events"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "count",
+                    label: "Lost events",
+                    format: MarkerFieldFormat::Integer,
+                    searchable: false,
+                }),
+                MarkerSchemaField::Static(MarkerStaticField {
+                    label: "Description",
+                    value: "The kernel dropped these events, most of them samples, because samply didn't read them fast enough.",
+                }),
+            ],
+        }
+    }
+}
+
 struct MmapMarker(String);
 
 impl ProfilerMarker for MmapMarker {
@@ -1821,3 +2759,77 @@ impl ProfilerMarker for MmapMarker {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    type TestConverter = Converter<framehop::x86_64::UnwinderX86_64<MmapRangeOrVec>>;
+
+    fn make_test_converter() -> TestConverter {
+        let props = ProfileCreationProps {
+            profile_name: "test".to_owned(),
+            main_thread_only: false,
+            reuse_threads: false,
+            recursion_folding: RecursionFolding::None,
+            unlink_aux_files: false,
+            create_per_cpu_threads: false,
+            override_arch: None,
+            unstable_presymbolicate: false,
+            coreclr: Default::default(),
+            unknown_event_markers: false,
+            post_processing: Default::default(),
+        };
+        let interpretation = EventInterpretation {
+            main_event_attr_index: 0,
+            main_event_name: "cycles".to_string(),
+            aux_event_attr_index: None,
+            track_event_attr_indices: Vec::new(),
+            sampling_is_time_based: Some(1_000_000),
+            off_cpu_indicator: None,
+            context_switches_have_cpu: true,
+            sched_switch_attr_index: None,
+            known_event_indices: HashMap::new(),
+            event_names: vec!["cycles".to_string()],
+        };
+        TestConverter::new(
+            &props,
+            ReferenceTimestamp::from_system_time(SystemTime::UNIX_EPOCH),
+            None,
+            HashMap::new(),
+            None,
+            0,
+            Endianness::LittleEndian,
+            framehop::x86_64::CacheX86_64::new(),
+            None,
+            interpretation,
+            None,
+            false,
+        )
+    }
+
+    #[test]
+    fn library_mapping_markers() {
+        let mut converter = make_test_converter();
+        converter.track_library_mapping(10, 10, b"/lib/early.so", true, 0);
+        converter.current_sample_time = 1_000_000;
+        converter.track_library_mapping(10, 10, b"/lib/libfoo.so", false, 2_000_000);
+        converter.track_library_mapping(10, 10, b"/lib/libfoo.so", true, 3_000_000);
+        converter.track_library_mapping(10, 10, b"/lib/libfoo.so", false, 5_000_000);
+        converter.track_library_mapping(10, 10, b"/usr/share/data.bin", false, 6_000_000);
+        converter.track_library_mapping(10, 10, b"[vdso]", true, 6_500_000);
+        converter.track_library_mapping(10, 10, b"/lib/libbar.so", true, 7_000_000);
+        let profile = serde_json::to_value(converter.finish()).unwrap();
+
+        let thread = &profile["threads"][0];
+        let markers = &thread["markers"];
+        assert_eq!(markers["length"], 2);
+        assert_eq!(markers["startTime"], json!([2.0, 7.0]));
+        assert_eq!(markers["endTime"], json!([5.0, 0.0]));
+        assert_eq!(markers["phase"], json!([1, 0]));
+        assert_eq!(markers["data"][0]["name"], "/lib/libfoo.so");
+        assert_eq!(markers["data"][1]["name"], "/lib/libbar.so");
+    }
+}
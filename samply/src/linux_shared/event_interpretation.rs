@@ -2,7 +2,9 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 
 use linux_perf_data::{linux_perf_event_reader, AttributeDescription};
-use linux_perf_event_reader::{AttrFlags, PerfEventType, SamplingPolicy, SoftwareCounterType};
+use linux_perf_event_reader::{
+    AttrFlags, PerfEventType, SampleFormat, SamplingPolicy, SoftwareCounterType,
+};
 
 #[derive(Debug, Clone)]
 pub enum KnownEvent {
@@ -11,6 +13,7 @@ pub enum KnownEvent {
     MmapExit,
     MprotectEnter,
     PageFault,
+    SchedWakeup,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -25,10 +28,18 @@ pub enum OffCpuIndicator {
 #[derive(Debug, Clone)]
 pub struct EventInterpretation {
     pub main_event_attr_index: usize,
-    #[allow(unused)]
     pub main_event_name: String,
+    /// A second sampled hardware event, e.g. cache-misses next to cycles.
+    /// Its sample periods become the auxiliary sample weight. Only used when
+    /// recording with `--aux-event`.
+    pub aux_event_attr_index: Option<usize>,
+    /// The other sampled events in a perf.data file, e.g. cache-misses next to
+    /// cycles. Each of them gets its own sample track for each thread.
+    pub track_event_attr_indices: Vec<usize>,
     pub sampling_is_time_based: Option<u64>,
     pub off_cpu_indicator: Option<OffCpuIndicator>,
+    /// Whether the CONTEXT_SWITCH records say on which CPU the switch happened.
+    pub context_switches_have_cpu: bool,
     pub sched_switch_attr_index: Option<usize>,
     pub known_event_indices: HashMap<usize, KnownEvent>,
     pub event_names: Vec<String>,
@@ -62,7 +73,17 @@ impl EventInterpretation {
             }
             (_, SamplingPolicy::Period(_)) => None,
         };
-        let have_context_switches = attrs[0].attr.flags.contains(AttrFlags::CONTEXT_SWITCH);
+        // `perf record --switch-events` requests the CONTEXT_SWITCH records on
+        // only one of the events, which is a separate dummy event if there are
+        // several events.
+        let context_switch_attr = attrs
+            .iter()
+            .find(|attr_desc| attr_desc.attr.flags.contains(AttrFlags::CONTEXT_SWITCH));
+        let have_context_switches = context_switch_attr.is_some();
+        let context_switches_have_cpu = context_switch_attr.is_some_and(|attr_desc| {
+            attr_desc.attr.flags.contains(AttrFlags::SAMPLE_ID_ALL)
+                && attr_desc.attr.sample_format.contains(SampleFormat::CPU)
+        });
         let sched_switch_attr_index = attrs
             .iter()
             .position(|attr_desc| attr_desc.name.as_deref() == Some("sched:sched_switch"));
@@ -71,6 +92,20 @@ impl EventInterpretation {
             (false, Some(_)) => Some(OffCpuIndicator::SchedSwitchAndSamples),
             _ => None,
         };
+        // Tracepoint samples are shown as markers instead.
+        let track_event_attr_indices = attrs
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(_, attr_desc)| {
+                !matches!(
+                    attr_desc.attr.type_,
+                    PerfEventType::Tracepoint(_)
+                        | PerfEventType::Software(SoftwareCounterType::Dummy)
+                ) && !matches!(attr_desc.attr.sampling_policy, SamplingPolicy::NoSampling)
+            })
+            .map(|(i, _)| i)
+            .collect();
         let mut known_event_indices = HashMap::new();
 
         let known_events = [
@@ -79,6 +114,9 @@ impl EventInterpretation {
             ("syscalls:sys_enter_mprotect", KnownEvent::MprotectEnter),
             ("syscalls:sys_enter_mmap", KnownEvent::MmapEnter),
             ("syscalls:sys_exit_mmap", KnownEvent::MmapExit),
+            ("sched:sched_waking", KnownEvent::SchedWakeup),
+            ("sched:sched_wakeup", KnownEvent::SchedWakeup),
+            ("sched:sched_wakeup_new", KnownEvent::SchedWakeup),
         ];
 
         for (event_name, event) in known_events {
@@ -104,8 +142,11 @@ impl EventInterpretation {
         Self {
             main_event_attr_index,
             main_event_name,
+            aux_event_attr_index: None,
+            track_event_attr_indices,
             sampling_is_time_based,
             off_cpu_indicator,
+            context_switches_have_cpu,
             sched_switch_attr_index,
             known_event_indices,
             event_names,
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use fxprof_processed_profile::{ProcessHandle, Profile, ThreadHandle, Timestamp};
+
+/// The sample tracks for the sampled events other than the main event, e.g.
+/// for cache-misses next to cycles. Each thread gets one track per event, in
+/// the thread's process, so that the event's samples can be looked at on
+/// their own.
+#[derive(Debug, Default)]
+pub struct EventTracks {
+    /// The track for each (pid, tid, attr_index).
+    tracks: HashMap<(i32, i32, usize), ThreadHandle>,
+}
+
+impl EventTracks {
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_or_create(
+        &mut self,
+        pid: i32,
+        tid: i32,
+        attr_index: usize,
+        process: ProcessHandle,
+        thread_name: Option<&str>,
+        event_name: &str,
+        start_time: Timestamp,
+        profile: &mut Profile,
+    ) -> ThreadHandle {
+        *self
+            .tracks
+            .entry((pid, tid, attr_index))
+            .or_insert_with(|| {
+                let track = profile.add_thread(process, tid as u32, start_time, false);
+                let thread_name = match thread_name {
+                    Some(name) => name.to_owned(),
+                    None => format!("Thread {tid}"),
+                };
+                profile.set_thread_name(track, &format!("{thread_name} ({event_name})"));
+                track
+            })
+    }
+
+    /// Ends the tracks of an exited thread, or of all threads of the process
+    /// if `tid` is `None`.
+    pub fn notify_exit(
+        &mut self,
+        pid: i32,
+        tid: Option<i32>,
+        end_time: Timestamp,
+        profile: &mut Profile,
+    ) {
+        self.tracks.retain(|&(track_pid, track_tid, _), track| {
+            let exited = track_pid == pid && tid.map_or(true, |tid| tid == track_tid);
+            if exited {
+                profile.set_thread_end_time(*track, end_time);
+            }
+            !exited
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{ReferenceTimestamp, SamplingInterval};
+
+    use super::*;
+
+    #[test]
+    fn one_track_per_thread_and_event() {
+        let mut profile = Profile::new(
+            "test",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let start = Timestamp::from_millis_since_reference(0.0);
+        let process = profile.add_process("app", 1, start);
+        let mut tracks = EventTracks::default();
+        let track = |tracks: &mut EventTracks, tid, attr_index, profile: &mut Profile| {
+            tracks.get_or_create(
+                1,
+                tid,
+                attr_index,
+                process,
+                Some("app"),
+                "cache-misses",
+                start,
+                profile,
+            )
+        };
+        let first = track(&mut tracks, 1, 1, &mut profile);
+        assert_eq!(track(&mut tracks, 1, 1, &mut profile), first);
+        let other_event = track(&mut tracks, 1, 2, &mut profile);
+        let other_thread = track(&mut tracks, 2, 1, &mut profile);
+        assert_ne!(other_event, first);
+        assert_ne!(other_thread, first);
+
+        let end = Timestamp::from_millis_since_reference(5.0);
+        tracks.notify_exit(1, Some(2), end, &mut profile);
+        assert_eq!(tracks.tracks.len(), 2);
+        assert_ne!(track(&mut tracks, 2, 1, &mut profile), other_thread);
+        tracks.notify_exit(1, None, end, &mut profile);
+        assert!(tracks.tracks.is_empty());
+
+        let json = serde_json::to_value(&profile).unwrap();
+        assert_eq!(json["threads"][0]["name"], "app (cache-misses)");
+    }
+}
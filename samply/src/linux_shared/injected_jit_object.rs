@@ -1,7 +1,33 @@
+use linux_perf_data::linux_perf_event_reader::EventRecord;
 use object::{Object, ObjectSection, ObjectSegment, ObjectSymbol, SymbolKind};
 
 use super::object_rewriter;
 
+/// Whether this is the path of one of the `jitted-<pid>-<index>.so` files which
+/// `perf inject --jit` creates, one for each function in the JITDUMP file.
+pub fn is_injected_jit_so_path(path: &[u8]) -> bool {
+    let filename = match path.iter().rposition(|&b| b == b'/') {
+        Some(pos) => &path[pos + 1..],
+        None => path,
+    };
+    filename.starts_with(b"jitted-") && filename.ends_with(b".so")
+}
+
+/// Whether this is one of the mmap records which `perf inject --jit` adds for its
+/// `jitted-<pid>-<index>.so` files.
+///
+/// `perf inject` writes all of a process's records in place of the mmap record for
+/// the JITDUMP file, but with the timestamps of the functions' code load records.
+/// So, unlike the timestamps of all other records, their timestamps can be far
+/// ahead of the records which follow them in the file.
+pub fn is_injected_jit_mmap(record: &EventRecord) -> bool {
+    match record {
+        EventRecord::Mmap(e) => is_injected_jit_so_path(&e.path.as_slice()),
+        EventRecord::Mmap2(e) => is_injected_jit_so_path(&e.path.as_slice()),
+        _ => false,
+    }
+}
+
 pub fn jit_function_name<'data>(obj: &object::File<'data>) -> Option<&'data str> {
     let mut text_symbols = obj.symbols().filter(|s| s.kind() == SymbolKind::Text);
     let symbol = text_symbols.next()?;
@@ -107,3 +133,21 @@ pub fn correct_bad_perf_jit_so_file(
 
     Some((fixed_file, fixed_path))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_injected_jit_so_paths() {
+        assert!(is_injected_jit_so_path(
+            b"/home/user/.debug/jit/java-jit-20240101.XXaBcDeF/jitted-1234-56.so"
+        ));
+        assert!(is_injected_jit_so_path(b"jitted-1234-56.so"));
+        assert!(!is_injected_jit_so_path(b"/tmp/jit-1234.dump"));
+        assert!(!is_injected_jit_so_path(b"/usr/lib/jitted-1234-56.so.1"));
+        assert!(!is_injected_jit_so_path(
+            b"/home/user/jitted-1234/libfoo.so"
+        ));
+    }
+}
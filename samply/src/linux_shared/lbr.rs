@@ -0,0 +1,221 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use linux_perf_data::linux_perf_event_reader::{
+    BranchSampleFormat, Endianness, RawEventRecord, ReadFormat, SampleFormat,
+};
+
+use crate::shared::types::{StackFrame, StackMode};
+
+/// Returns the call sites from the LBR call stack of a sample which was recorded
+/// with `perf record --call-graph lbr`, innermost first.
+///
+/// With LBR call stacks, the CPU keeps track of the calls and returns in user
+/// code, so the stacks are complete even if the code was compiled without frame
+/// pointers. The sample parser in linux-perf-event-reader skips over the branch
+/// stack, so we need to find it in the raw record ourselves.
+pub fn parse_lbr_call_stack(record: &RawEventRecord) -> Option<Vec<u64>> {
+    let parse_info = &record.parse_info;
+    if !parse_info
+        .sample_format
+        .contains(SampleFormat::BRANCH_STACK)
+        || !parse_info
+            .branch_sample_format
+            .contains(BranchSampleFormat::CALL_STACK)
+    {
+        return None;
+    }
+    match parse_info.endian {
+        Endianness::LittleEndian => parse_lbr_call_stack_impl::<LittleEndian>(record),
+        Endianness::BigEndian => parse_lbr_call_stack_impl::<BigEndian>(record),
+    }
+}
+
+fn parse_lbr_call_stack_impl<T: ByteOrder>(record: &RawEventRecord) -> Option<Vec<u64>> {
+    let parse_info = &record.parse_info;
+    let sample_format = parse_info.sample_format;
+    let mut cur = record.data;
+
+    // Skip all the fields which come before the branch stack.
+    for field in [
+        SampleFormat::IDENTIFIER,
+        SampleFormat::IP,
+        SampleFormat::TID,
+        SampleFormat::TIME,
+        SampleFormat::ADDR,
+        SampleFormat::ID,
+        SampleFormat::STREAM_ID,
+        SampleFormat::CPU,
+        SampleFormat::PERIOD,
+    ] {
+        if sample_format.contains(field) {
+            cur.skip(8).ok()?;
+        }
+    }
+    if sample_format.contains(SampleFormat::READ) {
+        let read_format = parse_info.read_format;
+        let value_count = if read_format.contains(ReadFormat::GROUP) {
+            cur.read_u64::<T>().ok()?
+        } else {
+            1
+        };
+        if read_format.contains(ReadFormat::TOTAL_TIME_ENABLED) {
+            cur.skip(8).ok()?;
+        }
+        if read_format.contains(ReadFormat::TOTAL_TIME_RUNNING) {
+            cur.skip(8).ok()?;
+        }
+        let value_size = if read_format.contains(ReadFormat::ID) {
+            16
+        } else {
+            8
+        };
+        cur.skip(usize::try_from(value_count).ok()? * value_size)
+            .ok()?;
+    }
+    if sample_format.contains(SampleFormat::CALLCHAIN) {
+        let len = cur.read_u64::<T>().ok()?;
+        cur.skip(usize::try_from(len).ok()? * 8).ok()?;
+    }
+    if sample_format.contains(SampleFormat::RAW) {
+        let size = cur.read_u32::<T>().ok()?;
+        cur.skip(size as usize).ok()?;
+    }
+
+    let entry_count = cur.read_u64::<T>().ok()?;
+    if parse_info
+        .branch_sample_format
+        .contains(BranchSampleFormat::HW_INDEX)
+    {
+        cur.skip(8).ok()?;
+    }
+    let mut call_sites = Vec::new();
+    for _ in 0..entry_count {
+        let from = cur.read_u64::<T>().ok()?;
+        let _to = cur.read_u64::<T>().ok()?;
+        let _flags = cur.read_u64::<T>().ok()?;
+        if from != 0 {
+            call_sites.push(from);
+        }
+    }
+    Some(call_sites)
+}
+
+/// Replaces the user frames of a stack, which come from the frame pointer
+/// callchain and may be truncated, with the frames from the LBR call stack:
+/// the user frame in which the sample was taken, followed by the call sites.
+///
+/// `user_ip` is the sample's instruction pointer, if the sample was taken in
+/// user code. It's used if the callchain has no user frames.
+pub fn replace_user_frames_with_lbr_call_stack(
+    stack: &mut Vec<StackFrame>,
+    call_sites: &[u64],
+    user_ip: Option<u64>,
+) {
+    let first_user_frame = stack
+        .iter()
+        .position(|frame| frame.stack_mode() == Some(StackMode::User));
+    match (first_user_frame, user_ip) {
+        (Some(index), _) => stack.truncate(index + 1),
+        (None, Some(ip)) => stack.push(StackFrame::InstructionPointer(ip, StackMode::User)),
+        (None, None) => {}
+    }
+    // The call sites are the addresses of the call instructions, so they
+    // don't need to be adjusted like return addresses.
+    stack.extend(
+        call_sites
+            .iter()
+            .map(|&address| StackFrame::AdjustedReturnAddress(address, StackMode::User)),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use linux_perf_data::linux_perf_event_reader::{
+        RawData, RecordIdParseInfo, RecordParseInfo, RecordType,
+    };
+
+    use super::*;
+
+    #[test]
+    fn parse_and_apply_lbr_call_stack() {
+        let sample_format = SampleFormat::IP
+            | SampleFormat::TID
+            | SampleFormat::TIME
+            | SampleFormat::CALLCHAIN
+            | SampleFormat::BRANCH_STACK;
+        let parse_info = RecordParseInfo {
+            endian: Endianness::LittleEndian,
+            sample_format,
+            branch_sample_format: BranchSampleFormat::USER
+                | BranchSampleFormat::CALL_STACK
+                | BranchSampleFormat::HW_INDEX,
+            read_format: ReadFormat::empty(),
+            common_data_offset_from_end: None,
+            sample_regs_user: 0,
+            user_regs_count: 0,
+            sample_regs_intr: 0,
+            intr_regs_count: 0,
+            id_parse_info: RecordIdParseInfo {
+                nonsample_record_id_offset_from_end: None,
+                sample_record_id_offset_from_start: None,
+            },
+            nonsample_record_time_offset_from_end: None,
+            sample_record_time_offset_from_start: None,
+        };
+        const PERF_CONTEXT_KERNEL: u64 = -128i64 as u64;
+        const PERF_CONTEXT_USER: u64 = -512i64 as u64;
+        let words: [u64; 19] = [
+            0xffff_ffff_8100_0000, // ip
+            0x0000_0010_0000_0010, // pid, tid
+            1_000_000,             // time
+            4,                     // callchain length
+            PERF_CONTEXT_KERNEL,
+            0xffff_ffff_8100_0000,
+            PERF_CONTEXT_USER,
+            0x40_1000,
+            3,         // branch stack entry count
+            0,         // hw_idx
+            0x40_2000, // from, to, flags
+            0x40_1000,
+            0,
+            0x40_3000,
+            0x40_2000,
+            0,
+            0x40_4000,
+            0x40_3000,
+            0,
+        ];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+
+        let record =
+            RawEventRecord::new(RecordType::SAMPLE, 0, RawData::Single(&bytes), parse_info);
+        let call_sites = parse_lbr_call_stack(&record).unwrap();
+        assert_eq!(call_sites, vec![0x40_2000, 0x40_3000, 0x40_4000]);
+
+        let mut stack = vec![
+            StackFrame::InstructionPointer(0xffff_ffff_8100_0000, StackMode::Kernel),
+            StackFrame::ReturnAddress(0x40_1000, StackMode::User),
+            StackFrame::ReturnAddress(0x40_5000, StackMode::User),
+        ];
+        replace_user_frames_with_lbr_call_stack(&mut stack, &call_sites, None);
+        assert_eq!(
+            stack,
+            vec![
+                StackFrame::InstructionPointer(0xffff_ffff_8100_0000, StackMode::Kernel),
+                StackFrame::ReturnAddress(0x40_1000, StackMode::User),
+                StackFrame::AdjustedReturnAddress(0x40_2000, StackMode::User),
+                StackFrame::AdjustedReturnAddress(0x40_3000, StackMode::User),
+                StackFrame::AdjustedReturnAddress(0x40_4000, StackMode::User),
+            ]
+        );
+
+        let mut stack = Vec::new();
+        replace_user_frames_with_lbr_call_stack(&mut stack, &call_sites[..1], Some(0x40_1234));
+        assert_eq!(
+            stack,
+            vec![
+                StackFrame::InstructionPointer(0x40_1234, StackMode::User),
+                StackFrame::AdjustedReturnAddress(0x40_2000, StackMode::User),
+            ]
+        );
+    }
+}
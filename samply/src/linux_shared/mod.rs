@@ -1,24 +1,36 @@
 mod avma_range;
+mod clock_skew;
 mod convert_regs;
 mod converter;
 mod event_interpretation;
+mod event_tracks;
 mod injected_jit_object;
 mod kernel_symbols;
+mod lbr;
 mod mmap_range_or_vec;
 mod object_rewriter;
 mod pe_mappings;
 mod per_cpu;
+mod priority_inversion;
 mod process;
 mod process_threads;
 mod processes;
 mod rss_stat;
+mod sched_switch;
+mod sched_wakeup;
+mod stack_usage;
 mod svma_file_range;
 mod thread;
+mod usdt;
 #[allow(unused)]
 pub mod vdso;
 
+pub use clock_skew::set_record_timestamp;
 pub use convert_regs::{ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64};
 pub use converter::Converter;
 #[allow(unused)]
 pub use event_interpretation::{EventInterpretation, KnownEvent, OffCpuIndicator};
+pub use injected_jit_object::is_injected_jit_mmap;
+pub use lbr::parse_lbr_call_stack;
 pub use mmap_range_or_vec::MmapRangeOrVec;
+pub use usdt::{find_usdt_probes, UsdtProbe, UsdtProbeSpec};
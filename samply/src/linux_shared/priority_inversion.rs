@@ -0,0 +1,139 @@
+//! Detects likely priority inversions from scheduler events.
+//!
+//! A priority inversion is flagged when a thread blocks, e.g. on a lock, and
+//! is eventually woken by a thread with a lower priority which was preempted
+//! while the first thread was waiting. The waker likely held the resource,
+//! and whatever preempted it delayed the higher-priority thread as well.
+//!
+//! Priorities are the kernel's, where lower values are more important. They
+//! come from the sched_switch and sched_waking tracepoints.
+
+use std::collections::{HashMap, VecDeque};
+
+/// The number of preemptions which are remembered per thread.
+const MAX_PREEMPTIONS_PER_THREAD: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriorityInversion {
+    /// When the woken thread blocked.
+    pub blocked_since: u64,
+    pub blocked_priority: i32,
+    pub waker_priority: i32,
+    /// How long the waker was preempted while the woken thread was blocked,
+    /// in nanoseconds.
+    pub waker_preempted_ns: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct PriorityInversionDetector {
+    threads: HashMap<i32, ThreadSchedulingState>,
+}
+
+#[derive(Debug, Default)]
+struct ThreadSchedulingState {
+    priority: Option<i32>,
+    /// Some() while the thread is blocked.
+    blocked_since: Option<u64>,
+    /// Some() while the thread is runnable but not running.
+    preempted_since: Option<u64>,
+    /// The most recent intervals in which the thread was runnable but not
+    /// running, oldest first.
+    preemptions: VecDeque<(u64, u64)>,
+}
+
+impl PriorityInversionDetector {
+    pub fn set_priority(&mut self, tid: i32, priority: i32) {
+        self.threads.entry(tid).or_default().priority = Some(priority);
+    }
+
+    pub fn switch_out(&mut self, tid: i32, timestamp: u64, preempted: bool) {
+        let thread = self.threads.entry(tid).or_default();
+        if preempted {
+            thread.preempted_since = Some(timestamp);
+        } else {
+            thread.blocked_since = Some(timestamp);
+        }
+    }
+
+    pub fn switch_in(&mut self, tid: i32, timestamp: u64) {
+        let thread = self.threads.entry(tid).or_default();
+        thread.blocked_since = None;
+        if let Some(start) = thread.preempted_since.take() {
+            if thread.preemptions.len() == MAX_PREEMPTIONS_PER_THREAD {
+                thread.preemptions.pop_front();
+            }
+            thread.preemptions.push_back((start, timestamp));
+        }
+    }
+
+    /// Called when `waker_tid` wakes `wakee_tid`. Returns the priority
+    /// inversion if the wakee was blocked and the waker has a lower priority
+    /// and was preempted in the meantime.
+    pub fn wakeup(
+        &mut self,
+        waker_tid: i32,
+        wakee_tid: i32,
+        timestamp: u64,
+    ) -> Option<PriorityInversion> {
+        let wakee = self.threads.get_mut(&wakee_tid)?;
+        let blocked_since = wakee.blocked_since.take()?;
+        let blocked_priority = wakee.priority?;
+        let waker = self.threads.get(&waker_tid)?;
+        let waker_priority = waker.priority?;
+        if waker_priority <= blocked_priority {
+            return None;
+        }
+        let waker_preempted_ns = waker
+            .preemptions
+            .iter()
+            .map(|&(start, end)| end.min(timestamp).saturating_sub(start.max(blocked_since)))
+            .sum();
+        if waker_preempted_ns == 0 {
+            return None;
+        }
+        Some(PriorityInversion {
+            blocked_since,
+            blocked_priority,
+            waker_priority,
+            waker_preempted_ns,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_preempted_low_priority_waker() {
+        let mut detector = PriorityInversionDetector::default();
+        let (high, medium, low) = (1, 2, 3);
+        detector.set_priority(high, 100);
+        detector.set_priority(medium, 110);
+        detector.set_priority(low, 120);
+
+        // The high-priority thread blocks on a lock held by the low-priority
+        // thread, which is then preempted by the medium-priority thread.
+        detector.switch_out(high, 1000, false);
+        detector.switch_out(low, 1500, true);
+        detector.switch_in(medium, 1500);
+        detector.switch_out(medium, 4500, false);
+        detector.switch_in(low, 4500);
+        assert_eq!(
+            detector.wakeup(low, high, 5000),
+            Some(PriorityInversion {
+                blocked_since: 1000,
+                blocked_priority: 100,
+                waker_priority: 120,
+                waker_preempted_ns: 3000,
+            })
+        );
+
+        // A wake-up by a more important thread, or by a thread which wasn't
+        // preempted while the wakee was blocked, is fine.
+        detector.switch_out(high, 6000, false);
+        assert_eq!(detector.wakeup(low, high, 7000), None);
+        detector.switch_out(low, 8000, false);
+        assert_eq!(detector.wakeup(medium, low, 9000), None);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use framehop::Unwinder;
@@ -14,7 +15,7 @@ use crate::shared::jit_function_recycler::JitFunctionRecycler;
 use crate::shared::jitdump_manager::JitDumpManager;
 use crate::shared::lib_mappings::{LibMappingAdd, LibMappingInfo, LibMappingOp, LibMappingOpQueue};
 use crate::shared::marker_file::get_markers;
-use crate::shared::perf_map::try_load_perf_map;
+use crate::shared::perf_map::PerfMapReader;
 use crate::shared::process_sample_data::{MarkerSpanOnThread, ProcessSampleData};
 use crate::shared::recycling::{ProcessRecyclingData, ThreadRecycler};
 use crate::shared::timestamp_converter::TimestampConverter;
@@ -24,6 +25,7 @@ pub struct Process<U> {
     pub profile_process: ProcessHandle,
     pub unwinder: U,
     pub jitdump_manager: JitDumpManager,
+    perf_map_reader: PerfMapReader,
     pub lib_mapping_ops: LibMappingOpQueue,
     pub name: Option<String>,
     pub threads: ProcessThreads,
@@ -36,6 +38,8 @@ pub struct Process<U> {
     pub prev_mm_swapents_size: i64,
     pub prev_mm_shmempages_size: i64,
     pub mem_counter: Option<CounterHandle>,
+    /// The counters which the process added over the marker socket, by name.
+    app_counters: HashMap<String, CounterHandle>,
 }
 
 pub struct ProcessForkData<U> {
@@ -62,6 +66,7 @@ where
             profile_process: process_handle,
             unwinder: U::default(),
             jitdump_manager: JitDumpManager::new(unlink_aux_files),
+            perf_map_reader: PerfMapReader::new(pid as u32),
             lib_mapping_ops: Default::default(),
             name: name.clone(),
             pid,
@@ -81,6 +86,7 @@ where
             prev_mm_swapents_size: 0,
             prev_mm_shmempages_size: 0,
             mem_counter: None,
+            app_counters: HashMap::new(),
         }
     }
 
@@ -170,6 +176,20 @@ where
         );
     }
 
+    pub fn check_perf_map(
+        &mut self,
+        timestamp: u64,
+        jit_category_manager: &mut JitCategoryManager,
+        profile: &mut Profile,
+    ) {
+        self.perf_map_reader.check(
+            timestamp,
+            profile,
+            jit_category_manager,
+            self.jit_function_recycler.as_mut(),
+        );
+    }
+
     pub fn add_marker_file_path(
         &mut self,
         thread: ThreadHandle,
@@ -185,6 +205,18 @@ where
         profile.set_process_end_time(self.profile_process, end_time);
     }
 
+    /// The samples so far, for a snapshot of the profile during the
+    /// recording.
+    pub fn sample_data_snapshot(&self) -> ProcessSampleData {
+        ProcessSampleData::new(
+            self.unresolved_samples.clone(),
+            self.lib_mapping_ops.clone(),
+            Vec::new(),
+            None,
+            Vec::new(),
+        )
+    }
+
     pub fn finish(
         mut self,
         profile: &mut Profile,
@@ -193,9 +225,12 @@ where
     ) -> (ProcessSampleData, Option<(String, ProcessRecyclingData)>) {
         self.unwinder = U::default();
 
-        let perf_map_mappings = if !self.unresolved_samples.is_empty() {
-            try_load_perf_map(
-                self.pid as u32,
+        let perf_map_reader = std::mem::replace(
+            &mut self.perf_map_reader,
+            PerfMapReader::new(self.pid as u32),
+        );
+        let perf_map_ops = if !self.unresolved_samples.is_empty() {
+            perf_map_reader.finish(
                 profile,
                 jit_category_manager,
                 self.jit_function_recycler.as_mut(),
@@ -206,12 +241,15 @@ where
 
         let jitdump_manager =
             std::mem::replace(&mut self.jitdump_manager, JitDumpManager::new(false));
-        let jitdump_ops = jitdump_manager.finish(
+        let mut jitdump_ops = jitdump_manager.finish(
             jit_category_manager,
             profile,
             self.jit_function_recycler.as_mut(),
             timestamp_converter,
         );
+        // The perf map entries take effect over time, just like the jitdump
+        // records.
+        jitdump_ops.extend(perf_map_ops);
 
         let mut marker_spans = Vec::new();
         for (thread_handle, marker_file_path, fallback_dir) in self.marker_file_paths {
@@ -235,7 +273,7 @@ where
             std::mem::take(&mut self.unresolved_samples),
             std::mem::take(&mut self.lib_mapping_ops),
             jitdump_ops,
-            perf_map_mappings,
+            None,
             marker_spans,
         );
 
@@ -324,6 +362,20 @@ where
         );
     }
 
+    pub fn get_or_make_app_counter(&mut self, name: &str, profile: &mut Profile) -> CounterHandle {
+        if let Some(counter) = self.app_counters.get(name) {
+            return *counter;
+        }
+        let counter = profile.add_counter(
+            self.profile_process,
+            name,
+            "App",
+            "Counter which the profiled process sent over the samply marker socket",
+        );
+        self.app_counters.insert(name.to_owned(), counter);
+        counter
+    }
+
     pub fn get_or_make_mem_counter(&mut self, profile: &mut Profile) -> CounterHandle {
         *self.mem_counter.get_or_insert_with(|| {
             profile.add_counter(
@@ -185,6 +185,7 @@ impl ProcessThreads {
                 off_cpu_stack: None,
                 name: None,
                 thread_label_frame,
+                wake_chain: Vec::new(),
             }
         })
     }
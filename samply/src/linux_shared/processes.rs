@@ -168,6 +168,23 @@ where
         })
     }
 
+    /// The pids of the processes which haven't exited yet.
+    pub fn live_pids(&self) -> Vec<i32> {
+        self.processes_by_pid.keys().copied().collect()
+    }
+
+    /// Finds the process of a thread which was seen before, e.g. in a sample
+    /// or in a FORK or COMM record.
+    pub fn pid_for_tid(&self, tid: i32) -> Option<i32> {
+        if self.processes_by_pid.contains_key(&tid) {
+            return Some(tid);
+        }
+        self.processes_by_pid
+            .values()
+            .find(|process| process.threads.threads_by_tid.contains_key(&tid))
+            .map(|process| process.pid)
+    }
+
     pub fn remove(
         &mut self,
         pid: i32,
@@ -247,17 +264,41 @@ where
             }
         }
 
-        let user_category = profile.add_category("User", CategoryColor::Yellow).into();
-        let kernel_category = profile.add_category("Kernel", CategoryColor::Orange).into();
-        let mut stack_frame_scratch_buf = Vec::new();
-        for process_sample_data in self.process_sample_datas {
-            process_sample_data.flush_samples_to_profile(
-                profile,
-                user_category,
-                kernel_category,
-                &mut stack_frame_scratch_buf,
-                unresolved_stacks,
-            );
-        }
+        flush_sample_datas(profile, self.process_sample_datas, unresolved_stacks);
+    }
+
+    /// Adds the samples so far to `profile`, a copy of the profile which is
+    /// being recorded, without finishing the processes. JIT functions and
+    /// the markers from marker files are only added by [`Processes::finish`].
+    pub fn flush_snapshot(&self, profile: &mut Profile, unresolved_stacks: &UnresolvedStacks) {
+        let live_sample_datas = self
+            .processes_by_pid
+            .values()
+            .map(|process| process.sample_data_snapshot());
+        let sample_datas = self
+            .process_sample_datas
+            .iter()
+            .cloned()
+            .chain(live_sample_datas);
+        flush_sample_datas(profile, sample_datas, unresolved_stacks);
+    }
+}
+
+fn flush_sample_datas(
+    profile: &mut Profile,
+    sample_datas: impl IntoIterator<Item = ProcessSampleData>,
+    unresolved_stacks: &UnresolvedStacks,
+) {
+    let user_category = profile.add_category("User", CategoryColor::Yellow).into();
+    let kernel_category = profile.add_category("Kernel", CategoryColor::Orange).into();
+    let mut stack_frame_scratch_buf = Vec::new();
+    for process_sample_data in sample_datas {
+        process_sample_data.flush_samples_to_profile(
+            profile,
+            user_category,
+            kernel_category,
+            &mut stack_frame_scratch_buf,
+            unresolved_stacks,
+        );
     }
 }
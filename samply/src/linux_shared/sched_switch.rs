@@ -0,0 +1,74 @@
+use std::fmt::Debug;
+
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+/// The task state bits below `TASK_REPORT_MAX`. `TASK_REPORT_MAX` itself is
+/// set in `prev_state` if the task was preempted.
+const TASK_REPORT_STATE_MASK: u64 = 0xff;
+
+/// ```
+/// # cat /sys/kernel/debug/tracing/events/sched/sched_switch/format
+/// name: sched_switch
+/// ID: 320
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:char prev_comm[16];       offset:8;       size:16;        signed:0;
+///         field:pid_t prev_pid;   offset:24;      size:4; signed:1;
+///         field:int prev_prio;    offset:28;      size:4; signed:1;
+///         field:long prev_state;  offset:32;      size:8; signed:1;
+///         field:char next_comm[16];       offset:40;      size:16;        signed:0;
+///         field:pid_t next_pid;   offset:56;      size:4; signed:1;
+///         field:int next_prio;    offset:60;      size:4; signed:1;
+///
+/// print fmt: "prev_comm=%s prev_pid=%d prev_prio=%d prev_state=%s%s ==> next_comm=%s next_pid=%d next_prio=%d", ...
+/// ```
+#[derive(Debug)]
+pub struct SchedSwitch {
+    pub prev_pid: i32,
+    pub prev_prio: i32,
+    pub prev_state: u64,
+    pub next_pid: i32,
+    pub next_prio: i32,
+}
+
+impl SchedSwitch {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    pub fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        let _common_type = data.read_u16::<O>()?;
+        let _common_flags = data.read_u8()?;
+        let _common_preempt_count = data.read_u8()?;
+        let _common_pid = data.read_i32::<O>()?;
+        data.skip(16)?; // prev_comm
+        let prev_pid = data.read_i32::<O>()?;
+        let prev_prio = data.read_i32::<O>()?;
+        let prev_state = data.read_u64::<O>()?;
+        data.skip(16)?; // next_comm
+        let next_pid = data.read_i32::<O>()?;
+        let next_prio = data.read_i32::<O>()?;
+        Ok(SchedSwitch {
+            prev_pid,
+            prev_prio,
+            prev_state,
+            next_pid,
+            next_prio,
+        })
+    }
+
+    /// Whether the previous task was still runnable, i.e. it was preempted
+    /// rather than blocked.
+    pub fn prev_was_runnable(&self) -> bool {
+        self.prev_state & TASK_REPORT_STATE_MASK == 0
+    }
+}
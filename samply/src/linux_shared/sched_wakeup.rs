@@ -0,0 +1,57 @@
+use std::fmt::Debug;
+
+use byteorder::ByteOrder;
+use linux_perf_data::{linux_perf_event_reader, Endianness};
+use linux_perf_event_reader::RawData;
+
+/// The fields shared by `sched:sched_waking`, `sched:sched_wakeup` and
+/// `sched:sched_wakeup_new`. The sample's pid and tid are the waker's; the
+/// fields describe the wakee. Older kernels have a `success` field before
+/// `target_cpu`, so only the fields up to `prio` are read.
+///
+/// ```
+/// # cat /sys/kernel/debug/tracing/events/sched/sched_waking/format
+/// name: sched_waking
+/// ID: 322
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:char comm[16];    offset:8;       size:16;        signed:0;
+///         field:pid_t pid;        offset:24;      size:4; signed:1;
+///         field:int prio; offset:28;      size:4; signed:1;
+///         field:int target_cpu;   offset:32;      size:4; signed:1;
+///
+/// print fmt: "comm=%s pid=%d prio=%d target_cpu=%03d", REC->comm, REC->pid, REC->prio, REC->target_cpu
+/// ```
+#[derive(Debug)]
+pub struct SchedWakeup {
+    pub comm: String,
+    pub pid: i32,
+    pub prio: i32,
+}
+
+impl SchedWakeup {
+    pub fn parse(data: RawData, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => Self::parse_impl::<byteorder::LittleEndian>(data),
+            Endianness::BigEndian => Self::parse_impl::<byteorder::BigEndian>(data),
+        }
+    }
+
+    pub fn parse_impl<O: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        let _common_type = data.read_u16::<O>()?;
+        let _common_flags = data.read_u8()?;
+        let _common_preempt_count = data.read_u8()?;
+        let _common_pid = data.read_i32::<O>()?;
+        let mut comm = [0; 16];
+        data.read_exact(&mut comm)?;
+        let comm_len = comm.iter().position(|&b| b == 0).unwrap_or(comm.len());
+        let comm = String::from_utf8_lossy(&comm[..comm_len]).into_owned();
+        let pid = data.read_i32::<O>()?;
+        let prio = data.read_i32::<O>()?;
+        Ok(SchedWakeup { comm, pid, prio })
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use fxprof_processed_profile::{MarkerFieldFormat, Profile, ThreadHandle};
+
+/// Tracks the range of user stack pointers in each thread's samples. The
+/// distance between the highest and the lowest stack pointer is a lower bound
+/// for how much stack memory the thread has used, which is a cheap way to find
+/// threads which are close to overflowing their stack.
+#[derive(Debug, Default)]
+pub struct StackUsage {
+    threads: HashMap<ThreadHandle, ThreadStackUsage>,
+}
+
+#[derive(Debug)]
+struct ThreadStackUsage {
+    tid: i32,
+    name: Option<String>,
+    lowest_sp: u64,
+    highest_sp: u64,
+}
+
+impl StackUsage {
+    pub fn observe(&mut self, thread: ThreadHandle, tid: i32, name: Option<&str>, sp: u64) {
+        let usage = self
+            .threads
+            .entry(thread)
+            .or_insert_with(|| ThreadStackUsage {
+                tid,
+                name: None,
+                lowest_sp: sp,
+                highest_sp: sp,
+            });
+        // Stacks grow downwards, so the highest stack pointer is the closest
+        // one to the base of the stack.
+        usage.lowest_sp = usage.lowest_sp.min(sp);
+        usage.highest_sp = usage.highest_sp.max(sp);
+        if usage.name.as_deref() != name {
+            usage.name = name.map(ToOwned::to_owned);
+        }
+    }
+
+    /// Adds the stack usage of each thread to the profile's metadata, deepest
+    /// stack first.
+    pub fn add_to_profile(self, profile: &mut Profile) {
+        let mut threads: Vec<ThreadStackUsage> = self
+            .threads
+            .into_values()
+            .filter(|usage| usage.highest_sp > usage.lowest_sp)
+            .collect();
+        threads.sort_by_key(|usage| (std::cmp::Reverse(usage.max_depth()), usage.tid));
+        for usage in threads {
+            let label = format!(
+                "{} ({})",
+                usage.name.as_deref().unwrap_or("<unknown>"),
+                usage.tid
+            );
+            profile.add_extra_info(
+                "Maximum observed stack usage",
+                &label,
+                MarkerFieldFormat::Bytes,
+                usage.max_depth(),
+            );
+        }
+    }
+}
+
+impl ThreadStackUsage {
+    fn max_depth(&self) -> u64 {
+        self.highest_sp - self.lowest_sp
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{ReferenceTimestamp, SamplingInterval, Timestamp};
+
+    use super::*;
+
+    #[test]
+    fn report_deepest_stacks_first() {
+        let mut profile = Profile::new(
+            "test",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let process = profile.add_process("app", 1, Timestamp::from_millis_since_reference(0.0));
+        let main = profile.add_thread(
+            process,
+            1,
+            Timestamp::from_millis_since_reference(0.0),
+            true,
+        );
+        let worker = profile.add_thread(
+            process,
+            2,
+            Timestamp::from_millis_since_reference(0.0),
+            false,
+        );
+        let idle = profile.add_thread(
+            process,
+            3,
+            Timestamp::from_millis_since_reference(0.0),
+            false,
+        );
+
+        let mut usage = StackUsage::default();
+        usage.observe(main, 1, Some("app"), 0x7fff_0000);
+        usage.observe(main, 1, Some("app"), 0x7ffe_f000);
+        usage.observe(worker, 2, None, 0x1000_0000);
+        usage.observe(worker, 2, Some("worker"), 0x0ff0_0000);
+        usage.observe(worker, 2, Some("worker"), 0x0ff8_0000);
+        usage.observe(idle, 3, Some("idle"), 0x2000_0000);
+        usage.add_to_profile(&mut profile);
+
+        let json = serde_json::to_value(&profile).unwrap();
+        let entries = &json["meta"]["extra"][0]["entries"];
+        assert_eq!(entries[0]["label"], "worker (2)");
+        assert_eq!(entries[0]["value"], 0x10_0000);
+        assert_eq!(entries[1]["label"], "app (1)");
+        assert_eq!(entries[1]["value"], 0x1000);
+        assert!(entries.get(2).is_none());
+    }
+}
@@ -17,6 +17,10 @@ pub struct Thread {
     pub off_cpu_stack: Option<UnresolvedStackHandle>,
     pub name: Option<String>,
     pub thread_label_frame: FrameInfo,
+
+    /// The threads which woke this thread, directly or transitively, since it
+    /// last blocked, as "name (tid)" labels with the first waker first.
+    pub wake_chain: Vec<String>,
 }
 
 impl Thread {
@@ -32,6 +36,7 @@ impl Thread {
             off_cpu_stack: None,
             name,
             thread_label_frame,
+            wake_chain: Vec::new(),
         }
     }
 
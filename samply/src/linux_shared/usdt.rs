@@ -0,0 +1,399 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use linux_perf_data::linux_perf_event_reader::Regs;
+use object::{Object, ObjectSection, ObjectSegment};
+
+/// A USDT probe which should be recorded, as given on the command line:
+/// `provider:probe`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsdtProbeSpec {
+    pub provider: String,
+    pub name: String,
+}
+
+impl FromStr for UsdtProbeSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((provider, name)) if !provider.is_empty() && !name.is_empty() => Ok(Self {
+                provider: provider.to_owned(),
+                name: name.to_owned(),
+            }),
+            _ => Err(format!("expected provider:probe, got {s}")),
+        }
+    }
+}
+
+impl fmt::Display for UsdtProbeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.provider, self.name)
+    }
+}
+
+/// A probe from an ELF file's `.note.stapsdt` section, which is where
+/// `DTRACE_PROBE` and `STAP_PROBE` from `<sys/sdt.h>` put their probes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SdtNote {
+    provider: String,
+    name: String,
+    /// The address of the probe's nop instruction.
+    pc: u64,
+    /// The link-time address of the `.stapsdt.base` section, for adjusting
+    /// `pc` and `semaphore` if the file was prelinked.
+    base: u64,
+    /// The address of the probe's semaphore, or 0 if it doesn't have one.
+    semaphore: u64,
+    args: Vec<SdtArg>,
+}
+
+const NT_STAPSDT: u32 = 3;
+
+fn parse_stapsdt_notes(data: &[u8], is_64: bool, is_little_endian: bool) -> Vec<SdtNote> {
+    let read_u32 = |bytes: &[u8]| -> u32 {
+        let bytes = bytes.try_into().unwrap();
+        if is_little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    };
+    let read_address = |bytes: &[u8]| -> u64 {
+        match (is_64, is_little_endian) {
+            (true, true) => u64::from_le_bytes(bytes.try_into().unwrap()),
+            (true, false) => u64::from_be_bytes(bytes.try_into().unwrap()),
+            (false, _) => read_u32(bytes).into(),
+        }
+    };
+    let address_size = if is_64 { 8 } else { 4 };
+    let align4 = |len: usize| (len + 3) & !3;
+
+    let mut notes = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 12 {
+        let name_size = read_u32(&rest[0..4]) as usize;
+        let desc_size = read_u32(&rest[4..8]) as usize;
+        let note_type = read_u32(&rest[8..12]);
+        let desc_start = 12 + align4(name_size);
+        let Some(next) = rest.get(desc_start + align4(desc_size)..) else {
+            break;
+        };
+        let name = &rest[12..12 + name_size];
+        let desc = &rest[desc_start..desc_start + desc_size];
+        rest = next;
+        if note_type != NT_STAPSDT || name != b"stapsdt\0" || desc.len() < 3 * address_size {
+            continue;
+        }
+        let pc = read_address(&desc[..address_size]);
+        let base = read_address(&desc[address_size..2 * address_size]);
+        let semaphore = read_address(&desc[2 * address_size..3 * address_size]);
+        let mut strings = desc[3 * address_size..]
+            .split(|&b| b == 0)
+            .map(|s| String::from_utf8_lossy(s).into_owned());
+        let (Some(provider), Some(name)) = (strings.next(), strings.next()) else {
+            continue;
+        };
+        let args = parse_sdt_args(&strings.next().unwrap_or_default());
+        notes.push(SdtNote {
+            provider,
+            name,
+            pc,
+            base,
+            semaphore,
+            args,
+        });
+    }
+    notes
+}
+
+/// An argument of a probe. The argument string of a probe lists them as
+/// `size@location`, e.g. `-4@%edi 8@$5 8@-8(%rbp)`. A negative size means
+/// that the value is signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SdtArg {
+    size: u8,
+    is_signed: bool,
+    location: SdtArgLocation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SdtArgLocation {
+    /// The value is in this register, as a perf register index.
+    Register(u64),
+    Constant(i64),
+    /// The value is in memory, which we can't read.
+    Unsupported,
+}
+
+fn parse_sdt_args(args: &str) -> Vec<SdtArg> {
+    args.split_whitespace()
+        .filter_map(|arg| {
+            let (size, location) = arg.split_once('@')?;
+            let size: i8 = size.parse().ok()?;
+            let location = if let Some(constant) = location
+                .strip_prefix('$')
+                .or_else(|| location.strip_prefix('#'))
+            {
+                constant
+                    .parse()
+                    .map_or(SdtArgLocation::Unsupported, SdtArgLocation::Constant)
+            } else {
+                perf_register_index(location)
+                    .map_or(SdtArgLocation::Unsupported, SdtArgLocation::Register)
+            };
+            Some(SdtArg {
+                size: size.unsigned_abs(),
+                is_signed: size < 0,
+                location,
+            })
+        })
+        .collect()
+}
+
+/// The perf register index of a register in a probe argument, for the
+/// architecture samply was compiled for.
+fn perf_register_index(name: &str) -> Option<u64> {
+    if cfg!(target_arch = "aarch64") {
+        aarch64_register_index(name)
+    } else {
+        x86_64_register_index(name)
+    }
+}
+
+fn x86_64_register_index(name: &str) -> Option<u64> {
+    let name = name.strip_prefix('%')?;
+    let index = match name {
+        "rax" | "eax" | "ax" | "al" => 0,
+        "rbx" | "ebx" | "bx" | "bl" => 1,
+        "rcx" | "ecx" | "cx" | "cl" => 2,
+        "rdx" | "edx" | "dx" | "dl" => 3,
+        "rsi" | "esi" | "si" | "sil" => 4,
+        "rdi" | "edi" | "di" | "dil" => 5,
+        "rbp" | "ebp" | "bp" | "bpl" => 6,
+        "rsp" | "esp" | "sp" | "spl" => 7,
+        _ => {
+            // r8 to r15, with the d/w/b suffixes for the smaller sizes.
+            let number: u64 = name
+                .strip_prefix('r')?
+                .trim_end_matches(['d', 'w', 'b'])
+                .parse()
+                .ok()?;
+            if !(8..=15).contains(&number) {
+                return None;
+            }
+            16 + (number - 8)
+        }
+    };
+    Some(index)
+}
+
+fn aarch64_register_index(name: &str) -> Option<u64> {
+    if name == "sp" {
+        return Some(31);
+    }
+    let number: u64 = name
+        .strip_prefix('x')
+        .or_else(|| name.strip_prefix('w'))?
+        .parse()
+        .ok()?;
+    (number <= 30).then_some(number)
+}
+
+/// A USDT probe in a binary, ready for attaching a uprobe to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsdtProbe {
+    pub provider: String,
+    pub name: String,
+    pub path: PathBuf,
+    /// The file offset of the probe's instruction.
+    pub file_offset: u64,
+    /// The file offset of the probe's semaphore, which the kernel increments
+    /// while the probe is attached, so that the program knows that it needs
+    /// to compute the probe's arguments.
+    pub semaphore_file_offset: Option<u64>,
+    args: Vec<SdtArg>,
+}
+
+impl UsdtProbe {
+    pub fn marker_name(&self) -> String {
+        format!("{}:{}", self.provider, self.name)
+    }
+
+    /// The registers which need to be sampled when the probe fires, for
+    /// decoding its arguments.
+    pub fn regs_mask(&self) -> u64 {
+        self.args
+            .iter()
+            .filter_map(|arg| match arg.location {
+                SdtArgLocation::Register(index) => Some(1 << index),
+                _ => None,
+            })
+            .fold(0, |mask, bit| mask | bit)
+    }
+
+    /// Formats the arguments of a firing of the probe, e.g. `arg1=5 arg2=-1`.
+    /// Arguments which can't be decoded are shown as `?`.
+    pub fn format_args(&self, regs: Option<&Regs>) -> String {
+        let values = self.args.iter().enumerate().map(|(i, arg)| {
+            let raw = match arg.location {
+                SdtArgLocation::Register(index) => regs.and_then(|regs| regs.get(index)),
+                SdtArgLocation::Constant(value) => Some(value as u64),
+                SdtArgLocation::Unsupported => None,
+            };
+            let value = match raw {
+                Some(raw) => format_arg_value(raw, arg.size, arg.is_signed),
+                None => "?".to_owned(),
+            };
+            format!("arg{}={value}", i + 1)
+        });
+        values.collect::<Vec<_>>().join(" ")
+    }
+}
+
+fn format_arg_value(raw: u64, size: u8, is_signed: bool) -> String {
+    let bits = u32::from(size.clamp(1, 8)) * 8;
+    let shift = 64 - bits;
+    if is_signed {
+        (((raw << shift) as i64) >> shift).to_string()
+    } else {
+        ((raw << shift) >> shift).to_string()
+    }
+}
+
+/// Looks up the requested probes in the binary at `path`. Returns the probes
+/// which were found, and the specs for which no probe was found.
+pub fn find_usdt_probes(
+    path: &Path,
+    specs: &[UsdtProbeSpec],
+) -> std::io::Result<(Vec<UsdtProbe>, Vec<UsdtProbeSpec>)> {
+    let data = std::fs::read(path)?;
+    let file = object::File::parse(&data[..])
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let notes = match file.section_by_name(".note.stapsdt") {
+        Some(section) => parse_stapsdt_notes(
+            section.data().unwrap_or_default(),
+            file.is_64(),
+            file.is_little_endian(),
+        ),
+        None => Vec::new(),
+    };
+    let actual_base = file
+        .section_by_name(".stapsdt.base")
+        .map(|section| section.address());
+    let file_offset_for_address = |address: u64| {
+        file.segments().find_map(|segment| {
+            let (offset, size) = segment.file_range();
+            let start = segment.address();
+            (start..start + size)
+                .contains(&address)
+                .then(|| offset + (address - start))
+        })
+    };
+
+    let mut probes = Vec::new();
+    let mut missing = Vec::new();
+    for spec in specs {
+        let mut found = false;
+        for note in notes
+            .iter()
+            .filter(|note| note.provider == spec.provider && note.name == spec.name)
+        {
+            // Prelinking moves the sections, and the addresses in the notes
+            // need to be adjusted by the same amount.
+            let adjust = |address: u64| match actual_base {
+                Some(actual_base) if note.base != 0 => {
+                    address.wrapping_add(actual_base).wrapping_sub(note.base)
+                }
+                _ => address,
+            };
+            let Some(file_offset) = file_offset_for_address(adjust(note.pc)) else {
+                continue;
+            };
+            let semaphore_file_offset = match note.semaphore {
+                0 => None,
+                semaphore => file_offset_for_address(adjust(semaphore)),
+            };
+            probes.push(UsdtProbe {
+                provider: note.provider.clone(),
+                name: note.name.clone(),
+                path: path.to_owned(),
+                file_offset,
+                semaphore_file_offset,
+                args: note.args.clone(),
+            });
+            found = true;
+        }
+        if !found {
+            missing.push(spec.clone());
+        }
+    }
+    Ok((probes, missing))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_notes_and_args() {
+        let mut desc = Vec::new();
+        desc.extend_from_slice(&0x1234u64.to_le_bytes());
+        desc.extend_from_slice(&0x2000u64.to_le_bytes());
+        desc.extend_from_slice(&0u64.to_le_bytes());
+        desc.extend_from_slice(b"myapp\0request_start\0-4@%edi 8@%r12 -4@$-3 8@-8(%rbp)\0");
+        let mut note = Vec::new();
+        note.extend_from_slice(&8u32.to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&NT_STAPSDT.to_le_bytes());
+        note.extend_from_slice(b"stapsdt\0");
+        note.extend_from_slice(&desc);
+        note.resize((note.len() + 3) & !3, 0);
+
+        let notes = parse_stapsdt_notes(&note, true, true);
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].provider, "myapp");
+        assert_eq!(notes[0].name, "request_start");
+        assert_eq!((notes[0].pc, notes[0].base), (0x1234, 0x2000));
+
+        let args = parse_sdt_args("-4@%edi 8@%r12 -4@$-3 8@-8(%rbp)");
+        let locations: Vec<_> = args.iter().map(|arg| arg.location).collect();
+        if cfg!(target_arch = "x86_64") {
+            assert_eq!(notes[0].args, args);
+            assert_eq!(
+                locations,
+                vec![
+                    SdtArgLocation::Register(5),
+                    SdtArgLocation::Register(20),
+                    SdtArgLocation::Constant(-3),
+                    SdtArgLocation::Unsupported,
+                ]
+            );
+        }
+        assert_eq!(aarch64_register_index("w3"), Some(3));
+        assert_eq!(aarch64_register_index("x31"), None);
+
+        let probe = UsdtProbe {
+            provider: "myapp".to_owned(),
+            name: "request_start".to_owned(),
+            path: PathBuf::from("/usr/bin/myapp"),
+            file_offset: 0x1234,
+            semaphore_file_offset: None,
+            args,
+        };
+        assert_eq!(probe.marker_name(), "myapp:request_start");
+        assert_eq!(probe.format_args(None), "arg1=? arg2=? arg3=-3 arg4=?");
+        assert_eq!(format_arg_value(0xffff_ffff, 4, true), "-1");
+        assert_eq!(format_arg_value(0x1_0000_0005, 4, false), "5");
+
+        assert_eq!(
+            "myapp:request_start".parse::<UsdtProbeSpec>(),
+            Ok(UsdtProbeSpec {
+                provider: "myapp".to_owned(),
+                name: "request_start".to_owned()
+            })
+        );
+        assert!("request_start".parse::<UsdtProbeSpec>().is_err());
+    }
+}
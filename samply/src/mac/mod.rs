@@ -3,6 +3,7 @@ mod dyld_bindings;
 
 pub mod codesign_setup;
 mod error;
+#[allow(dead_code)]
 pub mod kernel_error;
 mod mach_ipc;
 mod proc_maps;
@@ -10,7 +11,9 @@ mod process_launcher;
 pub mod profiler;
 mod sampler;
 mod task_profiler;
+#[allow(dead_code)]
 pub mod thread_act;
+#[allow(dead_code)]
 pub mod thread_info;
 mod thread_profiler;
 mod time;
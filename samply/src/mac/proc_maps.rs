@@ -44,6 +44,7 @@ use super::dyld_bindings::{self};
 use super::error::SamplingError;
 use super::kernel_error::{self, IntoResult, KernelError};
 use super::task_profiler::UnwindSectionBytes;
+use crate::shared::recursion_folding::RecursionFolding;
 
 pub const TASK_DYLD_INFO_COUNT: mach_msg_type_number_t = 5;
 
@@ -479,7 +480,7 @@ pub fn get_backtrace(
     memory: &mut ForeignMemory,
     thread_act: mach_port_t,
     frames: &mut Vec<FrameAddress>,
-    fold_recursive_prefix: bool,
+    recursion_folding: RecursionFolding,
 ) -> Result<(), SamplingError> {
     with_suspended_thread(thread_act, || {
         let (pc, regs) = get_unwinding_registers(thread_act).map_err(|err| match err {
@@ -506,12 +507,7 @@ pub fn get_backtrace(
         )),
     })?;
 
-    if fold_recursive_prefix && !frames.is_empty() {
-        let last_frame = *frames.last().unwrap();
-        while frames.len() >= 2 && frames[frames.len() - 2] == last_frame {
-            frames.pop();
-        }
-    }
+    recursion_folding.fold(frames);
 
     Ok(())
 }
@@ -15,6 +15,7 @@ use super::process_launcher::{
 };
 use super::sampler::{JitdumpOrMarkerPath, Sampler, TaskInit, TaskInitOrShutdown};
 use super::time::get_monotonic_timestamp;
+use crate::profile_tools::post_process_profile_file;
 use crate::server::{start_server_main, ServerProps};
 use crate::shared::recording_props::{
     ProcessLaunchProps, ProfileCreationProps, RecordingMode, RecordingProps,
@@ -87,7 +88,9 @@ pub fn start_recording(
     };
 
     let unstable_presymbolicate = profile_creation_props.unstable_presymbolicate;
+    let post_processing = profile_creation_props.post_processing.clone();
 
+    let linger = recording_props.linger;
     let (task_sender, task_receiver) = unbounded();
 
     let sampler_thread = thread::spawn(move || {
@@ -179,6 +182,16 @@ pub fn start_recording(
     // Run the root task: either launch or attach to existing pid
     let exit_status = root_task_runner.run_root_task()?;
 
+    if let Some(linger) = linger {
+        // Keep accepting and sampling new tasks for a while, for example to
+        // see what the launched command's daemonized children are doing.
+        eprintln!(
+            "The command has exited, recording for another {:.1} seconds...",
+            linger.as_secs_f64()
+        );
+        thread::sleep(linger);
+    }
+
     accepter_sender
         .send(())
         .expect("couldn't tell accepter thread to stop");
@@ -213,6 +226,9 @@ pub fn start_recording(
         to_writer(writer, &profile).expect("Couldn't write JSON");
     }
 
+    post_process_profile_file(&output_file, &post_processing)
+        .expect("Couldn't post-process the profile");
+
     if unstable_presymbolicate {
         crate::shared::symbol_precog::presymbolicate(
             &profile,
@@ -10,6 +10,7 @@ use mach::port::mach_port_t;
 use super::error::SamplingError;
 use super::task_profiler::TaskProfiler;
 use super::time::get_monotonic_timestamp;
+use crate::shared::marker_file::MarkerNameWatcher;
 use crate::shared::recording_props::{ProfileCreationProps, RecordingProps};
 use crate::shared::recycling::ProcessRecycler;
 use crate::shared::timestamp_converter::TimestampConverter;
@@ -71,6 +72,7 @@ impl Sampler {
         let timestamp_converter = TimestampConverter {
             reference_raw: reference_mono,
             raw_to_ns_factor: 1,
+            tsc_conversion: None,
         };
 
         let mut profile = Profile::new(
@@ -119,6 +121,11 @@ impl Sampler {
         let mut unresolved_stacks = UnresolvedStacks::default();
         let mut last_sleep_overshoot = 0;
         let mut stop_profiling = false;
+        let mut stop_marker_watcher = self
+            .recording_props
+            .stop_on_marker
+            .clone()
+            .map(MarkerNameWatcher::new);
 
         loop {
             loop {
@@ -182,6 +189,11 @@ impl Sampler {
             mem::swap(&mut live_tasks, &mut tasks);
             for mut task in tasks.into_iter() {
                 task.check_received_paths();
+                if let Some(watcher) = &mut stop_marker_watcher {
+                    for path in task.marker_file_paths() {
+                        watcher.add_file(path);
+                    }
+                }
                 task.check_jitdump(&mut profile, &mut jit_category_manager);
                 let still_alive = task.sample(
                     sample_timestamp,
@@ -208,6 +220,13 @@ impl Sampler {
                 }
             }
 
+            if let Some(watcher) = &mut stop_marker_watcher {
+                if watcher.poll() {
+                    eprintln!("Found the marker for stopping the recording.");
+                    break;
+                }
+            }
+
             let intended_wakeup_time =
                 sample_mono + self.recording_props.interval.as_nanos() as u64;
             let before_sleep = get_monotonic_timestamp();
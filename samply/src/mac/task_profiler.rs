@@ -420,7 +420,7 @@ impl TaskProfiler {
                 stack_scratch_buffer,
                 unresolved_stacks,
                 &mut self.unresolved_samples,
-                self.profile_creation_props.fold_recursive_prefix,
+                self.profile_creation_props.recursion_folding,
             )?;
             if still_alive {
                 now_live_threads.insert(thread_act);
@@ -569,6 +569,13 @@ impl TaskProfiler {
         self.unwinder.add_module(module);
     }
 
+    /// The marker files which this task has told us about so far.
+    pub fn marker_file_paths(&self) -> impl Iterator<Item = &Path> {
+        self.marker_file_paths
+            .iter()
+            .map(|(_, path)| path.as_path())
+    }
+
     pub fn check_received_paths(&mut self) {
         while let Ok(jitdump_or_marker_file_path) = self.path_receiver.try_recv() {
             match jitdump_or_marker_file_path {
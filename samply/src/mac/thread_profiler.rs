@@ -16,6 +16,7 @@ use super::thread_info::{
     THREAD_EXTENDED_INFO_COUNT, THREAD_IDENTIFIER_INFO, THREAD_IDENTIFIER_INFO_COUNT,
 };
 use crate::mac::time;
+use crate::shared::recursion_folding::RecursionFolding;
 use crate::shared::recycling::ThreadRecycler;
 use crate::shared::types::{StackFrame, StackMode};
 use crate::shared::unresolved_samples::{UnresolvedSamples, UnresolvedStacks};
@@ -84,7 +85,7 @@ impl ThreadProfiler {
         stack_scratch_buffer: &mut Vec<FrameAddress>,
         unresolved_stacks: &mut UnresolvedStacks,
         unresolved_samples: &mut UnresolvedSamples,
-        fold_recursive_prefix: bool,
+        recursion_folding: RecursionFolding,
     ) -> Result<bool, SamplingError> {
         let result = self.sample_impl(
             stackwalker,
@@ -93,7 +94,7 @@ impl ThreadProfiler {
             stack_scratch_buffer,
             unresolved_stacks,
             unresolved_samples,
-            fold_recursive_prefix,
+            recursion_folding,
         );
         match result {
             Ok(()) => Ok(true),
@@ -126,7 +127,7 @@ impl ThreadProfiler {
         stack_scratch_buffer: &mut Vec<FrameAddress>,
         unresolved_stacks: &mut UnresolvedStacks,
         unresolved_samples: &mut UnresolvedSamples,
-        fold_recursive_prefix: bool,
+        recursion_folding: RecursionFolding,
     ) -> Result<(), SamplingError> {
         self.tick_count += 1;
 
@@ -142,7 +143,7 @@ impl ThreadProfiler {
                 &mut self.stack_memory,
                 self.thread_act,
                 stack_scratch_buffer,
-                fold_recursive_prefix,
+                recursion_folding,
             )?;
             // make sure to use the time immediately after the stack is sampled so that any
             // jitdump records emitted in the interval between samply starting to sample
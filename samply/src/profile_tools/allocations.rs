@@ -0,0 +1,220 @@
+//! Exports of the native allocations table, which has one row per allocation
+//! (with a positive weight in bytes) and per deallocation (with a negative
+//! weight), and the address of the allocated memory in `memoryAddress`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use serde_json::Value;
+
+use super::collapsed::write_collapsed_stack_weights;
+use super::{column_values, frame_names, stack_frames, Error};
+
+/// What the collapsed allocation stacks are weighted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationWeight {
+    Bytes,
+    Count,
+}
+
+/// Writes the allocations of all threads in the folded stacks format, like
+/// [`write_collapsed_stacks`](super::write_collapsed_stacks) does for samples.
+/// Deallocations are skipped.
+pub fn write_collapsed_allocations(
+    profile: &Value,
+    weight: AllocationWeight,
+    writer: impl Write,
+) -> Result<(), Error> {
+    write_collapsed_stack_weights(
+        profile,
+        |thread| {
+            let mut stack_weights = BTreeMap::new();
+            for allocation in allocations(thread).filter(|a| a.size > 0) {
+                if let Some(stack) = allocation.stack {
+                    *stack_weights.entry(stack).or_default() += match weight {
+                        AllocationWeight::Bytes => allocation.size as f64,
+                        AllocationWeight::Count => 1.0,
+                    };
+                }
+            }
+            stack_weights
+        },
+        writer,
+    )
+}
+
+/// Writes the allocations which weren't freed by the end of the profile,
+/// grouped by stack and sorted by the number of bytes, largest first.
+///
+/// Allocations are matched with deallocations by their memory address.
+/// Deallocations of memory which was allocated before the profile started
+/// are ignored.
+pub fn write_leak_report(profile: &Value, mut writer: impl Write) -> Result<(), Error> {
+    let mut leaks = Vec::new();
+    for thread in profile["threads"].as_array().into_iter().flatten() {
+        let Some(table) = thread.get("nativeAllocations") else {
+            continue;
+        };
+        if !table["memoryAddress"].is_array() {
+            return Err(Error::InvalidProfile(
+                "nativeAllocations has no memoryAddress column, so frees can't be matched",
+            ));
+        }
+        let mut live: HashMap<u64, Allocation> = HashMap::new();
+        let mut allocations: Vec<Allocation> = allocations(thread).collect();
+        allocations.sort_by(|a, b| a.time.total_cmp(&b.time));
+        for allocation in allocations {
+            if allocation.size > 0 {
+                live.insert(allocation.address, allocation);
+            } else {
+                live.remove(&allocation.address);
+            }
+        }
+
+        let mut by_stack: HashMap<Option<usize>, LeakSuspect> = HashMap::new();
+        for allocation in live.into_values() {
+            let suspect = by_stack
+                .entry(allocation.stack)
+                .or_insert_with(|| LeakSuspect {
+                    thread,
+                    stack: allocation.stack,
+                    bytes: 0,
+                    count: 0,
+                });
+            suspect.bytes += allocation.size;
+            suspect.count += 1;
+        }
+        leaks.extend(by_stack.into_values());
+    }
+    leaks.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.count.cmp(&a.count)));
+
+    let bytes: i64 = leaks.iter().map(|leak| leak.bytes).sum();
+    let count: usize = leaks.iter().map(|leak| leak.count).sum();
+    writeln!(
+        writer,
+        "{count} allocations ({bytes} bytes) were not freed, from {} stacks.",
+        leaks.len()
+    )?;
+    for leak in leaks {
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "{} bytes in {} allocations on thread {}:",
+            leak.bytes,
+            leak.count,
+            leak.thread["name"].as_str().unwrap_or("Thread")
+        )?;
+        let Some(stack) = leak.stack else {
+            writeln!(writer, "    <no stack>")?;
+            continue;
+        };
+        let names = frame_names(leak.thread);
+        let stack_table = leak.thread.get("stackTable");
+        let prefixes: Vec<&Value> = column_values(stack_table, "prefix").collect();
+        let frames: Vec<&Value> = column_values(stack_table, "frame").collect();
+        for frame in stack_frames(&prefixes, &frames, stack).into_iter().rev() {
+            writeln!(
+                writer,
+                "    {}",
+                names.get(frame).map_or("", String::as_str)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+struct Allocation {
+    time: f64,
+    stack: Option<usize>,
+    size: i64,
+    address: u64,
+}
+
+struct LeakSuspect<'a> {
+    thread: &'a Value,
+    stack: Option<usize>,
+    bytes: i64,
+    count: usize,
+}
+
+fn allocations(thread: &Value) -> impl Iterator<Item = Allocation> + '_ {
+    let table = thread.get("nativeAllocations");
+    let times: Vec<&Value> = column_values(table, "time").collect();
+    let stacks: Vec<&Value> = column_values(table, "stack").collect();
+    let addresses: Vec<&Value> = column_values(table, "memoryAddress").collect();
+    column_values(table, "weight")
+        .enumerate()
+        .map(move |(i, size)| Allocation {
+            time: times.get(i).and_then(|t| t.as_f64()).unwrap_or(0.0),
+            stack: stacks.get(i).and_then(|s| s.as_u64()).map(|s| s as usize),
+            size: size.as_i64().unwrap_or(0),
+            address: addresses.get(i).and_then(|a| a.as_u64()).unwrap_or(0),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn profile() -> Value {
+        // Stacks: 0 = main, 1 = main;alloc
+        json!({
+            "threads": [{
+                "name": "main",
+                "stringArray": ["main", "alloc"],
+                "funcTable": { "length": 2, "name": [0, 1] },
+                "frameTable": { "length": 2, "func": [0, 1], "address": [-1, -1] },
+                "stackTable": { "length": 2, "frame": [0, 1], "prefix": [null, 0] },
+                "nativeAllocations": {
+                    "length": 5,
+                    "time": [0.0, 1.0, 2.0, 3.0, 4.0],
+                    "stack": [1, 1, 0, 0, 1],
+                    "weight": [100, 20, 8, -100, -64],
+                    "weightType": "bytes",
+                    "memoryAddress": [16, 32, 48, 16, 4096],
+                },
+            }],
+        })
+    }
+
+    #[test]
+    fn collapse_allocations() {
+        let mut output = Vec::new();
+        write_collapsed_allocations(&profile(), AllocationWeight::Bytes, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "main;main 8\nmain;main;alloc 120\n"
+        );
+        let mut output = Vec::new();
+        write_collapsed_allocations(&profile(), AllocationWeight::Count, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "main;main 1\nmain;main;alloc 2\n"
+        );
+    }
+
+    #[test]
+    fn report_unfreed_allocations() {
+        let mut output = Vec::new();
+        write_leak_report(&profile(), &mut output).unwrap();
+        let expected = [
+            "2 allocations (28 bytes) were not freed, from 2 stacks.",
+            "",
+            "20 bytes in 1 allocations on thread main:",
+            "    alloc",
+            "    main",
+            "",
+            "8 bytes in 1 allocations on thread main:",
+            "    main",
+        ];
+        assert_eq!(
+            String::from_utf8(output)
+                .unwrap()
+                .lines()
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+}
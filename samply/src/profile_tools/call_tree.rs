@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::{column_values, frame_names, stack_frames, stack_sample_weights};
+
+/// The call tree of the samples in one or more threads. Call nodes are keyed
+/// by function name, so the same function under the same parent is merged,
+/// also across threads.
+pub struct CallTree {
+    nodes: Vec<CallNode>,
+}
+
+pub struct CallNode {
+    pub name: String,
+    pub parent: Option<usize>,
+    /// The children, sorted by decreasing total weight.
+    pub children: Vec<usize>,
+    /// The sample weight of this node and its descendants.
+    pub total: f64,
+    /// The sample weight of the samples whose leaf frame is this node.
+    pub self_weight: f64,
+}
+
+/// The sample weights of one function across the whole call tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSummary {
+    pub name: String,
+    pub self_weight: f64,
+    /// The weight of the samples which have this function on the stack,
+    /// counted once per sample even if the function is recursive.
+    pub total: f64,
+}
+
+impl CallTree {
+    /// The root node is index 0. It stands for all samples and has no
+    /// function name.
+    pub const ROOT: usize = 0;
+
+    pub fn from_threads<'a>(threads: impl IntoIterator<Item = &'a Value>) -> Self {
+        let mut tree = CallTree {
+            nodes: vec![CallNode {
+                name: String::new(),
+                parent: None,
+                children: Vec::new(),
+                total: 0.0,
+                self_weight: 0.0,
+            }],
+        };
+        let mut child_for_name: HashMap<(usize, String), usize> = HashMap::new();
+        for thread in threads {
+            let names = frame_names(thread);
+            let stack_table = thread.get("stackTable");
+            let prefixes: Vec<&Value> = column_values(stack_table, "prefix").collect();
+            let frames: Vec<&Value> = column_values(stack_table, "frame").collect();
+            for (stack, weight) in stack_sample_weights(thread) {
+                let mut node = Self::ROOT;
+                tree.nodes[node].total += weight;
+                for frame in stack_frames(&prefixes, &frames, stack) {
+                    let name = names.get(frame).cloned().unwrap_or_default();
+                    node = *child_for_name
+                        .entry((node, name.clone()))
+                        .or_insert_with(|| tree.add_child(node, name));
+                    tree.nodes[node].total += weight;
+                }
+                tree.nodes[node].self_weight += weight;
+            }
+        }
+        for i in 0..tree.nodes.len() {
+            let mut children = std::mem::take(&mut tree.nodes[i].children);
+            children.sort_by(|a, b| tree.nodes[*b].total.total_cmp(&tree.nodes[*a].total));
+            tree.nodes[i].children = children;
+        }
+        tree
+    }
+
+    fn add_child(&mut self, parent: usize, name: String) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(CallNode {
+            name,
+            parent: Some(parent),
+            children: Vec::new(),
+            total: 0.0,
+            self_weight: 0.0,
+        });
+        self.nodes[parent].children.push(index);
+        index
+    }
+
+    pub fn node(&self, index: usize) -> &CallNode {
+        &self.nodes[index]
+    }
+
+    /// The total weight of all samples.
+    pub fn total(&self) -> f64 {
+        self.nodes[Self::ROOT].total
+    }
+
+    /// The function names from the root's child down to `index`.
+    pub fn path(&self, index: usize) -> Vec<&str> {
+        let mut path = Vec::new();
+        let mut current = index;
+        while let Some(parent) = self.nodes[current].parent {
+            path.push(self.nodes[current].name.as_str());
+            current = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Sums up the weights per function, sorted by decreasing self weight.
+    pub fn function_summaries(&self) -> Vec<FunctionSummary> {
+        let mut summaries: HashMap<&str, FunctionSummary> = HashMap::new();
+        // How often each function is on the path to the current node.
+        let mut on_path: HashMap<&str, usize> = HashMap::new();
+        // (node, whether the node is being exited)
+        let mut work = vec![(Self::ROOT, false)];
+        while let Some((index, exiting)) = work.pop() {
+            let node = &self.nodes[index];
+            let is_root = index == Self::ROOT;
+            if exiting {
+                if !is_root {
+                    *on_path.get_mut(node.name.as_str()).unwrap() -= 1;
+                }
+                continue;
+            }
+            if !is_root {
+                let summary = summaries
+                    .entry(&node.name)
+                    .or_insert_with(|| FunctionSummary {
+                        name: node.name.clone(),
+                        self_weight: 0.0,
+                        total: 0.0,
+                    });
+                summary.self_weight += node.self_weight;
+                let count = on_path.entry(&node.name).or_default();
+                if *count == 0 {
+                    summary.total += node.total;
+                }
+                *count += 1;
+            }
+            work.push((index, true));
+            work.extend(node.children.iter().map(|child| (*child, false)));
+        }
+        let mut summaries: Vec<FunctionSummary> = summaries.into_values().collect();
+        summaries.sort_by(|a, b| {
+            b.self_weight
+                .total_cmp(&a.self_weight)
+                .then_with(|| b.total.total_cmp(&a.total))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn build_call_tree_and_function_summaries() {
+        // Stacks: 0 = main, 1 = main;work, 2 = main;work;work
+        let thread = json!({
+            "stringArray": ["main", "work"],
+            "funcTable": { "length": 2, "name": [0, 1] },
+            "frameTable": { "length": 2, "func": [0, 1], "address": [-1, -1] },
+            "stackTable": { "length": 3, "frame": [0, 1, 1], "prefix": [null, 0, 1] },
+            "samples": { "length": 4, "stack": [2, 1, 0, 2], "weight": [1, 1, 1, 1] },
+        });
+        let tree = CallTree::from_threads([&thread, &thread]);
+        assert_eq!(tree.total(), 8.0);
+        let main = tree.node(CallTree::ROOT).children[0];
+        assert_eq!(tree.node(main).name, "main");
+        assert_eq!(tree.node(main).self_weight, 2.0);
+        let work = tree.node(main).children[0];
+        assert_eq!(tree.node(work).total, 6.0);
+        assert_eq!(
+            tree.path(tree.node(work).children[0]),
+            ["main", "work", "work"]
+        );
+
+        let summaries = tree.function_summaries();
+        let summary = |name: &str, self_weight: f64, total: f64| FunctionSummary {
+            name: name.to_owned(),
+            self_weight,
+            total,
+        };
+        assert_eq!(
+            summaries,
+            [summary("work", 6.0, 6.0), summary("main", 2.0, 8.0)]
+        );
+    }
+}
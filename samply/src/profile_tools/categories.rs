@@ -0,0 +1,199 @@
+//! Category rules, which put frames into categories based on their function
+//! or library name, so that the category colors in the Firefox Profiler match
+//! the parts of the program the user cares about.
+//!
+//! A rules file has one rule per line: a regular expression and a category
+//! name, separated by `->`, e.g. `::gc -> GC` or `ntoskrnl|vmlinux -> Kernel`.
+//! Empty lines and lines starting with `#` are ignored. The first rule whose
+//! regular expression matches a frame's function name or library name
+//! decides the frame's category.
+
+use std::path::Path;
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use super::Error;
+
+/// The colors for categories which the profile doesn't have yet, used in turn.
+const NEW_CATEGORY_COLORS: &[&str] = &[
+    "blue",
+    "green",
+    "purple",
+    "magenta",
+    "brown",
+    "lightblue",
+    "red",
+    "orange",
+];
+
+#[derive(Debug, Clone)]
+pub struct CategoryRules {
+    /// (regex, category name)
+    rules: Vec<(Regex, String)>,
+}
+
+impl CategoryRules {
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut rules = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: String| Error::InvalidCategoryRule(index + 1, reason);
+            // Regular expressions can contain "->", category names can't.
+            let (regex, category) = line
+                .rsplit_once("->")
+                .ok_or_else(|| invalid("expected `REGEX -> CATEGORY`".to_owned()))?;
+            let regex = Regex::new(regex.trim()).map_err(|err| invalid(err.to_string()))?;
+            let category = category.trim();
+            if category.is_empty() {
+                return Err(invalid("the category name is empty".to_owned()));
+            }
+            rules.push((regex, category.to_owned()));
+        }
+        Ok(Self { rules })
+    }
+
+    /// Returns the index of the first rule which matches one of the names.
+    fn matching_rule(&self, names: &[&str]) -> Option<usize> {
+        self.rules
+            .iter()
+            .position(|(regex, _)| names.iter().any(|name| regex.is_match(name)))
+    }
+}
+
+/// Changes the category of every frame which matches a rule. Categories which
+/// the profile doesn't have yet are added.
+///
+/// Native functions only have names once the profile is symbolicated, before
+/// that only their library names can be matched.
+pub fn apply_category_rules(profile: &mut Value, rules: &CategoryRules) {
+    let lib_names: Vec<&str> = profile["libs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|lib| lib["name"].as_str().unwrap_or(""))
+        .collect();
+
+    // (thread index, frame index, rule index)
+    let mut matches = Vec::new();
+    for (thread_index, thread) in profile["threads"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let string = |index: &Value| {
+            let index = index.as_u64()? as usize;
+            thread["stringArray"].get(index)?.as_str()
+        };
+        let funcs = &thread["funcTable"];
+        let resources = &thread["resourceTable"];
+        let frame_funcs = thread["frameTable"]["func"]
+            .as_array()
+            .into_iter()
+            .flatten();
+        for (frame_index, func) in frame_funcs.enumerate() {
+            let Some(func) = func.as_u64().map(|func| func as usize) else {
+                continue;
+            };
+            let mut names = Vec::with_capacity(2);
+            names.extend(string(&funcs["name"][func]));
+            if let Some(resource) = funcs["resource"][func].as_i64() {
+                let lib = resources["lib"][resource as usize].as_u64();
+                names.extend(lib.and_then(|lib| lib_names.get(lib as usize)));
+            }
+            if let Some(rule_index) = rules.matching_rule(&names) {
+                matches.push((thread_index, frame_index, rule_index));
+            }
+        }
+    }
+
+    let mut category_indexes: Vec<Option<usize>> = vec![None; rules.rules.len()];
+    for &(thread_index, frame_index, rule_index) in &matches {
+        let category = *category_indexes[rule_index]
+            .get_or_insert_with(|| category_index(profile, &rules.rules[rule_index].1));
+        let frames = &mut profile["threads"][thread_index]["frameTable"];
+        frames["category"][frame_index] = category.into();
+        if frames["subcategory"].is_array() {
+            frames["subcategory"][frame_index] = 0.into();
+        }
+    }
+}
+
+/// Returns the index of the category with this name, adding it to the
+/// profile's categories if needed.
+fn category_index(profile: &mut Value, name: &str) -> usize {
+    if !profile["meta"]["categories"].is_array() {
+        profile["meta"]["categories"] = Value::Array(Vec::new());
+    }
+    let categories = profile["meta"]["categories"].as_array_mut().unwrap();
+    if let Some(index) = categories.iter().position(|c| c["name"] == name) {
+        return index;
+    }
+    let color = NEW_CATEGORY_COLORS[categories.len() % NEW_CATEGORY_COLORS.len()];
+    categories.push(json!({
+        "name": name,
+        "color": color,
+        "subcategories": ["Other"],
+    }));
+    categories.len() - 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames_get_categories_from_rules() {
+        let rules = CategoryRules::parse(
+            "# Rules for this test\n\
+             ::gc -> GC\n\
+             \n\
+             ^libGL -> Graphics\n\
+             ^libc -> Other\n",
+        )
+        .unwrap();
+        let mut profile = json!({
+            "meta": { "categories": [
+                { "name": "Other", "color": "grey", "subcategories": ["Other"] },
+                { "name": "User", "color": "yellow", "subcategories": ["Other"] },
+            ] },
+            "libs": [{ "name": "libGL.so.1" }, { "name": "libc.so.6" }],
+            "threads": [{
+                "stringArray": ["main", "heap::gc::collect", "0x1234", "0x5678"],
+                "resourceTable": { "length": 2, "lib": [0, 1], "name": [2, 3] },
+                "funcTable": { "length": 4, "name": [0, 1, 2, 3], "resource": [-1, -1, 0, 1] },
+                "frameTable": {
+                    "length": 4,
+                    "func": [0, 1, 2, 3],
+                    "category": [1, 1, 1, 1],
+                    "subcategory": [0, 0, 0, 0],
+                },
+            }],
+        });
+        apply_category_rules(&mut profile, &rules);
+        assert_eq!(
+            profile["threads"][0]["frameTable"]["category"],
+            json!([1, 2, 3, 0])
+        );
+        let category_names: Vec<&str> = profile["meta"]["categories"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(category_names, ["Other", "User", "GC", "Graphics"]);
+
+        assert!(matches!(
+            CategoryRules::parse("libGL Graphics"),
+            Err(Error::InvalidCategoryRule(1, _))
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use std::io::Write;
+
+use serde_json::{json, Value};
+
+use super::{column_values, frame_names, stack_frames, Error};
+
+/// Writes the profile in Chrome's Trace Event format, which can be loaded
+/// into Perfetto and chrome://tracing.
+///
+/// Markers become complete ("X") or instant ("i") events. Samples become a
+/// flame chart of begin / end ("B" / "E") events per thread, where every
+/// sample lasts until the next sample on the same thread. Timestamps are in
+/// microseconds since the start of the profile.
+pub fn write_chrome_trace(profile: &Value, writer: impl Write) -> Result<(), Error> {
+    let categories: Vec<&str> = profile["meta"]["categories"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|category| category["name"].as_str().unwrap_or("Other"))
+        .collect();
+    let mut events = Vec::new();
+    let threads = profile["threads"].as_array().into_iter().flatten();
+    for (thread_index, thread) in threads.enumerate() {
+        let ids = TraceIds {
+            pid: numeric_id(&thread["pid"]).unwrap_or(thread_index as u64),
+            tid: numeric_id(&thread["tid"]).unwrap_or(thread_index as u64),
+        };
+        add_name_events(thread, &ids, &mut events);
+        add_marker_events(thread, &ids, &categories, &mut events);
+        add_sample_events(thread, &ids, &mut events);
+    }
+    let trace = json!({
+        "traceEvents": events,
+        "displayTimeUnit": "ms",
+    });
+    serde_json::to_writer(writer, &trace)?;
+    Ok(())
+}
+
+struct TraceIds {
+    pid: u64,
+    tid: u64,
+}
+
+/// Trace events need numeric pids and tids. Profiles from `samply merge`
+/// can have pids like "1234.1", which are used without their suffix.
+fn numeric_id(id: &Value) -> Option<u64> {
+    match id {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.split('.').next()?.parse().ok(),
+        _ => None,
+    }
+}
+
+fn ms_to_us(ms: f64) -> f64 {
+    ms * 1000.0
+}
+
+fn add_name_events(thread: &Value, ids: &TraceIds, events: &mut Vec<Value>) {
+    if let Some(process_name) = thread["processName"].as_str() {
+        events.push(json!({
+            "ph": "M", "name": "process_name", "pid": ids.pid, "tid": ids.tid,
+            "args": { "name": process_name },
+        }));
+    }
+    if let Some(thread_name) = thread["name"].as_str() {
+        events.push(json!({
+            "ph": "M", "name": "thread_name", "pid": ids.pid, "tid": ids.tid,
+            "args": { "name": thread_name },
+        }));
+    }
+}
+
+fn add_marker_events(thread: &Value, ids: &TraceIds, categories: &[&str], events: &mut Vec<Value>) {
+    let strings = thread["stringArray"].as_array();
+    let markers = thread.get("markers");
+    let names: Vec<&Value> = column_values(markers, "name").collect();
+    let marker_categories: Vec<&Value> = column_values(markers, "category").collect();
+    let start_times: Vec<&Value> = column_values(markers, "startTime").collect();
+    let end_times: Vec<&Value> = column_values(markers, "endTime").collect();
+    for (i, data) in column_values(markers, "data").enumerate() {
+        let name = names
+            .get(i)
+            .and_then(|name| strings?.get(name.as_u64()? as usize)?.as_str())
+            .unwrap_or("Marker");
+        let category = marker_categories
+            .get(i)
+            .and_then(|c| categories.get(c.as_u64()? as usize).copied())
+            .unwrap_or("Other");
+        let start = start_times.get(i).and_then(|t| t.as_f64());
+        let end = end_times.get(i).and_then(|t| t.as_f64());
+        let args = if data.is_object() {
+            data.clone()
+        } else {
+            json!({})
+        };
+        let event = match (start, end) {
+            (Some(start), Some(end)) => json!({
+                "ph": "X", "name": name, "cat": category, "pid": ids.pid, "tid": ids.tid,
+                "ts": ms_to_us(start), "dur": ms_to_us(end - start), "args": args,
+            }),
+            (Some(time), None) | (None, Some(time)) => json!({
+                "ph": "i", "s": "t", "name": name, "cat": category, "pid": ids.pid,
+                "tid": ids.tid, "ts": ms_to_us(time), "args": args,
+            }),
+            (None, None) => continue,
+        };
+        events.push(event);
+    }
+}
+
+fn add_sample_events(thread: &Value, ids: &TraceIds, events: &mut Vec<Value>) {
+    let names = frame_names(thread);
+    let stack_table = thread.get("stackTable");
+    let prefixes: Vec<&Value> = column_values(stack_table, "prefix").collect();
+    let frames: Vec<&Value> = column_values(stack_table, "frame").collect();
+    let samples = thread.get("samples");
+    let times: Vec<f64> = column_values(samples, "time")
+        .map(|t| t.as_f64().unwrap_or(0.0))
+        .collect();
+    let frame_event = |ph: &str, frame: usize, time: f64| {
+        json!({
+            "ph": ph, "name": names.get(frame), "cat": "Samples",
+            "pid": ids.pid, "tid": ids.tid, "ts": ms_to_us(time),
+        })
+    };
+
+    // The frames which are currently open, from the root to the leaf.
+    let mut open_frames: Vec<usize> = Vec::new();
+    for (stack, &time) in column_values(samples, "stack").zip(&times) {
+        let sample_frames = match stack.as_u64() {
+            Some(stack) => stack_frames(&prefixes, &frames, stack as usize),
+            None => Vec::new(),
+        };
+        let common_len = open_frames
+            .iter()
+            .zip(&sample_frames)
+            .take_while(|(a, b)| a == b)
+            .count();
+        while open_frames.len() > common_len {
+            let frame = open_frames.pop().unwrap();
+            events.push(frame_event("E", frame, time));
+        }
+        for &frame in &sample_frames[common_len..] {
+            open_frames.push(frame);
+            events.push(frame_event("B", frame, time));
+        }
+    }
+
+    // The last sample lasts until the thread ends, if that's known.
+    if let Some(&last_time) = times.last() {
+        let end = thread["unregisterTime"]
+            .as_f64()
+            .filter(|end| *end > last_time)
+            .unwrap_or(last_time);
+        while let Some(frame) = open_frames.pop() {
+            events.push(frame_event("E", frame, end));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn export_markers_and_samples() {
+        let profile = json!({
+            "meta": { "categories": [{ "name": "Other" }, { "name": "IO" }] },
+            "threads": [{
+                "processName": "app",
+                "name": "main",
+                "pid": "10.1",
+                "tid": "11",
+                "unregisterTime": 4.0,
+                "stringArray": ["start", "work", "Read"],
+                "funcTable": { "length": 2, "name": [0, 1] },
+                "frameTable": { "length": 2, "func": [0, 1], "address": [-1, -1] },
+                "stackTable": { "length": 2, "frame": [0, 1], "prefix": [null, 0] },
+                "samples": { "length": 3, "stack": [1, 1, 0], "time": [1.0, 2.0, 3.0] },
+                "markers": {
+                    "length": 1,
+                    "name": [2],
+                    "category": [1],
+                    "startTime": [1.5],
+                    "endTime": [2.5],
+                    "data": [{ "type": "FileIO" }],
+                },
+            }],
+        });
+        let mut output = Vec::new();
+        write_chrome_trace(&profile, &mut output).unwrap();
+        let output: Value = serde_json::from_slice(&output).unwrap();
+        let events = output["traceEvents"].as_array().unwrap();
+        let summary: Vec<(&str, &str, f64)> = events
+            .iter()
+            .filter(|e| e["ph"] != "M")
+            .map(|e| {
+                (
+                    e["ph"].as_str().unwrap(),
+                    e["name"].as_str().unwrap(),
+                    e["ts"].as_f64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("X", "Read", 1500.0),
+                ("B", "start", 1000.0),
+                ("B", "work", 1000.0),
+                ("E", "work", 3000.0),
+                ("E", "start", 4000.0),
+            ]
+        );
+        assert_eq!(events[0]["pid"], json!(10));
+        assert_eq!(events[2]["dur"], json!(1000.0));
+        assert_eq!(events[2]["cat"], json!("IO"));
+    }
+}
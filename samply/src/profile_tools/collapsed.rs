@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use serde_json::Value;
+
+use super::{column_values, frame_names, stack_frames, stack_sample_weights, Error};
+
+/// Writes the samples of all threads in Brendan Gregg's "folded stacks"
+/// format, which is understood by flamegraph.pl, inferno and many other tools.
+///
+/// Every line has the frames of one stack, from the root to the leaf,
+/// separated by semicolons, followed by a space and the summed sample weight.
+/// The first frame of each stack is the name of the thread.
+pub fn write_collapsed_stacks(profile: &Value, writer: impl Write) -> Result<(), Error> {
+    write_collapsed_stack_weights(profile, stack_sample_weights, writer)
+}
+
+/// Writes the folded stacks with the weights which `stack_weights` returns
+/// for each thread, keyed by stack index.
+pub(super) fn write_collapsed_stack_weights(
+    profile: &Value,
+    stack_weights: impl Fn(&Value) -> BTreeMap<usize, f64>,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let mut lines: BTreeMap<String, i64> = BTreeMap::new();
+    for thread in profile["threads"].as_array().into_iter().flatten() {
+        let thread_name = thread["name"].as_str().unwrap_or("Thread");
+        let names = frame_names(thread);
+        let prefixes: Vec<&Value> = column_values(thread.get("stackTable"), "prefix").collect();
+        let frames: Vec<&Value> = column_values(thread.get("stackTable"), "frame").collect();
+        for (stack, weight) in stack_weights(thread) {
+            let mut line = sanitize_frame_name(thread_name);
+            for frame in stack_frames(&prefixes, &frames, stack) {
+                line.push(';');
+                line.push_str(&sanitize_frame_name(
+                    names.get(frame).map_or("", String::as_str),
+                ));
+            }
+            *lines.entry(line).or_default() += weight.round() as i64;
+        }
+    }
+    for (line, weight) in lines {
+        if weight != 0 {
+            writeln!(writer, "{line} {weight}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Semicolons separate frames and newlines separate stacks, so neither can
+/// appear in frame names.
+fn sanitize_frame_name(name: &str) -> String {
+    name.replace(';', ":").replace(['\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn collapse_stacks() {
+        let profile = json!({
+            "threads": [{
+                "name": "main",
+                "stringArray": ["start", "work", "a;b"],
+                "funcTable": { "length": 3, "name": [0, 1, 2] },
+                "frameTable": { "length": 3, "func": [0, 1, 2], "address": [-1, -1, -1] },
+                "stackTable": { "length": 3, "frame": [0, 1, 2], "prefix": [null, 0, 0] },
+                "samples": { "length": 4, "stack": [1, 1, 2, null], "weight": null },
+            }],
+        });
+        let mut output = Vec::new();
+        write_collapsed_stacks(&profile, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "main;start;a:b 1\nmain;start;work 2\n"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use serde_json::Value;
+
+use super::{column_mut, merge_profiles, threads_mut, Error, TimelineAlignment};
+
+/// Compares two profiles.
+///
+/// The result contains the threads of `after`. Each thread also gets the
+/// samples of the matching thread in `before`, with negated weights, so that
+/// every call node in the call tree shows the difference between the two
+/// profiles: positive where `after` spends more time, negative where it
+/// spends less.
+///
+/// Threads are matched by process name and thread name. Threads which only
+/// exist in `before` are kept with negated weights.
+pub fn diff_profiles(before: Value, after: Value) -> Result<Value, Error> {
+    let before_thread_count = before["threads"].as_array().map_or(0, Vec::len);
+    let mut diff = merge_profiles(vec![before, after], TimelineAlignment::Aligned)?;
+    let mut threads = std::mem::take(threads_mut(&mut diff)?);
+    let after_threads = threads.split_off(before_thread_count);
+    let mut before_threads: Vec<Option<Value>> = threads.into_iter().map(Some).collect();
+
+    // Maps thread indexes in the merged profile to thread indexes in the diff.
+    let mut new_thread_indexes = vec![None; before_thread_count + after_threads.len()];
+    let mut diff_threads = Vec::new();
+    for (i, mut thread) in after_threads.into_iter().enumerate() {
+        let matching_before_thread = before_threads
+            .iter_mut()
+            .find(|t| {
+                t.as_ref()
+                    .is_some_and(|t| thread_key(t) == thread_key(&thread))
+            })
+            .and_then(Option::take);
+        if let Some(mut before_thread) = matching_before_thread {
+            negate_sample_weights(&mut before_thread);
+            append_thread_samples(&mut thread, before_thread);
+        }
+        new_thread_indexes[before_thread_count + i] = Some(diff_threads.len());
+        diff_threads.push(thread);
+    }
+    for (i, thread) in before_threads.into_iter().enumerate() {
+        if let Some(mut thread) = thread {
+            negate_sample_weights(&mut thread);
+            new_thread_indexes[i] = Some(diff_threads.len());
+            diff_threads.push(thread);
+        }
+    }
+    for thread in &mut diff_threads {
+        // CPU usage can't be expressed as a difference, so don't draw any.
+        if let Some(samples) = thread["samples"].as_object_mut() {
+            samples.remove("threadCPUDelta");
+        }
+    }
+    *threads_mut(&mut diff)? = diff_threads;
+
+    // Counters belonging to threads which were merged away are dropped.
+    if let Some(counters) = diff["counters"].as_array_mut() {
+        counters.retain_mut(|counter| {
+            let Some(index) = counter["mainThreadIndex"].as_u64() else {
+                return true;
+            };
+            match new_thread_indexes.get(index as usize).copied().flatten() {
+                Some(new_index) => {
+                    counter["mainThreadIndex"] = new_index.into();
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+    Ok(diff)
+}
+
+fn thread_key(thread: &Value) -> (&Value, &Value) {
+    (&thread["processName"], &thread["name"])
+}
+
+/// Multiplies all sample weights in the thread by -1. Samples without an
+/// explicit weight have a weight of 1.
+fn negate_sample_weights(thread: &mut Value) {
+    let samples = &mut thread["samples"];
+    let len = samples["length"].as_u64().unwrap_or(0) as usize;
+    if !samples["weight"].is_array() {
+        samples["weight"] = Value::Array(vec![Value::from(1); len]);
+    }
+    if let Some(weights) = column_mut(samples, "weight") {
+        for weight in weights.iter_mut() {
+            *weight = match (weight.as_i64(), weight.as_f64()) {
+                (Some(w), _) => (-w).into(),
+                (None, Some(w)) => (-w).into(),
+                (None, None) => (-1).into(),
+            };
+        }
+    }
+}
+
+/// Appends the samples of `src` to `dest`, together with the stacks, frames,
+/// functions, resources, native symbols and strings they refer to.
+fn append_thread_samples(dest: &mut Value, src: Value) {
+    let string_offset = dest["stringArray"].as_array().map_or(0, Vec::len);
+    if let Some(strings) = src["stringArray"].as_array() {
+        match dest["stringArray"].as_array_mut() {
+            Some(dest_strings) => dest_strings.extend(strings.iter().cloned()),
+            None => dest["stringArray"] = Value::Array(strings.clone()),
+        }
+    }
+    let resource_offset = append_table(
+        &mut dest["resourceTable"],
+        &src["resourceTable"],
+        &[("name", string_offset)],
+    );
+    let native_symbol_offset = append_table(
+        &mut dest["nativeSymbols"],
+        &src["nativeSymbols"],
+        &[("name", string_offset)],
+    );
+    let func_offset = append_table(
+        &mut dest["funcTable"],
+        &src["funcTable"],
+        &[
+            ("name", string_offset),
+            ("fileName", string_offset),
+            ("resource", resource_offset),
+        ],
+    );
+    let frame_offset = append_table(
+        &mut dest["frameTable"],
+        &src["frameTable"],
+        &[
+            ("func", func_offset),
+            ("nativeSymbol", native_symbol_offset),
+        ],
+    );
+    let stack_offset = dest["stackTable"]["length"].as_u64().unwrap_or(0) as usize;
+    append_table(
+        &mut dest["stackTable"],
+        &src["stackTable"],
+        &[("frame", frame_offset), ("prefix", stack_offset)],
+    );
+    append_table(
+        &mut dest["samples"],
+        &src["samples"],
+        &[("stack", stack_offset)],
+    );
+    sort_samples_by_time(&mut dest["samples"]);
+}
+
+/// Appends the rows of the struct-of-arrays table `src` to `dest`. Index
+/// columns listed in `offsets` get the offset added to their non-null values.
+/// Returns the length of `dest` before appending, i.e. the index of the first
+/// appended row.
+fn append_table(dest: &mut Value, src: &Value, offsets: &[(&str, usize)]) -> usize {
+    let dest_len = dest["length"].as_u64().unwrap_or(0) as usize;
+    let src_len = src["length"].as_u64().unwrap_or(0) as usize;
+    let mut column_names: Vec<String> = Vec::new();
+    for table in [&*dest, src] {
+        for (name, column) in table.as_object().into_iter().flatten() {
+            if column.is_array() && !column_names.contains(name) {
+                column_names.push(name.clone());
+            }
+        }
+    }
+    for name in column_names {
+        let offset = offsets.iter().find(|(n, _)| *n == name).map(|(_, o)| *o);
+        let appended: Vec<Value> = match src[&name].as_array() {
+            Some(column) => column
+                .iter()
+                .map(|value| match (offset, value.as_u64()) {
+                    (Some(offset), Some(index)) => (index as usize + offset).into(),
+                    _ => value.clone(),
+                })
+                .collect(),
+            None => vec![Value::Null; src_len],
+        };
+        if !dest[&name].is_array() {
+            dest[&name] = Value::Array(vec![Value::Null; dest_len]);
+        }
+        column_mut(dest, &name).unwrap().extend(appended);
+    }
+    dest["length"] = (dest_len + src_len).into();
+    dest_len
+}
+
+fn sort_samples_by_time(samples: &mut Value) {
+    let times: Vec<f64> = samples["time"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|t| t.as_f64().unwrap_or(0.0))
+        .collect();
+    let mut order: Vec<usize> = (0..times.len()).collect();
+    order.sort_by(|a, b| times[*a].total_cmp(&times[*b]));
+    for column in samples
+        .as_object_mut()
+        .into_iter()
+        .flat_map(|s| s.values_mut())
+    {
+        if let Some(values) = column.as_array_mut().filter(|v| v.len() == order.len()) {
+            *values = order.iter().map(|&i| values[i].take()).collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn profile(func_name: &str, weights: Value) -> Value {
+        json!({
+            "meta": { "startTime": 0.0, "categories": [] },
+            "libs": [],
+            "counters": [],
+            "threads": [{
+                "processName": "app",
+                "name": "main",
+                "pid": "1",
+                "tid": "1",
+                "stringArray": [func_name],
+                "resourceTable": { "length": 0, "lib": [], "name": [] },
+                "nativeSymbols": { "length": 0, "libIndex": [], "name": [] },
+                "funcTable": { "length": 1, "name": [0], "resource": [-1] },
+                "frameTable": { "length": 1, "func": [0], "nativeSymbol": [null] },
+                "stackTable": { "length": 1, "frame": [0], "prefix": [null] },
+                "samples": {
+                    "length": 2,
+                    "stack": [0, 0],
+                    "time": [1.0, 3.0],
+                    "weight": weights,
+                    "threadCPUDelta": [0, 1],
+                },
+            }],
+        })
+    }
+
+    #[test]
+    fn diff_matching_threads() {
+        let before = profile("slow", json!([1, 1]));
+        let after = profile("fast", json!([2, 1]));
+        let diff = diff_profiles(before, after).unwrap();
+        let threads = diff["threads"].as_array().unwrap();
+        assert_eq!(threads.len(), 1);
+        let thread = &threads[0];
+        assert_eq!(thread["stringArray"], json!(["fast", "slow"]));
+        assert_eq!(thread["funcTable"]["name"], json!([0, 1]));
+        assert_eq!(thread["funcTable"]["resource"], json!([-1, -1]));
+        assert_eq!(thread["stackTable"]["frame"], json!([0, 1]));
+        assert_eq!(thread["stackTable"]["prefix"], json!([null, null]));
+        assert_eq!(thread["samples"]["length"], json!(4));
+        assert_eq!(thread["samples"]["stack"], json!([0, 1, 0, 1]));
+        assert_eq!(thread["samples"]["weight"], json!([2, -1, 1, -1]));
+        assert!(thread["samples"].get("threadCPUDelta").is_none());
+    }
+}
@@ -0,0 +1,216 @@
+use serde_json::{json, Value};
+
+use super::{
+    column_mut, column_values, filter_table_rows, remap_index_column, remap_stack_references,
+    threads_mut, Error, STACK_REFERENCES,
+};
+
+/// Give up once the remaining samples would stand for this many original samples.
+const MAX_DOWNSAMPLING_FACTOR: usize = 1024;
+
+/// How much a profile was reduced to fit into the size budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeReduction {
+    pub original_size: usize,
+    pub reduced_size: usize,
+    /// Only one sample out of each group of this many consecutive samples is kept.
+    pub downsampling_factor: usize,
+    /// Stacks with fewer samples than this were merged into their parent stack.
+    pub cold_stack_threshold: usize,
+}
+
+/// Makes the profile's JSON fit into `max_size` bytes, by keeping only one
+/// out of every N samples and by merging rarely sampled stacks into their
+/// parent stack. N is doubled until the profile is small enough.
+///
+/// The kept samples get the summed weight and CPU delta of the dropped
+/// samples, so the call tree totals stay the same. The applied reduction is
+/// recorded in the profile's metadata.
+///
+/// Returns `None` if the profile was small enough already.
+pub fn reduce_profile_size(
+    profile: &mut Value,
+    max_size: usize,
+) -> Result<Option<SizeReduction>, Error> {
+    let original_size = serde_json::to_vec(profile)?.len();
+    if original_size <= max_size {
+        return Ok(None);
+    }
+
+    let mut downsampling_factor: usize = 2;
+    loop {
+        let cold_stack_threshold = downsampling_factor.trailing_zeros() as usize;
+        let mut reduced = profile.clone();
+        for thread in threads_mut(&mut reduced)? {
+            downsample_thread(thread, downsampling_factor);
+            merge_cold_stacks(thread, cold_stack_threshold);
+            remove_unused_stacks(thread);
+        }
+        let reduced_size = serde_json::to_vec(&reduced)?.len();
+        if reduced_size <= max_size || downsampling_factor >= MAX_DOWNSAMPLING_FACTOR {
+            let reduction = SizeReduction {
+                original_size,
+                reduced_size,
+                downsampling_factor,
+                cold_stack_threshold,
+            };
+            add_reduction_to_meta(&mut reduced, &reduction);
+            *profile = reduced;
+            return Ok(Some(reduction));
+        }
+        downsampling_factor *= 2;
+    }
+}
+
+/// Keeps the first sample of each group of `factor` consecutive samples, and
+/// gives it the summed weight and CPU delta of the whole group.
+fn downsample_thread(thread: &mut Value, factor: usize) {
+    let samples = &mut thread["samples"];
+    let len = samples["length"].as_u64().unwrap_or(0) as usize;
+    if !samples["weight"].is_array() {
+        samples["weight"] = Value::Array(vec![Value::from(1); len]);
+    }
+    for column_name in ["weight", "auxWeight", "threadCPUDelta"] {
+        let Some(values) = column_mut(samples, column_name) else {
+            continue;
+        };
+        for group in values.chunks_mut(factor) {
+            let sum: f64 = group.iter().filter_map(Value::as_f64).sum();
+            group[0] = if group[0].is_i64() || group[0].is_u64() {
+                (sum as i64).into()
+            } else {
+                sum.into()
+            };
+        }
+    }
+    let keep: Vec<bool> = (0..len).map(|i| i % factor == 0).collect();
+    filter_table_rows(samples, &keep);
+}
+
+/// Moves samples whose stack (including its descendants) has fewer than
+/// `threshold` samples up to the nearest ancestor stack which has enough.
+fn merge_cold_stacks(thread: &mut Value, threshold: usize) {
+    if threshold <= 1 {
+        return;
+    }
+    let prefixes = stack_prefixes(thread);
+    let mut subtree_sample_counts = vec![0; prefixes.len()];
+    for stack in column_values(thread.get("samples"), "stack").filter_map(Value::as_u64) {
+        if let Some(count) = subtree_sample_counts.get_mut(stack as usize) {
+            *count += 1;
+        }
+    }
+    // Prefixes always come before the stacks which use them.
+    for stack in (0..prefixes.len()).rev() {
+        if let Some(prefix) = prefixes[stack] {
+            subtree_sample_counts[prefix] += subtree_sample_counts[stack];
+        }
+    }
+    let Some(stacks) = column_mut(&mut thread["samples"], "stack") else {
+        return;
+    };
+    for stack in stacks {
+        let Some(mut index) = stack.as_u64().map(|s| s as usize) else {
+            continue;
+        };
+        while subtree_sample_counts
+            .get(index)
+            .is_some_and(|c| *c < threshold)
+        {
+            match prefixes[index] {
+                Some(prefix) => index = prefix,
+                None => break,
+            }
+        }
+        *stack = index.into();
+    }
+}
+
+fn stack_prefixes(thread: &Value) -> Vec<Option<usize>> {
+    column_values(thread.get("stackTable"), "prefix")
+        .map(|prefix| prefix.as_u64().map(|p| p as usize))
+        .collect()
+}
+
+/// Removes the stacks which are no longer referenced by any sample or marker.
+fn remove_unused_stacks(thread: &mut Value) {
+    let prefixes = stack_prefixes(thread);
+    let mut used = vec![false; prefixes.len()];
+    let mut mark_used = |stack: &Value| {
+        if let Some(used) = stack.as_u64().and_then(|s| used.get_mut(s as usize)) {
+            *used = true;
+        }
+    };
+    for (table_name, column_name) in STACK_REFERENCES {
+        column_values(thread.get(*table_name), column_name).for_each(&mut mark_used);
+    }
+    for data in column_values(thread.get("markers"), "data") {
+        mark_used(&data["stack"]);
+        mark_used(&data["cause"]["stack"]);
+    }
+    for stack in (0..prefixes.len()).rev() {
+        if let (true, Some(prefix)) = (used[stack], prefixes[stack]) {
+            used[prefix] = true;
+        }
+    }
+
+    let mut new_indexes = Vec::with_capacity(used.len());
+    let mut next_index = 0;
+    for is_used in &used {
+        new_indexes.push(next_index);
+        next_index += usize::from(*is_used);
+    }
+    let stack_table = &mut thread["stackTable"];
+    filter_table_rows(stack_table, &used);
+    remap_index_column(stack_table, "prefix", &new_indexes);
+    remap_stack_references(thread, &new_indexes);
+}
+
+/// Adds a section to `meta.extra`, which the profiler shows in the profile info panel.
+fn add_reduction_to_meta(profile: &mut Value, reduction: &SizeReduction) {
+    let section = json!({
+        "label": "Size reduction",
+        "entries": [
+            { "label": "Original size", "format": "bytes", "value": reduction.original_size },
+            { "label": "Samples merged per kept sample", "format": "integer", "value": reduction.downsampling_factor },
+            { "label": "Stacks merged into parent below sample count", "format": "integer", "value": reduction.cold_stack_threshold },
+        ],
+    });
+    match profile["meta"]["extra"].as_array_mut() {
+        Some(extra) => extra.push(section),
+        None => profile["meta"]["extra"] = json!([section]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn downsample_and_merge_cold_stacks() {
+        let mut thread = json!({
+            "stackTable": { "length": 4, "prefix": [null, 0, 1, 0], "frame": [0, 1, 2, 3] },
+            "samples": {
+                "length": 8,
+                "stack": [1, 1, 2, 1, 1, 1, 3, 1],
+                "time": [0, 1, 2, 3, 4, 5, 6, 7],
+                "weight": [1, 1, 1, 1, 1, 1, 1, 1],
+                "threadCPUDelta": [5, 5, 5, 5, 5, 5, 5, 5],
+            },
+            "markers": { "length": 1, "data": [{ "type": "Text", "cause": { "stack": 3 } }] },
+        });
+        downsample_thread(&mut thread, 2);
+        assert_eq!(thread["samples"]["stack"], json!([1, 2, 1, 3]));
+        assert_eq!(thread["samples"]["weight"], json!([2, 2, 2, 2]));
+        assert_eq!(thread["samples"]["threadCPUDelta"], json!([10, 10, 10, 10]));
+
+        merge_cold_stacks(&mut thread, 2);
+        assert_eq!(thread["samples"]["stack"], json!([1, 1, 1, 0]));
+
+        remove_unused_stacks(&mut thread);
+        assert_eq!(thread["stackTable"]["length"], json!(3));
+        assert_eq!(thread["stackTable"]["frame"], json!([0, 1, 3]));
+        assert_eq!(thread["stackTable"]["prefix"], json!([null, 0, 0]));
+        assert_eq!(thread["markers"]["data"][0]["cause"]["stack"], json!(2));
+    }
+}
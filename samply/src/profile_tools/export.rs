@@ -0,0 +1,157 @@
+//! The output formats of `samply export`. Each format is an [`Exporter`],
+//! and new formats are added with [`ExporterRegistry::register`].
+
+use std::io::Write;
+
+use clap::builder::PossibleValue;
+use serde_json::Value;
+
+use super::{
+    write_chrome_trace, write_collapsed_allocations, write_collapsed_stacks, write_leak_report,
+    write_speedscope_profile, AllocationWeight, Error,
+};
+
+pub trait Exporter {
+    /// The name for `samply export --format`.
+    fn name(&self) -> &'static str;
+
+    /// What the format is, for the help text.
+    fn description(&self) -> &'static str;
+
+    fn export(&self, profile: &Value, writer: &mut dyn Write) -> Result<(), Error>;
+}
+
+/// An exporter which is just a function.
+struct FnExporter {
+    name: &'static str,
+    description: &'static str,
+    export: fn(&Value, &mut dyn Write) -> Result<(), Error>,
+}
+
+impl Exporter for FnExporter {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn export(&self, profile: &Value, writer: &mut dyn Write) -> Result<(), Error> {
+        (self.export)(profile, writer)
+    }
+}
+
+pub struct ExporterRegistry {
+    exporters: Vec<Box<dyn Exporter>>,
+}
+
+impl Default for ExporterRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExporterRegistry {
+    /// A registry with the built-in formats.
+    pub fn new() -> Self {
+        let builtin = [
+            FnExporter {
+                name: "collapsed",
+                description: "Brendan Gregg's folded stacks format, with one line per stack, \
+                              as used by flamegraph.pl and inferno",
+                export: |profile, writer| write_collapsed_stacks(profile, writer),
+            },
+            FnExporter {
+                name: "speedscope",
+                description: "Speedscope's JSON format, with one sampled profile per thread",
+                export: |profile, writer| write_speedscope_profile(profile, writer),
+            },
+            FnExporter {
+                name: "chrome-trace",
+                description: "Chrome's Trace Event format, for Perfetto and chrome://tracing. \
+                              Markers become trace events, and samples become a flame chart \
+                              per thread",
+                export: |profile, writer| write_chrome_trace(profile, writer),
+            },
+            FnExporter {
+                name: "collapsed-allocated-bytes",
+                description: "Folded stacks of the native allocations, weighted by the \
+                              allocated bytes",
+                export: |profile, writer| {
+                    write_collapsed_allocations(profile, AllocationWeight::Bytes, writer)
+                },
+            },
+            FnExporter {
+                name: "collapsed-allocation-count",
+                description: "Folded stacks of the native allocations, weighted by the number \
+                              of allocations",
+                export: |profile, writer| {
+                    write_collapsed_allocations(profile, AllocationWeight::Count, writer)
+                },
+            },
+            FnExporter {
+                name: "leak-report",
+                description: "A text report of the native allocations which were not freed, \
+                              by stack",
+                export: |profile, writer| write_leak_report(profile, writer),
+            },
+        ];
+        Self {
+            exporters: builtin
+                .into_iter()
+                .map(|exporter| Box::new(exporter) as Box<dyn Exporter>)
+                .collect(),
+        }
+    }
+
+    #[allow(unused)]
+    pub fn register(&mut self, exporter: Box<dyn Exporter>) {
+        self.exporters.push(exporter);
+    }
+
+    pub fn find(&self, name: &str) -> Option<&dyn Exporter> {
+        self.exporters
+            .iter()
+            .find(|exporter| exporter.name() == name)
+            .map(Box::as_ref)
+    }
+
+    /// The format names, for clap.
+    pub fn possible_values(&self) -> Vec<PossibleValue> {
+        self.exporters
+            .iter()
+            .map(|exporter| PossibleValue::new(exporter.name()).help(exporter.description()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn export_through_registry() {
+        let profile = json!({
+            "threads": [{
+                "name": "main",
+                "stringArray": ["main"],
+                "funcTable": { "length": 1, "name": [0] },
+                "frameTable": { "length": 1, "func": [0], "address": [-1] },
+                "stackTable": { "length": 1, "frame": [0], "prefix": [null] },
+                "samples": { "length": 2, "stack": [0, 0] },
+            }],
+        });
+        let registry = ExporterRegistry::new();
+        let mut output = Vec::new();
+        registry
+            .find("collapsed")
+            .unwrap()
+            .export(&profile, &mut output)
+            .unwrap();
+        assert!(String::from_utf8(output).unwrap().ends_with("main 2\n"));
+        assert!(registry.find("unknown").is_none());
+    }
+}
@@ -0,0 +1,119 @@
+use serde_json::Value;
+
+use super::{column_mut, threads_mut, Error};
+
+/// The processed profile format version which samply writes.
+const SAMPLY_PROFILE_VERSION: u64 = 48;
+
+/// Whether this is a profile straight from Firefox's Gecko profiler, e.g.
+/// from `MOZ_PROFILER_SHUTDOWN`, which the Firefox Profiler hasn't processed
+/// yet. Its threads are stored as rows with a schema rather than as columns.
+fn is_gecko_profile(profile: &Value) -> bool {
+    profile["meta"]["version"].is_number()
+        && profile["meta"]["preprocessedProfileVersion"].is_null()
+}
+
+/// Rewrites a profile which was saved from the Firefox Profiler, such as a
+/// Firefox profile, into the layout of samply's profiles, so that they can
+/// be merged.
+///
+/// Newer versions of the processed format store one string array for all
+/// threads in `shared.stringArray`, and can store the sample times as deltas.
+/// Each thread gets its own copy of the shared strings, so that the string
+/// indexes stay valid, and the times are made absolute.
+pub fn normalize_firefox_profile(profile: &mut Value) -> Result<(), Error> {
+    if is_gecko_profile(profile) {
+        return Err(Error::InvalidProfile(
+            "this is an unprocessed Gecko profile; load it in the Firefox Profiler and download it from there first",
+        ));
+    }
+    let shared_strings = profile
+        .get_mut("shared")
+        .and_then(|shared| shared.as_object_mut())
+        .and_then(|shared| shared.remove("stringArray"));
+    for thread in threads_mut(profile)? {
+        if let Some(strings) = &shared_strings {
+            if !thread["stringArray"].is_array() {
+                thread["stringArray"] = strings.clone();
+            }
+        }
+        for table_name in ["samples", "nativeAllocations", "jsAllocations"] {
+            if let Some(table) = thread.get_mut(table_name) {
+                resolve_time_deltas(table);
+            }
+        }
+    }
+    if let Some(counters) = profile["counters"].as_array_mut() {
+        for samples in counters.iter_mut().filter_map(|c| c.get_mut("samples")) {
+            resolve_time_deltas(samples);
+        }
+    }
+    if profile["shared"]
+        .as_object()
+        .is_some_and(|shared| shared.is_empty())
+    {
+        profile.as_object_mut().unwrap().remove("shared");
+    }
+
+    let version = &mut profile["meta"]["preprocessedProfileVersion"];
+    if version.as_u64().is_some_and(|v| v > SAMPLY_PROFILE_VERSION) {
+        *version = SAMPLY_PROFILE_VERSION.into();
+    }
+    Ok(())
+}
+
+/// Replaces a table's `timeDeltas` column with a `time` column.
+fn resolve_time_deltas(table: &mut Value) {
+    let Some(deltas) = table.as_object_mut().and_then(|t| t.remove("timeDeltas")) else {
+        return;
+    };
+    let mut time = 0.0;
+    let times: Vec<Value> = deltas
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|delta| {
+            time += delta.as_f64().unwrap_or(0.0);
+            time.into()
+        })
+        .collect();
+    if column_mut(table, "time").is_none() {
+        table["time"] = Value::Array(times);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn normalize_shared_strings_and_time_deltas() {
+        let mut profile = json!({
+            "meta": { "startTime": 1000.0, "preprocessedProfileVersion": 53 },
+            "shared": { "stringArray": ["main", "nsThread::ProcessNextEvent"] },
+            "threads": [{
+                "name": "GeckoMain",
+                "samples": { "length": 3, "stack": [0, 0, 0], "timeDeltas": [5.0, 1.0, 1.5] },
+                "funcTable": { "length": 1, "name": [1] },
+            }],
+            "counters": [{ "samples": { "length": 1, "count": [10], "timeDeltas": [2.0] } }],
+        });
+        normalize_firefox_profile(&mut profile).unwrap();
+
+        let thread = &profile["threads"][0];
+        assert_eq!(thread["stringArray"][1], "nsThread::ProcessNextEvent");
+        assert_eq!(thread["samples"]["time"], json!([5.0, 6.0, 7.5]));
+        assert!(thread["samples"].get("timeDeltas").is_none());
+        assert_eq!(profile["counters"][0]["samples"]["time"], json!([2.0]));
+        assert!(profile.get("shared").is_none());
+        assert_eq!(profile["meta"]["preprocessedProfileVersion"], 48);
+    }
+
+    #[test]
+    fn reject_gecko_profile() {
+        let mut profile = json!({ "meta": { "version": 27 }, "threads": [] });
+        assert!(normalize_firefox_profile(&mut profile).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde_json::Value;
+
+use super::{column_mut, column_values, frame_names, remap_stack_references, threads_mut, Error};
+
+/// Folds the frames whose name matches one of the patterns into their
+/// caller, in every stack of every thread, so that plumbing like interpreter
+/// trampolines doesn't clutter the call tree. Stacks which become identical
+/// are merged. Root frames are kept, because there is nothing to fold them
+/// into.
+pub fn fold_frames(profile: &mut Value, patterns: &[Regex]) -> Result<(), Error> {
+    if patterns.is_empty() {
+        return Ok(());
+    }
+    for thread in threads_mut(profile)? {
+        fold_thread_frames(thread, patterns);
+    }
+    Ok(())
+}
+
+fn fold_thread_frames(thread: &mut Value, patterns: &[Regex]) {
+    let folded_frames: Vec<bool> = frame_names(thread)
+        .iter()
+        .map(|name| patterns.iter().any(|pattern| pattern.is_match(name)))
+        .collect();
+    if !folded_frames.contains(&true) {
+        return;
+    }
+
+    let stack_table = thread.get("stackTable");
+    let frames: Vec<Option<usize>> = column_values(stack_table, "frame")
+        .map(|frame| frame.as_u64().map(|f| f as usize))
+        .collect();
+    let prefixes: Vec<Option<usize>> = column_values(stack_table, "prefix")
+        .map(|prefix| prefix.as_u64().map(|p| p as usize))
+        .collect();
+
+    // Prefixes always come before the stacks which use them, so the new
+    // index of the prefix is known when a stack is visited.
+    let mut new_indexes: Vec<usize> = Vec::with_capacity(frames.len());
+    let mut kept_stacks: Vec<usize> = Vec::new();
+    let mut new_prefixes: Vec<Option<usize>> = Vec::new();
+    let mut stack_lookup: HashMap<(Option<usize>, Option<usize>), usize> = HashMap::new();
+    for (stack, &frame) in frames.iter().enumerate() {
+        let prefix = prefixes
+            .get(stack)
+            .copied()
+            .flatten()
+            .filter(|p| *p < stack)
+            .map(|p| new_indexes[p]);
+        let is_folded = frame.is_some_and(|f| folded_frames.get(f) == Some(&true));
+        if let (true, Some(prefix)) = (is_folded, prefix) {
+            new_indexes.push(prefix);
+            continue;
+        }
+        let new_index = *stack_lookup.entry((prefix, frame)).or_insert_with(|| {
+            kept_stacks.push(stack);
+            new_prefixes.push(prefix);
+            kept_stacks.len() - 1
+        });
+        new_indexes.push(new_index);
+    }
+
+    let stack_table = &mut thread["stackTable"];
+    if let Some(columns) = stack_table.as_object_mut() {
+        for column in columns.values_mut() {
+            if let Some(values) = column.as_array_mut().filter(|v| v.len() == frames.len()) {
+                *values = kept_stacks.iter().map(|&s| values[s].clone()).collect();
+            }
+        }
+    }
+    if let Some(prefix_column) = column_mut(stack_table, "prefix") {
+        *prefix_column = new_prefixes.iter().map(|&p| p.into()).collect();
+    }
+    stack_table["length"] = kept_stacks.len().into();
+    remap_stack_references(thread, &new_indexes);
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn fold_matching_frames_into_caller() {
+        // Stacks: main, main;call_trampoline, main;call_trampoline;work, main;work
+        let mut profile = json!({
+            "threads": [{
+                "stringArray": ["main", "call_trampoline", "work"],
+                "funcTable": { "length": 3, "name": [0, 1, 2] },
+                "frameTable": { "length": 3, "func": [0, 1, 2], "address": [-1, -1, -1] },
+                "stackTable": { "length": 4, "frame": [0, 1, 2, 2], "prefix": [null, 0, 1, 0] },
+                "samples": { "length": 4, "stack": [0, 1, 2, 3] },
+                "markers": { "length": 1, "data": [{ "type": "Text", "stack": 2 }] },
+            }],
+        });
+        fold_frames(&mut profile, &[Regex::new("trampoline").unwrap()]).unwrap();
+        let thread = &profile["threads"][0];
+        assert_eq!(
+            thread["stackTable"],
+            json!({ "length": 2, "frame": [0, 2], "prefix": [null, 0] })
+        );
+        assert_eq!(thread["samples"]["stack"], json!([0, 0, 1, 1]));
+        assert_eq!(thread["markers"]["data"][0]["stack"], json!(1));
+    }
+}
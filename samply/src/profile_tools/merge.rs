@@ -0,0 +1,399 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use super::firefox::normalize_firefox_profile;
+use super::{
+    profile_time_range, remap_index_column, shift_counter_times, shift_thread_times, threads_mut,
+    Error,
+};
+
+/// How the timelines of the merged profiles are placed relative to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelineAlignment {
+    /// Keep the profiles' own start times, so that profiles which were
+    /// recorded at the same time (e.g. on different machines) overlap.
+    Absolute,
+    /// Move all profiles so that their first sample or marker is at the same time.
+    Aligned,
+    /// Place the profiles one after another.
+    Concatenated,
+}
+
+/// Merges multiple profiles into one.
+///
+/// The threads of all profiles are put into the same profile. Libraries,
+/// categories and marker schemas are de-duplicated, and conflicting pids and
+/// tids get a unique suffix. Profiles saved from the Firefox Profiler, e.g.
+/// a Firefox profile which was recorded at the same time as a samply
+/// profile, are converted to samply's layout first.
+pub fn merge_profiles(profiles: Vec<Value>, alignment: TimelineAlignment) -> Result<Value, Error> {
+    let mut profiles = profiles.into_iter();
+    let Some(mut merged) = profiles.next() else {
+        return Err(Error::InvalidProfile("no profiles to merge"));
+    };
+    normalize_firefox_profile(&mut merged)?;
+    let mut merger = ProfileMerger::new(&mut merged)?;
+    for mut profile in profiles {
+        normalize_firefox_profile(&mut profile)?;
+        merger.add_profile(&mut merged, profile, alignment)?;
+    }
+    Ok(merged)
+}
+
+struct ProfileMerger {
+    /// The value of `meta.startTime` in the merged profile.
+    start_time: f64,
+    /// The end of the timeline of the merged profile so far, relative to `start_time`.
+    end_time: f64,
+    /// The first timestamp of the first profile, relative to `start_time`.
+    first_time: f64,
+    /// (debugName, breakpadId) -> index in the merged libs array
+    lib_indexes: HashMap<(String, String), usize>,
+    used_pids: HashSet<String>,
+    used_tids: HashSet<String>,
+}
+
+impl ProfileMerger {
+    fn new(first_profile: &mut Value) -> Result<Self, Error> {
+        let start_time = first_profile["meta"]["startTime"].as_f64().unwrap_or(0.0);
+        let (first_time, end_time) = profile_time_range(first_profile).unwrap_or((0.0, 0.0));
+        let lib_indexes = first_profile["libs"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(i, lib)| (lib_key(lib), i))
+            .collect();
+        let mut used_pids = HashSet::new();
+        let mut used_tids = HashSet::new();
+        for thread in threads_mut(first_profile)?.iter() {
+            used_pids.insert(id_string(&thread["pid"]));
+            used_tids.insert(id_string(&thread["tid"]));
+        }
+        Ok(Self {
+            start_time,
+            end_time,
+            first_time,
+            lib_indexes,
+            used_pids,
+            used_tids,
+        })
+    }
+
+    fn add_profile(
+        &mut self,
+        merged: &mut Value,
+        mut profile: Value,
+        alignment: TimelineAlignment,
+    ) -> Result<(), Error> {
+        let start_time = profile["meta"]["startTime"].as_f64().unwrap_or(0.0);
+        let (first_time, end_time) = profile_time_range(&profile).unwrap_or((0.0, 0.0));
+        let time_delta = match alignment {
+            TimelineAlignment::Absolute => start_time - self.start_time,
+            TimelineAlignment::Aligned => self.first_time - first_time,
+            TimelineAlignment::Concatenated => self.end_time - first_time,
+        };
+        self.end_time = self.end_time.max(end_time + time_delta);
+
+        let lib_index_map = self.merge_libs(merged, &profile);
+        let category_map = merge_categories(merged, &profile);
+        merge_marker_schemas(merged, &profile);
+        merge_products(merged, &profile);
+
+        let thread_index_offset = threads_mut(merged)?.len();
+        let mut pid_map = HashMap::new();
+        let mut threads = std::mem::take(threads_mut(&mut profile)?);
+        for thread in &mut threads {
+            shift_thread_times(thread, time_delta);
+            remap_thread_libs(thread, &lib_index_map);
+            category_map.remap_thread(thread);
+
+            let pid = id_string(&thread["pid"]);
+            let new_pid = pid_map
+                .entry(pid.clone())
+                .or_insert_with(|| make_unique(&pid, &mut self.used_pids))
+                .clone();
+            thread["pid"] = new_pid.into();
+            let tid = id_string(&thread["tid"]);
+            thread["tid"] = make_unique(&tid, &mut self.used_tids).into();
+        }
+        threads_mut(merged)?.extend(threads);
+
+        if let Some(counters) = profile["counters"].as_array_mut() {
+            for counter in counters.iter_mut() {
+                shift_counter_times(counter, time_delta);
+                if let Some(index) = counter["mainThreadIndex"].as_u64() {
+                    counter["mainThreadIndex"] = (index as usize + thread_index_offset).into();
+                }
+                if let Some(new_pid) = pid_map.get(&id_string(&counter["pid"])) {
+                    counter["pid"] = new_pid.clone().into();
+                }
+            }
+            append_array(merged, "counters", counters);
+        }
+        if let Some(pages) = profile["pages"].as_array_mut() {
+            append_array(merged, "pages", pages);
+        }
+        if let Some(overhead) = profile["profilerOverhead"].as_array_mut() {
+            append_array(merged, "profilerOverhead", overhead);
+        }
+        Ok(())
+    }
+
+    /// Adds the libraries of `profile` to the merged libs array, and returns
+    /// the map from lib indexes in `profile` to lib indexes in `merged`.
+    fn merge_libs(&mut self, merged: &mut Value, profile: &Value) -> Vec<usize> {
+        let libs = profile["libs"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        if !merged["libs"].is_array() {
+            merged["libs"] = Value::Array(Vec::new());
+        }
+        let merged_libs = merged["libs"].as_array_mut().unwrap();
+        libs.iter()
+            .map(|lib| {
+                *self.lib_indexes.entry(lib_key(lib)).or_insert_with(|| {
+                    merged_libs.push(lib.clone());
+                    merged_libs.len() - 1
+                })
+            })
+            .collect()
+    }
+}
+
+fn lib_key(lib: &Value) -> (String, String) {
+    let debug_name = lib["debugName"].as_str().unwrap_or_default().to_owned();
+    let breakpad_id = lib["breakpadId"].as_str().unwrap_or_default().to_owned();
+    (debug_name, breakpad_id)
+}
+
+/// pids and tids are usually strings, but some profile producers use numbers.
+fn id_string(id: &Value) -> String {
+    match id {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Appends ".1" / ".2" etc. to the pid or tid if it has been used before.
+fn make_unique(id: &str, used_ids: &mut HashSet<String>) -> String {
+    let mut unique_id = id.to_owned();
+    let mut suffix = 1;
+    while used_ids.contains(&unique_id) {
+        unique_id = format!("{id}.{suffix}");
+        suffix += 1;
+    }
+    used_ids.insert(unique_id.clone());
+    unique_id
+}
+
+fn remap_thread_libs(thread: &mut Value, lib_index_map: &[usize]) {
+    if let Some(resource_table) = thread.get_mut("resourceTable") {
+        remap_index_column(resource_table, "lib", lib_index_map);
+    }
+    if let Some(native_symbols) = thread.get_mut("nativeSymbols") {
+        remap_index_column(native_symbols, "libIndex", lib_index_map);
+    }
+}
+
+fn append_array(merged: &mut Value, key: &str, values: &mut Vec<Value>) {
+    match merged[key].as_array_mut() {
+        Some(array) => array.append(values),
+        None => merged[key] = Value::Array(std::mem::take(values)),
+    }
+}
+
+fn merge_marker_schemas(merged: &mut Value, profile: &Value) {
+    let Some(schemas) = profile["meta"]["markerSchema"].as_array() else {
+        return;
+    };
+    if !merged["meta"]["markerSchema"].is_array() {
+        merged["meta"]["markerSchema"] = Value::Array(Vec::new());
+    }
+    let merged_schemas = merged["meta"]["markerSchema"].as_array_mut().unwrap();
+    for schema in schemas {
+        if !merged_schemas.iter().any(|s| s["name"] == schema["name"]) {
+            merged_schemas.push(schema.clone());
+        }
+    }
+}
+
+fn merge_products(merged: &mut Value, profile: &Value) {
+    let Some(product) = profile["meta"]["product"].as_str() else {
+        return;
+    };
+    let merged_product = merged["meta"]["product"].as_str().unwrap_or_default();
+    if !merged_product.split(" + ").any(|p| p == product) {
+        merged["meta"]["product"] = format!("{merged_product} + {product}").into();
+    }
+}
+
+/// Maps category and subcategory indexes of an added profile to the indexes
+/// in the merged profile. Categories are matched by name.
+struct CategoryMap {
+    categories: Vec<usize>,
+    /// Indexed by the category index in the added profile.
+    subcategories: Vec<Vec<usize>>,
+}
+
+fn merge_categories(merged: &mut Value, profile: &Value) -> CategoryMap {
+    let categories = profile["meta"]["categories"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or(&[]);
+    if !merged["meta"]["categories"].is_array() {
+        merged["meta"]["categories"] = Value::Array(Vec::new());
+    }
+    let merged_categories = merged["meta"]["categories"].as_array_mut().unwrap();
+
+    let mut category_map = CategoryMap {
+        categories: Vec::new(),
+        subcategories: Vec::new(),
+    };
+    for category in categories {
+        let index = match merged_categories
+            .iter()
+            .position(|c| c["name"] == category["name"])
+        {
+            Some(index) => index,
+            None => {
+                let mut new_category = category.clone();
+                new_category["subcategories"] = Value::Array(Vec::new());
+                merged_categories.push(new_category);
+                merged_categories.len() - 1
+            }
+        };
+        if !merged_categories[index]["subcategories"].is_array() {
+            merged_categories[index]["subcategories"] = Value::Array(Vec::new());
+        }
+        let merged_subcategories = merged_categories[index]["subcategories"]
+            .as_array_mut()
+            .unwrap();
+        let subcategory_map = category["subcategories"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(
+                |subcategory| match merged_subcategories.iter().position(|s| s == subcategory) {
+                    Some(index) => index,
+                    None => {
+                        merged_subcategories.push(subcategory.clone());
+                        merged_subcategories.len() - 1
+                    }
+                },
+            )
+            .collect();
+        category_map.categories.push(index);
+        category_map.subcategories.push(subcategory_map);
+    }
+    category_map
+}
+
+impl CategoryMap {
+    fn remap_thread(&self, thread: &mut Value) {
+        for table_name in ["frameTable", "stackTable"] {
+            if let Some(table) = thread.get_mut(table_name) {
+                self.remap_table(table);
+            }
+        }
+        if let Some(markers) = thread.get_mut("markers") {
+            remap_index_column(markers, "category", &self.categories);
+        }
+    }
+
+    /// Remaps a table with a category and a subcategory column. The subcategory
+    /// index is relative to the category, so it needs to be remapped first.
+    fn remap_table(&self, table: &mut Value) {
+        let categories: Vec<Option<usize>> = table["category"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|c| c.as_u64().map(|c| c as usize))
+            .collect();
+        if let Some(subcategories) = table.get_mut("subcategory").and_then(Value::as_array_mut) {
+            for (subcategory, category) in subcategories.iter_mut().zip(&categories) {
+                let (Some(index), Some(category)) = (subcategory.as_u64(), category) else {
+                    continue;
+                };
+                if let Some(&new_index) = self
+                    .subcategories
+                    .get(*category)
+                    .and_then(|map| map.get(index as usize))
+                {
+                    *subcategory = new_index.into();
+                }
+            }
+        }
+        remap_index_column(table, "category", &self.categories);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn thread(pid: &str, time: f64, lib: u64, category: u64) -> Value {
+        json!({
+            "pid": pid,
+            "tid": pid,
+            "registerTime": time,
+            "samples": { "length": 1, "time": [time], "stack": [0] },
+            "markers": { "length": 0, "startTime": [], "endTime": [], "category": [] },
+            "resourceTable": { "length": 1, "lib": [lib] },
+            "frameTable": { "length": 1, "category": [category], "subcategory": [0] },
+            "stackTable": { "length": 1, "category": [category], "subcategory": [0] },
+        })
+    }
+
+    fn profile(start_time: f64, libs: Value, categories: Value, threads: Vec<Value>) -> Value {
+        json!({
+            "meta": { "startTime": start_time, "product": "app", "categories": categories },
+            "libs": libs,
+            "threads": threads,
+            "counters": [],
+        })
+    }
+
+    #[test]
+    fn merge_dedups_libs_and_categories() {
+        let libs_a = json!([{ "debugName": "a.so", "breakpadId": "A" }]);
+        let libs_b = json!([
+            { "debugName": "b.so", "breakpadId": "B" },
+            { "debugName": "a.so", "breakpadId": "A" }
+        ]);
+        let categories_a = json!([{ "name": "Other", "subcategories": ["Other"] }]);
+        let categories_b = json!([
+            { "name": "User", "subcategories": ["Other"] },
+            { "name": "Other", "subcategories": ["Other"] }
+        ]);
+        let a = profile(1000.0, libs_a, categories_a, vec![thread("1", 5.0, 0, 0)]);
+        let b = profile(1500.0, libs_b, categories_b, vec![thread("1", 5.0, 1, 0)]);
+
+        let merged = merge_profiles(vec![a, b], TimelineAlignment::Absolute).unwrap();
+        assert_eq!(merged["libs"].as_array().unwrap().len(), 2);
+        assert_eq!(merged["meta"]["categories"].as_array().unwrap().len(), 2);
+
+        let threads = merged["threads"].as_array().unwrap();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[1]["pid"], "1.1");
+        assert_eq!(threads[1]["resourceTable"]["lib"], json!([0]));
+        assert_eq!(threads[1]["frameTable"]["category"], json!([1]));
+        assert_eq!(threads[1]["samples"]["time"], json!([505.0]));
+    }
+
+    #[test]
+    fn merge_concatenated() {
+        let categories = json!([{ "name": "Other", "subcategories": ["Other"] }]);
+        let a = profile(
+            0.0,
+            json!([]),
+            categories.clone(),
+            vec![thread("1", 10.0, 0, 0), thread("2", 30.0, 0, 0)],
+        );
+        let b = profile(0.0, json!([]), categories, vec![thread("3", 100.0, 0, 0)]);
+        let merged = merge_profiles(vec![a, b], TimelineAlignment::Concatenated).unwrap();
+        assert_eq!(merged["threads"][2]["samples"]["time"], json!([30.0]));
+        assert_eq!(merged["threads"][2]["registerTime"], json!(30.0));
+    }
+}
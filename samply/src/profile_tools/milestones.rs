@@ -0,0 +1,304 @@
+use std::str::FromStr;
+
+use fxprof_processed_profile::{
+    MarkerDynamicField, MarkerFieldFormat, MarkerLocation, MarkerSchema, MarkerSchemaField,
+    ProfilerMarker,
+};
+use serde_json::{json, Value};
+
+use super::{add_marker_schema, column_values, push_thread_marker, Error};
+
+/// A named point in time which is interesting for comparisons across runs,
+/// e.g. when `main` starts running or when the first frame is painted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Milestone {
+    pub name: String,
+    pub trigger: MilestoneTrigger,
+}
+
+/// What marks the time of a milestone in a process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MilestoneTrigger {
+    /// The first sample whose stack contains the function with this name.
+    FirstSampleInFunction(String),
+    /// The first marker with one of these names.
+    FirstMarker(Vec<String>),
+}
+
+impl FromStr for Milestone {
+    type Err = String;
+
+    /// Parses `NAME=func:FUNCTION` or `NAME=marker:MARKER`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, trigger) = s.split_once('=').ok_or_else(|| {
+            format!("Expected NAME=func:FUNCTION or NAME=marker:MARKER, got {s:?}")
+        })?;
+        let trigger = if let Some(function) = trigger.strip_prefix("func:") {
+            MilestoneTrigger::FirstSampleInFunction(function.to_owned())
+        } else if let Some(marker) = trigger.strip_prefix("marker:") {
+            MilestoneTrigger::FirstMarker(vec![marker.to_owned()])
+        } else {
+            return Err(format!(
+                "Unknown milestone trigger {trigger:?}, expected func:FUNCTION or marker:MARKER"
+            ));
+        };
+        Ok(Milestone {
+            name: name.to_owned(),
+            trigger,
+        })
+    }
+}
+
+/// Returns the milestones of a preset, or `None` if there's no preset with this name.
+///
+///  - `main`: The time from process start (exec) until `main` runs.
+///  - `first-paint`: The time until the first "FirstPaint" or
+///    "FirstContentfulPaint" marker, as emitted by browsers.
+pub fn preset_milestones(preset: &str) -> Option<Vec<Milestone>> {
+    let milestone = match preset {
+        "main" => Milestone {
+            name: "main".to_owned(),
+            trigger: MilestoneTrigger::FirstSampleInFunction("main".to_owned()),
+        },
+        "first-paint" => Milestone {
+            name: "first-paint".to_owned(),
+            trigger: MilestoneTrigger::FirstMarker(vec![
+                "FirstPaint".to_owned(),
+                "FirstContentfulPaint".to_owned(),
+            ]),
+        },
+        _ => return None,
+    };
+    Some(vec![milestone])
+}
+
+/// A milestone which was reached in one of the profile's processes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReachedMilestone {
+    pub name: String,
+    pub pid: String,
+    pub process_name: String,
+    /// The thread in which the milestone was reached.
+    pub thread_index: usize,
+    /// The process start time, in milliseconds relative to `meta.startTime`.
+    pub process_start: f64,
+    /// The time of the milestone, in milliseconds relative to `meta.startTime`.
+    pub time: f64,
+}
+
+/// Finds the first time each milestone is reached, in each process.
+///
+/// Function names are only known if the profile is symbolicated, or for
+/// JIT code. The returned milestones are sorted by time.
+pub fn find_milestones(profile: &Value, milestones: &[Milestone]) -> Vec<ReachedMilestone> {
+    let mut reached: Vec<ReachedMilestone> = Vec::new();
+    let threads = profile["threads"].as_array().into_iter().flatten();
+    for (thread_index, thread) in threads.enumerate() {
+        let pid = match &thread["pid"] {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        for milestone in milestones {
+            let time = match &milestone.trigger {
+                MilestoneTrigger::FirstSampleInFunction(function) => {
+                    first_sample_in_function(thread, function)
+                }
+                MilestoneTrigger::FirstMarker(names) => first_marker(thread, names),
+            };
+            let Some(time) = time else {
+                continue;
+            };
+            let existing = reached
+                .iter_mut()
+                .find(|r| r.name == milestone.name && r.pid == pid);
+            match existing {
+                Some(existing) if existing.time <= time => {}
+                Some(existing) => {
+                    existing.time = time;
+                    existing.thread_index = thread_index;
+                }
+                None => reached.push(ReachedMilestone {
+                    name: milestone.name.clone(),
+                    pid: pid.clone(),
+                    process_name: thread["processName"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_owned(),
+                    thread_index,
+                    process_start: thread["processStartupTime"]
+                        .as_f64()
+                        .or(thread["registerTime"].as_f64())
+                        .unwrap_or(time),
+                    time,
+                }),
+            }
+        }
+    }
+    reached.sort_by(|a, b| a.time.total_cmp(&b.time));
+    reached
+}
+
+/// Whether a (possibly demangled) function name refers to `function`, e.g.
+/// "main", "app::main" and "main(int, char**)" all match "main".
+fn function_name_matches(name: &str, function: &str) -> bool {
+    let Some(rest) = name
+        .strip_prefix(function)
+        .or_else(|| name.split("::").last()?.strip_prefix(function))
+    else {
+        return false;
+    };
+    rest.is_empty() || rest.starts_with('(')
+}
+
+fn first_sample_in_function(thread: &Value, function: &str) -> Option<f64> {
+    let strings = thread["stringArray"].as_array()?;
+    let matching_funcs: Vec<bool> = column_values(thread.get("funcTable"), "name")
+        .map(|name| {
+            let name = name
+                .as_u64()
+                .and_then(|i| strings.get(i as usize)?.as_str());
+            name.is_some_and(|name| function_name_matches(name, function))
+        })
+        .collect();
+    let matching_frames: Vec<bool> = column_values(thread.get("frameTable"), "func")
+        .map(|func| {
+            func.as_u64()
+                .is_some_and(|f| matching_funcs.get(f as usize) == Some(&true))
+        })
+        .collect();
+
+    // Prefixes always come before the stacks which use them.
+    let mut matching_stacks: Vec<bool> = Vec::new();
+    let stack_table = thread.get("stackTable");
+    let frames = column_values(stack_table, "frame");
+    let prefixes = column_values(stack_table, "prefix");
+    for (frame, prefix) in frames.zip(prefixes) {
+        let frame_matches = frame
+            .as_u64()
+            .is_some_and(|f| matching_frames.get(f as usize) == Some(&true));
+        let prefix_matches = prefix
+            .as_u64()
+            .is_some_and(|p| matching_stacks.get(p as usize) == Some(&true));
+        matching_stacks.push(frame_matches || prefix_matches);
+    }
+
+    let samples = thread.get("samples");
+    column_values(samples, "stack")
+        .zip(column_values(samples, "time"))
+        .find(|(stack, _)| {
+            stack
+                .as_u64()
+                .is_some_and(|s| matching_stacks.get(s as usize) == Some(&true))
+        })
+        .and_then(|(_, time)| time.as_f64())
+}
+
+fn first_marker(thread: &Value, names: &[String]) -> Option<f64> {
+    let strings = thread["stringArray"].as_array()?;
+    let markers = thread.get("markers");
+    column_values(markers, "name")
+        .zip(column_values(markers, "startTime"))
+        .filter(|(name, _)| {
+            let name = name
+                .as_u64()
+                .and_then(|i| strings.get(i as usize)?.as_str());
+            name.is_some_and(|name| names.iter().any(|n| n == name))
+        })
+        .filter_map(|(_, time)| time.as_f64())
+        .min_by(f64::total_cmp)
+}
+
+/// Adds an interval marker from process start to each reached milestone.
+pub fn add_milestone_markers(
+    profile: &mut Value,
+    reached: &[ReachedMilestone],
+) -> Result<(), Error> {
+    let category = profile["meta"]["categories"]
+        .as_array()
+        .and_then(|categories| categories.iter().position(|c| c["name"] == "Other"))
+        .unwrap_or(0);
+    for milestone in reached {
+        let thread = profile["threads"]
+            .get_mut(milestone.thread_index)
+            .ok_or(Error::InvalidProfile("milestone in unknown thread"))?;
+        let data = MilestoneMarker {
+            duration: milestone.time - milestone.process_start,
+        }
+        .json_marker_data();
+        push_thread_marker(
+            thread,
+            &milestone.name,
+            category,
+            data,
+            milestone.process_start,
+            Some(milestone.time),
+        );
+    }
+    add_marker_schema(profile, MilestoneMarker::schema());
+    Ok(())
+}
+
+struct MilestoneMarker {
+    /// The time since process start, in milliseconds.
+    duration: f64,
+}
+
+impl ProfilerMarker for MilestoneMarker {
+    const MARKER_TYPE_NAME: &'static str = "Milestone";
+
+    fn json_marker_data(&self) -> Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "sinceProcessStart": self.duration,
+        })
+    }
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![
+                MarkerLocation::MarkerChart,
+                MarkerLocation::MarkerTable,
+                MarkerLocation::TimelineOverview,
+            ],
+            chart_label: Some("{marker.name}"),
+            tooltip_label: Some("{marker.name} after {marker.data.sinceProcessStart}"),
+            table_label: Some("{marker.name} after {marker.data.sinceProcessStart}"),
+            fields: vec![MarkerSchemaField::Dynamic(MarkerDynamicField {
+                key: "sinceProcessStart",
+                label: "Time since process start",
+                format: MarkerFieldFormat::Milliseconds,
+                searchable: false,
+            })],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_main_and_marker_milestones() {
+        let profile = json!({
+            "threads": [{
+                "pid": "10",
+                "processName": "app",
+                "processStartupTime": 2.0,
+                "stringArray": ["_start", "app::main", "FirstPaint"],
+                "funcTable": { "length": 2, "name": [0, 1] },
+                "frameTable": { "length": 2, "func": [0, 1] },
+                "stackTable": { "length": 2, "frame": [0, 1], "prefix": [null, 0] },
+                "samples": { "length": 3, "stack": [0, 1, 1], "time": [3.0, 5.0, 6.0] },
+                "markers": { "length": 1, "name": [2], "startTime": [9.0], "endTime": [null] },
+            }],
+        });
+        let mut milestones = preset_milestones("main").unwrap();
+        milestones.push("paint=marker:FirstPaint".parse().unwrap());
+        let reached = find_milestones(&profile, &milestones);
+        assert_eq!(reached.len(), 2);
+        assert_eq!((reached[0].name.as_str(), reached[0].time), ("main", 5.0));
+        assert_eq!((reached[1].name.as_str(), reached[1].time), ("paint", 9.0));
+        assert_eq!(reached[1].process_start, 2.0);
+    }
+}
@@ -0,0 +1,539 @@
+//! Tools which operate on existing profile files in the Firefox Profiler's
+//! processed profile format, i.e. on the files written by `samply record`
+//! and `samply import`.
+//!
+//! These tools work on the profile JSON directly, via [`serde_json::Value`],
+//! so that they preserve any properties they don't know about.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::Command;
+
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use fxprof_processed_profile::MarkerSchema;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::history::{History, HistoryEntry};
+
+mod allocations;
+mod call_tree;
+mod categories;
+mod chrome_trace;
+mod collapsed;
+mod diff;
+mod downsample;
+mod export;
+mod firefox;
+mod fold;
+mod merge;
+mod milestones;
+mod report;
+mod scrub;
+mod speedscope;
+mod symbolicate;
+mod trim;
+mod upload;
+mod weights;
+
+pub use allocations::{write_collapsed_allocations, write_leak_report, AllocationWeight};
+pub use call_tree::{CallTree, FunctionSummary};
+pub use categories::{apply_category_rules, CategoryRules};
+pub use chrome_trace::write_chrome_trace;
+pub use collapsed::write_collapsed_stacks;
+pub use diff::diff_profiles;
+pub use downsample::reduce_profile_size;
+pub use export::{Exporter, ExporterRegistry};
+pub use fold::fold_frames;
+pub use merge::{merge_profiles, TimelineAlignment};
+pub use milestones::{add_milestone_markers, find_milestones, preset_milestones, Milestone};
+pub use report::{summarize_profile, write_report, ProfileSummary, SummaryThresholds};
+pub use scrub::{scrub_profile, ScrubOptions};
+pub use speedscope::write_speedscope_profile;
+pub use symbolicate::symbolicate_profile;
+pub use trim::trim_profile;
+pub use upload::upload_profile;
+pub use weights::swap_sample_weights;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid profile: {0}")]
+    InvalidProfile(&'static str),
+
+    #[error("Upload failed: {0}")]
+    Upload(String),
+
+    #[error("Line {0} of the category rules is invalid: {1}")]
+    InvalidCategoryRule(usize, String),
+}
+
+/// Reads a profile JSON file. Files with a .gz extension are decompressed.
+pub fn read_profile(path: &Path) -> Result<Value, Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let profile = if path.extension() == Some(OsStr::new("gz")) {
+        serde_json::from_reader(BufReader::new(GzDecoder::new(reader)))?
+    } else {
+        serde_json::from_reader(reader)?
+    };
+    Ok(profile)
+}
+
+/// Writes a profile JSON file. If the path has a .gz extension, the output
+/// is compressed.
+pub fn write_profile(path: &Path, profile: &Value) -> Result<(), Error> {
+    let writer = BufWriter::new(File::create(path)?);
+    if path.extension() == Some(OsStr::new("gz")) {
+        let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+        serde_json::to_writer(&mut encoder, profile)?;
+        encoder.finish()?.flush()?;
+    } else {
+        let mut writer = writer;
+        serde_json::to_writer(&mut writer, profile)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// What to do with a profile file after samply has written it.
+#[derive(Debug, Clone, Default)]
+pub struct PostProcessingOptions {
+    /// Remove personal information from the profile.
+    pub scrub: Option<ScrubOptions>,
+    /// Downsample the profile if its JSON is larger than this many bytes.
+    pub max_output_size: Option<usize>,
+    /// Make the auxiliary sample weight the primary one.
+    pub swap_sample_weights: bool,
+    /// Put frames into categories according to these rules.
+    pub category_rules: Option<CategoryRules>,
+    /// Fold the frames whose name matches one of these patterns into their
+    /// caller.
+    pub fold_frames: Vec<Regex>,
+    /// Add the profile to the recording history, which `samply list` shows.
+    pub history_entry: Option<HistoryEntry>,
+    /// A shell command which is run with the path of the finished profile
+    /// as its last argument.
+    pub command: Option<String>,
+}
+
+impl PostProcessingOptions {
+    pub fn is_empty(&self) -> bool {
+        self.scrub.is_none()
+            && self.max_output_size.is_none()
+            && !self.swap_sample_weights
+            && self.category_rules.is_none()
+            && self.fold_frames.is_empty()
+    }
+}
+
+/// Applies the post-processing steps to a profile file which samply has just
+/// written, in place.
+pub fn post_process_profile_file(
+    path: &Path,
+    options: &PostProcessingOptions,
+) -> Result<(), Error> {
+    if let Some(entry) = &options.history_entry {
+        add_to_history(entry.finish(path));
+    }
+    if !options.is_empty() {
+        apply_post_processing(path, options)?;
+    }
+    if let Some(command) = &options.command {
+        run_post_processing_command(command, path);
+    }
+    Ok(())
+}
+
+fn apply_post_processing(path: &Path, options: &PostProcessingOptions) -> Result<(), Error> {
+    let mut profile = read_profile(path)?;
+    if options.swap_sample_weights && !swap_sample_weights(&mut profile) {
+        eprintln!("Warning: The profile has no auxiliary sample weights to swap with.");
+    }
+    if let Some(category_rules) = &options.category_rules {
+        apply_category_rules(&mut profile, category_rules);
+    }
+    fold_frames(&mut profile, &options.fold_frames)?;
+    if let Some(scrub_options) = &options.scrub {
+        scrub_profile(&mut profile, scrub_options);
+    }
+    if let Some(max_output_size) = options.max_output_size {
+        if let Some(reduction) = reduce_profile_size(&mut profile, max_output_size)? {
+            eprintln!(
+                "The profile was {} bytes, which is more than the limit of {max_output_size} bytes. \
+                 Kept one of every {} samples and merged rarely sampled stacks, \
+                 which reduced it to {} bytes.",
+                reduction.original_size, reduction.downsampling_factor, reduction.reduced_size
+            );
+        }
+    }
+    write_profile(path, &profile)
+}
+
+/// Runs the user's command for the finished profile. The profile is still
+/// served if the command fails, so failures are only reported.
+fn run_post_processing_command(command: &str, profile_path: &Path) {
+    let mut shell_command = if cfg!(windows) {
+        let mut shell_command = Command::new("cmd");
+        shell_command
+            .arg("/C")
+            .arg(format!("{command} \"{}\"", profile_path.display()));
+        shell_command
+    } else {
+        // The path is passed as $1, so that it doesn't need to be quoted.
+        let mut shell_command = Command::new("sh");
+        shell_command
+            .arg("-c")
+            .arg(format!("{command} \"$1\""))
+            .arg("samply")
+            .arg(profile_path);
+        shell_command
+    };
+    shell_command.env("SAMPLY_PROFILE", profile_path);
+    match shell_command.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("Warning: The post-processing command failed ({status})."),
+        Err(err) => eprintln!("Warning: Could not run the post-processing command: {err}"),
+    }
+}
+
+fn add_to_history(entry: HistoryEntry) {
+    let result = match History::open() {
+        Some(history) => history.add(entry),
+        None => return,
+    };
+    if let Err(err) = result {
+        eprintln!("Warning: Could not add the profile to the recording history: {err}");
+    }
+}
+
+/// Returns the threads array of the profile.
+pub fn threads_mut(profile: &mut Value) -> Result<&mut Vec<Value>, Error> {
+    profile
+        .get_mut("threads")
+        .and_then(Value::as_array_mut)
+        .ok_or(Error::InvalidProfile("missing threads array"))
+}
+
+/// Returns the column with the given name of a struct-of-arrays table,
+/// such as a thread's `samples` or `frameTable`.
+pub fn column_mut<'a>(table: &'a mut Value, column_name: &str) -> Option<&'a mut Vec<Value>> {
+    table.get_mut(column_name).and_then(Value::as_array_mut)
+}
+
+/// Replaces every non-null value in a table column with `f(value)`.
+pub fn map_column(table: &mut Value, column_name: &str, mut f: impl FnMut(&Value) -> Value) {
+    if let Some(column) = column_mut(table, column_name) {
+        for value in column.iter_mut().filter(|v| !v.is_null()) {
+            *value = f(value);
+        }
+    }
+}
+
+/// Remaps the index values in a table column. Null values stay null.
+pub fn remap_index_column(table: &mut Value, column_name: &str, index_map: &[usize]) {
+    map_column(table, column_name, |value| match value.as_u64() {
+        Some(index) => index_map
+            .get(index as usize)
+            .map_or(Value::Null, |&new_index| new_index.into()),
+        None => value.clone(),
+    });
+}
+
+/// The stack columns of the tables which refer to the stack table.
+pub const STACK_REFERENCES: &[(&str, &str)] = &[
+    ("samples", "stack"),
+    ("nativeAllocations", "stack"),
+    ("jsAllocations", "stack"),
+];
+
+/// Remaps the stack indexes in the samples, allocations and marker stacks
+/// of the thread, after its stack table was changed.
+pub fn remap_stack_references(thread: &mut Value, index_map: &[usize]) {
+    for (table_name, column_name) in STACK_REFERENCES {
+        if let Some(table) = thread.get_mut(*table_name) {
+            remap_index_column(table, column_name, index_map);
+        }
+    }
+    if let Some(datas) = column_mut(&mut thread["markers"], "data") {
+        for data in datas.iter_mut().filter(|d| d.is_object()) {
+            if let Some(stack) = data.get_mut("stack") {
+                remap_index(stack, index_map);
+            }
+            if let Some(stack) = data.get_mut("cause").and_then(|c| c.get_mut("stack")) {
+                remap_index(stack, index_map);
+            }
+        }
+    }
+}
+
+fn remap_index(value: &mut Value, index_map: &[usize]) {
+    if let Some(index) = value.as_u64() {
+        *value = index_map
+            .get(index as usize)
+            .map_or(Value::Null, |&new_index| new_index.into());
+    }
+}
+
+/// Keeps only the rows of a struct-of-arrays table for which `keep` is true,
+/// and updates the table's length.
+pub fn filter_table_rows(table: &mut Value, keep: &[bool]) {
+    let Some(columns) = table.as_object_mut() else {
+        return;
+    };
+    for column in columns.values_mut() {
+        if let Some(values) = column.as_array_mut().filter(|v| v.len() == keep.len()) {
+            let mut keep = keep.iter();
+            values.retain(|_| *keep.next().unwrap());
+        }
+    }
+    table["length"] = keep.iter().filter(|k| **k).count().into();
+}
+
+/// Removes the threads for which `f` returns false, and updates the thread
+/// indexes in the counters. Counters of removed threads are removed too.
+pub fn retain_threads(profile: &mut Value, mut f: impl FnMut(&Value) -> bool) -> Result<(), Error> {
+    let threads = threads_mut(profile)?;
+    let mut new_thread_indexes = Vec::with_capacity(threads.len());
+    let mut next_index = 0;
+    for thread in threads.iter() {
+        if f(thread) {
+            new_thread_indexes.push(Some(next_index));
+            next_index += 1;
+        } else {
+            new_thread_indexes.push(None);
+        }
+    }
+    let mut new_thread_indexes_iter = new_thread_indexes.iter();
+    threads.retain(|_| new_thread_indexes_iter.next().unwrap().is_some());
+
+    if let Some(counters) = profile["counters"].as_array_mut() {
+        counters.retain_mut(|counter| {
+            let Some(index) = counter["mainThreadIndex"].as_u64() else {
+                return true;
+            };
+            match new_thread_indexes.get(index as usize).copied().flatten() {
+                Some(new_index) => {
+                    counter["mainThreadIndex"] = new_index.into();
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Returns the index of `s` in the thread's string array, adding it if needed.
+pub fn intern_thread_string(thread: &mut Value, s: &str) -> usize {
+    if !thread["stringArray"].is_array() {
+        thread["stringArray"] = Value::Array(Vec::new());
+    }
+    let strings = thread["stringArray"].as_array_mut().unwrap();
+    match strings.iter().position(|existing| existing == s) {
+        Some(index) => index,
+        None => {
+            strings.push(s.into());
+            strings.len() - 1
+        }
+    }
+}
+
+/// Appends a marker to the thread's marker table. Markers without an end
+/// time are instant markers.
+pub fn push_thread_marker(
+    thread: &mut Value,
+    name: &str,
+    category: usize,
+    data: Value,
+    start_time: f64,
+    end_time: Option<f64>,
+) {
+    let name = intern_thread_string(thread, name);
+    let phase = match end_time {
+        Some(_) => 1, // interval
+        None => 0,    // instant
+    };
+    let markers = &mut thread["markers"];
+    for (column_name, value) in [
+        ("category", category.into()),
+        ("data", data),
+        ("name", name.into()),
+        ("startTime", start_time.into()),
+        ("endTime", end_time.into()),
+        ("phase", phase.into()),
+    ] {
+        if !markers[column_name].is_array() {
+            markers[column_name] = Value::Array(Vec::new());
+        }
+        markers[column_name].as_array_mut().unwrap().push(value);
+    }
+    let length = markers["length"].as_u64().unwrap_or(0);
+    markers["length"] = (length + 1).into();
+}
+
+/// Adds a marker schema to `meta.markerSchema`, unless there already is a
+/// schema for this marker type.
+pub fn add_marker_schema(profile: &mut Value, schema: MarkerSchema) {
+    if !profile["meta"]["markerSchema"].is_array() {
+        profile["meta"]["markerSchema"] = Value::Array(Vec::new());
+    }
+    let schemas = profile["meta"]["markerSchema"].as_array_mut().unwrap();
+    if !schemas.iter().any(|s| s["name"] == schema.type_name) {
+        schemas.push(serde_json::to_value(schema).expect("schemas are serializable"));
+    }
+}
+
+/// Adds `delta_ms` to a timestamp value. Null values stay null.
+fn shift_timestamp(value: &mut Value, delta_ms: f64) {
+    if let Some(time) = value.as_f64() {
+        *value = (time + delta_ms).into();
+    }
+}
+
+/// Moves everything in this thread by `delta_ms` on the timeline.
+pub fn shift_thread_times(thread: &mut Value, delta_ms: f64) {
+    if delta_ms == 0.0 {
+        return;
+    }
+    for key in [
+        "registerTime",
+        "unregisterTime",
+        "processStartupTime",
+        "processShutdownTime",
+    ] {
+        if let Some(value) = thread.get_mut(key) {
+            shift_timestamp(value, delta_ms);
+        }
+    }
+    for table_name in ["samples", "nativeAllocations", "jsAllocations"] {
+        let Some(table) = thread.get_mut(table_name) else {
+            continue;
+        };
+        if let Some(times) = column_mut(table, "time") {
+            times.iter_mut().for_each(|t| shift_timestamp(t, delta_ms));
+        } else if let Some(first_delta) =
+            column_mut(table, "timeDeltas").and_then(|deltas| deltas.first_mut())
+        {
+            // With delta-encoded times, only the first entry is absolute.
+            shift_timestamp(first_delta, delta_ms);
+        }
+    }
+    if let Some(markers) = thread.get_mut("markers") {
+        for column_name in ["startTime", "endTime"] {
+            if let Some(times) = column_mut(markers, column_name) {
+                times.iter_mut().for_each(|t| shift_timestamp(t, delta_ms));
+            }
+        }
+    }
+}
+
+/// Moves all samples of this counter by `delta_ms` on the timeline.
+pub fn shift_counter_times(counter: &mut Value, delta_ms: f64) {
+    if let Some(times) = counter
+        .get_mut("samples")
+        .and_then(|samples| column_mut(samples, "time"))
+    {
+        times.iter_mut().for_each(|t| shift_timestamp(t, delta_ms));
+    }
+}
+
+/// Iterates over the values of a table column, if the table and the column exist.
+pub fn column_values<'a>(
+    table: Option<&'a Value>,
+    column_name: &str,
+) -> impl Iterator<Item = &'a Value> {
+    table
+        .and_then(|table| table.get(column_name))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+/// Returns the earliest and latest timestamp of any sample or marker in
+/// the profile, in milliseconds relative to `meta.startTime`.
+pub fn profile_time_range(profile: &Value) -> Option<(f64, f64)> {
+    let threads = profile["threads"].as_array().into_iter().flatten();
+    let counters = profile["counters"].as_array().into_iter().flatten();
+    let thread_times = threads.flat_map(|thread| {
+        let markers = thread.get("markers");
+        column_values(thread.get("samples"), "time")
+            .chain(column_values(markers, "startTime"))
+            .chain(column_values(markers, "endTime"))
+    });
+    let counter_times = counters.flat_map(|counter| column_values(counter.get("samples"), "time"));
+    thread_times
+        .chain(counter_times)
+        .filter_map(Value::as_f64)
+        .fold(None, |range, t| match range {
+            Some((start, end)) => Some((f64::min(start, t), f64::max(end, t))),
+            None => Some((t, t)),
+        })
+}
+
+/// Returns the name of each frame in the thread's frame table: the name of
+/// its function, or its address if there's no function name.
+pub fn frame_names(thread: &Value) -> Vec<String> {
+    let strings = thread["stringArray"].as_array();
+    let func_names: Vec<Option<&str>> = column_values(thread.get("funcTable"), "name")
+        .map(|name| strings?.get(name.as_u64()? as usize)?.as_str())
+        .collect();
+    let frame_table = thread.get("frameTable");
+    let addresses: Vec<&Value> = column_values(frame_table, "address").collect();
+    column_values(frame_table, "func")
+        .enumerate()
+        .map(|(frame, func)| {
+            let func_name = func
+                .as_u64()
+                .and_then(|func| *func_names.get(func as usize)?);
+            match (func_name, addresses.get(frame).and_then(|a| a.as_i64())) {
+                (Some(name), _) => name.to_owned(),
+                (None, Some(address)) if address >= 0 => format!("0x{address:x}"),
+                (None, _) => "<unknown>".to_owned(),
+            }
+        })
+        .collect()
+}
+
+/// Returns the frames of a stack from the root to the leaf, given the
+/// `prefix` and `frame` columns of the thread's stack table.
+pub fn stack_frames(prefixes: &[&Value], frames: &[&Value], stack: usize) -> Vec<usize> {
+    let mut stack_frames = Vec::new();
+    let mut current = Some(stack);
+    while let Some(stack) = current {
+        if let Some(frame) = frames.get(stack).and_then(|f| f.as_u64()) {
+            stack_frames.push(frame as usize);
+        }
+        current = prefixes
+            .get(stack)
+            .and_then(|p| p.as_u64())
+            .map(|p| p as usize)
+            .filter(|p| *p < stack);
+    }
+    stack_frames.reverse();
+    stack_frames
+}
+
+/// Sums up the sample weights per stack, ordered by stack index. Samples
+/// without a stack are skipped, and samples without a weight count as 1.
+pub fn stack_sample_weights(thread: &Value) -> BTreeMap<usize, f64> {
+    let samples = thread.get("samples");
+    let weights: Vec<&Value> = column_values(samples, "weight").collect();
+    let mut stack_weights = BTreeMap::new();
+    for (i, stack) in column_values(samples, "stack").enumerate() {
+        let Some(stack) = stack.as_u64() else {
+            continue;
+        };
+        let weight = weights.get(i).and_then(|w| w.as_f64()).unwrap_or(1.0);
+        *stack_weights.entry(stack as usize).or_default() += weight;
+    }
+    stack_weights
+}
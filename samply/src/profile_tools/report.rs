@@ -0,0 +1,421 @@
+use std::io::Write;
+
+use serde_derive::Serialize;
+use serde_json::Value;
+
+use super::symbolicate::is_address_name;
+use super::{column_values, profile_time_range, CallTree, Error, FunctionSummary};
+
+/// Writes a plain text summary of the profile: the functions with the most
+/// self time across all threads, and per thread the sample count, the number
+/// of lost events and the functions with the most total time. If the profile
+/// has priority inversion markers, the longest ones are listed at the end.
+///
+/// `top` is the number of functions listed in each table.
+pub fn write_report(profile: &Value, top: usize, mut writer: impl Write) -> Result<(), Error> {
+    let threads: Vec<&Value> = profile["threads"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .collect();
+    let mut thread_summaries: Vec<ThreadSummary> = threads
+        .iter()
+        .map(|thread| ThreadSummary::new(thread))
+        .collect();
+    thread_summaries.sort_by_key(|t| std::cmp::Reverse(t.sample_count));
+    let sample_count: usize = thread_summaries.iter().map(|t| t.sample_count).sum();
+    let lost_events: u64 = thread_summaries.iter().map(|t| t.lost_events).sum();
+    let duration = profile_time_range(profile).map_or(0.0, |(start, end)| end - start);
+
+    writeln!(
+        writer,
+        "Profile of {}: {duration:.0} ms, sampled every {} ms",
+        profile["meta"]["product"].as_str().unwrap_or("unknown"),
+        profile["meta"]["interval"].as_f64().unwrap_or(1.0)
+    )?;
+    writeln!(
+        writer,
+        "Samples: {sample_count} in {} threads",
+        thread_summaries
+            .iter()
+            .filter(|t| t.sample_count > 0)
+            .count()
+    )?;
+    writeln!(writer, "Lost events: {lost_events}")?;
+    writeln!(writer)?;
+
+    let tree = CallTree::from_threads(threads.iter().copied());
+    writeln!(writer, "Top functions by self time, all threads:")?;
+    write_function_table(&mut writer, &tree.function_summaries(), tree.total(), top)?;
+
+    for summary in thread_summaries.iter().filter(|t| t.sample_count > 0) {
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "{}: {} samples ({:.1}%), {} lost events",
+            summary.label,
+            summary.sample_count,
+            summary.sample_count as f64 / sample_count as f64 * 100.0,
+            summary.lost_events
+        )?;
+        let tree = CallTree::from_threads([summary.thread]);
+        let mut functions = tree.function_summaries();
+        functions.sort_by(|a, b| b.total.total_cmp(&a.total));
+        write_function_table(&mut writer, &functions, tree.total(), top)?;
+    }
+
+    let mut priority_inversions: Vec<(&ThreadSummary, &PriorityInversion)> = thread_summaries
+        .iter()
+        .flat_map(|t| t.priority_inversions.iter().map(move |p| (t, p)))
+        .collect();
+    if !priority_inversions.is_empty() {
+        priority_inversions.sort_by(|a, b| b.1.duration.total_cmp(&a.1.duration));
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "Likely priority inversions: {}",
+            priority_inversions.len()
+        )?;
+        for (summary, inversion) in priority_inversions.iter().take(top) {
+            writeln!(
+                writer,
+                "  {:>8.1} ms  {} blocked until woken by {}, which was preempted for {:.1} ms",
+                inversion.duration, summary.label, inversion.waker, inversion.waker_preempted
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The machine-readable summary of a profile which `samply record --ci`
+/// prints, for performance regression checks in CI.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ProfileSummary {
+    pub duration_ms: f64,
+    pub samples: usize,
+    /// The number of threads with samples.
+    pub threads: usize,
+    pub lost_events: u64,
+    /// The fraction of the sample weight whose leaf frame has no function
+    /// name, between 0 and 1.
+    pub unsymbolicated_ratio: f64,
+    /// The functions with the most self time, across all threads.
+    pub top_functions: Vec<FunctionShare>,
+    /// The thresholds which the profile exceeds, as messages.
+    pub violations: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FunctionShare {
+    pub name: String,
+    pub self_percent: f64,
+    pub total_percent: f64,
+}
+
+/// Limits for a [`ProfileSummary`]. Each one which the profile exceeds adds
+/// a violation.
+#[derive(Debug, Clone, Default)]
+pub struct SummaryThresholds {
+    pub min_samples: Option<usize>,
+    pub max_lost_events: Option<u64>,
+    pub max_unsymbolicated_ratio: Option<f64>,
+    /// (function name, the maximum percentage of the samples which have the
+    /// function on the stack)
+    pub max_function_share: Vec<(String, f64)>,
+}
+
+/// Summarizes the profile and checks it against the thresholds. `top` is the
+/// number of functions in `top_functions`.
+pub fn summarize_profile(
+    profile: &Value,
+    top: usize,
+    thresholds: &SummaryThresholds,
+) -> ProfileSummary {
+    let threads: Vec<&Value> = profile["threads"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .collect();
+    let thread_summaries: Vec<ThreadSummary> = threads
+        .iter()
+        .map(|thread| ThreadSummary::new(thread))
+        .collect();
+    let tree = CallTree::from_threads(threads.iter().copied());
+    let functions = tree.function_summaries();
+    let total = tree.total();
+    let percent = |weight: f64| {
+        if total > 0.0 {
+            weight / total * 100.0
+        } else {
+            0.0
+        }
+    };
+    let unsymbolicated: f64 = functions
+        .iter()
+        .filter(|function| is_address_name(&function.name))
+        .map(|function| function.self_weight)
+        .sum();
+
+    let mut summary = ProfileSummary {
+        duration_ms: profile_time_range(profile).map_or(0.0, |(start, end)| end - start),
+        samples: thread_summaries.iter().map(|t| t.sample_count).sum(),
+        threads: thread_summaries
+            .iter()
+            .filter(|t| t.sample_count > 0)
+            .count(),
+        lost_events: thread_summaries.iter().map(|t| t.lost_events).sum(),
+        unsymbolicated_ratio: percent(unsymbolicated) / 100.0,
+        top_functions: functions
+            .iter()
+            .take(top)
+            .map(|function| FunctionShare {
+                name: function.name.clone(),
+                self_percent: percent(function.self_weight),
+                total_percent: percent(function.total),
+            })
+            .collect(),
+        violations: Vec::new(),
+    };
+
+    if let Some(min_samples) = thresholds.min_samples {
+        if summary.samples < min_samples {
+            summary.violations.push(format!(
+                "{} samples, fewer than the minimum of {min_samples}",
+                summary.samples
+            ));
+        }
+    }
+    if let Some(max_lost_events) = thresholds.max_lost_events {
+        if summary.lost_events > max_lost_events {
+            summary.violations.push(format!(
+                "{} lost events, more than the maximum of {max_lost_events}",
+                summary.lost_events
+            ));
+        }
+    }
+    if let Some(max_ratio) = thresholds.max_unsymbolicated_ratio {
+        if summary.unsymbolicated_ratio > max_ratio {
+            summary.violations.push(format!(
+                "{:.3} of the samples are unsymbolicated, more than the maximum of {max_ratio}",
+                summary.unsymbolicated_ratio
+            ));
+        }
+    }
+    for (name, max_percent) in &thresholds.max_function_share {
+        let share = functions
+            .iter()
+            .find(|function| function.name == *name)
+            .map_or(0.0, |function| percent(function.total));
+        if share > *max_percent {
+            summary.violations.push(format!(
+                "{name} is on the stack in {share:.1}% of the samples, more than the maximum of {max_percent}%"
+            ));
+        }
+    }
+    summary
+}
+
+struct ThreadSummary<'a> {
+    thread: &'a Value,
+    label: String,
+    sample_count: usize,
+    lost_events: u64,
+    priority_inversions: Vec<PriorityInversion>,
+}
+
+/// A "PriorityInversion" marker, with times in milliseconds.
+struct PriorityInversion {
+    duration: f64,
+    waker: String,
+    waker_preempted: f64,
+}
+
+impl<'a> ThreadSummary<'a> {
+    fn new(thread: &'a Value) -> Self {
+        let lost_events = column_values(thread.get("markers"), "data")
+            .filter(|data| data["type"] == "LostEvents")
+            .filter_map(|data| data["count"].as_u64())
+            .sum();
+        let markers = thread.get("markers");
+        let priority_inversions = column_values(markers, "data")
+            .zip(column_values(markers, "startTime"))
+            .zip(column_values(markers, "endTime"))
+            .filter(|((data, _), _)| data["type"] == "PriorityInversion")
+            .map(|((data, start), end)| PriorityInversion {
+                duration: end.as_f64().unwrap_or(0.0) - start.as_f64().unwrap_or(0.0),
+                waker: data["waker"].as_str().unwrap_or("unknown").to_owned(),
+                waker_preempted: data["wakerPreempted"].as_f64().unwrap_or(0.0),
+            })
+            .collect();
+        ThreadSummary {
+            thread,
+            label: format!(
+                "{} - {} ({})",
+                thread["processName"].as_str().unwrap_or("Process"),
+                thread["name"].as_str().unwrap_or("Thread"),
+                thread["tid"].as_str().unwrap_or_default()
+            ),
+            sample_count: column_values(thread.get("samples"), "stack").count(),
+            lost_events,
+            priority_inversions,
+        }
+    }
+}
+
+fn write_function_table(
+    writer: &mut impl Write,
+    functions: &[FunctionSummary],
+    total: f64,
+    top: usize,
+) -> Result<(), Error> {
+    let percent = |weight: f64| {
+        if total > 0.0 {
+            weight / total * 100.0
+        } else {
+            0.0
+        }
+    };
+    writeln!(writer, "   Self %   Total %  Function")?;
+    for function in functions.iter().take(top) {
+        writeln!(
+            writer,
+            "  {:>6.1}%  {:>7.1}%  {}",
+            percent(function.self_weight),
+            percent(function.total),
+            function.name
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn report_functions_and_lost_events() {
+        let profile = json!({
+            "meta": { "product": "app", "interval": 1.0 },
+            "threads": [{
+                "processName": "app",
+                "name": "main",
+                "tid": "7",
+                "stringArray": ["main", "work", "Lost events"],
+                "funcTable": { "length": 2, "name": [0, 1] },
+                "frameTable": { "length": 2, "func": [0, 1], "address": [-1, -1] },
+                "stackTable": { "length": 2, "frame": [0, 1], "prefix": [null, 0] },
+                "samples": { "length": 4, "stack": [1, 1, 1, 0], "time": [0.0, 1.0, 2.0, 3.0] },
+                "markers": {
+                    "length": 1,
+                    "name": [2],
+                    "startTime": [1.5],
+                    "endTime": [null],
+                    "data": [{ "type": "LostEvents", "count": 12 }],
+                },
+            }],
+        });
+        let mut output = Vec::new();
+        write_report(&profile, 1, &mut output).unwrap();
+        let expected = [
+            "Profile of app: 3 ms, sampled every 1 ms",
+            "Samples: 4 in 1 threads",
+            "Lost events: 12",
+            "",
+            "Top functions by self time, all threads:",
+            "   Self %   Total %  Function",
+            "    75.0%     75.0%  work",
+            "",
+            "app - main (7): 4 samples (100.0%), 12 lost events",
+            "   Self %   Total %  Function",
+            "    25.0%    100.0%  main",
+        ];
+        assert_eq!(
+            String::from_utf8(output)
+                .unwrap()
+                .lines()
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn summary_thresholds() {
+        let profile = json!({
+            "meta": { "product": "app", "interval": 1.0 },
+            "threads": [{
+                "stringArray": ["main", "work", "0x1234"],
+                "funcTable": { "length": 3, "name": [0, 1, 2] },
+                "frameTable": { "length": 3, "func": [0, 1, 2], "address": [-1, -1, 4660] },
+                "stackTable": { "length": 3, "frame": [0, 1, 2], "prefix": [null, 0, 0] },
+                "samples": { "length": 4, "stack": [1, 1, 1, 2], "time": [0.0, 1.0, 2.0, 3.0] },
+            }],
+        });
+        let thresholds = SummaryThresholds {
+            min_samples: Some(2),
+            max_lost_events: Some(0),
+            max_unsymbolicated_ratio: Some(0.2),
+            max_function_share: vec![("work".to_owned(), 50.0), ("main".to_owned(), 100.0)],
+        };
+        let summary = summarize_profile(&profile, 1, &thresholds);
+        assert_eq!(summary.samples, 4);
+        assert_eq!(summary.unsymbolicated_ratio, 0.25);
+        assert_eq!(
+            summary.top_functions,
+            [FunctionShare {
+                name: "work".to_owned(),
+                self_percent: 75.0,
+                total_percent: 75.0
+            }]
+        );
+        assert_eq!(
+            summary.violations,
+            [
+                "0.250 of the samples are unsymbolicated, more than the maximum of 0.2",
+                "work is on the stack in 75.0% of the samples, more than the maximum of 50%",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_priority_inversions() {
+        let profile = json!({
+            "meta": { "product": "app", "interval": 1.0 },
+            "threads": [{
+                "processName": "app",
+                "name": "audio",
+                "tid": "8",
+                "stringArray": ["main", "Priority inversion"],
+                "funcTable": { "length": 1, "name": [0] },
+                "frameTable": { "length": 1, "func": [0], "address": [-1] },
+                "stackTable": { "length": 1, "frame": [0], "prefix": [null] },
+                "samples": { "length": 1, "stack": [0], "time": [0.0] },
+                "markers": {
+                    "length": 2,
+                    "name": [1, 1],
+                    "startTime": [1.0, 10.0],
+                    "endTime": [3.5, 22.0],
+                    "data": [
+                        { "type": "PriorityInversion", "waker": "worker (9)", "wakerPreempted": 2.0 },
+                        { "type": "PriorityInversion", "waker": "io (10)", "wakerPreempted": 11.5 },
+                    ],
+                },
+            }],
+        });
+        let mut output = Vec::new();
+        write_report(&profile, 1, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let summary: Vec<&str> = output
+            .lines()
+            .skip_while(|line| !line.starts_with("Likely priority inversions"))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                "Likely priority inversions: 2",
+                "      12.0 ms  app - audio (8) blocked until woken by io (10), which was preempted for 11.5 ms",
+            ]
+        );
+    }
+}
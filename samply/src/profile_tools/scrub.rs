@@ -0,0 +1,216 @@
+use std::borrow::Cow;
+
+use serde_json::Value;
+
+/// What to remove from a profile before it's shared.
+#[derive(Debug, Clone, Default)]
+pub struct ScrubOptions {
+    /// Function names which contain any of these strings are replaced.
+    pub function_denylist: Vec<String>,
+}
+
+/// Environment variables whose values are not worth redacting, because they
+/// are either not sensitive or so common that redacting them would mangle
+/// unrelated strings.
+const HARMLESS_ENV_VARS: &[&str] = &[
+    "COLORTERM",
+    "DISPLAY",
+    "HOME",
+    "LANG",
+    "LANGUAGE",
+    "LOGNAME",
+    "OLDPWD",
+    "PATH",
+    "PWD",
+    "SHELL",
+    "SHLVL",
+    "TERM",
+    "TMPDIR",
+    "USER",
+    "USERNAME",
+    "USERPROFILE",
+    "_",
+];
+
+/// Only environment values of at least this length are redacted.
+const MIN_REDACTED_ENV_VALUE_LEN: usize = 8;
+
+/// Removes personal information from the profile, so that it can be shared:
+///
+///  - Paths in home directories become relative to `~`.
+///  - Values of environment variables are replaced with `<NAME>`.
+///  - URLs in marker data are replaced with `<url>`.
+///  - Function names matching the denylist are replaced with `<redacted>`.
+///
+/// Scrubbing library paths means that libraries in home directories can no
+/// longer be symbolicated from local files.
+pub fn scrub_profile(profile: &mut Value, options: &ScrubOptions) {
+    let scrubber = Scrubber::from_env(options);
+    scrubber.scrub(profile);
+}
+
+struct Scrubber<'a> {
+    home_dirs: Vec<String>,
+    /// (name, value)
+    env_vars: Vec<(String, String)>,
+    function_denylist: &'a [String],
+}
+
+impl<'a> Scrubber<'a> {
+    fn from_env(options: &'a ScrubOptions) -> Self {
+        let home_dirs = ["HOME", "USERPROFILE"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .filter(|dir| dir.len() > 1)
+            .collect();
+        let env_vars = std::env::vars()
+            .filter(|(name, value)| {
+                !HARMLESS_ENV_VARS.contains(&name.as_str())
+                    && value.len() >= MIN_REDACTED_ENV_VALUE_LEN
+            })
+            .collect();
+        Self {
+            home_dirs,
+            env_vars,
+            function_denylist: &options.function_denylist,
+        }
+    }
+
+    fn scrub(&self, profile: &mut Value) {
+        for thread in profile["threads"].as_array_mut().into_iter().flatten() {
+            self.scrub_function_names(thread);
+            if let Some(datas) = thread["markers"]["data"].as_array_mut() {
+                for data in datas {
+                    self.scrub_strings(data, &|s| scrub_urls(s));
+                }
+            }
+        }
+        self.scrub_strings(profile, &|s| self.scrub_string(s));
+    }
+
+    /// Replaces the names of functions which match the denylist. The names of
+    /// native symbols live in the same string array, so they're covered too.
+    fn scrub_function_names(&self, thread: &mut Value) {
+        if self.function_denylist.is_empty() {
+            return;
+        }
+        let Some(strings) = thread["stringArray"].as_array_mut() else {
+            return;
+        };
+        for string in strings {
+            if let Some(s) = string.as_str() {
+                if self
+                    .function_denylist
+                    .iter()
+                    .any(|d| s.contains(d.as_str()))
+                {
+                    *string = "<redacted>".into();
+                }
+            }
+        }
+    }
+
+    /// Applies `f` to every string in `value`, recursively.
+    fn scrub_strings(&self, value: &mut Value, f: &dyn Fn(&str) -> Cow<'_, str>) {
+        match value {
+            Value::String(s) => {
+                if let Cow::Owned(scrubbed) = f(s) {
+                    *s = scrubbed;
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|v| self.scrub_strings(v, f)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.scrub_strings(v, f)),
+            _ => {}
+        }
+    }
+
+    fn scrub_string<'s>(&self, s: &'s str) -> Cow<'s, str> {
+        let mut result = Cow::Borrowed(s);
+        for (name, value) in &self.env_vars {
+            if result.contains(value.as_str()) {
+                result = Cow::Owned(result.replace(value.as_str(), &format!("<{name}>")));
+            }
+        }
+        for home_dir in &self.home_dirs {
+            if result.contains(home_dir.as_str()) {
+                result = Cow::Owned(result.replace(home_dir.as_str(), "~"));
+            }
+        }
+        match scrub_other_home_dirs(&result) {
+            Cow::Owned(scrubbed) => Cow::Owned(scrubbed),
+            Cow::Borrowed(_) => result,
+        }
+    }
+}
+
+/// Replaces home directories of users other than the current one, e.g. from
+/// a profile that was recorded on a different machine.
+fn scrub_other_home_dirs(s: &str) -> Cow<'_, str> {
+    let mut result = Cow::Borrowed(s);
+    for (prefix, separator) in [("/home/", '/'), ("/Users/", '/'), ("C:\\Users\\", '\\')] {
+        while let Some(start) = result.find(prefix) {
+            let user_start = start + prefix.len();
+            let user_end = result[user_start..]
+                .find(separator)
+                .map_or(result.len(), |end| user_start + end);
+            if user_end == user_start {
+                break;
+            }
+            let mut scrubbed = result[..start].to_owned();
+            scrubbed.push('~');
+            scrubbed.push_str(&result[user_end..]);
+            result = Cow::Owned(scrubbed);
+        }
+    }
+    result
+}
+
+/// Replaces everything that looks like a URL with `<url>`.
+fn scrub_urls(s: &str) -> Cow<'_, str> {
+    if !s.contains("://") {
+        return Cow::Borrowed(s);
+    }
+    let scrubbed: Vec<&str> = s
+        .split(' ')
+        .map(|word| if word.contains("://") { "<url>" } else { word })
+        .collect();
+    Cow::Owned(scrubbed.join(" "))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn scrub_paths_env_urls_and_functions() {
+        let denylist = vec!["secret_project".to_owned()];
+        let scrubber = Scrubber {
+            home_dirs: vec!["/home/alice".to_owned()],
+            env_vars: vec![("API_TOKEN".to_owned(), "hunter2hunter2".to_owned())],
+            function_denylist: &denylist,
+        };
+        let mut profile = json!({
+            "libs": [{ "path": "/home/alice/build/libfoo.so" }],
+            "threads": [{
+                "stringArray": ["secret_project::run", "main", "/home/bob/x.so"],
+                "markers": {
+                    "data": [
+                        { "type": "Text", "name": "GET https://example.com/?q=1 done" },
+                        { "type": "Text", "name": "token=hunter2hunter2" },
+                    ],
+                },
+            }],
+        });
+        scrubber.scrub(&mut profile);
+        assert_eq!(profile["libs"][0]["path"], "~/build/libfoo.so");
+        assert_eq!(
+            profile["threads"][0]["stringArray"],
+            json!(["<redacted>", "main", "~/x.so"])
+        );
+        let datas = &profile["threads"][0]["markers"]["data"];
+        assert_eq!(datas[0]["name"], "GET <url> done");
+        assert_eq!(datas[1]["name"], "token=<API_TOKEN>");
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use serde_json::{json, Value};
+
+use super::{column_values, frame_names, stack_frames, Error};
+
+/// Writes the profile in speedscope's file format, with one "sampled"
+/// profile per thread.
+///
+/// Samples are kept in their recorded order, so that speedscope's "Time
+/// Order" view matches the timeline. Each sample's weight is its sample
+/// weight multiplied by the sampling interval, in milliseconds.
+pub fn write_speedscope_profile(profile: &Value, writer: impl Write) -> Result<(), Error> {
+    let interval = profile["meta"]["interval"].as_f64().unwrap_or(1.0);
+    let mut frames = SharedFrames::default();
+    let mut profiles = Vec::new();
+    for thread in profile["threads"].as_array().into_iter().flatten() {
+        if let Some(thread_profile) = sampled_profile(thread, interval, &mut frames) {
+            profiles.push(thread_profile);
+        }
+    }
+    let name = profile["meta"]["product"].as_str().unwrap_or("samply");
+    let speedscope_profile = json!({
+        "$schema": "https://www.speedscope.app/file-format-schema.json",
+        "exporter": concat!("samply ", env!("CARGO_PKG_VERSION")),
+        "name": name,
+        "activeProfileIndex": 0,
+        "shared": { "frames": frames.frames },
+        "profiles": profiles,
+    });
+    serde_json::to_writer(writer, &speedscope_profile)?;
+    Ok(())
+}
+
+/// The frames of all threads, deduplicated by name.
+#[derive(Default)]
+struct SharedFrames {
+    frames: Vec<Value>,
+    index_for_name: HashMap<String, usize>,
+}
+
+impl SharedFrames {
+    fn index_for_name(&mut self, name: &str) -> usize {
+        if let Some(index) = self.index_for_name.get(name) {
+            return *index;
+        }
+        let index = self.frames.len();
+        self.frames.push(json!({ "name": name }));
+        self.index_for_name.insert(name.to_owned(), index);
+        index
+    }
+}
+
+fn sampled_profile(thread: &Value, interval: f64, frames: &mut SharedFrames) -> Option<Value> {
+    let names = frame_names(thread);
+    let stack_table = thread.get("stackTable");
+    let prefixes: Vec<&Value> = column_values(stack_table, "prefix").collect();
+    let stack_frame_column: Vec<&Value> = column_values(stack_table, "frame").collect();
+
+    // Speedscope frame indexes from the root to the leaf, per stack.
+    let mut speedscope_stacks: HashMap<usize, Vec<usize>> = HashMap::new();
+    let samples_table = thread.get("samples");
+    let weight_column: Vec<&Value> = column_values(samples_table, "weight").collect();
+    let mut samples = Vec::new();
+    let mut weights = Vec::new();
+    for (i, stack) in column_values(samples_table, "stack").enumerate() {
+        let Some(stack) = stack.as_u64().map(|s| s as usize) else {
+            continue;
+        };
+        let speedscope_stack = speedscope_stacks.entry(stack).or_insert_with(|| {
+            stack_frames(&prefixes, &stack_frame_column, stack)
+                .into_iter()
+                .map(|frame| frames.index_for_name(names.get(frame).map_or("", String::as_str)))
+                .collect()
+        });
+        samples.push(speedscope_stack.clone());
+        let weight = weight_column.get(i).and_then(|w| w.as_f64()).unwrap_or(1.0);
+        weights.push(weight * interval);
+    }
+    if samples.is_empty() {
+        return None;
+    }
+
+    let name = format!(
+        "{} - {} ({})",
+        thread["processName"].as_str().unwrap_or("Process"),
+        thread["name"].as_str().unwrap_or("Thread"),
+        thread["tid"].as_str().unwrap_or_default()
+    );
+    let end_value: f64 = weights.iter().sum();
+    Some(json!({
+        "type": "sampled",
+        "name": name,
+        "unit": "milliseconds",
+        "startValue": 0.0,
+        "endValue": end_value,
+        "samples": samples,
+        "weights": weights,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn export_sampled_profiles() {
+        let profile = json!({
+            "meta": { "interval": 2.0, "product": "app" },
+            "threads": [{
+                "processName": "app",
+                "name": "main",
+                "tid": "7",
+                "stringArray": ["start", "work"],
+                "funcTable": { "length": 2, "name": [0, 1] },
+                "frameTable": { "length": 2, "func": [0, 1], "address": [-1, -1] },
+                "stackTable": { "length": 2, "frame": [0, 1], "prefix": [null, 0] },
+                "samples": { "length": 3, "stack": [1, 0, 1], "weight": [1, 1, 3] },
+            }],
+        });
+        let mut output = Vec::new();
+        write_speedscope_profile(&profile, &mut output).unwrap();
+        let output: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            output["shared"]["frames"],
+            json!([{ "name": "start" }, { "name": "work" }])
+        );
+        let thread_profile = &output["profiles"][0];
+        assert_eq!(thread_profile["name"], "app - main (7)");
+        assert_eq!(thread_profile["samples"], json!([[0, 1], [0], [0, 1]]));
+        assert_eq!(thread_profile["weights"], json!([2.0, 2.0, 6.0]));
+        assert_eq!(thread_profile["endValue"], json!(10.0));
+    }
+}
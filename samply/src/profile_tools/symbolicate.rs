@@ -0,0 +1,232 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde_json::Value;
+use wholesym::debugid::DebugId;
+use wholesym::{CodeId, LibraryInfo, LookupAddress, SymbolMap};
+
+use super::{column_mut, column_values, intern_thread_string, threads_mut, Error};
+use crate::server::create_symbol_manager;
+use crate::shared::symbol_props::SymbolProps;
+
+/// Replaces the addresses in the function names of native frames with the
+/// names of the functions which contain them, like the Firefox Profiler does
+/// when it loads a profile from samply.
+///
+/// Libraries for which no symbols can be found keep their addresses. The
+/// others get a `symbolProvenance` object which says which file supplied the
+/// symbols, so that differences between two symbolicated profiles of the same
+/// binary can be tracked down.
+#[tokio::main(flavor = "current_thread")]
+pub async fn symbolicate_profile(
+    profile: &mut Value,
+    symbol_props: SymbolProps,
+    verbose: bool,
+) -> Result<(), Error> {
+    let symbol_manager = create_symbol_manager(symbol_props, verbose);
+    let lib_infos: Vec<Option<LibraryInfo>> = profile["libs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(library_info_for_lib)
+        .collect();
+    for lib_info in lib_infos.iter().flatten() {
+        symbol_manager.add_known_library(lib_info.clone());
+    }
+
+    let mut symbol_maps: Vec<Option<SymbolMap>> = Vec::with_capacity(lib_infos.len());
+    for lib_info in &lib_infos {
+        let symbol_map = match lib_info {
+            Some(LibraryInfo {
+                debug_name: Some(debug_name),
+                debug_id: Some(debug_id),
+                ..
+            }) => match symbol_manager.load_symbol_map(debug_name, *debug_id).await {
+                Ok(symbol_map) => Some(symbol_map),
+                Err(err) => {
+                    if verbose {
+                        eprintln!("Couldn't load symbols for {debug_name}: {err}");
+                    }
+                    None
+                }
+            },
+            _ => None,
+        };
+        symbol_maps.push(symbol_map);
+    }
+
+    let has_inline_info: Vec<Cell<bool>> = symbol_maps.iter().map(|_| Cell::new(false)).collect();
+    for thread in threads_mut(profile)? {
+        symbolicate_thread(thread, &|lib_index, address| {
+            let symbol_map = symbol_maps.get(lib_index)?.as_ref()?;
+            let info = symbol_map.lookup_sync(LookupAddress::Relative(address))?;
+            if info.frames.is_some() {
+                has_inline_info[lib_index].set(true);
+            }
+            Some(info.symbol.name)
+        });
+    }
+    if let Some(libs) = profile["libs"].as_array_mut() {
+        for ((lib, symbol_map), has_inline_info) in
+            libs.iter_mut().zip(&symbol_maps).zip(has_inline_info)
+        {
+            if let Some(symbol_map) = symbol_map {
+                lib["symbolProvenance"] = symbol_provenance(
+                    symbol_manager.debug_file_description(symbol_map),
+                    symbol_map.debug_id(),
+                    has_inline_info.get(),
+                );
+            }
+        }
+    }
+    profile["meta"]["symbolicated"] = true.into();
+    Ok(())
+}
+
+/// Where the symbols of a library came from: the path or URL of the file,
+/// its debug ID, and whether it had line numbers and inline frames for any of
+/// the library's frames.
+fn symbol_provenance(file: String, debug_id: DebugId, has_inline_info: bool) -> Value {
+    serde_json::json!({
+        "file": file,
+        "debugId": debug_id.breakpad().to_string(),
+        "hasInlineInfo": has_inline_info,
+    })
+}
+
+fn library_info_for_lib(lib: &Value) -> Option<LibraryInfo> {
+    let string = |key: &str| lib[key].as_str().map(ToOwned::to_owned);
+    Some(LibraryInfo {
+        debug_name: string("debugName"),
+        debug_id: Some(DebugId::from_breakpad(lib["breakpadId"].as_str()?).ok()?),
+        debug_path: string("debugPath"),
+        name: string("name"),
+        code_id: lib["codeId"]
+            .as_str()
+            .and_then(|code_id| CodeId::from_str(code_id).ok()),
+        path: string("path"),
+        arch: string("arch"),
+    })
+}
+
+/// Points every native frame whose function name is still an address at a
+/// function with the name returned by `lookup(lib_index, relative_address)`.
+fn symbolicate_thread(thread: &mut Value, lookup: &dyn Fn(usize, u32) -> Option<String>) {
+    let strings = thread["stringArray"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let func_names: Vec<Option<u64>> = column_values(thread.get("funcTable"), "name")
+        .map(Value::as_u64)
+        .collect();
+    let func_resources: Vec<Option<u64>> = column_values(thread.get("funcTable"), "resource")
+        .map(Value::as_u64)
+        .collect();
+    let resource_libs: Vec<Option<u64>> = column_values(thread.get("resourceTable"), "lib")
+        .map(Value::as_u64)
+        .collect();
+    let frames: Vec<(Option<u64>, Option<u64>)> = column_values(thread.get("frameTable"), "func")
+        .zip(column_values(thread.get("frameTable"), "address"))
+        .map(|(func, address)| (func.as_u64(), address.as_u64()))
+        .collect();
+
+    // (resource, name string index) -> func index
+    let mut symbolicated_funcs: HashMap<(u64, usize), usize> = HashMap::new();
+    let mut new_frame_funcs = Vec::with_capacity(frames.len());
+    for (func, address) in frames {
+        let new_func = (|| {
+            let func = func? as usize;
+            let name = strings.get((*func_names.get(func)?)? as usize)?.as_str()?;
+            if !is_address_name(name) {
+                return None;
+            }
+            let resource = (*func_resources.get(func)?)?;
+            let lib_index = (*resource_libs.get(resource as usize)?)? as usize;
+            let symbol_name = lookup(lib_index, u32::try_from(address?).ok()?)?;
+            let name_index = intern_thread_string(thread, &symbol_name);
+            let func_table = &mut thread["funcTable"];
+            let new_func = *symbolicated_funcs
+                .entry((resource, name_index))
+                .or_insert_with(|| append_func(func_table, name_index, resource));
+            Some(new_func.into())
+        })();
+        new_frame_funcs.push(new_func.unwrap_or_else(|| func.map_or(Value::Null, Value::from)));
+    }
+    if let Some(frame_funcs) = column_mut(&mut thread["frameTable"], "func") {
+        *frame_funcs = new_frame_funcs;
+    }
+}
+
+/// Whether a function name is the placeholder for an unsymbolicated address,
+/// either "0x1234" or "libfoo.so!+0x1234".
+pub(super) fn is_address_name(name: &str) -> bool {
+    name.starts_with("0x") || name.contains("!+0x")
+}
+
+/// Appends a native function to the func table and returns its index.
+fn append_func(func_table: &mut Value, name_index: usize, resource: u64) -> usize {
+    let index = func_table["length"].as_u64().unwrap_or(0) as usize;
+    for (column_name, value) in [
+        ("name", Value::from(name_index)),
+        ("resource", resource.into()),
+        ("isJS", false.into()),
+        ("relevantForJS", false.into()),
+        ("fileName", Value::Null),
+        ("lineNumber", Value::Null),
+        ("columnNumber", Value::Null),
+    ] {
+        if let Some(column) = column_mut(func_table, column_name) {
+            column.push(value);
+        }
+    }
+    func_table["length"] = (index + 1).into();
+    index
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn symbolicate_frames_in_known_libs() {
+        let mut thread = json!({
+            "stringArray": ["0x10", "libfoo.so!+0x18", "0x20", "libfoo.so"],
+            "resourceTable": { "length": 1, "lib": [0], "name": [3] },
+            "funcTable": {
+                "length": 3,
+                "name": [0, 1, 2],
+                "resource": [0, 0, 0],
+                "isJS": [false, false, false],
+                "relevantForJS": [false, false, false],
+                "fileName": [null, null, null],
+                "lineNumber": [null, null, null],
+                "columnNumber": [null, null, null],
+            },
+            "frameTable": { "length": 3, "func": [0, 1, 2], "address": [16, 24, 32] },
+        });
+        symbolicate_thread(
+            &mut thread,
+            &|lib_index, address| match (lib_index, address) {
+                (0, 0x10..=0x1f) => Some("foo".to_owned()),
+                _ => None,
+            },
+        );
+        assert_eq!(thread["funcTable"]["length"], json!(4));
+        assert_eq!(thread["stringArray"][4], json!("foo"));
+        assert_eq!(thread["funcTable"]["name"][3], json!(4));
+        assert_eq!(thread["frameTable"]["func"], json!([3, 3, 2]));
+
+        let debug_id = DebugId::from_breakpad("44E4EC8C2F41492B9369D6B9A059577C2").unwrap();
+        assert_eq!(
+            symbol_provenance("/tmp/xul.pdb".to_owned(), debug_id, true),
+            json!({
+                "file": "/tmp/xul.pdb",
+                "debugId": "44E4EC8C2F41492B9369D6B9A059577C2",
+                "hasInlineInfo": true,
+            })
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use serde_json::Value;
+
+use super::{column_values, filter_table_rows, profile_time_range, retain_threads, Error};
+
+/// Cuts a profile down to the time range `from..=to`.
+///
+/// `from` and `to` are in milliseconds, relative to the first sample or
+/// marker in the profile, which is where the timeline in the profiler starts.
+/// Samples, markers and counter values outside the range are dropped, and
+/// threads which don't overlap the range are removed.
+pub fn trim_profile(
+    mut profile: Value,
+    from: Option<f64>,
+    to: Option<f64>,
+) -> Result<Value, Error> {
+    let Some((profile_start, profile_end)) = profile_time_range(&profile) else {
+        return Ok(profile);
+    };
+    let start = from.map_or(profile_start, |from| profile_start + from);
+    let end = to.map_or(profile_end, |to| profile_start + to);
+    if start > end {
+        return Err(Error::InvalidProfile(
+            "the start of the range is after its end",
+        ));
+    }
+
+    retain_threads(&mut profile, |thread| {
+        let registered_after_end = thread["registerTime"].as_f64().is_some_and(|t| t > end);
+        let unregistered_before_start =
+            thread["unregisterTime"].as_f64().is_some_and(|t| t < start);
+        !registered_after_end && !unregistered_before_start
+    })?;
+
+    for thread in profile["threads"].as_array_mut().into_iter().flatten() {
+        trim_thread(thread, start, end);
+    }
+    for counter in profile["counters"].as_array_mut().into_iter().flatten() {
+        trim_table_by_time(&mut counter["samples"], start, end);
+    }
+    Ok(profile)
+}
+
+fn trim_thread(thread: &mut Value, start: f64, end: f64) {
+    for key in ["registerTime", "processStartupTime"] {
+        if let Some(time) = thread[key].as_f64() {
+            thread[key] = time.max(start).into();
+        }
+    }
+    for key in ["unregisterTime", "processShutdownTime"] {
+        if let Some(time) = thread[key].as_f64() {
+            thread[key] = time.min(end).into();
+        }
+    }
+    for table_name in ["samples", "nativeAllocations", "jsAllocations"] {
+        if let Some(table) = thread.get_mut(table_name) {
+            trim_table_by_time(table, start, end);
+        }
+    }
+    if let Some(markers) = thread.get_mut("markers") {
+        trim_markers(markers, start, end);
+    }
+}
+
+fn trim_table_by_time(table: &mut Value, start: f64, end: f64) {
+    let keep: Vec<bool> = column_values(Some(table), "time")
+        .map(|time| time.as_f64().is_some_and(|t| start <= t && t <= end))
+        .collect();
+    if !keep.is_empty() {
+        filter_table_rows(table, &keep);
+    }
+}
+
+/// Drops markers which are entirely outside the range, and clamps interval
+/// markers which are partially outside of it.
+fn trim_markers(markers: &mut Value, start: f64, end: f64) {
+    let start_times: Vec<Option<f64>> = column_values(Some(markers), "startTime")
+        .map(Value::as_f64)
+        .collect();
+    let end_times: Vec<Option<f64>> = column_values(Some(markers), "endTime")
+        .map(Value::as_f64)
+        .collect();
+    let keep: Vec<bool> = start_times
+        .iter()
+        .enumerate()
+        .map(|(i, marker_start)| {
+            let marker_end = end_times.get(i).copied().flatten();
+            match (*marker_start, marker_end) {
+                (Some(s), Some(e)) => s <= end && e >= start,
+                (Some(t), None) | (None, Some(t)) => start <= t && t <= end,
+                (None, None) => true,
+            }
+        })
+        .collect();
+    if keep.is_empty() {
+        return;
+    }
+    filter_table_rows(markers, &keep);
+    let interval_rows: Vec<usize> = column_values(Some(markers), "startTime")
+        .zip(column_values(Some(markers), "endTime"))
+        .enumerate()
+        .filter(|(_, (s, e))| s.is_number() && e.is_number())
+        .map(|(i, _)| i)
+        .collect();
+    for i in interval_rows {
+        let marker_start = markers["startTime"][i].as_f64().unwrap_or(start);
+        markers["startTime"][i] = marker_start.max(start).into();
+        let marker_end = markers["endTime"][i].as_f64().unwrap_or(end);
+        markers["endTime"][i] = marker_end.min(end).into();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn trim_samples_markers_and_threads() {
+        let profile = json!({
+            "meta": { "startTime": 0.0 },
+            "threads": [
+                {
+                    "registerTime": 100.0,
+                    "unregisterTime": null,
+                    "samples": { "length": 4, "time": [100.0, 105.0, 110.0, 120.0], "stack": [0, 1, 2, 3] },
+                    "markers": {
+                        "length": 3,
+                        "startTime": [101.0, 104.0, 130.0],
+                        "endTime": [null, 108.0, null],
+                        "name": [0, 1, 2],
+                    },
+                },
+                {
+                    "registerTime": 100.0,
+                    "unregisterTime": 102.0,
+                    "samples": { "length": 1, "time": [101.0], "stack": [0] },
+                },
+            ],
+            "counters": [{
+                "mainThreadIndex": 0,
+                "samples": { "length": 2, "time": [100.0, 106.0], "count": [1, 2] },
+            }],
+        });
+        let trimmed = trim_profile(profile, Some(5.0), Some(10.0)).unwrap();
+        let threads = trimmed["threads"].as_array().unwrap();
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0]["registerTime"], json!(105.0));
+        assert_eq!(threads[0]["samples"]["length"], json!(2));
+        assert_eq!(threads[0]["samples"]["stack"], json!([1, 2]));
+        assert_eq!(threads[0]["markers"]["name"], json!([1]));
+        assert_eq!(threads[0]["markers"]["startTime"], json!([105.0]));
+        assert_eq!(trimmed["counters"][0]["samples"]["count"], json!([2]));
+    }
+}
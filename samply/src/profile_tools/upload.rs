@@ -0,0 +1,63 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use flate2::write::GzEncoder;
+use serde_json::Value;
+
+use super::Error;
+
+/// The endpoint which the Firefox Profiler's "Upload" button uses.
+const UPLOAD_URL: &str = "https://api.profiler.firefox.com/compressed-store";
+const UPLOAD_ACCEPT_HEADER: &str = "application/vnd.firefox-profiler+json;version=1.0";
+
+/// Uploads a profile to the Firefox Profiler's public storage and returns
+/// the URL under which it can be viewed. Anyone with the URL can view the
+/// profile.
+///
+/// The Firefox Profiler can't look up symbols for a shared profile, so the
+/// profile should be symbolicated with [`super::symbolicate_profile`] first.
+#[tokio::main(flavor = "current_thread")]
+pub async fn upload_profile(profile: &Value) -> Result<String, Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    serde_json::to_writer(&mut encoder, profile)?;
+    let compressed = encoder.finish()?;
+
+    let response = reqwest::Client::new()
+        .post(UPLOAD_URL)
+        .header(reqwest::header::ACCEPT, UPLOAD_ACCEPT_HEADER)
+        .body(compressed)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| Error::Upload(e.to_string()))?;
+    let jwt = response
+        .text()
+        .await
+        .map_err(|e| Error::Upload(e.to_string()))?;
+    let profile_token = profile_token_from_jwt(&jwt)
+        .ok_or_else(|| Error::Upload(format!("Unexpected response from server: {jwt}")))?;
+    Ok(format!(
+        "https://profiler.firefox.com/public/{profile_token}/"
+    ))
+}
+
+/// The server replies with a JSON Web Token whose payload contains the
+/// token under which the profile was stored.
+fn profile_token_from_jwt(jwt: &str) -> Option<String> {
+    let payload = jwt.trim().split('.').nth(1)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let payload: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+    Some(payload["profileToken"].as_str()?.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_upload_response() {
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"profileToken":"abc123"}"#);
+        let jwt = format!("eyJhbGciOiJIUzI1NiJ9.{payload}.signature");
+        assert_eq!(profile_token_from_jwt(&jwt).as_deref(), Some("abc123"));
+        assert_eq!(profile_token_from_jwt("not a token"), None);
+    }
+}
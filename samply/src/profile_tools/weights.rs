@@ -0,0 +1,55 @@
+use serde_json::Value;
+
+/// Swaps the `weight` and `auxWeight` columns of the samples, for profiles
+/// which sampled two events at once, so that the profiler shows the
+/// auxiliary event. Returns false if the profile has no auxiliary weights.
+pub fn swap_sample_weights(profile: &mut Value) -> bool {
+    let mut swapped = false;
+    for thread in profile["threads"].as_array_mut().into_iter().flatten() {
+        let samples = &mut thread["samples"];
+        if !samples["auxWeight"].is_array() {
+            continue;
+        }
+        if !samples["weight"].is_array() {
+            let len = samples["length"].as_u64().unwrap_or(0) as usize;
+            samples["weight"] = Value::Array(vec![Value::from(1); len]);
+        }
+        let aux_weight = samples["auxWeight"].take();
+        samples["auxWeight"] = std::mem::replace(&mut samples["weight"], aux_weight);
+        swapped = true;
+    }
+    if let Some(names) = profile["meta"].get_mut("sampleWeightNames") {
+        let aux_weight = names["auxWeight"].take();
+        names["auxWeight"] = std::mem::replace(&mut names["weight"], aux_weight);
+    }
+    swapped
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn swap_weight_columns_and_names() {
+        let mut profile = json!({
+            "meta": { "sampleWeightNames": { "weight": "cycles", "auxWeight": "cache-misses" } },
+            "threads": [
+                { "samples": { "length": 2, "weight": null, "auxWeight": [0, 250] } },
+                { "samples": { "length": 1, "weight": [1] } },
+            ],
+        });
+        assert!(swap_sample_weights(&mut profile));
+        assert_eq!(
+            profile["meta"]["sampleWeightNames"],
+            json!({ "weight": "cache-misses", "auxWeight": "cycles" })
+        );
+        assert_eq!(
+            profile["threads"][0]["samples"],
+            json!({ "length": 2, "weight": [0, 250], "auxWeight": [1, 1] })
+        );
+        assert_eq!(profile["threads"][1]["samples"]["weight"], json!([1]));
+        assert!(!swap_sample_weights(&mut json!({ "threads": [] })));
+    }
+}
@@ -0,0 +1,247 @@
+//! Uploads the symbols of locally built binaries to a symbol store, so that
+//! profiles of these binaries which were recorded on other machines can be
+//! symbolicated with `--breakpad-symbol-server` or `--windows-symbol-server`.
+//! The store can be an HTTP server, an S3 or GCS bucket, or a directory.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use wholesym::{CloudStore, CodeId, LibraryInfo, SymbolManager, SymbolManagerConfig};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Could not read the symbols of {0:?}: {1}")]
+    Symbols(PathBuf, wholesym::Error),
+
+    #[error("{0:?} has no debug ID, so its symbols can't be looked up by debug ID")]
+    NoDebugId(PathBuf),
+
+    #[error("Upload failed: {0}")]
+    Upload(String),
+}
+
+/// Where to put the symbol files.
+#[derive(Debug, Clone)]
+pub enum SymbolStore {
+    /// An HTTP(S) symbol store which accepts PUT requests at the symbol file
+    /// paths, with these extra request headers.
+    Http {
+        base_url: String,
+        headers: Vec<(String, String)>,
+    },
+    /// An S3 or GCS bucket, from an `s3://` or `gs://` URL.
+    Cloud(CloudStore),
+    /// A local directory with the symbol store layout.
+    Directory(PathBuf),
+}
+
+impl SymbolStore {
+    /// Anything that's not an http(s), s3 or gs URL is treated as a directory.
+    pub fn new(server: &str, headers: Vec<(String, String)>) -> Self {
+        if let Some(cloud_store) = CloudStore::from_url(server) {
+            SymbolStore::Cloud(cloud_store)
+        } else if server.starts_with("http://") || server.starts_with("https://") {
+            SymbolStore::Http {
+                base_url: server.trim_end_matches('/').to_owned(),
+                headers,
+            }
+        } else {
+            SymbolStore::Directory(PathBuf::from(server))
+        }
+    }
+}
+
+/// What to upload for each binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolFileKind {
+    /// A Breakpad .sym file with the binary's symbol table, at
+    /// `<debug name>/<debug ID>/<debug name without .pdb>.sym`.
+    Breakpad,
+    /// The original debug file at `<debug name>/<debug ID>/<debug name>`, and
+    /// for Windows binaries also the binary itself at `<name>/<code ID>/<name>`.
+    Original,
+}
+
+/// A file which is about to be stored, with its path in the store.
+pub struct SymbolFile {
+    pub store_path: String,
+    pub contents: Vec<u8>,
+}
+
+/// Collects the symbol files for the binary at `path`.
+#[tokio::main(flavor = "current_thread")]
+pub async fn symbol_files_for_binary(
+    path: &Path,
+    kind: SymbolFileKind,
+) -> Result<Vec<SymbolFile>, Error> {
+    let symbols_error = |err| Error::Symbols(path.to_owned(), err);
+    let info = SymbolManager::library_info_for_binary_at_path(path, None)
+        .await
+        .map_err(symbols_error)?;
+    let (Some(debug_name), Some(debug_id)) = (info.debug_name.as_deref(), info.debug_id) else {
+        return Err(Error::NoDebugId(path.to_owned()));
+    };
+    let debug_id = debug_id.breakpad().to_string();
+
+    match kind {
+        SymbolFileKind::Breakpad => {
+            let symbol_manager = SymbolManager::with_config(SymbolManagerConfig::new());
+            let symbol_map = symbol_manager
+                .load_symbol_map_for_binary_at_path(path, None)
+                .await
+                .map_err(symbols_error)?;
+            let mut symbols: Vec<_> = symbol_map.iter_symbols().collect();
+            symbols.sort_by_key(|(address, _)| *address);
+            let sym = breakpad_sym_file(&info, &debug_id, &symbols);
+            Ok(vec![SymbolFile {
+                store_path: format!(
+                    "{debug_name}/{debug_id}/{}.sym",
+                    debug_name.trim_end_matches(".pdb")
+                ),
+                contents: sym.into_bytes(),
+            }])
+        }
+        SymbolFileKind::Original => {
+            let debug_path = info.debug_path.as_deref().map_or(path, Path::new);
+            let mut files = vec![SymbolFile {
+                store_path: format!("{debug_name}/{debug_id}/{debug_name}"),
+                contents: std::fs::read(debug_path)?,
+            }];
+            if let (Some(name), Some(CodeId::PeCodeId(code_id))) = (&info.name, &info.code_id) {
+                files.push(SymbolFile {
+                    store_path: format!("{name}/{code_id}/{name}"),
+                    contents: std::fs::read(path)?,
+                });
+            }
+            Ok(files)
+        }
+    }
+}
+
+/// Creates a Breakpad .sym file with a PUBLIC record for every symbol.
+fn breakpad_sym_file(
+    info: &LibraryInfo,
+    debug_id: &str,
+    symbols: &[(u32, impl AsRef<str>)],
+) -> String {
+    let os = match info.code_id {
+        Some(CodeId::PeCodeId(_)) => "windows",
+        Some(CodeId::MachoUuid(_)) => "mac",
+        _ => "Linux",
+    };
+    let arch = info.arch.as_deref().unwrap_or("unknown");
+    let debug_name = info.debug_name.as_deref().unwrap_or_default();
+    let mut sym = format!("MODULE {os} {arch} {debug_id} {debug_name}\n");
+    if let Some(code_id) = &info.code_id {
+        let name = info.name.as_deref().unwrap_or(debug_name);
+        writeln!(sym, "INFO CODE_ID {code_id} {name}").unwrap();
+    }
+    for (address, name) in symbols {
+        writeln!(sym, "PUBLIC {address:x} 0 {}", name.as_ref()).unwrap();
+    }
+    sym
+}
+
+/// Stores a symbol file and returns where it was stored.
+#[tokio::main(flavor = "current_thread")]
+pub async fn store_symbol_file(store: &SymbolStore, file: SymbolFile) -> Result<String, Error> {
+    match store {
+        SymbolStore::Directory(dir) => {
+            let path = dir.join(&file.store_path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, file.contents)?;
+            Ok(path.to_string_lossy().into_owned())
+        }
+        SymbolStore::Http { base_url, headers } => {
+            let url = format!("{base_url}/{}", file.store_path);
+            let mut request = reqwest::Client::new().put(&url).body(file.contents);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            let response = request
+                .send()
+                .await
+                .map_err(|err| Error::Upload(err.to_string()))?;
+            if !response.status().is_success() {
+                return Err(Error::Upload(format!(
+                    "{url} returned {}",
+                    response.status()
+                )));
+            }
+            Ok(url)
+        }
+        SymbolStore::Cloud(cloud_store) => {
+            let url = cloud_store.url_for_path(&file.store_path);
+            let response = cloud_store
+                .put(&reqwest::Client::new(), &file.store_path, file.contents)
+                .send()
+                .await
+                .map_err(|err| Error::Upload(err.to_string()))?;
+            if !response.status().is_success() {
+                return Err(Error::Upload(format!(
+                    "{url} returned {}",
+                    response.status()
+                )));
+            }
+            Ok(url)
+        }
+    }
+}
+
+/// Whether the store already has a file at `store_path`.
+#[tokio::main(flavor = "current_thread")]
+pub async fn is_already_stored(store: &SymbolStore, store_path: &str) -> Result<bool, Error> {
+    match store {
+        SymbolStore::Directory(dir) => Ok(dir.join(store_path).exists()),
+        SymbolStore::Http { base_url, headers } => {
+            let url = format!("{base_url}/{store_path}");
+            let mut request = reqwest::Client::new().head(&url);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            let response = request
+                .send()
+                .await
+                .map_err(|err| Error::Upload(err.to_string()))?;
+            Ok(response.status().is_success())
+        }
+        SymbolStore::Cloud(cloud_store) => {
+            let paths = cloud_store
+                .list(&reqwest::Client::new(), store_path)
+                .await
+                .map_err(|err| Error::Upload(err.to_string()))?;
+            Ok(paths.iter().any(|path| path == store_path))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wholesym::ElfBuildId;
+
+    use super::*;
+
+    #[test]
+    fn write_breakpad_sym_file() {
+        let info = LibraryInfo {
+            debug_name: Some("myapp".to_owned()),
+            name: Some("myapp".to_owned()),
+            code_id: Some(CodeId::ElfBuildId(ElfBuildId::from_bytes(&[0xab, 0xcd]))),
+            arch: Some("x86_64".to_owned()),
+            ..Default::default()
+        };
+        let sym = breakpad_sym_file(&info, "ABCD0", &[(0x1000, "main"), (0x1040, "helper")]);
+        assert_eq!(
+            sym,
+            "MODULE Linux x86_64 ABCD0 myapp\n\
+             INFO CODE_ID abcd myapp\n\
+             PUBLIC 1000 0 main\n\
+             PUBLIC 1040 0 helper\n"
+        );
+    }
+}
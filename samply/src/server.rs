@@ -1,10 +1,12 @@
 use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::future::Future;
 use std::net::{IpAddr, SocketAddr};
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use futures_util::TryStreamExt;
 use http_body_util::combinators::BoxBody;
@@ -14,25 +16,63 @@ use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{header, Method, Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
-use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
 use platform_dirs::AppDirs;
 use rand::RngCore;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio_rustls::TlsAcceptor;
 use tokio_util::io::ReaderStream;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 use wholesym::debugid::DebugId;
 use wholesym::{LibraryInfo, SymbolManager, SymbolManagerConfig};
 
+#[cfg(feature = "grpc")]
+use crate::grpc_server;
 use crate::name::SAMPLY_NAME;
+use crate::profile_json_preparse::parse_libinfo_map_from_profile_file;
+use crate::server_health::HealthCheck;
+use crate::server_limits::{retry_after_secs, RequestLimiter, RequestLimits};
+use crate::server_metrics::ServerMetrics;
+use crate::server_tls::{local_host_name, make_tls_acceptor, TlsSource};
 use crate::shared;
 use crate::shared::ctrl_c::CtrlC;
+use crate::shared::symbol_debug::SymbolDebugReport;
 use crate::shared::symbol_props::SymbolProps;
+use crate::shared::utils::perf_build_id_cache_dir;
 
 #[derive(Clone, Debug)]
 pub struct ServerProps {
     pub address: IpAddr,
     pub port_selection: PortSelection,
+    /// If set, the symbolication API is also served over gRPC on this port.
+    #[cfg(feature = "grpc")]
+    pub grpc_port_selection: Option<PortSelection>,
     pub verbose: bool,
     pub open_in_browser: bool,
+    /// If set, the server listens on this Unix domain socket instead of a
+    /// TCP port.
+    pub unix_socket: Option<PathBuf>,
+    /// The URL under which the server is reachable, e.g. through a reverse
+    /// proxy, for the links which the server prints and serves.
+    pub public_url: Option<String>,
+    /// If set, the server speaks HTTPS with this certificate.
+    pub tls: Option<TlsSource>,
+    /// If set, the symbolication, source and asm requests need an
+    /// `Authorization: Bearer` header with this token.
+    pub auth_token: Option<String>,
+    /// The path under which the symbolication API is served. A random one is
+    /// generated if this is not set.
+    pub path_prefix: Option<String>,
+    /// The origins of the web pages which may query the API, e.g. a
+    /// self-hosted profiler. Any origin may if this is empty.
+    pub allowed_origins: Vec<String>,
+    /// Limits on the symbolication requests, for servers which many clients
+    /// query.
+    pub request_limits: RequestLimits,
 }
 
 #[tokio::main]
@@ -42,7 +82,67 @@ pub async fn start_server_main(
     symbol_props: SymbolProps,
     libinfo_map: HashMap<(String, DebugId), LibraryInfo>,
 ) {
-    start_server(Some(file), props, symbol_props, libinfo_map).await;
+    start_server(
+        ServedProfiles::Single(file.to_owned()),
+        props,
+        symbol_props,
+        libinfo_map,
+    )
+    .await;
+}
+
+/// Serves an index page which lists the profiles in `profiles`, newest last.
+/// The list can grow while the server is running, and profiles in
+/// `addable_dirs` can be added by POSTing their path to `<prefix>/profiles`.
+#[tokio::main]
+pub async fn start_profile_list_server_main(
+    profiles: Arc<Mutex<Vec<PathBuf>>>,
+    addable_dirs: Vec<PathBuf>,
+    props: ServerProps,
+    symbol_props: SymbolProps,
+    libinfo_map: HashMap<(String, DebugId), LibraryInfo>,
+) {
+    start_server(
+        ServedProfiles::List(profiles, Arc::new(addable_dirs)),
+        props,
+        symbol_props,
+        libinfo_map,
+    )
+    .await;
+}
+
+/// Serves the profile which a recording with `--live` keeps rewriting. The
+/// profiler shows the newest snapshot when it's reloaded.
+#[tokio::main]
+pub async fn start_live_server_main(file: &Path, props: ServerProps, symbol_props: SymbolProps) {
+    start_server(
+        ServedProfiles::Live(file.to_owned()),
+        props,
+        symbol_props,
+        HashMap::new(),
+    )
+    .await;
+}
+
+/// Serves only the symbolication API, for `samply serve-symbols`. Profiles
+/// from other machines and CI jobs can be symbolicated by opening them with
+/// this server as the profiler's symbol server.
+#[tokio::main]
+pub async fn start_symbol_server_main(props: ServerProps, symbol_props: SymbolProps) {
+    start_server(ServedProfiles::None, props, symbol_props, HashMap::new()).await;
+}
+
+/// The profiles which the server makes available to the profiler.
+#[derive(Clone, Debug)]
+enum ServedProfiles {
+    None,
+    Single(PathBuf),
+    /// A profile which is being recorded. The libraries in each snapshot
+    /// become known to the symbol manager when the snapshot is requested.
+    Live(PathBuf),
+    /// The listed profiles, and the directories from which more profiles
+    /// can be added.
+    List(Arc<Mutex<Vec<PathBuf>>>, Arc<Vec<PathBuf>>),
 }
 
 const BAD_CHARS: &AsciiSet = &CONTROLS.add(b':').add(b'/');
@@ -65,7 +165,22 @@ impl PortSelection {
     }
 }
 
-fn create_symbol_manager_config(symbol_props: SymbolProps, verbose: bool) -> SymbolManagerConfig {
+/// Creates the symbol manager for the symbol props, which reports its
+/// lookups if `--symbol-debug` was given.
+pub fn create_symbol_manager(symbol_props: SymbolProps, verbose: bool) -> SymbolManager {
+    let report_path = symbol_props.symbol_debug_report.clone();
+    let mut symbol_manager =
+        SymbolManager::with_config(create_symbol_manager_config(symbol_props, verbose));
+    if let Some(report_path) = report_path {
+        symbol_manager.set_load_observer(Some(Arc::new(SymbolDebugReport::new(report_path))));
+    }
+    symbol_manager
+}
+
+pub fn create_symbol_manager_config(
+    symbol_props: SymbolProps,
+    verbose: bool,
+) -> SymbolManagerConfig {
     let _config_dir = AppDirs::new(Some(SAMPLY_NAME), true).map(|dirs| dirs.config_dir);
     let cache_base_dir = AppDirs::new(Some(SAMPLY_NAME), false).map(|dirs| dirs.cache_dir);
     let cache_base_dir = cache_base_dir.as_deref();
@@ -77,9 +192,11 @@ fn create_symbol_manager_config(symbol_props: SymbolProps, verbose: bool) -> Sym
         .use_spotlight(true);
 
     if let Some(cache_base_dir) = cache_base_dir {
-        config = config.debuginfod_cache_dir_if_not_installed(
-            cache_base_dir.join("symbols").join("debuginfod"),
-        );
+        config = config
+            .debuginfod_cache_dir_if_not_installed(
+                cache_base_dir.join("symbols").join("debuginfod"),
+            )
+            .source_cache_dir(cache_base_dir.join("sources"));
     }
 
     // TODO: Read symbol server config from some kind of config file
@@ -117,60 +234,97 @@ fn create_symbol_manager_config(symbol_props: SymbolProps, verbose: bool) -> Sym
         config = config.simpleperf_binary_cache_dir(binary_cache);
     }
 
+    if let Some(perf_build_id_cache) = perf_build_id_cache_dir() {
+        config = config.perf_build_id_cache_dir(perf_build_id_cache);
+    }
+
     for dir in symbol_props.symbol_dir {
         config = config.extra_symbols_directory(dir);
     }
 
+    if let Some(max_size) = symbol_props.symbol_cache_max_size {
+        config = config.max_cache_size(max_size);
+    }
+    if let Some(count) = symbol_props.max_downloads_per_server {
+        config = config.max_concurrent_downloads_per_server(count);
+    }
+
+    for (debug_name, backend) in symbol_props.forced_backends {
+        config = config.force_backend(debug_name, backend);
+    }
+    config = config.pdb_function_arguments(symbol_props.pdb_function_arguments);
+
     config
 }
 
 async fn start_server(
-    profile_filename: Option<&Path>,
+    served_profiles: ServedProfiles,
     server_props: ServerProps,
     symbol_props: SymbolProps,
     libinfo_map: HashMap<(String, DebugId), LibraryInfo>,
 ) {
-    let (listener, addr) = make_listener(server_props.address, server_props.port_selection).await;
+    let scheme = match server_props.tls {
+        Some(_) => "https",
+        None => "http",
+    };
+    let (listener, local_origin) = match &server_props.unix_socket {
+        Some(path) => (make_unix_listener(path), format!("{scheme}://localhost")),
+        None => {
+            let (listener, addr) =
+                make_listener(server_props.address, server_props.port_selection).await;
+            (ServerListener::Tcp(listener), format!("{scheme}://{addr}"))
+        }
+    };
+    let tls_acceptor = server_props.tls.as_ref().map(|tls_source| {
+        let mut host_names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+        if !server_props.address.is_unspecified() && !server_props.address.is_loopback() {
+            host_names.push(server_props.address.to_string());
+        }
+        host_names.extend(local_host_name());
+        match make_tls_acceptor(tls_source, host_names) {
+            Ok(acceptor) => acceptor,
+            Err(e) => {
+                eprintln!("Could not set up TLS: {e}");
+                std::process::exit(1)
+            }
+        }
+    });
 
-    let token = generate_token();
-    let path_prefix = format!("/{token}");
-    let server_origin = format!("http://{addr}");
+    let path_prefix = match &server_props.path_prefix {
+        Some(path_prefix) => format!("/{}", path_prefix.trim_matches('/')),
+        None => format!("/{}", generate_token()),
+    };
+    let server_origin = match &server_props.public_url {
+        Some(public_url) => public_url.trim_end_matches('/').to_string(),
+        None => local_origin,
+    };
     let symbol_server_url = format!("{server_origin}{path_prefix}");
     let mut template_values: HashMap<&'static str, String> = HashMap::new();
     template_values.insert("SERVER_URL", server_origin.clone());
     template_values.insert("PATH_PREFIX", path_prefix.clone());
 
-    let profiler_url = if profile_filename.is_some() {
-        let profile_url = format!("{symbol_server_url}/profile.json");
-
-        let env_profiler_override = std::env::var("PROFILER_URL").ok();
-        let profiler_origin = match &env_profiler_override {
-            Some(s) => s.trim_end_matches('/'),
-            None => "https://profiler.firefox.com",
-        };
-
-        let encoded_profile_url = utf8_percent_encode(&profile_url, BAD_CHARS).to_string();
-        let encoded_symbol_server_url =
-            utf8_percent_encode(&symbol_server_url, BAD_CHARS).to_string();
-        let profiler_url = format!(
-            "{profiler_origin}/from-url/{encoded_profile_url}/?symbolServer={encoded_symbol_server_url}"
-        );
-        template_values.insert("PROFILER_URL", profiler_url.clone());
-        template_values.insert("PROFILE_URL", profile_url);
-        Some(profiler_url)
-    } else {
-        None
+    let profiler_url = match &served_profiles {
+        ServedProfiles::Single(_) | ServedProfiles::Live(_) => {
+            let profile_url = format!("{symbol_server_url}/profile.json");
+            let profiler_url = profiler_url_for_profile(&profile_url, &symbol_server_url);
+            template_values.insert("PROFILER_URL", profiler_url.clone());
+            template_values.insert("PROFILE_URL", profile_url);
+            Some(profiler_url)
+        }
+        ServedProfiles::List(..) => Some(format!("{server_origin}/")),
+        ServedProfiles::None => None,
     };
 
     let template_values = Arc::new(template_values);
 
-    let config = create_symbol_manager_config(symbol_props, server_props.verbose);
-    let mut symbol_manager = SymbolManager::with_config(config);
+    let symbol_manager_config =
+        create_symbol_manager_config(symbol_props.clone(), server_props.verbose);
+    let mut symbol_manager = create_symbol_manager(symbol_props, server_props.verbose);
     for lib_info in libinfo_map.into_values() {
         symbol_manager.add_known_library(lib_info);
     }
 
-    if let Some(profile_filename) = profile_filename {
+    if let ServedProfiles::Single(profile_filename) = &served_profiles {
         let precog_filename = profile_filename.with_extension("syms.json");
         if let Some(precog_info) =
             shared::symbol_precog::PrecogSymbolInfo::try_load(&precog_filename)
@@ -186,24 +340,102 @@ async fn start_server(
     }
 
     let symbol_manager = Arc::new(symbol_manager);
+    let auth_token = server_props.auth_token.clone().map(Arc::new);
+    let request_limiter = Arc::new(RequestLimiter::new(server_props.request_limits));
+    let health_check = Arc::new(HealthCheck::new(
+        symbol_manager_config.cache_dirs(),
+        symbol_manager_config.server_urls(),
+    ));
+    let shutdown = GracefulShutdown::default();
+    let metrics = Arc::new(ServerMetrics::new());
+    #[cfg(feature = "grpc")]
+    let grpc_addr = match server_props.grpc_port_selection {
+        Some(port_selection) => {
+            let (grpc_listener, grpc_addr) =
+                make_listener(server_props.address, port_selection).await;
+            tokio::task::spawn(grpc_server::run_grpc_server(
+                grpc_listener,
+                symbol_manager.clone(),
+                metrics.clone(),
+                auth_token.clone(),
+                request_limiter.clone(),
+                shutdown.clone(),
+            ));
+            Some(grpc_addr)
+        }
+        None => None,
+    };
+    let is_profile_list = matches!(served_profiles, ServedProfiles::List(..));
+    let serves_symbols_only = matches!(served_profiles, ServedProfiles::None);
 
+    // The profiler which samply's links open can always load the profile.
+    let mut allowed_origins: Vec<String> = server_props
+        .allowed_origins
+        .iter()
+        .map(|origin| origin.trim_end_matches('/').to_string())
+        .collect();
+    if !allowed_origins.is_empty() {
+        allowed_origins.push(url_origin(&profiler_base_url()).to_string());
+    }
     let server = tokio::task::spawn(run_server(
         listener,
         symbol_manager,
-        profile_filename.map(PathBuf::from),
+        metrics,
+        served_profiles,
         template_values,
-        path_prefix,
+        path_prefix.clone(),
+        tls_acceptor,
+        auth_token,
+        Arc::new(allowed_origins),
+        request_limiter,
+        health_check,
+        shutdown,
     ));
 
-    eprintln!("Local server listening at {server_origin}");
-    if !server_props.open_in_browser {
+    match &server_props.unix_socket {
+        Some(path) => eprintln!("Local server listening on the Unix socket {path:?}"),
+        None => eprintln!("Local server listening at {server_origin}"),
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_addr) = grpc_addr {
+        eprintln!("gRPC symbolication service listening at {grpc_addr}");
+    }
+    // Without a public URL, the browser can't reach a server on a Unix
+    // socket.
+    let open_in_browser = server_props.open_in_browser
+        && (server_props.unix_socket.is_none() || server_props.public_url.is_some());
+    if server_props.unix_socket.is_some() && server_props.public_url.is_none() {
+        eprintln!(
+            "  The paths start with {path_prefix}. Pass --public-url with the URL of the proxy"
+        );
+        eprintln!("  in front of the socket to get a link which opens the profiler.");
+    } else if !open_in_browser {
         if let Some(profiler_url) = &profiler_url {
-            eprintln!("  Open the profiler at {profiler_url}");
+            if is_profile_list {
+                eprintln!("  Open the list of profiles at {profiler_url}");
+            } else {
+                eprintln!("  Open the profiler at {profiler_url}");
+            }
         }
     }
-    eprintln!("Press Ctrl+C to stop.");
+    if serves_symbols_only {
+        eprintln!("  The symbol server URL is {symbol_server_url}");
+        eprintln!("  Add symbolServer={symbol_server_url} to the profiler URL to use it.");
+        eprintln!("  Prometheus metrics are served at {server_origin}/metrics");
+        eprintln!("  The readiness check is at {server_origin}/health");
+    }
+    if server_props.tls == Some(TlsSource::SelfSigned) {
+        eprintln!("  The certificate is self-signed. Open {server_origin}/ and accept it first,");
+        eprintln!("  so that the profiler can load the profile.");
+    }
+    if let Some(auth_token) = &server_props.auth_token {
+        eprintln!("  Symbolication requests need the header `Authorization: Bearer {auth_token}`.");
+    }
+    if !is_profile_list {
+        eprintln!("Press Ctrl+C to stop.");
+    }
 
-    if server_props.open_in_browser {
+    if open_in_browser {
         if let Some(profiler_url) = &profiler_url {
             let _ = opener::open_browser(profiler_url);
         }
@@ -213,10 +445,42 @@ async fn start_server(
     if let Err(e) = server.await {
         eprintln!("server error: {e}");
     }
+    if let Some(path) = &server_props.unix_socket {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Returns the URL which opens the profile at `profile_url` in the profiler,
+/// symbolicated by the symbol server at `symbol_server_url`.
+fn profiler_url_for_profile(profile_url: &str, symbol_server_url: &str) -> String {
+    let profiler_origin = profiler_base_url();
+
+    let encoded_profile_url = utf8_percent_encode(profile_url, BAD_CHARS).to_string();
+    let encoded_symbol_server_url = utf8_percent_encode(symbol_server_url, BAD_CHARS).to_string();
+    format!(
+        "{profiler_origin}/from-url/{encoded_profile_url}/?symbolServer={encoded_symbol_server_url}"
+    )
+}
+
+/// The URL of the profiler which the links open, without a trailing slash.
+fn profiler_base_url() -> String {
+    match std::env::var("PROFILER_URL") {
+        Ok(s) => s.trim_end_matches('/').to_string(),
+        Err(_) => "https://profiler.firefox.com".to_string(),
+    }
+}
+
+/// The scheme, host and port of the URL.
+fn url_origin(url: &str) -> &str {
+    let after_scheme = url.find("://").map_or(0, |index| index + 3);
+    match url[after_scheme..].find('/') {
+        Some(index) => &url[..after_scheme + index],
+        None => url,
+    }
 }
 
 // Returns a base32 string for 24 random bytes.
-fn generate_token() -> String {
+pub fn generate_token() -> String {
     let mut bytes = [0u8; 24];
     rand::thread_rng().fill_bytes(&mut bytes);
     nix_base32::to_nix_base32(&bytes)
@@ -258,6 +522,61 @@ async fn make_listener(addr: IpAddr, port_selection: PortSelection) -> (TcpListe
     }
 }
 
+/// Where the server accepts connections.
+enum ServerListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+trait ServerStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ServerStream for T {}
+
+impl ServerListener {
+    /// Accepts a connection, and returns it with the client's address. There
+    /// is no address for connections over a Unix socket.
+    async fn accept(&self) -> std::io::Result<(Box<dyn ServerStream>, Option<IpAddr>)> {
+        match self {
+            ServerListener::Tcp(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                Ok((Box::new(stream), Some(addr.ip())))
+            }
+            #[cfg(unix)]
+            ServerListener::Unix(listener) => Ok((Box::new(listener.accept().await?.0), None)),
+        }
+    }
+}
+
+/// Binds the Unix socket at `path`, replacing a socket which a previous
+/// server left behind. Only the current user can connect to it.
+#[cfg(unix)]
+fn make_unix_listener(path: &Path) -> ServerListener {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        let _ = std::fs::remove_file(path);
+    }
+    let listener = match UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Could not bind to the Unix socket {path:?}: {e}");
+            std::process::exit(1)
+        }
+    };
+    if let Err(e) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)) {
+        eprintln!("Could not restrict the permissions of the Unix socket {path:?}: {e}");
+        std::process::exit(1)
+    }
+    ServerListener::Unix(listener)
+}
+
+#[cfg(not(unix))]
+fn make_unix_listener(_path: &Path) -> ServerListener {
+    eprintln!("Serving over a Unix socket is not supported on this platform.");
+    std::process::exit(1)
+}
+
 const TEMPLATE_WITH_PROFILE: &str = r#"
 <!DOCTYPE html>
 <html lang="en">
@@ -288,37 +607,89 @@ const TEMPLATE_WITHOUT_PROFILE: &str = r#"
 </ul>
 "#;
 
+const TEMPLATE_PROFILE_LIST: &str = r#"
+<!DOCTYPE html>
+<html lang="en">
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="5">
+<title>Profiler Symbol Server</title>
+<body>
+
+<p>This is the profiler symbol server, running at <code>SERVER_URL</code>. These are the profiles, most recently added first:</p>
+<ul>
+PROFILE_LIST</ul>
+<p>Symbols can be obtained by POSTing to <code>PATH_PREFIX/symbolicate/v5</code>, with the format specified by the <a href="https://tecken.readthedocs.io/en/latest/symbolication.html">Mozilla symbolication API documentation</a>.</p>
+"#;
+
+#[allow(clippy::too_many_arguments)]
 async fn run_server(
-    listener: TcpListener,
+    listener: ServerListener,
     symbol_manager: Arc<SymbolManager>,
-    profile_filename: Option<PathBuf>,
+    metrics: Arc<ServerMetrics>,
+    served_profiles: ServedProfiles,
     template_values: Arc<HashMap<&'static str, String>>,
     path_prefix: String,
+    tls_acceptor: Option<TlsAcceptor>,
+    auth_token: Option<Arc<String>>,
+    allowed_origins: Arc<Vec<String>>,
+    request_limiter: Arc<RequestLimiter>,
+    health_check: Arc<HealthCheck>,
+    shutdown: GracefulShutdown,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut ctrl_c_receiver = CtrlC::observe_oneshot();
+    // A server for a profile list runs next to `samply watch`, which needs
+    // Ctrl+C for stopping the recordings. That server runs until the process
+    // exits.
+    let ctrl_c_receiver = match served_profiles {
+        // Ctrl+C stops a live recording, and the server keeps running after
+        // it until the process exits.
+        ServedProfiles::List(..) | ServedProfiles::Live(_) => None,
+        _ => Some(CtrlC::observe_oneshot()),
+    };
+    let ctrl_c = async {
+        match ctrl_c_receiver {
+            Some(receiver) => receiver.await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(ctrl_c);
 
     // We start a loop to continuously accept incoming connections
-    loop {
-        let (stream, _) = tokio::select! {
-            stream_and_addr_res = listener.accept() => stream_and_addr_res?,
-            ctrl_c_result = &mut ctrl_c_receiver => {
-                return Ok(ctrl_c_result?);
-            }
+    let ctrl_c_result = loop {
+        let (stream, client_addr) = tokio::select! {
+            stream_res = listener.accept() => stream_res?,
+            ctrl_c_result = &mut ctrl_c => break ctrl_c_result,
         };
 
-        // Use an adapter to access something implementing `tokio::io` traits as if they implement
-        // `hyper::rt` IO traits.
-        let io = TokioIo::new(stream);
-
         let symbol_manager = symbol_manager.clone();
-        let profile_filename = profile_filename.clone();
+        let metrics = metrics.clone();
+        let served_profiles = served_profiles.clone();
         let template_values = template_values.clone();
         let path_prefix = path_prefix.clone();
+        let tls_acceptor = tls_acceptor.clone();
+        let auth_token = auth_token.clone();
+        let allowed_origins = allowed_origins.clone();
+        let request_limiter = request_limiter.clone();
+        let health_check = health_check.clone();
+        let connection_shutdown = shutdown.clone();
 
         // Spawn a tokio task to serve multiple connections concurrently
-        tokio::task::spawn(async move {
+        shutdown.spawn(async move {
+            let stream: Box<dyn ServerStream> = match tls_acceptor {
+                Some(tls_acceptor) => match tls_acceptor.accept(stream).await {
+                    Ok(stream) => Box::new(stream),
+                    // E.g. a browser which doesn't trust a self-signed
+                    // certificate yet.
+                    Err(_) => return,
+                },
+                None => stream,
+            };
+
+            // Use an adapter to access something implementing `tokio::io` traits as if they implement
+            // `hyper::rt` IO traits.
+            let io = TokioIo::new(stream);
+
             // Finally, we bind the incoming connection to our service
-            if let Err(err) = http1::Builder::new()
+            let connection = http1::Builder::new()
                 // `service_fn` converts our function in a `Service`
                 .serve_connection(
                     io,
@@ -327,27 +698,89 @@ async fn run_server(
                             req,
                             template_values.clone(),
                             symbol_manager.clone(),
-                            profile_filename.clone(),
+                            metrics.clone(),
+                            served_profiles.clone(),
                             path_prefix.clone(),
+                            auth_token.clone(),
+                            allowed_origins.clone(),
+                            request_limiter.clone(),
+                            health_check.clone(),
+                            client_addr,
                         )
                     }),
-                )
-                .await
-            {
+                );
+            tokio::pin!(connection);
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = connection_shutdown.signalled() => {
+                    // Finish the request in flight, but don't take new ones.
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(err) = result {
                 println!("Error serving connection: {:?}", err);
             }
         });
+    };
+
+    shutdown.shut_down().await;
+    Ok(ctrl_c_result?)
+}
+
+/// Lets the connections of the server finish the requests in flight when it
+/// stops, e.g. because a service manager sent SIGTERM.
+#[derive(Clone, Default)]
+pub struct GracefulShutdown {
+    signal: CancellationToken,
+    connections: TaskTracker,
+}
+
+impl GracefulShutdown {
+    /// How long the requests in flight get to finish.
+    const TIMEOUT: Duration = Duration::from_secs(20);
+
+    /// Runs the task which serves a connection.
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        self.connections.spawn(task);
+    }
+
+    /// Resolves once the server is stopping.
+    pub async fn signalled(&self) {
+        self.signal.cancelled().await
+    }
+
+    /// Tells the connections to close once their requests are done, and
+    /// waits for them.
+    async fn shut_down(&self) {
+        self.signal.cancel();
+        self.connections.close();
+        if !self.connections.is_empty() {
+            eprintln!("Waiting for the requests in flight to finish...");
+        }
+        if tokio::time::timeout(Self::TIMEOUT, self.connections.wait())
+            .await
+            .is_err()
+        {
+            eprintln!("Some requests did not finish in time.");
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn symbolication_service(
     req: Request<hyper::body::Incoming>,
     template_values: Arc<HashMap<&'static str, String>>,
     symbol_manager: Arc<SymbolManager>,
-    profile_filename: Option<PathBuf>,
+    metrics: Arc<ServerMetrics>,
+    served_profiles: ServedProfiles,
     path_prefix: String,
+    auth_token: Option<Arc<String>>,
+    allowed_origins: Arc<Vec<String>>,
+    request_limiter: Arc<RequestLimiter>,
+    health_check: Arc<HealthCheck>,
+    client_addr: Option<IpAddr>,
 ) -> Result<Response<Either<String, BoxBody<Bytes, std::io::Error>>>, hyper::Error> {
-    let has_profile = profile_filename.is_some();
     let method = req.method();
     let path = req.uri().path();
     let mut response = Response::new(Either::Left(String::new()));
@@ -360,12 +793,57 @@ async fn symbolication_service(
                     header::CONTENT_TYPE,
                     header::HeaderValue::from_static("text/html"),
                 );
-                let template = match has_profile {
-                    true => TEMPLATE_WITH_PROFILE,
-                    false => TEMPLATE_WITHOUT_PROFILE,
+                let page = match &served_profiles {
+                    ServedProfiles::Single(_) | ServedProfiles::Live(_) => {
+                        substitute_template(TEMPLATE_WITH_PROFILE, &template_values)
+                    }
+                    ServedProfiles::List(profiles, _) => {
+                        let profiles = profiles.lock().unwrap().clone();
+                        profile_list_page(&profiles, &template_values)
+                    }
+                    ServedProfiles::None => {
+                        substitute_template(TEMPLATE_WITHOUT_PROFILE, &template_values)
+                    }
+                };
+                *response.body_mut() = Either::Left(page);
+            }
+            (&Method::GET, "/metrics")
+                if auth_token
+                    .as_deref()
+                    .is_some_and(|token| !has_bearer_token(req.headers(), token)) =>
+            {
+                *response.status_mut() = StatusCode::UNAUTHORIZED;
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    header::HeaderValue::from_static("Bearer"),
+                );
+            }
+            (&Method::GET, "/metrics") => {
+                response.headers_mut().insert(
+                    header::CONTENT_TYPE,
+                    header::HeaderValue::from_static("text/plain; version=0.0.4"),
+                );
+                let text = metrics.render(&symbol_manager.symbol_file_stats());
+                *response.body_mut() = Either::Left(text);
+            }
+            (&Method::GET, "/health") => {
+                let report = health_check.report().await;
+                if !report.ready {
+                    *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                }
+                response.headers_mut().insert(
+                    header::CONTENT_TYPE,
+                    header::HeaderValue::from_static("application/json"),
+                );
+                // The details name local paths and servers, so they need the
+                // token if there is one. Probes only need the status.
+                let json = match auth_token.as_deref() {
+                    Some(token) if !has_bearer_token(req.headers(), token) => {
+                        serde_json::json!({ "ready": report.ready })
+                    }
+                    _ => serde_json::to_value(&report).unwrap_or_default(),
                 };
-                *response.body_mut() =
-                    Either::Left(substitute_template(template, &template_values));
+                *response.body_mut() = Either::Left(json.to_string());
             }
             _ => {
                 *response.status_mut() = StatusCode::NOT_FOUND;
@@ -375,15 +853,26 @@ async fn symbolication_service(
     };
 
     // If we get here, then the secret prefix was part of the URL.
-    // This part is open to the public: we allow requests across origins.
+    // This part is open to the public: we allow requests across origins,
+    // or from the configured origins.
     // For background on CORS, see this document:
     // https://w3c.github.io/webappsec-cors-for-developers/#cors
-    response.headers_mut().insert(
-        header::ACCESS_CONTROL_ALLOW_ORIGIN,
-        header::HeaderValue::from_static("*"),
-    );
+    if !allowed_origins.is_empty() {
+        // The response depends on the Origin header, so caches must not
+        // give it to other origins.
+        response
+            .headers_mut()
+            .insert(header::VARY, header::HeaderValue::from_static("Origin"));
+    }
+    if let Some(allow_origin) =
+        cors_allow_origin(&allowed_origins, req.headers().get(header::ORIGIN))
+    {
+        response
+            .headers_mut()
+            .insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+    }
 
-    match (method, path_without_prefix, profile_filename) {
+    match (method, path_without_prefix, served_profiles) {
         (&Method::OPTIONS, _, _) => {
             // https://developer.mozilla.org/en-US/docs/Web/HTTP/Methods/OPTIONS
             *response.status_mut() = StatusCode::NO_CONTENT;
@@ -416,31 +905,94 @@ async fn symbolication_service(
                 );
             }
         }
-        (&Method::GET, "/profile.json", Some(profile_filename)) => {
-            if profile_filename.extension() == Some(OsStr::new("gz")) {
-                response.headers_mut().insert(
-                    header::CONTENT_ENCODING,
-                    header::HeaderValue::from_static("gzip"),
-                );
+        (&Method::GET, "/profile.json", ServedProfiles::Single(profile_filename)) => {
+            send_profile_file(&mut response, &profile_filename).await;
+        }
+        (&Method::GET, "/profile.json", ServedProfiles::Live(profile_filename)) => {
+            if profile_filename.exists() {
+                add_known_libraries(&symbol_manager, &profile_filename).await;
+                send_profile_file(&mut response, &profile_filename).await;
+            } else {
+                // The first snapshot hasn't been written yet.
+                *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, header::HeaderValue::from_static("1"));
+            }
+        }
+        (&Method::GET, path, ServedProfiles::List(profiles, _))
+            if path.starts_with("/profiles/") =>
+        {
+            // Only the files in the list can be requested.
+            let profile_filename = find_listed_profile(&profiles.lock().unwrap(), path);
+            match profile_filename {
+                Some(profile_filename) if profile_filename.exists() => {
+                    send_profile_file(&mut response, &profile_filename).await;
+                }
+                _ => {
+                    *response.status_mut() = StatusCode::NOT_FOUND;
+                }
             }
+        }
+        (&Method::POST, _, _)
+            if auth_token
+                .as_deref()
+                .is_some_and(|token| !has_bearer_token(req.headers(), token)) =>
+        {
+            *response.status_mut() = StatusCode::UNAUTHORIZED;
             response.headers_mut().insert(
-                header::CONTENT_TYPE,
-                header::HeaderValue::from_static("application/json; charset=UTF-8"),
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Bearer"),
             );
-
-            // Stream the file. This follows the send_file example from the hyper repo.
-            // https://github.com/hyperium/hyper/blob/7206fe30302937075c51c16a69d1eb3bbce6a671/examples/send_file.rs
-            let file = tokio::fs::File::open(&profile_filename)
-                .await
-                .expect("couldn't open profile file");
-
-            // Wrap in a tokio_util::io::ReaderStream
-            let reader_stream = ReaderStream::new(file);
-
-            let stream_body = StreamBody::new(reader_stream.map_ok(Frame::data));
-            *response.body_mut() = Either::Right(stream_body.boxed());
+        }
+        (&Method::POST, "/profiles", ServedProfiles::List(profiles, addable_dirs)) => {
+            let body = req.into_body().collect().await?.to_bytes();
+            let path = String::from_utf8_lossy(&body).trim().to_string();
+            // The path is chosen by the client, so only profiles in the
+            // directories which were given to samply can be added.
+            match addable_profile_path(Path::new(&path), &addable_dirs) {
+                Some(path) => {
+                    let mut profiles = profiles.lock().unwrap();
+                    let index = match profiles.iter().position(|p| *p == path) {
+                        Some(index) => index,
+                        None => {
+                            profiles.push(path);
+                            profiles.len() - 1
+                        }
+                    };
+                    let urls = listed_profile_urls(&profiles, index, &template_values);
+                    response.headers_mut().insert(
+                        header::CONTENT_TYPE,
+                        header::HeaderValue::from_static("application/json"),
+                    );
+                    let (profile_url, profiler_url) = urls.unwrap_or_default();
+                    *response.body_mut() = Either::Left(
+                        serde_json::json!({
+                            "profileUrl": profile_url,
+                            "profilerUrl": profiler_url,
+                        })
+                        .to_string(),
+                    );
+                }
+                None => {
+                    *response.status_mut() = StatusCode::FORBIDDEN;
+                    *response.body_mut() =
+                        Either::Left(format!("{path:?} is not a profile which can be added\n"));
+                }
+            }
         }
         (&Method::POST, path, _) => {
+            if let Err(wait) = request_limiter.check_rate(client_addr, Instant::now()) {
+                *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                response.headers_mut().insert(
+                    header::RETRY_AFTER,
+                    header::HeaderValue::from(retry_after_secs(wait)),
+                );
+                return Ok(response);
+            }
+            // Wait for a turn before reading the body, so that waiting
+            // requests don't take up memory.
+            let _permit = request_limiter.acquire().await;
             response.headers_mut().insert(
                 header::CONTENT_TYPE,
                 header::HeaderValue::from_static("application/json"),
@@ -451,7 +1003,9 @@ async fn symbolication_service(
             // Convert the `Collected<Bytes>` into a `String`.
             let full_body =
                 String::from_utf8(full_body.to_bytes().to_vec()).expect("invalid utf-8");
+            let start = Instant::now();
             let response_json = symbol_manager.query_json_api(&path, &full_body).await;
+            metrics.record_request(&path, start.elapsed());
 
             *response.body_mut() = Either::Left(response_json);
         }
@@ -463,6 +1017,187 @@ async fn symbolication_service(
     Ok(response)
 }
 
+/// The `Access-Control-Allow-Origin` value for a request from `origin`, or
+/// `None` if pages from that origin may not read the response.
+pub fn cors_allow_origin(
+    allowed_origins: &[String],
+    origin: Option<&header::HeaderValue>,
+) -> Option<header::HeaderValue> {
+    if allowed_origins.is_empty() {
+        return Some(header::HeaderValue::from_static("*"));
+    }
+    let origin = origin?;
+    allowed_origins
+        .iter()
+        .any(|allowed| allowed.as_bytes() == origin.as_bytes())
+        .then(|| origin.clone())
+}
+
+/// Checks the `Authorization: Bearer` header of a request. The comparison
+/// takes the same time wherever the tokens differ.
+pub fn has_bearer_token(headers: &header::HeaderMap, token: &str) -> bool {
+    let Some(value) = headers.get(header::AUTHORIZATION) else {
+        return false;
+    };
+    let Some(given) = value.as_bytes().strip_prefix(b"Bearer ") else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token.as_bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Tells the symbol manager about the libraries in the profile, so that it
+/// can find their files.
+async fn add_known_libraries(symbol_manager: &SymbolManager, profile_filename: &Path) {
+    let profile_filename = profile_filename.to_owned();
+    let libinfo_map = tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&profile_filename).ok()?;
+        parse_libinfo_map_from_profile_file(file, &profile_filename).ok()
+    })
+    .await;
+    if let Ok(Some(libinfo_map)) = libinfo_map {
+        for lib_info in libinfo_map.into_values() {
+            symbol_manager.add_known_library(lib_info);
+        }
+    }
+}
+
+async fn send_profile_file(
+    response: &mut Response<Either<String, BoxBody<Bytes, std::io::Error>>>,
+    profile_filename: &Path,
+) {
+    if profile_filename.extension() == Some(OsStr::new("gz")) {
+        response.headers_mut().insert(
+            header::CONTENT_ENCODING,
+            header::HeaderValue::from_static("gzip"),
+        );
+    }
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json; charset=UTF-8"),
+    );
+
+    // Stream the file. This follows the send_file example from the hyper repo.
+    // https://github.com/hyperium/hyper/blob/7206fe30302937075c51c16a69d1eb3bbce6a671/examples/send_file.rs
+    let file = tokio::fs::File::open(profile_filename)
+        .await
+        .expect("couldn't open profile file");
+
+    // Wrap in a tokio_util::io::ReaderStream
+    let reader_stream = ReaderStream::new(file);
+
+    let stream_body = StreamBody::new(reader_stream.map_ok(Frame::data));
+    *response.body_mut() = Either::Right(stream_body.boxed());
+}
+
+/// Renders the index page of a profile list, with the newest profile first.
+fn profile_list_page(
+    profiles: &[PathBuf],
+    template_values: &HashMap<&'static str, String>,
+) -> String {
+    let mut items = String::new();
+    for (index, profile) in profiles.iter().enumerate().rev() {
+        let Some((profile_url, profiler_url)) =
+            listed_profile_urls(profiles, index, template_values)
+        else {
+            continue;
+        };
+        let name = profile.file_name().unwrap_or_default().to_string_lossy();
+        let has_same_name = |p: &&PathBuf| p.file_name() == profile.file_name();
+        let label = match profile.parent() {
+            Some(dir) if profiles.iter().filter(has_same_name).count() > 1 => {
+                format!("{name} (in {})", dir.display())
+            }
+            _ => name.into_owned(),
+        };
+        items += &format!(
+            "    <li><a href=\"{}\">{}</a> (<a download href=\"{}\">raw JSON</a>)</li>\n",
+            escape_html(&profiler_url),
+            escape_html(&label),
+            escape_html(&profile_url),
+        );
+    }
+    if items.is_empty() {
+        items = "    <li>No profiles have been recorded yet.</li>\n".to_string();
+    }
+    substitute_template(TEMPLATE_PROFILE_LIST, template_values).replace("PROFILE_LIST", &items)
+}
+
+/// The URL path of the profile at `index` in the list. Profiles with the same
+/// file name, e.g. profile.json files from different directories, are told
+/// apart by the number of profiles with that name before them.
+fn listed_profile_url_path(profiles: &[PathBuf], index: usize) -> Option<String> {
+    let name = profiles[index].file_name()?;
+    let number = profiles[..index]
+        .iter()
+        .filter(|p| p.file_name() == Some(name))
+        .count();
+    let encoded_name = utf8_percent_encode(name.to_str()?, BAD_CHARS);
+    Some(format!("/profiles/{number}/{encoded_name}"))
+}
+
+/// Returns the URL of the listed profile, and the URL which opens it in the
+/// profiler.
+fn listed_profile_urls(
+    profiles: &[PathBuf],
+    index: usize,
+    template_values: &HashMap<&'static str, String>,
+) -> Option<(String, String)> {
+    let symbol_server_url = format!(
+        "{}{}",
+        template_values["SERVER_URL"], template_values["PATH_PREFIX"]
+    );
+    let url_path = listed_profile_url_path(profiles, index)?;
+    let profile_url = format!("{symbol_server_url}{url_path}");
+    let profiler_url = profiler_url_for_profile(&profile_url, &symbol_server_url);
+    Some((profile_url, profiler_url))
+}
+
+/// The inverse of [`listed_profile_url_path`].
+fn find_listed_profile(profiles: &[PathBuf], url_path: &str) -> Option<PathBuf> {
+    let (number, name) = url_path.strip_prefix("/profiles/")?.split_once('/')?;
+    let number: usize = number.parse().ok()?;
+    let name = percent_decode_str(name).decode_utf8().ok()?;
+    profiles
+        .iter()
+        .filter(|p| p.file_name() == Some(OsStr::new(name.as_ref())))
+        .nth(number)
+        .cloned()
+}
+
+/// Returns the canonical path of the profile at `path` if it can be added to
+/// the list: it has to be a .json, .json.gz or .json.zst file in one of
+/// `addable_dirs`, or in a subdirectory of one.
+fn addable_profile_path(path: &Path, addable_dirs: &[PathBuf]) -> Option<PathBuf> {
+    let path = std::fs::canonicalize(path).ok()?;
+    if !path.is_file() {
+        return None;
+    }
+    let name = path.file_name()?.to_str()?;
+    if ![".json", ".json.gz", ".json.zst"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+    {
+        return None;
+    }
+    let is_in_addable_dir = addable_dirs
+        .iter()
+        .filter_map(|dir| std::fs::canonicalize(dir).ok())
+        .any(|dir| path.starts_with(dir));
+    is_in_addable_dir.then_some(path)
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 fn substitute_template(template: &str, template_values: &HashMap<&'static str, String>) -> String {
     let mut s = template.to_string();
     for (key, value) in template_values {
@@ -470,3 +1205,79 @@ fn substitute_template(template: &str, template_values: &HashMap<&'static str, S
     }
     s
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bearer_token_check() {
+        let mut headers = header::HeaderMap::new();
+        assert!(!has_bearer_token(&headers, "secret"));
+        headers.insert(
+            header::AUTHORIZATION,
+            header::HeaderValue::from_static("Bearer secret"),
+        );
+        assert!(has_bearer_token(&headers, "secret"));
+        assert!(!has_bearer_token(&headers, "secreT"));
+        assert!(!has_bearer_token(&headers, "secret2"));
+        headers.insert(
+            header::AUTHORIZATION,
+            header::HeaderValue::from_static("Basic secret"),
+        );
+        assert!(!has_bearer_token(&headers, "secret"));
+    }
+
+    #[test]
+    fn cors_origins() {
+        let profiler = header::HeaderValue::from_static("https://profiler.example.com");
+        let other = header::HeaderValue::from_static("https://other.example.com");
+        assert_eq!(
+            cors_allow_origin(&[], Some(&other)),
+            Some(header::HeaderValue::from_static("*"))
+        );
+        let allowed = ["https://profiler.example.com".to_string()];
+        assert_eq!(
+            cors_allow_origin(&allowed, Some(&profiler)),
+            Some(profiler.clone())
+        );
+        assert_eq!(cors_allow_origin(&allowed, Some(&other)), None);
+        assert_eq!(cors_allow_origin(&allowed, None), None);
+
+        assert_eq!(
+            url_origin("https://profiler.example.com:8443/profiler/"),
+            "https://profiler.example.com:8443"
+        );
+        assert_eq!(url_origin("http://localhost:4242"), "http://localhost:4242");
+    }
+
+    #[test]
+    fn addable_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let profiles_dir = dir.path().join("profiles");
+        std::fs::create_dir(&profiles_dir).unwrap();
+        for name in ["profile.json", "profile.json.gz", "notes.txt"] {
+            std::fs::write(profiles_dir.join(name), "{}").unwrap();
+        }
+        std::fs::write(dir.path().join("other.json"), "{}").unwrap();
+        let addable_dirs = [profiles_dir.clone()];
+
+        let profile = addable_profile_path(&profiles_dir.join("profile.json"), &addable_dirs);
+        assert_eq!(
+            profile,
+            Some(std::fs::canonicalize(profiles_dir.join("profile.json")).unwrap())
+        );
+        assert!(
+            addable_profile_path(&profiles_dir.join("profile.json.gz"), &addable_dirs).is_some()
+        );
+        assert!(addable_profile_path(&profiles_dir.join("notes.txt"), &addable_dirs).is_none());
+        assert!(addable_profile_path(&profiles_dir.join("missing.json"), &addable_dirs).is_none());
+        assert!(addable_profile_path(&dir.path().join("other.json"), &addable_dirs).is_none());
+        assert!(
+            addable_profile_path(&profiles_dir.join("..").join("other.json"), &addable_dirs)
+                .is_none()
+        );
+        assert!(addable_profile_path(Path::new("/etc/passwd"), &addable_dirs).is_none());
+        assert!(addable_profile_path(&profiles_dir.join("profile.json"), &[]).is_none());
+    }
+}
@@ -0,0 +1,145 @@
+//! The `/health` endpoint of the server, for the readiness probes of service
+//! managers like systemd or Kubernetes: whether the cache directories can be
+//! written to, and whether the symbol servers can be reached.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_derive::Serialize;
+use wholesym::CloudStore;
+
+/// How long the symbol servers' reachability is remembered, so that frequent
+/// probes don't send a request to every symbol server each time.
+const SERVER_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a symbol server has to answer.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The name of the file which is written to check that a cache directory is
+/// writable.
+const PROBE_FILE_NAME: &str = ".samply-health-check";
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct HealthReport {
+    /// Whether all cache directories are writable, and at least one of the
+    /// symbol servers, if there are any, is reachable. A server which is
+    /// down only means that some symbols are missing.
+    pub ready: bool,
+    pub cache_dirs: Vec<CacheDirHealth>,
+    pub symbol_servers: Vec<SymbolServerHealth>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct CacheDirHealth {
+    pub path: String,
+    pub writable: bool,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct SymbolServerHealth {
+    pub url: String,
+    /// Whether the server answered at all. Any HTTP status counts.
+    pub reachable: bool,
+}
+
+pub struct HealthCheck {
+    cache_dirs: Vec<PathBuf>,
+    symbol_servers: Vec<String>,
+    last_server_check: Mutex<Option<(Instant, Vec<SymbolServerHealth>)>>,
+}
+
+impl HealthCheck {
+    pub fn new(cache_dirs: Vec<PathBuf>, symbol_servers: Vec<String>) -> Self {
+        Self {
+            cache_dirs,
+            symbol_servers,
+            last_server_check: Mutex::new(None),
+        }
+    }
+
+    pub async fn report(&self) -> HealthReport {
+        let mut cache_dirs = Vec::new();
+        for dir in &self.cache_dirs {
+            cache_dirs.push(CacheDirHealth {
+                path: dir.to_string_lossy().into_owned(),
+                writable: is_writable(dir).await,
+            });
+        }
+        let symbol_servers = self.symbol_server_health().await;
+        HealthReport {
+            ready: is_ready(&cache_dirs, &symbol_servers),
+            cache_dirs,
+            symbol_servers,
+        }
+    }
+
+    async fn symbol_server_health(&self) -> Vec<SymbolServerHealth> {
+        if let Some((checked_at, servers)) = &*self.last_server_check.lock().unwrap() {
+            if checked_at.elapsed() < SERVER_CHECK_INTERVAL {
+                return servers.clone();
+            }
+        }
+        let client = reqwest::Client::builder()
+            .timeout(SERVER_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        let mut servers = Vec::new();
+        for url in &self.symbol_servers {
+            let http_url = match CloudStore::from_url(url) {
+                Some(cloud_store) => cloud_store.url_for_path(""),
+                None => url.clone(),
+            };
+            servers.push(SymbolServerHealth {
+                url: url.clone(),
+                reachable: client.head(&http_url).send().await.is_ok(),
+            });
+        }
+        *self.last_server_check.lock().unwrap() = Some((Instant::now(), servers.clone()));
+        servers
+    }
+}
+
+fn is_ready(cache_dirs: &[CacheDirHealth], symbol_servers: &[SymbolServerHealth]) -> bool {
+    cache_dirs.iter().all(|dir| dir.writable)
+        && (symbol_servers.is_empty() || symbol_servers.iter().any(|server| server.reachable))
+}
+
+/// Checks that a file can be created in the directory, creating the directory
+/// if needed, like a download would.
+async fn is_writable(dir: &std::path::Path) -> bool {
+    let probe_path = dir.join(PROBE_FILE_NAME);
+    let writable = tokio::fs::create_dir_all(dir).await.is_ok()
+        && tokio::fs::write(&probe_path, b"").await.is_ok();
+    let _ = tokio::fs::remove_file(&probe_path).await;
+    writable
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn writable_cache_dirs_are_ready() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+
+        let health = HealthCheck::new(vec![dir.path().join("symbols")], vec![]);
+        let report = health.report().await;
+        assert!(report.ready);
+        assert!(report.cache_dirs[0].writable);
+        assert!(!dir.path().join("symbols").join(PROBE_FILE_NAME).exists());
+
+        // A directory can't be created below a file.
+        let health = HealthCheck::new(vec![file.join("symbols")], vec![]);
+        assert!(!health.report().await.ready);
+
+        let server = |reachable| SymbolServerHealth {
+            url: String::new(),
+            reachable,
+        };
+        assert!(is_ready(&[], &[server(false), server(true)]));
+        assert!(!is_ready(&[], &[server(false)]));
+    }
+}
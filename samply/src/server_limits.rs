@@ -0,0 +1,128 @@
+//! Limits on the symbolication requests which the server handles, so that
+//! one client can't make the server download and parse so many symbol files
+//! at once that it runs out of memory.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Above this many tracked clients, the ones which haven't used any of their
+/// rate are forgotten.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestLimits {
+    /// The number of symbolication requests which are handled at the same
+    /// time. More requests wait until one of them is done.
+    pub max_concurrent_requests: Option<usize>,
+    /// The number of symbolication requests per minute from one client
+    /// address. Short bursts of up to this many requests are allowed.
+    pub max_requests_per_minute: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct RequestLimiter {
+    concurrency: Option<Arc<Semaphore>>,
+    requests_per_minute: Option<u32>,
+    clients: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+/// The requests which a client can still make right now, refilled at the
+/// rate of the limit.
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RequestLimiter {
+    pub fn new(limits: RequestLimits) -> Self {
+        Self {
+            concurrency: limits
+                .max_concurrent_requests
+                .map(|count| Arc::new(Semaphore::new(count.max(1)))),
+            requests_per_minute: limits.max_requests_per_minute,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request from `client` against its rate. Returns how long the
+    /// client has to wait if it has made too many requests. Clients without
+    /// an address, e.g. on a Unix socket, aren't limited.
+    pub fn check_rate(&self, client: Option<IpAddr>, now: Instant) -> Result<(), Duration> {
+        let (Some(client), Some(per_minute)) = (client, self.requests_per_minute) else {
+            return Ok(());
+        };
+        let capacity = f64::from(per_minute.max(1));
+        let tokens_per_sec = capacity / 60.0;
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(&client) {
+            clients.retain(|_, bucket| bucket.refilled(now, tokens_per_sec, capacity) < capacity);
+        }
+        let bucket = clients.entry(client).or_insert(TokenBucket {
+            tokens: capacity,
+            last_refill: now,
+        });
+        bucket.tokens = bucket.refilled(now, tokens_per_sec, capacity);
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / tokens_per_sec;
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+
+    /// Waits until fewer than the maximum number of requests are handled.
+    /// The request counts as handled until the returned permit is dropped.
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        let semaphore = self.concurrency.clone()?;
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+impl TokenBucket {
+    fn refilled(&self, now: Instant, tokens_per_sec: f64, capacity: f64) -> f64 {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        (self.tokens + elapsed.as_secs_f64() * tokens_per_sec).min(capacity)
+    }
+}
+
+/// The value of a `Retry-After` header: whole seconds, rounded up.
+pub fn retry_after_secs(wait: Duration) -> u64 {
+    wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rate_limit_per_client() {
+        let limiter = RequestLimiter::new(RequestLimits {
+            max_concurrent_requests: None,
+            max_requests_per_minute: Some(2),
+        });
+        let a = Some(IpAddr::from([10, 0, 0, 1]));
+        let b = Some(IpAddr::from([10, 0, 0, 2]));
+        let start = Instant::now();
+        assert!(limiter.check_rate(a, start).is_ok());
+        assert!(limiter.check_rate(a, start).is_ok());
+        let wait = limiter.check_rate(a, start).unwrap_err();
+        assert_eq!(retry_after_secs(wait), 30);
+        // Other clients and clients without an address have their own rate.
+        assert!(limiter.check_rate(b, start).is_ok());
+        assert!(limiter.check_rate(None, start).is_ok());
+        // One request per 30 seconds comes back.
+        assert!(limiter
+            .check_rate(a, start + Duration::from_secs(30))
+            .is_ok());
+        assert!(limiter
+            .check_rate(a, start + Duration::from_secs(31))
+            .is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! The `/metrics` endpoint of the server, in the Prometheus text format: how
+//! many symbolication API requests there were and how long they took, and
+//! how many symbol files were found in the caches or downloaded. This is for
+//! monitoring samply when it runs as a long-lived symbol server.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use wholesym::SymbolFileStats;
+
+/// The upper bounds of the buckets of the request duration histogram, in
+/// seconds. Requests which need downloads can take many seconds.
+const DURATION_BUCKETS: [f64; 9] = [0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0, 10.0, 30.0];
+
+/// The API paths which get their own label, including the paths of the gRPC
+/// methods. Others are counted as "other", so that arbitrary request paths
+/// don't create new time series.
+const ENDPOINTS: [&str; 9] = [
+    "/symbolicate/v5",
+    "/source/v1",
+    "/asm/v1",
+    "/functions/v1",
+    "/symbol-table/v1",
+    "/samply.symbolication.v1.Symbolication/Symbolicate",
+    "/samply.symbolication.v1.Symbolication/SymbolicateStream",
+    "/samply.symbolication.v1.Symbolication/QueryAsm",
+    "/samply.symbolication.v1.Symbolication/QuerySource",
+];
+
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    endpoints: Mutex<BTreeMap<&'static str, EndpointMetrics>>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct EndpointMetrics {
+    /// The number of requests in each bucket of `DURATION_BUCKETS`, not
+    /// cumulative.
+    buckets: [u64; DURATION_BUCKETS.len()],
+    count: u64,
+    duration_sum: f64,
+}
+
+impl ServerMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an API request for `path`, which is the path after the secret
+    /// prefix, or the path of a gRPC method.
+    pub fn record_request(&self, path: &str, duration: Duration) {
+        let endpoint = ENDPOINTS
+            .into_iter()
+            .find(|endpoint| *endpoint == path)
+            .unwrap_or("other");
+        let seconds = duration.as_secs_f64();
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let metrics = endpoints.entry(endpoint).or_default();
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|le| seconds <= *le) {
+            metrics.buckets[bucket] += 1;
+        }
+        metrics.count += 1;
+        metrics.duration_sum += seconds;
+    }
+
+    /// The text for the `/metrics` response.
+    pub fn render(&self, file_stats: &SymbolFileStats) -> String {
+        let endpoints = self.endpoints.lock().unwrap().clone();
+        let mut text = String::new();
+
+        let name = "samply_symbolication_request_duration_seconds";
+        let _ = writeln!(
+            text,
+            "# HELP {name} The duration of the symbolication API requests, by endpoint."
+        );
+        let _ = writeln!(text, "# TYPE {name} histogram");
+        for (endpoint, metrics) in &endpoints {
+            let mut cumulative = 0;
+            for (le, count) in DURATION_BUCKETS.iter().zip(metrics.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    text,
+                    "{name}_bucket{{endpoint=\"{endpoint}\",le=\"{le}\"}} {cumulative}"
+                );
+            }
+            let count = metrics.count;
+            let _ = writeln!(
+                text,
+                "{name}_bucket{{endpoint=\"{endpoint}\",le=\"+Inf\"}} {count}"
+            );
+            let sum = metrics.duration_sum;
+            let _ = writeln!(text, "{name}_sum{{endpoint=\"{endpoint}\"}} {sum}");
+            let _ = writeln!(text, "{name}_count{{endpoint=\"{endpoint}\"}} {count}");
+        }
+
+        let counters = [
+            (
+                "samply_symbol_cache_hits_total",
+                "Symbol files which were found in a cache directory.",
+                file_stats.cache_hits,
+            ),
+            (
+                "samply_symbol_downloads_total",
+                "Symbol files which were requested from a symbol server or debuginfod.",
+                file_stats.downloads,
+            ),
+            (
+                "samply_symbol_download_failures_total",
+                "Symbol file downloads which did not produce a file.",
+                file_stats.failed_downloads,
+            ),
+            (
+                "samply_symbol_downloaded_bytes_total",
+                "The size of the downloaded symbol files.",
+                file_stats.downloaded_bytes,
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(text, "# HELP {name} {help}");
+            let _ = writeln!(text, "# TYPE {name} counter");
+            let _ = writeln!(text, "{name} {value}");
+        }
+
+        let lookups = file_stats.cache_hits + file_stats.downloads;
+        if lookups > 0 {
+            let name = "samply_symbol_cache_hit_ratio";
+            let ratio = file_stats.cache_hits as f64 / lookups as f64;
+            let _ = writeln!(
+                text,
+                "# HELP {name} The share of the symbol files which did not need a download."
+            );
+            let _ = writeln!(text, "# TYPE {name} gauge");
+            let _ = writeln!(text, "{name} {ratio}");
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_metrics() {
+        let metrics = ServerMetrics::new();
+        metrics.record_request("/symbolicate/v5", Duration::from_millis(30));
+        metrics.record_request("/symbolicate/v5", Duration::from_secs(2));
+        metrics.record_request("/unknown", Duration::from_millis(1));
+        metrics.record_request(
+            "/samply.symbolication.v1.Symbolication/Symbolicate",
+            Duration::from_millis(20),
+        );
+        let text = metrics.render(&SymbolFileStats {
+            cache_hits: 3,
+            downloads: 1,
+            failed_downloads: 0,
+            downloaded_bytes: 4096,
+        });
+        let lines: Vec<&str> = text.lines().collect();
+        let has_line = |line: &str| lines.contains(&line);
+        assert!(has_line(
+            "samply_symbolication_request_duration_seconds_bucket{endpoint=\"/symbolicate/v5\",le=\"0.01\"} 0"
+        ));
+        assert!(has_line(
+            "samply_symbolication_request_duration_seconds_bucket{endpoint=\"/symbolicate/v5\",le=\"0.05\"} 1"
+        ));
+        assert!(has_line(
+            "samply_symbolication_request_duration_seconds_bucket{endpoint=\"/symbolicate/v5\",le=\"5\"} 2"
+        ));
+        assert!(has_line(
+            "samply_symbolication_request_duration_seconds_count{endpoint=\"/symbolicate/v5\"} 2"
+        ));
+        assert!(has_line(
+            "samply_symbolication_request_duration_seconds_count{endpoint=\"other\"} 1"
+        ));
+        assert!(has_line(
+            "samply_symbolication_request_duration_seconds_count{endpoint=\"/samply.symbolication.v1.Symbolication/Symbolicate\"} 1"
+        ));
+        assert!(has_line("samply_symbol_downloaded_bytes_total 4096"));
+        assert!(has_line("samply_symbol_cache_hit_ratio 0.75"));
+    }
+}
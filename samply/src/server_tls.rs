@@ -0,0 +1,171 @@
+//! TLS for the local server, so that it can be reached from other machines
+//! without sending the profile and the secret path prefix in the clear.
+//!
+//! The certificate is either given by the user, or self-signed. The
+//! self-signed certificate is kept in the cache directory, so that the
+//! exception which the browser has for it keeps working across runs.
+
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use platform_dirs::AppDirs;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+use crate::name::SAMPLY_NAME;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlsSource {
+    /// A self-signed certificate for the given host names.
+    SelfSigned,
+    /// PEM files with the certificate chain and the private key.
+    Files { cert: PathBuf, key: PathBuf },
+}
+
+/// Creates the acceptor for the certificate. `host_names` are the names and
+/// addresses which a self-signed certificate is valid for.
+pub fn make_tls_acceptor(
+    source: &TlsSource,
+    host_names: Vec<String>,
+) -> Result<TlsAcceptor, String> {
+    let (cert_pem, key_pem) = match source {
+        TlsSource::Files { cert, key } => (read_pem_file(cert)?, read_pem_file(key)?),
+        TlsSource::SelfSigned => cached_self_signed_certificate(host_names)?,
+    };
+    acceptor_from_pem(&cert_pem, &key_pem)
+}
+
+fn read_pem_file(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Could not read {path:?}: {e}"))
+}
+
+fn acceptor_from_pem(cert_pem: &str, key_pem: &str) -> Result<TlsAcceptor, String> {
+    let certs: Vec<CertificateDer<'static>> =
+        rustls_pemfile::certs(&mut BufReader::new(cert_pem.as_bytes()))
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Could not parse the certificate: {e}"))?;
+    if certs.is_empty() {
+        return Err("The certificate file contains no certificates".to_string());
+    }
+    let key: PrivateKeyDer<'static> =
+        rustls_pemfile::private_key(&mut BufReader::new(key_pem.as_bytes()))
+            .map_err(|e| format!("Could not parse the private key: {e}"))?
+            .ok_or_else(|| "The key file contains no private key".to_string())?;
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("The certificate can't be used: {e}"))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Returns the PEM of the certificate and of the private key.
+fn self_signed_certificate(host_names: Vec<String>) -> Result<(String, String), String> {
+    let cert = rcgen::generate_simple_self_signed(host_names)
+        .map_err(|e| format!("Could not generate a certificate: {e}"))?;
+    let cert_pem = cert
+        .serialize_pem()
+        .map_err(|e| format!("Could not serialize the certificate: {e}"))?;
+    Ok((cert_pem, cert.serialize_private_key_pem()))
+}
+
+/// Loads the self-signed certificate for these host names from the cache
+/// directory, or generates and stores it.
+fn cached_self_signed_certificate(host_names: Vec<String>) -> Result<(String, String), String> {
+    let Some(dir) = AppDirs::new(Some(SAMPLY_NAME), false).map(|dirs| dirs.cache_dir.join("tls"))
+    else {
+        return self_signed_certificate(host_names);
+    };
+    let file_stem: String = host_names
+        .join("_")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let cert_path = dir.join(format!("{file_stem}.cert.pem"));
+    let key_path = dir.join(format!("{file_stem}.key.pem"));
+    if let (Ok(cert_pem), Ok(key_pem)) = (
+        std::fs::read_to_string(&cert_path),
+        std::fs::read_to_string(&key_path),
+    ) {
+        return Ok((cert_pem, key_pem));
+    }
+
+    let (cert_pem, key_pem) = self_signed_certificate(host_names)?;
+    let stored = std::fs::create_dir_all(&dir)
+        .and_then(|_| write_private_file(&key_path, &key_pem))
+        .and_then(|_| std::fs::write(&cert_path, &cert_pem));
+    if let Err(e) = stored {
+        eprintln!("Could not store the self-signed certificate in {dir:?}: {e}");
+    }
+    Ok((cert_pem, key_pem))
+}
+
+/// Writes a file which only the current user can read.
+fn write_private_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, contents.as_bytes())
+}
+
+/// The name of this machine, for the self-signed certificate.
+pub fn local_host_name() -> Option<String> {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+            return None;
+        }
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        String::from_utf8(buf[..len].to_vec())
+            .ok()
+            .filter(|name| !name.is_empty())
+    }
+    #[cfg(not(unix))]
+    {
+        std::env::var("COMPUTERNAME").ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn self_signed_handshake() {
+        let (cert_pem, key_pem) = self_signed_certificate(vec!["localhost".to_string()]).unwrap();
+        let acceptor = acceptor_from_pem(&cert_pem, &key_pem).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut BufReader::new(cert_pem.as_bytes())) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let client_config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(client_config));
+
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        let server = tokio::spawn(async move {
+            let mut stream = acceptor.accept(server_io).await.unwrap();
+            let mut buf = [0u8; 4];
+            stream.read_exact(&mut buf).await.unwrap();
+            buf
+        });
+        let server_name = ServerName::try_from("localhost").unwrap();
+        let mut client = connector.connect(server_name, client_io).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        client.flush().await.unwrap();
+        assert_eq!(&server.await.unwrap(), b"ping");
+
+        assert!(acceptor_from_pem("", &key_pem).is_err());
+    }
+}
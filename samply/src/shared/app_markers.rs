@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use fxprof_processed_profile::{
+    MarkerDynamicField, MarkerFieldFormat, MarkerLocation, MarkerSchema, MarkerSchemaField,
+    MarkerStaticField, ProfilerMarker,
+};
+use serde_json::json;
+
+/// The environment variable with the path of the Unix socket to which the
+/// profiled process can send markers. samply sets it when it launches the
+/// process.
+///
+/// Every line which is written to the socket is one marker message:
+///
+/// ```text
+/// <B|E|I> <timestamp> <tid> <name>[\t<payload>]
+/// ```
+///
+/// `B` begins an interval marker and `E` ends the most recent interval with
+/// the same name on the same thread. `I` is an instant marker. The timestamp
+/// is in nanoseconds from `CLOCK_MONOTONIC`, like the timestamps in marker
+/// files. The optional payload after a tab is shown in the marker's tooltip.
+///
+/// `C <timestamp> <tid> <name>\t<delta>` adds `delta` to the process's
+/// counter with this name.
+#[allow(unused)] // Only used on Linux so far.
+pub const MARKER_SOCKET_ENV_VAR: &str = "SAMPLY_MARKER_SOCKET";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppMarkerEventKind {
+    Begin,
+    End,
+    Instant,
+    Counter,
+}
+
+/// A message which the profiled process sent over the marker socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppMarkerEvent {
+    pub kind: AppMarkerEventKind,
+    /// The event timestamp, in the same clock as the raw sample timestamps.
+    pub timestamp_raw: u64,
+    pub tid: i32,
+    pub name: String,
+    pub payload: Option<String>,
+}
+
+pub fn parse_app_marker_message(line: &str) -> Option<AppMarkerEvent> {
+    let mut split = line.splitn(4, ' ');
+    let kind = match split.next()? {
+        "B" => AppMarkerEventKind::Begin,
+        "E" => AppMarkerEventKind::End,
+        "I" => AppMarkerEventKind::Instant,
+        "C" => AppMarkerEventKind::Counter,
+        _ => return None,
+    };
+    let timestamp_raw = split.next()?.parse().ok()?;
+    let tid = split.next()?.parse().ok()?;
+    let rest = split.next()?;
+    let (name, payload) = match rest.split_once('\t') {
+        Some((name, payload)) => (name, Some(payload.to_owned())),
+        None => (rest, None),
+    };
+    if name.is_empty() {
+        return None;
+    }
+    Some(AppMarkerEvent {
+        kind,
+        timestamp_raw,
+        tid,
+        name: name.to_owned(),
+        payload,
+    })
+}
+
+/// An app marker which is ready to be added to the profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletedAppMarker {
+    pub tid: i32,
+    pub start_raw: u64,
+    /// `None` for instant markers.
+    pub end_raw: Option<u64>,
+    pub name: String,
+    pub payload: Option<String>,
+}
+
+/// Matches the begin and end messages of interval markers. Begin messages
+/// which are never ended don't produce a marker.
+#[derive(Debug, Default)]
+pub struct AppMarkerPairer {
+    open_intervals: HashMap<(i32, String), Vec<OpenInterval>>,
+}
+
+#[derive(Debug)]
+struct OpenInterval {
+    start_raw: u64,
+    payload: Option<String>,
+}
+
+impl AppMarkerPairer {
+    /// Returns the marker which this event completes. Counter events are
+    /// ignored.
+    pub fn handle_event(&mut self, event: AppMarkerEvent) -> Option<CompletedAppMarker> {
+        let AppMarkerEvent {
+            kind,
+            timestamp_raw,
+            tid,
+            name,
+            payload,
+        } = event;
+        match kind {
+            AppMarkerEventKind::Counter => None,
+            AppMarkerEventKind::Instant => Some(CompletedAppMarker {
+                tid,
+                start_raw: timestamp_raw,
+                end_raw: None,
+                name,
+                payload,
+            }),
+            AppMarkerEventKind::Begin => {
+                self.open_intervals
+                    .entry((tid, name))
+                    .or_default()
+                    .push(OpenInterval {
+                        start_raw: timestamp_raw,
+                        payload,
+                    });
+                None
+            }
+            AppMarkerEventKind::End => {
+                let begin = self.open_intervals.get_mut(&(tid, name.clone()))?.pop()?;
+                Some(CompletedAppMarker {
+                    tid,
+                    start_raw: begin.start_raw,
+                    end_raw: Some(timestamp_raw),
+                    name,
+                    payload: payload.or(begin.payload),
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AppMarker {
+    pub payload: Option<String>,
+}
+
+impl ProfilerMarker for AppMarker {
+    const MARKER_TYPE_NAME: &'static str = "AppMarker";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "payload": self.payload.as_deref().unwrap_or_default(),
+        })
+    }
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![
+                MarkerLocation::MarkerChart,
+                MarkerLocation::MarkerTable,
+                MarkerLocation::TimelineOverview,
+            ],
+            chart_label: Some("{marker.name}"),
+            tooltip_label: Some("{marker.name} {marker.data.payload}"),
+            table_label: Some("{marker.name} {marker.data.payload}"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "payload",
+                    label: "Payload",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Static(MarkerStaticField {
+                    label: "Description",
+                    value: "Emitted by the profiled process over the samply marker socket.",
+                }),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_pair_messages() {
+        assert_eq!(parse_app_marker_message("X 1 2 name"), None);
+        assert_eq!(parse_app_marker_message("B 1 2 "), None);
+        assert_eq!(parse_app_marker_message("B one 2 name"), None);
+
+        let messages = [
+            "B 1000 7 Load file",
+            "I 1500 7 Checkpoint\treached 50%",
+            "B 2000 8 Load file",
+            "E 3000 7 Load file\t12 bytes",
+            "E 3500 9 Load file",
+            "C 4000 7 Files\t1",
+        ];
+        let mut pairer = AppMarkerPairer::default();
+        let markers: Vec<_> = messages
+            .iter()
+            .map(|line| parse_app_marker_message(line).unwrap())
+            .filter_map(|event| pairer.handle_event(event))
+            .collect();
+        assert_eq!(
+            markers,
+            vec![
+                CompletedAppMarker {
+                    tid: 7,
+                    start_raw: 1500,
+                    end_raw: None,
+                    name: "Checkpoint".to_owned(),
+                    payload: Some("reached 50%".to_owned()),
+                },
+                CompletedAppMarker {
+                    tid: 7,
+                    start_raw: 1000,
+                    end_raw: Some(3000),
+                    name: "Load file".to_owned(),
+                    payload: Some("12 bytes".to_owned()),
+                },
+            ]
+        );
+        assert_eq!(
+            parse_app_marker_message(messages[5]).unwrap().kind,
+            AppMarkerEventKind::Counter
+        );
+    }
+}
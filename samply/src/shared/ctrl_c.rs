@@ -5,7 +5,8 @@ use tokio::sync::oneshot;
 static INSTANCE: OnceLock<Arc<Mutex<CtrlCState>>> = OnceLock::new();
 
 /// Provides Ctrl+C notifications, and allows suppressing the automatic termination
-/// of the process.
+/// of the process. SIGTERM and SIGHUP count as Ctrl+C, so that a service manager
+/// can stop samply as gracefully as a user can.
 ///
 /// Ctrl+C can only be suppressed "once at a time". Imagine the following scenario:
 ///
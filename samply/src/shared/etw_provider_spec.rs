@@ -0,0 +1,126 @@
+use std::fmt;
+use std::str::FromStr;
+
+use uuid::Uuid;
+
+/// An ETW provider whose events should be recorded as markers, as given to
+/// `--etw-provider`. The syntax is the one xperf uses for user providers:
+/// `<provider>[:<keywords>[:<level>]]`, where the provider is either a GUID
+/// or the name of a registered provider. Names of TraceLogging and
+/// EventSource providers, which aren't registered, are prefixed with `*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EtwProviderSpec {
+    pub provider: EtwProviderId,
+    pub keywords: Option<u64>,
+    pub level: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EtwProviderId {
+    Guid(Uuid),
+    Name(String),
+}
+
+impl EtwProviderSpec {
+    /// Whether an event with this provider GUID and provider name comes from
+    /// this provider. Names are compared case-insensitively, like ETW does.
+    #[allow(unused)]
+    pub fn matches(&self, provider_guid: &Uuid, provider_name: &str) -> bool {
+        match &self.provider {
+            EtwProviderId::Guid(guid) => guid == provider_guid,
+            EtwProviderId::Name(name) => name
+                .trim_start_matches('*')
+                .eq_ignore_ascii_case(provider_name),
+        }
+    }
+}
+
+impl FromStr for EtwProviderSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let provider = parts.next().unwrap_or_default();
+        if provider.is_empty() {
+            return Err("expected <provider>[:<keywords>[:<level>]]".to_string());
+        }
+        let guid = provider.trim_start_matches('{').trim_end_matches('}');
+        let provider = match Uuid::parse_str(guid) {
+            Ok(guid) => EtwProviderId::Guid(guid),
+            Err(_) => EtwProviderId::Name(provider.to_string()),
+        };
+        let keywords = match parts.next() {
+            Some(keywords) => {
+                let parsed = match keywords.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => keywords.parse(),
+                };
+                Some(parsed.map_err(|_| format!("invalid keywords {keywords:?}"))?)
+            }
+            None => None,
+        };
+        let level = match parts.next() {
+            Some(level) => Some(
+                level
+                    .parse()
+                    .map_err(|_| format!("invalid level {level:?}"))?,
+            ),
+            None => None,
+        };
+        if parts.next().is_some() {
+            return Err("expected <provider>[:<keywords>[:<level>]]".to_string());
+        }
+        Ok(Self {
+            provider,
+            keywords,
+            level,
+        })
+    }
+}
+
+/// Formats the provider in the syntax which xperf accepts for `-on`.
+impl fmt::Display for EtwProviderSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.provider {
+            EtwProviderId::Guid(guid) => write!(f, "{guid}")?,
+            EtwProviderId::Name(name) => write!(f, "{name}")?,
+        }
+        match (self.keywords, self.level) {
+            (Some(keywords), Some(level)) => write!(f, ":{keywords:#x}:{level}"),
+            (Some(keywords), None) => write!(f, ":{keywords:#x}"),
+            (None, Some(level)) => write!(f, ":0xffffffffffffffff:{level}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_match_provider_specs() {
+        let guid_spec: EtwProviderSpec = "{c923f508-96e4-5515-e32c-7539d1b10504}:0x6:4"
+            .parse()
+            .unwrap();
+        let guid = Uuid::parse_str("c923f508-96e4-5515-e32c-7539d1b10504").unwrap();
+        assert_eq!(guid_spec.provider, EtwProviderId::Guid(guid));
+        assert_eq!(guid_spec.keywords, Some(6));
+        assert_eq!(guid_spec.level, Some(4));
+        assert_eq!(
+            guid_spec.to_string(),
+            "c923f508-96e4-5515-e32c-7539d1b10504:0x6:4"
+        );
+        assert!(guid_spec.matches(&guid, "Mozilla.FirefoxTraceLogger"));
+
+        let name_spec: EtwProviderSpec = "*MyCompany-MyApp".parse().unwrap();
+        assert_eq!(name_spec.keywords, None);
+        assert_eq!(name_spec.to_string(), "*MyCompany-MyApp");
+        assert!(name_spec.matches(&Uuid::nil(), "mycompany-myapp"));
+        assert!(!name_spec.matches(&guid, "MyCompany-Other"));
+
+        assert!("".parse::<EtwProviderSpec>().is_err());
+        assert!("MyApp:zz".parse::<EtwProviderSpec>().is_err());
+        assert!("MyApp:1:2:3".parse::<EtwProviderSpec>().is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use fxprof_processed_profile::{
+    CategoryHandle, MarkerDynamicField, MarkerFieldFormat, MarkerLocation, MarkerSchema,
+    MarkerSchemaField, MarkerStaticField, MarkerTiming, Profile, ProfilerMarker,
+};
+use serde_json::json;
+
+use super::synthetic_pids::WINDOW_FOCUS_PID;
+use super::timestamp_converter::TimestampConverter;
+
+/// A change of the focused window, i.e. of the application in the foreground.
+/// These only come from X11 at the moment, see `linux::focus_events`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FocusEvent {
+    /// The time of the focus change, in the same clock as the raw sample timestamps.
+    pub timestamp_raw: u64,
+    /// The application which now owns the focused window, or `None` if no
+    /// window has focus.
+    pub app: Option<String>,
+    /// The title of the focused window, if known.
+    pub window_title: Option<String>,
+}
+
+/// The time during which one window had focus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FocusSpan<'a> {
+    start_raw: u64,
+    end_raw: u64,
+    app: &'a str,
+    window_title: Option<&'a str>,
+}
+
+/// Turns the focus changes into spans. Repeated events for the same window are
+/// combined, and the last span extends to `end_raw`.
+fn focus_spans(events: &[FocusEvent], end_raw: u64) -> Vec<FocusSpan<'_>> {
+    let mut spans: Vec<FocusSpan> = Vec::new();
+    for (i, event) in events.iter().enumerate() {
+        if i > 0
+            && events[i - 1].app == event.app
+            && events[i - 1].window_title == event.window_title
+        {
+            continue;
+        }
+        let Some(app) = &event.app else {
+            continue;
+        };
+        let span_end_raw = events[i + 1..]
+            .iter()
+            .find(|next| next.app != event.app || next.window_title != event.window_title)
+            .map_or(end_raw, |next| next.timestamp_raw);
+        spans.push(FocusSpan {
+            start_raw: event.timestamp_raw,
+            end_raw: span_end_raw,
+            app,
+            window_title: event.window_title.as_deref(),
+        });
+    }
+    spans
+}
+
+/// Adds an interval marker for each focused window to a separate "Window Focus"
+/// track, so that it's visible which application was in the foreground at any
+/// point during the recording.
+///
+/// `events` needs to be sorted by timestamp. `end_raw` is the time at which the
+/// recording stopped.
+pub fn add_focus_markers(
+    profile: &mut Profile,
+    events: &[FocusEvent],
+    end_raw: u64,
+    timestamp_converter: &TimestampConverter,
+) {
+    let Some(first_event) = events.first() else {
+        return;
+    };
+
+    let start_time = timestamp_converter.convert_time(first_event.timestamp_raw);
+    let process = profile.add_process("Window Focus", WINDOW_FOCUS_PID, start_time);
+    let thread = profile.add_thread(process, 0, start_time, true);
+
+    for span in focus_spans(events, end_raw) {
+        let start = timestamp_converter.convert_time(span.start_raw);
+        let end = timestamp_converter.convert_time(span.end_raw);
+        profile.add_marker(
+            thread,
+            CategoryHandle::OTHER,
+            "Focus",
+            FocusMarker {
+                app: span.app.to_owned(),
+                window_title: span.window_title.map(ToOwned::to_owned),
+            },
+            MarkerTiming::Interval(start, end),
+        );
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FocusMarker {
+    pub app: String,
+    pub window_title: Option<String>,
+}
+
+impl ProfilerMarker for FocusMarker {
+    const MARKER_TYPE_NAME: &'static str = "WindowFocus";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "app": self.app,
+            "title": self.window_title,
+        })
+    }
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![
+                MarkerLocation::MarkerChart,
+                MarkerLocation::MarkerTable,
+                MarkerLocation::TimelineOverview,
+            ],
+            chart_label: Some("{marker.data.app}"),
+            tooltip_label: Some("{marker.data.app}: {marker.data.title}"),
+            table_label: Some("{marker.data.app}: {marker.data.title}"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "app",
+                    label: "Application",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "title",
+                    label: "Window title",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Static(MarkerStaticField {
+                    label: "Description",
+                    value: "The application whose window had the input focus during this time.",
+                }),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(timestamp_raw: u64, app: Option<&str>) -> FocusEvent {
+        FocusEvent {
+            timestamp_raw,
+            app: app.map(ToOwned::to_owned),
+            window_title: None,
+        }
+    }
+
+    #[test]
+    fn spans_between_focus_changes() {
+        let events = [
+            event(10, Some("firefox")),
+            event(20, Some("firefox")),
+            event(30, None),
+            event(40, Some("code")),
+        ];
+        let spans = focus_spans(&events, 100);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(
+            (spans[0].app, spans[0].start_raw, spans[0].end_raw),
+            ("firefox", 10, 30)
+        );
+        assert_eq!(
+            (spans[1].app, spans[1].start_raw, spans[1].end_raw),
+            ("code", 40, 100)
+        );
+    }
+}
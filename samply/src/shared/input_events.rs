@@ -0,0 +1,202 @@
+use fxprof_processed_profile::{
+    CategoryHandle, MarkerDynamicField, MarkerFieldFormat, MarkerLocation, MarkerSchema,
+    MarkerSchemaField, MarkerStaticField, MarkerTiming, Profile, ProfilerMarker,
+};
+use serde_json::json;
+
+use super::synthetic_pids::INPUT_PID;
+use super::timestamp_converter::TimestampConverter;
+
+/// Pointer motion and wheel events arrive at a very high rate. Consecutive
+/// events of these kinds which are less than this many nanoseconds apart are
+/// combined into a single interval marker.
+const CONTINUOUS_EVENT_MERGE_GAP_NS: u64 = 50_000_000; // 50ms
+
+/// The kind of a user input event. We only record what kind of input happened,
+/// never which key was pressed or where the pointer was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEventKind {
+    KeyDown,
+    KeyUp,
+    MouseDown,
+    MouseUp,
+    MouseMove,
+    MouseWheel,
+    Touch,
+}
+
+impl InputEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputEventKind::KeyDown => "KeyDown",
+            InputEventKind::KeyUp => "KeyUp",
+            InputEventKind::MouseDown => "MouseDown",
+            InputEventKind::MouseUp => "MouseUp",
+            InputEventKind::MouseMove => "MouseMove",
+            InputEventKind::MouseWheel => "MouseWheel",
+            InputEventKind::Touch => "Touch",
+        }
+    }
+
+    /// Whether events of this kind come in high-frequency bursts.
+    fn is_continuous(&self) -> bool {
+        matches!(self, InputEventKind::MouseMove | InputEventKind::MouseWheel)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    /// The event timestamp, in the same clock as the raw sample timestamps.
+    pub timestamp_raw: u64,
+    pub kind: InputEventKind,
+}
+
+/// A run of input events of the same kind, ready to be turned into a marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InputEventRun {
+    kind: InputEventKind,
+    start_raw: u64,
+    end_raw: u64,
+    event_count: u32,
+}
+
+/// Merges bursts of continuous events (pointer motion, scrolling) into runs.
+/// Discrete events like key presses always get a run of their own.
+fn coalesce_input_events(events: &[InputEvent]) -> Vec<InputEventRun> {
+    let mut runs: Vec<InputEventRun> = Vec::new();
+    for event in events {
+        if event.kind.is_continuous() {
+            if let Some(run) = runs
+                .iter_mut()
+                .rev()
+                .find(|run| run.kind == event.kind)
+                .filter(|run| {
+                    event.timestamp_raw.saturating_sub(run.end_raw) < CONTINUOUS_EVENT_MERGE_GAP_NS
+                })
+            {
+                run.end_raw = run.end_raw.max(event.timestamp_raw);
+                run.event_count += 1;
+                continue;
+            }
+        }
+        runs.push(InputEventRun {
+            kind: event.kind,
+            start_raw: event.timestamp_raw,
+            end_raw: event.timestamp_raw,
+            event_count: 1,
+        });
+    }
+    runs
+}
+
+/// Adds a marker for each input event to a separate "Input" track.
+///
+/// `events` needs to be sorted by timestamp.
+pub fn add_input_event_markers(
+    profile: &mut Profile,
+    events: &[InputEvent],
+    timestamp_converter: &TimestampConverter,
+) {
+    let Some(first_event) = events.first() else {
+        return;
+    };
+
+    let start_time = timestamp_converter.convert_time(first_event.timestamp_raw);
+    let process = profile.add_process("Input", INPUT_PID, start_time);
+    let thread = profile.add_thread(process, 0, start_time, true);
+
+    for run in coalesce_input_events(events) {
+        let start = timestamp_converter.convert_time(run.start_raw);
+        let timing = if run.kind.is_continuous() {
+            let end = timestamp_converter.convert_time(run.end_raw);
+            MarkerTiming::Interval(start, end)
+        } else {
+            MarkerTiming::Instant(start)
+        };
+        profile.add_marker(
+            thread,
+            CategoryHandle::OTHER,
+            run.kind.name(),
+            InputEventMarker {
+                event_count: run.event_count,
+            },
+            timing,
+        );
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InputEventMarker {
+    pub event_count: u32,
+}
+
+impl ProfilerMarker for InputEventMarker {
+    const MARKER_TYPE_NAME: &'static str = "InputEvent";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "eventCount": self.event_count,
+        })
+    }
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![
+                MarkerLocation::MarkerChart,
+                MarkerLocation::MarkerTable,
+                MarkerLocation::TimelineOverview,
+            ],
+            chart_label: Some("{marker.name}"),
+            tooltip_label: Some("{marker.name}"),
+            table_label: Some("{marker.name} ({marker.data.eventCount} events)"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "eventCount",
+                    label: "Event count",
+                    format: MarkerFieldFormat::Integer,
+                    searchable: false,
+                }),
+                MarkerSchemaField::Static(MarkerStaticField {
+                    label: "Description",
+                    value: "Emitted for keyboard and pointer input during recording. Only the kind of input is recorded, not its contents.",
+                }),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(timestamp_raw: u64, kind: InputEventKind) -> InputEvent {
+        InputEvent {
+            timestamp_raw,
+            kind,
+        }
+    }
+
+    #[test]
+    fn coalesce_mouse_moves() {
+        let ms = 1_000_000;
+        let events = [
+            event(0, InputEventKind::MouseMove),
+            event(10 * ms, InputEventKind::MouseMove),
+            event(15 * ms, InputEventKind::KeyDown),
+            event(20 * ms, InputEventKind::MouseMove),
+            event(30 * ms, InputEventKind::KeyUp),
+            event(200 * ms, InputEventKind::MouseMove),
+        ];
+        let runs = coalesce_input_events(&events);
+        assert_eq!(runs.len(), 4);
+        assert_eq!(runs[0].kind, InputEventKind::MouseMove);
+        assert_eq!((runs[0].start_raw, runs[0].end_raw), (0, 20 * ms));
+        assert_eq!(runs[0].event_count, 3);
+        assert_eq!(runs[1].kind, InputEventKind::KeyDown);
+        assert_eq!(runs[2].kind, InputEventKind::KeyUp);
+        assert_eq!(runs[3].kind, InputEventKind::MouseMove);
+        assert_eq!((runs[3].start_raw, runs[3].event_count), (200 * ms, 1));
+    }
+}
@@ -22,9 +22,16 @@ pub struct JitCategoryManager {
     ion_ic_category: LazilyCreatedCategory,
     wasm_liftoff_category: LazilyCreatedCategory,
     wasm_turbofan_category: LazilyCreatedCategory,
+    python_category: LazilyCreatedCategory,
     generic_jit_category: LazilyCreatedCategory,
 }
 
+impl Default for JitCategoryManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl JitCategoryManager {
     /// (prefix, name, color, is_js)
     const CATEGORIES: &'static [(&'static str, &'static str, CategoryColor, bool)] = &[
@@ -97,6 +104,7 @@ impl JitCategoryManager {
                 "Turbofan (wasm)",
                 CategoryColor::Green,
             ),
+            python_category: LazilyCreatedCategory::new("Python", CategoryColor::Yellow),
             generic_jit_category: LazilyCreatedCategory::new("JIT", CategoryColor::Purple),
         }
     }
@@ -185,6 +193,18 @@ impl JitCategoryManager {
             }
         }
 
+        if let Some(python_func) = name.strip_prefix("py::") {
+            // The perf trampolines of CPython 3.12+, "py::<qualname>:<filename>".
+            // "py::Server.handle_request:/usr/lib/python3.12/socketserver.py"
+            let new_name = match python_func.split_once(':') {
+                Some((func, file)) => format!("{func} ({file})"),
+                None => python_func.to_owned(),
+            };
+            let category = self.python_category.get(profile);
+            let js_func = JsFrame::Regular(Self::intern_js_name(profile, &new_name));
+            return (category.into(), Some(js_func));
+        }
+
         // "run_wasm_sm.js line 41 > WebAssembly.Module:916249: Function Element.updateChild"
         // "run_wasm_sm.js line 41 > WebAssembly.Module:825626: Function wasm-function[1491]"
 
@@ -268,5 +288,18 @@ mod test {
             }
             _ => panic!(),
         }
+        let (_category, js_name) = manager.classify_jit_symbol(
+            "py::Server.handle_request:/usr/lib/python3.12/socketserver.py",
+            &mut profile,
+        );
+        match js_name {
+            Some(JsFrame::Regular(JsName::NonSelfHosted(s))) => {
+                assert_eq!(
+                    profile.get_string(s),
+                    "Server.handle_request (/usr/lib/python3.12/socketserver.py)"
+                )
+            }
+            _ => panic!(),
+        }
     }
 }
@@ -15,6 +15,10 @@ use super::lib_mappings::{
 use super::timestamp_converter::TimestampConverter;
 use super::utils::open_file_with_fallback;
 
+/// The jitdump header flag which says that the record timestamps are TSC
+/// values rather than CLOCK_MONOTONIC timestamps.
+const JITDUMP_FLAGS_ARCH_TIMESTAMP: u64 = 1;
+
 #[derive(Debug)]
 pub struct JitDumpManager {
     pending_jitdump_paths: Vec<(ThreadHandle, PathBuf, Option<PathBuf>)>,
@@ -111,6 +115,9 @@ struct SingleJitDumpProcessor {
     symbols: Vec<Symbol>,
     thread_handle: ThreadHandle,
 
+    /// Whether the record timestamps are TSC values.
+    timestamps_are_tsc: bool,
+
     /// The relative_address of the next JIT function.
     ///
     /// We define the relative address space for Jitdump files as follows:
@@ -127,12 +134,14 @@ impl SingleJitDumpProcessor {
         lib_handle: LibraryHandle,
         thread_handle: ThreadHandle,
     ) -> Self {
+        let timestamps_are_tsc = reader.header().flags & JITDUMP_FLAGS_ARCH_TIMESTAMP != 0;
         Self {
             reader: Some(reader),
             lib_handle,
             lib_mapping_ops: Default::default(),
             symbols: Default::default(),
             thread_handle,
+            timestamps_are_tsc,
             cumulative_address: 0,
         }
     }
@@ -169,6 +178,11 @@ impl SingleJitDumpProcessor {
             let Ok(Some(raw_jitdump_record)) = reader.next_record() else {
                 break;
             };
+            let timestamp_raw = if self.timestamps_are_tsc {
+                timestamp_converter.tsc_to_raw(raw_jitdump_record.timestamp)
+            } else {
+                raw_jitdump_record.timestamp
+            };
             match raw_jitdump_record.parse() {
                 Ok(JitDumpRecord::CodeLoad(record)) => {
                     let start_avma = record.code_addr;
@@ -186,7 +200,7 @@ impl SingleJitDumpProcessor {
                         name: symbol_name.to_owned(),
                     });
 
-                    let timestamp = timestamp_converter.convert_time(raw_jitdump_record.timestamp);
+                    let timestamp = timestamp_converter.convert_time(timestamp_raw);
                     let timing = MarkerTiming::Instant(timestamp);
                     profile.add_marker(
                         self.thread_handle,
@@ -211,7 +225,7 @@ impl SingleJitDumpProcessor {
                     let (category, js_frame) =
                         jit_category_manager.classify_jit_symbol(symbol_name, profile);
                     self.lib_mapping_ops.push(
-                        raw_jitdump_record.timestamp,
+                        timestamp_raw,
                         LibMappingOp::Add(LibMappingAdd {
                             start_avma,
                             end_avma,
@@ -223,7 +237,7 @@ impl SingleJitDumpProcessor {
                 }
                 Ok(JitDumpRecord::CodeMove(record)) => {
                     self.lib_mapping_ops.push(
-                        raw_jitdump_record.timestamp,
+                        timestamp_raw,
                         LibMappingOp::Move(LibMappingMove {
                             old_start_avma: record.old_code_addr,
                             new_start_avma: record.new_code_addr,
@@ -237,7 +251,7 @@ impl SingleJitDumpProcessor {
                 }
                 Ok(JitDumpRecord::CodeClose) => {
                     self.lib_mapping_ops
-                        .push(raw_jitdump_record.timestamp, LibMappingOp::Clear);
+                        .push(timestamp_raw, LibMappingOp::Clear);
                     self.close_and_commit_symbol_table(profile);
                     return;
                 }
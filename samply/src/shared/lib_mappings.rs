@@ -136,6 +136,7 @@ impl LibMappingOpQueue {
         self.0.push((timestamp, op));
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn into_iter(self) -> LibMappingOpQueueIter {
         LibMappingOpQueueIter(self.0.into_iter().peekable())
     }
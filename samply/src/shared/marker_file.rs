@@ -1,6 +1,6 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader, Lines};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use fxprof_processed_profile::Timestamp;
 
@@ -88,3 +88,86 @@ pub fn get_markers(
     marker_spans.sort_by_key(|m| m.start_time);
     Ok(marker_spans)
 }
+
+/// Watches marker files while the profiled program is writing to them, to
+/// find out when a marker with a specific name has been added.
+pub struct MarkerNameWatcher {
+    name: String,
+    files: Vec<WatchedMarkerFile>,
+}
+
+struct WatchedMarkerFile {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+    /// The start of a line which hasn't been completely written yet.
+    pending_line: String,
+}
+
+impl MarkerNameWatcher {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            files: Vec::new(),
+        }
+    }
+
+    pub fn add_file(&mut self, path: &Path) {
+        if self.files.iter().any(|file| file.path == path) {
+            return;
+        }
+        self.files.push(WatchedMarkerFile {
+            path: path.to_owned(),
+            reader: None,
+            pending_line: String::new(),
+        });
+    }
+
+    /// Reads the lines which have been added to the marker files since the
+    /// last call, and returns whether any of them has the watched name.
+    pub fn poll(&mut self) -> bool {
+        let mut found = false;
+        for file in &mut self.files {
+            if file.reader.is_none() {
+                file.reader = File::open(&file.path).ok().map(BufReader::new);
+            }
+            let Some(reader) = &mut file.reader else {
+                continue;
+            };
+            while let Ok(len) = reader.read_line(&mut file.pending_line) {
+                if len == 0 || !file.pending_line.ends_with('\n') {
+                    // Wait for the rest of the line.
+                    break;
+                }
+                let name = file.pending_line.trim_end().splitn(3, ' ').nth(2);
+                found |= name == Some(self.name.as_str());
+                file.pending_line.clear();
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn watch_for_marker_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("marker-123.txt");
+        let mut watcher = MarkerNameWatcher::new("Shutdown".to_owned());
+        watcher.add_file(&path);
+        assert!(!watcher.poll());
+
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "100 200 Startup").unwrap();
+        write!(file, "300 400 Shut").unwrap();
+        assert!(!watcher.poll());
+        writeln!(file, "down").unwrap();
+        assert!(watcher.poll());
+        writeln!(file, "500 600 Shutdown complete").unwrap();
+        assert!(!watcher.poll());
+    }
+}
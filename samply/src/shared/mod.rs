@@ -1,21 +1,31 @@
+pub mod app_markers;
 pub mod context_switch;
 pub mod ctrl_c;
+pub mod etw_provider_spec;
+pub mod focus_events;
 pub mod included_processes;
+pub mod input_events;
 pub mod jit_category_manager;
 pub mod jit_function_add_marker;
 pub mod jit_function_recycler;
 pub mod jitdump_manager;
 pub mod lib_mappings;
 pub mod marker_file;
+pub mod output_markers;
 pub mod perf_map;
 pub mod process_sample_data;
+pub mod profiler_overhead;
 pub mod recording_props;
+pub mod recursion_folding;
 pub mod recycling;
 pub mod stack_converter;
 pub mod stack_depth_limiting_frame_iter;
+pub mod symbol_debug;
 pub mod symbol_precog;
 pub mod symbol_props;
+pub mod synthetic_pids;
 pub mod timestamp_converter;
 pub mod types;
 pub mod unresolved_samples;
+pub mod user_markers;
 pub mod utils;
@@ -0,0 +1,176 @@
+use fxprof_processed_profile::{
+    CategoryHandle, MarkerDynamicField, MarkerFieldFormat, MarkerLocation, MarkerSchema,
+    MarkerSchemaField, MarkerTiming, Profile, ProfilerMarker,
+};
+use regex::Regex;
+use serde_json::json;
+
+use super::synthetic_pids::OUTPUT_PID;
+use super::timestamp_converter::TimestampConverter;
+
+/// The patterns from `--marker-from-output`. Lines of the launched command's
+/// output which match one of them become instant markers.
+#[derive(Debug, Clone, Default)]
+pub struct OutputMarkerPatterns {
+    regexes: Vec<Regex>,
+}
+
+/// A marker for a matching output line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLineMarker {
+    /// The matched part of the line.
+    pub name: String,
+    /// The captured groups, as `<group>=<value>` pairs separated by `, `.
+    /// Named groups use their name, other groups their index.
+    pub payload: Option<String>,
+}
+
+impl OutputMarkerPatterns {
+    pub fn new(regexes: Vec<Regex>) -> Self {
+        Self { regexes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regexes.is_empty()
+    }
+
+    /// Returns the marker for the first pattern which matches this line.
+    #[allow(unused)]
+    pub fn marker_for_line(&self, line: &str) -> Option<OutputLineMarker> {
+        self.regexes.iter().find_map(|regex| {
+            let captures = regex.captures(line)?;
+            let groups: Vec<String> = regex
+                .capture_names()
+                .enumerate()
+                .skip(1)
+                .filter_map(|(index, name)| {
+                    let value = captures.get(index)?.as_str();
+                    Some(match name {
+                        Some(name) => format!("{name}={value}"),
+                        None => format!("{index}={value}"),
+                    })
+                })
+                .collect();
+            let payload = (!groups.is_empty()).then(|| groups.join(", "));
+            Some(OutputLineMarker {
+                name: captures[0].to_string(),
+                payload,
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+/// A line of the launched command's output, for `--log-output`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLine {
+    /// The time at which we read the line, in the same clock as the raw
+    /// sample timestamps.
+    pub timestamp_raw: u64,
+    pub stream: OutputStream,
+    pub text: String,
+}
+
+/// Adds a marker for each output line to a separate "Output" track.
+///
+/// `lines` needs to be sorted by timestamp.
+pub fn add_output_log_markers(
+    profile: &mut Profile,
+    lines: &[OutputLine],
+    timestamp_converter: &TimestampConverter,
+) {
+    let Some(first_line) = lines.first() else {
+        return;
+    };
+
+    let start_time = timestamp_converter.convert_time(first_line.timestamp_raw);
+    let process = profile.add_process("Output", OUTPUT_PID, start_time);
+    let thread = profile.add_thread(process, 0, start_time, true);
+
+    for line in lines {
+        let timing = MarkerTiming::Instant(timestamp_converter.convert_time(line.timestamp_raw));
+        profile.add_marker(
+            thread,
+            CategoryHandle::OTHER,
+            line.stream.name(),
+            LogLineMarker {
+                text: line.text.clone(),
+            },
+            timing,
+        );
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogLineMarker {
+    pub text: String,
+}
+
+impl ProfilerMarker for LogLineMarker {
+    const MARKER_TYPE_NAME: &'static str = "LogLine";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "text": self.text,
+        })
+    }
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![MarkerLocation::MarkerChart, MarkerLocation::MarkerTable],
+            chart_label: Some("{marker.data.text}"),
+            tooltip_label: Some("{marker.name}: {marker.data.text}"),
+            table_label: Some("{marker.data.text}"),
+            fields: vec![MarkerSchemaField::Dynamic(MarkerDynamicField {
+                key: "text",
+                label: "Text",
+                format: MarkerFieldFormat::String,
+                searchable: true,
+            })],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn markers_for_matching_lines() {
+        let patterns = OutputMarkerPatterns::new(vec![
+            Regex::new(r"Compiling (?<crate>\S+) v(\S+)").unwrap(),
+            Regex::new(r"^ready$").unwrap(),
+        ]);
+        assert_eq!(
+            patterns.marker_for_line("   Compiling serde v1.0.200"),
+            Some(OutputLineMarker {
+                name: "Compiling serde v1.0.200".to_string(),
+                payload: Some("crate=serde, 2=1.0.200".to_string()),
+            })
+        );
+        assert_eq!(
+            patterns.marker_for_line("ready"),
+            Some(OutputLineMarker {
+                name: "ready".to_string(),
+                payload: None,
+            })
+        );
+        assert_eq!(patterns.marker_for_line("not ready"), None);
+    }
+}
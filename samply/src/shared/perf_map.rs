@@ -1,11 +1,15 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 use std::sync::Arc;
 
 use debugid::DebugId;
-use fxprof_processed_profile::{LibMappings, LibraryInfo, Profile, Symbol, SymbolTable};
+use fxprof_processed_profile::{
+    LibMappings, LibraryHandle, LibraryInfo, Profile, Symbol, SymbolTable,
+};
 
 use super::jit_category_manager::JitCategoryManager;
 use super::jit_function_recycler::JitFunctionRecycler;
-use super::lib_mappings::LibMappingInfo;
+use super::lib_mappings::{LibMappingAdd, LibMappingInfo, LibMappingOp, LibMappingOpQueue};
 
 fn process_perf_map_line(line: &str) -> Option<(u64, u64, &str)> {
     let mut split = line.splitn(3, ' ');
@@ -22,15 +26,17 @@ fn process_perf_map_line(line: &str) -> Option<(u64, u64, &str)> {
 
 /// Tries to load a perf mapping file that could have been generated by the process during
 /// execution.
+///
+/// This is used on macOS. On Linux, [`PerfMapReader`] reads the file during the
+/// recording.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn try_load_perf_map(
     pid: u32,
     profile: &mut Profile,
     jit_category_manager: &mut JitCategoryManager,
     mut recycler: Option<&mut JitFunctionRecycler>,
 ) -> Option<LibMappings<LibMappingInfo>> {
-    let name = format!("perf-{}.map", pid);
-    let path = format!("/tmp/{name}");
-    let Ok(content) = std::fs::read_to_string(&path) else {
+    let Ok(content) = std::fs::read_to_string(perf_map_path(pid)) else {
         return None;
     };
 
@@ -54,7 +60,45 @@ pub fn try_load_perf_map(
     // both a function name and a code address.
 
     // Create a fake "library" for the JIT code.
-    let lib_handle = profile.add_lib(LibraryInfo {
+    let lib_handle = add_perf_map_lib(pid, profile);
+
+    let mut symbols = Vec::new();
+    let mut mappings = LibMappings::new();
+    let mut cumulative_address = 0;
+
+    for (addr, len, symbol_name) in content.lines().filter_map(process_perf_map_line) {
+        let mapping = add_jit_function(
+            addr,
+            len,
+            symbol_name,
+            lib_handle,
+            &mut cumulative_address,
+            &mut symbols,
+            profile,
+            jit_category_manager,
+            recycler.as_deref_mut(),
+        );
+        mappings.add_mapping(
+            mapping.start_avma,
+            mapping.end_avma,
+            mapping.relative_address_at_start,
+            mapping.info,
+        );
+    }
+
+    profile.set_lib_symbol_table(lib_handle, Arc::new(SymbolTable::new(symbols)));
+
+    Some(mappings)
+}
+
+fn perf_map_path(pid: u32) -> String {
+    format!("/tmp/perf-{pid}.map")
+}
+
+fn add_perf_map_lib(pid: u32, profile: &mut Profile) -> LibraryHandle {
+    let name = format!("perf-{pid}.map");
+    let path = perf_map_path(pid);
+    profile.add_lib(LibraryInfo {
         debug_name: name.clone(),
         name,
         debug_path: path.clone(),
@@ -63,52 +107,184 @@ pub fn try_load_perf_map(
         code_id: None,
         arch: None,
         symbol_table: None,
+    })
+}
+
+/// Adds a symbol for a perf map entry to the fake library, and returns the
+/// lib mapping for the entry's code.
+#[allow(clippy::too_many_arguments)]
+fn add_jit_function(
+    addr: u64,
+    len: u64,
+    symbol_name: &str,
+    lib_handle: LibraryHandle,
+    cumulative_address: &mut u32,
+    symbols: &mut Vec<Symbol>,
+    profile: &mut Profile,
+    jit_category_manager: &mut JitCategoryManager,
+    recycler: Option<&mut JitFunctionRecycler>,
+) -> LibMappingAdd {
+    let code_size = len as u32;
+
+    // Pretend that all JIT code is laid out consecutively in our fake library.
+    // This relative address is used for symbolication whenever we add a frame
+    // to the profile.
+    let relative_address = *cumulative_address;
+    *cumulative_address += code_size;
+
+    // Add a symbol for this function to the fake library's symbol table.
+    // This symbol will be looked up when the address is added to the profile,
+    // based on the relative address.
+    symbols.push(Symbol {
+        address: relative_address,
+        size: Some(code_size),
+        name: symbol_name.to_owned(),
     });
 
-    let mut symbols = Vec::new();
-    let mut mappings = LibMappings::new();
-    let mut cumulative_address = 0;
+    let (lib_handle, relative_address) = if let Some(recycler) = recycler {
+        recycler.recycle(symbol_name, code_size, lib_handle, relative_address)
+    } else {
+        (lib_handle, relative_address)
+    };
 
-    for (addr, len, symbol_name) in content.lines().filter_map(process_perf_map_line) {
-        let start_address = addr;
-        let end_address = addr + len;
-        let code_size = len as u32;
-
-        // Pretend that all JIT code is laid out consecutively in our fake library.
-        // This relative address is used for symbolication whenever we add a frame
-        // to the profile.
-        let relative_address = cumulative_address;
-        cumulative_address += code_size;
-
-        // Add a symbol for this function to the fake library's symbol table.
-        // This symbol will be looked up when the address is added to the profile,
-        // based on the relative address.
-        symbols.push(Symbol {
-            address: relative_address,
-            size: Some(code_size),
-            name: symbol_name.to_owned(),
-        });
-
-        let (lib_handle, relative_address) = if let Some(recycler) = recycler.as_deref_mut() {
-            recycler.recycle(symbol_name, code_size, lib_handle, relative_address)
-        } else {
-            (lib_handle, relative_address)
-        };
+    let (category, js_frame) = jit_category_manager.classify_jit_symbol(symbol_name, profile);
 
-        let (category, js_frame) = jit_category_manager.classify_jit_symbol(symbol_name, profile);
+    // This mapping is consulted for category information, JS function
+    // prepending, and to translate the absolute address into a relative
+    // address.
+    LibMappingAdd {
+        start_avma: addr,
+        end_avma: addr + len,
+        relative_address_at_start: relative_address,
+        info: LibMappingInfo::new_jit_function(lib_handle, category, js_frame),
+    }
+}
 
-        // Add this function to the JIT lib mappings so that it can be consulted for
-        // category information, JS function prepending, and to translate the absolute
-        // address into a relative address.
-        mappings.add_mapping(
-            start_address,
-            end_address,
-            relative_address,
-            LibMappingInfo::new_jit_function(lib_handle, category, js_frame),
-        );
+/// How often [`PerfMapReader`] looks for new lines, in sample time.
+const PERF_MAP_CHECK_INTERVAL_NS: u64 = 50_000_000;
+
+/// Reads the /tmp/perf-<pid>.map file of a process while the recording is
+/// going on.
+///
+/// JITs only ever append to this file, and an address can appear several
+/// times: V8 with `--perf-basic-prof`, for example, reuses the memory of code
+/// which was discarded by the garbage collector. Loading the file once at the
+/// end would give all samples at such an address the name of the last
+/// function which was put there. Reading the new lines from time to time
+/// instead tells us when each entry appeared, so that the earlier samples
+/// keep the name of the earlier function.
+#[derive(Debug)]
+pub struct PerfMapReader {
+    pid: u32,
+    /// How many bytes of the file have been processed, up to the end of the
+    /// last complete line.
+    consumed_len: u64,
+    lib_handle: Option<LibraryHandle>,
+    symbols: Vec<Symbol>,
+    lib_mapping_ops: LibMappingOpQueue,
+    cumulative_address: u32,
+    last_check_timestamp: Option<u64>,
+}
+
+impl PerfMapReader {
+    pub fn new(pid: u32) -> Self {
+        Self {
+            pid,
+            consumed_len: 0,
+            lib_handle: None,
+            symbols: Vec::new(),
+            lib_mapping_ops: LibMappingOpQueue::default(),
+            cumulative_address: 0,
+            last_check_timestamp: None,
+        }
     }
 
-    profile.set_lib_symbol_table(lib_handle, Arc::new(SymbolTable::new(symbols)));
+    /// Processes the lines which were added to the file since the last check,
+    /// unless the last check was less than 50ms ago. `timestamp` is the
+    /// timestamp of the sample which is currently being processed.
+    pub fn check(
+        &mut self,
+        timestamp: u64,
+        profile: &mut Profile,
+        jit_category_manager: &mut JitCategoryManager,
+        recycler: Option<&mut JitFunctionRecycler>,
+    ) {
+        if let Some(last_check_timestamp) = self.last_check_timestamp {
+            if timestamp < last_check_timestamp + PERF_MAP_CHECK_INTERVAL_NS {
+                return;
+            }
+        }
+        // New lines were written after the previous check. The earliest
+        // samples which can run their code are the ones after the previous
+        // check, so that's when the new entries take effect.
+        let valid_from = self.last_check_timestamp.unwrap_or(0);
+        self.last_check_timestamp = Some(timestamp);
+        self.read_new_lines(valid_from, profile, jit_category_manager, recycler);
+    }
 
-    Some(mappings)
+    /// Processes the remaining lines and returns the lib mapping ops for all
+    /// entries, or `None` if the process has no perf map.
+    pub fn finish(
+        mut self,
+        profile: &mut Profile,
+        jit_category_manager: &mut JitCategoryManager,
+        recycler: Option<&mut JitFunctionRecycler>,
+    ) -> Option<LibMappingOpQueue> {
+        let valid_from = self.last_check_timestamp.unwrap_or(0);
+        self.read_new_lines(valid_from, profile, jit_category_manager, recycler);
+        let lib_handle = self.lib_handle?;
+        profile.set_lib_symbol_table(lib_handle, Arc::new(SymbolTable::new(self.symbols)));
+        Some(self.lib_mapping_ops)
+    }
+
+    fn read_new_lines(
+        &mut self,
+        valid_from: u64,
+        profile: &mut Profile,
+        jit_category_manager: &mut JitCategoryManager,
+        mut recycler: Option<&mut JitFunctionRecycler>,
+    ) {
+        let Ok(mut file) = File::open(perf_map_path(self.pid)) else {
+            return;
+        };
+        let mut new_data = Vec::new();
+        if file.seek(SeekFrom::Start(self.consumed_len)).is_err()
+            || file.read_to_end(&mut new_data).is_err()
+        {
+            return;
+        }
+        // Leave an incomplete last line for the next check.
+        let Some(complete_len) = new_data.iter().rposition(|b| *b == b'\n') else {
+            return;
+        };
+        // Entries which were there when the file was first read are used for
+        // the whole recording. Some JITs only write the file when they exit,
+        // or when they're asked to, e.g. the JVM.
+        let valid_from = if self.consumed_len == 0 {
+            0
+        } else {
+            valid_from
+        };
+        self.consumed_len += complete_len as u64 + 1;
+
+        let new_lines = String::from_utf8_lossy(&new_data[..complete_len]);
+        for (addr, len, symbol_name) in new_lines.lines().filter_map(process_perf_map_line) {
+            let lib_handle = *self
+                .lib_handle
+                .get_or_insert_with(|| add_perf_map_lib(self.pid, profile));
+            let mapping = add_jit_function(
+                addr,
+                len,
+                symbol_name,
+                lib_handle,
+                &mut self.cumulative_address,
+                &mut self.symbols,
+                profile,
+                jit_category_manager,
+                recycler.as_deref_mut(),
+            );
+            self.lib_mapping_ops
+                .push(valid_from, LibMappingOp::Add(mapping));
+        }
+    }
 }
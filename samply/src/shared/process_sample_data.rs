@@ -104,8 +104,19 @@ impl ProcessSampleData {
             );
             let frames = StackDepthLimitingFrameIter::new(profile, frames, user_category);
             match sample_or_marker {
-                SampleOrMarker::Sample(SampleData { cpu_delta, weight }) => {
-                    profile.add_sample(thread_handle, timestamp, frames, cpu_delta, weight);
+                SampleOrMarker::Sample(SampleData {
+                    cpu_delta,
+                    weight,
+                    aux_weight,
+                }) => {
+                    profile.add_sample_with_aux_weight(
+                        thread_handle,
+                        timestamp,
+                        frames,
+                        cpu_delta,
+                        weight,
+                        aux_weight,
+                    );
                 }
                 SampleOrMarker::MarkerHandle(mh) => {
                     profile.set_marker_stack(thread_handle, mh, frames);
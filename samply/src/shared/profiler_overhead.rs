@@ -0,0 +1,174 @@
+//! `--measure-overhead`: samply's own CPU time, the number of times it woke
+//! up to read the perf ring buffers, and the number of lost records, as
+//! counters on the profiled process and as totals in the profile metadata.
+//! This shows whether the profiler is perturbing the results.
+
+use fxprof_processed_profile::{CounterHandle, MarkerFieldFormat, ProcessHandle, Profile};
+
+use super::timestamp_converter::TimestampConverter;
+
+/// What happened since the previous sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverheadSample {
+    pub timestamp_raw: u64,
+    /// The CPU time which samply used, in all of its threads.
+    pub cpu_time_ns: u64,
+    pub wakeups: u64,
+    pub lost_records: u64,
+}
+
+/// The totals over the whole recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OverheadTotals {
+    pub duration_ns: u64,
+    pub cpu_time_ns: u64,
+    pub wakeups: u64,
+    pub lost_records: u64,
+}
+
+impl OverheadTotals {
+    pub fn add(&mut self, sample: &OverheadSample) {
+        self.cpu_time_ns += sample.cpu_time_ns;
+        self.wakeups += sample.wakeups;
+        self.lost_records += sample.lost_records;
+    }
+
+    pub fn add_to_profile(&self, profile: &mut Profile) {
+        const SECTION: &str = "Profiler overhead";
+        profile.add_extra_info(
+            SECTION,
+            "samply CPU time",
+            MarkerFieldFormat::Nanoseconds,
+            self.cpu_time_ns,
+        );
+        if self.duration_ns > 0 {
+            profile.add_extra_info(
+                SECTION,
+                "samply CPU usage",
+                MarkerFieldFormat::Percentage,
+                self.cpu_time_ns as f64 / self.duration_ns as f64,
+            );
+        }
+        profile.add_extra_info(
+            SECTION,
+            "Ring buffer wakeups",
+            MarkerFieldFormat::Integer,
+            self.wakeups,
+        );
+        profile.add_extra_info(
+            SECTION,
+            "Lost records",
+            MarkerFieldFormat::Integer,
+            self.lost_records,
+        );
+    }
+}
+
+/// The counter tracks of the overhead samples.
+#[derive(Debug, Clone, Copy)]
+pub struct OverheadCounters {
+    cpu_time: CounterHandle,
+    lost_records: CounterHandle,
+}
+
+impl OverheadCounters {
+    pub fn new(profile: &mut Profile, process: ProcessHandle) -> Self {
+        Self {
+            cpu_time: profile.add_counter(
+                process,
+                "samply CPU time",
+                "Profiler overhead",
+                "CPU time used by samply in milliseconds; the operations are ring buffer wakeups",
+            ),
+            lost_records: profile.add_counter(
+                process,
+                "Lost records",
+                "Profiler overhead",
+                "perf records which were lost because samply did not read them in time",
+            ),
+        }
+    }
+
+    pub fn add_sample(
+        &self,
+        profile: &mut Profile,
+        sample: &OverheadSample,
+        timestamp_converter: &TimestampConverter,
+    ) {
+        let timestamp = timestamp_converter.convert_time(sample.timestamp_raw);
+        profile.add_counter_sample(
+            self.cpu_time,
+            timestamp,
+            sample.cpu_time_ns as f64 / 1_000_000.0,
+            sample.wakeups as u32,
+        );
+        profile.add_counter_sample(
+            self.lost_records,
+            timestamp,
+            sample.lost_records as f64,
+            sample.lost_records as u32,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fxprof_processed_profile::{ReferenceTimestamp, SamplingInterval, Timestamp};
+
+    use super::*;
+
+    #[test]
+    fn counters_and_totals() {
+        let mut profile = Profile::new(
+            "test",
+            ReferenceTimestamp::from_millis_since_unix_epoch(0.0),
+            SamplingInterval::from_millis(1),
+        );
+        let process = profile.add_process("app", 1, Timestamp::from_millis_since_reference(0.0));
+        profile.add_thread(
+            process,
+            1,
+            Timestamp::from_millis_since_reference(0.0),
+            true,
+        );
+        let counters = OverheadCounters::new(&mut profile, process);
+        let timestamp_converter = TimestampConverter {
+            reference_raw: 0,
+            raw_to_ns_factor: 1,
+            tsc_conversion: None,
+        };
+        let samples = [
+            OverheadSample {
+                timestamp_raw: 100_000_000,
+                cpu_time_ns: 2_000_000,
+                wakeups: 10,
+                lost_records: 0,
+            },
+            OverheadSample {
+                timestamp_raw: 200_000_000,
+                cpu_time_ns: 3_000_000,
+                wakeups: 12,
+                lost_records: 5,
+            },
+        ];
+        let mut totals = OverheadTotals {
+            duration_ns: 200_000_000,
+            ..Default::default()
+        };
+        for sample in &samples {
+            counters.add_sample(&mut profile, sample, &timestamp_converter);
+            totals.add(sample);
+        }
+        totals.add_to_profile(&mut profile);
+
+        let json = serde_json::to_value(&profile).unwrap();
+        let cpu_samples = &json["counters"][0]["samples"];
+        assert_eq!(cpu_samples["count"], serde_json::json!([2.0, 3.0]));
+        assert_eq!(cpu_samples["number"], serde_json::json!([10, 12]));
+        let entries = &json["meta"]["extra"][0]["entries"];
+        assert_eq!(entries[0]["value"], 5_000_000);
+        assert_eq!(entries[1]["value"], 0.025);
+        assert_eq!(entries[2]["value"], 22);
+        assert_eq!(entries[3]["value"], 5);
+    }
+}
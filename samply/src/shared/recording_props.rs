@@ -4,6 +4,12 @@ use std::time::Duration;
 
 use serde_derive::{Deserialize, Serialize};
 
+use super::etw_provider_spec::EtwProviderSpec;
+use super::output_markers::OutputMarkerPatterns;
+use super::recursion_folding::RecursionFolding;
+use crate::linux_shared::UsdtProbeSpec;
+use crate::profile_tools::PostProcessingOptions;
+
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct CoreClrProfileProps {
     pub enabled: bool,
@@ -33,6 +39,64 @@ pub struct RecordingProps {
     pub vm_hack: bool,
     pub gfx: bool,
     pub browsers: bool,
+    /// Record keyboard and pointer input events as markers.
+    pub input_markers: bool,
+    /// Record the focused X11 window as markers. Linux only.
+    pub focus_markers: bool,
+    /// A second event to sample next to the main event. Linux only.
+    pub aux_event: Option<AuxEvent>,
+    /// How long to keep recording after the launched command has exited.
+    /// Linux and macOS only.
+    pub linger: Option<Duration>,
+    /// Stop recording once a marker with this name has been added to a
+    /// marker file. Linux and macOS only.
+    pub stop_on_marker: Option<String>,
+    /// The USDT probes in the profiled binary whose firings are recorded as
+    /// markers. Linux only.
+    pub usdt_probes: Vec<UsdtProbeSpec>,
+    /// Additional ETW providers whose events are recorded as markers.
+    /// Windows only.
+    #[allow(unused)]
+    pub etw_providers: Vec<EtwProviderSpec>,
+    /// Lines of the launched command's output which become markers.
+    /// Linux only.
+    pub output_marker_patterns: OutputMarkerPatterns,
+    /// Record every line of the launched command's output as a marker.
+    /// Linux only.
+    pub log_output: bool,
+    /// A FIFO from which lines of text are read as user markers. Linux only.
+    pub marker_fifo: Option<PathBuf>,
+    /// Turn on the perf trampolines of launched Python processes, so that
+    /// Python functions show up in the stacks. Linux only.
+    pub python: bool,
+    /// Record samply's own CPU usage, ring buffer wakeups and lost records
+    /// as counters and profile metadata. Linux only.
+    pub measure_overhead: bool,
+    /// With a server, serve snapshots of the profile during the recording,
+    /// written at this interval. Linux only.
+    pub live_interval: Option<Duration>,
+}
+
+/// A hardware event which can be sampled next to the main event. The event
+/// counts become the auxiliary weight of the samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuxEvent {
+    Instructions,
+    CacheReferences,
+    CacheMisses,
+    BranchMisses,
+}
+
+impl AuxEvent {
+    /// The event name, as used by perf.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuxEvent::Instructions => "instructions",
+            AuxEvent::CacheReferences => "cache-references",
+            AuxEvent::CacheMisses => "cache-misses",
+            AuxEvent::BranchMisses => "branch-misses",
+        }
+    }
 }
 
 /// Which process(es) to record.
@@ -66,8 +130,8 @@ pub struct ProfileCreationProps {
     pub main_thread_only: bool,
     /// Merge non-overlapping threads of the same name.
     pub reuse_threads: bool,
-    /// Fold repeated frames at the base of the stack.
-    pub fold_recursive_prefix: bool,
+    /// How repeated frames in the stacks are collapsed.
+    pub recursion_folding: RecursionFolding,
     /// Unlink jitdump/marker files
     pub unlink_aux_files: bool,
     /// Create a separate thread for each CPU.
@@ -81,6 +145,8 @@ pub struct ProfileCreationProps {
     pub coreclr: CoreClrProfileProps,
     /// Create markers for unknown events.
     pub unknown_event_markers: bool,
+    /// Steps to apply to the profile file after writing it.
+    pub post_processing: PostProcessingOptions,
 }
 
 /// Properties which are meaningful for launching and recording a fresh process.
@@ -0,0 +1,117 @@
+/// How repeated frames in a stack are collapsed, so that deeply recursive
+/// code, like interpreters and recursive descent parsers, doesn't produce
+/// call trees which are thousands of levels deep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecursionFolding {
+    /// Keep the stacks as they are.
+    #[default]
+    None,
+    /// Fold repeated frames at the base of the stack into one frame.
+    Prefix,
+    /// Collapse any cycle of up to `max_cycle_length` frames which repeats
+    /// back to back anywhere in the stack, e.g. `a b c b c b c d` becomes
+    /// `a b c d` with a maximum cycle length of 2 or more. The first
+    /// `max_depth` repetitions of each cycle are kept, so that shallow
+    /// recursion stays visible.
+    Cycles {
+        max_cycle_length: usize,
+        max_depth: usize,
+    },
+}
+
+impl RecursionFolding {
+    /// Folds the stack in `frames`, which is ordered from callee-most to
+    /// root-most.
+    pub fn fold<T: PartialEq>(&self, frames: &mut Vec<T>) {
+        match *self {
+            RecursionFolding::None => {}
+            RecursionFolding::Prefix => {
+                while frames.len() >= 2 && frames[frames.len() - 2] == frames[frames.len() - 1] {
+                    frames.pop();
+                }
+            }
+            RecursionFolding::Cycles {
+                max_cycle_length,
+                max_depth,
+            } => {
+                let max_depth = max_depth.max(1);
+                if max_cycle_length == 0 || frames.len() <= max_depth {
+                    return;
+                }
+                // Walk from the root to the leaf, so that the outermost
+                // occurrence of each cycle is the one which is kept.
+                frames.reverse();
+                let mut folded: Vec<T> = Vec::with_capacity(frames.len());
+                for frame in frames.drain(..) {
+                    folded.push(frame);
+                    while let Some(len) =
+                        repeated_cycle_length(&folded, max_cycle_length, max_depth)
+                    {
+                        folded.truncate(folded.len() - len);
+                    }
+                }
+                folded.reverse();
+                *frames = folded;
+            }
+        }
+    }
+}
+
+/// The length of the shortest cycle which the end of `frames` repeats
+/// `max_depth` times right before it, if any.
+fn repeated_cycle_length<T: PartialEq>(
+    frames: &[T],
+    max_cycle_length: usize,
+    max_depth: usize,
+) -> Option<usize> {
+    let repetitions = max_depth + 1;
+    (1..=max_cycle_length.min(frames.len() / repetitions)).find(|&len| {
+        let cycle = &frames[frames.len() - len..];
+        frames[frames.len() - repetitions * len..]
+            .chunks(len)
+            .all(|chunk| chunk == cycle)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fold(folding: RecursionFolding, root_first: &str) -> String {
+        let mut frames: Vec<char> = root_first.chars().rev().collect();
+        folding.fold(&mut frames);
+        frames.into_iter().rev().collect()
+    }
+
+    #[test]
+    fn fold_prefix() {
+        assert_eq!(fold(RecursionFolding::Prefix, "aaabcbcc"), "abcbcc");
+        assert_eq!(fold(RecursionFolding::None, "aaab"), "aaab");
+    }
+
+    #[test]
+    fn fold_cycles() {
+        let cycles = |max_cycle_length| RecursionFolding::Cycles {
+            max_cycle_length,
+            max_depth: 1,
+        };
+        assert_eq!(fold(cycles(1), "aaabcbcbccd"), "abcbcbcd");
+        assert_eq!(fold(cycles(2), "aaabcbcbccd"), "abcd");
+        assert_eq!(fold(cycles(3), "xabcabcabcy"), "xabcy");
+        assert_eq!(fold(cycles(2), "xabcabcabcy"), "xabcabcabcy");
+        assert_eq!(fold(cycles(3), "abab"), "ab");
+        assert_eq!(fold(cycles(0), "aa"), "aa");
+    }
+
+    #[test]
+    fn fold_cycles_beyond_depth() {
+        let cycles = |max_cycle_length, max_depth| RecursionFolding::Cycles {
+            max_cycle_length,
+            max_depth,
+        };
+        assert_eq!(fold(cycles(1, 3), "xaaaaaay"), "xaaay");
+        assert_eq!(fold(cycles(1, 3), "xaay"), "xaay");
+        assert_eq!(fold(cycles(2, 2), "xabababy"), "xababy");
+        assert_eq!(fold(cycles(2, 0), "xabababy"), "xaby");
+    }
+}
@@ -0,0 +1,224 @@
+//! The report of `--symbol-debug`: for each library, the files which were
+//! tried for its symbols, why each of them was not used, and which one was.
+//! The report file is rewritten whenever a library's lookup finishes, so it
+//! is complete while the server is still running.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde_derive::Serialize;
+use wholesym::{Error, LibraryInfo, SymbolMapLoadObserver};
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct CandidateReport {
+    pub path: String,
+    /// "used", "missing", "wrong debug id" or "parse error".
+    pub outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct LibraryReport {
+    pub name: String,
+    pub debug_id: Option<String>,
+    pub candidates: Vec<CandidateReport>,
+    /// The file whose symbols are used.
+    pub used: Option<String>,
+    /// Why no symbols were found, if none were.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Collects the candidate files of each lookup and writes the report file.
+pub struct SymbolDebugReport {
+    path: PathBuf,
+    state: Mutex<ReportState>,
+}
+
+#[derive(Default)]
+struct ReportState {
+    /// The candidates of lookups which haven't finished yet, by library key.
+    pending: HashMap<String, Vec<CandidateReport>>,
+    /// The finished lookups. A library which is looked up again replaces its
+    /// earlier entry.
+    libraries: Vec<LibraryReport>,
+}
+
+impl SymbolDebugReport {
+    /// The report is written as JSON if the path ends in `.json`, and as
+    /// text otherwise.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            state: Mutex::new(ReportState::default()),
+        }
+    }
+
+    fn write(&self, libraries: &[LibraryReport]) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&self.path)?);
+        if self.path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::to_writer_pretty(&mut file, libraries)?;
+            writeln!(file)?;
+        } else {
+            write_text_report(libraries, &mut file)?;
+        }
+        file.flush()
+    }
+}
+
+impl SymbolMapLoadObserver for SymbolDebugReport {
+    fn on_candidate_tried(
+        &self,
+        library_info: &LibraryInfo,
+        location: &str,
+        result: Result<(), &Error>,
+    ) {
+        let candidate = match result {
+            Ok(()) => CandidateReport {
+                path: location.to_owned(),
+                outcome: "used",
+                error: None,
+            },
+            Err(err) => CandidateReport {
+                path: location.to_owned(),
+                outcome: failure_outcome(err),
+                error: Some(match err {
+                    // The path is already in the report.
+                    Error::HelperErrorDuringOpenFile(_, source) => source.to_string(),
+                    err => err.to_string(),
+                }),
+            },
+        };
+        let mut state = self.state.lock().unwrap();
+        state
+            .pending
+            .entry(library_key(library_info))
+            .or_default()
+            .push(candidate);
+    }
+
+    fn on_symbol_map_load_finished(&self, library_info: &LibraryInfo, result: Result<(), &Error>) {
+        let mut state = self.state.lock().unwrap();
+        let key = library_key(library_info);
+        let candidates = state.pending.remove(&key).unwrap_or_default();
+        let used = candidates
+            .iter()
+            .find(|candidate| candidate.outcome == "used")
+            .map(|candidate| candidate.path.clone());
+        let report = LibraryReport {
+            name: library_name(library_info),
+            debug_id: library_info
+                .debug_id
+                .map(|debug_id| debug_id.breakpad().to_string()),
+            candidates,
+            used,
+            error: result.err().map(|err| match err {
+                // The candidates' errors are listed with them.
+                Error::NoSuccessfulCandidate(_) => "no candidate file worked".to_owned(),
+                err => err.to_string(),
+            }),
+        };
+        state
+            .libraries
+            .retain(|library| library.name != report.name || library.debug_id != report.debug_id);
+        state.libraries.push(report);
+        if let Err(err) = self.write(&state.libraries) {
+            eprintln!(
+                "Could not write the symbol lookup report to {:?}: {err}",
+                self.path
+            );
+        }
+    }
+}
+
+fn library_name(library_info: &LibraryInfo) -> String {
+    library_info
+        .debug_name
+        .clone()
+        .or_else(|| library_info.name.clone())
+        .unwrap_or_else(|| "<unknown>".to_owned())
+}
+
+fn library_key(library_info: &LibraryInfo) -> String {
+    match library_info.debug_id {
+        Some(debug_id) => format!("{} {}", library_name(library_info), debug_id.breakpad()),
+        None => library_name(library_info),
+    }
+}
+
+/// Why a candidate file was not used.
+fn failure_outcome(err: &Error) -> &'static str {
+    match err {
+        Error::HelperErrorDuringOpenFile(..) => "missing",
+        Error::UnmatchedDebugId(..) | Error::UnmatchedDebugIdOptional(..) => "wrong debug id",
+        _ => "parse error",
+    }
+}
+
+fn write_text_report(libraries: &[LibraryReport], out: &mut impl Write) -> std::io::Result<()> {
+    for library in libraries {
+        let debug_id = library.debug_id.as_deref().unwrap_or("no debug id");
+        writeln!(out, "{} ({debug_id})", library.name)?;
+        for candidate in &library.candidates {
+            match &candidate.error {
+                Some(error) => writeln!(
+                    out,
+                    "  {:<15} {}: {}",
+                    candidate.outcome,
+                    candidate.path,
+                    error.replace('\n', " ")
+                )?,
+                None => writeln!(out, "  {:<15} {}", candidate.outcome, candidate.path)?,
+            }
+        }
+        if library.candidates.is_empty() {
+            writeln!(out, "  no candidate files")?;
+        }
+        if let Some(error) = &library.error {
+            writeln!(out, "  => no symbols: {}", error.replace('\n', " "))?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use wholesym::debugid::DebugId;
+
+    use super::*;
+
+    #[test]
+    fn report_lists_candidates_per_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("symbols.txt");
+        let report = SymbolDebugReport::new(path.clone());
+        let info = LibraryInfo {
+            debug_name: Some("libfoo.so".to_owned()),
+            debug_id: Some(DebugId::nil()),
+            ..Default::default()
+        };
+        let open_error = Error::HelperErrorDuringOpenFile(
+            "/usr/lib/debug/libfoo.so".to_owned(),
+            "No such file or directory".into(),
+        );
+        report.on_candidate_tried(&info, "/usr/lib/debug/libfoo.so", Err(&open_error));
+        let mismatch = Error::UnmatchedDebugId(DebugId::nil(), DebugId::nil());
+        report.on_candidate_tried(&info, "/tmp/libfoo.so", Err(&mismatch));
+        report.on_candidate_tried(&info, "/opt/libfoo.so", Ok(()));
+        report.on_symbol_map_load_finished(&info, Ok(()));
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!("libfoo.so ({})", DebugId::nil().breakpad())
+        );
+        assert!(lines[1].starts_with("  missing         /usr/lib/debug/libfoo.so: "));
+        assert!(lines[2].starts_with("  wrong debug id  /tmp/libfoo.so: "));
+        assert_eq!(lines[3], "  used            /opt/libfoo.so");
+    }
+}
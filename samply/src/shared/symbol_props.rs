@@ -1,5 +1,7 @@
 use std::path::PathBuf;
 
+use wholesym::SymbolBackend;
+
 #[derive(Debug, Clone)]
 pub struct SymbolProps {
     /// Extra directories containing symbol files
@@ -16,4 +18,37 @@ pub struct SymbolProps {
     pub breakpad_symbol_cache: Option<PathBuf>,
     /// Extra directory containing symbol files, with the directory structure used by simpleperf's scripts
     pub simpleperf_binary_cache: Option<PathBuf>,
+    /// Libraries, by debug name, whose symbols should only come from one kind of symbol file
+    pub forced_backends: Vec<(String, SymbolBackend)>,
+    /// Where to write the report of which symbol files were tried for each library
+    pub symbol_debug_report: Option<PathBuf>,
+    /// The maximum size of the directories with downloaded symbol files
+    pub symbol_cache_max_size: Option<u64>,
+    /// The maximum number of files which are downloaded at the same time
+    /// from each symbol server
+    pub max_downloads_per_server: Option<usize>,
+    /// Whether the names of functions from PDB type information include the
+    /// argument types
+    pub pdb_function_arguments: bool,
+}
+
+/// The names of the symbol backends on the command line.
+pub const SYMBOL_BACKEND_NAMES: [(&str, SymbolBackend); 3] = [
+    ("dwarf", SymbolBackend::Dwarf),
+    ("breakpad", SymbolBackend::Breakpad),
+    ("pdb", SymbolBackend::Pdb),
+];
+
+pub fn parse_symbol_backend(s: &str) -> Option<SymbolBackend> {
+    SYMBOL_BACKEND_NAMES
+        .iter()
+        .find(|(name, _)| *name == s)
+        .map(|(_, backend)| *backend)
+}
+
+pub fn symbol_backend_name(backend: SymbolBackend) -> &'static str {
+    SYMBOL_BACKEND_NAMES
+        .iter()
+        .find(|(_, b)| *b == backend)
+        .map_or("unknown", |(name, _)| name)
 }
@@ -0,0 +1,8 @@
+//! Pids for the processes which hold tracks that don't belong to a real
+//! process. The profiler groups threads into tracks by pid, so each of these
+//! processes needs a pid of its own. Real pids are much smaller than these.
+
+pub const INPUT_PID: u32 = u32::MAX;
+pub const WINDOW_FOCUS_PID: u32 = u32::MAX - 1;
+pub const USER_MARKERS_PID: u32 = u32::MAX - 2;
+pub const OUTPUT_PID: u32 = u32::MAX - 3;
@@ -6,6 +6,9 @@ pub struct TimestampConverter {
     pub reference_raw: u64,
     /// A "ticks per nanosecond" conversion factor. If raw values are in nanoseconds, this is 1.
     pub raw_to_ns_factor: u64,
+    /// How to convert TSC values, e.g. the timestamps of jitdump files which
+    /// were written with `JITDUMP_FLAGS_ARCH_TIMESTAMP`, into raw timestamps.
+    pub tsc_conversion: Option<TscConversion>,
 }
 
 impl TimestampConverter {
@@ -21,4 +24,77 @@ impl TimestampConverter {
             (time_us * 1000).saturating_sub(self.reference_raw * self.raw_to_ns_factor),
         )
     }
+
+    /// Converts a TSC value into a raw timestamp. Without a TSC conversion,
+    /// the value is assumed to be a raw timestamp already.
+    pub fn tsc_to_raw(&self, tsc: u64) -> u64 {
+        match &self.tsc_conversion {
+            Some(conversion) => conversion.tsc_to_perf_time(tsc),
+            None => tsc,
+        }
+    }
+}
+
+/// The parameters from a perf.data file's `PERF_RECORD_TIME_CONV` record,
+/// which the kernel exposes in the perf mmap page for converting TSC values
+/// into perf timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TscConversion {
+    pub time_shift: u64,
+    pub time_mult: u64,
+    pub time_zero: u64,
+    pub time_cycles: u64,
+    pub time_mask: u64,
+    /// Whether only the bits in `time_mask` of the TSC values are valid.
+    pub cap_user_time_short: bool,
+}
+
+impl TscConversion {
+    /// The same calculation as `tsc_to_perf_time` in the perf tools.
+    pub fn tsc_to_perf_time(&self, tsc: u64) -> u64 {
+        let tsc = if self.cap_user_time_short {
+            self.time_cycles
+                .wrapping_add(tsc.wrapping_sub(self.time_cycles) & self.time_mask)
+        } else {
+            tsc
+        };
+        let shift = self.time_shift.min(63);
+        let quot = tsc >> shift;
+        let rem = tsc & ((1 << shift) - 1);
+        self.time_zero
+            .wrapping_add(quot.wrapping_mul(self.time_mult))
+            .wrapping_add(rem.wrapping_mul(self.time_mult) >> shift)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn convert_tsc_to_perf_time() {
+        // A 2 GHz TSC: mult / 2^shift = 0.5 ns per cycle.
+        let conversion = TscConversion {
+            time_shift: 31,
+            time_mult: 1 << 30,
+            time_zero: 1_000_000,
+            time_cycles: 0,
+            time_mask: u64::MAX,
+            cap_user_time_short: false,
+        };
+        assert_eq!(conversion.tsc_to_perf_time(0), 1_000_000);
+        assert_eq!(conversion.tsc_to_perf_time(2_000_000_001), 1_001_000_000);
+
+        let converter = TimestampConverter {
+            reference_raw: 1_000_000,
+            raw_to_ns_factor: 1,
+            tsc_conversion: Some(conversion),
+        };
+        assert_eq!(converter.tsc_to_raw(4000), 1_002_000);
+        let converter = TimestampConverter {
+            tsc_conversion: None,
+            ..converter
+        };
+        assert_eq!(converter.tsc_to_raw(4000), 4000);
+    }
 }
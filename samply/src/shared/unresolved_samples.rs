@@ -43,7 +43,11 @@ impl UnresolvedSamples {
             timestamp_mono,
             stack,
             extra_label_frame,
-            sample_or_marker: SampleOrMarker::Sample(SampleData { weight, cpu_delta }),
+            sample_or_marker: SampleOrMarker::Sample(SampleData {
+                weight,
+                cpu_delta,
+                aux_weight: 0,
+            }),
         });
         self.prev_sample_info_per_thread.insert(
             thread_handle,
@@ -55,6 +59,32 @@ impl UnresolvedSamples {
         );
     }
 
+    /// Adds a sample of the auxiliary event, which has a weight of zero for
+    /// the main event. It doesn't count as the thread's previous sample for
+    /// [`UnresolvedSamples::add_sample_same_stack_zero_cpu`].
+    pub fn add_aux_event_sample(
+        &mut self,
+        thread_handle: ThreadHandle,
+        timestamp: Timestamp,
+        timestamp_mono: u64,
+        stack: UnresolvedStackHandle,
+        aux_weight: i32,
+        extra_label_frame: Option<FrameInfo>,
+    ) {
+        self.samples_and_markers.push(UnresolvedSampleOrMarker {
+            thread_handle,
+            timestamp,
+            timestamp_mono,
+            stack,
+            extra_label_frame,
+            sample_or_marker: SampleOrMarker::Sample(SampleData {
+                weight: 0,
+                cpu_delta: CpuDelta::ZERO,
+                aux_weight,
+            }),
+        });
+    }
+
     #[allow(unused)]
     pub fn add_sample_same_stack_zero_cpu(
         &mut self,
@@ -86,6 +116,7 @@ impl UnresolvedSamples {
                         sample_or_marker: SampleOrMarker::Sample(SampleData {
                             weight,
                             cpu_delta: CpuDelta::ZERO,
+                            aux_weight: 0,
                         }),
                     });
                     sample_info.prev_sample_index_if_zero_cpu = Some(sample_index);
@@ -103,6 +134,7 @@ impl UnresolvedSamples {
                     sample_or_marker: SampleOrMarker::Sample(SampleData {
                         weight,
                         cpu_delta: CpuDelta::ZERO,
+                        aux_weight: 0,
                     }),
                 });
                 entry.insert(PreviousSampleInfo {
@@ -152,6 +184,8 @@ pub enum SampleOrMarker {
 pub struct SampleData {
     pub cpu_delta: CpuDelta,
     pub weight: i32,
+    /// The weight for the auxiliary event, if two events are sampled.
+    pub aux_weight: i32,
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
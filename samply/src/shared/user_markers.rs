@@ -0,0 +1,98 @@
+use fxprof_processed_profile::{
+    CategoryHandle, MarkerLocation, MarkerSchema, MarkerSchemaField, MarkerStaticField,
+    MarkerTiming, Profile, ProfilerMarker,
+};
+use serde_json::json;
+
+use super::synthetic_pids::USER_MARKERS_PID;
+use super::timestamp_converter::TimestampConverter;
+
+/// A marker which the user added by hand during the recording, e.g. to flag
+/// the moment a hang happened: by sending SIGUSR2 to samply, by pressing
+/// Enter in samply's terminal, or by writing a line to the marker FIFO.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserMarker {
+    /// The marker timestamp, in the same clock as the raw sample timestamps.
+    pub timestamp_raw: u64,
+    pub name: String,
+}
+
+/// The name for the `number`th user marker. Text which the user typed is
+/// used as is, otherwise the markers are numbered: "user marker 1", ...
+pub fn user_marker_name(text: &str, number: usize) -> String {
+    match text.trim() {
+        "" => format!("user marker {number}"),
+        text => text.to_string(),
+    }
+}
+
+/// Adds the user markers to a separate "User Markers" track.
+///
+/// `markers` needs to be sorted by timestamp.
+pub fn add_user_markers(
+    profile: &mut Profile,
+    markers: &[UserMarker],
+    timestamp_converter: &TimestampConverter,
+) {
+    let Some(first_marker) = markers.first() else {
+        return;
+    };
+
+    let start_time = timestamp_converter.convert_time(first_marker.timestamp_raw);
+    let process = profile.add_process("User Markers", USER_MARKERS_PID, start_time);
+    let thread = profile.add_thread(process, 0, start_time, true);
+
+    for marker in markers {
+        let timing = MarkerTiming::Instant(timestamp_converter.convert_time(marker.timestamp_raw));
+        profile.add_marker(
+            thread,
+            CategoryHandle::OTHER,
+            &marker.name,
+            UserMarkerMarker,
+            timing,
+        );
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserMarkerMarker;
+
+impl ProfilerMarker for UserMarkerMarker {
+    const MARKER_TYPE_NAME: &'static str = "UserMarker";
+
+    fn json_marker_data(&self) -> serde_json::Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+        })
+    }
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![
+                MarkerLocation::MarkerChart,
+                MarkerLocation::MarkerTable,
+                MarkerLocation::TimelineOverview,
+            ],
+            chart_label: Some("{marker.name}"),
+            tooltip_label: Some("{marker.name}"),
+            table_label: Some("{marker.name}"),
+            fields: vec![MarkerSchemaField::Static(MarkerStaticField {
+                label: "Description",
+                value: "Added by hand during the recording, with SIGUSR2, the Enter key or the marker FIFO.",
+            })],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numbered_and_typed_names() {
+        assert_eq!(user_marker_name("", 1), "user marker 1");
+        assert_eq!(user_marker_name("  \n", 2), "user marker 2");
+        assert_eq!(user_marker_name("the hang\n", 3), "the hang");
+    }
+}
@@ -19,6 +19,55 @@ pub fn open_file_with_fallback(
     }
 }
 
+/// The root directory of a running process, `/proc/<pid>/root`, if the
+/// process is in a different mount namespace than samply, e.g. because it runs
+/// in a container. The paths of the files which such a process has mapped only
+/// exist relative to this directory.
+pub fn foreign_process_root(pid: i32) -> Option<PathBuf> {
+    let own_namespace = std::fs::read_link("/proc/self/ns/mnt").ok()?;
+    let proc_dir = Path::new("/proc").join(pid.to_string());
+    let namespace = std::fs::read_link(proc_dir.join("ns/mnt")).ok()?;
+    (namespace != own_namespace).then(|| proc_dir.join("root"))
+}
+
+/// Opens a file by its path inside a process's root directory.
+pub fn open_file_in_process_root(
+    root: &Path,
+    path: &Path,
+) -> std::io::Result<(std::fs::File, PathBuf)> {
+    let p = root.join(path.strip_prefix("/").unwrap_or(path));
+    std::fs::File::open(&p).map(|file| (file, p))
+}
+
+/// The directory of perf's build-id cache: `$PERF_BUILDID_DIR`, or `~/.debug`.
+pub fn perf_build_id_cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("PERF_BUILDID_DIR") {
+        return Some(dir.into());
+    }
+    let home = std::env::var_os("HOME")?;
+    Some(Path::new(&home).join(".debug"))
+}
+
+/// Opens the binary with this ELF build ID from perf's build-id cache, for
+/// files which no longer exist at the path in the perf.data file.
+pub fn open_file_from_perf_build_id_cache(
+    cache_dir: &Path,
+    build_id: &[u8],
+) -> Option<(std::fs::File, PathBuf)> {
+    let build_id: String = build_id.iter().map(|b| format!("{b:02x}")).collect();
+    if build_id.len() <= 2 {
+        return None;
+    }
+    let (two_chars, rest) = build_id.split_at(2);
+    let entry = cache_dir.join(".build-id").join(two_chars).join(rest);
+    // Current versions of perf make the entry a directory with the binary in
+    // `elf`, older versions make it a symlink to the binary.
+    [entry.join("elf"), entry].into_iter().find_map(|path| {
+        let file = std::fs::File::open(&path).ok()?;
+        file.metadata().ok()?.is_file().then_some((file, path))
+    })
+}
+
 pub fn lib_handle_for_jitdump(
     path: &Path,
     header: &JitDumpHeader,
@@ -45,3 +94,21 @@ pub fn lib_handle_for_jitdump(
         symbol_table: None,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn open_file_relative_to_process_root() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("usr/lib")).unwrap();
+        std::fs::write(root.path().join("usr/lib/libfoo.so"), b"").unwrap();
+
+        let (_, path) =
+            open_file_in_process_root(root.path(), Path::new("/usr/lib/libfoo.so")).unwrap();
+        assert_eq!(path, root.path().join("usr/lib/libfoo.so"));
+        assert!(open_file_in_process_root(root.path(), Path::new("/usr/lib/libbar.so")).is_err());
+        assert_eq!(foreign_process_root(std::process::id() as i32), None);
+    }
+}
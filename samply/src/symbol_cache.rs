@@ -0,0 +1,117 @@
+//! `samply cache`: shows how much space the downloaded symbol files take up,
+//! and removes them. The same size limit can be kept automatically with
+//! `--symbol-cache-size`.
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use platform_dirs::AppDirs;
+use wholesym::{cache_dir_usage, evict_least_recently_used, CacheDirUsage, CacheEviction};
+
+use crate::name::SAMPLY_NAME;
+use crate::server::create_symbol_manager_config;
+use crate::shared::symbol_props::SymbolProps;
+
+/// The directories which samply caches downloaded files in: the ones which
+/// the symbol props configure, and samply's default cache directories, which
+/// may hold files from earlier runs with other symbol servers.
+pub fn symbol_cache_dirs(symbol_props: &SymbolProps) -> Vec<PathBuf> {
+    let mut dirs = create_symbol_manager_config(symbol_props.clone(), false).cache_dirs();
+    let cache_base_dir = AppDirs::new(Some(SAMPLY_NAME), false).map(|dirs| dirs.cache_dir);
+    let default_dirs = cache_base_dir.into_iter().flat_map(|base| {
+        let symbols = base.join("symbols");
+        [
+            symbols.join("breakpad"),
+            symbols.join("breakpad-symindex"),
+            symbols.join("windows"),
+            symbols.join("debuginfod"),
+            base.join("sources"),
+        ]
+    });
+    let configured_dirs = symbol_props
+        .breakpad_symbol_cache
+        .iter()
+        .chain(&symbol_props.windows_symbol_cache)
+        .cloned();
+    for dir in configured_dirs.chain(default_dirs) {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// Prints the size of each cache directory and the total.
+pub fn print_usage(dirs: &[PathBuf], mut out: impl Write) -> std::io::Result<()> {
+    let mut total = CacheDirUsage::default();
+    for dir in dirs {
+        let usage = cache_dir_usage(dir);
+        writeln!(
+            out,
+            "{:>10}  {:>7} files  {}",
+            format_bytes(usage.total_bytes),
+            usage.file_count,
+            dir.display()
+        )?;
+        total.file_count += usage.file_count;
+        total.total_bytes += usage.total_bytes;
+    }
+    writeln!(
+        out,
+        "{:>10}  {:>7} files  total",
+        format_bytes(total.total_bytes),
+        total.file_count
+    )
+}
+
+/// Removes all cached files.
+pub fn clear(dirs: &[PathBuf]) -> CacheEviction {
+    evict_least_recently_used(dirs, 0)
+}
+
+/// Removes the least recently used files until the directories take up at
+/// most `max_bytes`.
+pub fn trim(dirs: &[PathBuf], max_bytes: u64) -> CacheEviction {
+    evict_least_recently_used(dirs, max_bytes)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn usage_and_trim() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("breakpad/libxul.so")).unwrap();
+        std::fs::write(
+            dir.path().join("breakpad/libxul.so/libxul.sym"),
+            [0u8; 1500],
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("other.pdb"), [0u8; 500]).unwrap();
+        let dirs = [dir.path().to_owned()];
+
+        let mut out = Vec::new();
+        print_usage(&dirs, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with("    2.0 KB        2 files  total\n"), "{out}");
+
+        assert_eq!(trim(&dirs, 1600).removed_files, 1);
+        assert_eq!(clear(&dirs).removed_files, 1);
+        assert_eq!(format_bytes(2_500_000_000), "2.5 GB");
+    }
+}
@@ -0,0 +1,726 @@
+//! `samply top`: an interactive terminal view of a profile, for when opening
+//! a browser is inconvenient, e.g. over SSH. It shows a table of the functions
+//! with the most samples, and a simple flame graph of the call tree.
+//!
+//! With `--follow`, the view is updated whenever the profile file changes, so
+//! that it can show a recording while `samply record --live` writes it.
+
+// The interactive view needs a Unix terminal.
+#![cfg_attr(not(unix), allow(dead_code))]
+
+#[cfg(unix)]
+use std::io::IsTerminal;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+
+use crate::profile_tools::{CallTree, FunctionSummary};
+
+/// Shows the profile until the user quits. Without a terminal, the function
+/// table is printed once instead.
+pub fn run_top(profile: &Value) -> std::io::Result<()> {
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    let mut view = TopView::new(profile);
+    #[cfg(unix)]
+    if is_interactive() {
+        let _raw_terminal = RawTerminal::enter()?;
+        view.run_interactive(None, || false)?;
+        return Ok(());
+    }
+
+    view.print_functions()
+}
+
+/// How often `follow_top` checks whether the profile file has changed.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Shows the profile at `path` until the user quits, and reloads it with
+/// `load_profile` whenever the file changes. Waits for the file if it doesn't
+/// exist yet. Without a terminal, the function table is printed each time the
+/// profile changes.
+pub fn follow_top(
+    path: &Path,
+    mut load_profile: impl FnMut(&Path) -> Option<Value>,
+) -> std::io::Result<()> {
+    let mut follower = ProfileFollower {
+        path,
+        last_modified: None,
+    };
+    if !path.exists() {
+        eprintln!("Waiting for {path:?} to be written...");
+    }
+    let mut profile = follower.wait_for_change(&mut load_profile);
+
+    #[cfg(unix)]
+    if is_interactive() {
+        let _raw_terminal = RawTerminal::enter()?;
+        let mut state = None;
+        loop {
+            let mut view = TopView::new(&profile);
+            if let Some(state) = state {
+                view.restore_state(state);
+            }
+            let mut next_profile = None;
+            let quit = view.run_interactive(Some(FOLLOW_INTERVAL), || {
+                next_profile = follower.load_if_changed(&mut load_profile);
+                next_profile.is_some()
+            })?;
+            if quit {
+                return Ok(());
+            }
+            state = Some(view.state());
+            profile = next_profile.expect("only returns without quitting after a reload");
+        }
+    }
+
+    loop {
+        TopView::new(&profile).print_functions()?;
+        profile = follower.wait_for_change(&mut load_profile);
+    }
+}
+
+/// Watches the modification time of a profile file.
+struct ProfileFollower<'a> {
+    path: &'a Path,
+    last_modified: Option<SystemTime>,
+}
+
+impl ProfileFollower<'_> {
+    /// Loads the profile if the file has changed since the last load.
+    fn load_if_changed(
+        &mut self,
+        load_profile: &mut impl FnMut(&Path) -> Option<Value>,
+    ) -> Option<Value> {
+        let modified = std::fs::metadata(self.path).ok()?.modified().ok()?;
+        if self.last_modified == Some(modified) {
+            return None;
+        }
+        let profile = load_profile(self.path)?;
+        self.last_modified = Some(modified);
+        Some(profile)
+    }
+
+    fn wait_for_change(&mut self, load_profile: &mut impl FnMut(&Path) -> Option<Value>) -> Value {
+        loop {
+            if let Some(profile) = self.load_if_changed(load_profile) {
+                return profile;
+            }
+            std::thread::sleep(FOLLOW_INTERVAL);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stdout().is_terminal()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Functions,
+    Flame,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortBy {
+    SelfWeight,
+    Total,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Back,
+    Tab,
+    Quit,
+    Char(char),
+}
+
+/// What the user has chosen in a `TopView`, so that it can be kept when the
+/// profile is reloaded.
+struct ViewState {
+    mode: Mode,
+    sort: SortBy,
+    thread_choice: Option<usize>,
+    selected_function: Option<String>,
+    flame_path: Vec<String>,
+    flame_selected: usize,
+}
+
+struct TopView<'a> {
+    threads: Vec<&'a Value>,
+    /// The thread which is shown, or None for all threads.
+    thread_choice: Option<usize>,
+    tree: CallTree,
+    functions: Vec<FunctionSummary>,
+    mode: Mode,
+    sort: SortBy,
+    selected_function: usize,
+    flame_root: usize,
+    /// The index of the selected child of `flame_root`.
+    flame_selected: usize,
+}
+
+const FLAME_COLORS: [&str; 2] = ["\x1b[30;43m", "\x1b[30;41m"];
+const FLAME_SELECTED_COLOR: &str = "\x1b[30;46m";
+const RESET: &str = "\x1b[0m";
+const INVERSE: &str = "\x1b[7m";
+
+impl<'a> TopView<'a> {
+    fn new(profile: &'a Value) -> Self {
+        let threads: Vec<&Value> = profile["threads"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .collect();
+        let mut view = TopView {
+            tree: CallTree::from_threads(threads.iter().copied()),
+            threads,
+            thread_choice: None,
+            functions: Vec::new(),
+            mode: Mode::Functions,
+            sort: SortBy::SelfWeight,
+            selected_function: 0,
+            flame_root: CallTree::ROOT,
+            flame_selected: 0,
+        };
+        view.functions = view.tree.function_summaries();
+        view
+    }
+
+    fn select_thread(&mut self, thread_choice: Option<usize>) {
+        self.thread_choice = thread_choice;
+        self.tree = match thread_choice {
+            Some(index) => CallTree::from_threads([self.threads[index]]),
+            None => CallTree::from_threads(self.threads.iter().copied()),
+        };
+        self.functions = self.tree.function_summaries();
+        self.sort_functions();
+        self.selected_function = 0;
+        self.flame_root = CallTree::ROOT;
+        self.flame_selected = 0;
+    }
+
+    fn state(&self) -> ViewState {
+        ViewState {
+            mode: self.mode,
+            sort: self.sort,
+            thread_choice: self.thread_choice,
+            selected_function: self
+                .functions
+                .get(self.selected_function)
+                .map(|function| function.name.clone()),
+            flame_path: self
+                .tree
+                .path(self.flame_root)
+                .into_iter()
+                .map(ToOwned::to_owned)
+                .collect(),
+            flame_selected: self.flame_selected,
+        }
+    }
+
+    /// Applies the choices from the view of an earlier version of the profile,
+    /// as far as they still exist in this one.
+    fn restore_state(&mut self, state: ViewState) {
+        if state
+            .thread_choice
+            .is_some_and(|index| index < self.threads.len())
+        {
+            self.select_thread(state.thread_choice);
+        }
+        self.mode = state.mode;
+        self.sort = state.sort;
+        self.sort_functions();
+        if let Some(name) = state.selected_function {
+            self.selected_function = self
+                .functions
+                .iter()
+                .position(|function| function.name == name)
+                .unwrap_or(0);
+        }
+        for name in &state.flame_path {
+            let child = self
+                .tree
+                .node(self.flame_root)
+                .children
+                .iter()
+                .copied()
+                .find(|child| self.tree.node(*child).name == *name);
+            match child {
+                Some(child) => self.flame_root = child,
+                None => break,
+            }
+        }
+        let flame_child_count = self.tree.node(self.flame_root).children.len();
+        self.flame_selected = state
+            .flame_selected
+            .min(flame_child_count.saturating_sub(1));
+    }
+
+    fn sort_functions(&mut self) {
+        match self.sort {
+            SortBy::SelfWeight => self
+                .functions
+                .sort_by(|a, b| b.self_weight.total_cmp(&a.self_weight)),
+            SortBy::Total => self.functions.sort_by(|a, b| b.total.total_cmp(&a.total)),
+        }
+    }
+
+    fn thread_label(&self) -> String {
+        match self.thread_choice {
+            Some(index) => {
+                let thread = self.threads[index];
+                format!(
+                    "{} - {} ({})",
+                    thread["processName"].as_str().unwrap_or("Process"),
+                    thread["name"].as_str().unwrap_or("Thread"),
+                    thread["tid"].as_str().unwrap_or_default()
+                )
+            }
+            None => format!("All threads ({})", self.threads.len()),
+        }
+    }
+
+    /// Returns false if the view should be closed.
+    fn handle_key(&mut self, key: Key) -> bool {
+        let flame_child_count = self.tree.node(self.flame_root).children.len();
+        match (self.mode, key) {
+            (_, Key::Quit) => return false,
+            (_, Key::Tab) => {
+                self.mode = match self.mode {
+                    Mode::Functions => Mode::Flame,
+                    Mode::Flame => Mode::Functions,
+                }
+            }
+            (_, Key::Char('t')) => {
+                let next = match self.thread_choice {
+                    None if !self.threads.is_empty() => Some(0),
+                    Some(index) if index + 1 < self.threads.len() => Some(index + 1),
+                    _ => None,
+                };
+                self.select_thread(next);
+            }
+            (Mode::Functions, Key::Char('s')) => {
+                self.sort = match self.sort {
+                    SortBy::SelfWeight => SortBy::Total,
+                    SortBy::Total => SortBy::SelfWeight,
+                };
+                self.sort_functions();
+            }
+            (Mode::Functions, Key::Up) => {
+                self.selected_function = self.selected_function.saturating_sub(1);
+            }
+            (Mode::Functions, Key::Down) if self.selected_function + 1 < self.functions.len() => {
+                self.selected_function += 1;
+            }
+            (Mode::Flame, Key::Left) => {
+                self.flame_selected = self.flame_selected.saturating_sub(1);
+            }
+            (Mode::Flame, Key::Right) if self.flame_selected + 1 < flame_child_count => {
+                self.flame_selected += 1;
+            }
+            (Mode::Flame, Key::Enter | Key::Down) => {
+                if let Some(child) = self
+                    .tree
+                    .node(self.flame_root)
+                    .children
+                    .get(self.flame_selected)
+                {
+                    self.flame_root = *child;
+                    self.flame_selected = 0;
+                }
+            }
+            (Mode::Flame, Key::Back | Key::Up) => {
+                if let Some(parent) = self.tree.node(self.flame_root).parent {
+                    let node = self.flame_root;
+                    self.flame_root = parent;
+                    self.flame_selected = self
+                        .tree
+                        .node(parent)
+                        .children
+                        .iter()
+                        .position(|child| *child == node)
+                        .unwrap_or(0);
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn render(&self, width: usize, height: usize) -> String {
+        let mut lines = vec![format!(
+            "{INVERSE}{}{RESET}",
+            fit(
+                &format!(
+                    " samply top | {} | {} samples",
+                    self.thread_label(),
+                    self.tree.total()
+                ),
+                width
+            )
+        )];
+        let body_height = height.saturating_sub(2);
+        let (mut body, help) = match self.mode {
+            Mode::Functions => (
+                self.function_lines(width, body_height, true),
+                " Up/Down: select  s: sort  Tab: flame graph  t: next thread  q: quit",
+            ),
+            Mode::Flame => (
+                self.flame_lines(width, body_height),
+                " Left/Right: select  Enter: zoom in  Backspace: zoom out  Tab: functions  t: next thread  q: quit",
+            ),
+        };
+        body.resize(body_height, String::new());
+        lines.extend(body);
+        lines.push(fit(help, width));
+        lines.join("\r\n")
+    }
+
+    fn function_lines(&self, width: usize, height: usize, highlight: bool) -> Vec<String> {
+        let total = self.tree.total();
+        let percent = |weight: f64| {
+            if total > 0.0 {
+                weight / total * 100.0
+            } else {
+                0.0
+            }
+        };
+        let sort_marker = |sort: SortBy| if self.sort == sort { "*" } else { " " };
+        let mut lines = vec![fit(
+            &format!(
+                "{:>7}{} {:>9} {:>7}{} {:>9}  Function",
+                "Self %",
+                sort_marker(SortBy::SelfWeight),
+                "Self",
+                "Total %",
+                sort_marker(SortBy::Total),
+                "Total"
+            ),
+            width,
+        )];
+        let rows = height.saturating_sub(1);
+        let scroll = if highlight {
+            (self.selected_function + 1).saturating_sub(rows)
+        } else {
+            0
+        };
+        for (i, function) in self.functions.iter().enumerate().skip(scroll).take(rows) {
+            let line = fit(
+                &format!(
+                    "{:>7.1}% {:>9} {:>7.1}% {:>9}  {}",
+                    percent(function.self_weight),
+                    function.self_weight,
+                    percent(function.total),
+                    function.total,
+                    function.name
+                ),
+                width,
+            );
+            if highlight && i == self.selected_function {
+                lines.push(format!("{INVERSE}{line}{RESET}"));
+            } else {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    fn flame_lines(&self, width: usize, height: usize) -> Vec<String> {
+        let path = self.tree.path(self.flame_root);
+        let mut lines = vec![fit(
+            &if path.is_empty() {
+                "All samples".to_owned()
+            } else {
+                format!("Zoomed into: {}", path.join(" > "))
+            },
+            width,
+        )];
+        let root = self.tree.node(self.flame_root);
+        let selected = root.children.get(self.flame_selected).copied();
+        if let Some(selected) = selected {
+            let node = self.tree.node(selected);
+            let percent = node.total / root.total.max(1.0) * 100.0;
+            lines.push(fit(
+                &format!(
+                    "Selected: {} ({} samples, {percent:.1}%)",
+                    node.name, node.total
+                ),
+                width,
+            ));
+        } else {
+            lines.push(String::new());
+        }
+
+        // An icicle graph: the zoomed-in node at the top, callees below.
+        let mut rows = vec![FlameRow::default(); height.saturating_sub(2)];
+        self.add_flame_segment(self.flame_root, 0, 0, width, selected, &mut rows);
+        lines.extend(rows.into_iter().map(|row| row.text));
+        lines
+    }
+
+    fn add_flame_segment(
+        &self,
+        index: usize,
+        depth: usize,
+        start: usize,
+        width: usize,
+        selected: Option<usize>,
+        rows: &mut [FlameRow],
+    ) {
+        let Some(row) = rows.get_mut(depth) else {
+            return;
+        };
+        if width == 0 {
+            return;
+        }
+        let node = self.tree.node(index);
+        let name = match index {
+            CallTree::ROOT => "all samples",
+            _ => node.name.as_str(),
+        };
+        let color = if Some(index) == selected {
+            FLAME_SELECTED_COLOR
+        } else {
+            FLAME_COLORS[row.segment_count % FLAME_COLORS.len()]
+        };
+        row.text += &" ".repeat(start.saturating_sub(row.column));
+        row.text += &format!("{color}{}{RESET}", fit(name, width));
+        row.column = start + width;
+        row.segment_count += 1;
+
+        let mut child_start = start;
+        for child in &node.children {
+            let child_width = (self.tree.node(*child).total / node.total * width as f64) as usize;
+            if child_width == 0 {
+                // The children are sorted by size, so the rest is too small as well.
+                break;
+            }
+            self.add_flame_segment(*child, depth + 1, child_start, child_width, selected, rows);
+            child_start += child_width;
+        }
+    }
+
+    fn print_functions(&self) -> std::io::Result<()> {
+        let mut out = std::io::stdout().lock();
+        for line in self.function_lines(120, 40, false) {
+            writeln!(out, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+
+    /// Handles keys until the user quits, in which case true is returned, or
+    /// until `should_reload` returns true. `should_reload` is called whenever
+    /// no key has been pressed for `reload_interval`. The terminal needs to be
+    /// in raw mode.
+    #[cfg(unix)]
+    fn run_interactive(
+        &mut self,
+        reload_interval: Option<Duration>,
+        mut should_reload: impl FnMut() -> bool,
+    ) -> std::io::Result<bool> {
+        let mut stdin = std::io::stdin().lock();
+        let mut stdout = std::io::stdout().lock();
+        loop {
+            let (width, height) = terminal_size();
+            write!(stdout, "\x1b[H\x1b[2J{}", self.render(width, height))?;
+            stdout.flush()?;
+            while !wait_for_input(reload_interval)? {
+                if should_reload() {
+                    return Ok(false);
+                }
+            }
+            if let Some(key) = read_key(&mut stdin)? {
+                if !self.handle_key(key) {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+}
+
+/// Waits until stdin can be read, or until the timeout has passed, in which
+/// case false is returned.
+#[cfg(unix)]
+fn wait_for_input(timeout: Option<Duration>) -> std::io::Result<bool> {
+    let mut poll_fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout_ms = timeout.map_or(-1, |timeout| timeout.as_millis() as libc::c_int);
+    match unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) } {
+        -1 => {
+            let err = std::io::Error::last_os_error();
+            match err.kind() {
+                // Interrupted by a signal, e.g. when the terminal is resized.
+                std::io::ErrorKind::Interrupted => Ok(false),
+                _ => Err(err),
+            }
+        }
+        0 => Ok(false),
+        _ => Ok(true),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct FlameRow {
+    text: String,
+    column: usize,
+    segment_count: usize,
+}
+
+/// Cuts off or pads `s` to exactly `width` characters.
+fn fit(s: &str, width: usize) -> String {
+    let mut fitted: String = s.chars().take(width).collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat(' ').take(width - len));
+    fitted
+}
+
+fn read_key(stdin: &mut impl Read) -> std::io::Result<Option<Key>> {
+    let mut buf = [0u8; 8];
+    let len = stdin.read(&mut buf)?;
+    let key = match &buf[..len] {
+        // End of input, Ctrl+C, Escape
+        [] | [3] | [0x1b] | [b'q'] => Key::Quit,
+        [0x1b, b'[', b'A'] | [b'k'] => Key::Up,
+        [0x1b, b'[', b'B'] | [b'j'] => Key::Down,
+        [0x1b, b'[', b'C'] | [b'l'] => Key::Right,
+        [0x1b, b'[', b'D'] | [b'h'] => Key::Left,
+        [b'\r'] | [b'\n'] => Key::Enter,
+        [0x7f] | [0x08] => Key::Back,
+        [b'\t'] => Key::Tab,
+        [c] => Key::Char(*c as char),
+        _ => return Ok(None),
+    };
+    Ok(Some(key))
+}
+
+/// Switches the terminal to unbuffered input without echo and to the
+/// alternate screen, and restores it when dropped.
+#[cfg(unix)]
+struct RawTerminal {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawTerminal {
+    fn enter() -> std::io::Result<Self> {
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let original = termios;
+        // Ctrl+C is read as a key, so that the terminal is restored on exit.
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        print!("\x1b[?1049h\x1b[?25l");
+        Ok(RawTerminal { original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+#[cfg(unix)]
+fn terminal_size() -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result != 0 || size.ws_col == 0 || size.ws_row == 0 {
+        return (80, 24);
+    }
+    (size.ws_col as usize, size.ws_row as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn navigate_flame_graph() {
+        let profile = json!({
+            "threads": [{
+                "name": "main",
+                "stringArray": ["main", "parse", "render"],
+                "funcTable": { "length": 3, "name": [0, 1, 2] },
+                "frameTable": { "length": 3, "func": [0, 1, 2], "address": [-1, -1, -1] },
+                "stackTable": { "length": 3, "frame": [0, 1, 2], "prefix": [null, 0, 0] },
+                "samples": { "length": 4, "stack": [1, 1, 1, 2], "weight": [1, 1, 1, 1] },
+            }],
+        });
+        let mut view = TopView::new(&profile);
+        assert_eq!(view.functions[0].name, "parse");
+        assert!(view.handle_key(Key::Tab));
+        assert!(view.handle_key(Key::Enter));
+
+        // main is zoomed into, parse takes 3/4 of the width below it.
+        let lines = view.flame_lines(8, 4);
+        assert_eq!(lines[0], "Zoomed into: main"[..8]);
+        assert_eq!(lines[2], format!("{}main    {RESET}", FLAME_COLORS[0]));
+        assert_eq!(
+            lines[3],
+            format!(
+                "{FLAME_SELECTED_COLOR}parse {RESET}{}re{RESET}",
+                FLAME_COLORS[1]
+            )
+        );
+
+        assert!(view.handle_key(Key::Back));
+        assert_eq!(view.flame_root, CallTree::ROOT);
+        assert!(!view.handle_key(Key::Quit));
+    }
+
+    #[test]
+    fn keep_view_state_on_reload() {
+        let thread = |render_samples: usize| {
+            let stacks = [vec![1, 1], vec![2; render_samples]].concat();
+            json!({
+                "name": "main",
+                "stringArray": ["main", "parse", "render"],
+                "funcTable": { "length": 3, "name": [0, 1, 2] },
+                "frameTable": { "length": 3, "func": [0, 1, 2], "address": [-1, -1, -1] },
+                "stackTable": { "length": 3, "frame": [0, 1, 2], "prefix": [null, 0, 0] },
+                "samples": {
+                    "length": 2 + render_samples,
+                    "weight": vec![1; stacks.len()],
+                    "stack": stacks,
+                },
+            })
+        };
+        let profile = json!({ "threads": [thread(1)] });
+        let mut view = TopView::new(&profile);
+        assert!(view.handle_key(Key::Down));
+        assert_eq!(view.functions[view.selected_function].name, "render");
+        assert!(view.handle_key(Key::Tab));
+        assert!(view.handle_key(Key::Enter));
+        let state = view.state();
+
+        // render now has more samples than parse, and is still selected.
+        let reloaded = json!({ "threads": [thread(5)] });
+        let mut view = TopView::new(&reloaded);
+        view.restore_state(state);
+        assert_eq!(view.mode, Mode::Flame);
+        assert_eq!(view.functions[view.selected_function].name, "render");
+        assert_eq!(view.tree.path(view.flame_root), ["main"]);
+    }
+}
@@ -0,0 +1,174 @@
+//! Fetches the symbols of the modules which changed between two releases into
+//! the local symbol cache, so that the first profiles after a deploy don't
+//! have to wait for the downloads.
+//!
+//! A build manifest lists the modules of a release, one per line, as the
+//! debug name and the debug ID, e.g. `libxul.so 8B5A7F3C2E1D4A6B9C0D1E2F3A4B5C6D0`.
+//! Empty lines and lines starting with `#` are ignored. `samply
+//! publish-symbols --manifest` writes such a file.
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use debugid::DebugId;
+use futures_util::StreamExt;
+use wholesym::SymbolManager;
+
+use crate::server::create_symbol_manager;
+use crate::shared::symbol_props::SymbolProps;
+
+/// How many modules are fetched at the same time.
+const CONCURRENT_FETCHES: usize = 4;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Line {0} of the manifest is not a debug name and a debug ID")]
+    InvalidLine(usize),
+
+    #[error("Could not read {0:?}: {1}")]
+    Binary(PathBuf, wholesym::Error),
+
+    #[error("{0:?} has no debug ID")]
+    NoDebugId(PathBuf),
+}
+
+/// A module in a build manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ManifestEntry {
+    pub debug_name: String,
+    pub debug_id: DebugId,
+}
+
+pub fn read_manifest(path: &Path) -> Result<Vec<ManifestEntry>, Error> {
+    parse_manifest(&std::fs::read_to_string(path)?)
+}
+
+fn parse_manifest(text: &str) -> Result<Vec<ManifestEntry>, Error> {
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Debug names can contain spaces, debug IDs can't.
+        let entry = line
+            .rsplit_once(char::is_whitespace)
+            .and_then(|(debug_name, debug_id)| {
+                Some(ManifestEntry {
+                    debug_name: debug_name.trim_end().to_owned(),
+                    debug_id: DebugId::from_breakpad(debug_id)
+                        .or_else(|_| debug_id.parse())
+                        .ok()?,
+                })
+            })
+            .ok_or(Error::InvalidLine(index + 1))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+pub fn write_manifest(entries: &[ManifestEntry], mut writer: impl Write) -> std::io::Result<()> {
+    for entry in entries {
+        writeln!(writer, "{} {}", entry.debug_name, entry.debug_id.breakpad())?;
+    }
+    Ok(())
+}
+
+/// The manifest entry for a binary.
+#[tokio::main(flavor = "current_thread")]
+pub async fn manifest_entry_for_binary(path: &Path) -> Result<ManifestEntry, Error> {
+    let info = SymbolManager::library_info_for_binary_at_path(path, None)
+        .await
+        .map_err(|err| Error::Binary(path.to_owned(), err))?;
+    match (info.debug_name, info.debug_id) {
+        (Some(debug_name), Some(debug_id)) => Ok(ManifestEntry {
+            debug_name,
+            debug_id,
+        }),
+        _ => Err(Error::NoDebugId(path.to_owned())),
+    }
+}
+
+/// The modules of the new release which weren't in the old release.
+pub fn changed_modules(old: &[ManifestEntry], new: &[ManifestEntry]) -> Vec<ManifestEntry> {
+    let old: HashSet<&ManifestEntry> = old.iter().collect();
+    let mut seen = HashSet::new();
+    new.iter()
+        .filter(|entry| !old.contains(entry) && seen.insert(*entry))
+        .cloned()
+        .collect()
+}
+
+/// Loads the symbols of each module, which downloads them into the symbol
+/// cache and creates the index for Breakpad symbol files. Returns the error
+/// for each module whose symbols couldn't be loaded.
+#[tokio::main]
+pub async fn warm_symbols(
+    modules: &[ManifestEntry],
+    symbol_props: SymbolProps,
+    verbose: bool,
+) -> Vec<(ManifestEntry, wholesym::Error)> {
+    let symbol_manager = create_symbol_manager(symbol_props, verbose);
+    let symbol_manager = &symbol_manager;
+    futures_util::stream::iter(modules)
+        .map(|module| async move {
+            let result = symbol_manager
+                .load_symbol_map(&module.debug_name, module.debug_id)
+                .await;
+            match result {
+                Ok(_) => {
+                    if verbose {
+                        eprintln!("Fetched the symbols of {}", module.debug_name);
+                    }
+                    None
+                }
+                Err(err) => Some((module.clone(), err)),
+            }
+        })
+        .buffer_unordered(CONCURRENT_FETCHES)
+        .filter_map(|failure| async move { failure })
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_changed_modules() {
+        let old = parse_manifest(
+            "# release 1.0\n\
+             app 8B5A7F3C2E1D4A6B9C0D1E2F3A4B5C6D0\n\
+             libfoo.so 11111111111111111111111111111111a\n",
+        )
+        .unwrap();
+        let new = parse_manifest(
+            "app 8B5A7F3C2E1D4A6B9C0D1E2F3A4B5C6D0\n\
+             \n\
+             libfoo.so 22222222222222222222222222222222a\n\
+             My Library.pdb 33333333-3333-3333-3333-333333333333-1\n",
+        )
+        .unwrap();
+        let changed = changed_modules(&old, &new);
+        assert_eq!(changed.len(), 2);
+        assert_eq!(changed[0].debug_name, "libfoo.so");
+        assert_eq!(changed[1].debug_name, "My Library.pdb");
+
+        let mut written = Vec::new();
+        write_manifest(&changed, &mut written).unwrap();
+        assert_eq!(
+            parse_manifest(std::str::from_utf8(&written).unwrap()).unwrap(),
+            changed
+        );
+
+        assert!(matches!(
+            parse_manifest("app not-a-debug-id"),
+            Err(Error::InvalidLine(1))
+        ));
+    }
+}
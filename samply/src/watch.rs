@@ -0,0 +1,149 @@
+//! Helpers for `samply watch`, which records the same command again whenever
+//! the watched files change, and keeps the most recent profiles around.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// How often the watched paths are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Files and directories whose modification times are polled.
+pub struct WatchedPaths {
+    paths: Vec<PathBuf>,
+}
+
+impl WatchedPaths {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self { paths }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// The latest modification time of any watched file. Directories are
+    /// searched recursively, skipping hidden entries like `.git`.
+    pub fn latest_modification(&self) -> Option<SystemTime> {
+        self.paths
+            .iter()
+            .filter_map(|p| latest_modification(p))
+            .max()
+    }
+
+    /// Blocks until a watched file has been modified after `since`.
+    pub fn wait_for_change(&self, since: Option<SystemTime>) {
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            if self.latest_modification() > since {
+                return;
+            }
+        }
+    }
+}
+
+fn latest_modification(path: &Path) -> Option<SystemTime> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_dir() {
+        return metadata.modified().ok();
+    }
+    std::fs::read_dir(path)
+        .ok()?
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| latest_modification(&entry.path()))
+        .max()
+}
+
+/// Names the recorded profiles and deletes all but the most recent ones.
+pub struct ProfileRotation {
+    dir: PathBuf,
+    output_filename: String,
+    keep: usize,
+    next_number: usize,
+    profiles: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl ProfileRotation {
+    /// `output_filename` is the name of the profile file without a number,
+    /// like `profile.json`. The directory is created if it doesn't exist.
+    pub fn new(dir: &Path, output_filename: &Path, keep: usize) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let output_filename = output_filename
+            .file_name()
+            .map_or("profile.json".into(), |name| name.to_string_lossy());
+        Ok(Self {
+            dir: dir.to_owned(),
+            output_filename: output_filename.into_owned(),
+            keep: keep.max(1),
+            next_number: 1,
+            profiles: Default::default(),
+        })
+    }
+
+    /// The list of kept profiles, oldest first, which is shared with the
+    /// server's index page.
+    pub fn profiles(&self) -> Arc<Mutex<Vec<PathBuf>>> {
+        self.profiles.clone()
+    }
+
+    /// The path for the next recording.
+    pub fn next_path(&mut self) -> PathBuf {
+        let path = self
+            .dir
+            .join(numbered_filename(&self.output_filename, self.next_number));
+        self.next_number += 1;
+        path
+    }
+
+    /// Adds a recorded profile and deletes the oldest profiles beyond the
+    /// number of profiles to keep.
+    pub fn add(&mut self, path: PathBuf) {
+        let mut profiles = self.profiles.lock().unwrap();
+        profiles.push(path);
+        let excess = profiles.len().saturating_sub(self.keep);
+        for old_profile in profiles.drain(..excess) {
+            let _ = std::fs::remove_file(&old_profile);
+            let _ = std::fs::remove_file(old_profile.with_extension("syms.json"));
+        }
+    }
+}
+
+/// Inserts the number before the extensions: `profile.json.gz` becomes
+/// `profile-3.json.gz`.
+fn numbered_filename(filename: &str, number: usize) -> String {
+    match filename.split_once('.') {
+        Some((stem, extensions)) => format!("{stem}-{number}.{extensions}"),
+        None => format!("{filename}-{number}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rotate_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rotation =
+            ProfileRotation::new(dir.path(), Path::new("out/profile.json.gz"), 2).unwrap();
+        let mut paths = Vec::new();
+        for _ in 0..3 {
+            let path = rotation.next_path();
+            std::fs::write(&path, "{}").unwrap();
+            rotation.add(path.clone());
+            paths.push(path);
+        }
+        assert_eq!(
+            paths[0].file_name().unwrap().to_str(),
+            Some("profile-1.json.gz")
+        );
+        assert!(!paths[0].exists());
+        assert!(paths[1].exists() && paths[2].exists());
+        assert_eq!(*rotation.profiles().lock().unwrap(), paths[1..]);
+    }
+}
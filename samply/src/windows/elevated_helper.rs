@@ -29,6 +29,8 @@ pub struct ElevatedRecordingProps {
     pub is_attach: bool,
     pub gfx: bool,
     pub browsers: bool,
+    /// The `--etw-provider` arguments, in xperf syntax.
+    pub etw_providers: Vec<String>,
 }
 
 impl ElevatedRecordingProps {
@@ -45,6 +47,11 @@ impl ElevatedRecordingProps {
             is_attach: recording_mode.is_attach_mode(),
             gfx: recording_props.gfx,
             browsers: recording_props.browsers,
+            etw_providers: recording_props
+                .etw_providers
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
         }
     }
 }
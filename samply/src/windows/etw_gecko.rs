@@ -45,8 +45,9 @@ pub fn profile_pid_from_etl_file(context: &mut ProfileContext, etl_file: &Path)
                 if events_lost != 0 {
                     log::warn!("{} events lost", events_lost);
                 }
+                let start_time: i64 = parser.parse("StartTime");
 
-                context.handle_header(timestamp_raw, perf_freq, clock_type);
+                context.handle_header(timestamp_raw, perf_freq, clock_type, start_time);
 
                 if log::log_enabled!(log::Level::Info) {
                     for i in 0..s.property_count() {
@@ -353,6 +354,26 @@ pub fn profile_pid_from_etl_file(context: &mut ProfileContext, etl_file: &Path)
                 // Note: No "/" at end of event name, because we want DotNETRuntimeRundown as well
                 coreclr::handle_coreclr_event(context, &mut core_clr_context, &s, &mut parser);
             }
+            _ if context.has_custom_providers()
+                && context.is_custom_provider(
+                    &guid_to_uuid(&e.EventHeader.ProviderId),
+                    &s.provider_name(),
+                ) =>
+            {
+                let tid = e.EventHeader.ThreadId;
+                let task_and_op = match s.name().split_once('/') {
+                    Some((_provider, task_and_op)) => task_and_op,
+                    None => s.name(),
+                };
+                let fields = event_properties_to_string(&s, &mut parser, None);
+                context.handle_custom_provider_event(
+                    timestamp_raw,
+                    tid,
+                    &s.provider_name(),
+                    task_and_op,
+                    fields.trim_end_matches(", ").to_string(),
+                );
+            }
             _ => {
                 let tid = e.EventHeader.ThreadId;
                 if context.has_thread(tid) {
@@ -364,8 +385,8 @@ pub fn profile_pid_from_etl_file(context: &mut ProfileContext, etl_file: &Path)
         }
     });
 
-    if result.is_err() {
-        dbg!(&result);
+    if let Err(err) = result {
+        eprintln!("Could not read the ETW trace {}: {err}", etl_file.display());
         std::process::exit(1);
     }
 
@@ -374,3 +395,7 @@ pub fn profile_pid_from_etl_file(context: &mut ProfileContext, etl_file: &Path)
         (Instant::now() - processing_start_timestamp).as_secs_f32()
     );
 }
+
+fn guid_to_uuid(guid: &GUID) -> Uuid {
+    Uuid::from_fields(guid.data1, guid.data2, guid.data3, &guid.data4)
+}
@@ -12,3 +12,4 @@ mod winutils;
 mod xperf;
 
 pub use elevated_helper::run_elevated_helper;
+pub use winutils::is_elevated;
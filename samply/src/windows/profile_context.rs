@@ -7,7 +7,8 @@ use fxprof_processed_profile::{
     CategoryColor, CategoryHandle, CounterHandle, CpuDelta, Frame, FrameFlags, FrameInfo,
     LibraryHandle, LibraryInfo, MarkerDynamicField, MarkerFieldFormat, MarkerHandle,
     MarkerLocation, MarkerSchema, MarkerSchemaField, MarkerTiming, ProcessHandle, Profile,
-    ProfilerMarker, SamplingInterval, Symbol, SymbolTable, ThreadHandle, Timestamp,
+    ProfilerMarker, ReferenceTimestamp, SamplingInterval, Symbol, SymbolTable, ThreadHandle,
+    Timestamp,
 };
 use serde_json::{json, Value};
 use uuid::Uuid;
@@ -17,6 +18,7 @@ use super::winutils;
 use crate::shared::context_switch::{
     ContextSwitchHandler, OffCpuSampleGroup, ThreadContextSwitchData,
 };
+use crate::shared::etw_provider_spec::EtwProviderSpec;
 use crate::shared::included_processes::IncludedProcesses;
 use crate::shared::jit_category_manager::JitCategoryManager;
 use crate::shared::jit_function_add_marker::JitFunctionAddMarker;
@@ -292,6 +294,9 @@ pub struct ProfileContext {
 
     /// Only include main threads.
     main_thread_only: bool,
+
+    /// The providers from `--etw-provider`, whose events become markers.
+    custom_providers: Vec<EtwProviderSpec>,
 }
 
 impl ProfileContext {
@@ -344,12 +349,28 @@ impl ProfileContext {
             timestamp_converter: TimestampConverter {
                 reference_raw: 0,
                 raw_to_ns_factor: 1,
+                tsc_conversion: None,
             },
             event_timestamps_are_qpc: false,
             main_thread_only,
+            custom_providers: Vec::new(),
         }
     }
 
+    pub fn set_custom_providers(&mut self, custom_providers: Vec<EtwProviderSpec>) {
+        self.custom_providers = custom_providers;
+    }
+
+    pub fn has_custom_providers(&self) -> bool {
+        !self.custom_providers.is_empty()
+    }
+
+    pub fn is_custom_provider(&self, provider_guid: &Uuid, provider_name: &str) -> bool {
+        self.custom_providers
+            .iter()
+            .any(|spec| spec.matches(provider_guid, provider_name))
+    }
+
     pub fn creation_props(&self) -> ProfileCreationProps {
         self.profile_creation_props.clone()
     }
@@ -524,7 +545,24 @@ impl ProfileContext {
             .add_marker(thread.handle, category, name, marker, timing)
     }
 
-    pub fn handle_header(&mut self, timestamp_raw: u64, perf_freq: u64, clock_type: u32) {
+    /// `start_time` is the wall-clock time at which the trace started, as a
+    /// FILETIME, i.e. in 100ns units since 1601-01-01.
+    pub fn handle_header(
+        &mut self,
+        timestamp_raw: u64,
+        perf_freq: u64,
+        clock_type: u32,
+        start_time: i64,
+    ) {
+        const FILETIME_UNIX_EPOCH: i64 = 116_444_736_000_000_000;
+        if start_time > FILETIME_UNIX_EPOCH {
+            let ms_since_unix_epoch = (start_time - FILETIME_UNIX_EPOCH) as f64 / 10_000.0;
+            self.profile
+                .set_reference_timestamp(ReferenceTimestamp::from_millis_since_unix_epoch(
+                    ms_since_unix_epoch,
+                ));
+        }
+
         if clock_type != 1 {
             log::warn!("QPC not used as clock");
             self.event_timestamps_are_qpc = false;
@@ -535,6 +573,7 @@ impl ProfileContext {
         self.timestamp_converter = TimestampConverter {
             reference_raw: timestamp_raw,
             raw_to_ns_factor: 1000 * 1000 * 1000 / perf_freq,
+            tsc_conversion: None,
         };
     }
 
@@ -1747,6 +1786,33 @@ impl ProfileContext {
         //println!("unhandled {}", s.name())
     }
 
+    pub fn handle_custom_provider_event(
+        &mut self,
+        timestamp_raw: u64,
+        tid: u32,
+        provider_name: &str,
+        task_and_op: &str,
+        fields: String,
+    ) {
+        let Some(thread) = self.threads.get_mut(&tid) else {
+            return;
+        };
+
+        let timestamp = self.timestamp_converter.convert_time(timestamp_raw);
+        let timing = MarkerTiming::Instant(timestamp);
+        let category = self.categories.get(KnownCategory::User, &mut self.profile);
+        self.profile.add_marker(
+            thread.handle,
+            category,
+            task_and_op,
+            CustomProviderEventMarker {
+                provider: provider_name.to_string(),
+                fields,
+            },
+            timing,
+        );
+    }
+
     pub fn finish(mut self) -> Profile {
         // Push queued samples into the profile.
         // We queue them so that we can get symbolicated JIT function names. To get symbolicated JIT function names,
@@ -1898,3 +1964,46 @@ pub fn make_thread_label_frame(
         flags: FrameFlags::empty(),
     }
 }
+
+/// An event from a provider which was requested with `--etw-provider`.
+#[derive(Debug, Clone)]
+pub struct CustomProviderEventMarker {
+    pub provider: String,
+    pub fields: String,
+}
+
+impl ProfilerMarker for CustomProviderEventMarker {
+    const MARKER_TYPE_NAME: &'static str = "ETWEvent";
+
+    fn json_marker_data(&self) -> Value {
+        json!({
+            "type": Self::MARKER_TYPE_NAME,
+            "provider": self.provider,
+            "fields": self.fields,
+        })
+    }
+
+    fn schema() -> MarkerSchema {
+        MarkerSchema {
+            type_name: Self::MARKER_TYPE_NAME,
+            locations: vec![MarkerLocation::MarkerChart, MarkerLocation::MarkerTable],
+            chart_label: Some("{marker.name}"),
+            tooltip_label: Some("{marker.name} {marker.data.fields}"),
+            table_label: Some("{marker.name} - {marker.data.fields}"),
+            fields: vec![
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "provider",
+                    label: "Provider",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+                MarkerSchemaField::Dynamic(MarkerDynamicField {
+                    key: "fields",
+                    label: "Fields",
+                    format: MarkerFieldFormat::String,
+                    searchable: true,
+                }),
+            ],
+        }
+    }
+}
@@ -15,6 +15,7 @@ use serde_json::to_writer;
 
 use super::profile_context::ProfileContext;
 use super::{etw_gecko, winutils};
+use crate::profile_tools::post_process_profile_file;
 use crate::server::{start_server_main, ServerProps};
 use crate::shared::ctrl_c::CtrlC;
 use crate::shared::included_processes::IncludedProcesses;
@@ -150,8 +151,10 @@ pub fn start_recording(
         .unwrap_or(get_native_arch().to_string());
 
     let unstable_presymbolicate = profile_creation_props.unstable_presymbolicate;
+    let post_processing = profile_creation_props.post_processing.clone();
     let mut context =
         ProfileContext::new(profile, &arch, included_processes, profile_creation_props);
+    context.set_custom_providers(recording_props.etw_providers.clone());
     etw_gecko::profile_pid_from_etl_file(&mut context, &merged_etl);
     let profile = context.finish();
 
@@ -170,6 +173,9 @@ pub fn start_recording(
         to_writer(writer, &profile).expect("Couldn't write JSON");
     }
 
+    post_process_profile_file(&output_file, &post_processing)
+        .expect("Couldn't post-process the profile");
+
     if unstable_presymbolicate {
         crate::shared::symbol_precog::presymbolicate(
             &profile,
@@ -61,6 +61,7 @@ impl Xperf {
         user_providers.append(&mut super::gfx::gfx_xperf_args(props));
         user_providers.append(&mut super::firefox::firefox_xperf_args(props));
         user_providers.append(&mut super::chrome::chrome_xperf_args(props));
+        user_providers.extend(props.etw_providers.iter().cloned());
         user_providers.sort_unstable();
         user_providers.dedup();
 
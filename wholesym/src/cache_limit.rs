@@ -0,0 +1,240 @@
+//! Keeping the cache directories of downloaded files below a maximum size.
+//!
+//! The files are evicted in least-recently-used order. A file's modification
+//! time is its last use: it is set when the file is downloaded, and again
+//! whenever the file is loaded from the cache.
+//!
+//! Downloads can run while files are evicted. They write to a `.part` file
+//! next to the destination and rename it when they're done, and symsrv also
+//! keeps a `.lock` file there, so these files are never evicted. The eviction
+//! which follows a download also keeps the files which were used after the
+//! download started.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Some file systems store modification times in steps of up to two seconds,
+/// so a file which was used up to this long before a point in time could have
+/// been used after it.
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+/// The files in a cache directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheDirUsage {
+    pub file_count: u64,
+    pub total_bytes: u64,
+}
+
+/// What [`evict_least_recently_used`] removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheEviction {
+    pub removed_files: u64,
+    pub removed_bytes: u64,
+}
+
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    last_use: SystemTime,
+}
+
+/// Counts the files in the directory and its subdirectories. A directory
+/// which doesn't exist is empty.
+pub fn cache_dir_usage(dir: &Path) -> CacheDirUsage {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files);
+    CacheDirUsage {
+        file_count: files.len() as u64,
+        total_bytes: files.iter().map(|file| file.size).sum(),
+    }
+}
+
+/// Removes the least recently used files in the directories until all of
+/// them together take up at most `max_bytes`.
+pub fn evict_least_recently_used(dirs: &[PathBuf], max_bytes: u64) -> CacheEviction {
+    evict_files(dirs, max_bytes, None)
+}
+
+/// Like [`evict_least_recently_used`], but keeps all files which were used
+/// since `start`, e.g. the files of the download which made the cache too
+/// large. They count towards the total, so fewer files may be left than
+/// `max_bytes` would allow.
+pub(crate) fn evict_files_used_before(
+    dirs: &[PathBuf],
+    max_bytes: u64,
+    start: SystemTime,
+) -> CacheEviction {
+    let cutoff = start.checked_sub(MTIME_GRANULARITY).unwrap_or(start);
+    evict_files(dirs, max_bytes, Some(cutoff))
+}
+
+fn evict_files(
+    dirs: &[PathBuf],
+    max_bytes: u64,
+    keep_used_since: Option<SystemTime>,
+) -> CacheEviction {
+    let mut files = Vec::new();
+    for dir in dirs {
+        collect_files(dir, &mut files);
+    }
+    let mut total_bytes: u64 = files.iter().map(|file| file.size).sum();
+    files.sort_by_key(|file| file.last_use);
+
+    let mut eviction = CacheEviction::default();
+    for file in files {
+        if total_bytes <= max_bytes {
+            break;
+        }
+        if keep_used_since.is_some_and(|since| file.last_use >= since) {
+            break;
+        }
+        if is_download_in_progress(&file.path) {
+            continue;
+        }
+        if std::fs::remove_file(&file.path).is_ok() {
+            total_bytes -= file.size;
+            eviction.removed_files += 1;
+            eviction.removed_bytes += file.size;
+        }
+    }
+    eviction
+}
+
+/// Makes the file the most recently used one.
+pub fn mark_used(path: &Path) {
+    let _ = filetime::set_file_mtime(path, filetime::FileTime::now());
+}
+
+/// The path which a download to `dest_path` writes to until it's complete.
+pub(crate) fn part_path(dest_path: &Path) -> PathBuf {
+    let mut file_name = dest_path
+        .file_name()
+        .map(OsString::from)
+        .unwrap_or_default();
+    file_name.push(".part");
+    dest_path.with_file_name(file_name)
+}
+
+/// Whether the file is one which a download is writing, or the lock file of
+/// one, as created by [`part_path`] or by symsrv.
+fn is_download_in_progress(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "part" || extension == "lock")
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<CachedFile>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            collect_files(&entry.path(), files);
+        } else if metadata.is_file() {
+            files.push(CachedFile {
+                path: entry.path(),
+                size: metadata.len(),
+                last_use: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn evicts_oldest_files_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        for (name, age_secs) in [("a", 30), ("sub/b", 20), ("c", 10)] {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, [0u8; 100]).unwrap();
+            let mtime = filetime::FileTime::from_system_time(now - Duration::from_secs(age_secs));
+            filetime::set_file_mtime(&path, mtime).unwrap();
+        }
+        // "a" is the oldest, but it was used just now.
+        mark_used(&dir.path().join("a"));
+
+        let dirs = [dir.path().to_owned()];
+        assert_eq!(
+            cache_dir_usage(dir.path()),
+            CacheDirUsage {
+                file_count: 3,
+                total_bytes: 300
+            }
+        );
+        assert_eq!(
+            evict_least_recently_used(&dirs, 150),
+            CacheEviction {
+                removed_files: 2,
+                removed_bytes: 200
+            }
+        );
+        assert!(dir.path().join("a").exists());
+        assert!(!dir.path().join("sub/b").exists());
+        assert!(!dir.path().join("c").exists());
+    }
+
+    #[test]
+    fn keeps_recently_used_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        for (name, age_secs) in [("old", 60), ("downloaded", 1)] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, [0u8; 100]).unwrap();
+            let mtime = filetime::FileTime::from_system_time(now - Duration::from_secs(age_secs));
+            filetime::set_file_mtime(&path, mtime).unwrap();
+        }
+        // The file whose download started the eviction is kept even though
+        // the cache is still too large without the other file.
+        let dirs = [dir.path().to_owned()];
+        let eviction = evict_files_used_before(&dirs, 50, now - Duration::from_secs(10));
+        assert_eq!(eviction.removed_files, 1);
+        assert!(!dir.path().join("old").exists());
+        assert!(dir.path().join("downloaded").exists());
+    }
+
+    #[test]
+    fn keeps_files_which_are_being_downloaded() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let old_path = dir.path().join("old");
+        std::fs::write(&old_path, [0u8; 100]).unwrap();
+        let old_mtime = SystemTime::now() - Duration::from_secs(60);
+        filetime::set_file_mtime(&old_path, filetime::FileTime::from_system_time(old_mtime))
+            .unwrap();
+
+        // A download which was started a while ago and is still being
+        // written while the eviction runs.
+        let dest_path = dir.path().join("sub/file.sym");
+        std::fs::create_dir_all(dest_path.parent().unwrap()).unwrap();
+        let part = part_path(&dest_path);
+        let mut file = std::fs::File::create(&part).unwrap();
+        file.write_all(&[0u8; 100]).unwrap();
+        filetime::set_file_mtime(&part, filetime::FileTime::from_system_time(old_mtime)).unwrap();
+        let writer_dest_path = dest_path.clone();
+        let writer = std::thread::spawn(move || {
+            for _ in 0..100 {
+                file.write_all(&[0u8; 100]).unwrap();
+            }
+            drop(file);
+            std::fs::rename(&part, &writer_dest_path).unwrap();
+        });
+
+        let dirs = [dir.path().to_owned()];
+        let eviction = evict_least_recently_used(&dirs, 0);
+        writer.join().unwrap();
+        assert_eq!(eviction.removed_files, 1);
+        assert!(!old_path.exists());
+        assert_eq!(std::fs::metadata(&dest_path).unwrap().len(), 10100);
+    }
+}
@@ -3,6 +3,8 @@ use std::path::PathBuf;
 
 use symsrv::{parse_nt_symbol_path, NtSymbolPathEntry};
 
+use crate::cloud_store::is_cloud_store_url;
+
 /// The configuration of a [`SymbolManager`](crate::SymbolManager).
 ///
 /// Allows specifying various sources of symbol files.
@@ -15,6 +17,9 @@ pub struct SymbolManagerConfig {
     pub(crate) breakpad_directories_readonly: Vec<PathBuf>,
     pub(crate) breakpad_servers: Vec<(String, PathBuf)>,
     pub(crate) breakpad_symindex_cache_dir: Option<PathBuf>,
+    pub(crate) source_cache_dir: Option<PathBuf>,
+    pub(crate) max_cache_size: Option<u64>,
+    pub(crate) max_concurrent_downloads_per_server: Option<usize>,
     pub(crate) windows_servers: Vec<(String, PathBuf)>,
     pub(crate) use_debuginfod: bool,
     pub(crate) use_spotlight: bool,
@@ -22,6 +27,21 @@ pub struct SymbolManagerConfig {
     pub(crate) debuginfod_servers: Vec<(String, PathBuf)>,
     pub(crate) extra_symbol_directories: Vec<PathBuf>,
     pub(crate) simpleperf_binary_cache_directories: Vec<PathBuf>,
+    pub(crate) perf_build_id_cache_directories: Vec<PathBuf>,
+    pub(crate) forced_backends: HashMap<String, SymbolBackend>,
+    pub(crate) omit_pdb_function_arguments: bool,
+}
+
+/// The kind of symbol file which symbols are obtained from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolBackend {
+    /// Symbol tables and DWARF debug info in ELF and Mach-O files, including
+    /// separate debug files and dSYM bundles.
+    Dwarf,
+    /// Breakpad .sym files, from local directories or symbol servers.
+    Breakpad,
+    /// PDB files, and PE binaries which refer to their PDB file.
+    Pdb,
 }
 
 impl SymbolManagerConfig {
@@ -79,6 +99,10 @@ impl SymbolManagerConfig {
             (None, None) => None,
         };
         for (base_url, cache_dir) in &self.windows_servers {
+            if is_cloud_store_url(base_url) {
+                // Cloud storage buckets are accessed by the helper, not by symsrv.
+                continue;
+            }
             path.get_or_insert_with(Default::default)
                 .push(NtSymbolPathEntry::Chain {
                     dll: "symsrv.dll".to_string(),
@@ -99,6 +123,7 @@ impl SymbolManagerConfig {
     }
 
     /// Add a server to search for breakpad symbol files, along with a local cache directory.
+    /// The server can also be an `s3://` or `gs://` bucket URL, see [`CloudStore`](crate::CloudStore).
     ///
     /// This method can be called multiple times; the servers and caches will be tried in the order of those calls.
     pub fn breakpad_symbols_server(
@@ -122,7 +147,69 @@ impl SymbolManagerConfig {
         self
     }
 
+    /// Set a directory to cache source files in which were downloaded from a
+    /// repository or bucket, for source files with a mapped path, e.g. from the
+    /// srcsrv stream of a PDB. Without this directory, these source files are
+    /// downloaded on every request.
+    pub fn source_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.source_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Keep the cache directories below this many bytes together, by removing
+    /// the least recently used files after downloads. See
+    /// [`cache_dirs`](Self::cache_dirs) for the directories which count.
+    pub fn max_cache_size(mut self, max_bytes: u64) -> Self {
+        self.max_cache_size = Some(max_bytes);
+        self
+    }
+
+    /// Download at most this many files at the same time from each server.
+    /// Further downloads wait until one of the running ones is done. The
+    /// Windows symbol servers of the `_NT_SYMBOL_PATH` share one limit, and
+    /// so do the debuginfod servers. By default, there is no limit.
+    pub fn max_concurrent_downloads_per_server(mut self, count: usize) -> Self {
+        self.max_concurrent_downloads_per_server = Some(count);
+        self
+    }
+
+    /// The directories which downloaded files are cached in: the cache
+    /// directories of the symbol servers and of debuginfod, and the symindex
+    /// and source cache directories.
+    pub fn cache_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        let candidates = self
+            .breakpad_servers
+            .iter()
+            .chain(&self.windows_servers)
+            .chain(&self.debuginfod_servers)
+            .map(|(_, dir)| dir)
+            .chain(&self.breakpad_symindex_cache_dir)
+            .chain(&self.source_cache_dir)
+            .chain(&self.debuginfod_cache_dir_if_not_installed);
+        for dir in candidates {
+            if !dirs.contains(dir) {
+                dirs.push(dir.clone());
+            }
+        }
+        dirs
+    }
+
+    /// The URLs of the breakpad, Windows and debuginfod servers which files
+    /// are downloaded from. Servers which only come from the
+    /// `_NT_SYMBOL_PATH` aren't included.
+    pub fn server_urls(&self) -> Vec<String> {
+        self.breakpad_servers
+            .iter()
+            .chain(&self.windows_servers)
+            .chain(&self.debuginfod_servers)
+            .map(|(url, _)| url.clone())
+            .collect()
+    }
+
     /// Add a server to search for Windows symbol files (pdb / exe / dll), along with a local cache directory.
+    /// The server can also be an `s3://` or `gs://` bucket URL, see [`CloudStore`](crate::CloudStore).
+    /// Files in buckets need to be stored uncompressed.
     ///
     /// This method can be called multiple times; the servers and caches will be tried in the order of those calls.
     pub fn windows_symbols_server(
@@ -191,4 +278,32 @@ impl SymbolManagerConfig {
         self.simpleperf_binary_cache_directories.push(dir.into());
         self
     }
+
+    /// Add a perf build-id cache directory, usually `~/.debug`, which will be
+    /// checked for binaries and debug files with a matching ELF build ID.
+    ///
+    /// `perf record`, `perf buildid-cache` and `perf archive` put files
+    /// there, at `<dir>/.build-id/<first two hex chars>/<remaining hex chars>`.
+    pub fn perf_build_id_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.perf_build_id_cache_directories.push(dir.into());
+        self
+    }
+
+    /// Only use symbol files of the given kind for the library with this
+    /// debug name, e.g. to compare the results of different kinds of symbol
+    /// files for the same library. By default, the first symbol file found
+    /// is used, whatever its kind.
+    pub fn force_backend(mut self, debug_name: impl Into<String>, backend: SymbolBackend) -> Self {
+        self.forced_backends.insert(debug_name.into(), backend);
+        self
+    }
+
+    /// Whether the names of functions from PDB files include the argument
+    /// types from the PDB's type information, e.g. `Foo::Bar(int, char*)`
+    /// instead of `Foo::Bar`. This tells overloaded functions apart, and is
+    /// on by default.
+    pub fn pdb_function_arguments(mut self, include: bool) -> Self {
+        self.omit_pdb_function_arguments = !include;
+        self
+    }
 }
@@ -1,5 +1,10 @@
 use std::path::{Path, PathBuf};
 
+use tokio::io::AsyncWriteExt;
+
+use crate::cache_limit::part_path;
+use crate::cloud_store::uri_encode;
+
 pub struct DebuginfodSymbolCache(DebuginfodSymbolCacheInner);
 
 enum DebuginfodSymbolCacheInner {
@@ -58,6 +63,26 @@ impl DebuginfodSymbolCache {
             DebuginfodSymbolCacheInner::Manual(manual) => manual.get_file(buildid, file_type).await,
         }
     }
+
+    /// Gets a source file from the servers' source endpoint. `path` is the
+    /// absolute path of the file in the debug info.
+    pub async fn get_source_file(&self, buildid: &str, path: &str) -> Option<PathBuf> {
+        self.get_file(buildid, &source_file_type(path)?).await
+    }
+}
+
+/// The part of the URL after the build ID for a source file, e.g.
+/// `source/usr/src/debug/glibc/malloc.c`. This is also the file's path in the
+/// cache directory, so paths which could leave it aren't allowed.
+fn source_file_type(path: &str) -> Option<String> {
+    let path = path.strip_prefix('/')?;
+    if path
+        .split('/')
+        .any(|component| component == ".." || component == ".")
+    {
+        return None;
+    }
+    Some(format!("source/{}", uri_encode(path, false)))
 }
 
 /// Uses debuginfod-find on the shell maybe, not sure
@@ -128,13 +153,35 @@ impl ManualDebuginfodSymbolCache {
         if self.verbose {
             eprintln!("Saving bytes to {dest_path:?}.");
         }
-        let file = tokio::fs::File::create(&dest_path).await?;
+        let part_path = part_path(&dest_path);
+        let file = tokio::fs::File::create(&part_path).await?;
         let mut writer = tokio::io::BufWriter::new(file);
         use futures_util::StreamExt;
         while let Some(item) = stream.next().await {
             tokio::io::copy(&mut item?.as_ref(), &mut writer).await?;
         }
+        writer.flush().await?;
         drop(writer);
+        tokio::fs::rename(&part_path, &dest_path).await?;
         Ok(dest_path)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn source_file_urls() {
+        assert_eq!(
+            source_file_type("/usr/src/debug/glibc-2.39/malloc/malloc.c").as_deref(),
+            Some("source/usr/src/debug/glibc-2.39/malloc/malloc.c")
+        );
+        assert_eq!(
+            source_file_type("/build/my project/a+b.c").as_deref(),
+            Some("source/build/my%20project/a%2Bb.c")
+        );
+        assert_eq!(source_file_type("relative/file.c"), None);
+        assert_eq!(source_file_type("/usr/src/../../etc/passwd"), None);
+    }
+}
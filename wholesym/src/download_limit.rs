@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits the number of downloads which run at the same time from each
+/// server, so that a burst of requests for many symbol files doesn't open
+/// hundreds of connections to a symbol server and hold all the responses in
+/// memory at once.
+#[derive(Debug, Default)]
+pub struct DownloadLimiter {
+    max_per_server: Option<usize>,
+    servers: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl DownloadLimiter {
+    pub fn new(max_per_server: Option<usize>) -> Self {
+        Self {
+            max_per_server,
+            servers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits until a download from `server` can start. The download counts
+    /// as running until the returned permit is dropped.
+    pub async fn acquire(&self, server: &str) -> Option<OwnedSemaphorePermit> {
+        let max_per_server = self.max_per_server?;
+        let semaphore = self
+            .servers
+            .lock()
+            .unwrap()
+            .entry(server_key(server).to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(max_per_server.max(1))))
+            .clone();
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+/// Downloads from the same host share one limit, even if they're for
+/// different paths on it.
+fn server_key(url: &str) -> &str {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url;
+    };
+    let host_len = rest.find('/').unwrap_or(rest.len());
+    &url[..scheme.len() + 3 + host_len]
+}
+
+#[cfg(test)]
+mod test {
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn limits_downloads_per_server() {
+        let limiter = DownloadLimiter::new(Some(1));
+        let permit = limiter
+            .acquire("https://symbols.example.com/a")
+            .now_or_never()
+            .unwrap();
+        assert!(permit.is_some());
+        assert!(limiter
+            .acquire("https://symbols.example.com/b")
+            .now_or_never()
+            .is_none());
+        assert!(limiter
+            .acquire("https://debuginfod.example.com/b")
+            .now_or_never()
+            .is_some());
+        drop(permit);
+        assert!(limiter
+            .acquire("https://symbols.example.com/b")
+            .now_or_never()
+            .is_some());
+
+        let unlimited = DownloadLimiter::new(None);
+        assert!(unlimited.acquire("x").now_or_never().unwrap().is_none());
+    }
+}
@@ -1,21 +1,27 @@
 use std::collections::HashMap;
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 use bytes::Bytes;
 use debugid::DebugId;
 use samply_symbols::{
     BreakpadIndex, BreakpadIndexParser, CandidatePathInfo, CodeId, ElfBuildId, FileAndPathHelper,
-    FileAndPathHelperResult, FileLocation, LibraryInfo, OptionallySendFuture, PeCodeId,
+    FileAndPathHelperResult, FileLocation, LibraryInfo, MappedPath, OptionallySendFuture, PeCodeId,
     SymbolMapTrait,
 };
 use symsrv::{SymsrvDownloader, SymsrvObserver};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use uuid::Uuid;
 
-use crate::config::SymbolManagerConfig;
+use crate::cache_limit::{evict_files_used_before, mark_used, part_path};
+use crate::cloud_store::CloudStore;
+use crate::config::{SymbolBackend, SymbolManagerConfig};
 use crate::debuginfod::DebuginfodSymbolCache;
+use crate::download_limit::DownloadLimiter;
+use crate::mapped_source::{cache_path_for_mapped_path, source_url_for_mapped_path};
 use crate::vdso::get_vdso_data;
 
 /// This is how the symbol file contents are returned. If there's an uncompressed file
@@ -49,14 +55,133 @@ pub enum WholesymFileLocation {
     LocalSymsrvFile(String, String),
     LocalBreakpadFile(PathBuf, String),
     SymsrvFile(String, String),
+    CloudSymsrvFile(String, String),
     BreakpadSymbolServerFile(String),
     BreakpadSymindexFile(String),
     DebuginfodDebugFile(ElfBuildId),
     DebuginfodExecutable(ElfBuildId),
+    /// A source file from the debuginfod servers, for a debug file which was
+    /// found with debuginfod.
+    DebuginfodSourceFile(ElfBuildId, String),
     UrlForSourceFile(String),
+    /// A source file in a repository or bucket, downloaded into the source
+    /// cache directory.
+    MappedSourceFile(MappedPath),
     VdsoLoadedIntoThisProcess,
 }
 
+impl WholesymFileLocation {
+    /// The kind of symbol file which is expected at this location.
+    fn backend(&self) -> Option<SymbolBackend> {
+        match self {
+            Self::LocalFile(path) => {
+                let extension = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or_default();
+                match extension.to_ascii_lowercase().as_str() {
+                    "pdb" | "exe" | "dll" | "sys" => Some(SymbolBackend::Pdb),
+                    "sym" => Some(SymbolBackend::Breakpad),
+                    _ => Some(SymbolBackend::Dwarf),
+                }
+            }
+            Self::LocalSymsrvFile(..) | Self::SymsrvFile(..) | Self::CloudSymsrvFile(..) => {
+                Some(SymbolBackend::Pdb)
+            }
+            Self::LocalBreakpadFile(..)
+            | Self::BreakpadSymbolServerFile(_)
+            | Self::BreakpadSymindexFile(_) => Some(SymbolBackend::Breakpad),
+            Self::DebuginfodDebugFile(_)
+            | Self::DebuginfodExecutable(_)
+            | Self::VdsoLoadedIntoThisProcess => Some(SymbolBackend::Dwarf),
+            Self::DebuginfodSourceFile(..)
+            | Self::UrlForSourceFile(_)
+            | Self::MappedSourceFile(_) => None,
+        }
+    }
+}
+
+impl WholesymFileLocation {
+    /// Whether loading the file goes to a cache directory of downloaded files.
+    fn is_cache(&self) -> bool {
+        matches!(
+            self,
+            Self::LocalSymsrvFile(..) | Self::LocalBreakpadFile(..) | Self::BreakpadSymindexFile(_)
+        )
+    }
+
+    /// Whether loading the file downloads it, or, for symsrv and debuginfod,
+    /// may download it.
+    fn is_download(&self) -> bool {
+        matches!(
+            self,
+            Self::SymsrvFile(..)
+                | Self::CloudSymsrvFile(..)
+                | Self::BreakpadSymbolServerFile(_)
+                | Self::DebuginfodDebugFile(_)
+                | Self::DebuginfodExecutable(_)
+                | Self::DebuginfodSourceFile(..)
+                | Self::UrlForSourceFile(_)
+                | Self::MappedSourceFile(_)
+        )
+    }
+}
+
+/// Counts of the symbol files which were loaded from caches and from
+/// servers, for monitoring a long-running symbol server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SymbolFileStats {
+    /// Files which were found in a cache directory.
+    pub cache_hits: u64,
+    /// Files which were requested from a symbol server or debuginfod.
+    pub downloads: u64,
+    /// The downloads which did not produce a file.
+    pub failed_downloads: u64,
+    /// The size of the downloaded files.
+    pub downloaded_bytes: u64,
+}
+
+#[derive(Default)]
+struct SymbolFileCounters {
+    cache_hits: AtomicU64,
+    downloads: AtomicU64,
+    failed_downloads: AtomicU64,
+    downloaded_bytes: AtomicU64,
+}
+
+impl SymbolFileCounters {
+    fn count(
+        &self,
+        location: &WholesymFileLocation,
+        result: &FileAndPathHelperResult<WholesymFileContents>,
+    ) {
+        match result {
+            Ok(_) if location.is_cache() => {
+                self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(contents) if location.is_download() => {
+                self.downloads.fetch_add(1, Ordering::Relaxed);
+                self.downloaded_bytes
+                    .fetch_add(contents.len() as u64, Ordering::Relaxed);
+            }
+            Err(_) if location.is_download() => {
+                self.downloads.fetch_add(1, Ordering::Relaxed);
+                self.failed_downloads.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    fn stats(&self) -> SymbolFileStats {
+        SymbolFileStats {
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            downloads: self.downloads.load(Ordering::Relaxed),
+            failed_downloads: self.failed_downloads.load(Ordering::Relaxed),
+            downloaded_bytes: self.downloaded_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
 impl FileLocation for WholesymFileLocation {
     fn location_for_dyld_subcache(&self, suffix: &str) -> Option<Self> {
         // Dyld shared caches are only loaded from local files.
@@ -113,9 +238,15 @@ impl FileLocation for WholesymFileLocation {
                         .map(|base_path| Self::LocalFile(base_path.join(source_file_path)))
                 }
             }
-            Self::DebuginfodDebugFile(_build_id) | Self::DebuginfodExecutable(_build_id) => {
-                // TODO: load source file via debuginfod
-                None
+            Self::DebuginfodDebugFile(build_id) | Self::DebuginfodExecutable(build_id) => {
+                // Distro binaries are usually built in paths which don't exist
+                // locally, and their debug file came from debuginfod, so the
+                // source comes from the debuginfod source endpoint too. The
+                // absolute path in the downloaded file isn't opened locally.
+                Some(Self::DebuginfodSourceFile(
+                    build_id.clone(),
+                    source_file_path.to_owned(),
+                ))
             }
             _ => {
                 // We don't have local source files for debug files from symbol servers.
@@ -125,6 +256,13 @@ impl FileLocation for WholesymFileLocation {
         }
     }
 
+    fn location_for_mapped_source_file(&self, mapped_path: &MappedPath) -> Option<Self> {
+        // The mapped paths come from debug files, e.g. from the srcsrv stream of
+        // a PDB from a symbol server, and only lead to the known hosts.
+        source_url_for_mapped_path(mapped_path)?;
+        Some(Self::MappedSourceFile(mapped_path.clone()))
+    }
+
     fn location_for_breakpad_symindex(&self) -> Option<Self> {
         match self {
             Self::BreakpadSymbolServerFile(rel_path) | Self::LocalBreakpadFile(_, rel_path) => {
@@ -242,10 +380,20 @@ impl FileAndPathHelper for FileReadOnlyHelper {
 
 pub struct Helper {
     symsrv_downloader: Option<SymsrvDownloader>,
+    /// The cloud storage buckets among the symbol servers, by URL.
+    cloud_stores: HashMap<String, CloudStore>,
     debuginfod_symbol_cache: Option<DebuginfodSymbolCache>,
     known_libs: Mutex<KnownLibs>,
     config: SymbolManagerConfig,
     precog_symbol_data: Mutex<HashMap<DebugId, Arc<dyn SymbolMapTrait + Send + Sync>>>,
+    file_counters: SymbolFileCounters,
+    cache_dirs: Vec<PathBuf>,
+    /// Whether a thread is removing files to stay below the maximum cache size.
+    eviction_running: Arc<AtomicBool>,
+    download_limiter: DownloadLimiter,
+    /// The path or URL which each loaded file was read from, by the string
+    /// form of its location.
+    loaded_files: Mutex<HashMap<String, String>>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -278,12 +426,26 @@ impl Helper {
         } else {
             None
         };
+        let cloud_stores = config
+            .breakpad_servers
+            .iter()
+            .chain(&config.windows_servers)
+            .filter_map(|(url, _)| Some((url.clone(), CloudStore::from_url(url)?)))
+            .collect();
+        let cache_dirs = config.cache_dirs();
+        let download_limiter = DownloadLimiter::new(config.max_concurrent_downloads_per_server);
         Self {
             symsrv_downloader,
+            cloud_stores,
             debuginfod_symbol_cache,
             known_libs: Mutex::new(Default::default()),
             config,
             precog_symbol_data: Mutex::new(Default::default()),
+            file_counters: SymbolFileCounters::default(),
+            cache_dirs,
+            eviction_running: Arc::new(AtomicBool::new(false)),
+            download_limiter,
+            loaded_files: Mutex::new(HashMap::new()),
         }
     }
 
@@ -325,10 +487,72 @@ impl Helper {
         precog_symbol_data.insert(debug_id, symbol_map);
     }
 
+    pub fn symbol_file_stats(&self) -> SymbolFileStats {
+        self.file_counters.stats()
+    }
+
+    async fn load_file_and_count(
+        &self,
+        location: WholesymFileLocation,
+    ) -> FileAndPathHelperResult<WholesymFileContents> {
+        let start = SystemTime::now();
+        let result = self.load_file_impl(location.clone()).await;
+        self.file_counters.count(&location, &result);
+        if result.is_ok() && location.is_download() {
+            self.enforce_max_cache_size(start);
+        }
+        result
+    }
+
+    /// Removes the least recently used cached files on a separate thread, if
+    /// the cache directories are above the maximum size. Files which were used
+    /// since `start`, such as the download which made the cache too large, are
+    /// kept.
+    fn enforce_max_cache_size(&self, start: SystemTime) {
+        let Some(max_cache_size) = self.config.max_cache_size else {
+            return;
+        };
+        if self.eviction_running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let cache_dirs = self.cache_dirs.clone();
+        let eviction_running = self.eviction_running.clone();
+        let verbose = self.config.verbose;
+        std::thread::spawn(move || {
+            let eviction = evict_files_used_before(&cache_dirs, max_cache_size, start);
+            if verbose && eviction.removed_files > 0 {
+                eprintln!(
+                    "Removed {} files ({} bytes) from the symbol cache.",
+                    eviction.removed_files, eviction.removed_bytes
+                );
+            }
+            eviction_running.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Keeps a file in a cache directory from being evicted soon.
+    fn mark_cached_file_used(&self, path: &Path) {
+        if self.config.max_cache_size.is_some()
+            && self.cache_dirs.iter().any(|dir| path.starts_with(dir))
+        {
+            mark_used(path);
+        }
+    }
+
+    /// Remembers where the file at `location` was read from, for
+    /// `describe_loaded_file`.
+    fn remember_loaded_file(&self, location: &str, origin: impl std::fmt::Display) {
+        self.loaded_files
+            .lock()
+            .unwrap()
+            .insert(location.to_string(), origin.to_string());
+    }
+
     async fn load_file_impl(
         &self,
         location: WholesymFileLocation,
     ) -> FileAndPathHelperResult<WholesymFileContents> {
+        let location_string = location.to_string();
         match location {
             WholesymFileLocation::LocalFile(path) => {
                 if self.config.verbose {
@@ -336,6 +560,7 @@ impl Helper {
                 }
                 let path = self.config.redirect_paths.get(&path).unwrap_or(&path);
                 let file = File::open(path)?;
+                self.remember_loaded_file(&location_string, path.display());
                 Ok(WholesymFileContents::Mmap(unsafe {
                     memmap2::MmapOptions::new().map(&file)?
                 }))
@@ -352,6 +577,8 @@ impl Helper {
                     .unwrap()
                     .get_file_no_download(&filename, &hash)
                     .await?;
+                self.mark_cached_file_used(&file_path);
+                self.remember_loaded_file(&location_string, file_path.display());
                 Ok(WholesymFileContents::Mmap(unsafe {
                     memmap2::MmapOptions::new().map(&File::open(file_path)?)?
                 }))
@@ -361,7 +588,9 @@ impl Helper {
                     eprintln!("Opening file {:?}", path.to_string_lossy());
                 }
                 self.ensure_symindex(&path, &rel_path).await?;
-                let file = File::open(path)?;
+                self.mark_cached_file_used(&path);
+                let file = File::open(&path)?;
+                self.remember_loaded_file(&location_string, path.display());
                 Ok(WholesymFileContents::Mmap(unsafe {
                     memmap2::MmapOptions::new().map(&file)?
                 }))
@@ -370,36 +599,53 @@ impl Helper {
                 if self.config.verbose {
                     eprintln!("Trying to get file {url} from a URL");
                 }
+                let _permit = self.download_limiter.acquire(&url).await;
                 let bytes = reqwest::get(&url).await?.bytes().await?;
                 Ok(WholesymFileContents::Bytes(bytes))
             }
+            WholesymFileLocation::MappedSourceFile(mapped_path) => {
+                self.get_mapped_source_file(&mapped_path).await
+            }
             WholesymFileLocation::SymsrvFile(filename, hash) => {
                 if self.config.verbose {
                     eprintln!(
                         "Trying to get file {filename} {hash} from symbol cache (download allowed)"
                     );
                 }
+                let _permit = self.download_limiter.acquire("symsrv").await;
                 let file_path = self
                     .symsrv_downloader
                     .as_ref()
                     .unwrap()
                     .get_file(&filename, &hash)
                     .await?;
+                self.remember_loaded_file(&location_string, file_path.display());
                 Ok(WholesymFileContents::Mmap(unsafe {
                     memmap2::MmapOptions::new().map(&File::open(file_path)?)?
                 }))
             }
+            WholesymFileLocation::CloudSymsrvFile(filename, hash) => {
+                if self.config.verbose {
+                    eprintln!("Trying to get file {filename} {hash} from cloud symbol stores");
+                }
+                let (contents, url) = self.get_cloud_symsrv_file(&filename, &hash).await?;
+                self.remember_loaded_file(&location_string, url);
+                Ok(contents)
+            }
             WholesymFileLocation::BreakpadSymbolServerFile(path) => {
                 if self.config.verbose {
                     eprintln!("Trying to get file {path:?} from breakpad symbol server");
                 }
-                self.get_bp_sym_file(&path).await
+                let (contents, url) = self.get_bp_sym_file(&path).await?;
+                self.remember_loaded_file(&location_string, url);
+                Ok(contents)
             }
             WholesymFileLocation::BreakpadSymindexFile(rel_path) => {
                 if let Some(symindex_path) = self.symindex_path(&rel_path) {
                     if self.config.verbose {
                         eprintln!("Opening file {:?}", symindex_path.to_string_lossy());
                     }
+                    self.mark_cached_file_used(&symindex_path);
                     let file = File::open(symindex_path)?;
                     Ok(WholesymFileContents::Mmap(unsafe {
                         memmap2::MmapOptions::new().map(&file)?
@@ -409,6 +655,7 @@ impl Helper {
                 }
             }
             WholesymFileLocation::DebuginfodDebugFile(build_id) => {
+                let _permit = self.download_limiter.acquire("debuginfod").await;
                 let file_path = self
                     .debuginfod_symbol_cache
                     .as_ref()
@@ -416,12 +663,14 @@ impl Helper {
                     .get_file(&build_id.to_string(), "debuginfo")
                     .await
                     .ok_or("Debuginfod could not find debuginfo")?;
+                self.remember_loaded_file(&location_string, file_path.display());
 
                 Ok(WholesymFileContents::Mmap(unsafe {
                     memmap2::MmapOptions::new().map(&File::open(file_path)?)?
                 }))
             }
             WholesymFileLocation::DebuginfodExecutable(build_id) => {
+                let _permit = self.download_limiter.acquire("debuginfod").await;
                 let file_path = self
                     .debuginfod_symbol_cache
                     .as_ref()
@@ -429,6 +678,21 @@ impl Helper {
                     .get_file(&build_id.to_string(), "debuginfo")
                     .await
                     .ok_or("Debuginfod could not find debuginfo")?;
+                self.remember_loaded_file(&location_string, file_path.display());
+
+                Ok(WholesymFileContents::Mmap(unsafe {
+                    memmap2::MmapOptions::new().map(&File::open(file_path)?)?
+                }))
+            }
+            WholesymFileLocation::DebuginfodSourceFile(build_id, path) => {
+                let _permit = self.download_limiter.acquire("debuginfod").await;
+                let file_path = self
+                    .debuginfod_symbol_cache
+                    .as_ref()
+                    .unwrap()
+                    .get_source_file(&build_id.to_string(), &path)
+                    .await
+                    .ok_or("Debuginfod could not find the source file")?;
 
                 Ok(WholesymFileContents::Mmap(unsafe {
                     memmap2::MmapOptions::new().map(&File::open(file_path)?)?
@@ -450,10 +714,12 @@ impl Helper {
         }
     }
 
+    /// Downloads a breakpad sym file from the first server which has it.
+    /// Returns the file and the URL it was downloaded from.
     async fn get_bp_sym_file(
         &self,
         rel_path: &str,
-    ) -> FileAndPathHelperResult<WholesymFileContents> {
+    ) -> FileAndPathHelperResult<(WholesymFileContents, String)> {
         for (server_base_url, cache_dir) in &self.config.breakpad_servers {
             if let Ok(file) = self
                 .get_bp_sym_file_from_server(rel_path, server_base_url, cache_dir)
@@ -470,12 +736,26 @@ impl Helper {
         rel_path: &str,
         server_base_url: &str,
         cache_dir: &Path,
-    ) -> FileAndPathHelperResult<WholesymFileContents> {
-        let url = format!("{server_base_url}/{rel_path}");
+    ) -> FileAndPathHelperResult<(WholesymFileContents, String)> {
+        let cloud_store = self.cloud_stores.get(server_base_url);
+        let url = match cloud_store {
+            Some(cloud_store) => cloud_store.url_for_path(rel_path),
+            None => format!("{server_base_url}/{rel_path}"),
+        };
+        let _permit = self.download_limiter.acquire(&url).await;
         if self.config.verbose {
             eprintln!("Downloading {url}...");
         }
-        let sym_file_response = reqwest::get(&url).await?.error_for_status()?;
+        let sym_file_response = match cloud_store {
+            Some(cloud_store) => {
+                cloud_store
+                    .get(&reqwest::Client::new(), rel_path)
+                    .send()
+                    .await?
+            }
+            None => reqwest::get(&url).await?,
+        };
+        let sym_file_response = sym_file_response.error_for_status()?;
         let mut stream = sym_file_response.bytes_stream();
         let dest_path = cache_dir.join(rel_path);
         if let Some(dir) = dest_path.parent() {
@@ -484,7 +764,8 @@ impl Helper {
         if self.config.verbose {
             eprintln!("Saving bytes to {dest_path:?}.");
         }
-        let file = tokio::fs::File::create(&dest_path).await?;
+        let part_path = part_path(&dest_path);
+        let file = tokio::fs::File::create(&part_path).await?;
         let mut writer = tokio::io::BufWriter::new(file);
         use futures_util::StreamExt;
         let mut parser = BreakpadIndexParser::new();
@@ -494,7 +775,9 @@ impl Helper {
             parser.consume(item_slice);
             tokio::io::copy(&mut item_slice, &mut writer).await?;
         }
+        writer.flush().await?;
         drop(writer);
+        tokio::fs::rename(&part_path, &dest_path).await?;
 
         match parser.finish() {
             Ok(index) => self.write_symindex(rel_path, index).await?,
@@ -509,9 +792,97 @@ impl Helper {
             eprintln!("Opening file {:?}", dest_path.to_string_lossy());
         }
         let file = File::open(&dest_path)?;
-        Ok(WholesymFileContents::Mmap(unsafe {
-            memmap2::MmapOptions::new().map(&file)?
-        }))
+        let contents =
+            WholesymFileContents::Mmap(unsafe { memmap2::MmapOptions::new().map(&file)? });
+        Ok((contents, url))
+    }
+
+    /// The Windows symbol servers which are cloud storage buckets, with their
+    /// cache directories.
+    fn cloud_windows_stores(&self) -> impl Iterator<Item = (&CloudStore, &Path)> {
+        self.config
+            .windows_servers
+            .iter()
+            .filter_map(|(url, cache_dir)| Some((self.cloud_stores.get(url)?, cache_dir.as_path())))
+    }
+
+    /// Downloads a file from the first cloud bucket which has it, into the
+    /// symsrv layout of the bucket's cache directory. Returns the file and the
+    /// URL it was downloaded from.
+    async fn get_cloud_symsrv_file(
+        &self,
+        filename: &str,
+        hash: &str,
+    ) -> FileAndPathHelperResult<(WholesymFileContents, String)> {
+        let rel_path = format!("{filename}/{hash}/{filename}");
+        let client = reqwest::Client::new();
+        for (cloud_store, cache_dir) in self.cloud_windows_stores() {
+            let url = cloud_store.url_for_path(&rel_path);
+            let _permit = self.download_limiter.acquire(&url).await;
+            if self.config.verbose {
+                eprintln!("Downloading {url}...");
+            }
+            let response = match cloud_store.get(&client, &rel_path).send().await {
+                Ok(response) if response.status().is_success() => response,
+                _ => continue,
+            };
+            let bytes = response.bytes().await?;
+            let dest_path = cache_dir.join(&rel_path);
+            if let Some(dir) = dest_path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            if self.config.verbose {
+                eprintln!("Saving bytes to {dest_path:?}.");
+            }
+            write_cached_file(&dest_path, &bytes).await?;
+            return Ok((WholesymFileContents::Bytes(bytes), url));
+        }
+        Err("No file on cloud symbol stores".into())
+    }
+
+    /// Downloads a source file from its repository or bucket, or takes it from
+    /// the source cache directory.
+    async fn get_mapped_source_file(
+        &self,
+        mapped_path: &MappedPath,
+    ) -> FileAndPathHelperResult<WholesymFileContents> {
+        let source_url =
+            source_url_for_mapped_path(mapped_path).ok_or("No known URL for the source file")?;
+        let cache_path = self
+            .config
+            .source_cache_dir
+            .as_deref()
+            .zip(cache_path_for_mapped_path(mapped_path))
+            .map(|(dir, rel_path)| dir.join(rel_path));
+        if let Some(cache_path) = &cache_path {
+            if let Ok(bytes) = tokio::fs::read(cache_path).await {
+                self.mark_cached_file_used(cache_path);
+                return Ok(WholesymFileContents::Bytes(Bytes::from(bytes)));
+            }
+        }
+
+        let _permit = self.download_limiter.acquire(&source_url.url).await;
+        if self.config.verbose {
+            eprintln!("Downloading {}...", source_url.url);
+        }
+        let response = reqwest::get(&source_url.url).await?.error_for_status()?;
+        let mut bytes = response.bytes().await?;
+        if source_url.is_base64 {
+            use base64::Engine;
+            let text: Vec<u8> = bytes
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            bytes = Bytes::from(base64::engine::general_purpose::STANDARD.decode(text)?);
+        }
+        if let Some(cache_path) = &cache_path {
+            if let Some(dir) = cache_path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            write_cached_file(cache_path, &bytes).await?;
+        }
+        Ok(WholesymFileContents::Bytes(bytes))
     }
 
     fn symindex_path(&self, rel_path: &str) -> Option<PathBuf> {
@@ -534,9 +905,7 @@ impl Helper {
         }
         let parent_dir = symindex_path.parent().ok_or("invalid symindex path")?;
         tokio::fs::create_dir_all(parent_dir).await?;
-        let mut index_file = tokio::fs::File::create(&symindex_path).await?;
-        index_file.write_all(&index.serialize_to_bytes()).await?;
-        index_file.flush().await?;
+        write_cached_file(&symindex_path, &index.serialize_to_bytes()).await?;
         Ok(())
     }
 
@@ -734,6 +1103,16 @@ impl FileAndPathHelper for Helper {
                 ));
             }
 
+            if debug_name.ends_with(".pdb") {
+                // We might have downloaded this pdb file from a cloud bucket before.
+                let rel_path = format!("{debug_name}/{}/{debug_name}", debug_id.breakpad());
+                for (_cloud_store, cache_dir) in self.cloud_windows_stores() {
+                    paths.push(CandidatePathInfo::SingleFile(
+                        WholesymFileLocation::LocalFile(cache_dir.join(&rel_path)),
+                    ));
+                }
+            }
+
             if debug_name.ends_with(".pdb") && self.symsrv_downloader.is_some() {
                 // We might find this pdb file with the help of a symbol server.
                 paths.push(CandidatePathInfo::SingleFile(
@@ -761,6 +1140,16 @@ impl FileAndPathHelper for Helper {
                         ),
                     ));
                 }
+
+                if debug_name.ends_with(".pdb") && self.cloud_windows_stores().next().is_some() {
+                    // We might find this pdb file in a cloud bucket.
+                    paths.push(CandidatePathInfo::SingleFile(
+                        WholesymFileLocation::CloudSymsrvFile(
+                            debug_name.clone(),
+                            debug_id.breakpad().to_string(),
+                        ),
+                    ));
+                }
             }
         }
 
@@ -803,6 +1192,17 @@ impl FileAndPathHelper for Helper {
             }
         }
 
+        // Check any perf build-id cache directories.
+        if let Some(CodeId::ElfBuildId(build_id)) = &info.code_id {
+            for dir in &self.config.perf_build_id_cache_directories {
+                for p in perf_build_id_cache_paths(dir, build_id, true) {
+                    paths.push(CandidatePathInfo::SingleFile(
+                        WholesymFileLocation::LocalFile(p),
+                    ));
+                }
+            }
+        }
+
         if let Some(path) = &info.path {
             // Fall back to getting symbols from the binary itself.
             paths.push(CandidatePathInfo::SingleFile(
@@ -836,6 +1236,19 @@ impl FileAndPathHelper for Helper {
             ));
         }
 
+        let forced_backend = info
+            .debug_name
+            .as_ref()
+            .and_then(|debug_name| self.config.forced_backends.get(debug_name));
+        if let Some(forced_backend) = forced_backend {
+            paths.retain(|path| match path {
+                CandidatePathInfo::SingleFile(location) => {
+                    location.backend() == Some(*forced_backend)
+                }
+                CandidatePathInfo::InDyldCache { .. } => *forced_backend == SymbolBackend::Dwarf,
+            });
+        }
+
         Ok(paths)
     }
 
@@ -914,6 +1327,17 @@ impl FileAndPathHelper for Helper {
             }
         }
 
+        // Check any perf build-id cache directories.
+        if let Some(CodeId::ElfBuildId(build_id)) = &info.code_id {
+            for dir in &self.config.perf_build_id_cache_directories {
+                for p in perf_build_id_cache_paths(dir, build_id, false) {
+                    paths.push(CandidatePathInfo::SingleFile(
+                        WholesymFileLocation::LocalFile(p),
+                    ));
+                }
+            }
+        }
+
         if let Some(path) = &info.path {
             // For macOS system libraries, also consult the dyld shared cache.
             if path.starts_with("/usr/") || path.starts_with("/System/") {
@@ -942,6 +1366,15 @@ impl FileAndPathHelper for Helper {
                 ));
             }
 
+            if let (Some(name), Some(CodeId::PeCodeId(code_id))) = (&info.name, &info.code_id) {
+                // We might find this exe / dll file in a cloud bucket.
+                if self.cloud_windows_stores().next().is_some() {
+                    paths.push(CandidatePathInfo::SingleFile(
+                        WholesymFileLocation::CloudSymsrvFile(name.clone(), code_id.to_string()),
+                    ));
+                }
+            }
+
             if let (Some(_debuginfod_symbol_cache), Some(CodeId::ElfBuildId(build_id))) =
                 (self.debuginfod_symbol_cache.as_ref(), &info.code_id)
             {
@@ -966,7 +1399,15 @@ impl FileAndPathHelper for Helper {
         location: WholesymFileLocation,
     ) -> std::pin::Pin<Box<dyn OptionallySendFuture<Output = FileAndPathHelperResult<Self::F>> + '_>>
     {
-        Box::pin(self.load_file_impl(location))
+        Box::pin(self.load_file_and_count(location))
+    }
+
+    fn describe_loaded_file(&self, location: &WholesymFileLocation) -> String {
+        let location = location.to_string();
+        match self.loaded_files.lock().unwrap().get(&location) {
+            Some(origin) => origin.clone(),
+            None => location,
+        }
     }
 
     fn get_candidate_paths_for_supplementary_debug_file(
@@ -1025,6 +1466,14 @@ impl FileAndPathHelper for Helper {
     }
 }
 
+/// Writes a file into a cache directory. The bytes go to a `.part` file first,
+/// so that the file is never seen, or evicted, while it's only partly written.
+async fn write_cached_file(dest_path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let part_path = part_path(dest_path);
+    tokio::fs::write(&part_path, bytes).await?;
+    tokio::fs::rename(&part_path, dest_path).await
+}
+
 /// Return a Vec containing the potential paths where a dyld shared cache
 /// which contains an object of the given architecture might be found.
 ///
@@ -1071,6 +1520,31 @@ fn might_be_fake_jit_file(info: &LibraryInfo) -> bool {
     matches!(&info.name, Some(name) if (name.starts_with("jitted-") && name.ends_with(".so")) || name.contains("jit_app_cache:"))
 }
 
+/// The paths in a perf build-id cache directory which may contain the file
+/// with this build ID. Current versions of perf make
+/// `.build-id/xx/yyyy` a symlink to a directory with the binary in `elf`
+/// and the separate debug file in `debug`. Older versions made it a symlink
+/// to the binary itself.
+fn perf_build_id_cache_paths(
+    dir: &Path,
+    build_id: &ElfBuildId,
+    include_debug_file: bool,
+) -> Vec<PathBuf> {
+    let build_id = build_id.to_string();
+    if build_id.len() <= 2 {
+        return Vec::new();
+    }
+    let (two_chars, rest) = build_id.split_at(2);
+    let entry = dir.join(".build-id").join(two_chars).join(rest);
+    let mut paths = Vec::new();
+    if include_debug_file {
+        paths.push(entry.join("debug"));
+    }
+    paths.push(entry.join("elf"));
+    paths.push(entry);
+    paths
+}
+
 struct VerboseSymsrvObserver {
     urls: Mutex<HashMap<u64, String>>,
 }
@@ -70,6 +70,7 @@
 //!
 //!  - [x] Local PDB files at the absolute PDB path that's written down in the .exe / .dll
 //!  - [x] PDB files on Windows symbol servers + `_NT_SYMBOL_PATH` environment variable
+//!  - [x] PDB and Breakpad symbol files in S3 / GCS buckets, with `s3://` / `gs://` server URLs
 //!  - [x] Breakpad symbol files, local or on a server
 //!  - [x] DWARF-in-PE debug info
 //!  - [x] Fallback symbols from exported functions and function start addresses
@@ -129,21 +130,28 @@
 
 pub use debugid;
 
+mod cache_limit;
+mod cloud_store;
 mod config;
 mod debuginfod;
+mod download_limit;
 mod helper;
+mod mapped_source;
 mod moria_mac;
 #[cfg(target_os = "macos")]
 mod moria_mac_spotlight;
 mod symbol_manager;
 mod vdso;
 
-pub use config::SymbolManagerConfig;
+pub use cache_limit::{cache_dir_usage, evict_least_recently_used, CacheDirUsage, CacheEviction};
+pub use cloud_store::{is_cloud_store_url, CloudStore};
+pub use config::{SymbolBackend, SymbolManagerConfig};
+pub use helper::SymbolFileStats;
 pub use samply_symbols;
 pub use samply_symbols::{
     AddressInfo, CodeId, ElfBuildId, Error, ExternalFileAddressInFileRef, ExternalFileAddressRef,
     ExternalFileRef, ExternalFileSymbolMap, FrameDebugInfo, FramesLookupResult, LibraryInfo,
-    LookupAddress, MappedPath, MultiArchDisambiguator, PeCodeId, SourceFilePath, SymbolInfo,
-    SyncAddressInfo,
+    LookupAddress, MappedPath, MultiArchDisambiguator, PeCodeId, SourceFilePath, SourceLineRange,
+    SymbolInfo, SymbolMapLoadObserver, SymbolTableEntry, SymbolTablePage, SyncAddressInfo,
 };
 pub use symbol_manager::{SymbolFileOrigin, SymbolManager, SymbolMap};
@@ -0,0 +1,163 @@
+use std::path::PathBuf;
+
+use samply_symbols::MappedPath;
+
+/// Where the contents of a source file with a mapped path can be downloaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceUrl {
+    pub url: String,
+    /// Gitiles serves the raw file contents base64-encoded.
+    pub is_base64: bool,
+}
+
+/// Returns the URL of the raw contents of the file, for the hosts whose URL
+/// scheme is known.
+pub fn source_url_for_mapped_path(mapped_path: &MappedPath) -> Option<SourceUrl> {
+    let url = match mapped_path {
+        MappedPath::Git { repo, path, rev } => {
+            let (host, repo_path) = repo.split_once('/')?;
+            match host {
+                "github.com" => {
+                    format!("https://raw.githubusercontent.com/{repo_path}/{rev}/{path}")
+                }
+                "gitlab.com" => format!("https://gitlab.com/{repo_path}/-/raw/{rev}/{path}"),
+                _ if host.ends_with(".googlesource.com") => {
+                    return Some(SourceUrl {
+                        url: format!("https://{repo}/+/{rev}/{path}?format=TEXT"),
+                        is_base64: true,
+                    });
+                }
+                _ => return None,
+            }
+        }
+        MappedPath::Hg { repo, path, rev } => format!("https://{repo}/raw-file/{rev}/{path}"),
+        MappedPath::S3 {
+            bucket,
+            digest,
+            path,
+        } => format!("https://{bucket}.s3.amazonaws.com/{digest}/{path}"),
+        // Subversion serves files over HTTP, with a "peg revision" parameter.
+        MappedPath::Svn { repo, path, rev }
+            if repo.starts_with("https://") || repo.starts_with("http://") =>
+        {
+            format!("{repo}/{path}?p={rev}")
+        }
+        MappedPath::Perforce { .. } | MappedPath::Svn { .. } | MappedPath::Cargo { .. } => {
+            return None
+        }
+    };
+    Some(SourceUrl {
+        url,
+        is_base64: false,
+    })
+}
+
+/// The path of the downloaded file in the source cache directory. Files at a
+/// revision or digest never change, so they can be cached forever.
+pub fn cache_path_for_mapped_path(mapped_path: &MappedPath) -> Option<PathBuf> {
+    let parts: [&str; 4] = match mapped_path {
+        MappedPath::Git { repo, path, rev } => ["git", repo, rev, path],
+        MappedPath::Hg { repo, path, rev } => ["hg", repo, rev, path],
+        MappedPath::S3 {
+            bucket,
+            digest,
+            path,
+        } => ["s3", bucket, digest, path],
+        MappedPath::Svn { repo, path, rev } => {
+            let repo = repo
+                .split_once("://")
+                .map_or(repo.as_str(), |(_, repo)| repo);
+            ["svn", repo, rev, path]
+        }
+        MappedPath::Perforce { .. } | MappedPath::Cargo { .. } => return None,
+    };
+    let mut cache_path = PathBuf::new();
+    for component in parts.iter().flat_map(|part| part.split(['/', '\\'])) {
+        match component {
+            "" | "." => {}
+            // Don't let a path from a downloaded symbol file escape the
+            // cache directory.
+            ".." => return None,
+            component if component.contains(':') => return None,
+            component => cache_path.push(component),
+        }
+    }
+    Some(cache_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn urls_for_mapped_paths() {
+        let git = |repo: &str| MappedPath::Git {
+            repo: repo.to_string(),
+            path: "src/lib.rs".to_string(),
+            rev: "abc123".to_string(),
+        };
+        assert_eq!(
+            source_url_for_mapped_path(&git("github.com/mstange/samply")).unwrap(),
+            SourceUrl {
+                url: "https://raw.githubusercontent.com/mstange/samply/abc123/src/lib.rs"
+                    .to_string(),
+                is_base64: false,
+            }
+        );
+        assert_eq!(
+            source_url_for_mapped_path(&git("chromium.googlesource.com/chromium/src")).unwrap(),
+            SourceUrl {
+                url:
+                    "https://chromium.googlesource.com/chromium/src/+/abc123/src/lib.rs?format=TEXT"
+                        .to_string(),
+                is_base64: true,
+            }
+        );
+        assert_eq!(source_url_for_mapped_path(&git("example.com/repo")), None);
+
+        let hg = MappedPath::Hg {
+            repo: "hg.mozilla.org/mozilla-central".to_string(),
+            path: "widget/cocoa/nsAppShell.mm".to_string(),
+            rev: "997f00815e6b".to_string(),
+        };
+        assert_eq!(
+            source_url_for_mapped_path(&hg).unwrap().url,
+            "https://hg.mozilla.org/mozilla-central/raw-file/997f00815e6b/widget/cocoa/nsAppShell.mm"
+        );
+        assert_eq!(
+            cache_path_for_mapped_path(&hg),
+            Some(PathBuf::from(
+                "hg/hg.mozilla.org/mozilla-central/997f00815e6b/widget/cocoa/nsAppShell.mm"
+            ))
+        );
+
+        let escaping = MappedPath::Git {
+            repo: "github.com/a/b".to_string(),
+            path: "../../etc/passwd".to_string(),
+            rev: "abc123".to_string(),
+        };
+        assert_eq!(cache_path_for_mapped_path(&escaping), None);
+
+        let svn = MappedPath::Svn {
+            repo: "https://svn.example.com/repos/game".to_string(),
+            path: "trunk/src/main.cpp".to_string(),
+            rev: "1234".to_string(),
+        };
+        assert_eq!(
+            source_url_for_mapped_path(&svn).unwrap().url,
+            "https://svn.example.com/repos/game/trunk/src/main.cpp?p=1234"
+        );
+        assert_eq!(
+            cache_path_for_mapped_path(&svn),
+            Some(PathBuf::from(
+                "svn/svn.example.com/repos/game/1234/trunk/src/main.cpp"
+            ))
+        );
+        let perforce = MappedPath::Perforce {
+            server: "perforce.example.com:1666".to_string(),
+            path: "depot/main/Source/Engine.cpp".to_string(),
+            rev: "#3".to_string(),
+        };
+        assert_eq!(source_url_for_mapped_path(&perforce), None);
+    }
+}
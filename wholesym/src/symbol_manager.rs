@@ -1,15 +1,19 @@
 use std::borrow::Cow;
+use std::ops::Range;
 use std::path::Path;
 use std::sync::Arc;
 
 use debugid::DebugId;
 use samply_symbols::{
-    self, AddressInfo, Error, ExternalFileAddressInFileRef, ExternalFileAddressRef, FrameDebugInfo,
-    LibraryInfo, LookupAddress, MultiArchDisambiguator, SymbolMapTrait, SyncAddressInfo,
+    self, relative_address_base, AddressInfo, BinaryImage, Error, ExternalFileAddressInFileRef,
+    ExternalFileAddressRef, FrameDebugInfo, LibraryInfo, LookupAddress, MultiArchDisambiguator,
+    SourceLineRange, SymbolMapLoadObserver, SymbolMapTrait, SymbolTablePage, SyncAddressInfo,
 };
 
 use crate::config::SymbolManagerConfig;
-use crate::helper::{FileReadOnlyHelper, Helper, WholesymFileContents, WholesymFileLocation};
+use crate::helper::{
+    FileReadOnlyHelper, Helper, SymbolFileStats, WholesymFileContents, WholesymFileLocation,
+};
 
 /// Used in [`SymbolManager::load_external_file`] and returned by [`SymbolMap::symbol_file_origin`].
 #[derive(Debug, Clone)]
@@ -124,6 +128,20 @@ impl SymbolMap {
     pub fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
         self.0.iter_symbols()
     }
+
+    /// Returns at most `max_count` symbols, with their sizes, sorted by address
+    /// and starting at `start_address`. See [`SymbolTablePage`] for how to read
+    /// the whole symbol table.
+    pub fn symbol_table_page(&self, start_address: u32, max_count: usize) -> SymbolTablePage {
+        SymbolTablePage::from_symbol_map(&self.0, start_address, max_count)
+    }
+
+    /// Returns the line table entries which overlap the given range of
+    /// relative addresses, sorted by address, for example to annotate the
+    /// instructions of a function with their source lines.
+    pub fn source_line_ranges(&self, range: Range<u32>) -> Vec<SourceLineRange> {
+        self.0.source_line_ranges(range)
+    }
 }
 
 pub struct ExternalFileSymbolMap(samply_symbols::ExternalFileSymbolMap<WholesymFileContents>);
@@ -152,11 +170,19 @@ pub struct SymbolManager {
 impl SymbolManager {
     /// Create a new `SymbolManager` with the given config.
     pub fn with_config(config: SymbolManagerConfig) -> Self {
+        let pdb_function_arguments = !config.omit_pdb_function_arguments;
         let helper = Helper::with_config(config);
-        let symbol_manager = samply_symbols::SymbolManager::with_helper(helper);
+        let mut symbol_manager = samply_symbols::SymbolManager::with_helper(helper);
+        symbol_manager.set_pdb_function_arguments(pdb_function_arguments);
         Self { symbol_manager }
     }
 
+    /// Set an observer which is told about every candidate file that is tried
+    /// when loading a symbol map, and whether it was used or why not.
+    pub fn set_load_observer(&mut self, observer: Option<Arc<dyn SymbolMapLoadObserver>>) {
+        self.symbol_manager.set_load_observer(observer);
+    }
+
     /// Find symbols for the given binary.
     ///
     /// On macOS, the given path can also be a path to a system library which is
@@ -170,11 +196,17 @@ impl SymbolManager {
         path: &Path,
         disambiguator: Option<MultiArchDisambiguator>,
     ) -> Result<SymbolMap, Error> {
-        let library_info = Self::library_info_for_binary_at_path(path, disambiguator).await?;
-
-        Ok(SymbolMap(
-            self.symbol_manager.load_symbol_map(&library_info).await?,
-        ))
+        let binary = Self::load_binary_at_path(path, disambiguator).await?;
+        let mut symbol_map = self
+            .symbol_manager
+            .load_symbol_map(&binary.library_info())
+            .await?;
+        // Debug files like PDBs don't know the image base, so take it from the
+        // binary for lookups by SVMA.
+        if let Some(object) = binary.make_object() {
+            symbol_map.set_relative_address_base(relative_address_base(&object));
+        }
+        Ok(SymbolMap(symbol_map))
     }
 
     /// Computes the [`LibraryInfo`] for the given binary. This [`LibraryInfo`]
@@ -192,6 +224,14 @@ impl SymbolManager {
         path: &Path,
         disambiguator: Option<MultiArchDisambiguator>,
     ) -> Result<LibraryInfo, Error> {
+        let binary = Self::load_binary_at_path(path, disambiguator).await?;
+        Ok(binary.library_info())
+    }
+
+    async fn load_binary_at_path(
+        path: &Path,
+        disambiguator: Option<MultiArchDisambiguator>,
+    ) -> Result<BinaryImage<WholesymFileContents>, Error> {
         let might_be_in_dyld_shared_cache =
             path.starts_with("/usr/") || path.starts_with("/System/");
 
@@ -220,7 +260,7 @@ impl SymbolManager {
             }
             Err(e) => return Err(e),
         };
-        Ok(binary.library_info())
+        Ok(binary)
     }
 
     /// Tell the `SymbolManager` about a known library. This allows it to find
@@ -231,10 +271,16 @@ impl SymbolManager {
     /// `(debug_name, debug_id)` pairs, so there needs to be some stored auxiliary
     /// information which allows us to find the right debug files for the request.
     /// The list of "known libraries" is this auxiliary information.
-    pub fn add_known_library(&mut self, lib_info: LibraryInfo) {
+    pub fn add_known_library(&self, lib_info: LibraryInfo) {
         self.symbol_manager.helper().add_known_lib(lib_info);
     }
 
+    /// The numbers of symbol files which were found in cache directories and
+    /// which were downloaded, since this `SymbolManager` was created.
+    pub fn symbol_file_stats(&self) -> SymbolFileStats {
+        self.symbol_manager.helper().symbol_file_stats()
+    }
+
     /// Tell the `SymbolManager` about a library's symbol table. The library
     /// must contain a DebugId. This is useful when a library's symbols are
     /// available in some way other than normal symbol lookup, or if a custom
@@ -263,6 +309,27 @@ impl SymbolManager {
         Ok(SymbolMap(self.symbol_manager.load_symbol_map(&info).await?))
     }
 
+    /// Describes the file which supplied the symbols in `symbol_map`: its path
+    /// on disk, or the URL of the symbol server it was downloaded from.
+    pub fn debug_file_description(&self, symbol_map: &SymbolMap) -> String {
+        self.symbol_manager.debug_file_description(&symbol_map.0)
+    }
+
+    /// Obtain a page of the symbol table for the given `debug_name` and
+    /// `debug_id`, for tools which index all symbols of a library. Start with
+    /// a `start_address` of 0, and continue with the page's
+    /// `next_start_address` until it is `None`.
+    pub async fn symbol_table_page(
+        &self,
+        debug_name: &str,
+        debug_id: DebugId,
+        start_address: u32,
+        max_count: usize,
+    ) -> Result<SymbolTablePage, Error> {
+        let symbol_map = self.load_symbol_map(debug_name, debug_id).await?;
+        Ok(symbol_map.symbol_table_page(start_address, max_count))
+    }
+
     /// Manually load and return an external file with additional debug info.
     /// This is a lower-level alternative to [`lookup_external`](SymbolMap::lookup_external)
     /// and can be used if more control over caching is desired.
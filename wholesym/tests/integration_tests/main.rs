@@ -421,3 +421,107 @@ mod simple_example {
         .await;
     }
 }
+
+#[test]
+fn forced_backend() {
+    use wholesym::{SymbolBackend, SymbolManager, SymbolManagerConfig};
+
+    let debug_id = DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap();
+    let load = |backend| {
+        let config = SymbolManagerConfig::default()
+            .extra_symbols_directory(fixtures_dir().join("win64-ci"))
+            .force_backend("mozglue.pdb", backend);
+        let symbol_manager = SymbolManager::with_config(config);
+        futures::executor::block_on(symbol_manager.load_symbol_map("mozglue.pdb", debug_id))
+    };
+    assert!(load(SymbolBackend::Pdb).is_ok());
+    // There is no Breakpad .sym file for mozglue.pdb in the symbol directory.
+    assert!(load(SymbolBackend::Breakpad).is_err());
+}
+
+#[test]
+fn pdb_svma_and_file_offset_lookups() {
+    use wholesym::{SymbolBackend, SymbolManager, SymbolManagerConfig};
+
+    let dir = fixtures_dir().join("win64-ci");
+    let config = SymbolManagerConfig::default().redirect_path_for_testing(
+        "/builds/worker/workspace/obj-build/mozglue/build/mozglue.pdb",
+        dir.join("mozglue.pdb"),
+    );
+    let symbol_manager = SymbolManager::with_config(config);
+    let lookup_name = |symbol_map: &wholesym::SymbolMap, address| {
+        let info = futures::executor::block_on(symbol_map.lookup(address))?;
+        assert!(
+            info.frames.is_some(),
+            "The symbols should come from the PDB"
+        );
+        Some(info.symbol.name)
+    };
+
+    // mozglue.dll has its image base at 0x180000000, and its .text section at
+    // the relative address 0x1000 and the file offset 0x400.
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_for_binary_at_path(&dir.join("mozglue.dll"), None),
+    )
+    .unwrap();
+    let name = lookup_name(&symbol_map, LookupAddress::Relative(0x1010));
+    assert!(name.is_some());
+    assert_eq!(
+        lookup_name(&symbol_map, LookupAddress::Svma(0x180001010)),
+        name
+    );
+    assert_eq!(
+        lookup_name(&symbol_map, LookupAddress::FileOffset(0x410)),
+        name
+    );
+
+    // Without the binary, the image base is unknown, but the section headers
+    // in the PDB still map file offsets.
+    let debug_id = DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap();
+    let config = SymbolManagerConfig::default()
+        .extra_symbols_directory(&dir)
+        .force_backend("mozglue.pdb", SymbolBackend::Pdb);
+    let symbol_manager = SymbolManager::with_config(config);
+    let symbol_map =
+        futures::executor::block_on(symbol_manager.load_symbol_map("mozglue.pdb", debug_id))
+            .unwrap();
+    assert_eq!(
+        lookup_name(&symbol_map, LookupAddress::FileOffset(0x410)),
+        name
+    );
+    assert_eq!(
+        lookup_name(&symbol_map, LookupAddress::Svma(0x180001010)),
+        None
+    );
+}
+
+#[test]
+fn pdb_function_arguments() {
+    use wholesym::{SymbolBackend, SymbolManager, SymbolManagerConfig};
+
+    let dir = fixtures_dir().join("win64-ci");
+    let debug_id = DebugId::from_breakpad("63C609072D3499F64C4C44205044422E1").unwrap();
+    let lookup_name = |config: SymbolManagerConfig| {
+        let config = config
+            .extra_symbols_directory(&dir)
+            .force_backend("mozglue.pdb", SymbolBackend::Pdb);
+        let symbol_manager = SymbolManager::with_config(config);
+        let symbol_map =
+            futures::executor::block_on(symbol_manager.load_symbol_map("mozglue.pdb", debug_id))
+                .unwrap();
+        symbol_map
+            .lookup_sync(LookupAddress::Relative(0x1fb60))
+            .unwrap()
+            .symbol
+            .name
+    };
+
+    let with_arguments = lookup_name(SymbolManagerConfig::default());
+    let without_arguments =
+        lookup_name(SymbolManagerConfig::default().pdb_function_arguments(false));
+    assert_eq!(
+        with_arguments,
+        "patched_BaseThreadInitThunk(int, void*, void*)"
+    );
+    assert_eq!(without_arguments, "patched_BaseThreadInitThunk");
+}